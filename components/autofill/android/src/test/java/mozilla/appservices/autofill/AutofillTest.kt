@@ -1,4 +1,7 @@
+import mozilla.appservices.autofill.CreditCardFieldsWithNumber
+import mozilla.appservices.autofill.KeyManager
 import mozilla.appservices.autofill.Store
+import mozilla.appservices.autofill.createKey
 import mozilla.appservices.Megazord
 import mozilla.appservices.syncmanager.SyncManager
 
@@ -35,4 +38,27 @@ class AutofillTest {
         Assert.assertTrue(syncManager.getAvailableEngines().contains("addresses"))
         Assert.assertTrue(syncManager.getAvailableEngines().contains("creditcards"))
     }
+
+    @Test
+    fun testKeyManager() {
+        Megazord.init()
+        val key = createKey()
+        val store = Store.newWithKeyManager(
+            dbpath = dbFolder.newFile().absolutePath,
+            keyManager = object : KeyManager {
+                override fun getKey(): String? = key
+            }
+        )
+        val card = store.addCreditCardWithNumber(
+            CreditCardFieldsWithNumber(
+                ccName = "jane doe",
+                ccNumber = "1234567812345678",
+                ccExpMonth = 1,
+                ccExpYear = 2030,
+                ccType = "visa"
+            )
+        )
+        Assert.assertEquals("5678", card.ccNumberLast4)
+        Assert.assertEquals("1234567812345678", store.decryptCreditCardNumber(card.ccNumberEnc))
+    }
 }
@@ -0,0 +1,233 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+// A small amount of per-country address metadata - enough to make sense of an
+// address entered as free text. Countries we don't know about use a generic
+// format, which works reasonably well for most of the world.
+
+/// How postal codes are written in a country.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostalCodeFormat {
+    /// Exactly this many digits.
+    Digits(usize),
+    /// "12345" or "12345-6789".
+    UsZip,
+    /// "A1A 1A1".
+    Canada,
+    /// "SW1A 1AA", "M1 1AE", etc.
+    UnitedKingdom,
+    /// Anything which looks vaguely like a postal code.
+    Any,
+}
+
+impl PostalCodeFormat {
+    /// Whether `s` is a postal code in this format. Codes which are usually
+    /// written with a space (eg, in the UK) may have it or not.
+    pub fn matches(&self, s: &str) -> bool {
+        let is_digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+        match self {
+            Self::Digits(n) => s.len() == *n && is_digits(s),
+            Self::UsZip => match s.split_once('-') {
+                Some((zip, plus4)) => {
+                    zip.len() == 5 && is_digits(zip) && plus4.len() == 4 && is_digits(plus4)
+                }
+                None => s.len() == 5 && is_digits(s),
+            },
+            Self::Canada => {
+                let s: Vec<char> = s.chars().filter(|c| *c != ' ').collect();
+                s.len() == 6
+                    && s.iter().enumerate().all(|(i, c)| {
+                        if i % 2 == 0 {
+                            c.is_ascii_alphabetic()
+                        } else {
+                            c.is_ascii_digit()
+                        }
+                    })
+            }
+            Self::UnitedKingdom => {
+                let s: String = s.chars().filter(|c| *c != ' ').collect();
+                if !(5..=7).contains(&s.len()) || !s.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    return false;
+                }
+                let (outward, inward) = s.split_at(s.len() - 3);
+                let inward: Vec<char> = inward.chars().collect();
+                outward.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && outward.chars().any(|c| c.is_ascii_digit())
+                    && inward[0].is_ascii_digit()
+                    && inward[1].is_ascii_alphabetic()
+                    && inward[2].is_ascii_alphabetic()
+            }
+            Self::Any => {
+                (3..=10).contains(&s.len())
+                    && s.chars().any(|c| c.is_ascii_digit())
+                    && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            }
+        }
+    }
+}
+
+/// Where the postal code goes relative to the city.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalityOrder {
+    /// "City, REGION POSTCODE" (eg, the US), or "City POSTCODE".
+    CityRegionPostalCode,
+    /// "POSTCODE City" (eg, Germany).
+    PostalCodeCity,
+}
+
+#[derive(Debug)]
+pub struct CountryMetadata {
+    /// The ISO 3166-1 alpha-2 code, which is what we store in `country`.
+    pub code: &'static str,
+    /// Names (and other codes) which users commonly write for the country.
+    pub names: &'static [&'static str],
+    pub postal_code: PostalCodeFormat,
+    pub locality_order: LocalityOrder,
+    /// Abbreviations for the country's regions (ie, `address_level1`), for
+    /// countries which commonly write them.
+    pub regions: &'static [&'static str],
+}
+
+/// The format we use for countries we don't have metadata for.
+pub static GENERIC: CountryMetadata = CountryMetadata {
+    code: "",
+    names: &[],
+    postal_code: PostalCodeFormat::Any,
+    locality_order: LocalityOrder::CityRegionPostalCode,
+    regions: &[],
+};
+
+static COUNTRIES: &[CountryMetadata] = &[
+    CountryMetadata {
+        code: "US",
+        names: &["USA", "United States", "United States of America"],
+        postal_code: PostalCodeFormat::UsZip,
+        locality_order: LocalityOrder::CityRegionPostalCode,
+        regions: &[
+            "AL", "AK", "AZ", "AR", "CA", "CO", "CT", "DE", "DC", "FL", "GA", "HI", "ID", "IL",
+            "IN", "IA", "KS", "KY", "LA", "ME", "MD", "MA", "MI", "MN", "MS", "MO", "MT", "NE",
+            "NV", "NH", "NJ", "NM", "NY", "NC", "ND", "OH", "OK", "OR", "PA", "PR", "RI", "SC",
+            "SD", "TN", "TX", "UT", "VT", "VA", "WA", "WV", "WI", "WY",
+        ],
+    },
+    CountryMetadata {
+        code: "CA",
+        names: &["Canada"],
+        postal_code: PostalCodeFormat::Canada,
+        locality_order: LocalityOrder::CityRegionPostalCode,
+        regions: &[
+            "AB", "BC", "MB", "NB", "NL", "NS", "NT", "NU", "ON", "PE", "QC", "SK", "YT",
+        ],
+    },
+    CountryMetadata {
+        code: "GB",
+        names: &[
+            "UK",
+            "United Kingdom",
+            "Great Britain",
+            "England",
+            "Scotland",
+            "Wales",
+        ],
+        postal_code: PostalCodeFormat::UnitedKingdom,
+        locality_order: LocalityOrder::CityRegionPostalCode,
+        regions: &[],
+    },
+    CountryMetadata {
+        code: "AU",
+        names: &["Australia"],
+        postal_code: PostalCodeFormat::Digits(4),
+        locality_order: LocalityOrder::CityRegionPostalCode,
+        regions: &["ACT", "NSW", "NT", "QLD", "SA", "TAS", "VIC", "WA"],
+    },
+    CountryMetadata {
+        code: "NZ",
+        names: &["New Zealand", "Aotearoa"],
+        postal_code: PostalCodeFormat::Digits(4),
+        locality_order: LocalityOrder::CityRegionPostalCode,
+        regions: &[],
+    },
+    CountryMetadata {
+        code: "DE",
+        names: &["Germany", "Deutschland"],
+        postal_code: PostalCodeFormat::Digits(5),
+        locality_order: LocalityOrder::PostalCodeCity,
+        regions: &[],
+    },
+    CountryMetadata {
+        code: "FR",
+        names: &["France"],
+        postal_code: PostalCodeFormat::Digits(5),
+        locality_order: LocalityOrder::PostalCodeCity,
+        regions: &[],
+    },
+];
+
+/// Looks up a country by its ISO code.
+pub fn get_country(code: &str) -> Option<&'static CountryMetadata> {
+    COUNTRIES
+        .iter()
+        .find(|country| country.code.eq_ignore_ascii_case(code))
+}
+
+/// Looks up a country by anything a user might write for it - its name or
+/// its ISO code.
+pub fn find_country(name: &str) -> Option<&'static CountryMetadata> {
+    let name = name.trim().trim_end_matches('.');
+    get_country(name).or_else(|| {
+        COUNTRIES.iter().find(|country| {
+            country
+                .names
+                .iter()
+                .any(|candidate| candidate.eq_ignore_ascii_case(name))
+        })
+    })
+}
+
+/// Finds the country for a locale such as "en-US" or "fr_CA". Locales without
+/// a region don't tell us the country.
+pub fn country_for_locale(locale: &str) -> Option<&'static CountryMetadata> {
+    locale
+        .split(|c| c == '-' || c == '_')
+        .skip(1)
+        .find(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_alphabetic()))
+        .and_then(get_country)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_postal_codes() {
+        assert!(PostalCodeFormat::UsZip.matches("62701"));
+        assert!(PostalCodeFormat::UsZip.matches("62701-1234"));
+        assert!(!PostalCodeFormat::UsZip.matches("6270"));
+        assert!(!PostalCodeFormat::UsZip.matches("62701-12"));
+        assert!(PostalCodeFormat::Canada.matches("K1A 0B1"));
+        assert!(PostalCodeFormat::Canada.matches("k1a0b1"));
+        assert!(!PostalCodeFormat::Canada.matches("K1A 0B"));
+        assert!(PostalCodeFormat::UnitedKingdom.matches("SW1A 2AA"));
+        assert!(PostalCodeFormat::UnitedKingdom.matches("M1 1AE"));
+        assert!(!PostalCodeFormat::UnitedKingdom.matches("London"));
+        assert!(PostalCodeFormat::Digits(5).matches("10115"));
+        assert!(!PostalCodeFormat::Digits(5).matches("1011"));
+        assert!(PostalCodeFormat::Any.matches("1010-AB"));
+        assert!(!PostalCodeFormat::Any.matches("Main"));
+    }
+
+    #[test]
+    fn test_find_country() {
+        assert_eq!(find_country("us").unwrap().code, "US");
+        assert_eq!(find_country("U.S.A.").map(|c| c.code), None);
+        assert_eq!(find_country("Deutschland").unwrap().code, "DE");
+        assert_eq!(find_country(" united kingdom ").unwrap().code, "GB");
+        assert!(find_country("Narnia").is_none());
+        assert_eq!(country_for_locale("en-US").unwrap().code, "US");
+        assert_eq!(country_for_locale("fr_CA").unwrap().code, "CA");
+        assert_eq!(country_for_locale("zh-Hant-TW").map(|c| c.code), None);
+        assert!(country_for_locale("de").is_none());
+    }
+}
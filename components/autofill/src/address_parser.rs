@@ -0,0 +1,312 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+// Splits an address entered as a single piece of text (eg, pasted from
+// somewhere else) into the fields we store. This is necessarily a best-effort
+// thing - the consumer should show the result to the user to be corrected
+// before saving it.
+
+use crate::address_metadata::{self, CountryMetadata, LocalityOrder};
+use crate::db::models::address::UpdatableAddressFields;
+
+/// Parses `free_text` into address fields. `locale` (eg, "en-US") is used to
+/// guess the country when the address doesn't include one. Anything we can't
+/// make sense of ends up in `street_address`.
+pub fn parse_address(free_text: &str, locale: &str) -> UpdatableAddressFields {
+    let mut fields = UpdatableAddressFields::default();
+    let mut lines = split_lines(free_text);
+
+    // Emails and phone numbers are easy to spot, wherever they are.
+    lines.retain(|line| {
+        if fields.email.is_empty() && is_email(line) {
+            fields.email = line.clone();
+            false
+        } else if fields.tel.is_empty() && is_tel(line) {
+            fields.tel = line.clone();
+            false
+        } else {
+            true
+        }
+    });
+
+    let mut country = None;
+    if let Some(last) = lines.last() {
+        country = address_metadata::find_country(last);
+        if country.is_some() {
+            lines.pop();
+        }
+    }
+    let country = country.or_else(|| address_metadata::country_for_locale(locale));
+    fields.country = country.map(|c| c.code.to_string()).unwrap_or_default();
+    let metadata = country.unwrap_or(&address_metadata::GENERIC);
+
+    parse_locality(&mut lines, metadata, &mut fields);
+
+    // If there's more than one line left, the first is probably a name -
+    // unless it has numbers in it, which suggests a street address.
+    if lines.len() > 1 && !lines[0].chars().any(|c| c.is_ascii_digit()) {
+        let name = lines.remove(0);
+        match name.rsplit_once(' ') {
+            Some((given, family)) => {
+                fields.given_name = given.trim().to_string();
+                fields.family_name = family.to_string();
+            }
+            None => fields.given_name = name,
+        }
+    }
+    fields.street_address = lines.join("\n");
+    fields
+}
+
+// Addresses are usually on multiple lines, but are sometimes on a single line
+// with commas.
+fn split_lines(free_text: &str) -> Vec<String> {
+    let lines: Vec<&str> = free_text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    let lines = if lines.len() == 1 {
+        lines[0].split(',').collect()
+    } else {
+        lines
+    };
+    lines
+        .into_iter()
+        .map(|l| l.trim().trim_end_matches(',').trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+fn is_email(line: &str) -> bool {
+    match line.split_once('@') {
+        Some((user, domain)) => {
+            !user.is_empty() && domain.contains('.') && !line.contains(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+fn is_tel(line: &str) -> bool {
+    line.chars()
+        .all(|c| c.is_ascii_digit() || " +-().".contains(c))
+        && line.chars().filter(|c| c.is_ascii_digit()).count() >= 7
+}
+
+// Finds the line with the postal code, and takes the postal code, city and
+// region from it (and sometimes from the line before). The lines used are
+// removed from `lines`.
+fn parse_locality(
+    lines: &mut Vec<String>,
+    metadata: &CountryMetadata,
+    fields: &mut UpdatableAddressFields,
+) {
+    let found = lines.iter().enumerate().rev().find_map(|(index, line)| {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        find_postal_code(&tokens, metadata).map(|(postal_code, rest)| (index, postal_code, rest))
+    });
+    let (index, postal_code, rest) = match found {
+        Some(found) => found,
+        None => return,
+    };
+    lines.truncate(index);
+    fields.postal_code = postal_code;
+
+    let rest = rest.trim_matches(|c: char| c == ',' || c.is_whitespace());
+    let (city, region) = match rest.rsplit_once(',') {
+        Some((city, region)) => (city.trim(), region.trim()),
+        None => match rest.rsplit_once(' ') {
+            Some((city, region)) if is_region(region, metadata) => (city.trim(), region),
+            _ if is_region(rest, metadata) => ("", rest),
+            _ => (rest, ""),
+        },
+    };
+    fields.address_level1 = region.to_string();
+    fields.address_level2 = if city.is_empty() && lines.len() > 1 {
+        // The city is on its own line (eg, "Springfield" then "IL 62701", or
+        // "London" then "SW1A 2AA").
+        lines.pop().unwrap_or_default()
+    } else {
+        city.to_string()
+    };
+}
+
+fn is_region(s: &str, metadata: &CountryMetadata) -> bool {
+    metadata
+        .regions
+        .iter()
+        .any(|region| region.eq_ignore_ascii_case(s.trim_end_matches('.')))
+}
+
+// Postal codes may be one token or two (eg, "K1A 0B1"). Returns the postal
+// code and the rest of the line.
+fn find_postal_code(tokens: &[&str], metadata: &CountryMetadata) -> Option<(String, String)> {
+    let matches = |candidate: &[&str]| {
+        let joined = candidate.join(" ");
+        let joined = joined.trim_end_matches(',');
+        metadata
+            .postal_code
+            .matches(joined)
+            .then(|| joined.to_string())
+    };
+    for len in [2, 1] {
+        if tokens.len() < len {
+            continue;
+        }
+        let (postal_code, rest) = match metadata.locality_order {
+            LocalityOrder::CityRegionPostalCode => {
+                let (rest, candidate) = tokens.split_at(tokens.len() - len);
+                (matches(candidate), rest)
+            }
+            LocalityOrder::PostalCodeCity => {
+                let (candidate, rest) = tokens.split_at(len);
+                (matches(candidate), rest)
+            }
+        };
+        if let Some(postal_code) = postal_code {
+            return Some((postal_code, rest.join(" ")));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(text: &str, locale: &str, expected: &[(&str, &str)]) -> UpdatableAddressFields {
+        let fields = parse_address(text, locale);
+        for (name, value) in expected {
+            let actual = match *name {
+                "given_name" => &fields.given_name,
+                "family_name" => &fields.family_name,
+                "street_address" => &fields.street_address,
+                "address_level2" => &fields.address_level2,
+                "address_level1" => &fields.address_level1,
+                "postal_code" => &fields.postal_code,
+                "country" => &fields.country,
+                "tel" => &fields.tel,
+                "email" => &fields.email,
+                _ => unreachable!("unknown field {}", name),
+            };
+            assert_eq!(actual, value, "{} in {:?}", name, text);
+        }
+        fields
+    }
+
+    #[test]
+    fn test_us() {
+        let expected = [
+            ("given_name", "John"),
+            ("family_name", "Smith"),
+            ("street_address", "123 Main St"),
+            ("address_level2", "Springfield"),
+            ("address_level1", "IL"),
+            ("postal_code", "62701"),
+            ("country", "US"),
+        ];
+        check(
+            "John Smith\n123 Main St\nSpringfield, IL 62701\nUSA",
+            "fr-FR",
+            &expected,
+        );
+        check(
+            "John Smith, 123 Main St, Springfield, IL 62701",
+            "en-US",
+            &expected,
+        );
+        check(
+            "John Smith\n123 Main St\nSpringfield IL 62701",
+            "en-US",
+            &expected,
+        );
+    }
+
+    #[test]
+    fn test_multi_line_street() {
+        check(
+            "Jane Doe\n1 Infinite Loop\nSuite 100\nCupertino, CA 95014-2083\n(555) 555-0100\njane@example.com",
+            "en-US",
+            &[
+                ("given_name", "Jane"),
+                ("family_name", "Doe"),
+                ("street_address", "1 Infinite Loop\nSuite 100"),
+                ("address_level2", "Cupertino"),
+                ("address_level1", "CA"),
+                ("postal_code", "95014-2083"),
+                ("tel", "(555) 555-0100"),
+                ("email", "jane@example.com"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_canada() {
+        check(
+            "1 Main St, Ottawa, ON K1A 0B1, Canada",
+            "en-US",
+            &[
+                ("given_name", ""),
+                ("street_address", "1 Main St"),
+                ("address_level2", "Ottawa"),
+                ("address_level1", "ON"),
+                ("postal_code", "K1A 0B1"),
+                ("country", "CA"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_germany() {
+        check(
+            "Max Mustermann\nMusterstraße 1\n10115 Berlin\nDeutschland",
+            "en-US",
+            &[
+                ("given_name", "Max"),
+                ("family_name", "Mustermann"),
+                ("street_address", "Musterstraße 1"),
+                ("address_level2", "Berlin"),
+                ("postal_code", "10115"),
+                ("country", "DE"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_uk() {
+        check(
+            "Jane Doe\n10 Downing Street\nLondon\nSW1A 2AA",
+            "en-GB",
+            &[
+                ("given_name", "Jane"),
+                ("family_name", "Doe"),
+                ("street_address", "10 Downing Street"),
+                ("address_level2", "London"),
+                ("address_level1", ""),
+                ("postal_code", "SW1A 2AA"),
+                ("country", "GB"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_unknown() {
+        // Without a postal code or country, everything but the name is the
+        // street address.
+        check(
+            "Someone\nSomewhere",
+            "",
+            &[
+                ("given_name", "Someone"),
+                ("street_address", "Somewhere"),
+                ("country", ""),
+                ("postal_code", ""),
+            ],
+        );
+        let fields = parse_address("", "en-US");
+        assert_eq!(fields.street_address, "");
+        assert_eq!(fields.country, "US");
+    }
+}
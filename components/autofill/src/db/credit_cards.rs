@@ -5,14 +5,19 @@
 
 use crate::db::{
     models::{
-        credit_card::{InternalCreditCard, UpdatableCreditCardFields},
+        credit_card::{
+            normalize_expiry_year, CreditCardKeyMigrationResult, InternalCreditCard,
+            UpdatableCreditCardFields,
+        },
         Metadata,
     },
     schema::{CREDIT_CARD_COMMON_COLS, CREDIT_CARD_COMMON_VALS},
 };
+use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
 
 use rusqlite::{Connection, Transaction};
+use sql_support::ConnExt;
 use sync_guid::Guid;
 use types::Timestamp;
 
@@ -30,7 +35,7 @@ pub(crate) fn add_credit_card(
         cc_number_enc: new_credit_card_fields.cc_number_enc,
         cc_number_last_4: new_credit_card_fields.cc_number_last_4,
         cc_exp_month: new_credit_card_fields.cc_exp_month,
-        cc_exp_year: new_credit_card_fields.cc_exp_year,
+        cc_exp_year: normalize_expiry_year(new_credit_card_fields.cc_exp_year),
         // Credit card types are a fixed set of strings as defined in the link below
         // (https://searchfox.org/mozilla-central/rev/7ef5cefd0468b8f509efe38e0212de2398f4c8b3/toolkit/modules/CreditCard.jsm#9-22)
         cc_type: new_credit_card_fields.cc_type,
@@ -136,7 +141,7 @@ pub fn update_credit_card(
             ":cc_number_enc": credit_card.cc_number_enc,
             ":cc_number_last_4": credit_card.cc_number_last_4,
             ":cc_exp_month": credit_card.cc_exp_month,
-            ":cc_exp_year": credit_card.cc_exp_year,
+            ":cc_exp_year": normalize_expiry_year(credit_card.cc_exp_year),
             ":cc_type": credit_card.cc_type,
             ":time_last_modified": Timestamp::now(),
             ":guid": guid,
@@ -211,6 +216,63 @@ pub fn scrub_encrypted_credit_card_data(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
+/// Re-encrypts the numbers of the credit cards which were encrypted with `old`,
+/// using `new`. Numbers which `old` can't decrypt are scrubbed, like
+/// `scrub_encrypted_credit_card_data` does, and the count of them is returned
+/// so that the caller can arrange for them to be fetched again by sync.
+/// Numbers which `new` can already decrypt were migrated before, and are left
+/// alone, so this can safely be run more than once.
+///
+/// If `old` can't decrypt any of the numbers, it's most likely the wrong key,
+/// so we fail with `IncorrectEncryptionKey` rather than scrub every card.
+pub fn migrate_credit_card_key(
+    conn: &Connection,
+    old: &EncryptorDecryptor,
+    new: &EncryptorDecryptor,
+) -> Result<CreditCardKeyMigrationResult> {
+    let tx = conn.unchecked_transaction()?;
+    let mut result = CreditCardKeyMigrationResult::default();
+    let cards: Vec<(Guid, String)> = tx.query_rows_and_then(
+        "SELECT guid, cc_number_enc FROM credit_cards_data WHERE cc_number_enc <> ''",
+        [],
+        |row| -> Result<_> { Ok((row.get("guid")?, row.get("cc_number_enc")?)) },
+    )?;
+    let mut updates = Vec::new();
+    for (guid, cc_number_enc) in cards {
+        if new.decrypt(&cc_number_enc).is_ok() {
+            continue;
+        }
+        let new_enc = match old.decrypt(&cc_number_enc) {
+            Ok(number) => {
+                result.migrated += 1;
+                new.encrypt(&number)?
+            }
+            Err(e) => {
+                log::warn!("Failed to decrypt credit card {}: {}", guid, e);
+                result.scrubbed += 1;
+                String::new()
+            }
+        };
+        updates.push((guid, new_enc));
+    }
+    if result.migrated == 0 && result.scrubbed > 0 {
+        return Err(Error::IncorrectEncryptionKey);
+    }
+    for (guid, new_enc) in updates {
+        // The number itself doesn't change, so neither does the sync change
+        // counter.
+        tx.execute(
+            "UPDATE credit_cards_data SET cc_number_enc = :cc_number_enc WHERE guid = :guid",
+            rusqlite::named_params! {
+                ":cc_number_enc": new_enc,
+                ":guid": guid,
+            },
+        )?;
+    }
+    tx.commit()?;
+    Ok(result)
+}
+
 pub fn touch(conn: &Connection, guid: &Guid) -> Result<()> {
     let tx = conn.unchecked_transaction()?;
     let now_ms = Timestamp::now();
@@ -469,6 +531,39 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_credit_card_expiry_year_normalized() -> Result<()> {
+        let db = new_mem_db();
+        let fields = UpdatableCreditCardFields {
+            cc_name: "jane doe".to_string(),
+            cc_number_enc: "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_string(),
+            cc_number_last_4: "4321".to_string(),
+            cc_exp_month: 9,
+            cc_exp_year: 27,
+            cc_type: "visa".to_string(),
+        };
+        let saved_credit_card = add_credit_card(&db, fields.clone())?;
+        assert_eq!(saved_credit_card.cc_exp_year, 2027);
+        assert_eq!(
+            get_credit_card(&db, &saved_credit_card.guid)?.cc_exp_year,
+            2027
+        );
+
+        update_credit_card(
+            &db,
+            &saved_credit_card.guid,
+            &UpdatableCreditCardFields {
+                cc_exp_year: 30,
+                ..fields
+            },
+        )?;
+        assert_eq!(
+            get_credit_card(&db, &saved_credit_card.guid)?.cc_exp_year,
+            2030
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_credit_card_update_internal_credit_card() -> Result<()> {
         let mut db = new_mem_db();
@@ -616,6 +711,67 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_migrate_credit_card_key() -> Result<()> {
+        let db = new_mem_db();
+        let old = EncryptorDecryptor::new(&crate::encryption::create_key().unwrap())?;
+        let new = EncryptorDecryptor::new(&crate::encryption::create_key().unwrap())?;
+        let other = EncryptorDecryptor::new_test_key();
+        let add = |encdec: &EncryptorDecryptor, number: &str| -> Result<InternalCreditCard> {
+            add_credit_card(
+                &db,
+                UpdatableCreditCardFields {
+                    cc_name: "john deer".to_string(),
+                    cc_number_enc: encdec.encrypt(number)?,
+                    cc_number_last_4: number[number.len() - 4..].to_string(),
+                    cc_exp_month: 10,
+                    cc_exp_year: 2025,
+                    cc_type: "mastercard".to_string(),
+                },
+            )
+        };
+        let with_old = add(&old, "1234567812345678")?;
+        let with_new = add(&new, "1234123412341234")?;
+        let with_other = add(&other, "8765432187654321")?;
+
+        let result = migrate_credit_card_key(&db, &old, &new)?;
+        assert_eq!(
+            result,
+            CreditCardKeyMigrationResult {
+                migrated: 1,
+                scrubbed: 1,
+            }
+        );
+        let cc = get_credit_card(&db, &with_old.guid)?;
+        assert_eq!(new.decrypt(&cc.cc_number_enc)?, "1234567812345678");
+        assert_eq!(
+            cc.metadata.sync_change_counter,
+            with_old.metadata.sync_change_counter
+        );
+        let cc = get_credit_card(&db, &with_new.guid)?;
+        assert_eq!(cc.cc_number_enc, with_new.cc_number_enc);
+        let cc = get_credit_card(&db, &with_other.guid)?;
+        assert_eq!(cc.cc_number_enc, "");
+
+        // Migrating again doesn't do anything.
+        assert_eq!(
+            migrate_credit_card_key(&db, &old, &new)?,
+            CreditCardKeyMigrationResult::default()
+        );
+
+        // A key which can't decrypt any of the numbers is rejected, and the
+        // cards are left alone.
+        let with_old = add(&old, "1234567812345678")?;
+        let wrong = EncryptorDecryptor::new(&crate::encryption::create_key().unwrap())?;
+        assert!(matches!(
+            migrate_credit_card_key(&db, &wrong, &new),
+            Err(Error::IncorrectEncryptionKey)
+        ));
+        let cc = get_credit_card(&db, &with_old.guid)?;
+        assert_eq!(cc.cc_number_enc, with_old.cc_number_enc);
+        Ok(())
+    }
+
     #[test]
     fn test_credit_card_trigger_on_create() -> Result<()> {
         let db = new_mem_db();
@@ -10,9 +10,9 @@ pub mod store;
 
 use crate::error::*;
 
-use interrupt_support::{SqlInterruptHandle, SqlInterruptScope};
+use interrupt_support::{register_interrupt, SqlInterruptHandle, SqlInterruptScope};
 use rusqlite::{Connection, OpenFlags};
-use sql_support::open_database;
+use sql_support::{maintenance::MaintenancePolicy, open_database};
 use std::sync::Arc;
 use std::{
     ops::{Deref, DerefMut},
@@ -50,8 +50,10 @@ impl AutofillDb {
             &schema::AutofillConnectionInitializer,
         )?;
 
+        let interrupt_handle = Arc::new(SqlInterruptHandle::new(&conn));
+        register_interrupt(Arc::<SqlInterruptHandle>::downgrade(&interrupt_handle));
         Ok(Self {
-            interrupt_handle: Arc::new(SqlInterruptHandle::new(&conn)),
+            interrupt_handle,
             writer: conn,
         })
     }
@@ -76,6 +78,14 @@ impl DerefMut for AutofillDb {
     }
 }
 
+// Autofill has nothing to prune: everything in the database is the user's
+// data, and tombstones are deleted once they've been synced.
+pub(crate) struct AutofillMaintenancePolicy;
+
+impl MaintenancePolicy for AutofillMaintenancePolicy {
+    const NAME: &'static str = "autofill";
+}
+
 fn unurl_path(p: impl AsRef<Path>) -> PathBuf {
     p.as_ref()
         .to_str()
@@ -4,6 +4,7 @@
 */
 
 use super::Metadata;
+use crate::error::Error;
 use rusqlite::Row;
 use sync_guid::Guid;
 
@@ -19,6 +20,17 @@ pub struct UpdatableCreditCardFields {
     pub cc_type: String,
 }
 
+/// The fields of a credit card with its number in the clear, for stores with a
+/// key manager, which encrypt the number themselves.
+#[derive(Debug, Clone, Default)]
+pub struct CreditCardFieldsWithNumber {
+    pub cc_name: String,
+    pub cc_number: String,
+    pub cc_exp_month: i64,
+    pub cc_exp_year: i64,
+    pub cc_type: String,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct CreditCard {
     pub guid: String,
@@ -39,6 +51,101 @@ pub struct CreditCard {
     pub times_used: i64,
 }
 
+impl CreditCard {
+    /// Whether the card has expired at `now` (in milliseconds since the epoch,
+    /// like our other timestamps). Cards are valid until the end of their
+    /// expiry month. Cards without a valid expiry never expire.
+    pub fn is_expired(&self, now: i64) -> bool {
+        if !(1..=12).contains(&self.cc_exp_month) || self.cc_exp_year <= 0 {
+            return false;
+        }
+        let (year, month) = year_month_from_millis(now);
+        (normalize_expiry_year(self.cc_exp_year), self.cc_exp_month) < (year, month)
+    }
+}
+
+/// What happened to the credit cards when their numbers were moved to a new
+/// encryption key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CreditCardKeyMigrationResult {
+    /// The number of cards whose number was re-encrypted with the new key.
+    pub migrated: u32,
+    /// The number of cards whose number couldn't be decrypted with the old
+    /// key, and was scrubbed.
+    pub scrubbed: u32,
+}
+
+/// A credit card's expiry, in the canonical form we store it: a month from 1
+/// to 12, and a four digit year.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreditCardExpiry {
+    pub month: i64,
+    pub year: i64,
+}
+
+/// Two digit years are assumed to be in the 21st century, like desktop does.
+pub fn normalize_expiry_year(year: i64) -> i64 {
+    if (0..100).contains(&year) {
+        year + 2000
+    } else {
+        year
+    }
+}
+
+impl CreditCardExpiry {
+    /// Parses an expiry as typed by a user, such as "9/27", "09/2027", "09-27",
+    /// "0927" or "2027-09".
+    pub fn parse(input: &str) -> crate::error::Result<Self> {
+        let invalid = |reason: &str| Error::InvalidCreditCardExpiry(reason.to_string());
+        let parts: Vec<&str> = input
+            .split(|c: char| c == '/' || c == '-' || c == '.' || c.is_whitespace())
+            .filter(|p| !p.is_empty())
+            .collect();
+        if parts.iter().any(|p| !p.chars().all(|c| c.is_ascii_digit())) {
+            return Err(invalid("expiry must only contain digits and separators"));
+        }
+        let (month, year) = match parts.as_slice() {
+            // "2027-09"
+            [year, month] if year.len() == 4 && month.len() <= 2 => (*month, *year),
+            // "9/27" or "09/2027"
+            [month, year] if month.len() <= 2 && (year.len() == 2 || year.len() == 4) => {
+                (*month, *year)
+            }
+            // "927", "0927" or "092027"
+            [digits] if (3..=4).contains(&digits.len()) || digits.len() == 6 => {
+                let year_len = if digits.len() == 6 { 4 } else { 2 };
+                digits.split_at(digits.len() - year_len)
+            }
+            _ => return Err(invalid("unrecognized expiry format")),
+        };
+        // These can't fail as we checked they are short strings of digits.
+        let month: i64 = month.parse().map_err(|_| invalid("invalid month"))?;
+        let year: i64 = year.parse().map_err(|_| invalid("invalid year"))?;
+        if !(1..=12).contains(&month) {
+            return Err(invalid("month must be between 1 and 12"));
+        }
+        Ok(CreditCardExpiry {
+            month,
+            year: normalize_expiry_year(year),
+        })
+    }
+}
+
+// Converts a timestamp to the (year, month) it falls in, in UTC. This is
+// Howard Hinnant's `civil_from_days` algorithm.
+fn year_month_from_millis(millis: i64) -> (i64, i64) {
+    let days = millis.div_euclid(24 * 60 * 60 * 1000) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month)
+}
+
 // This is used to "externalize" a credit-card, suitable for handing back to
 // consumers.
 impl From<InternalCreditCard> for CreditCard {
@@ -104,3 +211,67 @@ impl InternalCreditCard {
         self.cc_number_enc.is_empty()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_expiry() {
+        let expiry = |month, year| CreditCardExpiry { month, year };
+        for (input, expected) in [
+            ("9/27", expiry(9, 2027)),
+            ("09/27", expiry(9, 2027)),
+            ("09/2027", expiry(9, 2027)),
+            (" 9 / 2027 ", expiry(9, 2027)),
+            ("12-30", expiry(12, 2030)),
+            ("1.2031", expiry(1, 2031)),
+            ("927", expiry(9, 2027)),
+            ("0927", expiry(9, 2027)),
+            ("092027", expiry(9, 2027)),
+            ("2027-09", expiry(9, 2027)),
+        ] {
+            assert_eq!(
+                CreditCardExpiry::parse(input).unwrap(),
+                expected,
+                "{}",
+                input
+            );
+        }
+        for input in [
+            "", "13/27", "0/27", "9/", "9/2", "9/202", "123/27", "ab/cd", "9/27/1", "27",
+        ] {
+            assert!(CreditCardExpiry::parse(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_year_month_from_millis() {
+        assert_eq!(year_month_from_millis(0), (1970, 1));
+        // 2024-02-29T23:59:59.999Z
+        assert_eq!(year_month_from_millis(1_709_251_199_999), (2024, 2));
+        // 2024-03-01T00:00:00Z
+        assert_eq!(year_month_from_millis(1_709_251_200_000), (2024, 3));
+        // 1969-12-31T23:59:59Z
+        assert_eq!(year_month_from_millis(-1000), (1969, 12));
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let card = |month, year| CreditCard {
+            cc_exp_month: month,
+            cc_exp_year: year,
+            ..Default::default()
+        };
+        // 2024-03-15T00:00:00Z
+        let now = 1_710_460_800_000;
+        assert!(card(2, 2024).is_expired(now));
+        assert!(card(12, 23).is_expired(now));
+        assert!(!card(3, 2024).is_expired(now));
+        assert!(!card(4, 24).is_expired(now));
+        assert!(!card(1, 2025).is_expired(now));
+        // Cards without a valid expiry never expire.
+        assert!(!card(0, 0).is_expired(now));
+        assert!(!card(13, 2020).is_expired(now));
+    }
+}
@@ -3,15 +3,18 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::db::models::address::{Address, UpdatableAddressFields};
-use crate::db::models::credit_card::{CreditCard, UpdatableCreditCardFields};
-use crate::db::{addresses, credit_cards, AutofillDb};
+use crate::db::models::credit_card::{
+    CreditCard, CreditCardFieldsWithNumber, CreditCardKeyMigrationResult, UpdatableCreditCardFields,
+};
+use crate::db::{addresses, credit_cards, AutofillDb, AutofillMaintenancePolicy};
+use crate::encryption::{EncryptorDecryptor, KeyManager};
 use crate::error::*;
 use error_support::handle_error;
 use rusqlite::{
     types::{FromSql, ToSql},
     Connection,
 };
-use sql_support::{self, ConnExt};
+use sql_support::{self, maintenance, ConnExt};
 use std::path::Path;
 use std::sync::{Arc, Mutex, Weak};
 use sync15::engine::{SyncEngine, SyncEngineId};
@@ -46,6 +49,7 @@ pub fn get_registered_sync_engine(engine_id: &SyncEngineId) -> Option<Box<dyn Sy
 // This is the type that uniffi exposes.
 pub struct Store {
     pub(crate) db: Mutex<AutofillDb>,
+    key_manager: Option<Box<dyn KeyManager>>,
 }
 
 impl Store {
@@ -53,6 +57,21 @@ impl Store {
         handle_error! {
             Ok(Self {
                 db: Mutex::new(AutofillDb::new(db_path)?),
+                key_manager: None,
+            })
+        }
+    }
+
+    /// Creates a store which encrypts credit-card numbers with the key from
+    /// `key_manager`, and syncs credit cards with it.
+    pub fn new_with_key_manager(
+        db_path: impl AsRef<Path>,
+        key_manager: Box<dyn KeyManager>,
+    ) -> ApiResult<Self> {
+        handle_error! {
+            Ok(Self {
+                db: Mutex::new(AutofillDb::new(db_path)?),
+                key_manager: Some(key_manager),
             })
         }
     }
@@ -62,6 +81,7 @@ impl Store {
     pub fn new_memory() -> Self {
         Self {
             db: Mutex::new(crate::db::test::new_mem_db()),
+            key_manager: None,
         }
     }
 
@@ -70,10 +90,81 @@ impl Store {
         handle_error! {
             Ok(Self {
                 db: Mutex::new(AutofillDb::new_memory(db_name)?),
+                key_manager: None,
             })
         }
     }
 
+    // The key from the key manager, if we have one and it has the key.
+    pub(crate) fn managed_key(&self) -> Option<String> {
+        self.key_manager.as_ref().and_then(|km| km.get_key())
+    }
+
+    fn managed_encdec(&self) -> Result<EncryptorDecryptor> {
+        match self.managed_key() {
+            Some(key) => EncryptorDecryptor::new(&key),
+            None => Err(Error::MissingEncryptionKey),
+        }
+    }
+
+    // Encrypts the number in `fields` with the key manager's key.
+    fn encrypt_fields(
+        &self,
+        fields: CreditCardFieldsWithNumber,
+    ) -> Result<UpdatableCreditCardFields> {
+        let cc_number_enc = self.managed_encdec()?.encrypt(&fields.cc_number)?;
+        let digits: Vec<char> = fields
+            .cc_number
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect();
+        Ok(UpdatableCreditCardFields {
+            cc_name: fields.cc_name,
+            cc_number_enc,
+            cc_number_last_4: digits[digits.len().saturating_sub(4)..].iter().collect(),
+            cc_exp_month: fields.cc_exp_month,
+            cc_exp_year: fields.cc_exp_year,
+            cc_type: fields.cc_type,
+        })
+    }
+
+    /// Encrypts a credit-card number with the key manager's key, for use as
+    /// the `cc_number_enc` of a card.
+    pub fn encrypt_credit_card_number(&self, number: String) -> ApiResult<String> {
+        handle_error! {
+            self.managed_encdec()?.encrypt(&number)
+        }
+    }
+
+    /// Decrypts the `cc_number_enc` of a card with the key manager's key.
+    pub fn decrypt_credit_card_number(&self, cc_number_enc: String) -> ApiResult<String> {
+        handle_error! {
+            self.managed_encdec()?.decrypt(&cc_number_enc)
+        }
+    }
+
+    /// Moves the numbers of existing credit cards from `old_key`, which the
+    /// app managed itself, to the key manager's key. Numbers which can't be
+    /// decrypted with `old_key` are scrubbed, and fetched again on the next
+    /// credit-card sync.
+    pub fn migrate_credit_card_key(
+        self: Arc<Self>,
+        old_key: String,
+    ) -> ApiResult<CreditCardKeyMigrationResult> {
+        handle_error! {
+            let old = EncryptorDecryptor::new(&old_key)?;
+            let new = self.managed_encdec()?;
+            let result =
+                credit_cards::migrate_credit_card_key(&self.db.lock().unwrap().writer, &old, &new)?;
+            if result.scrubbed > 0 {
+                // Like `scrub_encrypted_data`, make the engine fetch the
+                // records again, so the scrubbed numbers come back.
+                crate::sync::credit_card::create_engine(self).reset_local_sync_data()?;
+            }
+            Ok(result)
+        }
+    }
+
     pub fn add_credit_card(&self, fields: UpdatableCreditCardFields) -> ApiResult<CreditCard> {
         handle_error! {
             let credit_card = credit_cards::add_credit_card(&self.db.lock().unwrap().writer, fields)?;
@@ -81,6 +172,18 @@ impl Store {
         }
     }
 
+    /// Adds a credit card, encrypting its number with the key manager's key.
+    pub fn add_credit_card_with_number(
+        &self,
+        fields: CreditCardFieldsWithNumber,
+    ) -> ApiResult<CreditCard> {
+        handle_error! {
+            let fields = self.encrypt_fields(fields)?;
+            let credit_card = credit_cards::add_credit_card(&self.db.lock().unwrap().writer, fields)?;
+            Ok(credit_card.into())
+        }
+    }
+
     pub fn get_credit_card(&self, guid: String) -> ApiResult<CreditCard> {
         handle_error! {
             let credit_card =
@@ -113,6 +216,22 @@ impl Store {
         }
     }
 
+    /// Updates a credit card, encrypting its number with the key manager's key.
+    pub fn update_credit_card_with_number(
+        &self,
+        guid: String,
+        fields: CreditCardFieldsWithNumber,
+    ) -> ApiResult<()> {
+        handle_error! {
+            let fields = self.encrypt_fields(fields)?;
+            credit_cards::update_credit_card(
+                &self.db.lock().unwrap().writer,
+                &Guid::new(&guid),
+                &fields,
+            )
+        }
+    }
+
     pub fn delete_credit_card(&self, guid: String) -> ApiResult<bool> {
         handle_error! {
             credit_cards::delete_credit_card(&self.db.lock().unwrap().writer, &Guid::new(&guid))
@@ -177,6 +296,15 @@ impl Store {
         }
     }
 
+    /// Vacuums and optimizes the database, when it needs it. Call this
+    /// during idle time.
+    pub fn run_maintenance(&self) -> ApiResult<()> {
+        handle_error! {
+            maintenance::run_maintenance(&self.db.lock().unwrap().writer, &AutofillMaintenancePolicy)?;
+            Ok(())
+        }
+    }
+
     // This allows the embedding app to say "make this instance available to
     // the sync manager". The implementation is more like "offer to sync mgr"
     // (thereby avoiding us needing to link with the sync manager) but
@@ -264,6 +392,95 @@ mod tests {
         Ok(())
     }
 
+    struct TestKeyManager(Option<String>);
+
+    impl KeyManager for TestKeyManager {
+        fn get_key(&self) -> Option<String> {
+            self.0.clone()
+        }
+    }
+
+    fn new_mem_store_with_key(key: Option<String>) -> Store {
+        Store {
+            db: Mutex::new(new_mem_db()),
+            key_manager: Some(Box::new(TestKeyManager(key))),
+        }
+    }
+
+    #[test]
+    fn test_key_manager() {
+        let key = crate::encryption::create_key().unwrap();
+        let store = Arc::new(new_mem_store_with_key(Some(key.clone())));
+        let cc_number_enc = store
+            .encrypt_credit_card_number("1234567812345678".to_string())
+            .expect("should encrypt");
+        assert_eq!(
+            crate::encryption::decrypt_string(key, cc_number_enc.clone()).unwrap(),
+            "1234567812345678"
+        );
+        assert_eq!(
+            store.decrypt_credit_card_number(cc_number_enc).unwrap(),
+            "1234567812345678"
+        );
+
+        let old_key = crate::encryption::create_key().unwrap();
+        let card = store
+            .add_credit_card(UpdatableCreditCardFields {
+                cc_name: "john deer".to_string(),
+                cc_number_enc: crate::encryption::encrypt_string(
+                    old_key.clone(),
+                    "1234123412341234".to_string(),
+                )
+                .unwrap(),
+                cc_number_last_4: "1234".to_string(),
+                cc_exp_month: 10,
+                cc_exp_year: 2025,
+                cc_type: "visa".to_string(),
+            })
+            .unwrap();
+        let result = Arc::clone(&store)
+            .migrate_credit_card_key(old_key)
+            .expect("should migrate");
+        assert_eq!(result.migrated, 1);
+        let card = store.get_credit_card(card.guid).unwrap();
+        assert_eq!(
+            store
+                .decrypt_credit_card_number(card.cc_number_enc)
+                .unwrap(),
+            "1234123412341234"
+        );
+
+        // The store encrypts the numbers of cards it's given in the clear.
+        let card = store
+            .add_credit_card_with_number(CreditCardFieldsWithNumber {
+                cc_name: "jane doe".to_string(),
+                cc_number: "5555 4444 3333 2222".to_string(),
+                cc_exp_month: 1,
+                cc_exp_year: 2030,
+                cc_type: "mastercard".to_string(),
+            })
+            .unwrap();
+        assert_eq!(card.cc_number_last_4, "2222");
+        assert_eq!(
+            store
+                .decrypt_credit_card_number(card.cc_number_enc)
+                .unwrap(),
+            "5555 4444 3333 2222"
+        );
+
+        // Without a key, we can't encrypt or decrypt.
+        let store = new_mem_store_with_key(None);
+        assert!(matches!(
+            store.encrypt_credit_card_number("1234567812345678".to_string()),
+            Err(AutofillApiError::CryptoError { .. })
+        ));
+        let store = Store::new_memory();
+        assert!(matches!(
+            store.decrypt_credit_card_number("ciphertext".to_string()),
+            Err(AutofillApiError::CryptoError { .. })
+        ));
+    }
+
     #[test]
     fn test_sync_manager_registration() {
         let store = Arc::new(Store::new_shared_memory("sync-mgr-test").unwrap());
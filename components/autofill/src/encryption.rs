@@ -27,10 +27,24 @@
 // multiple layers, from the app saying "sync now" all the way down to the
 // low level sync code.
 // To make life a little easier, we do that via a struct.
+//
+// Apps can instead give the store a `KeyManager`, which hands out the key
+// when we need it, so the key can live in the platform's keystore. The store
+// then encrypts and decrypts the numbers itself, and passes the key to the
+// credit-card sync engine, so the app doesn't need to hold the key at all.
 
 use crate::error::*;
 use error_support::handle_error;
 
+/// Supplies the key credit-card numbers are encrypted with. The key is a
+/// string created by `create_key()`, which the app keeps somewhere safe, such
+/// as the Android Keystore or the iOS Keychain.
+pub trait KeyManager: Send + Sync {
+    /// Returns the key, or `None` if it isn't available - for example,
+    /// because it was lost from the keystore.
+    fn get_key(&self) -> Option<String>;
+}
+
 // Rather than passing keys around everywhere we abstract the encryption
 // and decryption behind this struct.
 pub struct EncryptorDecryptor {
@@ -27,6 +27,9 @@ pub enum AutofillApiError {
     #[error("No record with guid exists: {guid}")]
     NoSuchRecord { guid: String },
 
+    #[error("Invalid credit card expiry: {reason}")]
+    InvalidCreditCardExpiry { reason: String },
+
     #[error("Unexpected Error: {reason}")]
     UnexpectedAutofillApiError { reason: String },
 }
@@ -66,8 +69,14 @@ pub enum Error {
     #[error("Missing local encryption key")]
     MissingEncryptionKey,
 
+    #[error("The key can't decrypt any credit card numbers")]
+    IncorrectEncryptionKey,
+
     #[error("No record with guid exists: {0}")]
     NoSuchRecord(String),
+
+    #[error("Invalid credit card expiry: {0}")]
+    InvalidCreditCardExpiry(String),
 }
 
 // Define how our internal errors are handled and converted to external errors
@@ -132,10 +141,22 @@ impl GetErrorHandling for Error {
             })
             .report_error("autofill-missing-encryption-key"),
 
+            Self::IncorrectEncryptionKey => ErrorHandling::convert(AutofillApiError::CryptoError {
+                reason: "Incorrect encryption key".to_string(),
+            })
+            .log_warning(),
+
             Self::NoSuchRecord(guid) => {
                 ErrorHandling::convert(AutofillApiError::NoSuchRecord { guid: guid.clone() })
                     .log_warning()
             }
+
+            // This is caused by user input, so there's nothing to report.
+            Self::InvalidCreditCardExpiry(reason) => {
+                ErrorHandling::convert(AutofillApiError::InvalidCreditCardExpiry {
+                    reason: reason.clone(),
+                })
+            }
         }
     }
 }
@@ -6,6 +6,8 @@
 #![allow(unknown_lints)]
 #![warn(rust_2018_idioms)]
 
+pub mod address_metadata;
+pub mod address_parser;
 pub mod db;
 pub mod encryption;
 pub mod error;
@@ -15,10 +17,22 @@ pub mod sync;
 pub use crate::db::store::get_registered_sync_engine;
 
 // Expose stuff needed by the uniffi generated code.
+use crate::address_parser::parse_address;
 use crate::db::models::address::*;
 use crate::db::models::credit_card::*;
 use crate::db::store::Store;
-use crate::encryption::{create_key, decrypt_string, encrypt_string};
+use crate::encryption::{create_key, decrypt_string, encrypt_string, KeyManager};
 pub use error::{ApiResult, AutofillApiError, Error, Result};
 
+// The exposed API for parsing expiries.
+fn parse_credit_card_expiry(input: &str) -> ApiResult<CreditCardExpiry> {
+    error_support::handle_error! {
+        CreditCardExpiry::parse(input)
+    }
+}
+
+fn is_credit_card_expired(card: CreditCard, now: i64) -> bool {
+    card.is_expired(now)
+}
+
 include!(concat!(env!("OUT_DIR"), "/autofill.uniffi.rs"));
@@ -52,6 +52,15 @@ impl SyncEngineStorageImpl<InternalAddress> for AddressesEngineStorageImpl {
         Ok(())
     }
 
+    fn wipe_storage(&self, tx: &Transaction<'_>) -> Result<()> {
+        tx.execute_batch(
+            "DELETE FROM addresses_data;
+            DELETE FROM addresses_mirror;
+            DELETE FROM addresses_tombstones;",
+        )?;
+        Ok(())
+    }
+
     fn get_outgoing_impl(
         &self,
         enc_key: &Option<String>,
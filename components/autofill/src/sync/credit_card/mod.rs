@@ -57,6 +57,15 @@ impl SyncEngineStorageImpl<InternalCreditCard> for CreditCardsEngineStorageImpl
         Ok(())
     }
 
+    fn wipe_storage(&self, tx: &Transaction<'_>) -> Result<()> {
+        tx.execute_batch(
+            "DELETE FROM credit_cards_data;
+            DELETE FROM credit_cards_mirror;
+            DELETE FROM credit_cards_tombstones;",
+        )?;
+        Ok(())
+    }
+
     fn get_outgoing_impl(
         &self,
         enc_key: &Option<String>,
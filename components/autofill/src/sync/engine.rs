@@ -36,6 +36,8 @@ pub trait SyncEngineStorageImpl<T> {
         enc_key: &Option<String>,
     ) -> Result<Box<dyn ProcessIncomingRecordImpl<Record = T>>>;
     fn reset_storage(&self, conn: &Transaction<'_>) -> Result<()>;
+    // Deletes all local records, including the sync metadata for them.
+    fn wipe_storage(&self, conn: &Transaction<'_>) -> Result<()>;
     fn get_outgoing_impl(
         &self,
         enc_key: &Option<String>,
@@ -75,6 +77,14 @@ impl<T> ConfigSyncEngine<T> {
         let key = format!("{}.{}", self.config.namespace, tail);
         crate::db::store::delete_meta(conn, &key)
     }
+    // The key from the store's key manager wins over one set by the sync
+    // manager, which apps only pass when they manage the key themselves.
+    fn local_enc_key(&self) -> Option<String> {
+        self.store
+            .managed_key()
+            .or_else(|| self.local_enc_key.clone())
+    }
+
     // Reset the local sync data so the next server request fetches all records.
     pub fn reset_local_sync_data(&self) -> Result<()> {
         let db = &self.store.db.lock().unwrap();
@@ -114,8 +124,9 @@ impl<T: SyncRecord + std::fmt::Debug> SyncEngine for ConfigSyncEngine<T> {
         let timestamp = inbound.timestamp;
         let num_incoming = inbound.changes.len() as u32;
         let tx = db.writer.unchecked_transaction()?;
-        let incoming_impl = self.storage_impl.get_incoming_impl(&self.local_enc_key)?;
-        let outgoing_impl = self.storage_impl.get_outgoing_impl(&self.local_enc_key)?;
+        let enc_key = self.local_enc_key();
+        let incoming_impl = self.storage_impl.get_incoming_impl(&enc_key)?;
+        let outgoing_impl = self.storage_impl.get_outgoing_impl(&enc_key)?;
 
         // The first step in the "apply incoming" process for syncing autofill records.
         incoming_impl.stage_incoming(&tx, inbound.changes, &signal)?;
@@ -162,7 +173,7 @@ impl<T: SyncRecord + std::fmt::Debug> SyncEngine for ConfigSyncEngine<T> {
             &(new_timestamp.as_millis() as i64),
         )?;
         let tx = db.writer.unchecked_transaction()?;
-        let outgoing_impl = self.storage_impl.get_outgoing_impl(&self.local_enc_key)?;
+        let outgoing_impl = self.storage_impl.get_outgoing_impl(&self.local_enc_key())?;
         outgoing_impl.finish_synced_items(&tx, records_synced)?;
         tx.commit()?;
         Ok(())
@@ -223,9 +234,17 @@ impl<T: SyncRecord + std::fmt::Debug> SyncEngine for ConfigSyncEngine<T> {
     }
 
     fn wipe(&self) -> anyhow::Result<()> {
-        log::warn!("not implemented as there isn't a valid use case for it");
+        let db = &self.store.db.lock().unwrap();
+        let tx = db.unchecked_transaction()?;
+        self.storage_impl.wipe_storage(&tx)?;
+        tx.commit()?;
         Ok(())
     }
+
+    fn wipe_local(&self) -> anyhow::Result<()> {
+        // `wipe` deletes the tombstones along with everything else.
+        self.wipe()
+    }
 }
 
 #[cfg(test)]
@@ -419,4 +438,37 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_engine_wipe() -> Result<()> {
+        let engine = create_engine();
+        let encdec = EncryptorDecryptor::new_test_key();
+
+        let cc = InternalCreditCard {
+            guid: Guid::random(),
+            cc_name: "Ms Jane Doe".to_string(),
+            cc_number_enc: encdec.encrypt("12341232412341234")?,
+            cc_number_last_4: "1234".to_string(),
+            cc_exp_month: 12,
+            cc_exp_year: 2021,
+            cc_type: "visa".to_string(),
+            ..Default::default()
+        };
+        {
+            let db = &engine.store.db.lock().unwrap();
+            let tx = db.writer.unchecked_transaction()?;
+            add_internal_credit_card(&tx, &cc)?;
+            test_insert_mirror_record(&tx, cc.into_sync_payload(&encdec));
+            insert_tombstone_record(&tx, Guid::random().to_string())?;
+            tx.commit()?;
+        }
+
+        engine.wipe().expect("should work");
+
+        let conn = &engine.store.db.lock().unwrap().writer;
+        assert!(get_all(conn, "credit_cards_data".to_string())?.is_empty());
+        assert!(get_all(conn, "credit_cards_mirror".to_string())?.is_empty());
+        assert!(get_all(conn, "credit_cards_tombstones".to_string())?.is_empty());
+        Ok(())
+    }
 }
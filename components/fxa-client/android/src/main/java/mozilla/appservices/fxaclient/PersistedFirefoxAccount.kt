@@ -81,6 +81,33 @@ class PersistedFirefoxAccount(inner: FirefoxAccount, persistCallback: PersistCal
         this.persistCallback = null
     }
 
+    /**
+     * Registers a listener to be told about changes to the account's state, such as the user
+     * needing to sign in again, their profile changing, or a command arriving from another
+     * device. This replaces any previously registered listener.
+     *
+     * The listener is called on the thread of the method which noticed the change.
+     */
+    fun registerStateListener(listener: AccountStateListener) {
+        this.inner.registerStateListener(listener)
+    }
+
+    /**
+     * Unregisters any previously registered AccountStateListener.
+     */
+    fun unregisterStateListener() {
+        this.inner.unregisterStateListener()
+    }
+
+    /**
+     * Returns the account's state, as of the last time it was checked.
+     *
+     * This does not make network requests, and can be used on the main thread.
+     */
+    fun getStateSnapshot(): AccountStateSnapshot {
+        return this.inner.getStateSnapshot()
+    }
+
     private fun tryPersistState() {
         this.persistCallback?.let {
             val json: String
@@ -250,6 +277,47 @@ class PersistedFirefoxAccount(inner: FirefoxAccount, persistCallback: PersistCal
         }
     }
 
+    /**
+     * Like [getAccessToken], but returns a cached token for as long as it's valid,
+     * instead of replacing it before it expires. This only performs network requests
+     * if there's no valid cached token.
+     *
+     * @param scope Single OAuth scope (no spaces) for which the client wants access
+     * @param ttl time in seconds for which a new token will be valid
+     * @return [AccessTokenInfo] that stores the token, along with its scopes and keys when complete
+     * @throws FxaException.Unauthorized We couldn't provide an access token
+     * for this scope.
+     */
+    fun getAccessTokenPreferCached(scope: String, ttl: Long? = null): AccessTokenInfo {
+        try {
+            return this.inner.getAccessTokenPreferCached(scope, ttl)
+        } finally {
+            this.tryPersistState()
+        }
+    }
+
+    /**
+     * Returns the cached access token for the given scope, if there's one which is
+     * still valid. This never performs network requests, so it's safe to call when
+     * the device is offline.
+     *
+     * @param scope Single OAuth scope (no spaces) for which the client wants access
+     * @return [AccessTokenInfo], or null if there's no valid cached token
+     */
+    fun getCachedAccessToken(scope: String): AccessTokenInfo? {
+        return this.inner.getCachedAccessToken(scope)
+    }
+
+    /**
+     * Check with the server whether our tokens are still active.
+     *
+     * This performs network requests, and should not be used on the main thread.
+     * It doesn't change the account state, even if the tokens are no longer active.
+     *
+     * @return [AuthorizationInfo] - if it isn't `active`, the user needs to sign in again.
+     * @throws FxaException.Network The status couldn't be checked. This says nothing about
+     * the tokens, so the user should not be disconnected because of it.
+     */
     fun checkAuthorizationStatus(): AuthorizationInfo {
         return this.inner.checkAuthorizationStatus()
     }
@@ -506,6 +574,18 @@ class PersistedFirefoxAccount(inner: FirefoxAccount, persistCallback: PersistCal
         this.inner.sendSingleTab(targetDeviceId, title, url)
     }
 
+    /**
+     * Send one or more tabs to another device identified by its device ID, in a single command.
+     *
+     * This performs network requests, and should not be used on the main thread.
+     *
+     * @param targetDeviceId The target Device ID
+     * @param tabs The tabs to send. Older devices only open the first tab.
+     */
+    fun sendTabs(targetDeviceId: String, tabs: List<SentTab>) {
+        this.inner.sendTabs(targetDeviceId, tabs)
+    }
+
     /**
      * Gather any telemetry which has been collected internally and return
      * the result as a JSON string.
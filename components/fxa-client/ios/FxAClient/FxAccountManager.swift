@@ -570,12 +570,8 @@ open class FxAccountManager {
                     // succeed.
 
                     func onError() {
-                        // We are either certainly in the scenario (2), or were unable to determine
-                        // our connectivity state. Let's assume we need to re-authenticate.
-                        // This uncertainty about real state means that, hopefully rarely,
-                        // we will disconnect users that hit transient network errors during
-                        // an authorization check.
-                        // See https://github.com/mozilla-mobile/android-components/issues/3347
+                        // We are either certainly in the scenario (2), or got an unexpected
+                        // error while checking. Let's assume we need to re-authenticate.
                         FxALog.error("Unable to recover from an auth problem.")
                         DispatchQueue.main.async {
                             NotificationCenter.default.post(
@@ -596,6 +592,14 @@ open class FxAccountManager {
                         // Make sure we're back on track by re-requesting the profile access token.
                         _ = try account.getAccessToken(scope: OAuthScope.profile)
                         return .recoveredFromAuthenticationProblem
+                    } catch FxaError.Network {
+                        // We couldn't reach the server, so we don't know whether we're in
+                        // scenario (2). Don't disconnect the user for a transient network
+                        // failure - if the problem persists, the next authentication error
+                        // will bring us back here.
+                        // See https://github.com/mozilla-mobile/android-components/issues/3347
+                        FxALog.info("Network error while checking the authorization status.")
+                        return .recoveredFromAuthenticationProblem
                     } catch {
                         onError()
                     }
@@ -49,6 +49,24 @@ class PersistedFirefoxAccount {
         persistCallback = nil
     }
 
+    /// Registers a listener to be told about changes to the account's state, such as
+    /// the user needing to sign in again, their profile changing, or a command arriving
+    /// from another device. This replaces any previously registered listener.
+    public func registerStateListener(_ listener: AccountStateListener) {
+        inner.registerStateListener(listener: listener)
+    }
+
+    /// Unregisters any previously registered state listener.
+    public func unregisterStateListener() {
+        inner.unregisterStateListener()
+    }
+
+    /// Returns the account's state, as of the last time it was checked. This doesn't
+    /// make network requests.
+    public func getStateSnapshot() -> AccountStateSnapshot {
+        return inner.getStateSnapshot()
+    }
+
     public static func fromJSON(data: String) throws -> PersistedFirefoxAccount {
         return PersistedFirefoxAccount(inner: try FirefoxAccount.fromJson(data: data))
     }
@@ -193,6 +211,12 @@ class PersistedFirefoxAccount {
         }
     }
 
+    public func sendTabs(targetDeviceId: String, tabs: [SentTab]) throws {
+        return try notifyAuthErrors {
+            try self.inner.sendTabs(targetDeviceId: targetDeviceId, tabs: tabs)
+        }
+    }
+
     public func getTokenServerEndpointURL() throws -> URL {
         return URL(string: try inner.getTokenServerEndpointUrl())!
     }
@@ -216,6 +240,22 @@ class PersistedFirefoxAccount {
         }
     }
 
+    /// Like `getAccessToken`, but returns a cached token for as long as it's valid,
+    /// instead of replacing it before it expires, so it never waits on the network
+    /// when a valid cached token exists.
+    public func getAccessTokenPreferCached(scope: String, ttl: UInt64? = nil) throws -> AccessTokenInfo {
+        defer { tryPersistState() }
+        return try notifyAuthErrors {
+            try self.inner.getAccessTokenPreferCached(scope: scope, ttl: ttl == nil ? nil : Int64(clamping: ttl!))
+        }
+    }
+
+    /// Returns the cached access token for `scope` if there's one which is still valid,
+    /// without using the network.
+    public func getCachedAccessToken(scope: String) throws -> AccessTokenInfo? {
+        return try self.inner.getCachedAccessToken(scope: scope)
+    }
+
     public func getSessionToken() throws -> String {
         defer { tryPersistState() }
         return try notifyAuthErrors {
@@ -22,6 +22,10 @@ use super::super::{device::Device, error::*, scoped_keys::ScopedKey, scopes, tel
 
 pub const COMMAND_NAME: &str = "https://identity.mozilla.com/cmd/open-uri";
 
+// Selected text is a preview rather than the full selection - it's encrypted
+// and stored by the FxA server, and there's a limit on the size of commands.
+const MAX_SELECTED_TEXT_LENGTH: usize = 512;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EncryptedSendTabPayload {
     /// URL Safe Base 64 encrypted send-tab payload.
@@ -37,41 +41,119 @@ impl EncryptedSendTabPayload {
     }
 }
 
+// The first tab is at the top level of the payload, where it always has been,
+// so that older clients which don't know about `additionalTabs` still open it.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SendTabPayload {
-    pub entries: Vec<TabHistoryEntry>,
+    #[serde(flatten)]
+    pub tab: SentTab,
+    #[serde(
+        rename = "additionalTabs",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub additional_tabs: Vec<SentTab>,
     #[serde(rename = "flowID", default)]
     pub flow_id: String,
     #[serde(rename = "streamID", default)]
     pub stream_id: String,
 }
 
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SentTab {
+    pub entries: Vec<TabHistoryEntry>,
+    #[serde(rename = "iconURL", default, skip_serializing_if = "Option::is_none")]
+    pub icon_url: Option<String>,
+    #[serde(
+        rename = "selectedText",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub selected_text: Option<String>,
+}
+
 impl From<SendTabPayload> for crate::SendTabPayload {
     fn from(payload: SendTabPayload) -> Self {
         crate::SendTabPayload {
-            entries: payload.entries.into_iter().map(From::from).collect(),
+            entries: payload.tab.entries.into_iter().map(From::from).collect(),
+            icon_url: payload.tab.icon_url,
+            selected_text: payload.tab.selected_text,
+            additional_tabs: payload
+                .additional_tabs
+                .into_iter()
+                .map(From::from)
+                .collect(),
             flow_id: payload.flow_id,
             stream_id: payload.stream_id,
         }
     }
 }
 
+impl From<SentTab> for crate::SentTab {
+    fn from(tab: SentTab) -> Self {
+        crate::SentTab {
+            entries: tab.entries.into_iter().map(From::from).collect(),
+            icon_url: tab.icon_url,
+            selected_text: tab.selected_text,
+        }
+    }
+}
+
+impl From<crate::SentTab> for SentTab {
+    fn from(tab: crate::SentTab) -> Self {
+        SentTab {
+            entries: tab
+                .entries
+                .into_iter()
+                .map(|e| TabHistoryEntry {
+                    title: e.title,
+                    url: e.url,
+                })
+                .collect(),
+            icon_url: tab.icon_url.filter(|url| !url.is_empty()),
+            selected_text: tab
+                .selected_text
+                .filter(|text| !text.is_empty())
+                .map(|text| truncate_chars(text, MAX_SELECTED_TEXT_LENGTH)),
+        }
+    }
+}
+
+fn truncate_chars(mut s: String, max_chars: usize) -> String {
+    if let Some((index, _)) = s.char_indices().nth(max_chars) {
+        s.truncate(index);
+    }
+    s
+}
+
 impl SendTabPayload {
     pub fn single_tab(title: &str, url: &str) -> (Self, telemetry::SentCommand) {
+        let tab = SentTab {
+            entries: vec![TabHistoryEntry {
+                title: title.to_string(),
+                url: url.to_string(),
+            }],
+            ..Default::default()
+        };
+        Self::multiple_tabs(tab, vec![])
+    }
+
+    pub fn multiple_tabs(
+        tab: SentTab,
+        additional_tabs: Vec<SentTab>,
+    ) -> (Self, telemetry::SentCommand) {
         let sent_telemetry: telemetry::SentCommand = Default::default();
         (
             SendTabPayload {
-                entries: vec![TabHistoryEntry {
-                    title: title.to_string(),
-                    url: url.to_string(),
-                }],
+                tab,
+                additional_tabs,
                 flow_id: sent_telemetry.flow_id.clone(),
                 stream_id: sent_telemetry.stream_id.clone(),
             },
             sent_telemetry,
         )
     }
-    fn encrypt(&self, keys: PublicSendTabKeys) -> Result<EncryptedSendTabPayload> {
+    pub(crate) fn encrypt(&self, keys: PublicSendTabKeys) -> Result<EncryptedSendTabPayload> {
         rc_crypto::ensure_initialized();
         let bytes = serde_json::to_vec(&self)?;
         let public_key = base64::decode_config(&keys.public_key, base64::URL_SAFE_NO_PAD)?;
@@ -262,8 +344,72 @@ mod tests {
         assert_ne!(telem.flow_id, telem.stream_id);
         let p2: SendTabPayload = serde_json::from_str(&json).expect("should work");
         // no 'PartialEq' derived so check each field individually...
-        assert_eq!(payload.entries[0].url, "http://example.com".to_string());
+        assert_eq!(payload.tab.entries[0].url, "http://example.com".to_string());
         assert_eq!(payload.flow_id, p2.flow_id);
         assert_eq!(payload.stream_id, p2.stream_id);
     }
+
+    #[test]
+    fn test_multiple_tabs_payload() {
+        let tab = |url: &str, selected_text: Option<String>| crate::SentTab {
+            entries: vec![crate::TabHistoryEntry {
+                title: "title".to_string(),
+                url: url.to_string(),
+            }],
+            icon_url: Some(format!("{}/favicon.ico", url)),
+            selected_text,
+        };
+        let (payload, _) = SendTabPayload::multiple_tabs(
+            tab("https://example.com", Some("é".repeat(1000))).into(),
+            vec![tab("https://example.org", Some("".to_string())).into()],
+        );
+        let json = serde_json::to_value(&payload).expect("should work");
+        // The first tab is where older clients expect it.
+        assert_eq!(json["entries"][0]["url"], "https://example.com");
+        assert_eq!(json["iconURL"], "https://example.com/favicon.ico");
+        assert_eq!(
+            json["selectedText"].as_str().unwrap().chars().count(),
+            MAX_SELECTED_TEXT_LENGTH
+        );
+        assert_eq!(
+            json["additionalTabs"][0]["entries"][0]["url"],
+            "https://example.org"
+        );
+        assert!(json["additionalTabs"][0].get("selectedText").is_none());
+
+        let p2: crate::SendTabPayload = serde_json::from_value::<SendTabPayload>(json)
+            .expect("should work")
+            .into();
+        assert_eq!(p2.entries[0].url, "https://example.com");
+        assert_eq!(p2.additional_tabs.len(), 1);
+        assert_eq!(p2.additional_tabs[0].entries[0].url, "https://example.org");
+        assert_eq!(
+            p2.additional_tabs[0].icon_url.as_deref(),
+            Some("https://example.org/favicon.ico")
+        );
+        assert_eq!(p2.additional_tabs[0].selected_text, None);
+    }
+
+    #[test]
+    fn test_old_payload_format() {
+        // What older clients send, and what they expect to receive.
+        let (payload, _) = SendTabPayload::single_tab("title", "http://example.com");
+        let json = serde_json::to_value(&payload).expect("should work");
+        let mut keys: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort_unstable();
+        assert_eq!(keys, vec!["entries", "flowID", "streamID"]);
+
+        let old = r#"{"entries": [{"title": "t", "url": "https://example.com"}], "flowID": "f"}"#;
+        let payload: crate::SendTabPayload = serde_json::from_str::<SendTabPayload>(old)
+            .expect("should work")
+            .into();
+        assert_eq!(payload.entries.len(), 1);
+        assert_eq!(payload.icon_url, None);
+        assert!(payload.additional_tabs.is_empty());
+    }
 }
@@ -41,6 +41,16 @@ mod util;
 
 type FxAClient = dyn http_client::FxAClient + Sync + Send;
 
+// The tests of the public API mock the FxA server, too.
+#[cfg(test)]
+pub(crate) use self::{
+    http_client::{
+        CommandData, FxAClientMock, IntrospectResponse, PendingCommand, PendingCommandsResponse,
+        ProfileResponse, ResponseAndETag,
+    },
+    oauth::AccessTokenInfo,
+};
+
 // FIXME: https://github.com/myelin-ai/mockiato/issues/106.
 #[cfg(test)]
 unsafe impl<'a> Send for http_client::FxAClientMock<'a> {}
@@ -91,6 +101,7 @@ impl FirefoxAccount {
             last_seen_profile: None,
             access_token_cache: HashMap::new(),
             in_flight_migration: None,
+            needs_reauthentication: false,
         })
     }
 
@@ -134,6 +145,18 @@ impl FirefoxAccount {
         state_persistence::state_to_json(&self.state)
     }
 
+    /// Whether we're connected to the user's account, based on the persisted
+    /// state. This never makes a network request.
+    pub fn get_auth_state(&self) -> crate::AccountAuthState {
+        if self.state.refresh_token.is_none() {
+            crate::AccountAuthState::Disconnected
+        } else if self.state.needs_reauthentication {
+            crate::AccountAuthState::NeedsReauthentication
+        } else {
+            crate::AccountAuthState::Authenticated
+        }
+    }
+
     /// Clear the attached clients and devices cache
     pub fn clear_devices_and_attached_clients_cache(&mut self) {
         self.attached_clients_cache = None;
@@ -369,6 +392,7 @@ mod tests {
                 token: "profiletok".to_string(),
                 key: None,
                 expires_at: u64::max_value(),
+                issued_at: 0,
             },
         );
 
@@ -26,6 +26,14 @@ use url::Url;
 // If a cached token has less than `OAUTH_MIN_TIME_LEFT` seconds left to live,
 // it will be considered already expired.
 const OAUTH_MIN_TIME_LEFT: u64 = 60;
+// If a cached token has less than `OAUTH_REFRESH_TIME_LEFT` seconds left to live,
+// `get_access_token` will try to replace it before it expires. Short-lived tokens
+// are replaced once three quarters of their lifetime has passed instead.
+const OAUTH_REFRESH_TIME_LEFT: u64 = 5 * 60;
+// Expiry times are computed with the local clock when a token is issued, so they
+// can't be trusted if the clock has gone backwards since. We notice that when a
+// token was issued more than `OAUTH_MAX_CLOCK_SKEW` seconds in the future.
+const OAUTH_MAX_CLOCK_SKEW: u64 = 60;
 // Special redirect urn based on the OAuth native spec, signals that the
 // WebChannel flow is used
 pub const OAUTH_WEBCHANNEL_REDIRECT: &str = "urn:ietf:wg:oauth:2.0:oob:oauth-redirect-webchannel";
@@ -41,14 +49,80 @@ impl FirefoxAccount {
     ///
     /// **💾 This method may alter the persisted account state.**
     pub fn get_access_token(&mut self, scope: &str, ttl: Option<u64>) -> Result<AccessTokenInfo> {
+        self.get_access_token_with_policy(scope, ttl, AccessTokenCachePolicy::RefreshEarly)
+    }
+
+    /// Like `get_access_token`, but returns a cached token for as long as it's
+    /// valid, instead of replacing it early. This only makes a network request if
+    /// there's no valid cached token.
+    ///
+    /// **💾 This method may alter the persisted account state.**
+    pub fn get_access_token_prefer_cached(
+        &mut self,
+        scope: &str,
+        ttl: Option<u64>,
+    ) -> Result<AccessTokenInfo> {
+        self.get_access_token_with_policy(scope, ttl, AccessTokenCachePolicy::PreferCached)
+    }
+
+    /// Returns the cached access token for `scope`, if there's one which is still
+    /// valid. This never makes a network request.
+    pub fn get_cached_access_token(&self, scope: &str) -> Result<Option<AccessTokenInfo>> {
         if scope.contains(' ') {
             return Err(ErrorKind::MultipleScopesRequested.into());
         }
-        if let Some(oauth_info) = self.state.access_token_cache.get(scope) {
-            if oauth_info.expires_at > util::now_secs() + OAUTH_MIN_TIME_LEFT {
-                return Ok(oauth_info.clone());
+        Ok(self.valid_cached_access_token(scope).map(|(info, _)| info))
+    }
+
+    fn valid_cached_access_token(
+        &self,
+        scope: &str,
+    ) -> Option<(AccessTokenInfo, CachedTokenState)> {
+        let info = self.state.access_token_cache.get(scope)?;
+        match info.state_at(util::now_secs()) {
+            CachedTokenState::Expired => None,
+            state => Some((info.clone(), state)),
+        }
+    }
+
+    fn get_access_token_with_policy(
+        &mut self,
+        scope: &str,
+        ttl: Option<u64>,
+        policy: AccessTokenCachePolicy,
+    ) -> Result<AccessTokenInfo> {
+        if scope.contains(' ') {
+            return Err(ErrorKind::MultipleScopesRequested.into());
+        }
+        let cached = self.valid_cached_access_token(scope);
+        match cached {
+            Some((info, CachedTokenState::Fresh)) => return Ok(info),
+            Some((info, CachedTokenState::Expiring))
+                if policy == AccessTokenCachePolicy::PreferCached =>
+            {
+                return Ok(info)
             }
+            _ => {}
         }
+        let result = self.fetch_access_token(scope, ttl);
+        if let Err(ref e) = result {
+            if let ErrorKind::RemoteError { code: 401, .. } = e.kind() {
+                // The server no longer accepts our refresh token.
+                self.state.needs_reauthentication = true;
+            }
+        }
+        match (result, cached) {
+            // The cached token is still usable, so a server we can't reach
+            // shouldn't stop the caller from using it.
+            (Err(e), Some((info, _))) if is_transient_error(&e) => {
+                log::warn!("Couldn't refresh an expiring access token: {}", e);
+                Ok(info)
+            }
+            (result, _) => result,
+        }
+    }
+
+    fn fetch_access_token(&mut self, scope: &str, ttl: Option<u64>) -> Result<AccessTokenInfo> {
         let resp = match self.state.refresh_token {
             Some(ref refresh_token) => {
                 if refresh_token.scopes.contains(scope) {
@@ -80,6 +154,7 @@ impl FirefoxAccount {
             token: resp.access_token,
             key: self.state.scoped_keys.get(scope).cloned(),
             expires_at,
+            issued_at: since_epoch.as_secs(),
         };
         self.state
             .access_token_cache
@@ -96,6 +171,12 @@ impl FirefoxAccount {
     }
 
     /// Check whether user is authorized using our refresh token.
+    ///
+    /// This doesn't change the account state, even if the token is no longer
+    /// active - callers decide what to do about it.
+    /// Note that failing to reach the server is an error (rather than a result
+    /// with `active: false`), because it doesn't tell us anything about the
+    /// token.
     pub fn check_authorization_status(&mut self) -> Result<IntrospectInfo> {
         let resp = match self.state.refresh_token {
             Some(ref refresh_token) => {
@@ -414,6 +495,7 @@ impl FirefoxAccount {
         // When our keys change, we might need to re-register device capabilities with the server.
         // Ensure that this happens on the next call to ensure_capabilities.
         self.state.device_capabilities.clear();
+        self.state.needs_reauthentication = false;
         Ok(())
     }
 
@@ -451,6 +533,7 @@ impl FirefoxAccount {
         // When our keys change, we might need to re-register device capabilities with the server.
         // Ensure that this happens on the next call to ensure_capabilities.
         self.state.device_capabilities.clear();
+        self.state.needs_reauthentication = false;
         Ok(())
     }
 
@@ -574,6 +657,55 @@ pub struct AccessTokenInfo {
     pub token: String,
     pub key: Option<ScopedKey>,
     pub expires_at: u64, // seconds since epoch
+    // Tokens cached before this was added don't have it, and we treat them
+    // as if they were issued at the epoch.
+    #[serde(default)]
+    pub issued_at: u64, // seconds since epoch
+}
+
+impl AccessTokenInfo {
+    fn state_at(&self, now: u64) -> CachedTokenState {
+        if self.issued_at > now + OAUTH_MAX_CLOCK_SKEW
+            || self.expires_at <= now + OAUTH_MIN_TIME_LEFT
+        {
+            return CachedTokenState::Expired;
+        }
+        let lifetime = self.expires_at.saturating_sub(self.issued_at);
+        if self.expires_at <= now + OAUTH_REFRESH_TIME_LEFT.min(lifetime / 4) {
+            CachedTokenState::Expiring
+        } else {
+            CachedTokenState::Fresh
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CachedTokenState {
+    Fresh,
+    /// Still valid, but should be replaced soon.
+    Expiring,
+    Expired,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AccessTokenCachePolicy {
+    /// Replace expiring tokens, falling back to the cached token if that fails.
+    RefreshEarly,
+    /// Use any valid cached token.
+    PreferCached,
+}
+
+// Errors which say nothing about whether our tokens are still good.
+fn is_transient_error(e: &Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::RequestError(_)
+            | ErrorKind::BackoffError(_)
+            | ErrorKind::RemoteError {
+                code: 500..=599,
+                ..
+            }
+    )
 }
 
 impl TryFrom<AccessTokenInfo> for crate::AccessTokenInfo {
@@ -594,6 +726,7 @@ impl std::fmt::Debug for AccessTokenInfo {
             .field("scope", &self.scope)
             .field("key", &self.key)
             .field("expires_at", &self.expires_at)
+            .field("issued_at", &self.issued_at)
             .finish()
     }
 }
@@ -622,6 +755,13 @@ mod tests {
         pub fn set_session_token(&mut self, session_token: &str) {
             self.state.session_token = Some(session_token.to_owned());
         }
+
+        pub fn set_refresh_token(&mut self, refresh_token: &str) {
+            self.state.refresh_token = Some(RefreshToken {
+                token: refresh_token.to_owned(),
+                scopes: std::collections::HashSet::new(),
+            });
+        }
     }
 
     #[test]
@@ -956,6 +1096,56 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_check_authorization_status_errors() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+
+        // Without a refresh token, we know we need to re-authenticate.
+        let err = fxa.check_authorization_status().unwrap_err();
+        assert!(matches!(
+            crate::FxaError::from(err),
+            crate::FxaError::Authentication
+        ));
+
+        fxa.state.refresh_token = Some(RefreshToken {
+            token: "refresh_token".to_owned(),
+            scopes: std::collections::HashSet::new(),
+        });
+        let mut client = FxAClientMock::new();
+        client
+            .expect_check_refresh_token_status(mockiato::Argument::any, |token| {
+                token.partial_eq("refresh_token")
+            })
+            .returns_once(Err(ErrorKind::RequestError(viaduct::Error::NetworkError(
+                "offline".to_string(),
+            ))
+            .into()));
+        client
+            .expect_check_refresh_token_status(mockiato::Argument::any, |token| {
+                token.partial_eq("refresh_token")
+            })
+            .returns_once(Ok(IntrospectResponse { active: false }));
+        client.expect_check_refresh_token_status_calls_in_order();
+        fxa.set_client(Arc::new(client));
+
+        // A network failure tells us nothing about the tokens, and is reported
+        // differently to an authentication problem.
+        let err = fxa.check_authorization_status().unwrap_err();
+        assert!(matches!(
+            crate::FxaError::from(err),
+            crate::FxaError::Network
+        ));
+        assert!(fxa.state.refresh_token.is_some());
+
+        // The check doesn't change the account state when the tokens are no
+        // longer active - it's up to the application to decide what to do.
+        let auth_status = fxa.check_authorization_status().unwrap();
+        assert!(!auth_status.active);
+        assert!(fxa.state.refresh_token.is_some());
+        assert_eq!(fxa.get_auth_state(), crate::AccountAuthState::Authenticated);
+    }
+
     use crate::internal::scopes;
 
     #[test]
@@ -1106,4 +1296,114 @@ mod tests {
             panic!("Should return an error that specifies the scope that is not in the state");
         }
     }
+
+    fn cached_token(token: &str, issued_at: u64, expires_at: u64) -> AccessTokenInfo {
+        AccessTokenInfo {
+            scope: "profile".to_string(),
+            token: token.to_string(),
+            key: None,
+            expires_at,
+            issued_at,
+        }
+    }
+
+    #[test]
+    fn test_cached_token_state() {
+        let now = 1_000_000;
+        // An hour-long token is refreshed in its last five minutes.
+        let token = cached_token("tok", now - 3000, now + 600);
+        assert_eq!(token.state_at(now), CachedTokenState::Fresh);
+        assert_eq!(token.state_at(now + 300), CachedTokenState::Expiring);
+        assert_eq!(token.state_at(now + 540), CachedTokenState::Expired);
+        // A 10 minute token is refreshed in its last quarter.
+        let token = cached_token("tok", now, now + 600);
+        assert_eq!(token.state_at(now + 440), CachedTokenState::Fresh);
+        assert_eq!(token.state_at(now + 450), CachedTokenState::Expiring);
+        // The clock went backwards since the token was issued, so we don't
+        // know how long it has left.
+        assert_eq!(token.state_at(now - 61), CachedTokenState::Expired);
+        assert_eq!(token.state_at(now - 60), CachedTokenState::Fresh);
+        // Tokens cached before we stored the issue time.
+        let token = cached_token("tok", 0, now + 600);
+        assert_eq!(token.state_at(now), CachedTokenState::Fresh);
+        assert_eq!(token.state_at(now + 300), CachedTokenState::Expiring);
+    }
+
+    #[test]
+    fn test_get_access_token_refreshes_early() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        let mut refresh_token_scopes = std::collections::HashSet::new();
+        refresh_token_scopes.insert("profile".to_owned());
+        fxa.state.refresh_token = Some(RefreshToken {
+            token: "refreshtok".to_owned(),
+            scopes: refresh_token_scopes,
+        });
+        let now = util::now_secs();
+        fxa.add_cached_token("profile", cached_token("expiring", now - 3500, now + 100));
+
+        let mut client = FxAClientMock::new();
+        client
+            .expect_create_access_token_using_refresh_token(
+                mockiato::Argument::any,
+                |token| token.partial_eq("refreshtok"),
+                mockiato::Argument::any,
+                mockiato::Argument::any,
+            )
+            .returns_once(Err(ErrorKind::RequestError(viaduct::Error::NetworkError(
+                "offline".to_string(),
+            ))
+            .into()));
+        client
+            .expect_create_access_token_using_refresh_token(
+                mockiato::Argument::any,
+                |token| token.partial_eq("refreshtok"),
+                mockiato::Argument::any,
+                mockiato::Argument::any,
+            )
+            .returns_once(Ok(OAuthTokenResponse {
+                keys_jwe: None,
+                refresh_token: None,
+                expires_in: 3600,
+                scope: "profile".to_owned(),
+                access_token: "fresh".to_owned(),
+                session_token: None,
+            }));
+        client.expect_create_access_token_using_refresh_token_calls_in_order();
+        fxa.set_client(Arc::new(client));
+
+        // These never touch the network while the cached token is valid.
+        assert_eq!(
+            fxa.get_cached_access_token("profile")
+                .unwrap()
+                .unwrap()
+                .token,
+            "expiring"
+        );
+        assert_eq!(
+            fxa.get_access_token_prefer_cached("profile", None)
+                .unwrap()
+                .token,
+            "expiring"
+        );
+        // We can't reach the server, but the cached token is still good.
+        assert_eq!(
+            fxa.get_access_token("profile", None).unwrap().token,
+            "expiring"
+        );
+        let token = fxa.get_access_token("profile", None).unwrap();
+        assert_eq!(token.token, "fresh");
+        assert!(token.issued_at >= now);
+        assert_eq!(
+            fxa.get_cached_access_token("profile")
+                .unwrap()
+                .unwrap()
+                .token,
+            "fresh"
+        );
+
+        // Without a valid token, we don't have anything to return.
+        fxa.clear_access_token_cache();
+        assert!(fxa.get_cached_access_token("profile").unwrap().is_none());
+    }
 }
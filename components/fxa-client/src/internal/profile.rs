@@ -35,6 +35,14 @@ impl FirefoxAccount {
         }
     }
 
+    /// The profile we last fetched, if any. This never makes a network request.
+    pub fn get_cached_profile(&self) -> Option<Profile> {
+        self.state
+            .last_seen_profile
+            .as_ref()
+            .map(|cached_profile| cached_profile.response.clone())
+    }
+
     fn get_profile_helper(&mut self, ignore_cache: bool) -> Result<Profile> {
         let mut etag = None;
         if let Some(ref cached_profile) = self.state.last_seen_profile {
@@ -118,6 +126,7 @@ mod tests {
                 token: "profiletok".to_string(),
                 key: None,
                 expires_at: u64::max_value(),
+                issued_at: 0,
             },
         );
 
@@ -157,6 +166,7 @@ mod tests {
                 token: "bad_access_token".to_string(),
                 key: None,
                 expires_at: u64::max_value(),
+                issued_at: 0,
             },
         );
         let mut refresh_token_scopes = std::collections::HashSet::new();
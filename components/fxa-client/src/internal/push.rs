@@ -90,6 +90,9 @@ impl FirefoxAccount {
                 // clear any device or client data due to password change.
                 self.clear_devices_and_attached_clients_cache();
                 Ok(if !status.active {
+                    // The password change signed us out, so remember that
+                    // the user needs to sign in again.
+                    self.state.needs_reauthentication = true;
                     vec![AccountEvent::AccountAuthStateChanged]
                 } else {
                     vec![]
@@ -241,6 +244,41 @@ mod tests {
         assert!(fxa.devices_cache.is_none());
     }
 
+    #[test]
+    fn test_push_password_changed_needs_reauthentication() {
+        let mut fxa =
+            FirefoxAccount::with_config(Config::stable_dev("12345678", "https://foo.bar"));
+        let mut client = FxAClientMock::new();
+        client
+            .expect_check_refresh_token_status(mockiato::Argument::any, |token| {
+                token.partial_eq("refresh_token")
+            })
+            .times(1)
+            .returns_once(Ok(IntrospectResponse { active: false }));
+        fxa.set_client(Arc::new(client));
+        fxa.state.refresh_token = Some(RefreshToken {
+            token: "refresh_token".to_owned(),
+            scopes: std::collections::HashSet::new(),
+        });
+        let json = "{\"version\":1,\"command\":\"fxaccounts:password_changed\"}";
+        let events = fxa.handle_push_message(json).unwrap();
+        assert!(matches!(
+            events[..],
+            [AccountEvent::AccountAuthStateChanged]
+        ));
+        assert_eq!(
+            fxa.get_auth_state(),
+            crate::AccountAuthState::NeedsReauthentication
+        );
+
+        // Which is remembered across restarts.
+        let fxa = FirefoxAccount::from_json(&fxa.to_json().unwrap()).unwrap();
+        assert_eq!(
+            fxa.get_auth_state(),
+            crate::AccountAuthState::NeedsReauthentication
+        );
+    }
+
     #[test]
     fn test_push_device_disconnected_remote() {
         let mut fxa = FirefoxAccount::with_config(crate::internal::Config::stable_dev(
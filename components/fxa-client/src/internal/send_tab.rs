@@ -6,7 +6,7 @@ use super::{
     commands::{
         send_tab::{
             self, EncryptedSendTabPayload, PrivateSendTabKeys, PublicSendTabKeys,
-            SendTabKeysPayload, SendTabPayload,
+            SendTabKeysPayload, SendTabPayload, SentTab,
         },
         IncomingDeviceCommand,
     },
@@ -56,13 +56,33 @@ impl FirefoxAccount {
         target_device_id: &str,
         title: &str,
         url: &str,
+    ) -> Result<()> {
+        let (payload, sent_telemetry) = SendTabPayload::single_tab(title, url);
+        self.send_tab_payload(target_device_id, payload, sent_telemetry)
+    }
+
+    /// Send one or more tabs to another device in a single command. Older
+    /// clients only know about the first tab.
+    pub fn send_tabs(&mut self, target_device_id: &str, tabs: Vec<SentTab>) -> Result<()> {
+        let mut tabs = tabs.into_iter();
+        let first = tabs
+            .next()
+            .ok_or(ErrorKind::IllegalState("No tabs to send"))?;
+        let (payload, sent_telemetry) = SendTabPayload::multiple_tabs(first, tabs.collect());
+        self.send_tab_payload(target_device_id, payload, sent_telemetry)
+    }
+
+    fn send_tab_payload(
+        &mut self,
+        target_device_id: &str,
+        payload: SendTabPayload,
+        sent_telemetry: telemetry::SentCommand,
     ) -> Result<()> {
         let devices = self.get_devices(false)?;
         let target = devices
             .iter()
             .find(|d| d.id == target_device_id)
             .ok_or_else(|| ErrorKind::UnknownTargetDevice(target_device_id.to_owned()))?;
-        let (payload, sent_telemetry) = SendTabPayload::single_tab(title, url);
         let oldsync_key = self.get_scoped_key(scopes::OLD_SYNC)?;
         let command_payload = send_tab::build_send_command(oldsync_key, target, &payload)?;
         self.invoke_command(send_tab::COMMAND_NAME, target, &command_payload)?;
@@ -153,3 +173,21 @@ impl FirefoxAccount {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl FirefoxAccount {
+        // Encrypts a tab for this device, as another device sending it would.
+        pub fn encrypt_tab_for_self(
+            &mut self,
+            title: &str,
+            url: &str,
+        ) -> Result<serde_json::Value> {
+            let public_keys: PublicSendTabKeys = self.load_or_generate_keys()?.into();
+            let (payload, _) = SendTabPayload::single_tab(title, url);
+            Ok(serde_json::to_value(payload.encrypt(public_keys)?)?)
+        }
+    }
+}
@@ -107,6 +107,11 @@ pub(crate) struct StateV2 {
     pub(crate) session_token: Option<String>, // Hex-formatted string.
     pub(crate) last_seen_profile: Option<CachedResponse<Profile>>,
     pub(crate) in_flight_migration: Option<MigrationData>,
+    // Set when the server rejects our refresh token, and cleared when the user
+    // signs in again, so that the app can tell the user needs to sign in again
+    // after a restart.
+    #[serde(default)]
+    pub(crate) needs_reauthentication: bool,
 }
 
 impl StateV2 {
@@ -130,6 +135,7 @@ impl StateV2 {
             device_capabilities: HashSet::new(),
             session_token: None,
             in_flight_migration: None,
+            needs_reauthentication: false,
         }
     }
 }
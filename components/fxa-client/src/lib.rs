@@ -41,6 +41,7 @@
 use serde_derive::*;
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
+use std::sync::{Arc, Mutex};
 use sync15::DeviceType;
 use thiserror::Error;
 
@@ -108,6 +109,7 @@ pub struct FirefoxAccount {
     // For now, we serialize all access on a single `Mutex` for thread safety across
     // the FFI. We should make the locking more granular in future.
     internal: std::sync::Mutex<internal::FirefoxAccount>,
+    state_listener: Mutex<Option<Arc<dyn AccountStateListener>>>,
 }
 
 /// # Constructors and state management
@@ -157,6 +159,7 @@ impl FirefoxAccount {
                 redirect_uri,
                 token_server_url_override.as_deref(),
             )),
+            state_listener: Mutex::default(),
         }
     }
 
@@ -173,6 +176,7 @@ impl FirefoxAccount {
     pub fn from_json(data: &str) -> Result<FirefoxAccount, FxaError> {
         Ok(FirefoxAccount {
             internal: std::sync::Mutex::new(internal::FirefoxAccount::from_json(data)?),
+            state_listener: Mutex::default(),
         })
     }
 
@@ -320,21 +324,27 @@ impl FirefoxAccount {
     ///   - `state` - the OAuth state parameter obtained from the redirect URI.
     ///
     pub fn complete_oauth_flow(&self, code: &str, state: &str) -> Result<(), FxaError> {
-        Ok(self
-            .internal
-            .lock()
-            .unwrap()
-            .complete_oauth_flow(code, state)?)
+        self.with_state_events(|account, _| Ok(account.complete_oauth_flow(code, state)?))
     }
 
     /// Check authorization status for this application.
     ///
-    /// **💾 This method alters the persisted account state.**
-    ///
     /// Applications may call this method to check with the FxA server about the status
     /// of their authentication tokens. It returns an [`AuthorizationInfo`] struct
     /// with details about whether the tokens are still active.
     ///
+    /// This method does not alter the account state, even when the tokens are found to be
+    /// inactive - it's up to the application to decide what to do.
+    ///
+    /// # Notes
+    ///
+    ///    - An [`AuthorizationInfo`] with `active: false`, or an
+    ///      [`Authentication`](FxaError::Authentication) error, mean that the user needs to
+    ///      sign in again.
+    ///    - A [`Network`](FxaError::Network) error means that the status couldn't be checked,
+    ///      and says nothing about the tokens. Applications should not disconnect the user
+    ///      because of it, and can check again later.
+    ///
     pub fn check_authorization_status(&self) -> Result<AuthorizationInfo, FxaError> {
         Ok(self
             .internal
@@ -358,7 +368,7 @@ impl FirefoxAccount {
     /// is not desired then the application should discard the persisted account state.
     ///
     pub fn disconnect(&self) {
-        self.internal.lock().unwrap().disconnect()
+        self.with_state_events(|account, _| account.disconnect())
     }
 }
 
@@ -389,12 +399,16 @@ impl FirefoxAccount {
     ///      [`Authentication`](FxaError::Authentication) error.
     ///
     pub fn get_profile(&self, ignore_cache: bool) -> Result<Profile, FxaError> {
-        Ok(self
-            .internal
-            .lock()
-            .unwrap()
-            .get_profile(ignore_cache)?
-            .into())
+        self.with_state_events(|account, events| {
+            let cached_profile = account.get_cached_profile().map(Profile::from);
+            let profile = Profile::from(account.get_profile(ignore_cache)?);
+            if cached_profile.as_ref() != Some(&profile) {
+                events.push(AccountStateEvent::ProfileUpdated {
+                    profile: profile.clone(),
+                });
+            }
+            Ok(profile)
+        })
     }
 }
 
@@ -639,7 +653,31 @@ impl FirefoxAccount {
     /// may use for further processing.
     ///
     pub fn handle_push_message(&self, payload: &str) -> Result<Vec<AccountEvent>, FxaError> {
-        Ok(self.internal.lock().unwrap().handle_push_message(payload)?)
+        let has_state_listener = self.state_listener.lock().unwrap().is_some();
+        self.with_state_events(|account, events| {
+            let account_events = account.handle_push_message(payload)?;
+            for account_event in &account_events {
+                match account_event {
+                    AccountEvent::CommandReceived { command } => {
+                        events.push(AccountStateEvent::DeviceCommandReceived {
+                            command: command.clone(),
+                        })
+                    }
+                    // Fetch the new profile for the state listener, so that it
+                    // doesn't have to.
+                    AccountEvent::ProfileUpdated if has_state_listener => {
+                        match account.get_profile(true) {
+                            Ok(profile) => events.push(AccountStateEvent::ProfileUpdated {
+                                profile: profile.into(),
+                            }),
+                            Err(e) => log::warn!("Failed to fetch the updated profile: {}", e),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(account_events)
+        })
     }
 
     /// Poll the server for any pending device commands.
@@ -659,14 +697,20 @@ impl FirefoxAccount {
     ///      granted the `https://identity.mozilla.com/apps/oldsync` scope.
     ///
     pub fn poll_device_commands(&self) -> Result<Vec<IncomingDeviceCommand>, FxaError> {
-        Ok(self
-            .internal
-            .lock()
-            .unwrap()
-            .poll_device_commands(internal::device::CommandFetchReason::Poll)?
-            .into_iter()
-            .map(TryFrom::try_from)
-            .collect::<Result<_, _>>()?)
+        self.with_state_events(|account, events| {
+            let commands = account
+                .poll_device_commands(internal::device::CommandFetchReason::Poll)?
+                .into_iter()
+                .map(TryFrom::try_from)
+                .collect::<Result<Vec<IncomingDeviceCommand>, _>>()?;
+            events.extend(
+                commands
+                    .iter()
+                    .cloned()
+                    .map(|command| AccountStateEvent::DeviceCommandReceived { command }),
+            );
+            Ok(commands)
+        })
     }
 
     /// Use device commands to send a single tab to another device.
@@ -681,8 +725,8 @@ impl FirefoxAccount {
     ///    - If the given device id does not existing or is not capable of receiving tabs,
     ///      this method will throw an [`Other`](FxaError::Other) error.
     ///        - (Yeah...sorry. This should be changed to do something better.)
-    ///    - It is not currently possible to send a full [`SendTabPayload`] to another device,
-    ///      but that's purely an API limitation that should go away in future.
+    ///    - To send more than one tab, or more details about the tab, use
+    ///      [`send_tabs`](FirefoxAccount::send_tabs).
     ///    - Device commands functionality is only available to applications that have been
     ///      granted the `https://identity.mozilla.com/apps/oldsync` scope.
     ///
@@ -698,6 +742,28 @@ impl FirefoxAccount {
             .unwrap()
             .send_single_tab(target_device_id, title, url)?)
     }
+
+    /// Use device commands to send one or more tabs to another device.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// All the tabs are sent in a single command. Each tab may include a favicon URL and
+    /// some selected text, which the receiving device can use to describe the tab.
+    ///
+    /// # Notes
+    ///
+    ///    - Devices running older versions of this component only know about the first
+    ///      tab, and ignore the favicon URL and selected text.
+    ///    - Selected text is truncated to a short preview.
+    ///    - The same notes as for [`send_single_tab`](FirefoxAccount::send_single_tab) apply.
+    ///
+    pub fn send_tabs(&self, target_device_id: &str, tabs: Vec<SentTab>) -> Result<(), FxaError> {
+        Ok(self
+            .internal
+            .lock()
+            .unwrap()
+            .send_tabs(target_device_id, tabs.into_iter().map(From::from).collect())?)
+    }
 }
 
 /// # Account Management URLs
@@ -816,6 +882,10 @@ impl FirefoxAccount {
     ///    - If the application receives an authorization error when trying to use the resulting
     ///      token, it should call [`clear_access_token_cache`](FirefoxAccount::clear_access_token_cache)
     ///      before requesting a fresh token.
+    ///    - Cached tokens which are about to expire are replaced before they do. If the server
+    ///      can't be reached to replace one, the cached token is returned while it's still valid.
+    ///    - Cached tokens are discarded if the device clock has gone backwards since they
+    ///      were issued, since their expiry time can't be trusted.
     ///
     pub fn get_access_token(
         &self,
@@ -824,12 +894,57 @@ impl FirefoxAccount {
     ) -> Result<AccessTokenInfo, FxaError> {
         // Signedness converstion for Kotlin compatibility :-/
         let ttl = ttl.map(|ttl| u64::try_from(ttl).unwrap_or_default());
-        Ok(self
-            .internal
+        self.with_state_events(|account, _| Ok(account.get_access_token(scope, ttl)?.try_into()?))
+    }
+
+    /// Get an OAuth access token for the user's account, preferring a cached one.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// This behaves like [`get_access_token`](FirefoxAccount::get_access_token), except that
+    /// a cached token is returned for as long as it's valid, instead of being replaced before
+    /// it expires. This means it never blocks on the network when a valid cached token exists.
+    ///
+    /// # Arguments
+    ///
+    ///    - `scope` - the OAuth scope to be granted by the token.
+    ///    - `ttl` - optionally, the time for which a new token should be valid, in seconds.
+    ///
+    pub fn get_access_token_prefer_cached(
+        &self,
+        scope: &str,
+        ttl: Option<i64>,
+    ) -> Result<AccessTokenInfo, FxaError> {
+        let ttl = ttl.map(|ttl| u64::try_from(ttl).unwrap_or_default());
+        self.with_state_events(|account, _| {
+            Ok(account
+                .get_access_token_prefer_cached(scope, ttl)?
+                .try_into()?)
+        })
+    }
+
+    /// Get a cached OAuth access token for the user's account, without using the network.
+    ///
+    /// Returns the cached token for `scope` if there's one which is still valid, or `None`
+    /// if there isn't, in which case the application should call
+    /// [`get_access_token`](FirefoxAccount::get_access_token) when it's able to make a
+    /// network request.
+    ///
+    /// # Arguments
+    ///
+    ///    - `scope` - the OAuth scope to be granted by the token.
+    ///
+    pub fn get_cached_access_token(
+        &self,
+        scope: &str,
+    ) -> Result<Option<AccessTokenInfo>, FxaError> {
+        self.internal
             .lock()
             .unwrap()
-            .get_access_token(scope, ttl)?
-            .try_into()?)
+            .get_cached_access_token(scope)?
+            .map(AccessTokenInfo::try_from)
+            .transpose()
+            .map_err(Into::into)
     }
 
     /// Get the session token for the user's account, if one is available.
@@ -866,11 +981,7 @@ impl FirefoxAccount {
     ///    - `session_token` - the new session token value provided from web content.
     ///
     pub fn handle_session_token_change(&self, session_token: &str) -> Result<(), FxaError> {
-        Ok(self
-            .internal
-            .lock()
-            .unwrap()
-            .handle_session_token_change(session_token)?)
+        self.with_state_events(|account, _| Ok(account.handle_session_token_change(session_token)?))
     }
 
     /// Create a new OAuth authorization code using the stored session token.
@@ -975,12 +1086,14 @@ impl FirefoxAccount {
         k_xcs: &str,
         copy_session_token: bool,
     ) -> Result<FxAMigrationResult, FxaError> {
-        Ok(self.internal.lock().unwrap().migrate_from_session_token(
-            session_token,
-            k_sync,
-            k_xcs,
-            copy_session_token,
-        )?)
+        self.with_state_events(|account, _| {
+            Ok(account.migrate_from_session_token(
+                session_token,
+                k_sync,
+                k_xcs,
+                copy_session_token,
+            )?)
+        })
     }
 
     /// Retry a previously failed migration from legacy session-token state.
@@ -992,7 +1105,7 @@ impl FirefoxAccount {
     /// in order to execute such a retry.
     ///
     pub fn retry_migrate_from_session_token(&self) -> Result<FxAMigrationResult, FxaError> {
-        Ok(self.internal.lock().unwrap().try_migration()?)
+        self.with_state_events(|account, _| Ok(account.try_migration()?))
     }
 
     /// Check for a previously failed migration from legacy session-token state.
@@ -1006,6 +1119,80 @@ impl FirefoxAccount {
     }
 }
 
+/// # Account state
+///
+/// Rather than polling the server to find out whether the user is still signed in,
+/// applications can register an [`AccountStateListener`], which is told when the state
+/// of the account changes, and use [`get_state_snapshot`](FirefoxAccount::get_state_snapshot)
+/// to find out the state it was left in when the application last ran.
+///
+/// Listeners are only told about changes noticed by methods called on this object. For
+/// example, the user needing to sign in again is noticed when the server rejects a
+/// request for an access token, or when a push message says the user's password changed
+/// and the tokens are no longer active. [`check_authorization_status`](
+/// FirefoxAccount::check_authorization_status) doesn't change the state.
+///
+impl FirefoxAccount {
+    /// Register a listener to be told about changes to the account's state.
+    ///
+    /// This replaces any previously-registered listener. The listener is called on the
+    /// thread of the method which noticed the change, after the method has finished
+    /// updating the account, so it may call other methods on the account, for example
+    /// to persist its state.
+    ///
+    pub fn register_state_listener(&self, listener: Box<dyn AccountStateListener>) {
+        *self.state_listener.lock().unwrap() = Some(Arc::from(listener));
+    }
+
+    /// Remove the listener registered with [`register_state_listener`](
+    /// FirefoxAccount::register_state_listener), if any.
+    ///
+    pub fn unregister_state_listener(&self) {
+        *self.state_listener.lock().unwrap() = None;
+    }
+
+    /// Get a snapshot of the account's state.
+    ///
+    /// This is read from the account state, including any persisted state it was
+    /// restored from, and never makes a network request.
+    ///
+    pub fn get_state_snapshot(&self) -> AccountStateSnapshot {
+        let mut account = self.internal.lock().unwrap();
+        AccountStateSnapshot {
+            auth_state: account.get_auth_state(),
+            profile: account.get_cached_profile().map(Into::into),
+            current_device_id: account.get_current_device_id().ok(),
+        }
+    }
+
+    // Runs `f` on the internal account, and then tells the state listener, if there is
+    // one, about any change to the auth state, followed by the `events` `f` collected.
+    // The listener is called after the account is unlocked, so that it can use it.
+    fn with_state_events<T>(
+        &self,
+        f: impl FnOnce(&mut internal::FirefoxAccount, &mut Vec<AccountStateEvent>) -> T,
+    ) -> T {
+        let mut events = Vec::new();
+        let result = {
+            let mut account = self.internal.lock().unwrap();
+            let auth_state = account.get_auth_state();
+            let result = f(&mut account, &mut events);
+            let new_auth_state = account.get_auth_state();
+            if new_auth_state != auth_state {
+                events.insert(0, new_auth_state.into());
+            }
+            result
+        };
+        let listener = self.state_listener.lock().unwrap().clone();
+        if let Some(listener) = listener {
+            for event in events {
+                listener.on_state_event(event);
+            }
+        }
+        result
+    }
+}
+
 /// Information about the authorization state of the application.
 ///
 /// This struct represents metadata about whether the application is currently
@@ -1101,7 +1288,7 @@ pub struct AuthorizationParameters {
 /// This data would typically be used to display e.g. the list of candidate devices
 /// in a "send tab" menu.
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Device {
     pub id: String,
     pub display_name: String,
@@ -1122,7 +1309,7 @@ pub struct Device {
 ///
 /// Managing a web-push subscription is outside of the scope of this component.
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DevicePushSubscription {
     pub endpoint: String,
     pub public_key: String,
@@ -1139,7 +1326,7 @@ pub struct DevicePushSubscription {
 ///
 /// In practice, the only currently-supported command is the ability to receive a tab.
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum DeviceCapability {
     SendTab,
 }
@@ -1204,7 +1391,7 @@ pub enum AccountEvent {
 /// the device. It is the responsibility of the application to interpret
 /// each command.
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum IncomingDeviceCommand {
     /// Indicates that a tab has been sent to this device.
     TabReceived {
@@ -1215,7 +1402,7 @@ pub enum IncomingDeviceCommand {
 
 /// The payload sent when invoking a "send tab" command.
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SendTabPayload {
     /// The navigation history of the sent tab.
     ///
@@ -1223,6 +1410,12 @@ pub struct SendTabPayload {
     /// while earlier items may be included in the navigation history
     /// as a convenience to the user.
     pub entries: Vec<TabHistoryEntry>,
+    /// The URL of the sent tab's favicon, if the sender included it.
+    pub icon_url: Option<String>,
+    /// A preview of the text selected in the sent tab, if the sender included it.
+    pub selected_text: Option<String>,
+    /// Any further tabs sent in the same command.
+    pub additional_tabs: Vec<SentTab>,
     /// A unique identifier to be included in send-tab metrics.
     ///
     /// The application should treat this as opaque.
@@ -1233,9 +1426,22 @@ pub struct SendTabPayload {
     pub stream_id: String,
 }
 
+/// A tab sent along with others in a single "send tab" command.
+///
+/// This is also used to describe the tabs to send with
+/// [`send_tabs`](FirefoxAccount::send_tabs).
+///
+#[derive(Debug, Clone)]
+pub struct SentTab {
+    /// The navigation history of the tab, as for [`SendTabPayload::entries`].
+    pub entries: Vec<TabHistoryEntry>,
+    pub icon_url: Option<String>,
+    pub selected_text: Option<String>,
+}
+
 /// An individual entry in the navigation history of a sent tab.
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TabHistoryEntry {
     pub title: String,
     pub url: String,
@@ -1269,6 +1475,7 @@ pub struct AttachedClient {
 /// used to customize account-related UI in the browser so that it is personalize
 /// for the current user.
 ///
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Profile {
     /// The user's account uid
     ///
@@ -1289,6 +1496,70 @@ pub struct Profile {
     pub is_default_avatar: bool,
 }
 
+/// Whether the application is connected to the user's account.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountAuthState {
+    /// The application isn't connected to an account, either because the user
+    /// never signed in, or because they signed out.
+    Disconnected,
+    /// The application is connected to the user's account.
+    Authenticated,
+    /// The application was connected to the user's account, but the server no
+    /// longer accepts its tokens, for example because the user changed their
+    /// password. The user needs to sign in again.
+    NeedsReauthentication,
+}
+
+/// A change to the state of the user's account.
+///
+/// These are passed to the [`AccountStateListener`] registered with
+/// [`register_state_listener`](FirefoxAccount::register_state_listener).
+///
+// See `AccountEvent` for why this isn't boxed.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum AccountStateEvent {
+    /// The user signed in, or signed in again after needing to.
+    Authenticated,
+    /// The server no longer accepts the application's tokens, and the user
+    /// needs to sign in again.
+    NeedsReauthentication,
+    /// The application is no longer connected to the user's account.
+    Disconnected,
+    /// The user's profile has changed, or was fetched for the first time.
+    ProfileUpdated { profile: Profile },
+    /// Another device has invoked a command for this device to execute.
+    DeviceCommandReceived { command: IncomingDeviceCommand },
+}
+
+impl From<AccountAuthState> for AccountStateEvent {
+    fn from(state: AccountAuthState) -> Self {
+        match state {
+            AccountAuthState::Disconnected => AccountStateEvent::Disconnected,
+            AccountAuthState::Authenticated => AccountStateEvent::Authenticated,
+            AccountAuthState::NeedsReauthentication => AccountStateEvent::NeedsReauthentication,
+        }
+    }
+}
+
+/// Receives the changes to the state of the user's account.
+///
+pub trait AccountStateListener: Send + Sync {
+    fn on_state_event(&self, event: AccountStateEvent);
+}
+
+/// The state of the user's account, as of the last time it was checked.
+///
+#[derive(Debug)]
+pub struct AccountStateSnapshot {
+    pub auth_state: AccountAuthState,
+    /// The user's profile, as it was last fetched.
+    pub profile: Option<Profile>,
+    /// The id of this device's record on the account, if it has one.
+    pub current_device_id: Option<String>,
+}
+
 /// The current state migration from legacy sign-in data.
 ///
 /// This enum distinguishes the different states of a potential in-flight
@@ -1319,3 +1590,218 @@ pub struct FxAMigrationResult {
     /// that do not have unsigned integers.
     pub total_duration: i64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::{
+        AccessTokenInfo, CommandData, Config, FxAClientMock, IntrospectResponse, PendingCommand,
+        PendingCommandsResponse, ProfileResponse, ResponseAndETag,
+    };
+
+    #[derive(Default)]
+    struct RecordingListener(Arc<Mutex<Vec<AccountStateEvent>>>);
+
+    impl AccountStateListener for RecordingListener {
+        fn on_state_event(&self, event: AccountStateEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    fn signed_in_account() -> internal::FirefoxAccount {
+        let mut account = internal::FirefoxAccount::with_config(Config::stable_dev(
+            "12345678",
+            "https://foo.bar",
+        ));
+        account.set_refresh_token("refresh_token");
+        account
+    }
+
+    // Wraps `account`, with a listener which records its events.
+    fn listen_to(
+        account: internal::FirefoxAccount,
+    ) -> (FirefoxAccount, Arc<Mutex<Vec<AccountStateEvent>>>) {
+        let account = FirefoxAccount {
+            internal: std::sync::Mutex::new(account),
+            state_listener: Mutex::default(),
+        };
+        let listener = RecordingListener::default();
+        let events = listener.0.clone();
+        account.register_state_listener(Box::new(listener));
+        (account, events)
+    }
+
+    // A password change which signs out this device, with `expect_inactive_tokens`.
+    const PASSWORD_CHANGED_PUSH: &str = r#"{"version":1,"command":"fxaccounts:password_changed"}"#;
+
+    fn expect_inactive_tokens(client: &mut FxAClientMock<'static>) {
+        client
+            .expect_check_refresh_token_status(mockiato::Argument::any, |token| {
+                token.partial_eq("refresh_token")
+            })
+            .times(1)
+            .returns_once(Ok(IntrospectResponse { active: false }));
+    }
+
+    // Mocks the server returning `payload`, an encrypted tab, as the only pending command.
+    fn expect_pending_tab(client: &mut FxAClientMock<'static>, payload: serde_json::Value) {
+        client
+            .expect_get_pending_commands(
+                mockiato::Argument::any,
+                |token| token.partial_eq("refresh_token"),
+                mockiato::Argument::any,
+                mockiato::Argument::any,
+            )
+            .times(1)
+            .returns_once(Ok(PendingCommandsResponse {
+                index: 1,
+                last: Some(true),
+                messages: vec![PendingCommand {
+                    index: 1,
+                    data: CommandData {
+                        command: "https://identity.mozilla.com/cmd/open-uri".to_string(),
+                        payload,
+                        sender: None,
+                    },
+                }],
+            }));
+    }
+
+    #[test]
+    fn test_state_listener_auth_state_comes_first() {
+        let mut account = signed_in_account();
+        let mut client = FxAClientMock::new();
+        expect_inactive_tokens(&mut client);
+        account.set_client(Arc::new(client));
+        let (account, events) = listen_to(account);
+
+        account.with_state_events(|account, events| {
+            events.push(AccountStateEvent::ProfileUpdated {
+                profile: Profile {
+                    uid: "123".to_string(),
+                    email: "test@example.com".to_string(),
+                    display_name: None,
+                    avatar: "".to_string(),
+                    is_default_avatar: true,
+                },
+            });
+            account.handle_push_message(PASSWORD_CHANGED_PUSH).unwrap();
+        });
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            AccountStateEvent::NeedsReauthentication
+        ));
+        assert!(matches!(
+            events[1],
+            AccountStateEvent::ProfileUpdated { .. }
+        ));
+    }
+
+    #[test]
+    fn test_state_listener_profile_updated() {
+        let mut account = signed_in_account();
+        account.add_cached_profile("123", "test@example.com");
+        account.add_cached_token(
+            "profile",
+            AccessTokenInfo {
+                scope: "profile".to_string(),
+                token: "profiletok".to_string(),
+                key: None,
+                expires_at: u64::max_value(),
+                issued_at: 0,
+            },
+        );
+        let mut client = FxAClientMock::new();
+        client
+            .expect_get_profile(
+                mockiato::Argument::any,
+                |token| token.partial_eq("profiletok"),
+                mockiato::Argument::any,
+            )
+            .times(1)
+            .returns_once(Ok(Some(ResponseAndETag {
+                response: ProfileResponse {
+                    uid: "123".to_string(),
+                    email: "new@example.com".to_string(),
+                    display_name: None,
+                    avatar: "".to_string(),
+                    avatar_default: true,
+                },
+                etag: None,
+            })));
+        account.set_client(Arc::new(client));
+        let (account, events) = listen_to(account);
+
+        // The fetched profile differs from the cached one, so the listener is told...
+        account.get_profile(true).unwrap();
+        // ...but not when the profile comes from the cache again.
+        account.get_profile(false).unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            AccountStateEvent::ProfileUpdated { profile } => {
+                assert_eq!(profile.email, "new@example.com")
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_state_listener_device_command_received() {
+        let mut account = signed_in_account();
+        let payload = account
+            .encrypt_tab_for_self("Example", "https://example.com")
+            .unwrap();
+        let mut client = FxAClientMock::new();
+        expect_pending_tab(&mut client, payload.clone());
+        expect_pending_tab(&mut client, payload);
+        client.expect_get_pending_commands_calls_in_order();
+        // The devices are cached after the first command.
+        client
+            .expect_get_devices(mockiato::Argument::any, |token| {
+                token.partial_eq("refresh_token")
+            })
+            .times(1)
+            .returns_once(Ok(vec![]));
+        account.set_client(Arc::new(client));
+        let (account, events) = listen_to(account);
+
+        // The listener is told about commands we poll for...
+        assert_eq!(account.poll_device_commands().unwrap().len(), 1);
+        // ...and those we're pushed.
+        let push = r#"{"version":1,"command":"fxaccounts:command_received","data":{"command":"send-tab-recv","index":1,"sender":"bobo","url":"https://mozilla.org"}}"#;
+        assert_eq!(account.handle_push_message(push).unwrap().len(), 1);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        for event in events.iter() {
+            assert!(matches!(
+                event,
+                AccountStateEvent::DeviceCommandReceived {
+                    command: IncomingDeviceCommand::TabReceived { .. }
+                }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_unregister_state_listener() {
+        let mut account = signed_in_account();
+        let mut client = FxAClientMock::new();
+        expect_inactive_tokens(&mut client);
+        account.set_client(Arc::new(client));
+        let (account, events) = listen_to(account);
+
+        account.unregister_state_listener();
+        account.handle_push_message(PASSWORD_CHANGED_PUSH).unwrap();
+        assert_eq!(
+            account.get_state_snapshot().auth_state,
+            AccountAuthState::NeedsReauthentication
+        );
+        assert!(events.lock().unwrap().is_empty());
+    }
+}
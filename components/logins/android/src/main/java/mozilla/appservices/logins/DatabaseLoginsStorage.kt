@@ -44,6 +44,61 @@ class DatabaseLoginsStorage(dbPath: String) : AutoCloseable {
         this.store.wipeLocal()
     }
 
+    @Throws(LoginsApiException::class)
+    fun runMaintenance() {
+        this.store.runMaintenance()
+    }
+
+    @Throws(LoginsApiException::class)
+    fun countUndecryptableLogins(encryptionKey: String): UndecryptableLoginsCounts {
+        return readQueryCounters.measure {
+            store.countUndecryptableLogins(encryptionKey)
+        }
+    }
+
+    /**
+     * Deletes the logins which can't be decrypted with `encryptionKey`, for
+     * example because the previous key was lost and a new one generated.
+     * Logins which were synced will be downloaded again on the next sync.
+     */
+    @Throws(LoginsApiException::class)
+    fun wipeUndecryptableLogins(encryptionKey: String): UndecryptableLoginsCounts {
+        val counts = writeQueryCounters.measure {
+            store.wipeUndecryptableLogins(encryptionKey)
+        }
+        LoginsStoreMetrics.undecryptableLoginsWiped.add(counts.total.toInt())
+        LoginsStoreMetrics.undecryptableLoginsRecoverable.add(counts.recoverable.toInt())
+        return counts
+    }
+
+    /**
+     * Moves the logins saved for `oldOrigin` to `newOrigin`, for sites which
+     * changed domains, so that users don't have to save them again. Logins
+     * already saved for `newOrigin` are kept, and their duplicates are deleted.
+     */
+    @Throws(LoginsApiException::class)
+    fun migrateLoginsOrigin(oldOrigin: String, newOrigin: String, encryptionKey: String): MigratedLoginsCounts {
+        return writeQueryCounters.measure {
+            store.migrateLoginsOrigin(oldOrigin, newOrigin, encryptionKey)
+        }
+    }
+
+    /**
+     * When enabled, each sync first quarantines the logins which can't be
+     * decrypted with the sync key, then downloads every record again, so
+     * that logins lost with an old key are restored from the server.
+     */
+    fun setRecoverUndecryptableOnSync(enabled: Boolean) {
+        store.setRecoverUndecryptableOnSync(enabled)
+    }
+
+    @Throws(LoginsApiException::class)
+    fun getQuarantinedLoginsCounts(): QuarantinedLoginsCounts {
+        return readQueryCounters.measure {
+            store.getQuarantinedLoginsCounts()
+        }
+    }
+
     @Throws(LoginsApiException::class)
     fun delete(id: String): Boolean {
         return writeQueryCounters.measure {
@@ -100,6 +155,36 @@ class DatabaseLoginsStorage(dbPath: String) : AutoCloseable {
         }
     }
 
+    /**
+     * Adds all of [entries] in a single transaction, returning the result
+     * for each in the same order. A login which can't be added is returned
+     * as a [BulkResultEntry.Error] without any of its changes, and the other
+     * logins are still added.
+     */
+    @Throws(LoginsApiException::class)
+    fun addMany(entries: List<LoginEntry>, encryptionKey: String): List<BulkResultEntry> {
+        return writeQueryCounters.measure {
+            store.addMany(entries, encryptionKey)
+        }
+    }
+
+    /**
+     * Like [addMany], but applies [updates] to existing logins.
+     */
+    @Throws(LoginsApiException::class)
+    fun updateMany(updates: List<LoginUpdate>, encryptionKey: String): List<BulkResultEntry> {
+        return writeQueryCounters.measure {
+            store.updateMany(updates, encryptionKey)
+        }
+    }
+
+    @Throws(LoginsApiException::class)
+    fun deleteMany(ids: List<String>): List<Boolean> {
+        return writeQueryCounters.measure {
+            store.deleteMany(ids)
+        }
+    }
+
     @Throws(LoginsApiException::class)
     fun addOrUpdate(entry: LoginEntry, encryptionKey: String): EncryptedLogin {
         return writeQueryCounters.measure {
@@ -42,6 +42,64 @@ open class LoginsStorage {
         }
     }
 
+    /// Vacuum and optimize the database when it needs it. Call this during
+    /// idle time.
+    open func runMaintenance() throws {
+        try queue.sync {
+            try self.store.runMaintenance()
+        }
+    }
+
+    /// Count the logins which can't be decrypted with `encryptionKey`.
+    open func countUndecryptableLogins(encryptionKey: String) throws -> UndecryptableLoginsCounts {
+        return try queue.sync {
+            return try self.store.countUndecryptableLogins(encryptionKey: encryptionKey)
+        }
+    }
+
+    /// Delete the logins which can't be decrypted with `encryptionKey`, for example
+    /// because the previous key was lost and a new one generated. Logins which were
+    /// synced will be downloaded again on the next sync.
+    open func wipeUndecryptableLogins(encryptionKey: String) throws -> UndecryptableLoginsCounts {
+        return try queue.sync {
+            return try self.store.wipeUndecryptableLogins(encryptionKey: encryptionKey)
+        }
+    }
+
+    /// Move the logins saved for `oldOrigin` to `newOrigin`, for sites which changed
+    /// domains, so that users don't have to save them again. Logins already saved for
+    /// `newOrigin` are kept, and their duplicates are deleted.
+    open func migrateLoginsOrigin(
+        oldOrigin: String,
+        newOrigin: String,
+        encryptionKey: String
+    ) throws -> MigratedLoginsCounts {
+        return try queue.sync {
+            return try self.store.migrateLoginsOrigin(
+                oldOrigin: oldOrigin,
+                newOrigin: newOrigin,
+                encryptionKey: encryptionKey
+            )
+        }
+    }
+
+    /// When enabled, each sync first quarantines the logins which can't be decrypted
+    /// with the sync key, then downloads every record again, so that logins lost with
+    /// an old key are restored from the server.
+    open func setRecoverUndecryptableOnSync(enabled: Bool) {
+        queue.sync {
+            self.store.setRecoverUndecryptableOnSync(enabled: enabled)
+        }
+    }
+
+    /// Count the logins quarantined by syncs with recovery enabled, and how many of
+    /// them have been downloaded again since.
+    open func getQuarantinedLoginsCounts() throws -> QuarantinedLoginsCounts {
+        return try queue.sync {
+            return try self.store.getQuarantinedLoginsCounts()
+        }
+    }
+
     /// Delete the record with the given ID. Returns false if no such record existed.
     open func delete(id: String) throws -> Bool {
         return try queue.sync {
@@ -76,6 +134,34 @@ open class LoginsStorage {
         }
     }
 
+    /// Insert all of `logins` into the database in a single transaction. The
+    /// result for each login is returned in the same order as `logins`. A
+    /// login which can't be added is returned as `BulkResultEntry.error`
+    /// without any of its changes, and doesn't prevent the others from being
+    /// added.
+    open func addMany(logins: [LoginEntry], encryptionKey: String) throws -> [BulkResultEntry] {
+        return try queue.sync {
+            return try self.store.addMany(logins: logins, encryptionKey: encryptionKey)
+        }
+    }
+
+    /// Apply all of `updates` in a single transaction. As with `addMany`, a
+    /// failed update is returned without any of its changes, and doesn't
+    /// prevent the others from being updated.
+    open func updateMany(updates: [LoginUpdate], encryptionKey: String) throws -> [BulkResultEntry] {
+        return try queue.sync {
+            return try self.store.updateMany(updates: updates, encryptionKey: encryptionKey)
+        }
+    }
+
+    /// Delete the records with the given IDs in a single transaction. Returns
+    /// whether each record existed.
+    open func deleteMany(ids: [String]) throws -> [Bool] {
+        return try queue.sync {
+            return try self.store.deleteMany(ids: ids)
+        }
+    }
+
     /// Get the record with the given id. Returns nil if there is no such record.
     open func get(id: String) throws -> EncryptedLogin? {
         return try queue.sync {
@@ -28,14 +28,14 @@ use crate::login::*;
 use crate::schema;
 use crate::sync::SyncStatus;
 use crate::util;
-use interrupt_support::{SqlInterruptHandle, SqlInterruptScope};
+use interrupt_support::{register_interrupt, SqlInterruptHandle, SqlInterruptScope};
 use lazy_static::lazy_static;
 use rusqlite::{
     named_params,
     types::{FromSql, ToSql},
     Connection,
 };
-use sql_support::ConnExt;
+use sql_support::{maintenance::MaintenancePolicy, ConnExt};
 use std::ops::Deref;
 use std::path::Path;
 use std::sync::Arc;
@@ -61,8 +61,10 @@ impl LoginDb {
         // do this on Android, or allow caller to configure it.
         db.set_pragma("temp_store", 2)?;
 
+        let interrupt_handle = Arc::new(SqlInterruptHandle::new(&db));
+        register_interrupt(Arc::<SqlInterruptHandle>::downgrade(&interrupt_handle));
         let mut logins = Self {
-            interrupt_handle: Arc::new(SqlInterruptHandle::new(&db)),
+            interrupt_handle,
             db,
         };
         let tx = logins.db.transaction()?;
@@ -72,7 +74,16 @@ impl LoginDb {
     }
 
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
-        Self::with_connection(Connection::open(path)?)
+        // We don't use `sql_support::open_database`, so record the health of
+        // the database ourselves.
+        let result = Connection::open(path)
+            .map_err(Error::from)
+            .and_then(Self::with_connection);
+        match &result {
+            Ok(_) => error_support::record_database_opened(schema::DB_NAME),
+            Err(e) => error_support::record_database_open_failure(schema::DB_NAME, &e.to_string()),
+        }
+        result
     }
 
     pub fn open_in_memory() -> Result<Self> {
@@ -104,6 +115,14 @@ impl Deref for LoginDb {
     }
 }
 
+// We never prune logins: they're all the user's data, and tombstones are
+// deleted once they've been synced.
+pub(crate) struct LoginsMaintenancePolicy;
+
+impl MaintenancePolicy for LoginsMaintenancePolicy {
+    const NAME: &'static str = "logins";
+}
+
 // login specific stuff.
 
 impl LoginDb {
@@ -138,6 +157,41 @@ impl LoginDb {
         rows.collect::<Result<_>>()
     }
 
+    /// Summarizes when and how often logins have been used. Logins which
+    /// haven't been used in the `unused_for_ms` milliseconds before `now_ms`
+    /// are listed in `unused_ids`.
+    pub fn get_usage_stats(&self, now_ms: i64, unused_for_ms: i64) -> Result<LoginUsageStats> {
+        const MONTH_MS: i64 = 30 * 24 * 60 * 60 * 1000;
+        let mut stats = LoginUsageStats::default();
+        let mut unused = vec![];
+        for login in self.get_all()? {
+            let record = login.record;
+            // `timeLastUsed` may be null (ie, 0 here) for very old records.
+            let last_used = record.time_last_used.max(record.time_created);
+            let age = now_ms - last_used;
+            stats.total_logins += 1;
+            stats.total_times_used += record.times_used;
+            if record.times_used <= 1 {
+                stats.never_used += 1;
+            }
+            if age < MONTH_MS {
+                stats.last_used_within_month += 1;
+            } else if age < 6 * MONTH_MS {
+                stats.last_used_within_six_months += 1;
+            } else if age < 12 * MONTH_MS {
+                stats.last_used_within_year += 1;
+            } else {
+                stats.last_used_over_a_year_ago += 1;
+            }
+            if age >= unused_for_ms {
+                unused.push((last_used, record.id));
+            }
+        }
+        unused.sort();
+        stats.unused_ids = unused.into_iter().map(|(_, id)| id).collect();
+        Ok(stats)
+    }
+
     pub fn get_by_base_domain(&self, base_domain: &str) -> Result<Vec<EncryptedLogin>> {
         // We first parse the input string as a host so it is normalized.
         let base_host = match Host::parse(base_domain) {
@@ -405,9 +459,54 @@ impl LoginDb {
     }
 
     pub fn add(&self, entry: LoginEntry, encdec: &EncryptorDecryptor) -> Result<EncryptedLogin> {
-        let guid = Guid::random();
         let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let tx = self.unchecked_transaction()?;
+        let result = self.add_in_tx(entry, now_ms, encdec)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    /// Adds all the entries in a single transaction. Invalid or duplicate
+    /// entries don't prevent the others from being added - the result for
+    /// each entry is returned in the same order as `entries`. Anything an
+    /// entry wrote before failing is rolled back.
+    pub fn add_many(
+        &self,
+        entries: Vec<LoginEntry>,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<Vec<Result<EncryptedLogin>>> {
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let tx = self.unchecked_transaction()?;
+        let results = entries
+            .into_iter()
+            .map(|entry| self.in_savepoint(|| self.add_in_tx(entry, now_ms, encdec)))
+            .collect::<Result<Vec<_>>>()?;
+        tx.commit()?;
+        Ok(results)
+    }
+
+    // Runs `f` in a savepoint, which is rolled back if `f` fails, so that
+    // an entry of `add_many` or `update_many` which fails part way through
+    // (eg, after cloning the mirror record to the local table) doesn't leave
+    // its partial changes in the transaction. The outer `Result` is only an
+    // error if the savepoint itself fails.
+    fn in_savepoint<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<Result<T>> {
+        self.execute_batch("SAVEPOINT bulk_entry")?;
+        let result = f();
+        if result.is_err() {
+            self.execute_batch("ROLLBACK TO bulk_entry")?;
+        }
+        self.execute_batch("RELEASE bulk_entry")?;
+        Ok(result)
+    }
 
+    fn add_in_tx(
+        &self,
+        entry: LoginEntry,
+        now_ms: i64,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<EncryptedLogin> {
+        let guid = Guid::random();
         let new_entry = self.fixup_and_check_for_dupes(&guid, entry, encdec)?;
         let result = EncryptedLogin {
             record: RecordFields {
@@ -420,9 +519,7 @@ impl LoginDb {
             fields: new_entry.fields,
             sec_fields: new_entry.sec_fields.encrypt(encdec)?,
         };
-        let tx = self.unchecked_transaction()?;
         self.insert_new_login(&result)?;
-        tx.commit()?;
         Ok(result)
     }
 
@@ -432,10 +529,39 @@ impl LoginDb {
         entry: LoginEntry,
         encdec: &EncryptorDecryptor,
     ) -> Result<EncryptedLogin> {
-        let guid = Guid::new(sguid);
         let now_ms = util::system_time_ms_i64(SystemTime::now());
         let tx = self.unchecked_transaction()?;
+        let result = self.update_in_tx(sguid, entry, now_ms, encdec)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    /// Updates all the logins in a single transaction. As with `add_many`, a
+    /// failure to update one login doesn't prevent the others from being
+    /// updated.
+    pub fn update_many(
+        &self,
+        updates: Vec<(String, LoginEntry)>,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<Vec<Result<EncryptedLogin>>> {
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let tx = self.unchecked_transaction()?;
+        let results = updates
+            .into_iter()
+            .map(|(id, entry)| self.in_savepoint(|| self.update_in_tx(&id, entry, now_ms, encdec)))
+            .collect::<Result<Vec<_>>>()?;
+        tx.commit()?;
+        Ok(results)
+    }
 
+    fn update_in_tx(
+        &self,
+        sguid: &str,
+        entry: LoginEntry,
+        now_ms: i64,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<EncryptedLogin> {
+        let guid = Guid::new(sguid);
         let entry = entry.fixup()?;
 
         // Check if there's an existing login that's the dupe of this login.  That indicates that
@@ -484,7 +610,6 @@ impl LoginDb {
         };
 
         self.update_existing_login(&result)?;
-        tx.commit()?;
         Ok(result)
     }
 
@@ -637,9 +762,94 @@ impl LoginDb {
     /// Delete the record with the provided id. Returns true if the record
     /// existed already.
     pub fn delete(&self, id: &str) -> Result<bool> {
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
         let tx = self.unchecked_transaction_imm()?;
-        let exists = self.exists(id)?;
+        let exists = self.delete_in_tx(id, now_ms)?;
+        tx.commit()?;
+        Ok(exists)
+    }
+
+    /// Deletes all the records in a single transaction. Returns whether each
+    /// record existed, in the same order as `ids`.
+    pub fn delete_many(&self, ids: &[String]) -> Result<Vec<bool>> {
         let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let tx = self.unchecked_transaction_imm()?;
+        let results = ids
+            .iter()
+            .map(|id| self.delete_in_tx(id, now_ms))
+            .collect::<Result<_>>()?;
+        tx.commit()?;
+        Ok(results)
+    }
+
+    /// Moves the logins saved for `old_origin` to `new_origin`, for sites
+    /// which changed domains. Form action origins which were the same as the
+    /// old origin move too. A login which duplicates one already saved for
+    /// the new origin is deleted instead, and the existing one is kept.
+    pub fn migrate_origin(
+        &self,
+        old_origin: &str,
+        new_origin: &str,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<MigratedLoginsCounts> {
+        let fixup = |origin: &str| -> Result<String> {
+            Ok(LoginFields::validate_and_fixup_origin(origin)?
+                .unwrap_or_else(|| origin.to_string()))
+        };
+        let old_origin = fixup(old_origin)?;
+        let new_origin = fixup(new_origin)?;
+        let mut counts = MigratedLoginsCounts::default();
+        if old_origin == new_origin {
+            return Ok(counts);
+        }
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let tx = self.unchecked_transaction()?;
+        for login in self.get_all()? {
+            if login.fields.origin != old_origin {
+                continue;
+            }
+            let mut fields = login.fields.clone();
+            fields.origin = new_origin.clone();
+            if fields.form_action_origin.as_deref() == Some(old_origin.as_str()) {
+                fields.form_action_origin = Some(new_origin.clone());
+            }
+            let entry = LoginEntry {
+                fields,
+                sec_fields: login.decrypt_fields(encdec)?,
+            };
+            // Logins we've already moved are found as duplicates too.
+            if self.dupe_exists(&login.guid(), &entry, encdec)? {
+                self.delete_in_tx(login.guid_str(), now_ms)?;
+                counts.removed_duplicates += 1;
+                continue;
+            }
+            self.ensure_local_overlay_exists(login.guid_str())?;
+            self.mark_mirror_overridden(login.guid_str())?;
+            self.execute_cached(
+                &format!(
+                    "UPDATE loginsL
+                     SET origin           = :origin,
+                         formActionOrigin = :form_action_origin,
+                         local_modified   = :now_millis,
+                         sync_status      = max(sync_status, {changed})
+                     WHERE guid = :guid",
+                    changed = SyncStatus::Changed as u8
+                ),
+                named_params! {
+                    ":origin": entry.fields.origin,
+                    ":form_action_origin": entry.fields.form_action_origin,
+                    ":now_millis": now_ms,
+                    ":guid": login.guid_str(),
+                },
+            )?;
+            counts.migrated += 1;
+        }
+        tx.commit()?;
+        Ok(counts)
+    }
+
+    fn delete_in_tx(&self, id: &str, now_ms: i64) -> Result<bool> {
+        let exists = self.exists(id)?;
 
         // For IDs that have, mark is_deleted and clear sensitive fields
         self.execute(
@@ -674,7 +884,6 @@ impl LoginDb {
             WHERE guid = :guid",
             changed = SyncStatus::Changed as u8),
             named_params! { ":now_ms": now_ms, ":guid": id })?;
-        tx.commit()?;
         Ok(exists)
     }
 
@@ -759,10 +968,184 @@ impl LoginDb {
             "DELETE FROM loginsL",
             "DELETE FROM loginsM",
             "DELETE FROM loginsSyncMeta",
+            "DELETE FROM loginsQuarantine",
         ])?;
         tx.commit()?;
         Ok(())
     }
+
+    // Returns the guids of the local and mirror records whose encrypted fields
+    // can't be decrypted with `encdec`.
+    fn find_undecryptable(&self, encdec: &EncryptorDecryptor) -> Result<(Vec<Guid>, Vec<Guid>)> {
+        let find = |sql: &str| -> Result<Vec<Guid>> {
+            let rows: Vec<(Guid, Option<String>)> =
+                self.query_rows_and_then(sql, [], |row| -> Result<_> {
+                    Ok((row.get("guid")?, row.get("secFields")?))
+                })?;
+            Ok(rows
+                .into_iter()
+                .filter(|(_, sec_fields)| {
+                    encdec
+                        .decrypt_struct::<SecureLoginFields>(
+                            sec_fields.as_deref().unwrap_or_default(),
+                        )
+                        .is_err()
+                })
+                .map(|(guid, _)| guid)
+                .collect())
+        };
+        Ok((
+            find("SELECT guid, secFields FROM loginsL WHERE is_deleted = 0")?,
+            find("SELECT guid, secFields FROM loginsM")?,
+        ))
+    }
+
+    fn count_undecryptable_in(
+        &self,
+        local: &[Guid],
+        mirror: &[Guid],
+    ) -> Result<UndecryptableLoginsCounts> {
+        let mut counts = UndecryptableLoginsCounts::default();
+        // A bad local record is always visible, and we can get it back from
+        // the server if it was ever synced.
+        for guid in local {
+            counts.total += 1;
+            if self.query_row_and_then_cachable(
+                "SELECT EXISTS(SELECT 1 FROM loginsM WHERE guid = :guid)",
+                named_params! { ":guid": guid },
+                |row| row.get::<_, bool>(0),
+                true,
+            )? {
+                counts.recoverable += 1;
+            }
+        }
+        // A bad mirror record is only visible if there's no local record
+        // overriding it.
+        for guid in mirror {
+            if self.query_row_and_then_cachable(
+                "SELECT is_overridden = 0 FROM loginsM WHERE guid = :guid",
+                named_params! { ":guid": guid },
+                |row| row.get::<_, bool>(0),
+                true,
+            )? {
+                counts.total += 1;
+                counts.recoverable += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Counts the logins which can't be decrypted with `encdec`, which
+    /// usually means the encryption key was lost and had to be regenerated.
+    pub fn count_undecryptable(
+        &self,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<UndecryptableLoginsCounts> {
+        let (local, mirror) = self.find_undecryptable(encdec)?;
+        self.count_undecryptable_in(&local, &mirror)
+    }
+
+    /// Deletes every record which can't be decrypted with `encdec`, and
+    /// returns the counts of logins that were removed.
+    ///
+    /// Local records can't be recovered, and are deleted without leaving a
+    /// tombstone - the data on the server isn't affected by losing our local
+    /// key, so we don't want to delete it there. If we remove any mirror
+    /// records, we reset our last sync time so that the next sync downloads
+    /// them again and encrypts them with the new key.
+    pub fn wipe_undecryptable(
+        &self,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<UndecryptableLoginsCounts> {
+        self.remove_undecryptable(encdec, false)
+    }
+
+    /// Like `wipe_undecryptable`, but moves the records into the quarantine
+    /// table instead of deleting them outright, so that we can report which
+    /// logins were affected, and whether sync brought them back. Used by the
+    /// sync engine's undecryptable recovery mode.
+    pub(crate) fn quarantine_undecryptable(
+        &self,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<UndecryptableLoginsCounts> {
+        self.remove_undecryptable(encdec, true)
+    }
+
+    fn remove_undecryptable(
+        &self,
+        encdec: &EncryptorDecryptor,
+        quarantine: bool,
+    ) -> Result<UndecryptableLoginsCounts> {
+        let tx = self.unchecked_transaction()?;
+        let (local, mirror) = self.find_undecryptable(encdec)?;
+        let counts = self.count_undecryptable_in(&local, &mirror)?;
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let quarantine_from = |table: &str, guid: &Guid| -> Result<()> {
+            if quarantine {
+                self.execute_cached(
+                    &format!(
+                        "INSERT INTO loginsQuarantine(guid, from_mirror, origin, secFields, quarantined_at)
+                         SELECT guid, {from_mirror}, origin, secFields, :now_ms
+                         FROM {table}
+                         WHERE guid = :guid",
+                        from_mirror = (table == "loginsM") as u8,
+                    ),
+                    named_params! { ":guid": guid, ":now_ms": now_ms },
+                )?;
+            }
+            Ok(())
+        };
+        for guid in &local {
+            quarantine_from("loginsL", guid)?;
+            self.execute_cached(
+                "DELETE FROM loginsL WHERE guid = :guid",
+                named_params! { ":guid": guid },
+            )?;
+            // If the mirror has a readable copy, it's the best we have.
+            self.execute_cached(
+                "UPDATE loginsM SET is_overridden = 0 WHERE guid = :guid",
+                named_params! { ":guid": guid },
+            )?;
+        }
+        for guid in &mirror {
+            quarantine_from("loginsM", guid)?;
+            self.execute_cached(
+                "DELETE FROM loginsM WHERE guid = :guid",
+                named_params! { ":guid": guid },
+            )?;
+        }
+        if !mirror.is_empty() {
+            self.delete_meta(schema::LAST_SYNC_META_KEY)?;
+        }
+        tx.commit()?;
+        log::warn!(
+            "{} {} undecryptable local and {} mirror records",
+            if quarantine { "Quarantined" } else { "Wiped" },
+            local.len(),
+            mirror.len()
+        );
+        Ok(counts)
+    }
+
+    /// Counts the logins in the quarantine table, and how many of them sync
+    /// has since brought back.
+    pub fn count_quarantined(&self) -> Result<QuarantinedLoginsCounts> {
+        self.query_row_and_then_cachable(
+            "SELECT COUNT(*),
+                    IFNULL(SUM(EXISTS(SELECT 1 FROM loginsM m WHERE m.guid = q.guid)
+                               OR EXISTS(SELECT 1 FROM loginsL l
+                                         WHERE l.guid = q.guid AND l.is_deleted = 0)), 0)
+             FROM (SELECT DISTINCT guid FROM loginsQuarantine) q",
+            [],
+            |row| -> Result<_> {
+                Ok(QuarantinedLoginsCounts {
+                    total: row.get(0)?,
+                    recovered: row.get(1)?,
+                })
+            },
+            true,
+        )
+    }
 }
 
 lazy_static! {
@@ -972,6 +1355,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "sekret".into(),
+                ..Default::default()
             },
         };
 
@@ -1018,6 +1402,7 @@ mod tests {
                     sec_fields: SecureLoginFields {
                         username: "😍".into(),
                         password: "😍".into(),
+                        ..Default::default()
                     },
                 },
                 &TEST_ENCRYPTOR,
@@ -1055,6 +1440,7 @@ mod tests {
                     sec_fields: SecureLoginFields {
                         username: "😍".into(),
                         password: "😍".into(),
+                        ..Default::default()
                     },
                 },
                 &TEST_ENCRYPTOR,
@@ -1189,6 +1575,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test_user".into(),
                 password: "test_password".into(),
+                ..Default::default()
             },
         };
         let login = db.add(to_add, &TEST_ENCRYPTOR).unwrap();
@@ -1213,6 +1600,7 @@ mod tests {
                     sec_fields: SecureLoginFields {
                         username: "user1".into(),
                         password: "password1".into(),
+                        ..Default::default()
                     },
                 },
                 &TEST_ENCRYPTOR,
@@ -1229,6 +1617,7 @@ mod tests {
                 sec_fields: SecureLoginFields {
                     username: "user2".into(),
                     password: "password2".into(),
+                    ..Default::default()
                 },
             },
             &TEST_ENCRYPTOR,
@@ -1247,6 +1636,111 @@ mod tests {
         assert_eq!(sec_fields.password, "password2");
     }
 
+    #[test]
+    fn test_update_many_rolls_back_failed_entries() {
+        use super::test_utils::{get_local_guids, insert_login};
+        use crate::encryption::create_key;
+
+        let db = LoginDb::open_in_memory().unwrap();
+        // A synced login written with a key we no longer have.
+        insert_login(&db, "aaaaaaaaaaaa", None, Some("password"));
+        let new_encdec = EncryptorDecryptor::new(&create_key().unwrap()).unwrap();
+        let entry = |username: &str| LoginEntry {
+            fields: LoginFields {
+                origin: "https://www.example.com".into(),
+                http_realm: Some("https://www.example.com".into()),
+                ..Default::default()
+            },
+            sec_fields: SecureLoginFields {
+                username: username.into(),
+                password: "password".into(),
+                ..Default::default()
+            },
+        };
+        let login = db.add(entry("user1"), &new_encdec).unwrap();
+
+        let results = db
+            .update_many(
+                vec![
+                    ("aaaaaaaaaaaa".into(), entry("user2")),
+                    (login.record.id.clone(), entry("user3")),
+                ],
+                &new_encdec,
+            )
+            .unwrap();
+        // Updating the synced login fails once it's been cloned into the
+        // local table, when we try to decrypt it. That clone is rolled back...
+        assert!(results[0].is_err());
+        assert_eq!(get_local_guids(&db), vec![login.record.id.clone()]);
+        assert_eq!(
+            db.query_one::<i64>("SELECT is_overridden FROM loginsM")
+                .unwrap(),
+            0
+        );
+        // ...but the other update is still committed.
+        let updated = results[1].as_ref().unwrap();
+        assert_eq!(
+            updated.decrypt_fields(&new_encdec).unwrap().username,
+            "user3"
+        );
+        let stored = db.get_by_id(&login.record.id).unwrap().unwrap();
+        assert_eq!(
+            stored.decrypt_fields(&new_encdec).unwrap().username,
+            "user3"
+        );
+    }
+
+    #[test]
+    fn test_notes() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let login = db
+            .add(
+                LoginEntry {
+                    fields: LoginFields {
+                        origin: "https://www.example.com".into(),
+                        http_realm: Some("https://www.example.com".into()),
+                        ..Default::default()
+                    },
+                    sec_fields: SecureLoginFields {
+                        username: "user1".into(),
+                        password: "password1".into(),
+                        notes: "first note".into(),
+                    },
+                },
+                &TEST_ENCRYPTOR,
+            )
+            .unwrap();
+        let sec_fields = db
+            .get_by_id(&login.record.id)
+            .unwrap()
+            .unwrap()
+            .decrypt_fields(&TEST_ENCRYPTOR)
+            .unwrap();
+        assert_eq!(sec_fields.notes, "first note");
+
+        db.update(
+            &login.record.id,
+            LoginEntry {
+                fields: login.fields.clone(),
+                sec_fields: SecureLoginFields {
+                    notes: "second note".into(),
+                    ..sec_fields
+                },
+            },
+            &TEST_ENCRYPTOR,
+        )
+        .unwrap();
+        let login2 = db.get_by_id(&login.record.id).unwrap().unwrap();
+        let sec_fields = login2.decrypt_fields(&TEST_ENCRYPTOR).unwrap();
+        assert_eq!(sec_fields.password, "password1");
+        assert_eq!(sec_fields.notes, "second note");
+        // Changing the notes alone isn't a password change.
+        assert_eq!(
+            login2.record.time_password_changed,
+            login.record.time_password_changed
+        );
+    }
+
     #[test]
     fn test_touch() {
         let db = LoginDb::open_in_memory().unwrap();
@@ -1261,6 +1755,7 @@ mod tests {
                     sec_fields: SecureLoginFields {
                         username: "user1".into(),
                         password: "password1".into(),
+                        ..Default::default()
                     },
                 },
                 &TEST_ENCRYPTOR,
@@ -1272,6 +1767,193 @@ mod tests {
         assert_eq!(login2.record.times_used, login.record.times_used + 1);
     }
 
+    #[test]
+    fn test_usage_stats() {
+        const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+        let db = LoginDb::open_in_memory().unwrap();
+        let mut ids = vec![];
+        for i in 0..4 {
+            let login = db
+                .add(
+                    LoginEntry {
+                        fields: LoginFields {
+                            origin: format!("https://www.example{}.com", i),
+                            http_realm: Some("https://www.example.com".into()),
+                            ..Default::default()
+                        },
+                        sec_fields: SecureLoginFields {
+                            username: "user".into(),
+                            password: "password".into(),
+                            ..Default::default()
+                        },
+                    },
+                    &TEST_ENCRYPTOR,
+                )
+                .unwrap();
+            ids.push(login.record.id);
+        }
+        db.touch(&ids[0]).unwrap();
+        db.touch(&ids[0]).unwrap();
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let set_last_used = |id: &str, days_ago: i64| {
+            db.execute(
+                "UPDATE loginsL SET timeLastUsed = :last_used, timeCreated = :last_used
+                 WHERE guid = :guid",
+                named_params! { ":last_used": now_ms - days_ago * DAY_MS, ":guid": id },
+            )
+            .unwrap();
+        };
+        set_last_used(&ids[1], 400);
+        set_last_used(&ids[2], 100);
+        set_last_used(&ids[3], 200);
+
+        let stats = db.get_usage_stats(now_ms, 90 * DAY_MS).unwrap();
+        assert_eq!(
+            stats,
+            LoginUsageStats {
+                total_logins: 4,
+                total_times_used: 6,
+                never_used: 3,
+                last_used_within_month: 1,
+                last_used_within_six_months: 1,
+                last_used_within_year: 1,
+                last_used_over_a_year_ago: 1,
+                unused_ids: vec![ids[1].clone(), ids[3].clone(), ids[2].clone()],
+            }
+        );
+
+        // Deleted logins aren't counted.
+        db.delete(&ids[1]).unwrap();
+        let stats = db.get_usage_stats(now_ms, 365 * DAY_MS).unwrap();
+        assert_eq!(stats.total_logins, 3);
+        assert_eq!(stats.last_used_over_a_year_ago, 0);
+        assert!(stats.unused_ids.is_empty());
+    }
+
+    #[test]
+    fn test_undecryptable() {
+        use super::test_utils::{get_local_guids, get_mirror_guids, insert_login};
+        use crate::encryption::create_key;
+
+        let db = LoginDb::open_in_memory().unwrap();
+        // Records written with the old key: one local only, one mirror only,
+        // and one that's in both.
+        insert_login(&db, "aaaaaaaaaaaa", Some("password"), None);
+        insert_login(&db, "bbbbbbbbbbbb", None, Some("password"));
+        insert_login(&db, "cccccccccccc", Some("password"), Some("password"));
+        db.put_meta(schema::LAST_SYNC_META_KEY, &1000).unwrap();
+
+        // The old key is lost, so a new one is used from now on.
+        let new_encdec = EncryptorDecryptor::new(&create_key().unwrap()).unwrap();
+        let new_login = db
+            .add(
+                LoginEntry {
+                    fields: LoginFields {
+                        origin: "https://www.example.com".into(),
+                        http_realm: Some("https://www.example.com".into()),
+                        ..Default::default()
+                    },
+                    sec_fields: SecureLoginFields {
+                        username: "user".into(),
+                        password: "password".into(),
+                        ..Default::default()
+                    },
+                },
+                &new_encdec,
+            )
+            .unwrap();
+
+        let expected = UndecryptableLoginsCounts {
+            total: 3,
+            recoverable: 2,
+        };
+        assert_eq!(db.count_undecryptable(&new_encdec).unwrap(), expected);
+        // Everything is readable with the old key, except the new login.
+        assert_eq!(
+            db.count_undecryptable(&TEST_ENCRYPTOR).unwrap(),
+            UndecryptableLoginsCounts {
+                total: 1,
+                recoverable: 0,
+            }
+        );
+
+        assert_eq!(db.wipe_undecryptable(&new_encdec).unwrap(), expected);
+        assert_eq!(get_local_guids(&db), vec![new_login.record.id]);
+        assert!(get_mirror_guids(&db).is_empty());
+        // No tombstones were written, and the next sync will download
+        // everything again.
+        assert_eq!(
+            db.query_one::<i64>("SELECT COUNT(*) FROM loginsL WHERE is_deleted = 1")
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            db.get_meta::<i64>(schema::LAST_SYNC_META_KEY).unwrap(),
+            None
+        );
+        assert_eq!(
+            db.count_undecryptable(&new_encdec).unwrap(),
+            UndecryptableLoginsCounts::default()
+        );
+    }
+
+    #[test]
+    fn test_quarantine_undecryptable() {
+        use super::test_utils::{get_local_guids, get_mirror_guids, insert_login};
+        use crate::encryption::create_key;
+
+        let db = LoginDb::open_in_memory().unwrap();
+        insert_login(&db, "aaaaaaaaaaaa", Some("password"), None);
+        insert_login(&db, "bbbbbbbbbbbb", None, Some("password"));
+        insert_login(&db, "cccccccccccc", Some("password"), Some("password"));
+        db.put_meta(schema::LAST_SYNC_META_KEY, &1000).unwrap();
+
+        let new_encdec = EncryptorDecryptor::new(&create_key().unwrap()).unwrap();
+        assert_eq!(
+            db.quarantine_undecryptable(&new_encdec).unwrap(),
+            UndecryptableLoginsCounts {
+                total: 3,
+                recoverable: 2,
+            }
+        );
+        assert!(get_local_guids(&db).is_empty());
+        assert!(get_mirror_guids(&db).is_empty());
+        assert_eq!(
+            db.get_meta::<i64>(schema::LAST_SYNC_META_KEY).unwrap(),
+            None
+        );
+        // "cccccccccccc" is quarantined from both tables, but only counted
+        // once.
+        assert_eq!(
+            db.query_one::<i64>("SELECT COUNT(*) FROM loginsQuarantine")
+                .unwrap(),
+            4
+        );
+        assert_eq!(
+            db.count_quarantined().unwrap(),
+            QuarantinedLoginsCounts {
+                total: 3,
+                recovered: 0,
+            }
+        );
+
+        // Sync downloads one of them again.
+        insert_login(&db, "bbbbbbbbbbbb", None, Some("password"));
+        assert_eq!(
+            db.count_quarantined().unwrap(),
+            QuarantinedLoginsCounts {
+                total: 3,
+                recovered: 1,
+            }
+        );
+
+        db.wipe_local().unwrap();
+        assert_eq!(
+            db.count_quarantined().unwrap(),
+            QuarantinedLoginsCounts::default()
+        );
+    }
+
     #[test]
     fn test_delete() {
         let db = LoginDb::open_in_memory().unwrap();
@@ -1286,6 +1968,7 @@ mod tests {
                     sec_fields: SecureLoginFields {
                         username: "test_user".into(),
                         password: "test_password".into(),
+                        ..Default::default()
                     },
                 },
                 &TEST_ENCRYPTOR,
@@ -1308,6 +1991,83 @@ mod tests {
         assert!(!db.exists(login.guid_str()).unwrap());
     }
 
+    #[test]
+    fn test_migrate_origin() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let entry = |origin: &str, username: &str| LoginEntry {
+            fields: LoginFields {
+                origin: origin.into(),
+                form_action_origin: Some(origin.into()),
+                ..Default::default()
+            },
+            sec_fields: SecureLoginFields {
+                username: username.into(),
+                password: "password".into(),
+                ..Default::default()
+            },
+        };
+        let moved = db
+            .add(entry("https://old.example.com", "moved"), &TEST_ENCRYPTOR)
+            .unwrap();
+        let dupe = db
+            .add(entry("https://old.example.com", "dupe"), &TEST_ENCRYPTOR)
+            .unwrap();
+        let existing = db
+            .add(entry("https://new.example.com", "dupe"), &TEST_ENCRYPTOR)
+            .unwrap();
+        let other = db
+            .add(entry("https://other.example.com", "moved"), &TEST_ENCRYPTOR)
+            .unwrap();
+
+        let counts = db
+            .migrate_origin(
+                "https://old.example.com/login",
+                "https://new.example.com",
+                &TEST_ENCRYPTOR,
+            )
+            .unwrap();
+        assert_eq!(
+            counts,
+            MigratedLoginsCounts {
+                migrated: 1,
+                removed_duplicates: 1,
+            }
+        );
+
+        let login = db.get_by_id(moved.guid_str()).unwrap().unwrap();
+        assert_eq!(login.fields.origin, "https://new.example.com");
+        assert_eq!(
+            login.fields.form_action_origin.as_deref(),
+            Some("https://new.example.com")
+        );
+        // Moving a login doesn't count as using it.
+        assert_eq!(login.record, moved.record);
+        assert!(!db.exists(dupe.guid_str()).unwrap());
+        assert!(db.exists(existing.guid_str()).unwrap());
+        assert_eq!(
+            db.get_by_id(other.guid_str())
+                .unwrap()
+                .unwrap()
+                .fields
+                .origin,
+            "https://other.example.com"
+        );
+
+        // There's nothing left to move.
+        assert_eq!(
+            db.migrate_origin(
+                "https://old.example.com",
+                "https://new.example.com",
+                &TEST_ENCRYPTOR
+            )
+            .unwrap(),
+            MigratedLoginsCounts::default()
+        );
+        assert!(db
+            .migrate_origin("not a url", "https://new.example.com", &TEST_ENCRYPTOR)
+            .is_err());
+    }
+
     #[test]
     fn test_wipe() {
         let db = LoginDb::open_in_memory().unwrap();
@@ -1322,6 +2082,7 @@ mod tests {
                     sec_fields: SecureLoginFields {
                         username: "test_user_1".into(),
                         password: "test_password_1".into(),
+                        ..Default::default()
                     },
                 },
                 &TEST_ENCRYPTOR,
@@ -1339,6 +2100,7 @@ mod tests {
                     sec_fields: SecureLoginFields {
                         username: "test_user_1".into(),
                         password: "test_password_2".into(),
+                        ..Default::default()
                     },
                 },
                 &TEST_ENCRYPTOR,
@@ -1398,6 +2160,7 @@ mod tests {
                     sec_fields: SecureLoginFields {
                         username: "test_user_1".into(),
                         password: "test_password_1".into(),
+                        ..Default::default()
                     },
                 },
                 &TEST_ENCRYPTOR,
@@ -1430,6 +2193,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
         let valid_login_guid2: Guid = Guid::random();
@@ -1447,6 +2211,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test2".into(),
                 password: "test2".into(),
+                ..Default::default()
             },
         };
         let valid_login_guid3: Guid = Guid::random();
@@ -1464,6 +2229,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test3".into(),
                 password: "test3".into(),
+                ..Default::default()
             },
         };
         let duplicate_login_guid: Guid = Guid::random();
@@ -1481,6 +2247,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test2".into(),
+                ..Default::default()
             },
         };
 
@@ -1520,6 +2287,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test2".into(),
+                ..Default::default()
             },
         };
         db.import_multiple(vec![login], &TEST_ENCRYPTOR).unwrap();
@@ -1541,6 +2309,7 @@ mod tests {
                 sec_fields: SecureLoginFields {
                     username: username.into(),
                     password: password.into(),
+                    ..Default::default()
                 },
             }
         }
@@ -1579,6 +2348,7 @@ mod tests {
                     sec_fields: SecureLoginFields {
                         username: "user".into(),
                         password: "pass".into(),
+                        ..Default::default()
                     },
                 },
                 &TEST_ENCRYPTOR,
@@ -1595,6 +2365,7 @@ mod tests {
                     sec_fields: SecureLoginFields {
                         username: "user".into(),
                         password: "pass".into(),
+                        ..Default::default()
                     },
                 },
                 &TEST_ENCRYPTOR,
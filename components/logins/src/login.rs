@@ -297,7 +297,7 @@ pub struct LoginFields {
 impl LoginFields {
     /// Internal helper for validation and fixups of an "origin" stored as
     /// a string.
-    fn validate_and_fixup_origin(origin: &str) -> Result<Option<String>> {
+    pub(crate) fn validate_and_fixup_origin(origin: &str) -> Result<Option<String>> {
         // Check we can parse the origin, then use the normalized version of it.
         match Url::parse(origin) {
             Ok(mut u) => {
@@ -366,6 +366,10 @@ pub struct SecureLoginFields {
     pub username: String,
     #[serde(rename = "p")]
     pub password: String,
+    // Free-form notes the user attached to the login. Records written before
+    // this field existed simply don't have it, so it defaults to empty.
+    #[serde(rename = "n", default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
 }
 
 impl SecureLoginFields {
@@ -496,6 +500,72 @@ impl EncryptedLogin {
     }
 }
 
+/// A summary of how the user's logins are used, intended to help them find
+/// logins they no longer need. The "last used" buckets are exclusive, so each
+/// login is counted in exactly one of them.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
+pub struct LoginUsageStats {
+    pub total_logins: i64,
+    /// The sum of `times_used` over all logins.
+    pub total_times_used: i64,
+    /// Logins which have never been used since they were saved. Note that
+    /// saving a login counts as using it once.
+    pub never_used: i64,
+    pub last_used_within_month: i64,
+    pub last_used_within_six_months: i64,
+    pub last_used_within_year: i64,
+    pub last_used_over_a_year_ago: i64,
+    /// The ids of logins which haven't been used for at least the number of
+    /// months requested, least recently used first.
+    pub unused_ids: Vec<String>,
+}
+
+/// Counts of logins whose encrypted fields can't be decrypted with the
+/// current key.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
+pub struct UndecryptableLoginsCounts {
+    /// The number of logins affected.
+    pub total: i64,
+    /// How many of those also exist on the sync server, and so will be
+    /// restored by the next sync after they are wiped.
+    pub recoverable: i64,
+}
+
+/// What `LoginStore::migrate_logins_origin` did.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
+pub struct MigratedLoginsCounts {
+    /// The number of logins moved to the new origin.
+    pub migrated: i64,
+    /// The number of logins deleted because the same login was already saved
+    /// for the new origin.
+    pub removed_duplicates: i64,
+}
+
+/// Counts of logins the sync engine's undecryptable recovery mode has
+/// quarantined.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
+pub struct QuarantinedLoginsCounts {
+    /// The number of logins quarantined.
+    pub total: i64,
+    /// How many of those have since been downloaded again from the sync
+    /// server.
+    pub recovered: i64,
+}
+
+/// An update to an existing login, for `LoginStore::update_many`.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
+pub struct LoginUpdate {
+    pub id: String,
+    pub entry: LoginEntry,
+}
+
+/// The result for a single entry of a bulk operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BulkResultEntry {
+    Success { login: EncryptedLogin },
+    Error { message: String },
+}
+
 fn string_or_default(row: &Row<'_>, col: &str) -> Result<String> {
     Ok(row.get::<_, Option<String>>(col)?.unwrap_or_default())
 }
@@ -720,6 +790,7 @@ pub mod test_utils {
         let sec_fields = SecureLoginFields {
             username: "user".to_string(),
             password: password.to_string(),
+            ..Default::default()
         };
         EncryptedLogin {
             record: RecordFields {
@@ -810,6 +881,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -822,6 +894,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -834,6 +907,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "".into(),
+                ..Default::default()
             },
         };
 
@@ -847,6 +921,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -858,6 +933,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -870,6 +946,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -882,6 +959,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "\0".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -894,6 +972,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "username".into(),
                 password: "test\0".into(),
+                ..Default::default()
             },
         };
 
@@ -906,6 +985,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -919,6 +999,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -931,6 +1012,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -943,6 +1025,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test\n".into(),
+                ..Default::default()
             },
         };
 
@@ -956,6 +1039,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -968,6 +1052,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -980,6 +1065,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -992,6 +1078,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -1004,6 +1091,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -1016,6 +1104,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -1028,6 +1117,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -1040,6 +1130,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -1191,6 +1282,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -1203,6 +1295,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -1215,6 +1308,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
         let login_with_empty_fsu = LoginEntry {
@@ -1226,6 +1320,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -1243,6 +1338,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
 
@@ -1306,6 +1402,7 @@ mod tests {
         let sf = SecureLoginFields {
             username: "foo".into(),
             password: "pwd".into(),
+            ..Default::default()
         };
         assert_eq!(
             serde_json::to_string(&sf).unwrap(),
@@ -1315,6 +1412,7 @@ mod tests {
         let expected = SecureLoginFields {
             username: "user".into(),
             password: "p".into(),
+            ..Default::default()
         };
         assert_eq!(got, expected);
     }
@@ -636,7 +636,12 @@ fn get_login_from_row(row: &Row<'_>) -> Result<Login> {
             username_field,
             password_field,
         },
-        sec_fields: SecureLoginFields { username, password },
+        // SQLCipher databases predate notes.
+        sec_fields: SecureLoginFields {
+            username,
+            password,
+            notes: String::new(),
+        },
     };
     Ok(login)
 }
@@ -882,8 +887,9 @@ mod tests {
             1234
         );
 
-        // The schema version should reset to 1 after the migration
-        assert_eq!(db.query_one::<i64>("PRAGMA user_version").unwrap(), 1);
+        // The schema version should reset after the migration, and the new
+        // database starts out at the current version.
+        assert_eq!(db.query_one::<i64>("PRAGMA user_version").unwrap(), 2);
     }
 
     #[test]
@@ -1125,6 +1131,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
         let valid_login_guid2: Guid = Guid::random();
@@ -1142,6 +1149,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test2".into(),
                 password: "test2".into(),
+                ..Default::default()
             },
         };
         let valid_login_guid3: Guid = Guid::random();
@@ -1159,6 +1167,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test3".into(),
                 password: "test3".into(),
+                ..Default::default()
             },
         };
         // local login + mirror login with override
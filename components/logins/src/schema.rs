@@ -6,11 +6,12 @@
 //! ================
 //!
 //! The schema we use is a evolution of the firefox-ios logins database format.
-//! There are three tables:
+//! There are four tables:
 //!
 //! - `loginsL`: The local table.
 //! - `loginsM`: The mirror table.
 //! - `loginsSyncMeta`: The table used to to store various sync metadata.
+//! - `loginsQuarantine`: Records we couldn't decrypt.
 //!
 //! ## `loginsL`
 //!
@@ -87,14 +88,26 @@
 //!    [GLOBAL_STATE_META_KEY]. This is a `sync15::GlobalState` stored as
 //!    JSON.
 //!
+//! ## `loginsQuarantine`
+//!
+//! When the sync engine's undecryptable recovery mode is on, records from
+//! `loginsL` and `loginsM` whose `secFields` can't be decrypted with the
+//! current key are moved here before syncing. We keep the guid, origin and
+//! encrypted fields, so that we can report which logins were affected and
+//! whether sync brought them back. This table was added in version 3.
+//!
 
 use crate::error::*;
 use lazy_static::lazy_static;
 use rusqlite::Connection;
 use sql_support::ConnExt;
 
-/// The current schema version is 1.  We reset it after the SQLCipher -> plaintext migration.
-const VERSION: i64 = 1;
+/// The current schema version is 3. The migration from SQLCipher to plaintext
+/// reset it to 1, and `upgrade` takes it from there.
+const VERSION: i64 = 3;
+
+/// The name we record the health of the database under.
+pub(crate) const DB_NAME: &str = "logins";
 
 /// Every column shared by both tables except for `id`
 ///
@@ -176,6 +189,18 @@ const CREATE_META_TABLE_SQL: &str = "
     )
 ";
 
+const CREATE_QUARANTINE_TABLE_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS loginsQuarantine (
+        id             INTEGER PRIMARY KEY,
+        guid           TEXT NOT NULL,
+        from_mirror    TINYINT NOT NULL,
+        origin         TEXT NOT NULL,
+        secFields      TEXT,
+        -- Milliseconds
+        quarantined_at INTEGER NOT NULL
+    )
+";
+
 const CREATE_OVERRIDE_ORIGIN_INDEX_SQL: &str = "
     CREATE INDEX IF NOT EXISTS idx_loginsM_is_overridden_origin
     ON loginsM (is_overridden, origin)
@@ -199,7 +224,14 @@ pub(crate) fn init(db: &Connection) -> Result<()> {
     }
     if user_version != VERSION {
         if user_version < VERSION {
+            let start = std::time::Instant::now();
             upgrade(db, user_version)?;
+            error_support::record_database_migration(
+                DB_NAME,
+                user_version as u32,
+                VERSION as u32,
+                start.elapsed(),
+            );
         } else {
             log::warn!(
                 "Loaded future schema version {} (we only understand version {}). \
@@ -212,9 +244,7 @@ pub(crate) fn init(db: &Connection) -> Result<()> {
     Ok(())
 }
 
-// Allow the redundant Ok() here.  It will make more sense once we have an actual upgrade function.
-#[allow(clippy::unnecessary_wraps)]
-fn upgrade(_db: &Connection, from: i64) -> Result<()> {
+fn upgrade(db: &Connection, from: i64) -> Result<()> {
     log::debug!("Upgrading schema from {} to {}", from, VERSION);
     if from == VERSION {
         return Ok(());
@@ -225,6 +255,21 @@ fn upgrade(_db: &Connection, from: i64) -> Result<()> {
     );
 
     // Schema upgrades that should happen after the sqlcipher -> plaintext migration go here
+    if from < 2 {
+        // Version 2 added notes, which are stored with the other encrypted
+        // fields in `secFields`, so the tables themselves don't change.
+        // However, records we've already synced were stored without any notes
+        // Desktop might have added, so forget the last sync time to download
+        // everything again.
+        db.execute(
+            "DELETE FROM loginsSyncMeta WHERE key = :key",
+            rusqlite::named_params! { ":key": LAST_SYNC_META_KEY },
+        )?;
+    }
+    if from < 3 {
+        db.execute_all(&[CREATE_QUARANTINE_TABLE_SQL])?;
+    }
+    db.execute_all(&[&*SET_VERSION_SQL])?;
     Ok(())
 }
 
@@ -236,7 +281,51 @@ pub(crate) fn create(db: &Connection) -> Result<()> {
         CREATE_OVERRIDE_ORIGIN_INDEX_SQL,
         CREATE_DELETED_ORIGIN_INDEX_SQL,
         CREATE_META_TABLE_SQL,
+        CREATE_QUARANTINE_TABLE_SQL,
         &*SET_VERSION_SQL,
     ])?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrade_v1_resets_last_sync() {
+        let db = Connection::open_in_memory().unwrap();
+        create(&db).unwrap();
+        db.execute_batch(
+            "PRAGMA user_version = 1;
+             INSERT INTO loginsSyncMeta (key, value) VALUES ('last_sync_time', 1000);
+             INSERT INTO loginsSyncMeta (key, value) VALUES ('passwords_sync_id', 'abc');",
+        )
+        .unwrap();
+        init(&db).unwrap();
+        assert_eq!(db.query_one::<i64>("PRAGMA user_version").unwrap(), VERSION);
+        let keys: Vec<String> = db
+            .query_rows_and_then("SELECT key FROM loginsSyncMeta ORDER BY key", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(keys, vec!["passwords_sync_id".to_string()]);
+    }
+
+    #[test]
+    fn test_upgrade_v2_adds_quarantine() {
+        let db = Connection::open_in_memory().unwrap();
+        create(&db).unwrap();
+        db.execute_batch(
+            "DROP TABLE loginsQuarantine;
+             PRAGMA user_version = 2;",
+        )
+        .unwrap();
+        init(&db).unwrap();
+        assert_eq!(db.query_one::<i64>("PRAGMA user_version").unwrap(), VERSION);
+        assert_eq!(
+            db.query_one::<i64>("SELECT COUNT(*) FROM loginsQuarantine")
+                .unwrap(),
+            0
+        );
+    }
+}
@@ -1,14 +1,21 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
-use crate::db::LoginDb;
+use crate::db::{LoginDb, LoginsMaintenancePolicy};
 use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
-use crate::login::{EncryptedLogin, Login, LoginEntry};
+use crate::login::{
+    BulkResultEntry, EncryptedLogin, Login, LoginEntry, LoginUpdate, LoginUsageStats,
+    MigratedLoginsCounts, QuarantinedLoginsCounts, UndecryptableLoginsCounts,
+};
+use crate::util;
 use crate::LoginsSyncEngine;
 use parking_lot::Mutex;
+use sql_support::maintenance;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Weak};
+use std::time::SystemTime;
 use sync15::client::{sync_multiple, MemoryCachedState, Sync15StorageClientInit};
 use sync15::engine::{EngineSyncAssociation, SyncEngine, SyncEngineId};
 
@@ -47,26 +54,43 @@ fn create_sync_engine(
     }
 }
 
+// Errors for individual entries go through the same handling as errors
+// returned from the API, so they are logged and reported in the same way.
+fn into_bulk_results(results: Vec<Result<EncryptedLogin>>) -> Vec<BulkResultEntry> {
+    results
+        .into_iter()
+        .map(|result| match result {
+            Ok(login) => BulkResultEntry::Success { login },
+            Err(e) => BulkResultEntry::Error {
+                message: error_support::convert_log_report_error::<_, LoginsApiError>(e)
+                    .to_string(),
+            },
+        })
+        .collect()
+}
+
 pub struct LoginStore {
     pub db: Mutex<LoginDb>,
+    recover_undecryptable_on_sync: AtomicBool,
 }
 
 impl LoginStore {
     pub fn new(path: impl AsRef<Path>) -> ApiResult<Self> {
         handle_error! {
-            let db = Mutex::new(LoginDb::open(path)?);
-            Ok(Self { db })
+            Ok(Self::new_from_db(LoginDb::open(path)?))
         }
     }
 
     pub fn new_from_db(db: LoginDb) -> Self {
-        Self { db: Mutex::new(db) }
+        Self {
+            db: Mutex::new(db),
+            recover_undecryptable_on_sync: AtomicBool::new(false),
+        }
     }
 
     pub fn new_in_memory() -> ApiResult<Self> {
         handle_error! {
-            let db = Mutex::new(LoginDb::open_in_memory()?);
-            Ok(Self { db })
+            Ok(Self::new_from_db(LoginDb::open_in_memory()?))
         }
     }
 
@@ -82,6 +106,17 @@ impl LoginStore {
         }
     }
 
+    /// Returns usage statistics for all logins. Logins which haven't been
+    /// used for `unused_months` (of 30 days each) are listed in
+    /// `LoginUsageStats::unused_ids`.
+    pub fn get_login_usage_stats(&self, unused_months: u32) -> ApiResult<LoginUsageStats> {
+        handle_error! {
+            let now_ms = util::system_time_ms_i64(SystemTime::now());
+            let unused_for_ms = i64::from(unused_months) * 30 * 24 * 60 * 60 * 1000;
+            self.db.lock().get_usage_stats(now_ms, unused_for_ms)
+        }
+    }
+
     pub fn get_by_base_domain(&self, base_domain: &str) -> ApiResult<Vec<EncryptedLogin>> {
         handle_error! {
             self.db.lock().get_by_base_domain(base_domain)
@@ -132,6 +167,76 @@ impl LoginStore {
         }
     }
 
+    /// Vacuums and optimizes the database, when it needs it. Call this
+    /// during idle time.
+    pub fn run_maintenance(&self) -> ApiResult<()> {
+        handle_error! {
+            maintenance::run_maintenance(&self.db.lock(), &LoginsMaintenancePolicy)?;
+            Ok(())
+        }
+    }
+
+    /// Counts the logins which can't be decrypted with `enc_key`. Consumers
+    /// should check this after they had to generate a new key because the
+    /// old one was lost.
+    pub fn count_undecryptable_logins(
+        &self,
+        enc_key: &str,
+    ) -> ApiResult<UndecryptableLoginsCounts> {
+        handle_error! {
+            let encdec = EncryptorDecryptor::new(enc_key)?;
+            self.db.lock().count_undecryptable(&encdec)
+        }
+    }
+
+    /// Deletes the logins which can't be decrypted with `enc_key`, so that
+    /// the rest of the store remains usable. Logins which exist on the sync
+    /// server are downloaded again on the next sync.
+    pub fn wipe_undecryptable_logins(&self, enc_key: &str) -> ApiResult<UndecryptableLoginsCounts> {
+        handle_error! {
+            let encdec = EncryptorDecryptor::new(enc_key)?;
+            self.db.lock().wipe_undecryptable(&encdec)
+        }
+    }
+
+    /// Moves the logins saved for `old_origin` to `new_origin`, for sites
+    /// which changed domains, so that users don't have to save them again.
+    /// Logins which are already saved for `new_origin` are kept, and their
+    /// duplicates for `old_origin` are deleted.
+    pub fn migrate_logins_origin(
+        &self,
+        old_origin: &str,
+        new_origin: &str,
+        enc_key: &str,
+    ) -> ApiResult<MigratedLoginsCounts> {
+        handle_error! {
+            let encdec = EncryptorDecryptor::new(enc_key)?;
+            self.db.lock().migrate_origin(old_origin, new_origin, &encdec)
+        }
+    }
+
+    /// When enabled, each sync first moves the logins which can't be
+    /// decrypted with the sync key into a quarantine table, then downloads
+    /// every record from the server again, so that logins which were synced
+    /// before the key was lost come back. Disabled by default.
+    pub fn set_recover_undecryptable_on_sync(&self, enabled: bool) {
+        self.recover_undecryptable_on_sync
+            .store(enabled, Ordering::SeqCst);
+    }
+
+    pub(crate) fn recover_undecryptable_on_sync(&self) -> bool {
+        self.recover_undecryptable_on_sync.load(Ordering::SeqCst)
+    }
+
+    /// Counts the logins quarantined by syncs with
+    /// `set_recover_undecryptable_on_sync` enabled, and how many of them
+    /// have since been downloaded again.
+    pub fn get_quarantined_logins_counts(&self) -> ApiResult<QuarantinedLoginsCounts> {
+        handle_error! {
+            self.db.lock().count_quarantined()
+        }
+    }
+
     pub fn reset(self: Arc<Self>) -> ApiResult<()> {
         // Reset should not exist here - all resets should be done via the
         // sync manager. It seems that actual consumers don't use this, but
@@ -157,6 +262,43 @@ impl LoginStore {
         }
     }
 
+    /// Adds all the entries in a single transaction. A problem with one entry
+    /// (eg, it's invalid or a duplicate) is reported in its result rather
+    /// than failing the whole operation.
+    pub fn add_many(
+        &self,
+        entries: Vec<LoginEntry>,
+        enc_key: &str,
+    ) -> ApiResult<Vec<BulkResultEntry>> {
+        handle_error! {
+            let encdec = EncryptorDecryptor::new(enc_key)?;
+            let results = self.db.lock().add_many(entries, &encdec)?;
+            Ok(into_bulk_results(results))
+        }
+    }
+
+    /// Like `add_many`, but for updating existing logins.
+    pub fn update_many(
+        &self,
+        updates: Vec<LoginUpdate>,
+        enc_key: &str,
+    ) -> ApiResult<Vec<BulkResultEntry>> {
+        handle_error! {
+            let encdec = EncryptorDecryptor::new(enc_key)?;
+            let updates = updates.into_iter().map(|u| (u.id, u.entry)).collect();
+            let results = self.db.lock().update_many(updates, &encdec)?;
+            Ok(into_bulk_results(results))
+        }
+    }
+
+    /// Deletes all the logins in a single transaction. Returns whether each
+    /// login existed, in the same order as `ids`.
+    pub fn delete_many(&self, ids: Vec<String>) -> ApiResult<Vec<bool>> {
+        handle_error! {
+            self.db.lock().delete_many(&ids)
+        }
+    }
+
     pub fn add_or_update(&self, entry: LoginEntry, enc_key: &str) -> ApiResult<EncryptedLogin> {
         handle_error! {
             let encdec = EncryptorDecryptor::new(enc_key)?;
@@ -290,6 +432,7 @@ mod test {
             sec_fields: SecureLoginFields {
                 username: "coolperson21".into(),
                 password: "p4ssw0rd".into(),
+                ..Default::default()
             },
         };
 
@@ -302,6 +445,7 @@ mod test {
             sec_fields: SecureLoginFields {
                 username: "asdf".into(),
                 password: "fdsa".into(),
+                ..Default::default()
             },
         };
         let a_id = store
@@ -372,6 +516,7 @@ mod test {
             sec_fields: SecureLoginFields {
                 username: b.sec_fields.username.to_owned(),
                 password: "newpass".into(),
+                ..Default::default()
             },
             ..b
         };
@@ -394,6 +539,80 @@ mod test {
         assert_eq!(b_after_update.record.times_used, 2);
     }
 
+    fn entry(origin: &str, username: &str, password: &str) -> LoginEntry {
+        LoginEntry {
+            fields: LoginFields {
+                origin: origin.into(),
+                http_realm: Some("Some String Here".into()),
+                ..Default::default()
+            },
+            sec_fields: SecureLoginFields {
+                username: username.into(),
+                password: password.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_bulk_operations() {
+        let store = LoginStore::new_in_memory().unwrap();
+        let results = store
+            .add_many(
+                vec![
+                    entry("https://www.example.com", "user1", "pass1"),
+                    entry("https://www.example.com", "user2", "pass2"),
+                    // A dupe of the first entry.
+                    entry("https://www.example.com", "user1", "pass3"),
+                    // Invalid.
+                    entry("https://www.example.com", "user4", ""),
+                ],
+                &TEST_ENCRYPTION_KEY,
+            )
+            .unwrap();
+        assert_eq!(results.len(), 4);
+        let ids: Vec<String> = results[..2]
+            .iter()
+            .map(|r| match r {
+                BulkResultEntry::Success { login } => login.record.id.clone(),
+                BulkResultEntry::Error { message } => panic!("unexpected error: {}", message),
+            })
+            .collect();
+        assert!(matches!(results[2], BulkResultEntry::Error { .. }));
+        assert!(matches!(results[3], BulkResultEntry::Error { .. }));
+        assert_eq!(store.list().unwrap().len(), 2);
+
+        let results = store
+            .update_many(
+                vec![
+                    LoginUpdate {
+                        id: ids[0].clone(),
+                        entry: entry("https://www.example.com", "user1", "newpass1"),
+                    },
+                    LoginUpdate {
+                        id: "not-a-login".into(),
+                        entry: entry("https://www.example.com", "user9", "pass9"),
+                    },
+                ],
+                &TEST_ENCRYPTION_KEY,
+            )
+            .unwrap();
+        match &results[0] {
+            BulkResultEntry::Success { login } => {
+                let sec_fields = login.decrypt_fields(&TEST_ENCRYPTOR).unwrap();
+                assert_eq!(sec_fields.password, "newpass1");
+            }
+            BulkResultEntry::Error { message } => panic!("unexpected error: {}", message),
+        }
+        assert!(matches!(results[1], BulkResultEntry::Error { .. }));
+
+        let results = store
+            .delete_many(vec![ids[0].clone(), "not-a-login".into(), ids[1].clone()])
+            .unwrap();
+        assert_eq!(results, vec![true, false, true]);
+        assert!(store.list().unwrap().is_empty());
+    }
+
     #[test]
     fn test_sync_manager_registration() {
         let store = Arc::new(LoginStore::new_in_memory().unwrap());
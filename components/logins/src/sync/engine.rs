@@ -459,6 +459,20 @@ impl SyncEngine for LoginsSyncEngine {
         server_timestamp: ServerTimestamp,
     ) -> anyhow::Result<Vec<CollectionRequest>> {
         let db = self.store.db.lock();
+        // Quarantining undecryptable records from the mirror resets our last
+        // sync time, so this has to happen first.
+        if self.store.recover_undecryptable_on_sync() {
+            if let Some(encdec) = &self.encdec {
+                let counts = db.quarantine_undecryptable(encdec)?;
+                if counts.total > 0 {
+                    log::warn!(
+                        "Quarantined {} undecryptable logins, {} recoverable from the server",
+                        counts.total,
+                        counts.recoverable
+                    );
+                }
+            }
+        }
         let since = self.get_last_sync(&db)?.unwrap_or_default();
         Ok(if since == server_timestamp {
             vec![]
@@ -488,6 +502,11 @@ impl SyncEngine for LoginsSyncEngine {
         db.wipe(&self.scope)?;
         Ok(())
     }
+
+    fn wipe_local(&self) -> anyhow::Result<()> {
+        self.store.db.lock().wipe_local()?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -496,7 +515,9 @@ mod tests {
     use crate::db::test_utils::insert_login;
     use crate::encryption::test_utils::{TEST_ENCRYPTION_KEY, TEST_ENCRYPTOR};
     use crate::login::test_utils::enc_login;
-    use crate::{LoginEntry, LoginFields, RecordFields, SecureLoginFields};
+    use crate::{
+        LoginEntry, LoginFields, QuarantinedLoginsCounts, RecordFields, SecureLoginFields,
+    };
     use std::collections::HashMap;
     use std::sync::Arc;
 
@@ -715,6 +736,51 @@ mod tests {
         assert_eq!(res[1].guid, "dummy_000003");
     }
 
+    #[test]
+    fn test_recover_undecryptable_on_sync() {
+        use crate::encryption::create_key;
+
+        let store = Arc::new(LoginStore::new_in_memory().unwrap());
+        insert_login(&store.db.lock(), "dummy_000001", None, Some("password"));
+        store
+            .db
+            .lock()
+            .put_meta(schema::LAST_SYNC_META_KEY, &10000)
+            .unwrap();
+        // The key the records were written with was lost.
+        let mut engine = LoginsSyncEngine::new(Arc::clone(&store)).unwrap();
+        engine
+            .set_local_encryption_key(&create_key().unwrap())
+            .unwrap();
+
+        // Nothing changes unless recovery is enabled.
+        assert!(engine
+            .get_collection_requests(ServerTimestamp(10000))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store.get_quarantined_logins_counts().unwrap(),
+            QuarantinedLoginsCounts::default()
+        );
+
+        store.set_recover_undecryptable_on_sync(true);
+        assert_eq!(
+            engine
+                .get_collection_requests(ServerTimestamp(10000))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(engine.get_last_sync(&store.db.lock()).unwrap(), None);
+        assert_eq!(
+            store.get_quarantined_logins_counts().unwrap(),
+            QuarantinedLoginsCounts {
+                total: 1,
+                recovered: 0,
+            }
+        );
+    }
+
     fn make_enc_login(
         username: &str,
         password: &str,
@@ -735,6 +801,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: username.into(),
                 password: password.into(),
+                ..Default::default()
             }
             .encrypt(&TEST_ENCRYPTOR)
             .unwrap(),
@@ -754,6 +821,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test".into(),
                 password: "test".into(),
+                ..Default::default()
             },
         };
         let first_id = store
@@ -771,6 +839,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test1".into(),
                 password: "test1".into(),
+                ..Default::default()
             },
         };
         let second_id = store
@@ -788,6 +857,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: "test1".into(),
                 password: "test1".into(),
+                ..Default::default()
             },
         };
         let no_form_origin_id = store
@@ -187,6 +187,7 @@ pub(crate) struct LoginDelta {
     pub username: Option<String>,
     pub http_realm: Option<String>,
     pub form_action_origin: Option<String>,
+    pub notes: Option<String>,
 
     pub time_created: Option<i64>,
     pub time_last_used: Option<i64>,
@@ -224,6 +225,7 @@ impl LoginDelta {
         merge_field!(merged, b, b_is_newer, username);
         merge_field!(merged, b, b_is_newer, http_realm);
         merge_field!(merged, b, b_is_newer, form_action_origin);
+        merge_field!(merged, b, b_is_newer, notes);
 
         merge_field!(merged, b, b_is_newer, time_created);
         merge_field!(merged, b, b_is_newer, time_last_used);
@@ -277,6 +279,9 @@ impl EncryptedLogin {
         if let Some(username) = delta.username.take() {
             sec_fields.username = username;
         }
+        if let Some(notes) = delta.notes.take() {
+            sec_fields.notes = notes;
+        }
         self.sec_fields = encdec.encrypt_struct(&sec_fields)?;
 
         // Use Some("") to indicate that it should be changed to be None (hacky...)
@@ -319,6 +324,9 @@ impl EncryptedLogin {
         if self_sec_fields.password != older_sec_fields.password {
             delta.password = Some(self_sec_fields.password);
         }
+        if self_sec_fields.notes != older_sec_fields.notes {
+            delta.notes = Some(self_sec_fields.notes);
+        }
         if self.fields.password_field != older.fields.password_field {
             delta.password_field = Some(self.fields.password_field.clone());
         }
@@ -40,6 +40,11 @@ pub struct LoginPayload {
 
     pub password: String,
 
+    // Desktop only writes this when the login has notes.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+
     #[serde(default)]
     pub username_field: String,
 
@@ -80,6 +85,7 @@ impl EncryptedLogin {
         let sec_fields = SecureLoginFields {
             username: p.username,
             password: p.password,
+            notes: p.notes,
         };
 
         // If we can't fix the parts we keep the invalid bits.
@@ -110,6 +116,7 @@ impl EncryptedLogin {
             password_field: self.fields.password_field,
             username: sec_fields.username,
             password: sec_fields.password,
+            notes: sec_fields.notes,
             time_created: self.record.time_created,
             time_password_changed: self.record.time_password_changed,
             time_last_used: self.record.time_last_used,
@@ -199,6 +206,7 @@ mod tests {
             sec_fields: encrypt_struct(&SecureLoginFields {
                 username: "user".into(),
                 password: "password".into(),
+                ..Default::default()
             }),
         };
         let payload = login.into_payload(&TEST_ENCRYPTOR).unwrap();
@@ -212,6 +220,45 @@ mod tests {
         assert!(!payload.data.contains_key("formActionOrigin"));
     }
 
+    #[test]
+    fn test_notes_round_trip() {
+        let payload = sync15::Payload::from_json(serde_json::json!({
+            "id": "123412341234",
+            "httpRealm": "test",
+            "hostname": "https://www.example.com",
+            "username": "user",
+            "password": "password",
+            "notes": "security question: blue",
+        }))
+        .unwrap();
+        let login = EncryptedLogin::from_payload(payload, &TEST_ENCRYPTOR).unwrap();
+        let sec_fields = login.decrypt_fields(&TEST_ENCRYPTOR).unwrap();
+        assert_eq!(sec_fields.notes, "security question: blue");
+
+        let payload = login.into_payload(&TEST_ENCRYPTOR).unwrap();
+        assert_eq!(payload.data["notes"], "security question: blue");
+
+        // Logins without notes don't upload an empty field.
+        let login = EncryptedLogin {
+            record: RecordFields {
+                id: "123412341234".into(),
+                ..Default::default()
+            },
+            fields: LoginFields {
+                http_realm: Some("test".into()),
+                origin: "https://www.example.com".into(),
+                ..Default::default()
+            },
+            sec_fields: encrypt_struct(&SecureLoginFields {
+                username: "user".into(),
+                password: "password".into(),
+                ..Default::default()
+            }),
+        };
+        let payload = login.into_payload(&TEST_ENCRYPTOR).unwrap();
+        assert!(!payload.data.contains_key("notes"));
+    }
+
     #[test]
     fn test_username_field_requires_a_form_target() {
         let bad_payload: sync15::Payload = serde_json::from_value(serde_json::json!({
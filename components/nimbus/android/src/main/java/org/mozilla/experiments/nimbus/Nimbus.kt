@@ -36,8 +36,10 @@ import org.mozilla.experiments.nimbus.internal.NimbusClient
 import org.mozilla.experiments.nimbus.internal.NimbusClientInterface
 import org.mozilla.experiments.nimbus.internal.NimbusException
 import org.mozilla.experiments.nimbus.internal.RemoteSettingsConfig
+import org.mozilla.experiments.nimbus.internal.TargetingAttributeProvider
 import java.io.File
 import java.io.IOException
+import java.util.concurrent.atomic.AtomicBoolean
 
 private const val EXPERIMENT_COLLECTION_NAME = "nimbus-mobile-experiments"
 private const val NIMBUS_DATA_DIR: String = "nimbus_data"
@@ -62,7 +64,8 @@ open class Nimbus(
     server: NimbusServerSettings?,
     deviceInfo: NimbusDeviceInfo,
     private val observer: NimbusInterface.Observer? = null,
-    delegate: NimbusDelegate
+    delegate: NimbusDelegate,
+    profileId: String? = null
 ) : NimbusInterface {
     // An I/O scope is used for reading or writing from the Nimbus's RKV database.
     private val dbScope: CoroutineScope = delegate.dbScope
@@ -78,6 +81,8 @@ open class Nimbus(
 
     private val nimbusClient: NimbusClientInterface
 
+    private val exposureFlushScheduled = AtomicBoolean(false)
+
     override var globalUserParticipation: Boolean
         get() = nimbusClient.getGlobalUserParticipation()
         set(active) {
@@ -115,7 +120,8 @@ open class Nimbus(
             remoteSettingsConfig,
             // The "dummy" field here is required for obscure reasons when generating code on desktop,
             // so we just automatically set it to a dummy value.
-            AvailableRandomizationUnits(clientId = null, dummy = 0)
+            AvailableRandomizationUnits(clientId = null, dummy = 0),
+            profileId
         )
     }
 
@@ -357,6 +363,12 @@ open class Nimbus(
         recordExposure(featureId)
     }
 
+    override fun flushExposureEvents() {
+        dbScope.launch {
+            flushExposureEventsOnThisThread()
+        }
+    }
+
     @WorkerThread
     override fun recordEvent(eventId: String) {
         dbScope.launch {
@@ -364,6 +376,10 @@ open class Nimbus(
         }
     }
 
+    override fun registerTargetingAttributeProvider(name: String, provider: TargetingAttributeProvider) {
+        nimbusClient.registerTargetingAttributeProvider(name, provider)
+    }
+
     override fun createMessageHelper(additionalContext: JSONObject?): GleanPlumbMessageHelper =
         GleanPlumbMessageHelper(
             nimbusClient.createTargetingHelper(additionalContext),
@@ -438,12 +454,26 @@ open class Nimbus(
     @VisibleForTesting(otherwise = VisibleForTesting.PRIVATE)
     @WorkerThread
     internal fun recordExposureOnThisThread(featureId: String) = withCatchAll {
-        val activeExperiments = getActiveExperiments()
-        activeExperiments.find { it.featureIds.contains(featureId) }?.also { experiment ->
+        nimbusClient.recordFeatureExposure(featureId)
+        // Flush once the exposures which are already queued have been recorded, so a burst of
+        // lookups only records one event per feature.
+        if (!exposureFlushScheduled.getAndSet(true)) {
+            dbScope.launch {
+                flushExposureEventsOnThisThread()
+            }
+        }
+    }
+
+    @VisibleForTesting(otherwise = VisibleForTesting.PRIVATE)
+    @WorkerThread
+    internal fun flushExposureEventsOnThisThread() = withCatchAll {
+        exposureFlushScheduled.set(false)
+        nimbusClient.flushExposureEvents().forEach { event ->
             NimbusEvents.exposure.record(NimbusEvents.ExposureExtra(
-                experiment = experiment.slug,
-                branch = experiment.branchSlug,
-                featureId = featureId
+                experiment = event.experimentSlug,
+                branch = event.branchSlug,
+                featureId = event.featureId,
+                count = event.count.toInt()
             ))
         }
     }
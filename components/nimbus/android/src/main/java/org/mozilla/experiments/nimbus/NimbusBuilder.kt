@@ -58,6 +58,12 @@ abstract class AbstractNimbusBuilder<T : NimbusInterface>(val context: Context)
      */
     var onCreateCallback: (T) -> Unit = {}
 
+    /**
+     * An optional ID for the app's current profile. Apps which switch between profiles should
+     * set this, so that each profile is enrolled in experiments separately.
+     */
+    var profileId: String? = null
+
     /**
      * Build a [Nimbus] singleton for the given [NimbusAppInfo]. Instances built with this method
      * have been initialized, and are ready for use by the app.
@@ -137,7 +143,8 @@ class DefaultNimbusBuilder(context: Context) : AbstractNimbusBuilder<NimbusInter
             appInfo = appInfo,
             server = serverSettings,
             deviceInfo = NimbusDeviceInfo(Locale.getDefault().toLanguageTag()),
-            delegate = NimbusDelegate.default()
+            delegate = NimbusDelegate.default(),
+            profileId = profileId
         )
 
     override fun newNimbusDisabled() = NullNimbus(context)
@@ -11,6 +11,7 @@ import kotlinx.coroutines.Job
 import org.mozilla.experiments.nimbus.internal.AvailableExperiment
 import org.mozilla.experiments.nimbus.internal.EnrolledExperiment
 import org.mozilla.experiments.nimbus.internal.ExperimentBranch
+import org.mozilla.experiments.nimbus.internal.TargetingAttributeProvider
 
 // Republish these classes from this package.
 typealias Branch = ExperimentBranch
@@ -181,6 +182,15 @@ interface NimbusInterface : FeaturesInterface, GleanPlumbInterface {
      */
     override fun recordExposureEvent(featureId: String) = Unit
 
+    /**
+     * Records the `exposure` events batched since the last flush in telemetry.
+     *
+     * Exposures are batched, so that a feature which is looked up many times only records a single
+     * event, with a count of how many exposures it stands for. The SDK flushes the batch shortly
+     * after recording an exposure, but apps may want to call this when they go into the background.
+     */
+    fun flushExposureEvents() = Unit
+
     /**
      * Records an event to the Nimbus event store.
      *
@@ -192,6 +202,19 @@ interface NimbusInterface : FeaturesInterface, GleanPlumbInterface {
      */
     fun recordEvent(eventId: String) = Unit
 
+    /**
+     * Registers a provider of extra targeting attributes, eg, coarse counts of the user's
+     * bookmarks and history from places. The attributes are available to targeting expressions
+     * as `name.attribute`.
+     *
+     * Providers are asked for their attributes whenever the enrollments are re-evaluated, so
+     * they should be registered before calling [initialize].
+     *
+     * @param name the name the attributes are available under in targeting expressions.
+     * @param provider returns the attributes as a JSON object.
+     */
+    fun registerTargetingAttributeProvider(name: String, provider: TargetingAttributeProvider) = Unit
+
     /**
      * Control the opt out for all experiments at once. This is likely a user action.
      */
@@ -221,6 +221,11 @@ class NimbusTests {
 
         // Record a valid exposure event in Glean that matches the featureId from the test experiment
         nimbus.recordExposureOnThisThread("about_welcome")
+        nimbus.recordExposureOnThisThread("about_welcome")
+
+        // Nothing is recorded until the batch is flushed.
+        assertNull("Event must not have a value", NimbusEvents.exposure.testGetValue())
+        nimbus.flushExposureEventsOnThisThread()
 
         // Use the Glean test API to check that the valid event is present
         assertNotNull("Event must have a value", NimbusEvents.exposure.testGetValue())
@@ -233,10 +238,12 @@ class NimbusTests {
             exposureEventExtras["experiment"]
         )
         assertEquals("Experiment branch must match", "test-branch", exposureEventExtras["branch"])
+        assertEquals("Exposure count must match", "2", exposureEventExtras["count"])
 
         // Attempt to record an event for a non-existent or feature we are not enrolled in an
         // experiment in to ensure nothing is recorded.
         nimbus.recordExposureOnThisThread("not-a-feature")
+        nimbus.flushExposureEventsOnThisThread()
 
         // Verify the invalid event was ignored by checking again that the valid event is still the only
         // event, and that it hasn't changed any of its extra properties.
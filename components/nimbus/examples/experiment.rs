@@ -198,7 +198,7 @@ fn main() -> Result<()> {
     let aru = AvailableRandomizationUnits::with_client_id(&client_id);
 
     // Here we initialize our main `NimbusClient` struct
-    let nimbus_client = NimbusClient::new(context.clone(), db_path, Some(config), aru)?;
+    let nimbus_client = NimbusClient::new(context.clone(), db_path, Some(config), aru, None)?;
     log::info!("Nimbus ID is {}", nimbus_client.nimbus_id()?);
 
     // Explicitly update experiments at least once for init purposes
@@ -12,6 +12,10 @@ public class Nimbus: NimbusApi {
 
     private let errorReporter: NimbusErrorReporter
 
+    private let exposureFlushLock = NSLock()
+
+    private var exposureFlushScheduled = false
+
     lazy var fetchQueue: OperationQueue = {
         var queue = OperationQueue()
         queue.name = "Nimbus fetch queue"
@@ -62,24 +66,43 @@ extension Nimbus: NimbusEvents {
             try self.nimbusClient.recordEvent(eventId: eventId)
         }
     }
+
+    public func flushExposureEvents() {
+        exposureFlushLock.lock()
+        exposureFlushScheduled = false
+        exposureFlushLock.unlock()
+
+        // Only exposures to features in an active experiment were batched, so there's
+        // nothing left to check before recording them.
+        for event in nimbusClient.flushExposureEvents() {
+            GleanMetrics.NimbusEvents.exposure.record(GleanMetrics.NimbusEvents.ExposureExtra(
+                branch: event.branchSlug,
+                count: Int32(clamping: event.count),
+                experiment: event.experimentSlug,
+                featureId: event.featureId
+            ))
+        }
+    }
 }
 
 extension Nimbus: FeaturesInterface {
     public func recordExposureEvent(featureId: String) {
-        // First we need a list of the active experiments that are enrolled.
-        let activeExperiments = getActiveExperiments()
-
-        // Next, we search for any experiment that has a matching featureId. This depends on the
-        // fact that we can only be enrolled in a single experiment per feature, so there should
-        // only ever be zero or one experiments for a given featureId.
-        if let experiment = activeExperiments.first(where: { $0.featureIds.contains(featureId) }) {
-            // Finally, if we do have an experiment for the given featureId, we will record the
-            // exposure event in Glean. This is to protect against accidentally recording an event
-            // for an experiment without an active enrollment.
-            GleanMetrics.NimbusEvents.exposure.record(GleanMetrics.NimbusEvents.ExposureExtra(
-                branch: experiment.branchSlug,
-                experiment: experiment.slug
-            ))
+        // The exposure is batched in Rust, which only keeps it if we're enrolled in an
+        // experiment for the given featureId. This protects against accidentally recording an
+        // event for an experiment without an active enrollment.
+        catchAll {
+            try nimbusClient.recordFeatureExposure(featureId: featureId)
+        }
+
+        // Flush once the operations which are already queued have run, so that a burst of
+        // lookups only records one event per feature.
+        exposureFlushLock.lock()
+        defer { exposureFlushLock.unlock() }
+        if !exposureFlushScheduled {
+            exposureFlushScheduled = true
+            dbQueue.addOperation {
+                self.flushExposureEvents()
+            }
         }
     }
 
@@ -283,6 +306,10 @@ extension Nimbus: NimbusUserConfiguration {
 }
 
 extension Nimbus: NimbusStartup {
+    public func registerTargetingAttributeProvider(name: String, provider: TargetingAttributeProvider) {
+        nimbusClient.registerTargetingAttributeProvider(name: name, provider: provider)
+    }
+
     public func initialize() {
         catchAll(dbQueue) {
             try self.initializeOnThisThread()
@@ -372,6 +399,8 @@ public extension NimbusDisabled {
         return NilVariables.instance
     }
 
+    func registerTargetingAttributeProvider(name _: String, provider _: TargetingAttributeProvider) {}
+
     func initialize() {}
 
     func fetchExperiments() {}
@@ -392,6 +421,8 @@ public extension NimbusDisabled {
 
     func recordEvent(_: String) {}
 
+    func flushExposureEvents() {}
+
     func getExperimentBranches(_: String) -> [Branch]? {
         return nil
     }
@@ -44,6 +44,19 @@ public extension FeaturesInterface {
 }
 
 public protocol NimbusStartup {
+    /// Registers a provider of extra targeting attributes, eg, coarse counts of the user's
+    /// bookmarks and history from places. The attributes are available to targeting expressions
+    /// as `name.attribute`.
+    ///
+    /// Providers are asked for their attributes whenever the enrollments are re-evaluated, so
+    /// they should be registered before calling `initialize()`.
+    ///
+    /// - Parameters:
+    ///     - name the name the attributes are available under in targeting expressions.
+    ///     - provider returns the attributes as a JSON object.
+    ///
+    func registerTargetingAttributeProvider(name: String, provider: TargetingAttributeProvider)
+
     /// Open the database and populate the SDK so as make it usable by feature developers.
     ///
     /// This performs the minimum amount of I/O needed to ensure `getExperimentBranch()` is usable.
@@ -149,6 +162,14 @@ public protocol NimbusEvents {
     ///
     /// - Parameter eventId string representing the id of the event which should be recorded.
     func recordEvent(_ eventId: String)
+
+    /// Records the `exposure` events batched since the last flush in telemetry.
+    ///
+    /// Exposures are batched, so that a feature which is looked up many times only records a
+    /// single event, with a count of how many exposures it stands for. The SDK flushes the batch
+    /// shortly after recording an exposure, but apps may want to call this when they go into
+    /// the background.
+    func flushExposureEvents()
 }
 
 /// Notifications emitted by the `NotificationCenter`.
@@ -28,6 +28,8 @@ public extension Nimbus {
     ///     - resourceBundles: an optional array of `Bundle` objects that are used to lookup text and images
     ///     - enabled: intended for FeatureFlags. If false, then return a dummy `Nimbus` instance. Defaults to `true`.
     ///     - errorReporter: a closure capable of reporting errors. Defaults to using a logger.
+    ///     - profileId: an optional ID for the app's current profile, so that each profile is enrolled in
+    ///       experiments separately.
     /// - Returns an implementation of `NimbusApi`.
     /// - Throws `NimbusError` if anything goes wrong with the Rust FFI or in the `NimbusClient` constructor.
     ///
@@ -37,7 +39,8 @@ public extension Nimbus {
         dbPath: String,
         resourceBundles: [Bundle] = [Bundle.main],
         enabled: Bool = true,
-        errorReporter: @escaping NimbusErrorReporter = defaultErrorReporter
+        errorReporter: @escaping NimbusErrorReporter = defaultErrorReporter,
+        profileId: String? = nil
     ) throws -> NimbusApi {
         guard enabled else {
             return NimbusDisabled.shared
@@ -56,7 +59,8 @@ public extension Nimbus {
             remoteSettingsConfig: remoteSettings,
             // The "dummy" field here is required for obscure reasons when generating code on desktop,
             // so we just automatically set it to a dummy value.
-            availableRandomizationUnits: AvailableRandomizationUnits(clientId: nil, dummy: 0)
+            availableRandomizationUnits: AvailableRandomizationUnits(clientId: nil, dummy: 0),
+            profileId: profileId
         )
 
         return Nimbus(nimbusClient: nimbusClient, resourceBundles: resourceBundles, errorReporter: errorReporter)
@@ -2,33 +2,26 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-//! This is a simple HTTP client that uses viaduct to retrieve experiment data from the server.
-//! Currently configured to use Kinto and the old schema, although that would change once we start
-//! working on the real Nimbus schema.
+//! Fetches experiment data from the server, with the Remote Settings client
+//! from the `remote_settings` component, which other components share.
 //!
-//! In the future we might replace this with a more fully-feature Remote Settings client, such as:
-//!
-//!   https://github.com/mozilla-services/remote-settings-client
-//!   Issue: https://github.com/mozilla/application-services/issues/3475
-//!
-//! But the simple subset implemented here meets our needs for now.
+//! The client keeps track of the `Backoff` and `Retry-After` headers the
+//! server sends, and refuses to make requests until the backoff is over.
 
 use std::time::{Duration, Instant};
 
+use super::CachedResponse;
 use crate::config::RemoteSettingsConfig;
 use crate::error::{NimbusError, Result};
 use crate::{Experiment, SettingsClient, SCHEMA_VERSION};
-use std::cell::Cell;
-use url::Url;
-use viaduct::{status_codes, Request, Response};
-
-const HEADER_BACKOFF: &str = "Backoff";
-const HEADER_RETRY_AFTER: &str = "Retry-After";
+use remote_settings::FetchResult;
+use std::cell::{Cell, RefCell};
 
 pub struct Client {
-    pub(crate) base_url: Url,
+    pub(crate) rs_client: remote_settings::Client,
     pub(crate) collection_name: String,
     pub(crate) remote_state: Cell<RemoteState>,
+    pub(crate) cached_response: RefCell<Option<CachedResponse>>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -43,25 +36,17 @@ pub(crate) enum RemoteState {
 impl Client {
     #[allow(unused)]
     pub fn new(config: RemoteSettingsConfig) -> Result<Self> {
-        let base_url = Url::parse(&config.server_url)?;
+        let rs_client = remote_settings::Client::new(
+            remote_settings::RemoteSettingsConfig::with_server_url(config.server_url),
+        )?;
         Ok(Self {
-            base_url,
+            rs_client,
             collection_name: config.collection_name,
             remote_state: Cell::new(RemoteState::Ok),
+            cached_response: RefCell::new(None),
         })
     }
 
-    fn make_request(&self, request: Request) -> Result<Response> {
-        self.ensure_no_backoff()?;
-        let resp = request.send()?;
-        self.handle_backoff_hint(&resp)?;
-        if resp.is_success() || resp.status == status_codes::NOT_MODIFIED {
-            Ok(resp)
-        } else {
-            Err(NimbusError::ResponseError(resp.text().to_string()))
-        }
-    }
-
     fn ensure_no_backoff(&self) -> Result<()> {
         if let RemoteState::Backoff {
             observed_at,
@@ -78,45 +63,54 @@ impl Client {
         }
         Ok(())
     }
+}
 
-    fn handle_backoff_hint(&self, response: &Response) -> Result<()> {
-        let extract_backoff_header = |header| -> Result<u64> {
-            Ok(response
-                .headers
-                .get_as::<u64, _>(header)
-                .transpose()
-                .unwrap_or_default() // Ignore number parsing errors.
-                .unwrap_or(0))
-        };
-        // In practice these two headers are mutually exclusive.
-        let backoff = extract_backoff_header(HEADER_BACKOFF)?;
-        let retry_after = extract_backoff_header(HEADER_RETRY_AFTER)?;
-        let max_backoff = backoff.max(retry_after);
+impl SettingsClient for Client {
+    fn get_experiments_metadata(&self) -> Result<String> {
+        unimplemented!();
+    }
 
-        if max_backoff > 0 {
+    fn fetch_experiments(&self) -> Result<Vec<Experiment>> {
+        self.ensure_no_backoff()?;
+        let etag = self
+            .cached_response
+            .borrow()
+            .as_ref()
+            .map(|cached| cached.etag.clone());
+        let response = self
+            .rs_client
+            .fetch_collection(&self.collection_name, etag.as_deref())?;
+        if let Some(duration) = response.backoff {
             self.remote_state.replace(RemoteState::Backoff {
                 observed_at: Instant::now(),
-                duration: Duration::from_secs(max_backoff),
+                duration,
             });
         }
-        Ok(())
+        match response.result? {
+            FetchResult::NotModified => match &*self.cached_response.borrow() {
+                Some(cached) => {
+                    log::info!("Experiments haven't changed, using the cached records");
+                    parse_experiments(&cached.body)
+                }
+                // The Remote Settings client already checks that we sent an
+                // `ETag`.
+                None => Err(NimbusError::InternalError("304 without a cached response")),
+            },
+            FetchResult::Changed { body, etag } => {
+                let experiments = parse_experiments(&body)?;
+                self.cached_response
+                    .replace(etag.map(|etag| CachedResponse { etag, body }));
+                Ok(experiments)
+            }
+        }
     }
-}
 
-impl SettingsClient for Client {
-    fn get_experiments_metadata(&self) -> Result<String> {
-        unimplemented!();
+    fn cached_response(&self) -> Option<CachedResponse> {
+        self.cached_response.borrow().clone()
     }
 
-    fn fetch_experiments(&self) -> Result<Vec<Experiment>> {
-        let path = format!(
-            "v1/buckets/main/collections/{}/records",
-            &self.collection_name
-        );
-        let url = self.base_url.join(&path)?;
-        let req = Request::get(url);
-        let resp = self.make_request(req)?;
-        parse_experiments(&resp.text())
+    fn set_cached_response(&self, response: Option<CachedResponse>) {
+        self.cached_response.replace(response);
     }
 }
 
@@ -11,6 +11,7 @@ use crate::RemoteSettingsConfig;
 use fs_client::FileSystemClient;
 use http_client::Client;
 use null_client::NullClient;
+use serde_derive::*;
 use url::Url;
 
 pub use http_client::parse_experiments;
@@ -46,4 +47,20 @@ pub(crate) fn create_client(
 pub(crate) trait SettingsClient {
     fn get_experiments_metadata(&self) -> Result<String>;
     fn fetch_experiments(&self) -> Result<Vec<Experiment>>;
+
+    // Clients which ask the server to only send the experiments when they've
+    // changed use the last response they got. Nimbus keeps it in the
+    // database, so that it survives the app restarting.
+    fn cached_response(&self) -> Option<CachedResponse> {
+        None
+    }
+    fn set_cached_response(&self, _response: Option<CachedResponse>) {}
+}
+
+// The last response we got from the server, so that we can ask the server to
+// only send the records again if they've changed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CachedResponse {
+    pub(crate) etag: String,
+    pub(crate) body: String,
 }
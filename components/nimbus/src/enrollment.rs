@@ -184,9 +184,9 @@ impl ExperimentEnrollment {
                 }
             }
             EnrollmentStatus::Enrolled {
+                ref enrollment_id,
                 ref branch,
                 ref reason,
-                ..
             } => {
                 if !is_user_participating {
                     log::debug!(
@@ -231,6 +231,26 @@ impl ExperimentEnrollment {
                             out_enrollment_events.push(updated_enrollment.get_change_event());
                             updated_enrollment
                         }
+                        // Experiments keep the clients they enrolled even if the
+                        // bucketing changes, but a rollout which is ramped down
+                        // should really reach fewer clients. We move them back
+                        // to not enrolled, rather than disqualifying them, so
+                        // that they're enrolled again if the rollout is ramped
+                        // back up, and so that older versions can still read
+                        // the enrollment after a downgrade.
+                        EnrollmentStatus::NotEnrolled {
+                            reason: NotEnrolledReason::NotSelected,
+                        } if updated_experiment.is_rollout() => {
+                            log::debug!("Existing rollout enrollment '{}' is now not enrolled (bucketing change)", &self.slug);
+                            out_enrollment_events.push(EnrollmentChangeEvent::new(
+                                &self.slug,
+                                enrollment_id,
+                                branch,
+                                Some("bucketing"),
+                                EnrollmentChangeEventType::Disqualification,
+                            ));
+                            evaluated_enrollment
+                        }
                         EnrollmentStatus::NotEnrolled { .. }
                         | EnrollmentStatus::Enrolled { .. }
                         | EnrollmentStatus::Disqualified { .. }
@@ -1023,8 +1043,15 @@ pub fn map_features_by_feature_id(
     let (experiments, exp_enrollments) =
         filter_experiments_and_enrollments(experiments, enrollments, |e| !e.is_rollout());
 
-    let features_under_rollout = map_features(&ro_enrollments, &map_experiments(&rollouts));
-    let features_under_experiment = map_features(&exp_enrollments, &map_experiments(&experiments));
+    let experiments = map_experiments(&experiments);
+    let features_under_experiment = map_features(&exp_enrollments, &experiments);
+    let mut features_under_rollout = map_features(&ro_enrollments, &map_experiments(&rollouts));
+    features_under_rollout.retain(|feature_id, _| {
+        !features_under_experiment
+            .get(feature_id)
+            .and_then(|config| experiments.get(&config.slug))
+            .map_or(false, |experiment| experiment.is_holdback)
+    });
 
     features_under_experiment
         .defaults(&features_under_rollout)
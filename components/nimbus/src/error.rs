@@ -75,6 +75,21 @@ pub enum BehaviorError {
     MissingEventStore,
 }
 
+// The errors from fetching experiments keep the variants they had before we
+// used the shared Remote Settings client, which apps already handle.
+impl From<remote_settings::RemoteSettingsError> for NimbusError {
+    fn from(error: remote_settings::RemoteSettingsError) -> Self {
+        use remote_settings::RemoteSettingsError;
+        match error {
+            RemoteSettingsError::JSONError(e) => NimbusError::JSONError(e),
+            RemoteSettingsError::UrlParsingError(e) => NimbusError::UrlParsingError(e),
+            RemoteSettingsError::RequestError(e) => NimbusError::RequestError(e),
+            RemoteSettingsError::BackoffError(secs) => NimbusError::BackoffError(secs),
+            RemoteSettingsError::ResponseError(message) => NimbusError::ResponseError(message),
+        }
+    }
+}
+
 impl<'a> From<jexl_eval::error::EvaluationError<'a>> for NimbusError {
     fn from(eval_error: jexl_eval::error::EvaluationError<'a>) -> Self {
         NimbusError::EvaluationError(eval_error.to_string())
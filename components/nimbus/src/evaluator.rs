@@ -15,7 +15,7 @@ use crate::{matcher::AppContext, sampling};
 use crate::{Branch, Experiment};
 use jexl_eval::Evaluator;
 use serde_derive::*;
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
 use std::collections::HashSet;
 use std::str::FromStr;
 use uuid::Uuid;
@@ -39,6 +39,23 @@ pub struct TargetingAttributes {
     pub days_since_install: Option<i32>,
     pub days_since_update: Option<i32>,
     pub active_experiments: HashSet<String>,
+    /// Attributes from the registered `TargetingAttributeProvider`s, keyed
+    /// by the name each provider was registered with.
+    #[serde(flatten)]
+    pub provided_attributes: Map<String, Value>,
+}
+
+/// Provides extra targeting attributes which are computed elsewhere - eg,
+/// coarse counts of the user's bookmarks and history from places - so that
+/// experiments can target heavy or light users without the app having to
+/// compute them up front.
+///
+/// The application implements this via a UniFFI callback interface, and
+/// registers it with `NimbusClient::register_targeting_attribute_provider()`.
+pub trait TargetingAttributeProvider: Send + Sync {
+    /// Returns the attributes as a JSON object. Providers should only return
+    /// coarse values (eg, "more than 100 bookmarks"), never raw user data.
+    fn get_attributes(&self) -> String;
 }
 
 impl From<AppContext> for TargetingAttributes {
@@ -0,0 +1,61 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Batches exposure events.
+//!
+//! Apps often look up a feature, and record an exposure to it, every time
+//! they draw a screen. Rather than recording a telemetry event each time, we
+//! collect the exposures here, and the app records a single event for each
+//! experiment, branch and feature when it flushes the batch, along with the
+//! number of exposures it stands for.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExposureEvent {
+    pub experiment_slug: String,
+    pub branch_slug: String,
+    pub feature_id: String,
+    /// The number of exposures since the last flush.
+    pub count: u32,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ExposureBatch {
+    // In the order they were first recorded.
+    events: Vec<ExposureEvent>,
+    // Indexes into `events`, keyed by the experiment, branch and feature.
+    index: HashMap<(String, String, String), usize>,
+}
+
+impl ExposureBatch {
+    pub(crate) fn record(&mut self, experiment_slug: &str, branch_slug: &str, feature_id: &str) {
+        let key = (
+            experiment_slug.to_string(),
+            branch_slug.to_string(),
+            feature_id.to_string(),
+        );
+        match self.index.get(&key) {
+            Some(&i) => {
+                let event = &mut self.events[i];
+                event.count = event.count.saturating_add(1);
+            }
+            None => {
+                self.index.insert(key, self.events.len());
+                self.events.push(ExposureEvent {
+                    experiment_slug: experiment_slug.to_string(),
+                    branch_slug: branch_slug.to_string(),
+                    feature_id: feature_id.to_string(),
+                    count: 1,
+                });
+            }
+        }
+    }
+
+    /// Returns the batched events, and starts a new batch.
+    pub(crate) fn take(&mut self) -> Vec<ExposureEvent> {
+        self.index.clear();
+        std::mem::take(&mut self.events)
+    }
+}
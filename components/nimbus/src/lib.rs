@@ -7,6 +7,7 @@ mod dbcache;
 mod enrollment;
 pub mod error;
 mod evaluator;
+mod exposure;
 use behavior::EventStore;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use defaults::Defaults;
@@ -23,7 +24,7 @@ pub mod versioning;
 #[cfg(debug_assertions)]
 pub use evaluator::evaluate_enrollment;
 
-use client::{create_client, parse_experiments, SettingsClient};
+use client::{create_client, parse_experiments, CachedResponse, SettingsClient};
 pub use config::RemoteSettingsConfig;
 use dbcache::DatabaseCache;
 pub use enrollment::EnrollmentStatus;
@@ -32,10 +33,14 @@ use enrollment::{
     EnrollmentChangeEvent, EnrollmentsEvolver,
 };
 use evaluator::is_experiment_available;
+use exposure::ExposureBatch;
+pub use exposure::ExposureEvent;
 
 // Exposed for Example only
 pub use evaluator::TargetingAttributes;
 
+pub use evaluator::TargetingAttributeProvider;
+
 // We only use this in a test, and with --no-default-features, we don't use it
 // at all
 #[allow(unused_imports)]
@@ -46,7 +51,7 @@ use once_cell::sync::OnceCell;
 use persistence::{Database, StoreId, Writer};
 use serde_derive::*;
 use serde_json::{Map, Value};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, MutexGuard};
 use updating::{read_and_remove_pending_experiments, write_pending_experiments};
@@ -57,6 +62,7 @@ mod tests;
 
 const DEFAULT_TOTAL_BUCKETS: u32 = 10000;
 const DB_KEY_NIMBUS_ID: &str = "nimbus-id";
+const DB_KEY_REMOTE_SETTINGS_RESPONSE: &str = "remote-settings-response";
 pub const DB_KEY_INSTALLATION_DATE: &str = "installation-date";
 pub const DB_KEY_UPDATE_DATE: &str = "update-date";
 pub const DB_KEY_APP_VERSION: &str = "app-version";
@@ -85,17 +91,25 @@ pub struct NimbusClient {
     database_cache: DatabaseCache,
     db_path: PathBuf,
     event_store: Arc<Mutex<EventStore>>,
+    attribute_providers: Mutex<HashMap<String, Arc<dyn TargetingAttributeProvider>>>,
+    exposures: Mutex<ExposureBatch>,
 }
 
 impl NimbusClient {
     // This constructor *must* not do any kind of I/O since it might be called on the main
     // thread in the gecko Javascript stack, hence the use of OnceCell for the db.
+    //
+    // Apps which switch between profiles pass a `profile_id`, so that each
+    // profile has its own database under `db_path`, with its own enrollments
+    // and Nimbus ID.
     pub fn new<P: Into<PathBuf>>(
         app_context: AppContext,
         db_path: P,
         config: Option<RemoteSettingsConfig>,
         available_randomization_units: AvailableRandomizationUnits,
+        profile_id: Option<String>,
     ) -> Result<Self> {
+        let db_path = profile_db_path(db_path.into(), profile_id.as_deref())?;
         let settings_client = Mutex::new(create_client(config)?);
 
         let mutable_state = Mutex::new(InternalMutableState {
@@ -108,9 +122,11 @@ impl NimbusClient {
             mutable_state,
             app_context,
             database_cache: Default::default(),
-            db_path: db_path.into(),
+            db_path,
             db: OnceCell::default(),
             event_store: Arc::default(),
+            attribute_providers: Default::default(),
+            exposures: Default::default(),
         })
     }
 
@@ -124,13 +140,34 @@ impl NimbusClient {
         state.targeting_attributes.clone()
     }
 
+    /// Registers a provider of extra targeting attributes, which are then
+    /// available to targeting expressions as `{name}.{attribute}`. Providers
+    /// are asked for their attributes whenever the enrollments are
+    /// re-evaluated (ie, in `initialize()` and `apply_pending_experiments()`),
+    /// so they should be registered before calling `initialize()`.
+    ///
+    /// `name` shouldn't be the same as one of the built-in attributes.
+    /// Registering another provider with the same name replaces it.
+    ///
+    /// Providers are called without holding any of our locks, so they can
+    /// call back into the client.
+    pub fn register_targeting_attribute_provider(
+        &self,
+        name: String,
+        provider: Box<dyn TargetingAttributeProvider>,
+    ) {
+        let mut providers = self.attribute_providers.lock().unwrap();
+        providers.insert(name, Arc::from(provider));
+    }
+
     pub fn initialize(&self) -> Result<()> {
+        let provided_attributes = self.get_provided_attributes();
         let db = self.db()?;
         // We're not actually going to write, we just want to exclude concurrent writers.
         let mut writer = db.write()?;
 
         let mut state = self.mutable_state.lock().unwrap();
-        self.begin_initialize(db, &mut writer, &mut state)?;
+        self.begin_initialize(db, &mut writer, &mut state, provided_attributes)?;
         self.end_initialize(db, writer, &mut state)?;
 
         Ok(())
@@ -143,8 +180,10 @@ impl NimbusClient {
         db: &Database,
         writer: &mut Writer,
         state: &mut MutexGuard<InternalMutableState>,
+        provided_attributes: Vec<(String, Value)>,
     ) -> Result<()> {
         self.update_ta_install_dates(db, writer, state)?;
+        self.update_ta_provided_attributes(state, provided_attributes);
         self.event_store.lock().unwrap().read_from_db(db)?;
         Ok(())
     }
@@ -254,10 +293,25 @@ impl NimbusClient {
     pub fn fetch_experiments(&self) -> Result<()> {
         log::info!("fetching experiments");
         let settings_client = self.settings_client.lock().unwrap();
-        let new_experiments = settings_client.fetch_experiments()?;
         let db = self.db()?;
+        let store = db.get_store(StoreId::Meta);
+        let cached_response: Option<CachedResponse> =
+            store.get(&db.read()?, DB_KEY_REMOTE_SETTINGS_RESPONSE)?;
+        let had_cached_response = cached_response.is_some();
+        settings_client.set_cached_response(cached_response);
+        let new_experiments = settings_client.fetch_experiments()?;
         let mut writer = db.write()?;
         write_pending_experiments(db, &mut writer, new_experiments)?;
+        // Keep the response with the experiments it produced, so we never ask
+        // the server for changes since a response we didn't store.
+        match settings_client.cached_response() {
+            Some(response) => store.put(&mut writer, DB_KEY_REMOTE_SETTINGS_RESPONSE, &response)?,
+            // Deleting a key which doesn't exist is an error.
+            None if had_cached_response => {
+                store.delete(&mut writer, DB_KEY_REMOTE_SETTINGS_RESPONSE)?
+            }
+            None => (),
+        }
         writer.commit()?;
         Ok(())
     }
@@ -298,6 +352,61 @@ impl NimbusClient {
         Ok(())
     }
 
+    /**
+     * Asks the registered providers for their targeting attributes. Providers are implemented
+     * by the application, which may call back into us, so this must be called before taking
+     * the database or state locks. Attributes which aren't a JSON object are dropped.
+     */
+    fn get_provided_attributes(&self) -> Vec<(String, Value)> {
+        let providers: Vec<_> = self
+            .attribute_providers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, provider)| (name.clone(), Arc::clone(provider)))
+            .collect();
+        providers
+            .into_iter()
+            .filter_map(|(name, provider)| {
+                match serde_json::from_str::<Value>(&provider.get_attributes()) {
+                    Ok(attributes @ Value::Object(_)) => Some((name, attributes)),
+                    Ok(_) => {
+                        log::warn!(
+                            "[Nimbus] Targeting attributes from '{}' are not an object",
+                            name
+                        );
+                        None
+                    }
+                    Err(e) => {
+                        log::warn!(
+                            "[Nimbus] Invalid targeting attributes from '{}': {}",
+                            name,
+                            e
+                        );
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /**
+     * Updates the targeting attributes with those from `get_provided_attributes()`. A provider
+     * whose attributes were dropped keeps its previous attributes, if any.
+     */
+    fn update_ta_provided_attributes(
+        &self,
+        state: &mut MutexGuard<InternalMutableState>,
+        provided_attributes: Vec<(String, Value)>,
+    ) {
+        for (name, attributes) in provided_attributes {
+            state
+                .targeting_attributes
+                .provided_attributes
+                .insert(name, attributes);
+        }
+    }
+
     /**
      * Calculates the active_experiments based on current enrollments for the targeting attributes.
      */
@@ -342,6 +451,7 @@ impl NimbusClient {
 
     pub fn apply_pending_experiments(&self) -> Result<Vec<EnrollmentChangeEvent>> {
         log::info!("updating experiment list");
+        let provided_attributes = self.get_provided_attributes();
         let db = self.db()?;
         let mut writer = db.write()?;
 
@@ -349,7 +459,7 @@ impl NimbusClient {
         // or by set_experiments_locally.
         let pending_updates = read_and_remove_pending_experiments(db, &mut writer)?;
         let mut state = self.mutable_state.lock().unwrap();
-        self.begin_initialize(db, &mut writer, &mut state)?;
+        self.begin_initialize(db, &mut writer, &mut state, provided_attributes)?;
 
         let res = match pending_updates {
             Some(new_experiments) => {
@@ -502,6 +612,9 @@ impl NimbusClient {
         // (No need to commit `writer` if the above check was false, since we didn't change anything)
         state.available_randomization_units = new_randomization_units;
 
+        // Exposures which haven't been flushed yet belong to the old identity.
+        self.exposures.lock().unwrap().take();
+
         Ok(events)
     }
 
@@ -584,11 +697,61 @@ impl NimbusClient {
         Ok(())
     }
 
+    /// Adds an exposure to `feature_id` to the batch returned by
+    /// `flush_exposure_events()`, if the user is enrolled in an experiment
+    /// which uses it. Repeated exposures to the same feature, in the same
+    /// experiment and branch, only add to the count of a single event.
+    ///
+    /// Note: the contract for this function is that it never blocks on IO.
+    pub fn record_feature_exposure(&self, feature_id: String) -> Result<()> {
+        let experiments = self.database_cache.get_active_experiments()?;
+        // We can only be enrolled in a single experiment per feature.
+        if let Some(experiment) = experiments
+            .iter()
+            .find(|e| e.feature_ids.contains(&feature_id))
+        {
+            self.exposures.lock().unwrap().record(
+                &experiment.slug,
+                &experiment.branch_slug,
+                &feature_id,
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the exposures recorded since the last flush, one event for
+    /// each experiment, branch and feature, and clears them.
+    pub fn flush_exposure_events(&self) -> Vec<ExposureEvent> {
+        self.exposures.lock().unwrap().take()
+    }
+
     pub fn event_store(&self) -> Arc<Mutex<EventStore>> {
         self.event_store.clone()
     }
 }
 
+/// Returns where the database for `profile_id` lives under `db_path`. Profile
+/// IDs become directory names, so they can only use letters, digits, `-` and
+/// `_`.
+fn profile_db_path(db_path: PathBuf, profile_id: Option<&str>) -> Result<PathBuf> {
+    Ok(match profile_id {
+        None => db_path,
+        Some(profile_id) => {
+            if profile_id.is_empty()
+                || !profile_id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(NimbusError::InvalidPath(format!(
+                    "Invalid profile ID: {:?}",
+                    profile_id
+                )));
+            }
+            db_path.join("profiles").join(profile_id)
+        }
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct EnrolledExperiment {
     pub feature_ids: Vec<String>,
@@ -632,6 +795,11 @@ pub struct Experiment {
     pub reference_branch: Option<String>,
     #[serde(default)]
     pub is_rollout: bool,
+    // Clients enrolled in a holdback experiment get only the values of their
+    // branch for its features, without those of any rollouts for them, so
+    // that the experiment can measure what the rollouts changed.
+    #[serde(default)]
+    pub is_holdback: bool,
     // N.B. records in RemoteSettings will have `id` and `filter_expression` fields,
     // but we ignore them because they're for internal use by RemoteSettings.
 }
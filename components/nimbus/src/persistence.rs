@@ -124,6 +124,9 @@ pub enum StoreId {
     ///   * "update-date": a UTC DateTime string, defining the date the consuming app was
     ///                     last updated
     ///   * "app-version": String, the version of the app last persisted
+    ///   * "remote-settings-response": the `ETag` and body of the last
+    ///                     response from Remote Settings, so that we only
+    ///                     download the experiments again when they change.
     Meta,
     /// Store containing pending updates to experiment data.
     ///
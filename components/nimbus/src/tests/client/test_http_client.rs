@@ -4,10 +4,10 @@
 
 use crate::client::{http_client::*, SettingsClient};
 use crate::{
-    error::NimbusError, Branch, BucketConfig, Experiment, FeatureConfig, RandomizationUnit,
-    RemoteSettingsConfig, SCHEMA_VERSION,
+    error::NimbusError, AppContext, Branch, BucketConfig, Experiment, FeatureConfig, NimbusClient,
+    RandomizationUnit, RemoteSettingsConfig, SCHEMA_VERSION,
 };
-use mockito::mock;
+use mockito::{mock, Matcher};
 use std::cell::Cell;
 use std::time::{Duration, Instant};
 
@@ -158,6 +158,77 @@ fn test_backoff_recovery() {
     m.expect(1).assert();
 }
 
+#[test]
+fn test_etag() {
+    viaduct_reqwest::use_reqwest_backend();
+    let path = "/v1/buckets/main/collections/messaging-experiments/records";
+    let m = mock("GET", path)
+        .match_header("if-none-match", Matcher::Missing)
+        .with_body(response_body())
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_header("ETag", "\"1234\"")
+        .create();
+    let m_not_modified = mock("GET", path)
+        .match_header("if-none-match", "\"1234\"")
+        .with_status(304)
+        .create();
+    let config = RemoteSettingsConfig {
+        server_url: mockito::server_url(),
+        collection_name: "messaging-experiments".to_string(),
+    };
+    let http_client = Client::new(config).unwrap();
+    let first = http_client.fetch_experiments().unwrap();
+    // The second request gets a 304, so uses the cached records.
+    let second = http_client.fetch_experiments().unwrap();
+    m.expect(1).assert();
+    m_not_modified.expect(1).assert();
+    assert_eq!(first.len(), 1);
+    assert_eq!(first, second);
+}
+
+#[test]
+fn test_etag_persisted() -> crate::error::Result<()> {
+    viaduct_reqwest::use_reqwest_backend();
+    let path = "/v1/buckets/main/collections/test-etag-persisted/records";
+    let m = mock("GET", path)
+        .match_header("if-none-match", Matcher::Missing)
+        .with_body(response_body())
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_header("ETag", "\"5678\"")
+        .create();
+    let m_not_modified = mock("GET", path)
+        .match_header("if-none-match", "\"5678\"")
+        .with_status(304)
+        .create();
+    let config = RemoteSettingsConfig {
+        server_url: mockito::server_url(),
+        collection_name: "test-etag-persisted".to_string(),
+    };
+    let tmp_dir = tempfile::tempdir()?;
+    let new_client = || {
+        NimbusClient::new(
+            AppContext::default(),
+            tmp_dir.path(),
+            Some(config.clone()),
+            Default::default(),
+            None,
+        )
+    };
+    new_client()?.update_experiments()?;
+    m.expect(1).assert();
+
+    // After a restart, we still only download the experiments if they've
+    // changed, and use the stored ones if they haven't.
+    let client = new_client()?;
+    client.update_experiments()?;
+    m.expect(1).assert();
+    m_not_modified.expect(1).assert();
+    assert_eq!(client.get_all_experiments()?.len(), 1);
+    Ok(())
+}
+
 fn response_body() -> String {
     format!(
         r#"
@@ -901,6 +901,138 @@ fn test_evolver_experiment_update_enrolled_then_bucketing_changed() -> Result<()
     Ok(())
 }
 
+#[test]
+fn test_evolver_rollout_update_enrolled_then_bucketing_changed() -> Result<()> {
+    let mut exp = get_test_experiments()[0].clone();
+    exp.is_rollout = true;
+    exp.bucket_config.count = 0; // Ramp the rollout down to nothing.
+    let (nimbus_id, app_ctx, aru) = local_ctx();
+    let targeting_attributes = app_ctx.into();
+    let evolver = enrollment_evolver(&nimbus_id, &targeting_attributes, &aru);
+    let mut events = vec![];
+    let event_store = EventStore::new();
+    let enrollment_id = Uuid::new_v4();
+    let existing_enrollment = ExperimentEnrollment {
+        slug: exp.slug.clone(),
+        status: EnrollmentStatus::Enrolled {
+            enrollment_id,
+            branch: "control".to_owned(),
+            reason: EnrolledReason::Qualified,
+        },
+    };
+    let enrollment = evolver
+        .evolve_enrollment(
+            true,
+            Some(&exp),
+            Some(&exp),
+            Some(&existing_enrollment),
+            &event_store,
+            &mut events,
+        )?
+        .unwrap();
+    // We don't use a new `Disqualified` reason, which older versions
+    // couldn't read.
+    assert_eq!(
+        enrollment.status,
+        EnrollmentStatus::NotEnrolled {
+            reason: NotEnrolledReason::NotSelected
+        }
+    );
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].enrollment_id, enrollment_id.to_string());
+    assert_eq!(events[0].branch_slug, "control");
+    assert_eq!(events[0].reason, Some("bucketing".to_owned()));
+    assert_eq!(
+        events[0].change,
+        EnrollmentChangeEventType::Disqualification
+    );
+
+    // Ramping the rollout back up enrolls again.
+    exp.bucket_config = BucketConfig::always();
+    let mut events = vec![];
+    let enrollment = evolver
+        .evolve_enrollment(
+            true,
+            Some(&exp),
+            Some(&exp),
+            Some(&enrollment),
+            &event_store,
+            &mut events,
+        )?
+        .unwrap();
+    assert!(matches!(
+        enrollment.status,
+        EnrollmentStatus::Enrolled {
+            reason: EnrolledReason::Qualified,
+            ..
+        }
+    ));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].change, EnrollmentChangeEventType::Enrollment);
+    Ok(())
+}
+
+#[test]
+fn test_evolver_rollout_disqualified_or_paused() -> Result<()> {
+    let mut exp = get_test_experiments()[0].clone();
+    exp.is_rollout = true;
+    exp.bucket_config = BucketConfig::always();
+    let (nimbus_id, app_ctx, aru) = local_ctx();
+    let targeting_attributes = app_ctx.into();
+    let evolver = enrollment_evolver(&nimbus_id, &targeting_attributes, &aru);
+    let event_store = EventStore::new();
+    let slug = exp.slug.clone();
+
+    // Rollouts treat disqualifications like experiments do, so users who
+    // opted out, or stopped being targeted, aren't enrolled again.
+    for reason in [DisqualifiedReason::OptOut, DisqualifiedReason::NotTargeted] {
+        let existing_enrollment = ExperimentEnrollment {
+            slug: slug.clone(),
+            status: EnrollmentStatus::Disqualified {
+                enrollment_id: Uuid::new_v4(),
+                branch: "control".to_owned(),
+                reason,
+            },
+        };
+        let mut events = vec![];
+        let enrollment = evolver
+            .evolve_enrollment(
+                true,
+                Some(&exp),
+                Some(&exp),
+                Some(&existing_enrollment),
+                &event_store,
+                &mut events,
+            )?
+            .unwrap();
+        assert_eq!(enrollment, existing_enrollment);
+        assert!(events.is_empty());
+    }
+
+    // Nobody is enrolled again while enrollment is paused.
+    exp.is_enrollment_paused = true;
+    let existing_enrollment = ExperimentEnrollment {
+        slug,
+        status: EnrollmentStatus::NotEnrolled {
+            reason: NotEnrolledReason::NotSelected,
+        },
+    };
+    let mut events = vec![];
+    let enrollment = evolver
+        .evolve_enrollment(
+            true,
+            Some(&exp),
+            Some(&exp),
+            Some(&existing_enrollment),
+            &event_store,
+            &mut events,
+        )?
+        .unwrap();
+    assert_eq!(enrollment, existing_enrollment);
+    assert!(events.is_empty());
+    Ok(())
+}
+
 #[test]
 fn test_evolver_experiment_update_enrolled_then_branches_changed() -> Result<()> {
     let mut exp = get_test_experiments()[0].clone();
@@ -2439,6 +2571,35 @@ fn test_rollouts_end_to_end() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_holdback_does_not_get_rollout_values() -> Result<()> {
+    let (rollout, mut experiment) = get_rollout_and_experiment();
+    experiment.is_holdback = true;
+    let recipes = &[rollout, experiment];
+
+    let (nimbus_id, app_ctx, aru) = local_ctx();
+    let targeting_attributes = app_ctx.into();
+    let evolver = enrollment_evolver(&nimbus_id, &targeting_attributes, &aru);
+    let event_store = EventStore::new();
+
+    let (enrollments, _events) =
+        evolver.evolve_enrollments(true, &[], recipes, &[], &event_store)?;
+
+    let features = map_features_by_feature_id(&enrollments, recipes);
+    assert_eq!(features.len(), 3);
+    // Bob is in the holdback, so the rollout's values aren't merged in.
+    assert!(!features["bob"].is_rollout());
+    assert_eq!(
+        Value::Object(features["bob"].feature.value.clone()),
+        json!({
+            "specified": "Experiment in part".to_string(),
+        })
+    );
+    // Charlie isn't, so still gets the rollout.
+    assert!(features["charlie"].is_rollout());
+    Ok(())
+}
+
 #[test]
 fn test_enrollment_explicit_opt_in() -> Result<()> {
     let exp = get_test_experiments()[0].clone();
@@ -10,12 +10,17 @@ use crate::{
     enrollment::{EnrolledReason, EnrollmentStatus, ExperimentEnrollment},
     error::Result,
     persistence::Database,
-    AppContext, AvailableRandomizationUnits, Experiment, NimbusClient, Path, StoreId,
-    TargetingAttributes, DB_KEY_APP_VERSION, DB_KEY_UPDATE_DATE,
+    AppContext, AvailableRandomizationUnits, Branch, Experiment, ExposureEvent, FeatureConfig,
+    NimbusClient, Path, StoreId, TargetingAttributeProvider, TargetingAttributes,
+    DB_KEY_APP_VERSION, DB_KEY_UPDATE_DATE,
 };
 use chrono::{DateTime, Duration, Utc};
 use serde_json::json;
-use std::{collections::VecDeque, io::Write};
+use std::{
+    collections::VecDeque,
+    io::Write,
+    sync::{Arc, Weak},
+};
 
 #[test]
 fn test_telemetry_reset() -> Result<()> {
@@ -32,6 +37,7 @@ fn test_telemetry_reset() -> Result<()> {
             client_id: Some(mock_client_id.clone()),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
 
     let get_client_id = || {
@@ -88,6 +94,147 @@ fn test_telemetry_reset() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_feature_exposures_are_batched() -> Result<()> {
+    let tmp_dir = tempfile::tempdir()?;
+    let client = NimbusClient::new(
+        AppContext::default(),
+        tmp_dir.path(),
+        None,
+        AvailableRandomizationUnits::default(),
+        None,
+    )?;
+
+    // Mock being enrolled in a single experiment, using a single feature.
+    let db = client.db()?;
+    let mut writer = db.write()?;
+    db.get_store(StoreId::Experiments).put(
+        &mut writer,
+        "exp-1",
+        &Experiment {
+            slug: "exp-1".to_string(),
+            branches: vec![Branch {
+                slug: "branch-1".to_string(),
+                ratio: 1,
+                feature: Some(FeatureConfig {
+                    feature_id: "feature-1".to_string(),
+                    ..FeatureConfig::default()
+                }),
+                features: None,
+            }],
+            ..Experiment::default()
+        },
+    )?;
+    db.get_store(StoreId::Enrollments).put(
+        &mut writer,
+        "exp-1",
+        &ExperimentEnrollment {
+            slug: "exp-1".to_string(),
+            status: EnrollmentStatus::new_enrolled(EnrolledReason::Qualified, "branch-1"),
+        },
+    )?;
+    writer.commit()?;
+
+    // Exposures can't be recorded until the database cache is ready.
+    assert!(client
+        .record_feature_exposure("feature-1".to_string())
+        .is_err());
+
+    client.initialize()?;
+
+    for _ in 0..3 {
+        client.record_feature_exposure("feature-1".to_string())?;
+    }
+    // Features which aren't part of an active experiment are ignored.
+    client.record_feature_exposure("feature-2".to_string())?;
+
+    assert_eq!(
+        client.flush_exposure_events(),
+        vec![ExposureEvent {
+            experiment_slug: "exp-1".to_string(),
+            branch_slug: "branch-1".to_string(),
+            feature_id: "feature-1".to_string(),
+            count: 3,
+        }]
+    );
+    assert!(client.flush_exposure_events().is_empty());
+
+    // Resetting telemetry throws away exposures which weren't flushed.
+    client.record_feature_exposure("feature-1".to_string())?;
+    client.reset_telemetry_identifiers(AvailableRandomizationUnits::default())?;
+    assert!(client.flush_exposure_events().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_profiles_are_isolated() -> Result<()> {
+    let tmp_dir = tempfile::tempdir()?;
+    let new_client = |profile_id: Option<&str>| {
+        NimbusClient::new(
+            AppContext::default(),
+            tmp_dir.path(),
+            None,
+            AvailableRandomizationUnits::default(),
+            profile_id.map(String::from),
+        )
+    };
+    let work = new_client(Some("work"))?;
+    let personal = new_client(Some("personal"))?;
+    let default = new_client(None)?;
+
+    // Enroll the work profile in an experiment.
+    let db = work.db()?;
+    let mut writer = db.write()?;
+    db.get_store(StoreId::Experiments).put(
+        &mut writer,
+        "exp-1",
+        &Experiment {
+            slug: "exp-1".to_string(),
+            branches: vec![Branch {
+                slug: "branch-1".to_string(),
+                ratio: 1,
+                feature: None,
+                features: None,
+            }],
+            ..Experiment::default()
+        },
+    )?;
+    db.get_store(StoreId::Enrollments).put(
+        &mut writer,
+        "exp-1",
+        &ExperimentEnrollment {
+            slug: "exp-1".to_string(),
+            status: EnrollmentStatus::new_enrolled(EnrolledReason::Qualified, "branch-1"),
+        },
+    )?;
+    writer.commit()?;
+
+    for client in [&work, &personal, &default] {
+        client.initialize()?;
+    }
+    assert_eq!(work.get_active_experiments()?.len(), 1);
+    assert!(personal.get_active_experiments()?.is_empty());
+    assert!(default.get_active_experiments()?.is_empty());
+
+    // Each profile is bucketed with its own Nimbus ID.
+    assert_ne!(work.nimbus_id()?, personal.nimbus_id()?);
+    assert_ne!(work.nimbus_id()?, default.nimbus_id()?);
+
+    // Opening the profile again finds its enrollments.
+    drop(work);
+    let work = new_client(Some("work"))?;
+    work.initialize()?;
+    assert_eq!(work.get_active_experiments()?.len(), 1);
+
+    // Profile IDs can't be used to reach outside the database directory.
+    for profile_id in ["", "..", "a/b", "a\\b"] {
+        assert!(new_client(Some(profile_id)).is_err());
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_installation_date() -> Result<()> {
     let mock_client_id = "client-1".to_string();
@@ -109,6 +256,7 @@ fn test_installation_date() -> Result<()> {
             client_id: Some(mock_client_id.clone()),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
 
     client.initialize()?;
@@ -145,6 +293,7 @@ fn test_installation_date() -> Result<()> {
             client_id: Some(mock_client_id.clone()),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     delete_test_creation_date(tmp_dir.path()).ok();
     // When we check the filesystem, we will fail. We haven't `set_test_creation_date`
@@ -168,6 +317,7 @@ fn test_installation_date() -> Result<()> {
             client_id: Some(mock_client_id.clone()),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     client.initialize()?;
     // We now store a date for days ago in our file system
@@ -199,6 +349,7 @@ fn test_installation_date() -> Result<()> {
             client_id: Some(mock_client_id),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     client.initialize()?;
     // now that the store is clear, we will fallback again to the
@@ -227,6 +378,7 @@ fn test_days_since_calculation_happens_at_startup() -> Result<()> {
         tmp_dir.path(),
         None,
         Default::default(),
+        None,
     )?;
 
     // 0. We haven't initialized anything yet, so dates won't be available.
@@ -247,7 +399,7 @@ fn test_days_since_calculation_happens_at_startup() -> Result<()> {
     // 2. This is the new case: exactly one of initialize() or apply_pending_experiments()
     // is called during start up.
     // This case ensures that dates are available after apply_pending_experiments().
-    let client = NimbusClient::new(app_context, tmp_dir.path(), None, Default::default())?;
+    let client = NimbusClient::new(app_context, tmp_dir.path(), None, Default::default(), None)?;
     client.apply_pending_experiments()?;
     let targeting_attributes = client.get_targeting_attributes();
     assert!(matches!(targeting_attributes.days_since_install, Some(3)));
@@ -268,6 +420,7 @@ fn test_days_since_update_changes_with_context() -> Result<()> {
             client_id: Some(mock_client_id.clone()),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     client.initialize()?;
 
@@ -289,6 +442,7 @@ fn test_days_since_update_changes_with_context() -> Result<()> {
             client_id: Some(mock_client_id.clone()),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     client.initialize()?;
     client.apply_pending_experiments()?;
@@ -317,6 +471,7 @@ fn test_days_since_update_changes_with_context() -> Result<()> {
             client_id: Some(mock_client_id.clone()),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     client.initialize()?;
     client.apply_pending_experiments()?;
@@ -351,6 +506,7 @@ fn test_days_since_update_changes_with_context() -> Result<()> {
             client_id: Some(mock_client_id),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     client.initialize()?;
     client.apply_pending_experiments()?;
@@ -393,6 +549,7 @@ fn test_days_since_install() -> Result<()> {
             client_id: Some(mock_client_id),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     let targeting_attributes = TargetingAttributes {
         app_context,
@@ -452,6 +609,91 @@ fn test_days_since_install() -> Result<()> {
     Ok(())
 }
 
+struct TestAttributeProvider(&'static str);
+
+impl TargetingAttributeProvider for TestAttributeProvider {
+    fn get_attributes(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+#[test]
+fn test_targeting_attribute_providers() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let client = NimbusClient::new(
+        AppContext::default(),
+        temp_dir.path(),
+        None,
+        AvailableRandomizationUnits::default(),
+        None,
+    )?;
+    client.register_targeting_attribute_provider(
+        "places".to_string(),
+        Box::new(TestAttributeProvider(
+            r#"{"bookmark_count": 100, "history_days": 25}"#,
+        )),
+    );
+    // Attributes which aren't a JSON object are ignored.
+    client.register_targeting_attribute_provider(
+        "broken".to_string(),
+        Box::new(TestAttributeProvider("[1, 2, 3]")),
+    );
+    client.initialize()?;
+
+    let helper = client.create_targeting_helper(None)?;
+    assert!(helper.eval_jexl("places.bookmark_count >= 100".to_string())?);
+    assert!(!helper.eval_jexl("places.history_days > 28".to_string())?);
+    assert!(!client
+        .get_targeting_attributes()
+        .provided_attributes
+        .contains_key("broken"));
+
+    // Providers are asked again when the enrollments are re-evaluated.
+    client.register_targeting_attribute_provider(
+        "places".to_string(),
+        Box::new(TestAttributeProvider(r#"{"bookmark_count": 5}"#)),
+    );
+    client.apply_pending_experiments()?;
+    let helper = client.create_targeting_helper(None)?;
+    assert!(helper.eval_jexl("places.bookmark_count < 100".to_string())?);
+    Ok(())
+}
+
+// A provider which calls back into the client, like an app computing its
+// attributes from what Nimbus already knows.
+struct ReentrantAttributeProvider(Weak<NimbusClient>);
+
+impl TargetingAttributeProvider for ReentrantAttributeProvider {
+    fn get_attributes(&self) -> String {
+        let client = self.0.upgrade().unwrap();
+        let attributes = client.get_targeting_attributes();
+        json!({ "has_places": attributes.provided_attributes.contains_key("places") }).to_string()
+    }
+}
+
+#[test]
+fn test_targeting_attribute_provider_reentrancy() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let client = Arc::new(NimbusClient::new(
+        AppContext::default(),
+        temp_dir.path(),
+        None,
+        AvailableRandomizationUnits::default(),
+        None,
+    )?);
+    client.register_targeting_attribute_provider(
+        "reentrant".to_string(),
+        Box::new(ReentrantAttributeProvider(Arc::downgrade(&client))),
+    );
+    // Providers are called without holding our locks, so this doesn't deadlock.
+    client.initialize()?;
+    client.apply_pending_experiments()?;
+
+    let helper = client.create_targeting_helper(None)?;
+    assert!(!helper.eval_jexl("reentrant.has_places".to_string())?);
+    Ok(())
+}
+
 #[test]
 fn test_days_since_install_failed_targeting() -> Result<()> {
     let mock_client_id = "client-1".to_string();
@@ -471,6 +713,7 @@ fn test_days_since_install_failed_targeting() -> Result<()> {
             client_id: Some(mock_client_id),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     let targeting_attributes = TargetingAttributes {
         app_context,
@@ -548,6 +791,7 @@ fn test_days_since_update() -> Result<()> {
             client_id: Some(mock_client_id),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     let targeting_attributes = TargetingAttributes {
         app_context,
@@ -626,6 +870,7 @@ fn test_days_since_update_failed_targeting() -> Result<()> {
             client_id: Some(mock_client_id),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     let targeting_attributes = TargetingAttributes {
         app_context,
@@ -716,6 +961,7 @@ fn event_store_exists_for_apply_pending_experiments() -> Result<()> {
             client_id: Some(mock_client_id),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     let targeting_attributes = TargetingAttributes {
         app_context,
@@ -838,6 +1084,7 @@ fn event_store_on_targeting_attributes_is_updated_after_an_event_is_recorded() -
             client_id: Some(mock_client_id),
             ..AvailableRandomizationUnits::default()
         },
+        None,
     )?;
     let targeting_attributes = TargetingAttributes {
         app_context,
@@ -38,7 +38,7 @@ fn new_test_client_internal(
         locale: Some("en-GB".to_string()),
         ..Default::default()
     };
-    NimbusClient::new(ctx, tmp_dir.path(), Some(config), aru)
+    NimbusClient::new(ctx, tmp_dir.path(), Some(config), aru, None)
 }
 
 #[allow(dead_code)] //  work around https://github.com/rust-lang/rust/issues/46379
@@ -4,7 +4,14 @@
 
 package mozilla.appservices.places
 
+import mozilla.appservices.places.uniffi.BookmarkGuidMapping
+import mozilla.appservices.places.uniffi.BookmarkImportSource
 import mozilla.appservices.places.uniffi.BookmarkItem
+import mozilla.appservices.places.uniffi.BookmarkTooLargeToSync
+import mozilla.appservices.places.uniffi.BookmarksStatistics
+import mozilla.appservices.places.uniffi.DuplicateBookmarks
+import mozilla.appservices.places.uniffi.DuplicateBookmarksStrategy
+import mozilla.appservices.places.uniffi.InsertableBookmarkTree
 
 /**
  * Enumeration of the ids of the roots of the bookmarks tree.
@@ -64,6 +71,46 @@ interface ReadableBookmarksConnection : InterruptibleConnection {
      */
     fun getBookmark(guid: Guid): BookmarkItem?
 
+    /**
+     * Returns a page of the children of a folder, in order. Unlike `getBookmarksTree`, this
+     * only loads the requested children, so it can be used to lazily render huge folders.
+     * Any folders in the page will have neither their `children` nor their `childGUIDs`
+     * populated.
+     *
+     * @param folderGUID the guid of the folder.
+     * @param offset the position of the first child to return.
+     * @param limit the maximum number of children to return.
+     * @return The children, or null if the provided guid didn't refer to a known bookmark item.
+     *
+     * @throws InvalidBookmarkOperation if `folderGUID` doesn't refer to a folder.
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun getBookmarksChildrenPage(folderGUID: Guid, offset: Int, limit: Int): List<BookmarkItem>?
+
+    /**
+     * Counts the bookmarks in a folder and all its subfolders, without loading the tree. Folders
+     * and separators aren't counted.
+     *
+     * @param folderGUID the guid of the folder.
+     * @return The number of bookmarks, or null if the provided guid didn't refer to a known
+     * bookmark item.
+     *
+     * @throws InvalidBookmarkOperation if `folderGUID` doesn't refer to a folder.
+     */
+    fun countBookmarksInFolder(folderGUID: Guid): Int?
+
+    /**
+     * Counts all the bookmarks in the tree. Folders and separators aren't counted.
+     */
+    fun countAllBookmarks(): Int
+
+    /**
+     * Returns the number of items, folders and separators in the tree, and how deeply the
+     * deepest item is nested, without loading the tree.
+     */
+    fun getBookmarksStatistics(): BookmarksStatistics
+
     /**
      * Returns the list of bookmarks with the provided URL.
      *
@@ -119,6 +166,52 @@ interface ReadableBookmarksConnection : InterruptibleConnection {
      * has its `interrupt()` method called on another thread.
      */
     fun getRecentBookmarks(limit: Int): List<BookmarkItem>
+
+    /**
+     * Returns every item an importer created from [source], so that users can review
+     * what an import added. Folders are returned without their children.
+     *
+     * @param source The importer to return the items of.
+     * @return A list of the imported bookmarks, folders and separators.
+     */
+    fun getImportedBookmarks(source: BookmarkImportSource): List<BookmarkItem>
+
+    /**
+     * Returns the URLs which are bookmarked more than once, so that users can
+     * review them before merging them with [WritableBookmarksConnection.mergeDuplicateBookmarks].
+     *
+     * @return The duplicated URLs, with their bookmarks, oldest first.
+     */
+    fun findDuplicateBookmarks(): List<DuplicateBookmarks>
+
+    /**
+     * Returns the bookmarks which were left out of the last sync because their records are
+     * larger than the server accepts, so that users can find and fix them. This usually
+     * means a folder with a huge number of children, or a bookmark with a huge URL or lots
+     * of tags. The bookmarks will be uploaded on a later sync once they're small enough.
+     *
+     * @return The guid of each bookmark, and our estimate of its record's size in bytes.
+     */
+    fun getBookmarksTooLargeToSync(): List<BookmarkTooLargeToSync>
+
+    /**
+     * Returns the tags for a URL, most recently modified first. Tags belong to URLs,
+     * rather than bookmarks, so every bookmark for [url] has the same tags.
+     *
+     * @param url The URL to return the tags of.
+     * @return A list of tags, which is empty if the URL isn't tagged.
+     */
+    fun getTagsForUrl(url: Url): List<String>
+
+    /**
+     * Returns the URLs tagged with [tag].
+     *
+     * @param tag The tag to look for.
+     * @return A list of URLs, ordered by frecency.
+     *
+     * @throws InvalidBookmarkOperation If [tag] is empty or too long.
+     */
+    fun getUrlsWithTag(tag: String): List<Url>
 }
 
 /**
@@ -132,6 +225,10 @@ interface WritableBookmarksConnection : ReadableBookmarksConnection {
      * If the requested bookmark is a folder, all children of
      * bookmark are deleted as well, recursively.
      *
+     * Deleted bookmarks go into the trash, and can be restored with [restoreBookmarkNode]
+     * until they're purged. [WritableHistoryConnection.runMaintenance] purges bookmarks which
+     * have been in the trash for 30 days.
+     *
      * @param guid The GUID of the bookmark to delete
      * @return Whether or not the bookmark existed.
      *
@@ -139,12 +236,69 @@ interface WritableBookmarksConnection : ReadableBookmarksConnection {
      */
     fun deleteBookmarkNode(guid: Guid): Boolean
 
+    /**
+     * Restore a deleted bookmark, and its children, from the trash. It goes back where it
+     * was, or into "Other Bookmarks" if its folder has been deleted too.
+     *
+     * @param guid The GUID of the deleted bookmark.
+     * @return The GUID the bookmark was restored with, which is only different from [guid]
+     * if it's been taken since, or null if the bookmark isn't in the trash.
+     */
+    fun restoreBookmarkNode(guid: Guid): Guid?
+
+    /**
+     * Permanently remove the bookmarks deleted before [olderThan] from the trash.
+     *
+     * @param olderThan A timestamp, in milliseconds.
+     * @return The number of bookmarks removed.
+     */
+    fun purgeBookmarksTrash(olderThan: Long): Int
+
     /**
      * Delete all bookmarks without affecting history
      *
      */
     fun deleteAllBookmarks()
 
+    /**
+     * Delete the bookmarks and separators an importer created from [source], and then
+     * the imported folders which that leaves empty. Folders the user has since added
+     * to are kept.
+     *
+     * @param source The importer to undo.
+     * @return The number of items deleted.
+     */
+    fun deleteImportedBookmarks(source: BookmarkImportSource): Int
+
+    /**
+     * Keep one bookmark for each URL which is bookmarked more than once, and move
+     * the others to the trash, all or nothing. The folders they were in are synced.
+     *
+     * @param strategy Whether to keep the oldest or newest bookmark for each URL.
+     * @return The number of bookmarks deleted.
+     */
+    fun mergeDuplicateBookmarks(strategy: DuplicateBookmarksStrategy): Int
+
+    /**
+     * Tags a URL. The tag is synced with every bookmark for the URL. Leading and
+     * trailing whitespace is removed from the tag.
+     *
+     * @param url The URL to tag.
+     * @param tag The tag to add.
+     *
+     * @throws InvalidBookmarkOperation If [url] isn't in places, or [tag] is empty or
+     * too long.
+     */
+    fun tagUrl(url: Url, tag: String)
+
+    /**
+     * Removes a tag from a URL. Does nothing if the URL doesn't have the tag.
+     *
+     * @param url The URL to untag.
+     * @param tag The tag to remove.
+     */
+    fun untagUrl(url: Url, tag: String)
+
     /**
      * Create a bookmark folder, returning its guid.
      *
@@ -210,6 +364,22 @@ interface WritableBookmarksConnection : ReadableBookmarksConnection {
         position: UInt? = null
     ): Guid
 
+    /**
+     * Insert a tree of bookmarks, folders and separators, such as one restored from a
+     * backup, in a single transaction. Items keep the guids they have in the tree,
+     * unless they're invalid or already in use, in which case they get new ones.
+     *
+     * @param parentGUID The GUID of the folder to insert the tree into.
+     * @param tree The items to insert. Their parent guids are ignored.
+     * @return The GUID each item was inserted with, in the order they appear in the
+     * tree, with the children of a folder following the folder.
+     *
+     * @throws CannotUpdateRoot If `parentGUID` is the [BookmarkRoot.Root] (e.g. "root________")
+     * @throws UnknownBookmarkItem If `parentGUID` does not refer to to a known bookmark.
+     * @throws InvalidParent If `parentGUID` does not refer to a folder node.
+     */
+    fun insertBookmarkTree(parentGUID: Guid, tree: InsertableBookmarkTree): List<BookmarkGuidMapping>
+
     /**
      * Update a bookmark to the provided info.
      *
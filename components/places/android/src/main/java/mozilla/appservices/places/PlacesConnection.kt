@@ -4,29 +4,64 @@
 
 package mozilla.appservices.places
 
+import mozilla.appservices.places.uniffi.BookmarkGuidMapping
+import mozilla.appservices.places.uniffi.BookmarkImportSource
 import mozilla.appservices.places.uniffi.BookmarkPosition
+import mozilla.appservices.places.uniffi.BookmarksStatistics
 import mozilla.appservices.places.uniffi.ConnectionType
 import mozilla.appservices.places.uniffi.DocumentType
+import mozilla.appservices.places.uniffi.DuplicateBookmarks
+import mozilla.appservices.places.uniffi.DuplicateBookmarksStrategy
 import mozilla.appservices.places.uniffi.FrecencyThresholdOption
+import mozilla.appservices.places.uniffi.FrecencyUpdateMetrics
 import mozilla.appservices.places.uniffi.PlacesApiException
 import mozilla.appservices.places.uniffi.HistoryHighlight
 import mozilla.appservices.places.uniffi.HistoryHighlightWeights
 import mozilla.appservices.places.uniffi.HistoryMetadata
+import mozilla.appservices.places.uniffi.HistoryMetadataGroup
 import mozilla.appservices.places.uniffi.HistoryMetadataObservation
+import mozilla.appservices.places.uniffi.HistoryMigrationResult
+import mozilla.appservices.places.uniffi.FrecencySettings
+import mozilla.appservices.places.uniffi.HistoryDeletionPolicy
+import mozilla.appservices.places.uniffi.HistoryDeletionSyncMode
+import mozilla.appservices.places.uniffi.HistorySearchOptions
+import mozilla.appservices.places.uniffi.HistorySearchResult
+import mozilla.appservices.places.uniffi.HostInfo
+import mozilla.appservices.places.uniffi.HtmlBookmarksImportResult
+import mozilla.appservices.places.uniffi.ImportedVisit
+import mozilla.appservices.places.uniffi.InterruptPriority
+import mozilla.appservices.places.uniffi.MaintenanceStepMetrics
+import mozilla.appservices.places.uniffi.PageMetadataKind
+import mozilla.appservices.places.uniffi.PinnedSite
+import mozilla.appservices.places.uniffi.PlacesChangeObserver
+import mozilla.appservices.places.uniffi.ChangesSince
+import mozilla.appservices.places.uniffi.PlacesDataSummary
+import mozilla.appservices.places.uniffi.PlacesTargetingAttributes
+import mozilla.appservices.places.uniffi.RestrictionTokens
+import mozilla.appservices.places.uniffi.RunMaintenanceMetrics
 import mozilla.appservices.places.uniffi.TopFrecentSiteInfo
+import mozilla.appservices.places.uniffi.TopFrecentSiteOptions
+import mozilla.appservices.places.uniffi.TopSitePrefetchHint
+import mozilla.appservices.places.uniffi.TypedUrlInfo
+import mozilla.appservices.places.uniffi.VisitCursor
+import mozilla.appservices.places.uniffi.ZeroPrefixSuggestion
 import mozilla.appservices.places.uniffi.PlacesApi as UniffiPlacesApi
 import mozilla.appservices.places.uniffi.PlacesConnection as UniffiPlacesConnection
 import mozilla.appservices.places.uniffi.placesApiNew
+import mozilla.appservices.places.uniffi.placesApiNewWithKey
 import mozilla.appservices.places.uniffi.VisitObservation
+import mozilla.appservices.places.uniffi.HistoryVisitDetails
 import mozilla.appservices.places.uniffi.HistoryVisitInfo
 import mozilla.appservices.places.uniffi.HistoryVisitInfosWithBound
 import mozilla.appservices.places.uniffi.SearchResult
 import mozilla.appservices.places.uniffi.SqlInterruptHandle
 import mozilla.appservices.places.uniffi.BookmarkItem
+import mozilla.appservices.places.uniffi.BookmarkTooLargeToSync
 import mozilla.appservices.places.uniffi.InsertableBookmark
 import mozilla.appservices.places.uniffi.InsertableBookmarkFolder
 import mozilla.appservices.places.uniffi.InsertableBookmarkItem
 import mozilla.appservices.places.uniffi.InsertableBookmarkSeparator
+import mozilla.appservices.places.uniffi.InsertableBookmarkTree
 import mozilla.appservices.places.uniffi.BookmarkUpdateInfo
 import mozilla.appservices.sync15.SyncTelemetryPing
 import mozilla.telemetry.glean.private.CounterMetricType
@@ -45,8 +80,19 @@ typealias Guid = String
  * where necessary).
  *
  * @param path an absolute path to a file that will be used for the internal database.
+ * @param frecencySettings the parameters frecencies are calculated with, for example to tune
+ * ranking in an experiment. The defaults are used if this is null. If the database is already
+ * open, it keeps the settings it was opened with. Throws if the settings are invalid, for
+ * example if `numVisits` is negative.
+ * @param encryptionKey if not null, the database is encrypted with SQLCipher using this key. An
+ * existing unencrypted database is encrypted when it's opened. Throws
+ * [PlacesApiException.IncorrectDatabaseKey] if the key is wrong.
  */
-class PlacesApi(path: String) : PlacesManager, AutoCloseable {
+class PlacesApi(
+    path: String,
+    frecencySettings: FrecencySettings? = null,
+    encryptionKey: String? = null
+) : PlacesManager, AutoCloseable {
     // References to our "api" object and the single writer connection.
     private var api: UniffiPlacesApi
     private var writeConn: PlacesWriterConnection
@@ -55,27 +101,51 @@ class PlacesApi(path: String) : PlacesManager, AutoCloseable {
         // as per https://github.com/mozilla/uniffi-rs/pull/1063, there was some
         // pushback on allowing this to actually be a constructor, so it's a global
         // function instead :(
-        api = placesApiNew(path)
+        api = if (encryptionKey == null) {
+            placesApiNew(path, frecencySettings)
+        } else {
+            placesApiNewWithKey(path, encryptionKey, frecencySettings)
+        }
 
         val uniffiConnection = api.newConnection(ConnectionType.READ_WRITE)
         writeConn = PlacesWriterConnection(uniffiConnection, this)
     }
 
-    companion object {
-        // These numbers come from `places::db::ConnectionType`
-        private const val READ_ONLY: Int = 1
-        private const val READ_WRITE: Int = 2
-    }
-
     override fun registerWithSyncManager() {
         this.api.registerWithSyncManager()
     }
 
+    override fun registerHistoryMetadataWithSyncManager() {
+        this.api.registerHistoryMetadataWithSyncManager()
+    }
+
     override fun openReader(): PlacesReaderConnection {
         val conn = api.newConnection(ConnectionType.READ_ONLY)
         return PlacesReaderConnection(conn)
     }
 
+    override fun openReaderPool(size: Int): PlacesReaderConnection {
+        val conn = api.newReadOnlyPool(size.toUInt())
+        return PlacesReaderConnection(conn)
+    }
+
+    override fun openSnapshot(): PlacesReaderConnection {
+        val conn = api.newSnapshot()
+        return PlacesReaderConnection(conn)
+    }
+
+    /**
+     * Runs [block] with a reader from [openSnapshot], and closes the reader afterwards.
+     */
+    fun <T> withSnapshot(block: (PlacesReaderConnection) -> T): T {
+        val reader = openSnapshot()
+        try {
+            return block(reader)
+        } finally {
+            reader.close()
+        }
+    }
+
     override fun getWriter(): PlacesWriterConnection {
         return writeConn
     }
@@ -86,39 +156,80 @@ class PlacesApi(path: String) : PlacesManager, AutoCloseable {
     }
 
     override fun syncHistory(syncInfo: SyncAuthInfo): SyncTelemetryPing {
-        val pingJSONString = this.api.historySync(
+        return this.api.historySync(
                 syncInfo.kid,
                 syncInfo.fxaAccessToken,
                 syncInfo.syncKey,
                 syncInfo.tokenserverURL
-            )
-        return SyncTelemetryPing.fromJSONString(pingJSONString)
+            ).toSyncTelemetryPing()
     }
 
     override fun syncBookmarks(syncInfo: SyncAuthInfo): SyncTelemetryPing {
-        val pingJSONString = this.api.bookmarksSync(
+        return this.api.bookmarksSync(
                 syncInfo.kid,
                 syncInfo.fxaAccessToken,
                 syncInfo.syncKey,
                 syncInfo.tokenserverURL
-            )
-        return SyncTelemetryPing.fromJSONString(pingJSONString)
+            ).toSyncTelemetryPing()
+    }
+
+    override fun importBookmarksFromFennec(
+        path: String,
+        mappings: List<BookmarkImportRootMapping>?,
+        key: String?
+    ): JSONObject {
+        val metrics = this.api.placesBookmarksImportFromFennec(path, mappings, key)
+        return JSONObject(metrics)
+    }
+
+    override fun importPinnedSitesFromFennec(path: String, key: String?): List<BookmarkItem> {
+        return this.api.placesPinnedSitesImportFromFennec(path, key)
     }
 
-    override fun importBookmarksFromFennec(path: String): JSONObject {
-        val metrics = this.api.placesBookmarksImportFromFennec(path)
+    override fun importVisitsFromFennec(path: String, key: String?): JSONObject {
+        val metrics = this.api.placesHistoryImportFromFennec(path, key)
         return JSONObject(metrics)
     }
 
-    override fun importPinnedSitesFromFennec(path: String): List<BookmarkItem> {
-        return this.api.placesPinnedSitesImportFromFennec(path)
+    override fun importVisitsFromChrome(path: String): HistoryMigrationResult {
+        return this.api.placesHistoryImportFromChrome(path)
+    }
+
+    override fun importVisitsFromDesktop(path: String): HistoryMigrationResult {
+        return this.api.placesHistoryImportFromDesktop(path)
     }
 
-    override fun importVisitsFromFennec(path: String): JSONObject {
-        val metrics = this.api.placesHistoryImportFromFennec(path)
+    override fun importVisits(visits: List<ImportedVisit>): HistoryMigrationResult {
+        return this.api.placesHistoryImportVisits(visits)
+    }
+
+    override fun importBookmarksFromDesktop(
+        path: String,
+        mappings: List<BookmarkImportRootMapping>?
+    ): JSONObject {
+        val metrics = this.api.placesBookmarksImportFromDesktop(path, mappings)
         return JSONObject(metrics)
     }
 
+    override fun importBookmarksFromHtml(
+        path: String,
+        mappings: List<BookmarkImportRootMapping>?
+    ): HtmlBookmarksImportResult {
+        return this.api.placesBookmarksImportFromHtml(path, mappings)
+    }
+
+    override fun exportBookmarksToHtml(path: String) {
+        this.api.bookmarksExportToHtml(path)
+    }
+
+    override fun setChangeObserver(observer: PlacesChangeObserver) {
+        this.api.setChangeObserver(observer)
+    }
+
+    override fun clearChangeObserver() {
+        this.api.clearChangeObserver()
+    }
+
     override fun resetHistorySyncMetadata() {
         this.api.resetHistory()
     }
@@ -152,6 +263,14 @@ open class PlacesConnection internal constructor(uniffiConn: UniffiPlacesConnect
     override fun interrupt() {
         this.interruptHandle.interrupt()
     }
+
+    override fun interrupt(priority: InterruptPriority) {
+        this.interruptHandle.interruptAtOrBelow(priority)
+    }
+
+    override fun onMemoryPressure() {
+        this.conn.onMemoryPressure()
+    }
 }
 
 /**
@@ -165,16 +284,56 @@ open class PlacesReaderConnection internal constructor(conn: UniffiPlacesConnect
     ReadableHistoryConnection,
     ReadableHistoryMetadataConnection,
     ReadableBookmarksConnection {
-    override fun queryAutocomplete(query: String, limit: Int): List<SearchResult> {
-        return this.conn.queryAutocomplete(query, limit)
+    override fun queryAutocomplete(query: String, limit: Int, restrictionTokens: RestrictionTokens?): List<SearchResult> {
+        return this.conn.queryAutocomplete(query, limit, restrictionTokens)
+    }
+
+    override fun searchHistory(query: String, options: HistorySearchOptions): List<HistorySearchResult> {
+        return readQueryCounters.measure("search_history") {
+            this.conn.searchHistory(query, options)
+        }
     }
 
     override fun matchUrl(query: String): Url? {
         return this.conn.matchUrl(query)
     }
 
-    override fun getTopFrecentSiteInfos(numItems: Int, frecencyThreshold: FrecencyThresholdOption): List<TopFrecentSiteInfo> {
-        return this.conn.getTopFrecentSiteInfos(numItems, frecencyThreshold)
+    override fun getTopFrecentSiteInfos(
+        numItems: Int,
+        frecencyThreshold: FrecencyThresholdOption,
+        options: TopFrecentSiteOptions?
+    ): List<TopFrecentSiteInfo> {
+        return this.conn.getTopFrecentSiteInfos(numItems, frecencyThreshold, options)
+    }
+
+    override fun searchHosts(prefix: String, limit: Int): List<HostInfo> {
+        return readQueryCounters.measure("search_hosts") {
+            this.conn.searchHosts(prefix, limit.toUInt())
+        }
+    }
+
+    override fun getPinnedSites(): List<PinnedSite> {
+        return readQueryCounters.measure("get_pinned_sites") {
+            this.conn.getPinnedSites()
+        }
+    }
+
+    override fun getZeroPrefixSuggestions(limit: Int): List<ZeroPrefixSuggestion> {
+        return readQueryCounters.measure("get_zero_prefix_suggestions") {
+            this.conn.getZeroPrefixSuggestions(limit.toUInt())
+        }
+    }
+
+    override fun getTypedUrls(limit: Int): List<TypedUrlInfo> {
+        return readQueryCounters.measure("get_typed_urls") {
+            this.conn.getTypedUrls(limit.toUInt())
+        }
+    }
+
+    override fun getTopSitesPrefetchHints(limit: Int): List<TopSitePrefetchHint> {
+        return readQueryCounters.measure("get_top_sites_prefetch_hints") {
+            this.conn.getTopSitesPrefetchHints(limit.toUInt())
+        }
     }
 
     override fun getVisited(urls: List<String>): List<Boolean> {
@@ -185,49 +344,116 @@ open class PlacesReaderConnection internal constructor(conn: UniffiPlacesConnect
         return this.conn.getVisitedUrlsInRange(start, end, includeRemote)
     }
 
-    override fun getVisitInfos(start: Long, end: Long, excludeTypes: List<VisitType>): List<HistoryVisitInfo> {
-        readQueryCounters.measure {
-            return this.conn.getVisitInfos(start, end, visitTransitionSet(excludeTypes))
+    override fun getVisitInfos(
+        start: Long,
+        end: Long,
+        excludeTypes: List<VisitType>,
+        containerId: String?
+    ): List<HistoryVisitInfo> {
+        readQueryCounters.measure("get_visit_infos") {
+            return this.conn.getVisitInfos(start, end, visitTransitionSet(excludeTypes), containerId)
         }
     }
 
-    override fun getVisitPage(offset: Long, count: Long, excludeTypes: List<VisitType>): List<HistoryVisitInfo> {
-        return this.conn.getVisitPage(offset, count, visitTransitionSet(excludeTypes))
+    override fun openVisitCursor(
+        start: Long,
+        end: Long,
+        excludeTypes: List<VisitType>,
+        containerId: String?
+    ): VisitCursor {
+        return this.conn.openVisitCursor(start, end, visitTransitionSet(excludeTypes), containerId)
+    }
+
+    override fun getVisitPage(
+        offset: Long,
+        count: Long,
+        excludeTypes: List<VisitType>,
+        containerId: String?
+    ): List<HistoryVisitInfo> {
+        return this.conn.getVisitPage(offset, count, visitTransitionSet(excludeTypes), containerId)
     }
 
     override fun getVisitPageWithBound(
         bound: Long,
         offset: Long,
         count: Long,
-        excludeTypes: List<VisitType>
+        excludeTypes: List<VisitType>,
+        containerId: String?
     ): HistoryVisitInfosWithBound {
-        return this.conn.getVisitPageWithBound(offset, bound, count, visitTransitionSet(excludeTypes))
+        return this.conn.getVisitPageWithBound(offset, bound, count, visitTransitionSet(excludeTypes), containerId)
+    }
+
+    override fun getVisitDetails(url: String): List<HistoryVisitDetails> {
+        return readQueryCounters.measure("get_visit_details") {
+            this.conn.getVisitDetails(url)
+        }
+    }
+
+    override fun getVisitCount(excludeTypes: List<VisitType>, containerId: String?): Long {
+        return this.conn.getVisitCount(visitTransitionSet(excludeTypes), containerId)
     }
 
-    override fun getVisitCount(excludeTypes: List<VisitType>): Long {
-        return this.conn.getVisitCount(visitTransitionSet(excludeTypes))
+    override fun getVisitCountPerDay(
+        start: Long,
+        end: Long,
+        timezoneOffset: Int
+    ): List<VisitCountForDay> {
+        return readQueryCounters.measure("get_visit_count_per_day") {
+            this.conn.getVisitCountPerDay(start, end, timezoneOffset)
+        }
+    }
+
+    override fun getTargetingAttributes(): PlacesTargetingAttributes {
+        return this.conn.getTargetingAttributes()
+    }
+
+    override fun getDataSummary(): PlacesDataSummary {
+        return readQueryCounters.measure("get_data_summary") {
+            this.conn.getDataSummary()
+        }
+    }
+
+    override fun getCurrentChangeSeq(): Long {
+        return readQueryCounters.measure("get_current_change_seq") {
+            this.conn.getCurrentChangeSeq()
+        }
+    }
+
+    override fun getChangesSince(seq: Long): ChangesSince {
+        return readQueryCounters.measure("get_changes_since") {
+            this.conn.getChangesSince(seq)
+        }
     }
 
     override suspend fun getLatestHistoryMetadataForUrl(url: Url): HistoryMetadata? {
-        return readQueryCounters.measure {
+        return readQueryCounters.measure("get_latest_history_metadata_for_url") {
             this.conn.getLatestHistoryMetadataForUrl(url)
         }
     }
 
     override suspend fun getHistoryMetadataSince(since: Long): List<HistoryMetadata> {
-        return readQueryCounters.measure {
+        return readQueryCounters.measure("get_history_metadata_since") {
             this.conn.getHistoryMetadataSince(since)
         }
     }
 
     override suspend fun getHistoryMetadataBetween(start: Long, end: Long): List<HistoryMetadata> {
-        return readQueryCounters.measure {
+        return readQueryCounters.measure("get_history_metadata_between") {
             this.conn.getHistoryMetadataBetween(start, end)
         }
     }
 
+    override suspend fun getHistoryMetadataGroupedBySearchTerm(
+        start: Long,
+        end: Long
+    ): List<HistoryMetadataGroup> {
+        return readQueryCounters.measure("get_history_metadata_grouped_by_search_term") {
+            this.conn.getHistoryMetadataGroupedBySearchTerm(start, end)
+        }
+    }
+
     override suspend fun queryHistoryMetadata(query: String, limit: Int): List<HistoryMetadata> {
-        return readQueryCounters.measure {
+        return readQueryCounters.measure("query_history_metadata") {
             this.conn.queryHistoryMetadata(query, limit)
         }
     }
@@ -236,17 +462,29 @@ open class PlacesReaderConnection internal constructor(conn: UniffiPlacesConnect
         weights: HistoryHighlightWeights,
         limit: Int
     ): List<HistoryHighlight> {
-        return readQueryCounters.measure {
+        return readQueryCounters.measure("get_highlights") {
             this.conn.getHistoryHighlights(weights, limit)
         }
     }
 
+    override suspend fun getNavigationChain(url: Url, depth: Int): List<HistoryMetadata> {
+        return readQueryCounters.measure("get_navigation_chain") {
+            this.conn.getNavigationChain(url, depth.toUInt())
+        }
+    }
+
     override fun getBookmark(guid: Guid): BookmarkItem? {
-        return readQueryCounters.measure {
+        return readQueryCounters.measure("get_bookmark") {
             this.conn.bookmarksGetByGuid(guid, false)
         }
     }
 
+    override fun getBookmarksChildrenPage(folderGUID: Guid, offset: Int, limit: Int): List<BookmarkItem>? {
+        return readQueryCounters.measure("get_bookmarks_children_page") {
+            this.conn.bookmarksGetChildrenPage(folderGUID, offset.toUInt(), limit.toUInt())
+        }
+    }
+
     override fun getBookmarksTree(rootGUID: Guid, recursive: Boolean): BookmarkItem? {
         if (recursive) {
             return this.conn.bookmarksGetTree(rootGUID)
@@ -256,7 +494,7 @@ open class PlacesReaderConnection internal constructor(conn: UniffiPlacesConnect
     }
 
     override fun getBookmarksWithURL(url: Url): List<BookmarkItem> {
-        return readQueryCounters.measure {
+        return readQueryCounters.measure("get_bookmarks_with_url") {
             this.conn.bookmarksGetAllWithUrl(url)
         }
     }
@@ -265,18 +503,66 @@ open class PlacesReaderConnection internal constructor(conn: UniffiPlacesConnect
         return this.conn.bookmarksGetUrlForKeyword(keyword)
     }
 
+    override fun countBookmarksInFolder(folderGUID: Guid): Int? {
+        return readQueryCounters.measure("count_bookmarks_in_folder") {
+            this.conn.bookmarksCountByParent(folderGUID)?.toInt()
+        }
+    }
+
+    override fun countAllBookmarks(): Int {
+        return readQueryCounters.measure("count_all_bookmarks") {
+            this.conn.bookmarksCountAll().toInt()
+        }
+    }
+
+    override fun getBookmarksStatistics(): BookmarksStatistics {
+        return readQueryCounters.measure("get_bookmarks_statistics") {
+            this.conn.bookmarksGetStatistics()
+        }
+    }
+
     override fun searchBookmarks(query: String, limit: Int): List<BookmarkItem> {
-        return readQueryCounters.measure {
+        return readQueryCounters.measure("search_bookmarks") {
             this.conn.bookmarksSearch(query, limit)
         }
     }
 
     override fun getRecentBookmarks(limit: Int): List<BookmarkItem> {
-        return readQueryCounters.measure {
+        return readQueryCounters.measure("get_recent_bookmarks") {
             this.conn.bookmarksGetRecent(limit)
         }
     }
 
+    override fun getImportedBookmarks(source: BookmarkImportSource): List<BookmarkItem> {
+        return readQueryCounters.measure("get_imported_bookmarks") {
+            this.conn.bookmarksGetImported(source)
+        }
+    }
+
+    override fun findDuplicateBookmarks(): List<DuplicateBookmarks> {
+        return readQueryCounters.measure("find_duplicate_bookmarks") {
+            this.conn.bookmarksFindDuplicates()
+        }
+    }
+
+    override fun getBookmarksTooLargeToSync(): List<BookmarkTooLargeToSync> {
+        return readQueryCounters.measure("get_bookmarks_too_large_to_sync") {
+            this.conn.bookmarksGetTooLargeToSync()
+        }
+    }
+
+    override fun getTagsForUrl(url: Url): List<String> {
+        return readQueryCounters.measure("get_tags_for_url") {
+            this.conn.bookmarksGetTagsForUrl(url)
+        }
+    }
+
+    override fun getUrlsWithTag(tag: String): List<Url> {
+        return readQueryCounters.measure("get_urls_with_tag") {
+            this.conn.bookmarksGetUrlsWithTag(tag)
+        }
+    }
+
     private val readQueryCounters: PlacesManagerCounterMetrics by lazy {
         PlacesManagerCounterMetrics(
             PlacesManagerMetrics.readQueryCount,
@@ -307,19 +593,49 @@ class PlacesWriterConnection internal constructor(conn: UniffiPlacesConnection,
     // The reference to our PlacesAPI. Mostly used to know how to handle getting closed.
     val apiRef = WeakReference(api)
     override fun noteObservation(data: VisitObservation) {
-        return writeQueryCounters.measure {
+        return writeQueryCounters.measure("note_observation") {
             this.conn.applyObservation(data)
         }
     }
 
+    override fun noteObservations(data: List<VisitObservation>): Int {
+        return writeQueryCounters.measure("note_observations") {
+            this.conn.applyObservations(data).toInt()
+        }
+    }
+
+    override fun updatePreviewImage(url: Url, previewImageUrl: Url): Boolean {
+        return writeQueryCounters.measure("update_preview_image") {
+            this.conn.updatePreviewImage(url, previewImageUrl)
+        }
+    }
+
     override fun deleteVisitsFor(url: String) {
-        return writeQueryCounters.measure {
+        return writeQueryCounters.measure("delete_visits_for") {
             this.conn.deleteVisitsFor(url)
         }
     }
 
+    override fun deleteVisitsFor(urls: List<String>) {
+        return writeQueryCounters.measure("delete_visits_for_urls") {
+            this.conn.deleteVisitsForUrls(urls)
+        }
+    }
+
+    override fun deleteVisitsForHost(host: String) {
+        return writeQueryCounters.measure("delete_visits_for_host") {
+            this.conn.deleteVisitsForHost(host)
+        }
+    }
+
+    override fun deleteEverythingForOrigin(origin: Url) {
+        return writeQueryCounters.measure("delete_everything_for_origin") {
+            this.conn.deleteEverythingForOrigin(origin)
+        }
+    }
+
     override fun deleteVisit(url: String, visitTimestamp: Long) {
-        return writeQueryCounters.measure {
+        return writeQueryCounters.measure("delete_visit") {
             this.conn.deleteVisit(url, visitTimestamp)
         }
     }
@@ -329,7 +645,7 @@ class PlacesWriterConnection internal constructor(conn: UniffiPlacesConnection,
     }
 
     override fun deleteVisitsBetween(startTime: Long, endTime: Long) {
-        return writeQueryCounters.measure {
+        return writeQueryCounters.measure("delete_visits_between") {
             this.conn.deleteVisitsBetween(startTime, endTime)
         }
     }
@@ -338,26 +654,75 @@ class PlacesWriterConnection internal constructor(conn: UniffiPlacesConnection,
         this.conn.wipeLocalHistory()
     }
 
-    override fun runMaintenance(dbSizeLimit: UInt) {
-        val pruneMetrics = PlacesManagerMetrics.runMaintenanceTime.measure {
+    override fun runMaintenance(dbSizeLimit: UInt): MaintenanceResult {
+        val result = PlacesManagerMetrics.runMaintenanceTime.measure {
             val pruneMetrics = PlacesManagerMetrics.runMaintenancePruneTime.measure {
                 this.conn.runMaintenancePrune(dbSizeLimit)
             }
 
-            PlacesManagerMetrics.runMaintenanceVacuumTime.measure {
+            val vacuumMetrics = PlacesManagerMetrics.runMaintenanceVacuumTime.measure {
                 this.conn.runMaintenanceVacuum()
             }
 
-            PlacesManagerMetrics.runMaintenanceOptimizeTime.measure {
+            val optimizeMetrics = PlacesManagerMetrics.runMaintenanceOptimizeTime.measure {
                 this.conn.runMaintenanceOptimize()
             }
 
-            PlacesManagerMetrics.runMaintenanceChkPntTime.measure {
+            val checkpointMetrics = PlacesManagerMetrics.runMaintenanceChkPntTime.measure {
                 this.conn.runMaintenanceCheckpoint()
             }
-            pruneMetrics
+            MaintenanceResult(pruneMetrics, vacuumMetrics, optimizeMetrics, checkpointMetrics)
+        }
+        PlacesManagerMetrics.dbSizeAfterMaintenance.accumulateSamples(listOf(result.prune.dbSizeAfter.toLong() / 1024))
+        return result
+    }
+
+    override fun setHistoryDeletionPolicy(policy: HistoryDeletionPolicy?) {
+        return writeQueryCounters.measure("set_history_deletion_policy") {
+            this.conn.setHistoryDeletionPolicy(policy)
+        }
+    }
+
+    override fun getHistoryDeletionPolicy(): HistoryDeletionPolicy? {
+        return writeQueryCounters.measure("get_history_deletion_policy") {
+            this.conn.getHistoryDeletionPolicy()
+        }
+    }
+
+    override fun runPendingFrecencyUpdates(chunkSize: UInt, maxDurationMs: UInt): FrecencyUpdateMetrics {
+        return writeQueryCounters.measure("run_pending_frecency_updates") {
+            this.conn.runPendingFrecencyUpdates(chunkSize, maxDurationMs)
+        }
+    }
+
+    override fun rekeyDatabase(newKey: String) {
+        return writeQueryCounters.measure("rekey_database") {
+            this.conn.rekeyDatabase(newKey)
+        }
+    }
+
+    override fun getPagesMissingMetadata(kind: PageMetadataKind, limit: Int): List<Url> {
+        return writeQueryCounters.measure("get_pages_missing_metadata") {
+            this.conn.getPagesMissingMetadata(kind, limit.toUInt())
+        }
+    }
+
+    override fun markPageMetadataAttempted(kind: PageMetadataKind, urls: List<Url>) {
+        return writeQueryCounters.measure("mark_page_metadata_attempted") {
+            this.conn.markPageMetadataAttempted(kind, urls)
+        }
+    }
+
+    override fun pinSite(url: Url, title: String?) {
+        return writeQueryCounters.measure("pin_site") {
+            this.conn.pinSite(url, title)
+        }
+    }
+
+    override fun unpinSite(url: Url): Boolean {
+        return writeQueryCounters.measure("unpin_site") {
+            this.conn.unpinSite(url)
         }
-        PlacesManagerMetrics.dbSizeAfterMaintenance.accumulateSamples(listOf(pruneMetrics.dbSizeAfter.toLong() / 1024))
     }
 
     override fun pruneDestructively() {
@@ -365,30 +730,72 @@ class PlacesWriterConnection internal constructor(conn: UniffiPlacesConnection,
     }
 
     override fun deleteEverything() {
-        return writeQueryCounters.measure {
+        return writeQueryCounters.measure("delete_everything") {
             this.conn.deleteEverythingHistory()
         }
     }
 
+    override fun deleteEverything(mode: HistoryDeletionSyncMode) {
+        return writeQueryCounters.measure("delete_everything") {
+            this.conn.deleteEverythingHistoryWithSyncMode(mode)
+        }
+    }
+
     override fun deleteAllBookmarks() {
-        return writeQueryCounters.measure {
+        return writeQueryCounters.measure("delete_all_bookmarks") {
             this.conn.bookmarksDeleteEverything()
         }
     }
 
+    override fun deleteImportedBookmarks(source: BookmarkImportSource): Int {
+        return writeQueryCounters.measure("delete_imported_bookmarks") {
+            this.conn.bookmarksDeleteImported(source).toInt()
+        }
+    }
+
+    override fun mergeDuplicateBookmarks(strategy: DuplicateBookmarksStrategy): Int {
+        return writeQueryCounters.measure("merge_duplicate_bookmarks") {
+            this.conn.bookmarksMergeDuplicates(strategy).toInt()
+        }
+    }
+
+    override fun tagUrl(url: Url, tag: String) {
+        return writeQueryCounters.measure("tag_url") {
+            this.conn.bookmarksTagUrl(url, tag)
+        }
+    }
+
+    override fun untagUrl(url: Url, tag: String) {
+        return writeQueryCounters.measure("untag_url") {
+            this.conn.bookmarksUntagUrl(url, tag)
+        }
+    }
+
     override fun deleteBookmarkNode(guid: Guid): Boolean {
-        return writeQueryCounters.measure {
+        return writeQueryCounters.measure("delete_bookmark_node") {
             this.conn.bookmarksDelete(guid)
         }
     }
 
+    override fun restoreBookmarkNode(guid: Guid): Guid? {
+        return writeQueryCounters.measure("restore_bookmark_node") {
+            this.conn.bookmarksRestore(guid)
+        }
+    }
+
+    override fun purgeBookmarksTrash(olderThan: Long): Int {
+        return writeQueryCounters.measure("purge_bookmarks_trash") {
+            this.conn.bookmarksPurgeTrash(olderThan).toInt()
+        }
+    }
+
     override suspend fun noteHistoryMetadataObservation(observation: HistoryMetadataObservation) {
         // Different types of `HistoryMetadataObservation` are flattened out into a list of values.
         // The other side of this (rust code) is going to deal with missing/absent values. We're just
         // passing them along here.
         // NB: Even though `MsgTypes.HistoryMetadataObservation` has an optional title field, we ignore it here.
         // That's used by consumers which aren't already using the history observation APIs.
-        return writeQueryCounters.measure {
+        return writeQueryCounters.measure("note_history_metadata_observation") {
             this.conn.noteHistoryMetadataObservation(observation)
         }
     }
@@ -398,7 +805,8 @@ class PlacesWriterConnection internal constructor(conn: UniffiPlacesConnection,
             url = key.url,
             searchTerm = key.searchTerm,
             referrerUrl = key.referrerUrl,
-            viewTime = viewTime
+            viewTime = viewTime,
+            metadataSessionId = key.metadataSessionId
         )
         noteHistoryMetadataObservation(obs)
     }
@@ -408,19 +816,26 @@ class PlacesWriterConnection internal constructor(conn: UniffiPlacesConnection,
             url = key.url,
             searchTerm = key.searchTerm,
             referrerUrl = key.referrerUrl,
-            documentType = documentType
+            documentType = documentType,
+            metadataSessionId = key.metadataSessionId
         )
         noteHistoryMetadataObservation(obs)
     }
 
     override suspend fun deleteHistoryMetadataOlderThan(olderThan: Long) {
-        return writeQueryCounters.measure {
+        return writeQueryCounters.measure("delete_history_metadata_older_than") {
             this.conn.metadataDeleteOlderThan(olderThan)
         }
     }
 
+    override suspend fun deleteHistoryMetadataForSearchTerm(searchTerm: String) {
+        return writeQueryCounters.measure("delete_history_metadata_for_search_term") {
+            this.conn.metadataDeleteBySearchTerm(searchTerm)
+        }
+    }
+
     override suspend fun deleteHistoryMetadata(key: HistoryMetadataKey) {
-        return writeQueryCounters.measure {
+        return writeQueryCounters.measure("delete_history_metadata") {
             this.conn.metadataDelete(
                 key.url,
                 key.referrerUrl,
@@ -431,7 +846,7 @@ class PlacesWriterConnection internal constructor(conn: UniffiPlacesConnection,
 
     // Does the shared insert work.
     private fun doInsert(item: InsertableBookmarkItem): Guid {
-        return writeQueryCounters.measure {
+        return writeQueryCounters.measure("insert_bookmark") {
             this.conn.bookmarksInsert(item)
         }
     }
@@ -466,13 +881,19 @@ class PlacesWriterConnection internal constructor(conn: UniffiPlacesConnection,
         return this.doInsert(InsertableBookmarkItem.Bookmark(bm))
     }
 
+    override fun insertBookmarkTree(parentGUID: Guid, tree: InsertableBookmarkTree): List<BookmarkGuidMapping> {
+        return writeQueryCounters.measure("insert_bookmark_tree") {
+            this.conn.bookmarksInsertTree(parentGUID, tree)
+        }
+    }
+
     override fun updateBookmark(guid: Guid, parentGuid: Guid?, position: UInt?, title: String?, url: Url?) {
         val p: UInt? = if (position == null) {
             null
         } else {
             position.toUInt()
         }
-        return writeQueryCounters.measure {
+        return writeQueryCounters.measure("update_bookmark") {
             val info = BookmarkUpdateInfo(guid = guid, title = title, url = url, parentGuid = parentGuid, position = p)
             return this.conn.bookmarksUpdate(info)
         }
@@ -482,6 +903,12 @@ class PlacesWriterConnection internal constructor(conn: UniffiPlacesConnection,
         return this.conn.acceptResult(searchString, url)
     }
 
+    override fun deleteAdaptiveHistory() {
+        return writeQueryCounters.measure("delete_adaptive_history") {
+            this.conn.deleteAdaptiveHistory()
+        }
+    }
+
     @Synchronized
     override fun close() {
         // If our API is still around, do nothing.
@@ -525,11 +952,38 @@ interface PlacesManager {
      */
     fun registerWithSyncManager()
 
+    /**
+     * Registers history metadata with the sync manager.
+     *
+     * History metadata isn't synced unless you call this, and also enable the
+     * `historymetadata` engine with `SyncParams.enabledChanges`.
+     */
+    fun registerHistoryMetadataWithSyncManager()
+
     /**
      * Open a reader connection.
      */
     fun openReader(): ReadableHistoryConnection
 
+    /**
+     * Open a reader connection backed by [size] database connections, so that
+     * up to [size] queries made on different threads can run at the same time,
+     * instead of waiting for each other. Calling `interrupt()` on it interrupts
+     * all of them.
+     */
+    fun openReaderPool(size: Int): ReadableHistoryConnection
+
+    /**
+     * Open a reader connection with a snapshot of the database: every query made on it
+     * sees the database as it was when it was opened, even if writes land in the meantime.
+     * Use this when a screen needs several queries (eg, a count and a page of results) to
+     * agree with each other.
+     *
+     * The snapshot holds a read transaction open until the connection is closed, so close
+     * it as soon as you're done with it.
+     */
+    fun openSnapshot(): ReadableHistoryConnection
+
     /**
      * Get a reference to the writer connection.
      *
@@ -563,9 +1017,16 @@ interface PlacesManager {
      * It has been designed exclusively for non-sync users.
      *
      * @param path Path to the `browser.db` file database.
+     * @param mappings Where to put the children of some of the imported roots,
+     * instead of in the matching local roots.
+     * @param key The key of the database, if it's encrypted with SQLCipher.
      * @return JSONObject with import metrics.
      */
-    fun importBookmarksFromFennec(path: String): JSONObject
+    fun importBookmarksFromFennec(
+        path: String,
+        mappings: List<BookmarkImportRootMapping>? = null,
+        key: String? = null
+    ): JSONObject
 
     /**
      * Imports visits from a Fennec `browser.db` database.
@@ -574,9 +1035,53 @@ interface PlacesManager {
      * be called before bookmarks import.
      *
      * @param path Path to the `browser.db` file database.
+     * @param key The key of the database, if it's encrypted with SQLCipher.
+     * @return JSONObject with import metrics.
+     */
+    fun importVisitsFromFennec(path: String, key: String? = null): JSONObject
+
+    /**
+     * Imports visits from Chrome's `History` database. Chrome keeps the database
+     * open, so [path] should be a copy of it.
+     *
+     * @param path Path to the copy of Chrome's `History` database.
+     * @return The import metrics.
+     */
+    fun importVisitsFromChrome(path: String): HistoryMigrationResult
+
+    /**
+     * Imports visits from a Firefox Desktop `places.sqlite` database. Desktop
+     * keeps the database open, so [path] should be a copy of it.
+     *
+     * @param path Path to the copy of the `places.sqlite` database.
+     * @return The import metrics.
+     */
+    fun importVisitsFromDesktop(path: String): HistoryMigrationResult
+
+    /**
+     * Imports visits the app has read from a browser we don't have an importer
+     * for, all or nothing. The visits are imported as if they were made on this
+     * device. Visits to URLs we don't record in history, and visits we already
+     * have, are skipped and counted as failed.
+     *
+     * @param visits The visits to import.
+     * @return The import metrics.
+     */
+    fun importVisits(visits: List<ImportedVisit>): HistoryMigrationResult
+
+    /**
+     * Imports bookmarks from a Firefox Desktop `places.sqlite` database,
+     * with their keywords and tags. Queries and livemarks aren't imported.
+     *
+     * @param path Path to the copy of the `places.sqlite` database.
+     * @param mappings Where to put the children of some of the imported roots,
+     * instead of in the matching local roots.
      * @return JSONObject with import metrics.
      */
-    fun importVisitsFromFennec(path: String): JSONObject
+    fun importBookmarksFromDesktop(
+        path: String,
+        mappings: List<BookmarkImportRootMapping>? = null
+    ): JSONObject
 
     /**
      * Returns pinned sites from a Fennec `browser.db` bookmark database.
@@ -588,9 +1093,49 @@ interface PlacesManager {
      * Fenix can store them in a collection.
      *
      * @param path Path to the `browser.db` file database.
+     * @param key The key of the database, if it's encrypted with SQLCipher.
      * @return A list of pinned websites.
      */
-    fun importPinnedSitesFromFennec(path: String): List<BookmarkItem>
+    fun importPinnedSitesFromFennec(path: String, key: String? = null): List<BookmarkItem>
+
+    /**
+     * Imports bookmarks from a file in the standard Netscape bookmarks HTML format,
+     * which every browser can export. They go into a new "Imported" folder in
+     * "Other Bookmarks". Bookmarks for URLs which are already bookmarked are skipped,
+     * so importing the same file again doesn't add duplicates. Use this to import
+     * Chrome's bookmarks, from the file Chrome exports.
+     *
+     * @param path Path to the file to import.
+     * @param mappings Where to put the bookmarks from some of the roots in the file,
+     * like the toolbar, instead of in the "Imported" folder.
+     * @return How many bookmarks were inserted and skipped.
+     */
+    fun importBookmarksFromHtml(
+        path: String,
+        mappings: List<BookmarkImportRootMapping>? = null
+    ): HtmlBookmarksImportResult
+
+    /**
+     * Writes all bookmarks to a file in the standard Netscape bookmarks HTML format,
+     * which other browsers can import. Keywords are included; tags aren't.
+     *
+     * @param path Path to the file to write. It's overwritten if it exists.
+     */
+    fun exportBookmarksToHtml(path: String)
+
+    /**
+     * Registers an observer for changes to history and bookmarks made by any
+     * connection, including sync, replacing any existing one.
+     *
+     * The observer is called on the thread which made the changes, once
+     * they've been committed.
+     */
+    fun setChangeObserver(observer: PlacesChangeObserver)
+
+    /**
+     * Removes the observer registered with [setChangeObserver].
+     */
+    fun clearChangeObserver()
 
     /**
      * Resets all sync metadata for history, including change flags,
@@ -622,6 +1167,20 @@ interface InterruptibleConnection : AutoCloseable {
      * Interrupt ongoing operations running on a separate thread.
      */
     fun interrupt()
+
+    /**
+     * Like [interrupt], but only interrupts operations with [priority] or lower. For example,
+     * interrupting at [InterruptPriority.BACKGROUND] cancels maintenance and imports, but not
+     * autocomplete queries.
+     */
+    fun interrupt(priority: InterruptPriority)
+
+    /**
+     * Free the memory held by this connection's caches. Call this from
+     * `onTrimMemory`, so that the app is less likely to be killed in the
+     * background. Connections which are busy on other threads are skipped.
+     */
+    fun onMemoryPressure()
 }
 
 /**
@@ -653,6 +1212,16 @@ interface ReadableHistoryMetadataConnection : InterruptibleConnection {
      */
     suspend fun getHistoryMetadataBetween(start: Long, end: Long): List<HistoryMetadata>
 
+    /**
+     * Groups the [HistoryMetadata] updated between [start] and [end], inclusive, by
+     * [HistoryMetadata.searchTerm]. Metadata without a search term is left out.
+     *
+     * @param start A `start` timestamp.
+     * @param end An `end` timestamp.
+     * @return A `List` of [HistoryMetadataGroup], most recently updated first.
+     */
+    suspend fun getHistoryMetadataGroupedBySearchTerm(start: Long, end: Long): List<HistoryMetadataGroup>
+
     /**
      * Searches through [HistoryMetadata] by [query], matching records by [HistoryMetadata.url],
      * [HistoryMetadata.title] and [HistoryMetadata.searchTerm].
@@ -672,6 +1241,17 @@ interface ReadableHistoryMetadataConnection : InterruptibleConnection {
      * @return A `List` of ranked [HistoryHighlight], empty if no history/metadata is found.
      */
     suspend fun getHighlights(weights: HistoryHighlightWeights, limit: Int): List<HistoryHighlight>
+
+    /**
+     * Reconstructs how the user arrived at [url] and where they went from there, by following
+     * [HistoryMetadata.referrerUrl] backwards and forwards from the latest metadata for [url].
+     *
+     * @param url A page to find the navigation chain of.
+     * @param depth The maximum number of steps to follow in each direction.
+     * @return A `List` of [HistoryMetadata], oldest first and including [url], empty if we don't
+     * have metadata for [url].
+     */
+    suspend fun getNavigationChain(url: String, depth: Int): List<HistoryMetadata>
 }
 
 /**
@@ -695,6 +1275,15 @@ interface WritableHistoryMetadataConnection : ReadableHistoryMetadataConnection
      */
     suspend fun deleteHistoryMetadataOlderThan(olderThan: Long)
 
+    /**
+     * Deletes the metadata records of every page the user came to from a search
+     * for [searchTerm], without needing to know their URLs. The pages no longer
+     * count towards history highlights.
+     *
+     * @param searchTerm The search term, which is matched ignoring case.
+     */
+    suspend fun deleteHistoryMetadataForSearchTerm(searchTerm: String)
+
     /**
      * Deletes metadata records that match [key].
      *
@@ -709,9 +1298,22 @@ interface ReadableHistoryConnection : InterruptibleConnection {
      *
      * @param query a string to match results against.
      * @param limit a maximum number of results to retrieve.
+     * @param restrictionTokens if given, tokens in the [query] like `*` restrict the results to
+     * bookmarks, tags, history and so on, like Desktop's URL bar. They're ignored otherwise.
      * @return a list of [SearchResult] matching the [query], in arbitrary order.
      */
-    fun queryAutocomplete(query: String, limit: Int): List<SearchResult>
+    fun queryAutocomplete(query: String, limit: Int, restrictionTokens: RestrictionTokens? = null): List<SearchResult>
+
+    /**
+     * Searches the titles and URLs of visited pages, for a full history search UI. Unlike
+     * [queryAutocomplete], the caller chooses how results are matched and ranked.
+     *
+     * @param query the words to search for. Every word must match a page's title or URL.
+     * @param options how to match and rank results, whether to include hidden pages, and the
+     * maximum number of results to return.
+     * @return a list of [HistorySearchResult], ranked as [options] asks.
+     */
+    fun searchHistory(query: String, options: HistorySearchOptions): List<HistorySearchResult>
 
     /**
      * See if a url that's sufficiently close to `search` exists in
@@ -731,9 +1333,60 @@ interface ReadableHistoryConnection : InterruptibleConnection {
      * @param numItems the number of top frecent sites to return in the list.
      * @param frecencyThreshold frecency threshold options for filtering visited sites based on
      * their frecency score.
+     * @param options whether to return one page per site, and which URLs and hosts to leave out.
+     * Pages which are left out don't count towards [numItems].
      * @return a list of the top frecent site infos sorted by most to least frecent.
      */
-    fun getTopFrecentSiteInfos(numItems: Int, frecencyThreshold: FrecencyThresholdOption): List<TopFrecentSiteInfo>
+    fun getTopFrecentSiteInfos(
+        numItems: Int,
+        frecencyThreshold: FrecencyThresholdOption,
+        options: TopFrecentSiteOptions? = null
+    ): List<TopFrecentSiteInfo>
+
+    /**
+     * Returns the hosts the user has history or bookmarks for, so that settings can offer them
+     * when the user adds a site exception.
+     *
+     * @param prefix only hosts starting with this, with or without a leading `www.`, are
+     * returned. An empty prefix returns every host.
+     * @param limit the maximum number of hosts to return.
+     * @return a list of [HostInfo], most visited first.
+     */
+    fun searchHosts(prefix: String, limit: Int): List<HostInfo>
+
+    /**
+     * Returns the sites the user pinned to their top sites, in the order they were pinned.
+     * Pinned sites aren't bookmarks, so they aren't returned by the bookmarks API.
+     */
+    fun getPinnedSites(): List<PinnedSite>
+
+    /**
+     * Returns suggestions for the empty URL bar, blending recent searches, top sites and
+     * recent bookmarks, without repeating a URL.
+     *
+     * @param limit the maximum number of suggestions to return.
+     * @return a list of [ZeroPrefixSuggestion], taking one of each kind in turn.
+     */
+    fun getZeroPrefixSuggestions(limit: Int): List<ZeroPrefixSuggestion>
+
+    /**
+     * Returns the pages the user has typed into the URL bar, most often typed first. Typed
+     * visits synced from other devices are counted too.
+     *
+     * @param limit the maximum number of pages to return.
+     * @return a list of [TypedUrlInfo], ordered by typed count and then frecency.
+     */
+    fun getTypedUrls(limit: Int): List<TypedUrlInfo>
+
+    /**
+     * Returns the origins with the highest frecency, each with the pages the user most often
+     * went to next from one of its pages, by following a link or a redirect. Use these to
+     * decide what to preconnect to or prefetch when showing top sites.
+     *
+     * @param limit the maximum number of origins to return.
+     * @return a list of [TopSitePrefetchHint], ordered by frecency.
+     */
+    fun getTopSitesPrefetchHints(limit: Int): List<TopSitePrefetchHint>
 
     /**
      * Maps a list of page URLs to a list of booleans indicating if each URL was visited.
@@ -760,13 +1413,32 @@ interface ReadableHistoryConnection : InterruptibleConnection {
      *
      * @param start The (inclusive) start time to bound the query.
      * @param end The (inclusive) end time to bound the query.
+     * @param containerId If set, only return visits made in this container.
      */
     fun getVisitInfos(
         start: Long,
         end: Long = Long.MAX_VALUE,
-        excludeTypes: List<VisitType> = listOf()
+        excludeTypes: List<VisitType> = listOf(),
+        containerId: String? = null
     ): List<HistoryVisitInfo>
 
+    /**
+     * Like [getVisitInfos], but returns the visits oldest first, a chunk at a
+     * time, as [VisitCursor.nextChunk] is called. Interrupting reads on this
+     * connection stops the chunk being read, and the cursor can be stopped
+     * early with [VisitCursor.finish].
+     *
+     * @param start The (inclusive) start time to bound the query.
+     * @param end The (inclusive) end time to bound the query.
+     * @param containerId If set, only return visits made in this container.
+     */
+    fun openVisitCursor(
+        start: Long,
+        end: Long = Long.MAX_VALUE,
+        excludeTypes: List<VisitType> = listOf(),
+        containerId: String? = null
+    ): VisitCursor
+
     /**
      * Return a "page" of history results. Each page will have visits in descending order
      * with respect to their visit timestamps. In the case of ties, their row id will
@@ -778,8 +1450,14 @@ interface ReadableHistoryConnection : InterruptibleConnection {
      * @param offset The offset where the page begins.
      * @param count The number of items to return in the page.
      * @param excludeTypes List of visit types to exclude.
+     * @param containerId If set, only return visits made in this container.
      */
-    fun getVisitPage(offset: Long, count: Long, excludeTypes: List<VisitType> = listOf()): List<HistoryVisitInfo>
+    fun getVisitPage(
+        offset: Long,
+        count: Long,
+        excludeTypes: List<VisitType> = listOf(),
+        containerId: String? = null
+    ): List<HistoryVisitInfo>
 
     /**
      * Page more efficiently than using simple numeric offset. We first figure out
@@ -791,14 +1469,26 @@ interface ReadableHistoryConnection : InterruptibleConnection {
      *  and last visited item.
      * @param count The number eof items to return in the page.
      * @param excludeTypes List of visit types to exclude.
+     * @param containerId If set, only return visits made in this container.
      */
     fun getVisitPageWithBound(
         bound: Long,
         offset: Long,
         count: Long,
-        excludeTypes: List<VisitType> = listOf()
+        excludeTypes: List<VisitType> = listOf(),
+        containerId: String? = null
     ): HistoryVisitInfosWithBound
 
+    /**
+     * Returns every visit to a page, along with the chain of visits which led to each one, so
+     * that the app can show how the user arrived there. The chain follows each visit's
+     * referrer, including redirect sources.
+     *
+     * @param url the URL of the page.
+     * @return a list of [HistoryVisitDetails], most recent visit first.
+     */
+    fun getVisitDetails(url: String): List<HistoryVisitDetails>
+
     /**
      * Get the number of history visits.
      *
@@ -810,8 +1500,58 @@ interface ReadableHistoryConnection : InterruptibleConnection {
      *
      *
      * @param excludeTypes List of visit types to exclude.
+     * @param containerId If set, only count visits made in this container.
+     */
+    fun getVisitCount(excludeTypes: List<VisitType> = listOf(), containerId: String? = null): Long
+
+    /**
+     * Get the number of visits on each day in a range, for drawing activity graphs.
+     * Days without any visits are left out. Visits to hidden pages aren't counted.
+     *
+     * @param start The (inclusive) start time to bound the query.
+     * @param end The (inclusive) end time to bound the query.
+     * @param timezoneOffset The user's offset from UTC, in minutes east, which decides
+     * the day each visit falls on. For example,
+     * `TimeZone.getDefault().getOffset(System.currentTimeMillis()) / 60000`.
+     */
+    fun getVisitCountPerDay(
+        start: Long,
+        end: Long = Long.MAX_VALUE,
+        timezoneOffset: Int = 0
+    ): List<VisitCountForDay>
+
+    /**
+     * Get coarse attributes about the user's bookmarks and history, such as
+     * the number of bookmarks, for targeting experiments. Counts are rounded
+     * down to one of a handful of buckets, so this reveals how heavily places
+     * is used, but nothing more.
+     */
+    fun getTargetingAttributes(): PlacesTargetingAttributes
+
+    /**
+     * Get the number of visits, visited pages, bookmarks and history
+     * metadata entries, along with the size of the database, in a single
+     * call. Intended for a "manage data" settings screen.
+     */
+    fun getDataSummary(): PlacesDataSummary
+
+    /**
+     * Get the sequence number of the last change to a page or bookmark, for
+     * apps which keep their own index of them. Note it before indexing
+     * everything, and then pass it to [getChangesSince].
+     */
+    fun getCurrentChangeSeq(): Long
+
+    /**
+     * Get the pages and bookmarks which changed after [seq], so that an
+     * index of them can be updated without indexing everything again.
+     * Deleted items are forgotten 90 days after they're deleted, so an index
+     * which hasn't been updated for longer than that should be rebuilt.
+     *
+     * @param seq The sequence number the index is up to date with.
+     * @return The changes, and the sequence number to pass next time.
      */
-    fun getVisitCount(excludeTypes: List<VisitType> = listOf()): Long
+    fun getChangesSince(seq: Long): ChangesSince
 }
 
 interface WritableHistoryConnection : ReadableHistoryConnection {
@@ -820,6 +1560,25 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      */
     fun noteObservation(data: VisitObservation)
 
+    /**
+     * Record many visits at once, eg when restoring history. This is much faster
+     * than calling [noteObservation] for each, since it uses a single transaction
+     * and only updates frecencies once at the end.
+     *
+     * @return The number of visits recorded.
+     */
+    fun noteObservations(data: List<VisitObservation>): Int
+
+    /**
+     * Set the preview image of a page which is already in history, eg once a
+     * thumbnail has been generated for it. To set it while recording a visit,
+     * use [VisitObservation.previewImageUrl] instead.
+     *
+     * @return false if the page isn't in history, or the preview image URL
+     * is too long to store.
+     */
+    fun updatePreviewImage(url: Url, previewImageUrl: Url): Boolean
+
     /**
      * Deletes all history visits, without recording tombstones.
      *
@@ -848,8 +1607,82 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      * For reference, desktop normally uses 75 MiB (78643200).  If it
      * determines that either the disk or memory is constrained then it halves
      * the amount. The default of 0 disables pruning.
+     * @return What each step of maintenance did, and how long it took, so
+     * that callers can log it, and adjust how often they run maintenance.
+     */
+    fun runMaintenance(dbSizeLimit: UInt = 0U): MaintenanceResult
+
+    /**
+     * Registers what [runMaintenance] should delete each time it runs, for "automatically
+     * delete browsing data" settings. The policy is stored in the database, so it is still
+     * enforced if the app is killed before it can clean up. The deletions only apply to this
+     * device, and aren't synced.
+     *
+     * Things are only deleted once they're older than the policy's `maxAgeDays`. To delete
+     * everything each time, eg, for "delete browsing data on quit", set it to 0; if it's
+     * null, nothing is deleted.
+     *
+     * @param policy what to delete, or null to stop deleting anything.
+     */
+    fun setHistoryDeletionPolicy(policy: HistoryDeletionPolicy?)
+
+    /**
+     * Returns the policy registered with [setHistoryDeletionPolicy], if any.
+     */
+    fun getHistoryDeletionPolicy(): HistoryDeletionPolicy?
+
+    /**
+     * Recalculates stale frecencies, like the ones left behind by an import, in chunks
+     * of [chunkSize] pages, until there are none left or [maxDurationMs] has passed.
+     * Each chunk is committed separately, so this is suitable for a background job;
+     * calling [interrupt] stops it between chunks.
+     *
+     * @return how many frecencies were recalculated, and how many are still stale.
+     */
+    fun runPendingFrecencyUpdates(chunkSize: UInt, maxDurationMs: UInt): FrecencyUpdateMetrics
+
+    /**
+     * Re-encrypts a database opened with an `encryptionKey` with [newKey]. All the readers
+     * have to be closed first, or this throws [PlacesApiException.PlacesConnectionBusy].
+     * Readers opened afterwards use the new key.
      */
-    fun runMaintenance(dbSizeLimit: UInt = 0U)
+    fun rekeyDatabase(newKey: String)
+
+    /**
+     * Returns pages which are missing some kind of metadata, most frecent first, so that the
+     * app can fetch it in the background. Pages which were marked as attempted with
+     * [markPageMetadataAttempted] in the last week aren't returned.
+     *
+     * Places doesn't store icons, so every page is missing a [PageMetadataKind.ICON] until it
+     * is marked as attempted.
+     *
+     * @param kind the kind of metadata to look for.
+     * @param limit the maximum number of pages to return.
+     */
+    fun getPagesMissingMetadata(kind: PageMetadataKind, limit: Int): List<Url>
+
+    /**
+     * Records that the app tried to fetch some kind of metadata for these pages, whether or
+     * not it succeeded.
+     */
+    fun markPageMetadataAttempted(kind: PageMetadataKind, urls: List<Url>)
+
+    /**
+     * Pins a site to the user's top sites, after the other pinned sites. Pinning a site
+     * which is already pinned only changes its title. Pinned sites are kept when history
+     * is deleted.
+     *
+     * @param url the URL of the site to pin.
+     * @param title the title to show for the site, if any.
+     */
+    fun pinSite(url: Url, title: String?)
+
+    /**
+     * Unpins a site.
+     *
+     * @return false if the site wasn't pinned.
+     */
+    fun unpinSite(url: Url): Boolean
 
     /**
      * Aggressively prune history visits. These deletions are not intended
@@ -874,9 +1707,25 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      * arbitrarially far in the future), wheras items which were
      * deleted by deleteEverything (or potentially could have been)
      * should not return.
+     *
+     * This is the same as `deleteEverything(HistoryDeletionSyncMode.LOCAL_ONLY)`.
      */
     fun deleteEverything()
 
+    /**
+     * Delete all history, choosing what happens on the Sync server.
+     *
+     * With [HistoryDeletionSyncMode.LOCAL_ONLY], this behaves like [deleteEverything]:
+     * nothing is uploaded, and the local Sync state is reset, so history from other
+     * devices will return on the next sync - apart from visits older than the deletion.
+     *
+     * With [HistoryDeletionSyncMode.PROPAGATE], the deletions are uploaded on the next
+     * sync, so the history is also removed from the server and from other devices.
+     *
+     * @param mode Whether to upload the deletions.
+     */
+    fun deleteEverything(mode: HistoryDeletionSyncMode)
+
     /**
      * Deletes all visits from the given URL. If the page has previously
      * been synced, a tombstone will be written to the Sync server, meaning
@@ -895,6 +1744,36 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      */
     fun deleteVisitsFor(url: String)
 
+    /**
+     * Like [deleteVisitsFor], but deletes history for all of [urls] at once. This is much
+     * faster than calling [deleteVisitsFor] for each URL, so use it when the user deletes
+     * several pages from their history together.
+     *
+     * @param urls the URLs of the pages to delete.
+     */
+    fun deleteVisitsFor(urls: List<String>)
+
+    /**
+     * Deletes all history for pages on the given host and its subdomains, whatever
+     * their scheme or port, for example to forget everything about a site. This works
+     * like [deleteVisitsFor] for each page, so the deletions are synced, and bookmarked
+     * pages keep everything but their visits and history metadata.
+     *
+     * Icons aren't stored by this library, so any icons for the host should be deleted
+     * separately.
+     *
+     * @param host the host to forget, like `example.com`.
+     */
+    fun deleteVisitsForHost(host: String)
+
+    /**
+     * Like [deleteVisitsForHost], but only deletes history for pages with the same scheme,
+     * host and port as the given origin. Subdomains aren't included.
+     *
+     * @param origin the origin to forget, like `https://example.com`. Any path is ignored.
+     */
+    fun deleteEverythingForOrigin(origin: Url)
+
     /**
      * Deletes all visits which occurred since the specified time. If the
      * deletion removes the last visit for a place, the place itself will also
@@ -938,6 +1817,13 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      * @param url The chosen URL string
      */
     fun acceptResult(searchString: String, url: String)
+
+    /**
+     * Forgets the matches recorded with [acceptResult]. The more often a match
+     * is accepted for what the user typed, the higher it ranks when they type
+     * it, or the start of it, again.
+     */
+    fun deleteAdaptiveHistory()
 }
 
 enum class VisitType(val type: Int) {
@@ -969,7 +1855,27 @@ enum class VisitType(val type: Int) {
 data class HistoryMetadataKey(
     val url: String,
     val searchTerm: String?,
-    val referrerUrl: String?
+    val referrerUrl: String?,
+    /**
+     * The tab, or other session, the page is being viewed in. Observations from
+     * different sessions are recorded separately.
+     */
+    val metadataSessionId: String? = null
+)
+
+/**
+ * What each step of [WritableHistoryConnection.runMaintenance] did.
+ * @property prune How many visits and pages were pruned, and the size of the
+ * database before and after.
+ * @property vacuum How much unused space vacuuming returned to the file system.
+ * @property optimize The outcome of optimizing the indices.
+ * @property checkpoint The outcome of checkpointing the write-ahead log.
+ */
+data class MaintenanceResult(
+    val prune: RunMaintenanceMetrics,
+    val vacuum: MaintenanceStepMetrics,
+    val optimize: MaintenanceStepMetrics,
+    val checkpoint: MaintenanceStepMetrics
 )
 
 /**
@@ -983,10 +1889,17 @@ data class HistoryMetadataKey(
  */
 class PlacesManagerCounterMetrics(
     val count: CounterMetricType,
-    val errCount: LabeledMetricType<CounterMetricType>
+    val errCount: LabeledMetricType<CounterMetricType>,
+    val interruptedCount: LabeledMetricType<CounterMetricType> = PlacesManagerMetrics.interruptedOperationCount,
+    val busyCount: LabeledMetricType<CounterMetricType> = PlacesManagerMetrics.busyOperationCount
 ) {
+    /**
+     * Counts a call to [callback], and any error it throws. Interruptions and timeouts
+     * are also counted against [operation], the name of the API method, which must be
+     * one of the labels listed for those counters in metrics.yaml.
+     */
     @Suppress("ComplexMethod", "TooGenericExceptionCaught")
-    inline fun <U> measure(callback: () -> U): U {
+    inline fun <U> measure(operation: String, callback: () -> U): U {
         count.add()
         try {
             return callback()
@@ -997,6 +1910,7 @@ class PlacesManagerCounterMetrics(
                 }
                 is PlacesApiException.OperationInterrupted -> {
                     errCount["operation_interrupted"].add()
+                    interruptedCount[operation].add()
                 }
                 is PlacesApiException.UnknownBookmarkItem -> {
                     errCount["unknown_bookmark_item"].add()
@@ -1006,6 +1920,7 @@ class PlacesManagerCounterMetrics(
                 }
                 is PlacesApiException.PlacesConnectionBusy -> {
                     errCount["places_connection_busy"].add()
+                    busyCount[operation].add()
                 }
                 is PlacesApiException.UnexpectedPlacesException -> {
                     errCount["unexpected_places_exception"].add()
@@ -0,0 +1,84 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+package mozilla.appservices.places
+
+import mozilla.appservices.sync15.EngineInfo
+import mozilla.appservices.sync15.EventInfo
+import mozilla.appservices.sync15.FailureName
+import mozilla.appservices.sync15.FailureReason
+import mozilla.appservices.sync15.IncomingInfo
+import mozilla.appservices.sync15.OutgoingInfo
+import mozilla.appservices.sync15.ProblemInfo
+import mozilla.appservices.sync15.SyncInfo
+import mozilla.appservices.sync15.SyncTelemetryPing
+import mozilla.appservices.sync15.ValidationInfo
+import mozilla.appservices.places.uniffi.EngineInfo as UniffiEngineInfo
+import mozilla.appservices.places.uniffi.EventInfo as UniffiEventInfo
+import mozilla.appservices.places.uniffi.FailureReason as UniffiFailureReason
+import mozilla.appservices.places.uniffi.SyncInfo as UniffiSyncInfo
+import mozilla.appservices.places.uniffi.SyncTelemetryPing as UniffiSyncTelemetryPing
+
+/**
+ * Converts the ping returned by the Places FFI into the `SyncTelemetryPing`
+ * the rest of the Android code expects.
+ */
+internal fun UniffiSyncTelemetryPing.toSyncTelemetryPing(): SyncTelemetryPing {
+    return SyncTelemetryPing(
+        version = version.toInt(),
+        uid = uid ?: SyncTelemetryPing.EMPTY_UID,
+        events = events.map { it.toEventInfo() },
+        syncs = syncs.map { it.toSyncInfo() }
+    )
+}
+
+private fun UniffiSyncInfo.toSyncInfo(): SyncInfo {
+    return SyncInfo(
+        at = at.toLong(),
+        took = took.toLong(),
+        engines = engines.map { it.toEngineInfo() },
+        failureReason = failureReason?.toFailureReason()
+    )
+}
+
+private fun UniffiEngineInfo.toEngineInfo(): EngineInfo {
+    return EngineInfo(
+        name = name,
+        at = at.toLong(),
+        took = took.toLong(),
+        incoming = incoming?.let {
+            IncomingInfo(
+                applied = it.applied.toInt(),
+                failed = it.failed.toInt(),
+                newFailed = it.newFailed.toInt(),
+                reconciled = it.reconciled.toInt()
+            )
+        },
+        outgoing = outgoing.map {
+            OutgoingInfo(sent = it.sent.toInt(), failed = it.failed.toInt())
+        },
+        failureReason = failureReason?.toFailureReason(),
+        validation = validation?.let {
+            ValidationInfo(
+                version = it.version.toInt(),
+                problems = it.problems.map { p -> ProblemInfo(name = p.name, count = p.count.toInt()) },
+                failureReason = it.failureReason?.toFailureReason()
+            )
+        }
+    )
+}
+
+private fun UniffiFailureReason.toFailureReason(): FailureReason {
+    return when (this) {
+        is UniffiFailureReason.Shutdown -> FailureReason(name = FailureName.Shutdown)
+        is UniffiFailureReason.Other -> FailureReason(name = FailureName.Other, message = error)
+        is UniffiFailureReason.Unexpected -> FailureReason(name = FailureName.Unexpected, message = error)
+        is UniffiFailureReason.Auth -> FailureReason(name = FailureName.Auth, message = from)
+        is UniffiFailureReason.Http -> FailureReason(name = FailureName.Http, code = code.toInt())
+    }
+}
+
+private fun UniffiEventInfo.toEventInfo(): EventInfo {
+    return EventInfo(obj = obj, method = method, value = value, extra = extra)
+}
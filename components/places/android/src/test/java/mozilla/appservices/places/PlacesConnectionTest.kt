@@ -10,6 +10,7 @@ import mozilla.appservices.places.uniffi.DocumentType
 import mozilla.appservices.places.uniffi.VisitObservation
 import mozilla.appservices.places.uniffi.VisitTransition
 import mozilla.appservices.places.uniffi.FrecencyThresholdOption
+import mozilla.appservices.places.uniffi.TopFrecentSiteOptions
 import mozilla.appservices.syncmanager.SyncManager
 import mozilla.appservices.places.uniffi.PlacesApiException
 import mozilla.appservices.places.uniffi.BookmarkItem
@@ -278,6 +279,17 @@ class PlacesConnectionTest {
         assertEquals(2, infos.size)
         assertEquals("https://www.mozilla.com/foo/bar/baz", infos[0].url)
         assertEquals("https://www.example.com/123", infos[1].url)
+
+        infos = db.getTopFrecentSiteInfos(
+            numItems = 5,
+            frecencyThreshold = FrecencyThresholdOption.NONE,
+            options = TopFrecentSiteOptions(dedupeOrigins = true, excluded = listOf("news.ycombinator.com"))
+        )
+
+        assertEquals(2, infos.size)
+        assertEquals("https://www.mozilla.com/foo/bar/baz", infos[0].url)
+        assertEquals(3, infos[0].visitCount.toInt())
+        assertEquals("https://www.example.com/123", infos[1].url)
     }
 
     // Basically equivalent to test_get_visited in rust, but exercises the FFI,
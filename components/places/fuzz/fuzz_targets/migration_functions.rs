@@ -0,0 +1,50 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Runs arbitrary values through the SQL functions the importers register, the
+// same way a malformed source database would.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use places::import::common::define_history_migration_functions;
+use rusqlite::{types::Value, Connection};
+
+fn to_value(data: &[u8]) -> Value {
+    match data.split_first() {
+        None => Value::Null,
+        Some((kind, rest)) => match kind % 5 {
+            0 => Value::Null,
+            1 => {
+                let mut bytes = [0u8; 8];
+                let len = rest.len().min(8);
+                bytes[..len].copy_from_slice(&rest[..len]);
+                Value::Integer(i64::from_le_bytes(bytes))
+            }
+            2 => {
+                let mut bytes = [0u8; 8];
+                let len = rest.len().min(8);
+                bytes[..len].copy_from_slice(&rest[..len]);
+                Value::Real(f64::from_le_bytes(bytes))
+            }
+            3 => Value::Blob(rest.to_vec()),
+            _ => Value::Text(String::from_utf8_lossy(rest).into_owned()),
+        },
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let conn = Connection::open_in_memory().unwrap();
+    define_history_migration_functions(&conn).unwrap();
+    let value = to_value(data);
+    // `CAST(... AS TEXT)` lets blobs through as text which isn't valid UTF-8,
+    // which is what we find in real databases.
+    for sql in &[
+        "SELECT sanitize_utf8(?), sanitize_utf8(CAST(? AS TEXT))",
+        "SELECT validate_url(?), validate_url(CAST(? AS TEXT))",
+        "SELECT sanitize_timestamp(?), sanitize_float_timestamp(?)",
+    ] {
+        conn.query_row(sql, [&value, &value], |_| Ok(())).unwrap();
+    }
+});
@@ -0,0 +1,19 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use places::import::common::{
+    sql_fns::{sanitize_float_timestamp_value, sanitize_timestamp},
+    NOW,
+};
+
+fuzz_target!(|data: [u8; 8]| {
+    let now = *NOW;
+    let ts = sanitize_timestamp(i64::from_le_bytes(data));
+    assert!(ts <= now, "{:?} is in the future", ts);
+    let ts = sanitize_float_timestamp_value(f64::from_le_bytes(data));
+    assert!(ts <= now, "{:?} is in the future", ts);
+});
@@ -0,0 +1,21 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use places::import::common::sql_fns::sanitize_utf8_value;
+use rusqlite::types::ValueRef;
+
+fuzz_target!(|data: &[u8]| {
+    let sanitized = sanitize_utf8_value(ValueRef::Text(data)).expect("text is never NULL");
+    // Valid UTF-8 must come through untouched.
+    if let Ok(s) = std::str::from_utf8(data) {
+        assert_eq!(sanitized, s);
+    }
+    assert_eq!(
+        sanitize_utf8_value(ValueRef::Blob(data)).as_deref(),
+        Some("")
+    );
+});
@@ -0,0 +1,16 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use places::import::common::sql_fns::validate_url_value;
+use rusqlite::types::ValueRef;
+
+fuzz_target!(|data: &[u8]| {
+    // We only care that this doesn't panic - what's a valid URL is up to the
+    // `url` crate.
+    let _ = validate_url_value(ValueRef::Text(data));
+    assert_eq!(validate_url_value(ValueRef::Blob(data)), None);
+});
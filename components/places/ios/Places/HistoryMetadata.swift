@@ -14,10 +14,14 @@ public struct HistoryMetadataKey: Codable {
     public let url: String
     public let searchTerm: String?
     public let referrerUrl: String?
+    /// The tab, or other session, the page is being viewed in. Observations
+    /// from different sessions are recorded separately.
+    public let metadataSessionId: String?
 
-    public init(url: String, searchTerm: String?, referrerUrl: String?) {
+    public init(url: String, searchTerm: String?, referrerUrl: String?, metadataSessionId: String? = nil) {
         self.url = url
         self.searchTerm = searchTerm
         self.referrerUrl = referrerUrl
+        self.metadataSessionId = metadataSessionId
     }
 }
@@ -32,12 +32,29 @@ public class PlacesAPI {
     /**
      * Initialize a PlacesAPI
      *
-     * - Parameter path: an absolute path to a file that will be used for the internal database.
-     *
-     * - Throws: `PlacesApiError` if initializing the database failed.
+     * - Parameters:
+     *     - path: an absolute path to a file that will be used for the internal database.
+     *     - frecencySettings: the parameters frecencies are calculated with, for example to
+     *       tune ranking in an experiment. The defaults are used if this is nil. If the
+     *       database is already open, it keeps the settings it was opened with. They
+     *       can't be invalid, for example `numVisits` can't be negative.
+     *     - encryptionKey: if not nil, the database is encrypted with SQLCipher
+     *       using this key. An existing unencrypted database is encrypted when
+     *       it's opened.
+     *
+     * - Throws: `PlacesApiError` if initializing the database failed, or
+     *   `PlacesApiError.IncorrectDatabaseKey` if the key is wrong.
      */
-    public init(path: String) throws {
-        try api = placesApiNew(dbPath: path)
+    public init(path: String, frecencySettings: FrecencySettings? = nil, encryptionKey: String? = nil) throws {
+        if let encryptionKey = encryptionKey {
+            try api = placesApiNewWithKey(
+                dbPath: path,
+                encryptionKey: encryptionKey,
+                frecencySettings: frecencySettings
+            )
+        } else {
+            try api = placesApiNew(dbPath: path, frecencySettings: frecencySettings)
+        }
 
         let uniffiConn = try api.newConnection(connType: ConnectionType.readWrite)
         writeConn = try PlacesWriteConnection(conn: uniffiConn)
@@ -54,6 +71,9 @@ public class PlacesAPI {
      *
      * This should be performed before any writes to the database.
      *
+     * The children of the roots in `mappings` go where the mapping says, instead
+     * of in the matching local roots.
+     *
      * Throws:
      *     - `PlacesApiError.databaseInterrupted`: If a call is made to `interrupt()` on this
      *                                             object from another thread.
@@ -70,9 +90,77 @@ public class PlacesAPI {
      *     - `PlacesApiError.panic`: If the rust code panics while completing this
      *                               operation. (If this occurs, please let us know).
      */
-    open func migrateBookmarksFromBrowserDb(path: String) throws {
+    open func migrateBookmarksFromBrowserDb(
+        path: String,
+        mappings: [BookmarkImportRootMapping]? = nil
+    ) throws {
         try queue.sync {
-            try self.api.placesBookmarksImportFromIos(dbPath: path)
+            try self.api.placesBookmarksImportFromIos(dbPath: path, mappings: mappings)
+        }
+    }
+
+    /**
+     * Import the bookmarks in `path`, a file in the standard Netscape bookmarks
+     * HTML format which every browser can export. They go into a new "Imported"
+     * folder in "Other Bookmarks". Bookmarks for URLs which are already bookmarked
+     * are skipped, so importing the same file again doesn't add duplicates.
+     * Bookmarks from the roots in `mappings`, like the toolbar, go where the mapping
+     * says instead.
+     *
+     * Returns how many bookmarks were inserted and skipped.
+     *
+     * Throws:
+     *     - `PlacesApiError.unexpected`: When an error that has not specifically been exposed
+     *                                    to Swift is encountered (for example IO errors from
+     *                                    reading the file).
+     *     - `PlacesApiError.panic`: If the rust code panics while completing this
+     *                               operation. (If this occurs, please let us know).
+     */
+    open func importBookmarksFromHtml(
+        path: String,
+        mappings: [BookmarkImportRootMapping]? = nil
+    ) throws -> HtmlBookmarksImportResult {
+        return try queue.sync {
+            return try self.api.placesBookmarksImportFromHtml(path: path, mappings: mappings)
+        }
+    }
+
+    /**
+     * Import `visits`, which the app has read from a browser we don't have an
+     * importer for, all or nothing. The visits are imported as if they were
+     * made on this device. Visits to URLs we don't record in history, and visits
+     * we already have, are skipped and counted as failed.
+     *
+     * Returns the import metrics.
+     *
+     * Throws:
+     *     - `PlacesApiError.unexpected`: When an error that has not specifically been exposed
+     *                                    to Swift is encountered (for example IO errors from
+     *                                    the database code, etc).
+     *     - `PlacesApiError.panic`: If the rust code panics while completing this
+     *                               operation. (If this occurs, please let us know).
+     */
+    open func importVisits(_ visits: [ImportedVisit]) throws -> HistoryMigrationResult {
+        return try queue.sync {
+            return try self.api.placesHistoryImportVisits(visits: visits)
+        }
+    }
+
+    /**
+     * Write all bookmarks to `path` in the standard Netscape bookmarks HTML
+     * format, which other browsers can import. Keywords are included; tags
+     * aren't. The file is overwritten if it exists.
+     *
+     * Throws:
+     *     - `PlacesApiError.unexpected`: When an error that has not specifically been exposed
+     *                                    to Swift is encountered (for example IO errors from
+     *                                    writing the file).
+     *     - `PlacesApiError.panic`: If the rust code panics while completing this
+     *                               operation. (If this occurs, please let us know).
+     */
+    open func exportBookmarksToHtml(path: String) throws {
+        try queue.sync {
+            try self.api.bookmarksExportToHtml(path: path)
         }
     }
 
@@ -88,6 +176,48 @@ public class PlacesAPI {
         }
     }
 
+    /**
+     * Open a new reader connection backed by `size` database connections, so
+     * that up to `size` queries made on different threads can run at once.
+     *
+     * - Throws: `PlacesApiError` if a connection could not be opened.
+     */
+    open func openReaderPool(size: UInt32) throws -> PlacesReadConnection {
+        return try queue.sync {
+            let uniffiConn = try api.newReadOnlyPool(size: size)
+            return try PlacesReadConnection(conn: uniffiConn, api: self)
+        }
+    }
+
+    /**
+     * Open a new reader connection with a snapshot of the database: every
+     * query made on it sees the database as it was when it was opened, even
+     * if writes land in the meantime. Use this when a screen needs several
+     * queries (eg, a count and a page of results) to agree with each other.
+     *
+     * The snapshot holds a read transaction open until the connection is
+     * deallocated, so don't keep it around; `withSnapshot` makes this easy.
+     *
+     * - Throws: `PlacesApiError` if a connection could not be opened.
+     */
+    open func openSnapshot() throws -> PlacesReadConnection {
+        return try queue.sync {
+            let uniffiConn = try api.newSnapshot()
+            return try PlacesReadConnection(conn: uniffiConn, api: self)
+        }
+    }
+
+    /**
+     * Runs `body` with a reader from `openSnapshot`. The snapshot ends once
+     * `body` returns, unless it keeps a reference to the reader.
+     *
+     * - Throws: `PlacesApiError` if a connection could not be opened, or
+     *           whatever `body` throws.
+     */
+    open func withSnapshot<T>(_ body: (PlacesReadConnection) throws -> T) throws -> T {
+        return try body(openSnapshot())
+    }
+
     /**
      * Get the writer connection.
      *
@@ -104,9 +234,8 @@ public class PlacesAPI {
     /**
      * Sync the bookmarks collection.
      *
-     * - Returns: A JSON string representing a telemetry ping for this sync. The
-     *            string contains the ping payload, and should be sent to the
-     *            telemetry submission endpoint.
+     * - Returns: The telemetry ping for this sync, which should be used to build
+     *            the payload sent to the telemetry submission endpoint.
      *
      * - Throws:
      *     - `PlacesApiError.databaseInterrupted`: If a call is made to `interrupt()` on this
@@ -117,7 +246,7 @@ public class PlacesAPI {
      *     - `PlacesApiError.panic`: If the rust code panics while completing this
      *                               operation. (If this occurs, please let us know).
      */
-    open func syncBookmarks(unlockInfo: SyncUnlockInfo) throws -> String {
+    open func syncBookmarks(unlockInfo: SyncUnlockInfo) throws -> SyncTelemetryPing {
         return try queue.sync {
             return try self.api.bookmarksSync(
                 keyId: unlockInfo.kid,
@@ -131,9 +260,8 @@ public class PlacesAPI {
     /**
      * Sync the History collection.
      *
-     * - Returns: A JSON string representing a telemetry ping for this sync. The
-     *            string contains the ping payload, and should be sent to the
-     *            telemetry submission endpoint.
+     * - Returns: The telemetry ping for this sync, which should be used to build
+     *            the payload sent to the telemetry submission endpoint.
      *
      * - Throws:
      *     - `PlacesApiError.databaseInterrupted`: If a call is made to `interrupt()` on this
@@ -144,7 +272,7 @@ public class PlacesAPI {
      *     - `PlacesApiError.panic`: If the rust code panics while completing this
      *                               operation. (If this occurs, please let us know).
      */
-    open func syncHistory(unlockInfo: SyncUnlockInfo) throws -> String {
+    open func syncHistory(unlockInfo: SyncUnlockInfo) throws -> SyncTelemetryPing {
         return try queue.sync {
             return try self.api.historySync(
                 keyId: unlockInfo.kid,
@@ -199,6 +327,28 @@ public class PlacesAPI {
             return try self.api.bookmarksReset()
         }
     }
+
+    /**
+     * Registers an observer for changes to history and bookmarks made by any
+     * connection, including sync, replacing any existing one.
+     *
+     * The observer is called on the thread which made the changes, once
+     * they've been committed.
+     */
+    open func setChangeObserver(observer: PlacesChangeObserver) {
+        queue.sync {
+            self.api.setChangeObserver(observer: observer)
+        }
+    }
+
+    /**
+     * Removes the observer registered with `setChangeObserver`.
+     */
+    open func clearChangeObserver() {
+        queue.sync {
+            self.api.clearChangeObserver()
+        }
+    }
 }
 
 /**
@@ -300,6 +450,82 @@ public class PlacesReadConnection {
         }
     }
 
+    /**
+     * Returns a page of the children of a folder, in order.
+     *
+     * Unlike `getBookmarksTree`, this only loads the requested children, so it can be
+     * used to lazily render huge folders. Any folders in the page will have neither
+     * their `children` nor their `childGUIDs` populated.
+     *
+     * - Parameter folderGUID: the guid of the folder.
+     * - Parameter offset: the position of the first child to return.
+     * - Parameter limit: the maximum number of children to return.
+     *
+     * - Returns: The children, or null if the provided guid didn't refer to a
+     *            known bookmark item.
+     * - Throws:
+     *     - `PlacesApiError.invalidBookmarkOperation`: If `folderGUID` doesn't refer to a folder.
+     *     - `PlacesApiError.databaseInterrupted`: If a call is made to `interrupt()` on this
+     *                                             object from another thread.
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     *     - `PlacesApiError.databaseBusy`: If this query times out with a SQLITE_BUSY error.
+     *     - `PlacesApiError.unexpected`: When an error that has not specifically been exposed
+     *                                    to Swift is encountered (for example IO errors from
+     *                                    the database code, etc).
+     *     - `PlacesApiError.panic`: If the rust code panics while completing this
+     *                               operation. (If this occurs, please let us know).
+     */
+    open func getBookmarksChildrenPage(folderGUID: Guid, offset: UInt32, limit: UInt32) throws -> [BookmarkNodeData]? {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksGetChildrenPage(folderGuid: folderGUID, offset: offset, limit: limit)?
+                .map { $0.asBookmarkNodeData }
+        }
+    }
+
+    /**
+     * Counts the bookmarks in a folder and all its subfolders, without
+     * loading the tree. Folders and separators aren't counted.
+     *
+     * - Returns: The number of bookmarks, or nil if `folderGUID` doesn't
+     *            refer to a known bookmark item.
+     *
+     * - Throws:
+     *     - `PlacesApiError.invalidBookmarkOperation`: If `folderGUID`
+     *                                                  refers to something
+     *                                                  other than a folder.
+     */
+    open func countBookmarksInFolder(folderGUID: Guid) throws -> UInt32? {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksCountByParent(folderGuid: folderGUID)
+        }
+    }
+
+    /**
+     * Counts all the bookmarks in the tree. Folders and separators aren't
+     * counted.
+     */
+    open func countAllBookmarks() throws -> UInt32 {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksCountAll()
+        }
+    }
+
+    /**
+     * Returns the number of items, folders and separators in the tree, and
+     * how deeply the deepest item is nested, without loading the tree.
+     */
+    open func getBookmarksStatistics() throws -> BookmarksStatistics {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksGetStatistics()
+        }
+    }
+
     /**
      * Returns the list of bookmarks with the provided URL.
      *
@@ -423,6 +649,61 @@ public class PlacesReadConnection {
         }
     }
 
+    /**
+     * Returns every item an importer created from `source`, so that users can
+     * review what an import added. Folders are returned without their children.
+     */
+    open func getImportedBookmarks(source: BookmarkImportSource) throws -> [BookmarkNodeData] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksGetImported(source: source).map { $0.asBookmarkNodeData }
+        }
+    }
+
+    /**
+     * Returns the URLs which are bookmarked more than once, with their
+     * bookmarks, oldest first.
+     */
+    open func findDuplicateBookmarks() throws -> [DuplicateBookmarks] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksFindDuplicates()
+        }
+    }
+
+    /**
+     * Returns the bookmarks which were left out of the last sync because their
+     * records are larger than the server accepts, so that users can find and
+     * fix them. They'll be uploaded on a later sync once they're small enough.
+     */
+    open func getBookmarksTooLargeToSync() throws -> [BookmarkTooLargeToSync] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksGetTooLargeToSync()
+        }
+    }
+
+    /**
+     * Returns the tags for a URL, most recently modified first. Tags belong to
+     * URLs, rather than bookmarks, so every bookmark for `url` has the same tags.
+     */
+    open func getTagsForUrl(url: Url) throws -> [String] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksGetTagsForUrl(url: url)
+        }
+    }
+
+    /**
+     * Returns the URLs tagged with `tag`, ordered by frecency.
+     */
+    open func getUrlsWithTag(tag: String) throws -> [Url] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksGetUrlsWithTag(tag: tag)
+        }
+    }
+
     open func getLatestHistoryMetadataForUrl(url: Url) throws -> HistoryMetadata? {
         return try queue.sync {
             try self.checkApi()
@@ -444,6 +725,13 @@ public class PlacesReadConnection {
         }
     }
 
+    open func getHistoryMetadataGroupedBySearchTerm(start: Int64, end: Int64) throws -> [HistoryMetadataGroup] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getHistoryMetadataGroupedBySearchTerm(start: start, end: end)
+        }
+    }
+
     open func getHighlights(weights: HistoryHighlightWeights, limit: Int32) throws -> [HistoryHighlight] {
         return try queue.sync {
             try self.checkApi()
@@ -458,6 +746,13 @@ public class PlacesReadConnection {
         }
     }
 
+    open func getNavigationChain(url: Url, depth: UInt32) throws -> [HistoryMetadata] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getNavigationChain(url: url, depth: depth)
+        }
+    }
+
     // MARK: History Read APIs
 
     open func matchUrl(query: String) throws -> Url? {
@@ -467,10 +762,60 @@ public class PlacesReadConnection {
         }
     }
 
-    open func queryAutocomplete(search: String, limit: Int32) throws -> [SearchResult] {
+    open func getZeroPrefixSuggestions(limit: UInt32) throws -> [ZeroPrefixSuggestion] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getZeroPrefixSuggestions(limit: limit)
+        }
+    }
+
+    open func getTypedUrls(limit: UInt32) throws -> [TypedUrlInfo] {
         return try queue.sync {
             try self.checkApi()
-            return try self.conn.queryAutocomplete(search: search, limit: limit)
+            return try self.conn.getTypedUrls(limit: limit)
+        }
+    }
+
+    /**
+     * Returns the origins with the highest frecency, each with the pages the
+     * user most often went to next from one of its pages, for speculative
+     * preconnects and prefetches.
+     */
+    open func getTopSitesPrefetchHints(limit: UInt32) throws -> [TopSitePrefetchHint] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getTopSitesPrefetchHints(limit: limit)
+        }
+    }
+
+    /**
+     * If `restrictionTokens` is given, tokens in `search` like `*` restrict
+     * the results to bookmarks, tags, history and so on, like Desktop's URL
+     * bar. They're ignored otherwise.
+     */
+    open func queryAutocomplete(
+        search: String,
+        limit: Int32,
+        restrictionTokens: RestrictionTokens? = nil
+    ) throws -> [SearchResult] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.queryAutocomplete(
+                search: search,
+                limit: limit,
+                restrictionTokens: restrictionTokens
+            )
+        }
+    }
+
+    /**
+     * Searches the titles and URLs of visited pages, for a full history search UI.
+     * Every word in `query` must match, and results are ranked as `options` asks.
+     */
+    open func searchHistory(query: String, options: HistorySearchOptions) throws -> [HistorySearchResult] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.searchHistory(query: query, options: options)
         }
     }
 
@@ -483,19 +828,122 @@ public class PlacesReadConnection {
         }
     }
 
-    open func getVisitInfos(start: PlacesTimestamp, end: PlacesTimestamp, excludeTypes: VisitTransitionSet)
+    /**
+     * The `getVisit*` functions return visits in every container, unless
+     * they're given a `containerId`, when they only return visits made in
+     * that container.
+     */
+    open func getVisitInfos(
+        start: PlacesTimestamp,
+        end: PlacesTimestamp,
+        excludeTypes: VisitTransitionSet,
+        containerId: String? = nil
+    )
         throws -> [HistoryVisitInfo]
     {
         return try queue.sync {
             try self.checkApi()
-            return try self.conn.getVisitInfos(startDate: start, endDate: end, excludeTypes: excludeTypes)
+            return try self.conn.getVisitInfos(
+                startDate: start, endDate: end, excludeTypes: excludeTypes, containerId: containerId
+            )
+        }
+    }
+
+    /**
+     * Like `getVisitInfos`, but returns the visits oldest first, a chunk at a
+     * time, as `nextChunk` is called on the cursor. Interrupting reads on this
+     * connection stops the chunk being read, and the cursor can be stopped
+     * early with `finish`.
+     */
+    open func openVisitCursor(
+        start: PlacesTimestamp,
+        end: PlacesTimestamp,
+        excludeTypes: VisitTransitionSet,
+        containerId: String? = nil
+    )
+        throws -> VisitCursor
+    {
+        return try queue.sync {
+            try self.checkApi()
+            return self.conn.openVisitCursor(
+                startDate: start, endDate: end, excludeTypes: excludeTypes, containerId: containerId
+            )
+        }
+    }
+
+    open func getVisitCount(excludedTypes: VisitTransitionSet, containerId: String? = nil) throws -> Int64 {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getVisitCount(excludeTypes: excludedTypes, containerId: containerId)
+        }
+    }
+
+    /**
+     * Returns the number of visits on each day between `start` and `end`,
+     * for drawing activity graphs. Days without any visits are left out.
+     * `timezoneOffset` is the user's offset from UTC, in minutes east, which
+     * decides the day each visit falls on; for example,
+     * `TimeZone.current.secondsFromGMT() / 60`.
+     */
+    open func getVisitCountPerDay(
+        start: PlacesTimestamp,
+        end: PlacesTimestamp,
+        timezoneOffset: Int32
+    ) throws -> [VisitCountForDay] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getVisitCountPerDay(start: start, end: end, timezoneOffset: timezoneOffset)
+        }
+    }
+
+    /**
+     * Returns coarse attributes about the user's bookmarks and history, such
+     * as the number of bookmarks, for targeting experiments. Counts are
+     * rounded down to one of a handful of buckets.
+     */
+    open func getTargetingAttributes() throws -> PlacesTargetingAttributes {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getTargetingAttributes()
+        }
+    }
+
+    /**
+     * Returns the number of visits, visited pages, bookmarks and history
+     * metadata entries, along with the size of the database, for a "manage
+     * data" screen.
+     */
+    open func getDataSummary() throws -> PlacesDataSummary {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getDataSummary()
         }
     }
 
-    open func getVisitCount(excludedTypes: VisitTransitionSet) throws -> Int64 {
+    /**
+     * Returns the sequence number of the last change to a page or bookmark,
+     * for apps which keep their own index of them, like the Spotlight index.
+     * Note it before indexing everything, and then pass it to
+     * `getChangesSince`.
+     */
+    open func getCurrentChangeSeq() throws -> Int64 {
         return try queue.sync {
             try self.checkApi()
-            return try self.conn.getVisitCount(excludeTypes: excludedTypes)
+            return try self.conn.getCurrentChangeSeq()
+        }
+    }
+
+    /**
+     * Returns the pages and bookmarks which changed after `seq`, and the
+     * sequence number to pass next time, so that an index of them can be
+     * updated without indexing everything again. Deleted items are forgotten
+     * 90 days after they're deleted, so an index which hasn't been updated
+     * for longer than that should be rebuilt.
+     */
+    open func getChangesSince(seq: Int64) throws -> ChangesSince {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getChangesSince(seq: seq)
         }
     }
 
@@ -503,18 +951,33 @@ public class PlacesReadConnection {
         bound: Int64,
         offset: Int64,
         count: Int64,
-        excludedTypes: VisitTransitionSet
+        excludedTypes: VisitTransitionSet,
+        containerId: String? = nil
     )
         throws -> HistoryVisitInfosWithBound
     {
         return try queue.sync {
             try self.checkApi()
             return try self.conn.getVisitPageWithBound(
-                bound: bound, offset: offset, count: count, excludeTypes: excludedTypes
+                bound: bound, offset: offset, count: count, excludeTypes: excludedTypes,
+                containerId: containerId
             )
         }
     }
 
+    /**
+     * Returns every visit to `url`, most recent first, along with the chain of
+     * visits which led to each one, so that the app can show how the user
+     * arrived there. The chain follows each visit's referrer, including
+     * redirect sources.
+     */
+    open func getVisitDetails(url: Url) throws -> [HistoryVisitDetails] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getVisitDetails(url: url)
+        }
+    }
+
     open func getVisited(urls: [String]) throws -> [Bool] {
         return try queue.sync {
             try self.checkApi()
@@ -522,18 +985,46 @@ public class PlacesReadConnection {
         }
     }
 
-    open func getTopFrecentSiteInfos(numItems: Int32, thresholdOption: FrecencyThresholdOption)
-        throws -> [TopFrecentSiteInfo]
-    {
+    open func getTopFrecentSiteInfos(
+        numItems: Int32,
+        thresholdOption: FrecencyThresholdOption,
+        options: TopFrecentSiteOptions? = nil
+    ) throws -> [TopFrecentSiteInfo] {
         return try queue.sync {
             try self.checkApi()
             return try self.conn.getTopFrecentSiteInfos(
                 numItems: numItems,
-                thresholdOption: thresholdOption
+                thresholdOption: thresholdOption,
+                options: options
             )
         }
     }
 
+    /**
+     * Returns the hosts the user has history or bookmarks for which start
+     * with `prefix`, with or without a leading `www.`, most visited first.
+     * An empty prefix returns every host. This is for settings, where the
+     * user picks sites to add exceptions for.
+     */
+    open func searchHosts(prefix: String, limit: UInt32) throws -> [HostInfo] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.searchHosts(prefix: prefix, limit: limit)
+        }
+    }
+
+    /**
+     * Returns the sites the user pinned to their top sites, in the order
+     * they were pinned. Pinned sites aren't bookmarks, so they aren't
+     * returned by the bookmarks API.
+     */
+    open func getPinnedSites() throws -> [PinnedSite] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getPinnedSites()
+        }
+    }
+
     /**
      * Attempt to interrupt a long-running operation which may be
      * happening concurrently. If the operation is interrupted,
@@ -546,6 +1037,25 @@ public class PlacesReadConnection {
     open func interrupt() {
         interruptHandle.interrupt()
     }
+
+    /**
+     * Like `interrupt()`, but only interrupts operations with `priority` or
+     * lower. For example, interrupting at `.background` cancels maintenance
+     * and imports, but not autocomplete queries.
+     */
+    open func interrupt(priority: InterruptPriority) {
+        interruptHandle.interruptAtOrBelow(priority: priority)
+    }
+
+    /**
+     * Free the memory held by this connection's caches, for example when
+     * the app gets a memory warning. Like `interrupt`, this doesn't wait for
+     * operations running on other threads; connections which are busy are
+     * skipped.
+     */
+    open func onMemoryPressure() throws {
+        try conn.onMemoryPressure()
+    }
 }
 
 /**
@@ -572,6 +1082,9 @@ public class PlacesWriteConnection: PlacesReadConnection {
      *   that either the disk or memory is constrained then it halves the amount.
      *   The default of 0 disables pruning.
      *
+     * - Returns: What each step of maintenance did, and how long it took, so
+     *   that callers can log it, and adjust how often they run maintenance.
+     *
      * - Throws:
      *     - `PlacesConnectionError.connUseAfterAPIClosed`: if the PlacesAPI that returned this connection
      *                                                      object has been closed. This indicates API
@@ -583,13 +1096,118 @@ public class PlacesWriteConnection: PlacesReadConnection {
      *                               operation. (If this occurs, please let us know).
      *
      */
-    open func runMaintenance(dbSizeLimit: UInt32 = 0) throws {
+    @discardableResult
+    open func runMaintenance(dbSizeLimit: UInt32 = 0) throws -> MaintenanceResult {
+        return try queue.sync {
+            try self.checkApi()
+            return try MaintenanceResult(
+                prune: self.conn.runMaintenancePrune(dbSizeLimit: dbSizeLimit),
+                vacuum: self.conn.runMaintenanceVacuum(),
+                optimize: self.conn.runMaintenanceOptimize(),
+                checkpoint: self.conn.runMaintenanceCheckpoint()
+            )
+        }
+    }
+
+    /**
+     * Register what `runMaintenance` should delete each time it runs, for
+     * "automatically delete browsing data" settings. The policy is stored in
+     * the database, so it is still enforced if the app is killed before it
+     * can clean up. The deletions only apply to this device, and aren't
+     * synced. Pass `nil` to stop deleting anything.
+     *
+     * Things are only deleted once they're older than the policy's
+     * `maxAgeDays`. To delete everything each time, eg, for "delete browsing
+     * data on quit", set it to 0; if it's `nil`, nothing is deleted.
+     */
+    open func setHistoryDeletionPolicy(policy: HistoryDeletionPolicy?) throws {
+        return try queue.sync {
+            try self.checkApi()
+            try self.conn.setHistoryDeletionPolicy(policy: policy)
+        }
+    }
+
+    open func getHistoryDeletionPolicy() throws -> HistoryDeletionPolicy? {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getHistoryDeletionPolicy()
+        }
+    }
+
+    /**
+     * Recalculates stale frecencies, like the ones left behind by an import,
+     * in chunks of `chunkSize` pages, until there are none left or
+     * `maxDurationMs` has passed. Each chunk is committed separately, so this
+     * is suitable for a background task; calling `interrupt()` stops it
+     * between chunks.
+     */
+    open func runPendingFrecencyUpdates(chunkSize: UInt32, maxDurationMs: UInt32) throws -> FrecencyUpdateMetrics {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.runPendingFrecencyUpdates(chunkSize: chunkSize, maxDurationMs: maxDurationMs)
+        }
+    }
+
+    /**
+     * Re-encrypts a database opened with an `encryptionKey` with `newKey`.
+     * All the readers have to be closed first, or this throws
+     * `PlacesApiError.PlacesConnectionBusy`. Readers opened afterwards use
+     * the new key.
+     */
+    open func rekeyDatabase(newKey: String) throws {
+        return try queue.sync {
+            try self.checkApi()
+            try self.conn.rekeyDatabase(newKey: newKey)
+        }
+    }
+
+    /**
+     * Returns pages which are missing `kind` metadata, most frecent first, so
+     * that the app can fetch it in the background. Pages which were marked as
+     * attempted with `markPageMetadataAttempted` in the last week aren't
+     * returned. Places doesn't store icons, so every page is missing an icon
+     * until it is marked as attempted.
+     */
+    open func getPagesMissingMetadata(kind: PageMetadataKind, limit: UInt32) throws -> [Url] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getPagesMissingMetadata(kind: kind, limit: limit)
+        }
+    }
+
+    /**
+     * Records that the app tried to fetch `kind` metadata for these pages,
+     * whether or not it succeeded.
+     */
+    open func markPageMetadataAttempted(kind: PageMetadataKind, urls: [Url]) throws {
+        return try queue.sync {
+            try self.checkApi()
+            try self.conn.markPageMetadataAttempted(kind: kind, urls: urls)
+        }
+    }
+
+    /**
+     * Pins a site to the user's top sites, after the other pinned sites.
+     * Pinning a site which is already pinned only changes its title. Pinned
+     * sites are kept when history is deleted.
+     */
+    open func pinSite(url: Url, title: String?) throws {
+        return try queue.sync {
+            try self.checkApi()
+            try self.conn.pinSite(url: url, title: title)
+        }
+    }
+
+    /**
+     * Unpins a site.
+     *
+     * - Returns: false if the site wasn't pinned.
+     */
+    @discardableResult
+    open func unpinSite(url: Url) throws -> Bool {
         return try queue.sync {
             try self.checkApi()
-            try self.conn.runMaintenancePrune(dbSizeLimit: dbSizeLimit)
-            try self.conn.runMaintenanceVacuum()
-            try self.conn.runMaintenanceOptimize()
-            try self.conn.runMaintenanceCheckpoint()
+            return try self.conn.unpinSite(url: url)
         }
     }
 
@@ -622,6 +1240,91 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 
+    /**
+     * Restore a deleted bookmark, and its children, from the trash. It goes
+     * back where it was, or into "Other Bookmarks" if its folder has been
+     * deleted too.
+     *
+     * - Returns: The guid the bookmark was restored with, which is only
+     *            different from `guid` if it's been taken since, or nil if
+     *            the bookmark isn't in the trash.
+     */
+    @discardableResult
+    open func restoreBookmarkNode(guid: Guid) throws -> Guid? {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksRestore(guid: guid)
+        }
+    }
+
+    /**
+     * Permanently remove the bookmarks deleted before `olderThan` from the
+     * trash.
+     *
+     * - Returns: The number of bookmarks removed.
+     */
+    @discardableResult
+    open func purgeBookmarksTrash(olderThan: PlacesTimestamp) throws -> UInt32 {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksPurgeTrash(olderThan: olderThan)
+        }
+    }
+
+    /**
+     * Tags a URL. The tag is synced with every bookmark for the URL.
+     *
+     * - Throws:
+     *     - `PlacesApiError.invalidBookmarkOperation`: If `url` isn't in places,
+     *                                                  or `tag` is empty or too long.
+     */
+    open func tagUrl(url: Url, tag: String) throws {
+        try queue.sync {
+            try self.checkApi()
+            try self.conn.bookmarksTagUrl(url: url, tag: tag)
+        }
+    }
+
+    /**
+     * Removes a tag from a URL. Does nothing if the URL doesn't have the tag.
+     */
+    open func untagUrl(url: Url, tag: String) throws {
+        try queue.sync {
+            try self.checkApi()
+            try self.conn.bookmarksUntagUrl(url: url, tag: tag)
+        }
+    }
+
+    /**
+     * Delete the bookmarks and separators an importer created from `source`,
+     * and then the imported folders which that leaves empty. Folders the user
+     * has since added to are kept.
+     *
+     * - Returns: The number of items deleted.
+     */
+    @discardableResult
+    open func deleteImportedBookmarks(source: BookmarkImportSource) throws -> UInt32 {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksDeleteImported(source: source)
+        }
+    }
+
+    /**
+     * Keep one bookmark for each URL which is bookmarked more than once, and
+     * move the others to the trash, all or nothing. The folders they were in
+     * are synced.
+     *
+     * - Returns: The number of bookmarks deleted.
+     */
+    @discardableResult
+    open func mergeDuplicateBookmarks(strategy: DuplicateBookmarksStrategy) throws -> UInt32 {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksMergeDuplicates(strategy: strategy)
+        }
+    }
+
     /**
      * Create a bookmark folder, returning its guid.
      *
@@ -742,6 +1445,38 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 
+    /**
+     * Insert a tree of bookmarks, folders and separators, such as one restored
+     * from a backup, in a single transaction. Items keep the guids they have in
+     * the tree, unless they're invalid or already in use, in which case they get
+     * new ones.
+     *
+     * - Parameter parentGUID: The GUID of the folder to insert the tree into.
+     *
+     * - Parameter tree: The items to insert. Their parent guids are ignored.
+     *
+     * - Returns: The GUID each item was inserted with, in the order they appear
+     *            in the tree, with the children of a folder following the folder.
+     *
+     * - Throws:
+     *     - `PlacesApiError.cannotUpdateRoot`: If `parentGUID` is `BookmarkRoots.RootGUID`.
+     *     - `PlacesApiError.noSuchItem`: If `parentGUID` does not refer to a known bookmark.
+     *     - `PlacesApiError.invalidParent`: If `parentGUID` refers to a bookmark which is
+     *                                       not a folder.
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: if the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     */
+    @discardableResult
+    open func insertBookmarkTree(parentGUID: Guid,
+                                 tree: InsertableBookmarkTree) throws -> [BookmarkGuidMapping]
+    {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksInsertTree(parentGuid: parentGUID, tree: tree)
+        }
+    }
+
     /**
      * Update a bookmark to the provided info.
      *
@@ -836,7 +1571,8 @@ public class PlacesWriteConnection: PlacesReadConnection {
             url: key.url,
             referrerUrl: key.referrerUrl,
             searchTerm: key.searchTerm,
-            viewTime: viewTime
+            viewTime: viewTime,
+            metadataSessionId: key.metadataSessionId
         )
         try noteHistoryMetadataObservation(observation: obs)
     }
@@ -846,7 +1582,8 @@ public class PlacesWriteConnection: PlacesReadConnection {
             url: key.url,
             referrerUrl: key.referrerUrl,
             searchTerm: key.searchTerm,
-            documentType: documentType
+            documentType: documentType,
+            metadataSessionId: key.metadataSessionId
         )
         try noteHistoryMetadataObservation(observation: obs)
     }
@@ -856,7 +1593,8 @@ public class PlacesWriteConnection: PlacesReadConnection {
             url: key.url,
             referrerUrl: key.referrerUrl,
             searchTerm: key.searchTerm,
-            title: title
+            title: title,
+            metadataSessionId: key.metadataSessionId
         )
         try noteHistoryMetadataObservation(observation: obs)
     }
@@ -868,6 +1606,17 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 
+    /**
+     * Deletes the metadata of every page the user came to from a search for
+     * `searchTerm`, which is matched ignoring case.
+     */
+    open func deleteHistoryMetadataForSearchTerm(searchTerm: String) throws {
+        try queue.sync {
+            try self.checkApi()
+            try self.conn.metadataDeleteBySearchTerm(searchTerm: searchTerm)
+        }
+    }
+
     open func deleteHistoryMetadata(key: HistoryMetadataKey) throws {
         try queue.sync {
             try self.checkApi()
@@ -888,6 +1637,40 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 
+    /**
+     * Like `deleteVisitsFor(url:)`, but deletes history for all of `urls` at
+     * once, which is much faster than deleting them one at a time.
+     */
+    open func deleteVisitsFor(urls: [Url]) throws {
+        try queue.sync {
+            try self.checkApi()
+            try self.conn.deleteVisitsForUrls(urls: urls)
+        }
+    }
+
+    /**
+     * Deletes all history for pages on `host` and its subdomains, whatever
+     * their scheme or port. Like `deleteVisitsFor`, the deletions are synced,
+     * and bookmarked pages keep everything but their visits and history metadata.
+     */
+    open func deleteVisitsForHost(host: String) throws {
+        try queue.sync {
+            try self.checkApi()
+            try self.conn.deleteVisitsForHost(host: host)
+        }
+    }
+
+    /**
+     * Like `deleteVisitsForHost`, but only deletes history for pages with the
+     * same scheme, host and port as `origin`.
+     */
+    open func deleteEverythingForOrigin(origin: Url) throws {
+        try queue.sync {
+            try self.checkApi()
+            try self.conn.deleteEverythingForOrigin(origin: origin)
+        }
+    }
+
     open func deleteVisitsBetween(start: PlacesTimestamp, end: PlacesTimestamp) throws {
         try queue.sync {
             try self.checkApi()
@@ -916,6 +1699,19 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 
+    /**
+     * Deletes all history. With `.localOnly`, this is the same as
+     * `deleteEverythingHistory()`: nothing is uploaded, and the local Sync
+     * state is reset. With `.propagate`, the deletions are uploaded on the
+     * next sync, so the history is removed from other devices too.
+     */
+    open func deleteEverythingHistory(mode: HistoryDeletionSyncMode) throws {
+        try queue.sync {
+            try self.checkApi()
+            try self.conn.deleteEverythingHistoryWithSyncMode(mode: mode)
+        }
+    }
+
     open func pruneDestructively() throws {
         try queue.sync {
             try self.checkApi()
@@ -930,6 +1726,17 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 
+    /**
+     * Forgets the matches recorded with `acceptResult`, which rank the
+     * autocomplete matches for what the user typed.
+     */
+    open func deleteAdaptiveHistory() throws {
+        try queue.sync {
+            try self.checkApi()
+            try self.conn.deleteAdaptiveHistory()
+        }
+    }
+
     open func applyObservation(visitObservation: VisitObservation) throws {
         return try queue.sync {
             try self.checkApi()
@@ -937,6 +1744,32 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 
+    /// Applies all the observations in a single transaction, which is much
+    /// faster than calling `applyObservation` for each. Returns the number of
+    /// visits added.
+    open func applyObservations(visitObservations: [VisitObservation]) throws -> UInt32 {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.applyObservations(visits: visitObservations)
+        }
+    }
+
+    /**
+     * Sets the preview image of a page which is already in history. To set it
+     * while recording a visit, set `previewImageUrl` on the
+     * `VisitObservation` instead.
+     *
+     * - Returns: false if the page isn't in history, or the preview image URL
+     *            is too long to store.
+     */
+    @discardableResult
+    open func updatePreviewImage(url: Url, previewImageUrl: Url) throws -> Bool {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.updatePreviewImage(url: url, previewImageUrl: previewImageUrl)
+        }
+    }
+
     open func migrateHistoryFromBrowserDb(path: String, lastSyncTimestamp: Int64) throws -> HistoryMigrationResult {
         return try queue.sync {
             try self.checkApi()
@@ -944,3 +1777,16 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 }
+
+/**
+ * What each step of `PlacesWriteConnection.runMaintenance` did.
+ */
+public struct MaintenanceResult {
+    /// How many visits and pages were pruned, and the size of the database
+    /// before and after.
+    public let prune: RunMaintenanceMetrics
+    /// How much unused space vacuuming returned to the file system.
+    public let vacuum: MaintenanceStepMetrics
+    public let optimize: MaintenanceStepMetrics
+    public let checkpoint: MaintenanceStepMetrics
+}
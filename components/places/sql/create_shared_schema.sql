@@ -58,6 +58,16 @@ CREATE TABLE IF NOT EXISTS moz_places_stale_frecencies (
     stale_at INTEGER NOT NULL -- In milliseconds.
 );
 
+-- Remembers when the app last tried to fetch a kind of metadata (a title, an
+-- icon, etc) for a page, so that we don't keep asking it to fetch metadata it
+-- can't get. `kind` is a `PageMetadataKind`.
+CREATE TABLE IF NOT EXISTS moz_places_metadata_fetch_attempts (
+    place_id INTEGER NOT NULL REFERENCES moz_places(id) ON DELETE CASCADE,
+    kind INTEGER NOT NULL,
+    attempted_at INTEGER NOT NULL, -- In milliseconds.
+    PRIMARY KEY(place_id, kind)
+) WITHOUT ROWID;
+
 
 CREATE TABLE IF NOT EXISTS moz_historyvisits (
     id INTEGER PRIMARY KEY,
@@ -67,6 +77,9 @@ CREATE TABLE IF NOT EXISTS moz_historyvisits (
     visit_date INTEGER NOT NULL,
     visit_type INTEGER NOT NULL,
     -- session INTEGER, -- XXX - what is 'session'? Appears unused.
+    -- The contextual identity (eg, "work" or "personal") the visit was made
+    -- in, or NULL for the default one. Not synced.
+    container_id TEXT,
 
     FOREIGN KEY(place_id) REFERENCES moz_places(id) ON DELETE CASCADE,
     FOREIGN KEY(from_visit) REFERENCES moz_historyvisits(id)
@@ -122,7 +135,10 @@ CREATE TABLE IF NOT EXISTS moz_bookmarks (
 );
 
 -- CREATE INDEX IF NOT EXISTS itemindex ON moz_bookmarks(fk, type);
--- CREATE INDEX IF NOT EXISTS parentindex ON moz_bookmarks(parent, position);
+-- Without this, every query for the children of a folder, and every update
+-- to their positions, scans the whole table, which is very slow for folders
+-- with thousands of children.
+CREATE INDEX IF NOT EXISTS parentindex ON moz_bookmarks(parent, position);
 CREATE INDEX IF NOT EXISTS itemlastmodifiedindex ON moz_bookmarks(fk, lastModified);
 -- CREATE INDEX IF NOT EXISTS dateaddedindex ON moz_bookmarks(dateAdded);
 CREATE UNIQUE INDEX IF NOT EXISTS guid_uniqueindex ON moz_bookmarks(guid);
@@ -238,6 +254,60 @@ CREATE TABLE IF NOT EXISTS moz_keywords(
     keyword TEXT NOT NULL UNIQUE
 );
 
+-- Remembers which importer created a bookmark, so that users can review or
+-- remove everything an import added. `source` is a `BookmarkImportSource`.
+-- Bookmarks created any other way aren't in this table.
+CREATE TABLE IF NOT EXISTS moz_bookmarks_import_sources(
+    bookmark_id INTEGER PRIMARY KEY REFERENCES moz_bookmarks(id)
+                        ON DELETE CASCADE,
+    source INTEGER NOT NULL
+);
+
+-- Bookmarks which were left out of the last sync, because their records are
+-- larger than the server accepts. Rebuilt every time we sync.
+CREATE TABLE IF NOT EXISTS moz_bookmarks_too_large_to_sync(
+    bookmark_id INTEGER PRIMARY KEY REFERENCES moz_bookmarks(id)
+                        ON DELETE CASCADE,
+    payload_size INTEGER NOT NULL -- Estimated, in bytes.
+);
+
+-- Bookmarks the user deleted, so that they can be restored until they're
+-- purged. `guid` is the deleted item, and `tree` is the item and its
+-- descendants, as JSON. Deleted items are removed from `moz_bookmarks` as
+-- usual, so nothing else, including Sync, needs to know about this table.
+CREATE TABLE IF NOT EXISTS moz_bookmarks_trash(
+    guid TEXT PRIMARY KEY,
+    parent_guid TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    tree TEXT NOT NULL,
+    date_deleted INTEGER NOT NULL -- In milliseconds.
+);
+
+-- The pages and bookmarks which have changed, in the order they changed, for
+-- apps which keep their own index of them (like the iOS Spotlight index).
+-- Each item only has one row: changing it again moves it to the end, with a
+-- new `seq`. `kind` is 1 for pages and 2 for bookmarks. Maintenance forgets
+-- deleted items after a while, using `date_changed`.
+CREATE TABLE IF NOT EXISTS moz_changes(
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    guid TEXT NOT NULL,
+    kind INTEGER NOT NULL,
+    is_deleted INTEGER NOT NULL,
+    date_changed INTEGER NOT NULL DEFAULT 0, -- In milliseconds.
+    UNIQUE(guid, kind)
+);
+
+-- Sites the user pinned to their top sites. These used to be stored as
+-- bookmarks in special folders, like Fennec did, but they aren't bookmarks and
+-- aren't synced.
+CREATE TABLE IF NOT EXISTS moz_pinned_sites(
+    place_id INTEGER PRIMARY KEY REFERENCES moz_places(id)
+                     ON DELETE RESTRICT,
+    title TEXT,
+    position INTEGER NOT NULL,
+    date_added INTEGER NOT NULL -- In milliseconds.
+);
+
 ----------------------------------------------------------------------
 --------------------History Metadata----------------------------------
 ----------------------------------------------------------------------
@@ -258,6 +328,14 @@ CREATE TABLE IF NOT EXISTS moz_places_metadata (
     typing_time INTEGER NOT NULL DEFAULT 0,
     key_presses INTEGER NOT NULL DEFAULT 0,
 
+    -- For syncing. `guid` is only NULL for a moment, while migrating.
+    guid TEXT,
+    sync_change_counter INTEGER NOT NULL DEFAULT 1,
+
+    -- The tab, or other session, the app observed the page in, so that
+    -- observations from different tabs accumulate separately. Not synced.
+    session_id TEXT,
+
     FOREIGN KEY(place_id) REFERENCES moz_places(id) ON DELETE CASCADE,
     FOREIGN KEY(search_query_id) REFERENCES moz_places_metadata_search_queries(id) ON DELETE CASCADE,
     FOREIGN KEY(referrer_place_id) REFERENCES moz_places(id) ON DELETE CASCADE
@@ -269,3 +347,8 @@ CREATE TABLE IF NOT EXISTS moz_places_metadata_search_queries (
     id INTEGER PRIMARY KEY,
     term TEXT NOT NULL UNIQUE
 );
+
+-- Metadata the user deleted, which we need to delete from the server.
+CREATE TABLE IF NOT EXISTS moz_places_metadata_tombstones (
+    guid TEXT PRIMARY KEY
+) WITHOUT ROWID;
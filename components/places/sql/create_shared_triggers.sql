@@ -60,6 +60,10 @@ BEGIN
                                          WHERE place_id = OLD.place_id AND NOT(is_local)
                                          ORDER BY visit_date DESC LIMIT 1), 0)
     WHERE id = OLD.place_id;
+    -- Visits which came from this one no longer know where they came from.
+    UPDATE moz_historyvisits SET
+        from_visit = NULL
+    WHERE from_visit = OLD.id;
 END;
 
 CREATE TEMP TRIGGER moz_bookmarks_foreign_count_afterdelete_trigger
@@ -339,6 +343,25 @@ BEGIN
     WHERE id = OLD.place_id;
 END;
 
+-- Likewise for pinned sites, which shouldn't disappear when history is
+-- cleared.
+
+CREATE TEMP TRIGGER moz_pinned_sites_afterinsert_trigger
+AFTER INSERT ON moz_pinned_sites
+BEGIN
+    UPDATE moz_places SET
+        foreign_count = foreign_count + 1
+    WHERE id = NEW.place_id;
+END;
+
+CREATE TEMP TRIGGER moz_pinned_sites_afterdelete_trigger
+AFTER DELETE ON moz_pinned_sites
+BEGIN
+    UPDATE moz_places SET
+        foreign_count = foreign_count - 1
+    WHERE id = OLD.place_id;
+END;
+
 -- This trigger removes search query entries which no longer have any metadata records that point to them.
 -- Due to SQLite's lack of 'FOR EACH STATEMENT' (only 'FOR EACH ROW' is supported), in case of bulk
 -- deletes of metadata this will perform unnecessary SELECTs.
@@ -351,3 +374,60 @@ BEGIN
         SELECT id FROM moz_places_metadata pm WHERE pm.search_query_id = OLD.search_query_id
     );
 END;
+
+-- These triggers record changes to the parts of pages and bookmarks that
+-- apps index in `moz_changes`. Changes Sync makes are recorded, too.
+CREATE TEMP TRIGGER moz_places_afterinsert_trigger_changes
+AFTER INSERT ON moz_places FOR EACH ROW
+BEGIN
+    INSERT OR REPLACE INTO moz_changes(guid, kind, is_deleted, date_changed)
+    VALUES(NEW.guid, 1, 0, now());
+END;
+
+CREATE TEMP TRIGGER moz_places_afterupdate_trigger_changes
+AFTER UPDATE OF guid, url, title, hidden, preview_image_url ON moz_places FOR EACH ROW
+WHEN NEW.guid <> OLD.guid
+  OR NEW.url <> OLD.url
+  OR NEW.title IS NOT OLD.title
+  OR NEW.hidden <> OLD.hidden
+  OR NEW.preview_image_url IS NOT OLD.preview_image_url
+BEGIN
+    INSERT OR REPLACE INTO moz_changes(guid, kind, is_deleted, date_changed)
+    SELECT OLD.guid, 1, 1, now() WHERE NEW.guid <> OLD.guid;
+    INSERT OR REPLACE INTO moz_changes(guid, kind, is_deleted, date_changed)
+    VALUES(NEW.guid, 1, 0, now());
+END;
+
+CREATE TEMP TRIGGER moz_places_afterdelete_trigger_changes
+AFTER DELETE ON moz_places FOR EACH ROW
+BEGIN
+    INSERT OR REPLACE INTO moz_changes(guid, kind, is_deleted, date_changed)
+    VALUES(OLD.guid, 1, 1, now());
+END;
+
+CREATE TEMP TRIGGER moz_bookmarks_afterinsert_trigger_changes
+AFTER INSERT ON moz_bookmarks FOR EACH ROW
+BEGIN
+    INSERT OR REPLACE INTO moz_changes(guid, kind, is_deleted, date_changed)
+    VALUES(NEW.guid, 2, 0, now());
+END;
+
+CREATE TEMP TRIGGER moz_bookmarks_afterupdate_trigger_changes
+AFTER UPDATE OF guid, fk, parent, title ON moz_bookmarks FOR EACH ROW
+WHEN NEW.guid <> OLD.guid
+  OR NEW.fk IS NOT OLD.fk
+  OR NEW.parent IS NOT OLD.parent
+  OR NEW.title IS NOT OLD.title
+BEGIN
+    INSERT OR REPLACE INTO moz_changes(guid, kind, is_deleted, date_changed)
+    SELECT OLD.guid, 2, 1, now() WHERE NEW.guid <> OLD.guid;
+    INSERT OR REPLACE INTO moz_changes(guid, kind, is_deleted, date_changed)
+    VALUES(NEW.guid, 2, 0, now());
+END;
+
+CREATE TEMP TRIGGER moz_bookmarks_afterdelete_trigger_changes
+AFTER DELETE ON moz_bookmarks FOR EACH ROW
+BEGIN
+    INSERT OR REPLACE INTO moz_changes(guid, kind, is_deleted, date_changed)
+    VALUES(OLD.guid, 2, 1, now());
+END;
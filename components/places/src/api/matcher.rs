@@ -6,9 +6,12 @@ use crate::db::PlacesDb;
 use crate::error::Result;
 use crate::ffi::{MatchReason as FfiMatchReason, SearchResult as FfiSearchResult};
 pub use crate::match_impl::{MatchBehavior, SearchBehavior};
+use crate::storage::{get_meta, put_meta};
 use rusqlite::Row;
 use serde_derive::*;
 use sql_support::ConnExt;
+use std::collections::HashSet;
+use types::Timestamp;
 use url::Url;
 
 // A helper to log, cache and execute a query, returning a vector of flattened rows.
@@ -41,6 +44,83 @@ where
 pub struct SearchParams {
     pub search_string: String,
     pub limit: u32,
+    /// If set, restriction tokens in `search_string` are stripped out, and
+    /// limit the results to what they ask for.
+    pub restriction_tokens: Option<RestrictionTokens>,
+}
+
+/// The tokens which restrict an autocomplete search to certain kinds of
+/// results, like Desktop's URL bar restriction tokens. A token restricts the
+/// search if it's typed as a word of its own, or at the start of the first
+/// word, like `*mozilla`. An empty token is never recognized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestrictionTokens {
+    /// Only visited pages.
+    pub history: String,
+    /// Only bookmarks.
+    pub bookmark: String,
+    /// Only tagged pages.
+    pub tag: String,
+    /// Only pages the user typed in.
+    pub typed: String,
+    /// Only match the words against titles and tags.
+    pub title: String,
+    /// Only match the words against URLs.
+    pub url: String,
+}
+
+impl Default for RestrictionTokens {
+    fn default() -> Self {
+        Self {
+            history: "%".into(),
+            bookmark: "*".into(),
+            tag: "+".into(),
+            typed: "~".into(),
+            title: "#".into(),
+            url: "$".into(),
+        }
+    }
+}
+
+impl RestrictionTokens {
+    /// Strips the restriction tokens out of `query`, returning the rest of
+    /// the query, and the search behavior the tokens ask for.
+    fn parse(&self, query: &str) -> (String, SearchBehavior) {
+        let restrictions = [
+            (&self.history, SearchBehavior::HISTORY),
+            (&self.bookmark, SearchBehavior::BOOKMARK),
+            (&self.tag, SearchBehavior::TAG),
+            (&self.typed, SearchBehavior::TYPED),
+            (&self.title, SearchBehavior::TITLE),
+            (&self.url, SearchBehavior::URL),
+        ];
+        let mut behavior = SearchBehavior::empty();
+        let mut words = Vec::new();
+        for (i, mut word) in query.split_whitespace().enumerate() {
+            while let Some((token, restriction)) = restrictions.iter().find(|(token, _)| {
+                !token.is_empty()
+                    && word.starts_with(token.as_str())
+                    && (i == 0 || word.len() == token.len())
+            }) {
+                behavior |= *restriction;
+                word = &word[token.len()..];
+            }
+            if !word.is_empty() {
+                words.push(word);
+            }
+        }
+        let kinds = SearchBehavior::HISTORY
+            | SearchBehavior::BOOKMARK
+            | SearchBehavior::TAG
+            | SearchBehavior::TYPED;
+        let behavior = if behavior.intersects(kinds) {
+            // Results must be all of the kinds asked for.
+            behavior | SearchBehavior::RESTRICT
+        } else {
+            SearchBehavior::default() | behavior
+        };
+        (words.join(" "), behavior)
+    }
 }
 
 /// Synchronously queries all providers for autocomplete matches, then filters
@@ -51,36 +131,36 @@ pub struct SearchParams {
 /// A provider can be anything that returns URL suggestions: Places history
 /// and bookmarks, synced tabs, search engine suggestions, and search keywords.
 pub fn search_frecent(conn: &PlacesDb, params: SearchParams) -> Result<Vec<SearchResult>> {
-    // TODO: Tokenize the query.
+    let (search_string, search_behavior) = match &params.restriction_tokens {
+        Some(tokens) => tokens.parse(&params.search_string),
+        None => (params.search_string, SearchBehavior::default()),
+    };
 
     // Try to find the first heuristic result. Desktop tries extensions,
     // search engine aliases, origins, URLs, search engine domains, and
     // preloaded sites, before trying to fall back to fixing up the URL,
     // and a search if all else fails. We only try origins and URLs for
-    // heuristic matches, since that's all we support.
+    // heuristic matches, since that's all we support. Origins and URLs can't
+    // be restricted, so we skip them for restricted searches.
+    let origin_or_url = OriginOrUrl::new(&search_string);
+    // query adaptive matches and suggestions, matching Anywhere.
+    let adaptive =
+        Adaptive::with_behavior(&search_string, MatchBehavior::Anywhere, search_behavior);
+    let suggestions =
+        Suggestions::with_behavior(&search_string, MatchBehavior::Anywhere, search_behavior);
+    let mut matchers: Vec<&dyn Matcher> = Vec::new();
+    if search_behavior == SearchBehavior::default() {
+        matchers.push(&origin_or_url);
+    }
+    matchers.push(&adaptive);
+    matchers.push(&suggestions);
 
-    let mut matches = match_with_limit(
-        conn,
-        &[
-            // Try to match on the origin, or the full URL.
-            &OriginOrUrl::new(&params.search_string),
-            // query adaptive matches and suggestions, matching Anywhere.
-            &Adaptive::with_behavior(
-                &params.search_string,
-                MatchBehavior::Anywhere,
-                SearchBehavior::default(),
-            ),
-            &Suggestions::with_behavior(
-                &params.search_string,
-                MatchBehavior::Anywhere,
-                SearchBehavior::default(),
-            ),
-        ],
-        params.limit,
-    )?;
+    let mut matches = match_with_limit(conn, &matchers, params.limit)?;
 
-    matches.sort_unstable_by(|a, b| a.url.cmp(&b.url));
-    matches.dedup_by(|a, b| a.url == b.url);
+    // Keep the first of each URL, so that adaptive matches stay ahead of the
+    // suggestions.
+    let mut seen = HashSet::new();
+    matches.retain(|m| seen.insert(m.url.clone()));
 
     Ok(matches)
 }
@@ -120,6 +200,159 @@ fn match_with_limit(
     Ok(results)
 }
 
+/// Where a zero-prefix suggestion came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZeroPrefixSuggestionKind {
+    RecentSearch,
+    TopSite,
+    RecentBookmark,
+}
+
+/// A suggestion to show when the user focuses an empty URL bar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZeroPrefixSuggestion {
+    pub kind: ZeroPrefixSuggestionKind,
+    /// The title to show - the search term for searches, otherwise the title
+    /// of the page or bookmark, or the URL if it doesn't have one.
+    pub title: String,
+    /// The URL to open - for searches, the results page the user last saw.
+    pub url: Url,
+    pub search_term: Option<String>,
+}
+
+// The search terms from history metadata, most recently used first, along
+// with the results page they were last used on.
+const RECENT_SEARCHES_QUERY: &str = "
+    SELECT s.term AS search_term, p.url AS url, MAX(m.updated_at) AS updated_at
+    FROM moz_places_metadata m
+    JOIN moz_places_metadata_search_queries s ON s.id = m.search_query_id
+    JOIN moz_places p ON p.id = m.place_id
+    GROUP BY s.id
+    ORDER BY updated_at DESC, s.id DESC
+    LIMIT :limit";
+
+/// Returns up to `limit` suggestions for the empty URL bar, blending recent
+/// searches, top sites (ignoring pages visited only once, as per
+/// `frecency_threshold`) and recent bookmarks. We take one of each in turn,
+/// so no kind crowds out the others, and drop any URL we've already suggested.
+pub fn get_zero_prefix_suggestions(
+    conn: &PlacesDb,
+    limit: u32,
+    frecency_threshold: i64,
+) -> Result<Vec<ZeroPrefixSuggestion>> {
+    let scope = conn.begin_interrupt_scope()?;
+    let searches = query_flat_rows_and_then(
+        conn,
+        RECENT_SEARCHES_QUERY,
+        &[(":limit", &limit)],
+        |row| -> Result<_> {
+            let search_term: String = row.get("search_term")?;
+            Ok(ZeroPrefixSuggestion {
+                kind: ZeroPrefixSuggestionKind::RecentSearch,
+                title: search_term.clone(),
+                url: Url::parse(&row.get::<_, String>("url")?)?,
+                search_term: Some(search_term),
+            })
+        },
+    )?;
+    scope.err_if_interrupted()?;
+    let top_sites = crate::storage::history::get_top_frecent_site_infos(
+        conn,
+        limit as i32,
+        frecency_threshold,
+        &Default::default(),
+    )?
+    .into_iter()
+    .map(|site| ZeroPrefixSuggestion {
+        kind: ZeroPrefixSuggestionKind::TopSite,
+        title: site.title.unwrap_or_else(|| site.url.to_string()),
+        url: site.url,
+        search_term: None,
+    })
+    .collect::<Vec<_>>();
+    scope.err_if_interrupted()?;
+    let bookmarks = crate::storage::bookmarks::fetch::recent_bookmarks(conn, limit)?
+        .into_iter()
+        .map(|bookmark| ZeroPrefixSuggestion {
+            kind: ZeroPrefixSuggestionKind::RecentBookmark,
+            title: bookmark.title.unwrap_or_else(|| bookmark.url.to_string()),
+            url: bookmark.url,
+            search_term: None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut sources = [
+        searches.into_iter(),
+        top_sites.into_iter(),
+        bookmarks.into_iter(),
+    ];
+    let mut suggestions: Vec<ZeroPrefixSuggestion> = Vec::new();
+    let mut exhausted = false;
+    while suggestions.len() < limit as usize && !exhausted {
+        exhausted = true;
+        for source in sources.iter_mut() {
+            if suggestions.len() == limit as usize {
+                break;
+            }
+            for suggestion in source.by_ref() {
+                exhausted = false;
+                if !suggestions.iter().any(|s| s.url == suggestion.url) {
+                    suggestions.push(suggestion);
+                    break;
+                }
+            }
+        }
+    }
+    Ok(suggestions)
+}
+
+/// A frecent origin which matches a keyword the user typed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginSuggestion {
+    /// The origin, like `https://www.example.com/`.
+    pub url: Url,
+    pub frecency: i64,
+}
+
+/// Returns up to `limit` of the most frecent origins whose host starts with
+/// `keyword`, with or without a leading `www.`.
+///
+/// This is the interface for other components which suggest things as the
+/// user types, to fall back to when their own data isn't available - for
+/// example, before a suggestion provider has downloaded its suggestions.
+/// Origins the user hasn't visited enough to have a positive frecency aren't
+/// worth suggesting.
+pub fn get_origin_suggestions(
+    conn: &PlacesDb,
+    keyword: &str,
+    limit: u32,
+) -> Result<Vec<OriginSuggestion>> {
+    let keyword = keyword.trim().to_lowercase();
+    if keyword.is_empty() {
+        return Ok(Vec::new());
+    }
+    let suggestions = conn.query_rows_and_then_cached(
+        "SELECT prefix || host || '/' AS url, frecency
+         FROM moz_origins
+         WHERE frecency > 0
+           AND (substr(host, 1, length(:keyword)) = :keyword
+                OR substr(host, 1, length(:keyword) + 4) = 'www.' || :keyword)
+         ORDER BY frecency DESC
+         LIMIT :limit",
+        rusqlite::named_params! {
+            ":keyword": keyword,
+            ":limit": limit,
+        },
+        |row| -> Result<_> {
+            Ok(OriginSuggestion {
+                url: Url::parse(&row.get::<_, String>("url")?)?,
+                frecency: row.get("frecency")?,
+            })
+        },
+    )?;
+    Ok(suggestions)
+}
+
 /// Records an accepted autocomplete match, recording the query string,
 /// and chosen URL for subsequent matches.
 pub fn accept_result(conn: &PlacesDb, search_string: &str, url: &Url) -> Result<()> {
@@ -139,6 +372,60 @@ pub fn accept_result(conn: &PlacesDb, search_string: &str, url: &Url) -> Result<
     Ok(())
 }
 
+// Adaptive history - the results the user accepted for what they typed, in
+// `moz_inputhistory` - decays by this much every day, like Desktop's, so that
+// results the user stopped choosing eventually stop ranking first.
+const ADAPTIVE_HISTORY_DECAY_RATE: f64 = 0.975;
+// Entries whose use count decays below this are forgotten.
+const ADAPTIVE_HISTORY_MIN_USE_COUNT: f64 = 0.01;
+const ADAPTIVE_HISTORY_DECAYED_AT_META_KEY: &str = "adaptive_history_decayed_at";
+const ONE_DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Decays the use counts of adaptive history for each whole day since it was
+/// last decayed, and removes the entries which have decayed away. This runs
+/// during maintenance, so it doesn't matter how often that is.
+pub fn decay_adaptive_history(conn: &PlacesDb, now: Timestamp) -> Result<()> {
+    let decayed_at = match get_meta::<Timestamp>(conn, ADAPTIVE_HISTORY_DECAYED_AT_META_KEY)? {
+        Some(decayed_at) => decayed_at.as_millis(),
+        None => {
+            put_meta(conn, ADAPTIVE_HISTORY_DECAYED_AT_META_KEY, &now)?;
+            return Ok(());
+        }
+    };
+    let days = now.as_millis().saturating_sub(decayed_at) / ONE_DAY_MS;
+    if days == 0 {
+        return Ok(());
+    }
+    let tx = conn.begin_transaction()?;
+    conn.execute_cached(
+        "UPDATE moz_inputhistory SET use_count = use_count * :factor",
+        &[(
+            ":factor",
+            &ADAPTIVE_HISTORY_DECAY_RATE.powi(days.min(i32::MAX as u64) as i32),
+        )],
+    )?;
+    conn.execute_cached(
+        "DELETE FROM moz_inputhistory WHERE use_count < :min_use_count",
+        &[(":min_use_count", &ADAPTIVE_HISTORY_MIN_USE_COUNT)],
+    )?;
+    // Only count whole days, so that running maintenance more than once a
+    // day doesn't lose the part days in between.
+    put_meta(
+        conn,
+        ADAPTIVE_HISTORY_DECAYED_AT_META_KEY,
+        &Timestamp(decayed_at + days * ONE_DAY_MS),
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Forgets all the results the user has accepted, so that they no longer
+/// rank autocomplete matches.
+pub fn delete_adaptive_history(conn: &PlacesDb) -> Result<()> {
+    conn.execute_one("DELETE FROM moz_inputhistory")?;
+    Ok(())
+}
+
 pub fn split_after_prefix(href: &str) -> (&str, &str) {
     // Only search up to 64 bytes (matches desktop behavior)
     let haystack = &href.as_bytes()[..href.len().min(64)];
@@ -480,6 +767,21 @@ impl<'query> Matcher for OriginOrUrl<'query> {
     }
 }
 
+/// Returns the SQL for a page's tags, which matchers select as `tags`. Tags
+/// are only looked up for searches restricted to tagged pages, or which match
+/// the words against titles and tags, because the subquery runs for every
+/// candidate page.
+fn tags_column(search_behavior: SearchBehavior) -> &'static str {
+    if search_behavior.intersects(SearchBehavior::TAG | SearchBehavior::TITLE) {
+        "(SELECT GROUP_CONCAT(t.tag, ', ')
+          FROM moz_tags t
+          JOIN moz_tags_relation r ON r.tag_id = t.id
+          WHERE r.place_id = h.id)"
+    } else {
+        "NULL"
+    }
+}
+
 struct Adaptive<'query> {
     query: &'query str,
     match_behavior: MatchBehavior,
@@ -504,7 +806,8 @@ impl<'query> Matcher for Adaptive<'query> {
     fn search(&self, conn: &PlacesDb, max_results: u32) -> Result<Vec<SearchResult>> {
         query_flat_rows_and_then(
             conn,
-            "
+            &format!(
+                "
             SELECT h.url as url,
                    h.title as title,
                    EXISTS(SELECT 1 FROM moz_bookmarks
@@ -514,7 +817,7 @@ impl<'query> Matcher for Adaptive<'query> {
                           title NOT NULL
                     ORDER BY lastModified DESC
                     LIMIT 1) AS btitle,
-                   NULL AS tags,
+                   {tags} AS tags,
                    h.visit_count_local + h.visit_count_remote AS visit_count,
                    h.typed as typed,
                    h.id as id,
@@ -522,7 +825,14 @@ impl<'query> Matcher for Adaptive<'query> {
                    h.frecency as frecency,
                    :searchString AS searchString
             FROM (
-              SELECT ROUND(MAX(use_count) * (1 + (input = :searchString)), 1) AS rank,
+              -- Each input the user typed before accepting the result counts
+              -- for the share of it that's been typed now, so results chosen
+              -- for what's been typed exactly rank ahead of ones chosen for
+              -- longer inputs. Exact matches count double, like Desktop's.
+              SELECT ROUND(TOTAL(use_count *
+                                 CASE WHEN input = :searchString THEN 2
+                                      ELSE length(:searchString) * 1.0 / length(input)
+                                 END), 1) AS rank,
                      place_id
               FROM moz_inputhistory
               WHERE input BETWEEN :searchString AND :searchString || X'FFFF'
@@ -535,6 +845,8 @@ impl<'query> Matcher for Adaptive<'query> {
                                      NULL, :matchBehavior, :searchBehavior)
             ORDER BY rank DESC, h.frecency DESC
             LIMIT :maxResults",
+                tags = tags_column(self.search_behavior)
+            ),
             &[
                 (":searchString", &self.query as &dyn rusqlite::ToSql),
                 (":matchBehavior", &self.match_behavior),
@@ -570,7 +882,8 @@ impl<'query> Matcher for Suggestions<'query> {
     fn search(&self, conn: &PlacesDb, max_results: u32) -> Result<Vec<SearchResult>> {
         query_flat_rows_and_then(
             conn,
-            "
+            &format!(
+                "
             SELECT h.url, h.title,
                    EXISTS(SELECT 1 FROM moz_bookmarks
                           WHERE fk = h.id) AS bookmarked,
@@ -579,7 +892,7 @@ impl<'query> Matcher for Suggestions<'query> {
                           title NOT NULL
                     ORDER BY lastModified DESC
                     LIMIT 1) AS btitle,
-                   NULL AS tags,
+                   {tags} AS tags,
                    h.visit_count_local + h.visit_count_remote AS visit_count,
                    h.typed as typed,
                    h.id as id,
@@ -594,6 +907,8 @@ impl<'query> Matcher for Suggestions<'query> {
               AND (+h.visit_count_local > 0 OR +h.visit_count_remote > 0)
             ORDER BY h.frecency DESC, h.id DESC
             LIMIT :maxResults",
+                tags = tags_column(self.search_behavior)
+            ),
             &[
                 (":searchString", &self.query as &dyn rusqlite::ToSql),
                 (":matchBehavior", &self.match_behavior),
@@ -614,6 +929,47 @@ mod tests {
     use crate::types::VisitTransition;
     use types::Timestamp;
 
+    #[test]
+    fn test_get_origin_suggestions() {
+        let conn = new_mem_connection();
+        for (url, visits) in [
+            ("https://www.example.com/a", 3),
+            ("https://www.example.com/b", 1),
+            ("http://exampleish.org/", 1),
+            ("https://notexample.com/", 5),
+        ] {
+            for _ in 0..visits {
+                apply_observation(
+                    &conn,
+                    VisitObservation::new(Url::parse(url).unwrap())
+                        .with_visit_type(VisitTransition::Typed),
+                )
+                .expect("should apply");
+            }
+        }
+
+        let suggestions = get_origin_suggestions(&conn, " Example ", 10).expect("should query");
+        assert_eq!(
+            suggestions
+                .iter()
+                .map(|s| s.url.as_str())
+                .collect::<Vec<_>>(),
+            vec!["https://www.example.com/", "http://exampleish.org/"]
+        );
+        assert_eq!(
+            get_origin_suggestions(&conn, "example", 1)
+                .expect("should query")
+                .len(),
+            1
+        );
+        assert!(get_origin_suggestions(&conn, "", 10)
+            .expect("should query")
+            .is_empty());
+        assert!(get_origin_suggestions(&conn, "mozilla", 10)
+            .expect("should query")
+            .is_empty());
+    }
+
     #[test]
     fn split() {
         assert_eq!(
@@ -659,6 +1015,111 @@ mod tests {
         );
     }
 
+    #[test]
+    fn zero_prefix_suggestions() {
+        use crate::storage::bookmarks::{
+            insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+        };
+        use crate::storage::history_metadata::{
+            apply_metadata_observation, HistoryMetadataObservation,
+        };
+
+        let conn = new_mem_connection();
+        assert!(get_zero_prefix_suggestions(&conn, 10, 0)
+            .expect("should work")
+            .is_empty());
+
+        for (url, times) in [
+            ("https://top.example.com/", 3),
+            ("https://other.example.com/", 1),
+        ] {
+            for _ in 0..times {
+                apply_observation(
+                    &conn,
+                    VisitObservation::new(Url::parse(url).unwrap())
+                        .with_visit_type(VisitTransition::Typed)
+                        .with_at(Timestamp::now()),
+                )
+                .expect("should apply");
+            }
+        }
+        for (search_term, url) in [
+            ("cats", "https://search.example.com/?q=cats"),
+            ("dogs", "https://search.example.com/?q=dogs"),
+        ] {
+            apply_metadata_observation(
+                &conn,
+                HistoryMetadataObservation {
+                    url: url.to_string(),
+                    view_time: Some(1000),
+                    search_term: Some(search_term.to_string()),
+                    document_type: None,
+                    referrer_url: None,
+                    title: None,
+                    metadata_session_id: None,
+                },
+            )
+            .expect("should apply");
+        }
+        for (url, title) in [
+            ("https://top.example.com/", Some("Top")),
+            ("https://bookmark.example.com/", None),
+        ] {
+            insert_bookmark(
+                &conn,
+                InsertableBookmark {
+                    parent_guid: BookmarkRootGuid::Unfiled.into(),
+                    position: BookmarkPosition::Append,
+                    date_added: None,
+                    last_modified: None,
+                    guid: None,
+                    url: Url::parse(url).unwrap(),
+                    title: title.map(str::to_string),
+                }
+                .into(),
+            )
+            .expect("should insert");
+        }
+
+        let suggestions = get_zero_prefix_suggestions(&conn, 10, 0).expect("should work");
+        let summary: Vec<_> = suggestions
+            .iter()
+            .map(|s| (s.kind, s.title.as_str()))
+            .collect();
+        // The top site is also bookmarked, but is only suggested once.
+        assert_eq!(
+            summary,
+            vec![
+                (ZeroPrefixSuggestionKind::RecentSearch, "dogs"),
+                (
+                    ZeroPrefixSuggestionKind::TopSite,
+                    "https://top.example.com/"
+                ),
+                (
+                    ZeroPrefixSuggestionKind::RecentBookmark,
+                    "https://bookmark.example.com/"
+                ),
+                (ZeroPrefixSuggestionKind::RecentSearch, "cats"),
+                (
+                    ZeroPrefixSuggestionKind::TopSite,
+                    "https://other.example.com/"
+                ),
+            ]
+        );
+        assert_eq!(
+            suggestions[0].url.as_str(),
+            "https://search.example.com/?q=dogs"
+        );
+        assert_eq!(suggestions[0].search_term.as_deref(), Some("dogs"));
+
+        assert_eq!(
+            get_zero_prefix_suggestions(&conn, 2, 0)
+                .expect("should work")
+                .len(),
+            2
+        );
+    }
+
     #[test]
     fn search() {
         let conn = new_mem_connection();
@@ -676,6 +1137,7 @@ mod tests {
             SearchParams {
                 search_string: "example.com".into(),
                 limit: 10,
+                restriction_tokens: None,
             },
         )
         .expect("Should search by origin");
@@ -691,6 +1153,7 @@ mod tests {
             SearchParams {
                 search_string: "http://example.com".into(),
                 limit: 10,
+                restriction_tokens: None,
             },
         )
         .expect("Should search by URL without path");
@@ -705,6 +1168,7 @@ mod tests {
             SearchParams {
                 search_string: "http://example.com/1".into(),
                 limit: 10,
+                restriction_tokens: None,
             },
         )
         .expect("Should search by URL with path");
@@ -721,6 +1185,7 @@ mod tests {
             SearchParams {
                 search_string: "ample".into(),
                 limit: 10,
+                restriction_tokens: None,
             },
         )
         .expect("Should search by adaptive input history");
@@ -735,6 +1200,7 @@ mod tests {
             SearchParams {
                 search_string: "example".into(),
                 limit: 1,
+                restriction_tokens: None,
             },
         )
         .expect("Should search until reaching limit");
@@ -750,6 +1216,75 @@ mod tests {
             }]
         );
     }
+    #[test]
+    fn search_adaptive_history() {
+        let conn = new_mem_connection();
+        let docs = Url::parse("https://example.org/docs").unwrap();
+        let dev = Url::parse("https://example.com/dev").unwrap();
+        for url in [&docs, &dev] {
+            apply_observation(
+                &conn,
+                VisitObservation::new(url.clone())
+                    .with_title("Developer".to_string())
+                    .with_visit_type(VisitTransition::Link),
+            )
+            .expect("should apply");
+        }
+        let search = |search_string: &str| {
+            search_frecent(
+                &conn,
+                SearchParams {
+                    search_string: search_string.into(),
+                    limit: 10,
+                    restriction_tokens: None,
+                },
+            )
+            .expect("should search")
+            .into_iter()
+            .map(|result| result.url)
+            .collect::<Vec<_>>()
+        };
+        let use_counts = || {
+            conn.query_rows_and_then(
+                "SELECT input, use_count FROM moz_inputhistory ORDER BY input",
+                [],
+                |row| -> Result<(String, f64)> { Ok((row.get(0)?, row.get(1)?)) },
+            )
+            .unwrap()
+        };
+
+        // The result chosen most often for what's been typed ranks first.
+        accept_result(&conn, "dev", &dev).unwrap();
+        for _ in 0..3 {
+            accept_result(&conn, "developer", &docs).unwrap();
+        }
+        assert_eq!(search("developer"), vec![docs.clone(), dev.clone()]);
+        // A result chosen for the exact input outranks one chosen more often
+        // for a longer input.
+        assert_eq!(search("dev"), vec![dev.clone(), docs.clone()]);
+
+        // Use counts decay for each whole day since they were last decayed.
+        let now = Timestamp::now();
+        decay_adaptive_history(&conn, now).unwrap();
+        assert_eq!(
+            use_counts(),
+            vec![("dev".into(), 1.0), ("developer".into(), 2.71)]
+        );
+        decay_adaptive_history(&conn, Timestamp(now.as_millis() + ONE_DAY_MS / 2)).unwrap();
+        assert_eq!(use_counts()[0].1, 1.0);
+        decay_adaptive_history(&conn, Timestamp(now.as_millis() + 2 * ONE_DAY_MS)).unwrap();
+        assert_eq!(use_counts()[0].1, ADAPTIVE_HISTORY_DECAY_RATE.powi(2));
+        // Entries which decay away are removed.
+        decay_adaptive_history(&conn, Timestamp(now.as_millis() + 200 * ONE_DAY_MS)).unwrap();
+        let remaining = use_counts();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].0, "developer");
+        assert!((remaining[0].1 - 2.71 * ADAPTIVE_HISTORY_DECAY_RATE.powi(200)).abs() < 1e-9);
+
+        delete_adaptive_history(&conn).unwrap();
+        assert!(use_counts().is_empty());
+    }
+
     #[test]
     fn search_unicode() {
         let conn = new_mem_connection();
@@ -767,6 +1302,7 @@ mod tests {
             SearchParams {
                 search_string: "http://exämple.com".into(),
                 limit: 10,
+                restriction_tokens: None,
             },
         )
         .expect("Should search by URL without path");
@@ -782,6 +1318,7 @@ mod tests {
             SearchParams {
                 search_string: "http://exämple.com/1".into(),
                 limit: 10,
+                restriction_tokens: None,
             },
         )
         .expect("Should search by URL with path");
@@ -806,10 +1343,124 @@ mod tests {
             SearchParams {
                 search_string: ball_of_yarn_about_blank.into(),
                 limit: 10,
+                restriction_tokens: None,
             },
         )
         .unwrap();
     }
+
+    #[test]
+    fn parse_restriction_tokens() {
+        let tokens = RestrictionTokens::default();
+        assert_eq!(
+            tokens.parse("mozilla firefox"),
+            ("mozilla firefox".to_string(), SearchBehavior::default())
+        );
+        assert_eq!(
+            tokens.parse("*mozilla firefox"),
+            (
+                "mozilla firefox".to_string(),
+                SearchBehavior::BOOKMARK | SearchBehavior::RESTRICT
+            )
+        );
+        assert_eq!(
+            tokens.parse("mozilla + firefox %"),
+            (
+                "mozilla firefox".to_string(),
+                SearchBehavior::TAG | SearchBehavior::HISTORY | SearchBehavior::RESTRICT
+            )
+        );
+        // Only the first word can start with a token.
+        assert_eq!(
+            tokens.parse("mozilla *firefox"),
+            ("mozilla *firefox".to_string(), SearchBehavior::default())
+        );
+        // Tokens which only change where we look don't restrict the results.
+        assert_eq!(
+            tokens.parse("$ mozilla"),
+            (
+                "mozilla".to_string(),
+                SearchBehavior::default() | SearchBehavior::URL
+            )
+        );
+        let no_bookmarks = RestrictionTokens {
+            bookmark: String::new(),
+            ..RestrictionTokens::default()
+        };
+        assert_eq!(
+            no_bookmarks.parse("* mozilla"),
+            ("* mozilla".to_string(), SearchBehavior::default())
+        );
+    }
+
+    #[test]
+    fn search_with_restriction_tokens() {
+        use crate::storage::bookmarks::{
+            insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+        };
+        use crate::storage::tags::tag_url;
+
+        let conn = new_mem_connection();
+        let bookmarked = Url::parse("https://example.com/bookmarked").unwrap();
+        let tagged = Url::parse("https://example.com/tagged").unwrap();
+        let visited = Url::parse("https://example.com/visited").unwrap();
+        for url in [&bookmarked, &tagged, &visited] {
+            apply_observation(
+                &conn,
+                VisitObservation::new(url.clone())
+                    .with_title("Example".to_string())
+                    .with_visit_type(VisitTransition::Link),
+            )
+            .expect("should apply");
+        }
+        insert_bookmark(
+            &conn,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: bookmarked.clone(),
+                title: None,
+            }
+            .into(),
+        )
+        .expect("should insert");
+        tag_url(&conn, &tagged, "stuff").expect("should tag");
+
+        let search = |search_string: &str, restriction_tokens| {
+            let mut urls = search_frecent(
+                &conn,
+                SearchParams {
+                    search_string: search_string.into(),
+                    limit: 10,
+                    restriction_tokens,
+                },
+            )
+            .expect("should search")
+            .into_iter()
+            .map(|result| result.url)
+            .collect::<Vec<_>>();
+            urls.sort();
+            urls
+        };
+        let tokens = Some(RestrictionTokens::default());
+        assert_eq!(search("*example", tokens.clone()), vec![bookmarked.clone()]);
+        assert_eq!(search("example +", tokens.clone()), vec![tagged.clone()]);
+        // Tags are searched, too, when the search is restricted to tagged
+        // pages or titles, but not otherwise.
+        assert_eq!(search("+ stuff", tokens.clone()), vec![tagged.clone()]);
+        assert_eq!(search("# stuff", tokens.clone()), vec![tagged.clone()]);
+        assert!(search("stuff", tokens.clone()).is_empty());
+        assert_eq!(
+            search("% example", tokens),
+            vec![bookmarked.clone(), tagged.clone(), visited.clone()]
+        );
+        // Without restriction tokens, they're just part of the query.
+        assert!(search("*example", None).is_empty());
+    }
+
     // This panics in tests but not for "real" consumers. In an effort to ensure
     // we are panicing where we think we are, note the 'expected' string.
     // (Not really clear this test offers much value, but seems worth having...)
@@ -833,6 +1484,7 @@ mod tests {
             SearchParams {
                 search_string: "not-a-url".into(),
                 limit: 10,
+                restriction_tokens: None,
             },
         );
     }
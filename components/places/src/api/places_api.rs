@@ -4,8 +4,12 @@
 
 use crate::bookmark_sync::BookmarksSyncEngine;
 use crate::db::db::{PlacesDb, SharedPlacesDb};
+use crate::db::encryption;
 use crate::error::*;
+use crate::frecency::FrecencySettings;
+use crate::history_metadata_sync::HistoryMetadataSyncEngine;
 use crate::history_sync::HistorySyncEngine;
+use crate::observer::{self, PlacesChangeObserver};
 use crate::storage::{
     self, bookmarks::bookmark_sync, delete_meta, get_meta, history::history_sync, put_meta,
 };
@@ -41,14 +45,24 @@ lazy_static::lazy_static! {
     // [Arc/Weak]: Stores the places api used to create the connection for
     //             BookmarksSyncEngine/HistorySyncEngine
     static ref PLACES_API_FOR_SYNC_MANAGER: Mutex<Weak<PlacesApi>> = Mutex::new(Weak::new());
+    // As above, but for HistoryMetadataSyncEngine, which apps opt in to
+    // separately.
+    static ref HISTORY_METADATA_API_FOR_SYNC_MANAGER: Mutex<Weak<PlacesApi>> = Mutex::new(Weak::new());
 }
 
 // Called by the sync manager to get a sync engine via the PlacesApi previously
 // registered with the sync manager.
 pub fn get_registered_sync_engine(engine_id: &SyncEngineId) -> Option<Box<dyn SyncEngine>> {
-    match PLACES_API_FOR_SYNC_MANAGER.lock().upgrade() {
+    let registered = match engine_id {
+        SyncEngineId::HistoryMetadata => &HISTORY_METADATA_API_FOR_SYNC_MANAGER,
+        _ => &PLACES_API_FOR_SYNC_MANAGER,
+    };
+    match registered.lock().upgrade() {
         None => {
-            log::warn!("places: get_registered_sync_engine: no PlacesApi registered");
+            log::warn!(
+                "places: get_registered_sync_engine: no PlacesApi registered for {}",
+                engine_id
+            );
             None
         }
         Some(places_api) => match create_sync_engine(&places_api, engine_id) {
@@ -65,7 +79,8 @@ pub fn get_registered_sync_engine(engine_id: &SyncEngineId) -> Option<Box<dyn Sy
     }
 }
 
-fn create_sync_engine(
+// Also used by the sync manager to wipe a places database it opened itself.
+pub fn create_sync_engine(
     places_api: &PlacesApi,
     engine_id: &SyncEngineId,
 ) -> Result<Box<dyn SyncEngine>> {
@@ -73,6 +88,7 @@ fn create_sync_engine(
     match engine_id {
         SyncEngineId::Bookmarks => Ok(Box::new(BookmarksSyncEngine::new(conn)?)),
         SyncEngineId::History => Ok(Box::new(HistorySyncEngine::new(conn)?)),
+        SyncEngineId::HistoryMetadata => Ok(Box::new(HistoryMetadataSyncEngine::new(conn)?)),
         _ => unreachable!("can't provide unknown engine: {}", engine_id),
     }
 }
@@ -124,9 +140,23 @@ pub struct SyncState {
 /// For uniffi we need to expose our `Arc` returning constructor as a global function :(
 /// https://github.com/mozilla/uniffi-rs/pull/1063 would fix this, but got some pushback
 /// meaning we are forced into this unfortunate workaround.
-pub fn places_api_new(db_name: impl AsRef<Path>) -> ApiResult<Arc<PlacesApi>> {
+pub fn places_api_new(
+    db_name: impl AsRef<Path>,
+    frecency_settings: Option<FrecencySettings>,
+) -> ApiResult<Arc<PlacesApi>> {
     handle_error! {
-        PlacesApi::new(db_name)
+        PlacesApi::new_with_frecency_settings(db_name, frecency_settings.unwrap_or_default())
+    }
+}
+
+/// Like `places_api_new`, but for a database encrypted with `encryption_key`.
+pub fn places_api_new_with_key(
+    db_name: impl AsRef<Path>,
+    encryption_key: String,
+    frecency_settings: Option<FrecencySettings>,
+) -> ApiResult<Arc<PlacesApi>> {
+    handle_error! {
+        PlacesApi::new_with_key(db_name, &encryption_key, frecency_settings.unwrap_or_default())
     }
 }
 
@@ -138,6 +168,8 @@ pub struct PlacesApi {
     write_connection: Mutex<Option<PlacesDb>>,
     sync_state: Mutex<Option<SyncState>>,
     coop_tx_lock: Arc<Mutex<()>>,
+    // Shared with every connection which calculates frecencies.
+    frecency_settings: Arc<FrecencySettings>,
     // Used for get_sync_connection()
     // - The inner mutux synchronizes sync operation (for example one of the [SyncEngine] methods).
     //   This avoids issues like #867
@@ -147,14 +179,43 @@ pub struct PlacesApi {
     // - The outer mutex synchronizes the `get_sync_connection()` operation.  If multiple threads
     //   ran that at the same time there would be issues.
     sync_connection: Mutex<Weak<SharedPlacesDb>>,
+    // The key the database is encrypted with, if it is. Shared with every
+    // connection, so the write connection can change it.
+    encryption_key: Arc<Mutex<Option<String>>>,
     id: usize,
 }
 
 impl PlacesApi {
     /// Create a new, or fetch an already open, PlacesApi backed by a file on disk.
     pub fn new(db_name: impl AsRef<Path>) -> Result<Arc<Self>> {
+        Self::new_with_frecency_settings(db_name, FrecencySettings::default())
+    }
+
+    /// Like `new`, but calculates frecencies with `frecency_settings` instead
+    /// of the defaults. Fails if the settings are invalid. If the PlacesApi is
+    /// already open, it keeps the settings it was created with, and
+    /// `frecency_settings` is ignored.
+    pub fn new_with_frecency_settings(
+        db_name: impl AsRef<Path>,
+        frecency_settings: FrecencySettings,
+    ) -> Result<Arc<Self>> {
+        let db_name = normalize_path(db_name)?;
+        Self::new_or_existing(db_name, None, frecency_settings)
+    }
+
+    /// Create a new, or fetch an already open, PlacesApi backed by a file on
+    /// disk which is encrypted with SQLCipher, using `encryption_key`. An
+    /// existing unencrypted database is encrypted first. Fails if SQLCipher
+    /// isn't available, if the key is wrong, or if the PlacesApi is already
+    /// open with a different key.
+    pub fn new_with_key(
+        db_name: impl AsRef<Path>,
+        encryption_key: &str,
+        frecency_settings: FrecencySettings,
+    ) -> Result<Arc<Self>> {
+        encryption::ensure_available()?;
         let db_name = normalize_path(db_name)?;
-        Self::new_or_existing(db_name)
+        Self::new_or_existing(db_name, Some(encryption_key), frecency_settings)
     }
 
     /// Create a new, or fetch an already open, memory-based PlacesApi. You must
@@ -162,25 +223,43 @@ impl PlacesApi {
     ///  reader connections to the same memory DB open.
     pub fn new_memory(db_name: &str) -> Result<Arc<Self>> {
         let name = PathBuf::from(format!("file:{}?mode=memory&cache=shared", db_name));
-        Self::new_or_existing(name)
+        Self::new_or_existing(name, None, FrecencySettings::default())
     }
     fn new_or_existing_into(
         target: &mut HashMap<PathBuf, Weak<PlacesApi>>,
         db_name: PathBuf,
+        encryption_key: Option<&str>,
+        frecency_settings: FrecencySettings,
     ) -> Result<Arc<Self>> {
         let id = ID_COUNTER.fetch_add(1, Ordering::SeqCst);
         match target.get(&db_name).and_then(Weak::upgrade) {
-            Some(existing) => Ok(existing),
+            Some(existing) => {
+                if existing.encryption_key.lock().as_deref() != encryption_key {
+                    return Err(Error::DifferentDatabaseKey);
+                }
+                Ok(existing)
+            }
             None => {
+                if let Some(key) = encryption_key {
+                    // Nothing else has the database open, so this is when we
+                    // can encrypt one which isn't yet.
+                    if encryption::is_plaintext_database(&db_name)? {
+                        encryption::encrypt_plaintext_database(&db_name, key)?;
+                    }
+                }
                 // We always create a new read-write connection for an initial open so
                 // we can create the schema and/or do version upgrades.
                 let coop_tx_lock = Arc::new(Mutex::new(()));
-                let connection = PlacesDb::open(
+                let frecency_settings = Arc::new(frecency_settings);
+                let encryption_key = Arc::new(Mutex::new(encryption_key.map(String::from)));
+                let mut connection = PlacesDb::open_with_key(
                     &db_name,
+                    Arc::clone(&encryption_key),
                     ConnectionType::ReadWrite,
                     id,
                     coop_tx_lock.clone(),
                 )?;
+                connection.set_frecency_settings(Arc::clone(&frecency_settings));
                 let new = PlacesApi {
                     db_name: db_name.clone(),
                     write_connection: Mutex::new(Some(connection)),
@@ -188,6 +267,8 @@ impl PlacesApi {
                     sync_connection: Mutex::new(Weak::new()),
                     id,
                     coop_tx_lock,
+                    frecency_settings,
+                    encryption_key,
                 };
                 let arc = Arc::new(new);
                 target.insert(db_name, Arc::downgrade(&arc));
@@ -196,9 +277,14 @@ impl PlacesApi {
         }
     }
 
-    fn new_or_existing(db_name: PathBuf) -> Result<Arc<Self>> {
+    fn new_or_existing(
+        db_name: PathBuf,
+        encryption_key: Option<&str>,
+        frecency_settings: FrecencySettings,
+    ) -> Result<Arc<Self>> {
+        frecency_settings.validate()?;
         let mut guard = APIS.lock();
-        Self::new_or_existing_into(&mut guard, db_name)
+        Self::new_or_existing_into(&mut guard, db_name, encryption_key, frecency_settings)
     }
 
     /// Open a connection to the database.
@@ -206,8 +292,9 @@ impl PlacesApi {
         match conn_type {
             ConnectionType::ReadOnly => {
                 // make a new one - we can have as many of these as we want.
-                PlacesDb::open(
+                PlacesDb::open_with_key(
                     self.db_name.clone(),
+                    Arc::clone(&self.encryption_key),
                     ConnectionType::ReadOnly,
                     self.id,
                     self.coop_tx_lock.clone(),
@@ -227,6 +314,39 @@ impl PlacesApi {
         }
     }
 
+    /// Open `size` read-only connections to the database, for a pool of
+    /// readers which can run queries at the same time. Each connection keeps
+    /// its own interrupt handle, so that an operation on one reader doesn't
+    /// stop us interrupting the others.
+    pub fn open_read_only_pool(&self, size: usize) -> Result<Vec<PlacesDb>> {
+        (0..size.max(1))
+            .map(|_| self.open_connection(ConnectionType::ReadOnly))
+            .collect()
+    }
+
+    /// Opens a read-only connection with a snapshot of the database. Every
+    /// query on it sees the database as it was when it was opened, even if
+    /// other connections write to it - so, for example, a count and a page of
+    /// results always agree. The snapshot ends when the connection is closed.
+    ///
+    /// Snapshots hold a read transaction open, which stops the WAL from being
+    /// checkpointed, so they should be short-lived.
+    pub fn open_snapshot(&self) -> Result<PlacesDb> {
+        let mut db = self.open_connection(ConnectionType::ReadOnly)?;
+        db.begin_snapshot()?;
+        Ok(db)
+    }
+
+    /// Registers an observer for changes to history and bookmarks made by
+    /// any connection to this API, replacing any existing one.
+    pub fn set_change_observer(&self, observer: Box<dyn PlacesChangeObserver>) {
+        observer::set_observer(self.id, observer);
+    }
+
+    pub fn clear_change_observer(&self) {
+        observer::clear_observer(self.id);
+    }
+
     // Get a database connection to sync with
     //
     // This function provides a couple features to facilitate sharing the connection between
@@ -242,12 +362,15 @@ impl PlacesApi {
             Some(db) => Ok(db),
             // If not, create a new connection
             None => {
-                let db = Arc::new(SharedPlacesDb::new(PlacesDb::open(
+                let mut db = PlacesDb::open_with_key(
                     self.db_name.clone(),
+                    Arc::clone(&self.encryption_key),
                     ConnectionType::Sync,
                     self.id,
                     self.coop_tx_lock.clone(),
-                )?));
+                )?;
+                db.set_frecency_settings(Arc::clone(&self.frecency_settings));
+                let db = Arc::new(SharedPlacesDb::new(db));
                 register_interrupt(Arc::<SharedPlacesDb>::downgrade(&db));
                 // Store a weakref for next time
                 *conn = Arc::downgrade(&db);
@@ -291,6 +414,13 @@ impl PlacesApi {
         *PLACES_API_FOR_SYNC_MANAGER.lock() = Arc::downgrade(&self);
     }
 
+    // History metadata isn't synced unless the app asks for it with this, as
+    // well as enabling the `historymetadata` engine, since not every app wants
+    // to upload what its users search for.
+    pub fn register_history_metadata_with_sync_manager(self: Arc<Self>) {
+        *HISTORY_METADATA_API_FOR_SYNC_MANAGER.lock() = Arc::downgrade(&self);
+    }
+
     // NOTE: These should be deprecated as soon as possible - that will be once
     // all consumers have been updated to use the .sync() method below, and/or
     // we have implemented the sync manager and migrated consumers to that.
@@ -464,6 +594,12 @@ impl PlacesApi {
     }
 }
 
+impl Drop for PlacesApi {
+    fn drop(&mut self) {
+        observer::clear_observer(self.id);
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
@@ -618,4 +754,199 @@ mod tests {
         assert_ne!(1, conn.db.query_one::<i64>("PRAGMA user_version")?);
         Ok(())
     }
+
+    #[test]
+    fn test_frecency_settings() -> Result<()> {
+        use crate::bookmark_sync::engine::update_frecencies;
+        use crate::observation::VisitObservation;
+        use crate::storage::{fetch_page_info, history::apply_observation};
+        use crate::types::VisitTransition;
+        use url::Url;
+
+        let dirname = tempfile::tempdir().unwrap();
+        let url = Url::parse("https://example.com/").unwrap();
+        let typed_frecency = |api: &PlacesApi| -> Result<i32> {
+            let conn = api.open_connection(ConnectionType::ReadWrite)?;
+            apply_observation(
+                &conn,
+                VisitObservation::new(url.clone()).with_visit_type(VisitTransition::Typed),
+            )?;
+            Ok(fetch_page_info(&conn, &url)?
+                .expect("should exist")
+                .page
+                .frecency)
+        };
+
+        let default_api = PlacesApi::new(dirname.path().join("default.db"))?;
+        let default_frecency = typed_frecency(&default_api)?;
+        assert!(default_frecency > 0);
+
+        let settings = FrecencySettings {
+            typed_visit_bonus: FrecencySettings::default().typed_visit_bonus * 2,
+            ..Default::default()
+        };
+        let api =
+            PlacesApi::new_with_frecency_settings(dirname.path().join("custom.db"), settings)?;
+        assert_eq!(typed_frecency(&api)?, default_frecency * 2);
+
+        // The settings are also used to recalculate stale frecencies.
+        {
+            let conn = api.get_sync_connection()?;
+            let conn = conn.lock();
+            assert_eq!(conn.frecency_settings().typed_visit_bonus, 4000);
+            conn.execute_batch(
+                "UPDATE moz_places SET frecency = -1;
+                 INSERT OR REPLACE INTO moz_places_stale_frecencies(place_id, stale_at)
+                 SELECT id, 0 FROM moz_places",
+            )?;
+            update_frecencies(&conn, &conn.begin_interrupt_scope()?)?;
+            let frecency = fetch_page_info(&conn, &url)?
+                .expect("should exist")
+                .page
+                .frecency;
+            assert_eq!(frecency, default_frecency * 2);
+        }
+
+        // An already open PlacesApi keeps the settings it was created with.
+        let reopened = PlacesApi::new(dirname.path().join("custom.db"))?;
+        assert!(Arc::ptr_eq(&api, &reopened));
+        assert_eq!(
+            reopened
+                .get_sync_connection()?
+                .lock()
+                .frecency_settings()
+                .typed_visit_bonus,
+            4000
+        );
+
+        let invalid = FrecencySettings {
+            num_visits: -1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            PlacesApi::new_with_frecency_settings(dirname.path().join("invalid.db"), invalid),
+            Err(Error::InvalidFrecencySettings(..))
+        ));
+        Ok(())
+    }
+
+    // The encryption tests are skipped unless SQLCipher is linked, eg, with
+    // `cargo test -p places --features sqlcipher`.
+    fn encryption_available() -> bool {
+        match encryption::ensure_available() {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("Skipping encryption test: {}", e);
+                false
+            }
+        }
+    }
+
+    fn add_visit(api: &PlacesApi, url: &str) -> Result<()> {
+        use crate::observation::VisitObservation;
+        use crate::storage::history::apply_observation;
+        use crate::types::VisitTransition;
+
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        apply_observation(
+            &conn,
+            VisitObservation::new(url::Url::parse(url).unwrap())
+                .with_visit_type(VisitTransition::Link),
+        )?;
+        api.close_connection(conn)
+    }
+
+    fn count_pages(api: &PlacesApi) -> Result<i64> {
+        let conn = api.open_connection(ConnectionType::ReadOnly)?;
+        Ok(conn.query_one::<i64>("SELECT COUNT(*) FROM moz_places")?)
+    }
+
+    #[test]
+    fn test_encrypt_existing_database() -> Result<()> {
+        if !encryption_available() {
+            return Ok(());
+        }
+        let dirname = tempfile::tempdir().unwrap();
+        let db_name = dirname.path().join("places.sqlite");
+        {
+            let api = PlacesApi::new(&db_name)?;
+            add_visit(&api, "https://example.com/")?;
+        }
+        assert!(encryption::is_plaintext_database(&db_name)?);
+
+        let api = PlacesApi::new_with_key(&db_name, "key", FrecencySettings::default())?;
+        assert!(!encryption::is_plaintext_database(&db_name)?);
+        assert_eq!(count_pages(&api)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_incorrect_database_key() -> Result<()> {
+        if !encryption_available() {
+            return Ok(());
+        }
+        let dirname = tempfile::tempdir().unwrap();
+        let db_name = dirname.path().join("places.sqlite");
+        {
+            let api = PlacesApi::new_with_key(&db_name, "key", FrecencySettings::default())?;
+            add_visit(&api, "https://example.com/")?;
+        }
+
+        let err = PlacesApi::new_with_key(&db_name, "wrong key", FrecencySettings::default())
+            .expect_err("should fail with the wrong key");
+        assert!(matches!(
+            err,
+            Error::OpenDatabaseError(sql_support::open_database::Error::IncorrectKey)
+        ));
+        // The database isn't treated as corrupt and deleted.
+        assert!(db_name.exists());
+        let api = PlacesApi::new_with_key(&db_name, "key", FrecencySettings::default())?;
+        assert_eq!(count_pages(&api)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rekey_database() -> Result<()> {
+        if !encryption_available() {
+            return Ok(());
+        }
+        let dirname = tempfile::tempdir().unwrap();
+        let db_name = dirname.path().join("places.sqlite");
+        {
+            let api = PlacesApi::new_with_key(&db_name, "old key", FrecencySettings::default())?;
+            add_visit(&api, "https://example.com/")?;
+            let conn = api.open_connection(ConnectionType::ReadWrite)?;
+            conn.rekey("new key")?;
+            // Connections opened after the rekey use the new key.
+            assert_eq!(count_pages(&api)?, 1);
+            api.close_connection(conn)?;
+        }
+
+        assert!(PlacesApi::new_with_key(&db_name, "old key", FrecencySettings::default()).is_err());
+        let api = PlacesApi::new_with_key(&db_name, "new key", FrecencySettings::default())?;
+        assert_eq!(count_pages(&api)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_different_database_key() -> Result<()> {
+        if !encryption_available() {
+            return Ok(());
+        }
+        let dirname = tempfile::tempdir().unwrap();
+        let db_name = dirname.path().join("places.sqlite");
+        let _api = PlacesApi::new_with_key(&db_name, "key", FrecencySettings::default())?;
+
+        assert!(matches!(
+            PlacesApi::new_with_key(&db_name, "other key", FrecencySettings::default()),
+            Err(Error::DifferentDatabaseKey)
+        ));
+        assert!(matches!(
+            PlacesApi::new(&db_name),
+            Err(Error::DifferentDatabaseKey)
+        ));
+        // But opening it with the same key gets the same API.
+        PlacesApi::new_with_key(&db_name, "key", FrecencySettings::default())?;
+        Ok(())
+    }
 }
@@ -10,11 +10,13 @@ use super::record::{
 use super::{SyncedBookmarkKind, SyncedBookmarkValidity};
 use crate::db::{GlobalChangeCounterTracker, PlacesDb, SharedPlacesDb};
 use crate::error::*;
-use crate::frecency::{calculate_frecency, DEFAULT_FRECENCY_SETTINGS};
+use crate::frecency::calculate_frecency;
+use crate::observer::{self, PlacesChange};
+use crate::spans;
 use crate::storage::{
     bookmarks::{
         bookmark_sync::{create_synced_bookmark_roots, reset},
-        BookmarkRootGuid,
+        delete_everything, BookmarkRootGuid,
     },
     delete_pending_temp_tables, get_meta, put_meta,
 };
@@ -27,8 +29,9 @@ use interrupt_support::SqlInterruptScope;
 use rusqlite::Row;
 use sql_support::ConnExt;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use sync15::engine::{
     CollSyncIds, CollectionRequest, EngineSyncAssociation, IncomingChangeset, OutgoingChangeset,
@@ -50,6 +53,14 @@ pub const COLLECTION_NAME: &str = "bookmarks";
 /// blocking writes from other connections.
 const MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK: usize = 400;
 
+/// The largest encrypted record the server accepts, until it tells us its
+/// own limit in its `info/configuration`.
+const MAX_RECORD_PAYLOAD_BYTES: usize = 256 * 1024;
+
+/// The base64 IV, hex HMAC, and JSON wrapper around an encrypted record's
+/// ciphertext, rounded up.
+const ENCRYPTED_PAYLOAD_OVERHEAD_BYTES: usize = 128;
+
 /// Adapts an interruptee to a Dogear abort signal.
 struct MergeInterruptee<'a>(&'a SqlInterruptScope);
 
@@ -433,9 +444,49 @@ fn update_local_items_in_places<'t>(
         },
     )?;
 
+    note_applied_changes(db, ops);
     Ok(())
 }
 
+/// Notes the bookmarks the merge inserted, updated and deleted, for the
+/// change observer. They're delivered once the merge commits.
+fn note_applied_changes(db: &PlacesDb, ops: &CompletionOps<'_>) {
+    if !observer::is_observed(db) {
+        return;
+    }
+    // New items always have a structure op, which tells us their parent.
+    let parents: HashMap<&str, &str> = ops
+        .apply_new_local_structure
+        .iter()
+        .map(|op| {
+            (
+                op.merged_node.guid.as_str(),
+                op.merged_parent_node.guid.as_str(),
+            )
+        })
+        .collect();
+    for op in &ops.apply_remote_items {
+        let guid = op.merged_node.guid.as_str();
+        let is_new = op.merged_node.merge_state.local_node().is_none();
+        let change = match parents.get(guid) {
+            Some(parent_guid) if is_new => PlacesChange::BookmarkInserted {
+                guid: guid.into(),
+                parent_guid: (*parent_guid).into(),
+            },
+            _ => PlacesChange::BookmarkUpdated { guid: guid.into() },
+        };
+        observer::note(db, change);
+    }
+    for op in &ops.delete_local_items {
+        observer::note(
+            db,
+            PlacesChange::BookmarkDeleted {
+                guid: op.local_node().guid.as_str().into(),
+            },
+        );
+    }
+}
+
 fn apply_remote_items(db: &PlacesDb, scope: &SqlInterruptScope, now: Timestamp) -> Result<()> {
     // Remove all keywords from old and new URLs, and remove new keywords
     // from all existing URLs. The `NOT NULL` conditions are important; they
@@ -642,16 +693,25 @@ fn stage_items_to_upload(
     Ok(())
 }
 
-/// Inflates Sync records for all staged outgoing items.
+/// Inflates Sync records for all staged outgoing items. Records which would
+/// be larger than `max_payload_bytes` once encrypted are left out, so that
+/// they don't fail the whole upload, and are recorded in
+/// `moz_bookmarks_too_large_to_sync` instead. Everything in a folder that's
+/// left out is left out too, because other devices couldn't place it without
+/// its folder. They all stay changed, so we'll try again on the next sync -
+/// if the user fixes the item, it'll be uploaded.
 fn fetch_outgoing_records(
     db: &PlacesDb,
     scope: &SqlInterruptScope,
     timestamp: ServerTimestamp,
+    max_payload_bytes: usize,
 ) -> Result<OutgoingChangeset> {
     let mut outgoing = OutgoingChangeset::new(COLLECTION_NAME, timestamp);
+    db.execute_batch("DELETE FROM moz_bookmarks_too_large_to_sync")?;
     let mut child_record_ids_by_local_parent_id: HashMap<i64, Vec<BookmarkRecordId>> =
         HashMap::new();
     let mut tags_by_local_id: HashMap<i64, Vec<String>> = HashMap::new();
+    let mut too_large_folder_ids = Vec::new();
 
     let mut stmt = db.prepare(
         "SELECT parentId, guid FROM structureToUpload
@@ -695,10 +755,12 @@ fn fetch_outgoing_records(
             ));
             continue;
         }
+        let local_guid = guid.clone();
         let parent_guid = row.get::<_, SyncGuid>("parentGuid")?;
         let parent_title = row.get::<_, String>("parentTitle")?;
         let date_added = row.get::<_, i64>("dateAdded")?;
-        let record: BookmarkItemRecord = match SyncedBookmarkKind::from_u8(row.get("kind")?)? {
+        let kind = SyncedBookmarkKind::from_u8(row.get("kind")?)?;
+        let record: BookmarkItemRecord = match kind {
             SyncedBookmarkKind::Bookmark => {
                 let local_id = row.get::<_, i64>("id")?;
                 let title = row.get::<_, String>("title")?;
@@ -762,12 +824,101 @@ fn fetch_outgoing_records(
                 .into()
             }
         };
-        outgoing.changes.push(Payload::from_record(record)?);
+        let payload = Payload::from_record(record)?;
+        let payload_size = estimated_encrypted_size(&payload)?;
+        if payload_size >= max_payload_bytes {
+            log::warn!(
+                "Not uploading bookmark {}: too large to sync ({} b)",
+                local_guid,
+                payload_size
+            );
+            db.execute_cached(
+                "INSERT OR REPLACE INTO moz_bookmarks_too_large_to_sync(bookmark_id, payload_size)
+                 SELECT id, :payload_size FROM moz_bookmarks WHERE guid = :guid",
+                rusqlite::named_params! {
+                    ":payload_size": payload_size as i64,
+                    ":guid": local_guid,
+                },
+            )?;
+            if kind == SyncedBookmarkKind::Folder {
+                too_large_folder_ids.push(row.get::<_, i64>("id")?);
+            }
+            continue;
+        }
+        outgoing.changes.push(payload);
+    }
+
+    if !too_large_folder_ids.is_empty() {
+        let descendants = fetch_descendant_guids(db, &too_large_folder_ids)?;
+        outgoing
+            .changes
+            .retain(|payload| !descendants.contains(&payload.id));
     }
 
     Ok(outgoing)
 }
 
+/// Returns the GUIDs of everything in the folders with the given local IDs,
+/// including everything in their subfolders.
+fn fetch_descendant_guids(db: &PlacesDb, folder_ids: &[i64]) -> Result<HashSet<SyncGuid>> {
+    let mut guids = HashSet::new();
+    sql_support::each_chunk(folder_ids, |chunk, _| -> Result<()> {
+        guids.extend(db.query_rows_and_then(
+            &format!(
+                "WITH RECURSIVE descendants(id) AS (
+                   SELECT id FROM moz_bookmarks
+                   WHERE parent IN ({})
+                   UNION ALL
+                   SELECT b.id FROM moz_bookmarks b
+                   JOIN descendants d ON d.id = b.parent
+                 )
+                 SELECT b.guid FROM moz_bookmarks b
+                 JOIN descendants d ON d.id = b.id",
+                sql_support::repeat_sql_vars(chunk.len())
+            ),
+            rusqlite::params_from_iter(chunk),
+            |row| row.get::<_, SyncGuid>(0),
+        )?);
+        Ok(())
+    })?;
+    Ok(guids)
+}
+
+/// Estimates how large `payload` will be once it's encrypted: the cleartext
+/// is padded to the AES block size, then base64-encoded, and sent along with
+/// the IV and HMAC.
+fn estimated_encrypted_size(payload: &Payload) -> Result<usize> {
+    let cleartext_len = serde_json::to_string(payload)?.len();
+    let ciphertext_len = (cleartext_len / 16 + 1) * 16;
+    Ok((ciphertext_len + 2) / 3 * 4 + ENCRYPTED_PAYLOAD_OVERHEAD_BYTES)
+}
+
+/// A bookmark which was left out of the last sync because its record is
+/// larger than the server accepts. This usually means a folder with a huge
+/// number of children, or a bookmark with a huge URL or lots of tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookmarkTooLargeToSync {
+    pub guid: SyncGuid,
+    /// Our estimate of the encrypted record's size, in bytes.
+    pub payload_size: u32,
+}
+
+/// Returns the bookmarks which were too large to upload on the last sync.
+pub fn get_too_large_to_sync(db: &PlacesDb) -> Result<Vec<BookmarkTooLargeToSync>> {
+    db.query_rows_and_then_cached(
+        "SELECT b.guid, t.payload_size
+         FROM moz_bookmarks_too_large_to_sync t
+         JOIN moz_bookmarks b ON b.id = t.bookmark_id",
+        [],
+        |row| -> Result<_> {
+            Ok(BookmarkTooLargeToSync {
+                guid: row.get("guid")?,
+                payload_size: row.get("payload_size")?,
+            })
+        },
+    )
+}
+
 /// Decrements the change counter, updates the sync status, and cleans up
 /// tombstones for successfully synced items. Sync calls this method at the
 /// end of each bookmark sync.
@@ -831,8 +982,7 @@ pub(crate) fn update_frecencies(db: &PlacesDb, scope: &SqlInterruptScope) -> Res
             // Frecency recalculation runs several statements, so check to
             // make sure we aren't interrupted before each calculation.
             scope.err_if_interrupted()?;
-            let frecency =
-                calculate_frecency(db, &DEFAULT_FRECENCY_SETTINGS, place_id, Some(false))?;
+            let frecency = calculate_frecency(db, db.frecency_settings(), place_id, Some(false))?;
             frecencies.push((place_id, frecency));
         }
         if frecencies.is_empty() {
@@ -888,6 +1038,7 @@ pub struct BookmarksSyncEngine {
     // Pub so that it can be used by the PlacesApi methods.  Once all syncing goes through the
     // `SyncManager` we should be able to make this private.
     pub(crate) scope: SqlInterruptScope,
+    max_record_payload_bytes: AtomicUsize,
 }
 
 impl BookmarksSyncEngine {
@@ -895,6 +1046,7 @@ impl BookmarksSyncEngine {
         Ok(Self {
             scope: db.begin_interrupt_scope()?,
             db,
+            max_record_payload_bytes: AtomicUsize::new(MAX_RECORD_PAYLOAD_BYTES),
         })
     }
 }
@@ -905,17 +1057,26 @@ impl SyncEngine for BookmarksSyncEngine {
         COLLECTION_NAME.into()
     }
 
+    fn set_max_record_payload_bytes(&self, max_bytes: usize) {
+        self.max_record_payload_bytes
+            .store(max_bytes, Ordering::Relaxed);
+    }
+
     fn apply_incoming(
         &self,
         inbound: Vec<IncomingChangeset>,
         telem: &mut telemetry::Engine,
     ) -> anyhow::Result<OutgoingChangeset> {
+        let _span = spans::enter("bookmarks_sync_apply_incoming");
         let conn = self.db.lock();
         assert_eq!(inbound.len(), 1, "bookmarks only requests one item");
         let inbound = inbound.into_iter().next().unwrap();
         // Stage all incoming items.
         let mut incoming_telemetry = telemetry::EngineIncoming::new();
-        let timestamp = stage_incoming(&conn, &self.scope, inbound, &mut incoming_telemetry)?;
+        let timestamp = {
+            let _span = spans::enter("bookmarks_sync_stage_incoming");
+            stage_incoming(&conn, &self.scope, inbound, &mut incoming_telemetry)?
+        };
         telem.incoming(incoming_telemetry);
 
         // write the timestamp now, so if we are interrupted merging or
@@ -924,11 +1085,23 @@ impl SyncEngine for BookmarksSyncEngine {
         put_meta(&conn, LAST_SYNC_META_KEY, &(timestamp.as_millis() as i64))?;
 
         // Merge.
-        let mut merger = Merger::with_telemetry(&conn, &self.scope, timestamp, telem);
-        merger.merge()?;
+        {
+            let _span = spans::enter("bookmarks_sync_merge");
+            let mut merger = Merger::with_telemetry(&conn, &self.scope, timestamp, telem);
+            merger.merge()?;
+        }
+        if let Some(pending) = observer::take_pending(&conn) {
+            pending.deliver();
+        }
 
         // Finally, stage outgoing items.
-        let outgoing = fetch_outgoing_records(&conn, &self.scope, timestamp)?;
+        let _span = spans::enter("bookmarks_sync_fetch_outgoing");
+        let outgoing = fetch_outgoing_records(
+            &conn,
+            &self.scope,
+            timestamp,
+            self.max_record_payload_bytes.load(Ordering::Relaxed),
+        )?;
         Ok(outgoing)
     }
 
@@ -937,6 +1110,7 @@ impl SyncEngine for BookmarksSyncEngine {
         new_timestamp: ServerTimestamp,
         records_synced: Vec<SyncGuid>,
     ) -> anyhow::Result<()> {
+        let _span = spans::enter("bookmarks_sync_finished");
         let conn = self.db.lock();
         push_synced_items(&conn, &self.scope, new_timestamp, records_synced)?;
         update_frecencies(&conn, &self.scope)?;
@@ -1013,6 +1187,14 @@ impl SyncEngine for BookmarksSyncEngine {
         tx.commit()?;
         Ok(())
     }
+
+    /// Erases all local items, and resets the sync metadata, so that the next
+    /// sync downloads the full remote tree without deleting anything on the
+    /// server.
+    fn wipe_local(&self) -> anyhow::Result<()> {
+        delete_everything(&self.db.lock())?;
+        Ok(())
+    }
 }
 
 #[derive(Default)]
@@ -2146,6 +2328,75 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_apply_notes_changes() {
+        #[derive(Default)]
+        struct Recorder(parking_lot::Mutex<Vec<(Vec<PlacesChange>, ConnectionType)>>);
+
+        impl observer::PlacesChangeObserver for Arc<Recorder> {
+            fn on_changes(&self, changes: Vec<PlacesChange>, source: ConnectionType) {
+                self.0.lock().push((changes, source));
+            }
+        }
+
+        let api = new_mem_api();
+        let recorder = Arc::new(Recorder::default());
+        api.set_change_observer(Box::new(Arc::clone(&recorder)));
+
+        apply_incoming(
+            &api,
+            ServerTimestamp(0),
+            json!([{
+                "id": "bookmark1___",
+                "type": "bookmark",
+                "parentid": "unfiled",
+                "parentName": "Unfiled Bookmarks",
+                "dateAdded": 1_381_542_355_843u64,
+                "title": "Some bookmark",
+                "bmkUri": "http://example.com",
+            },
+            {
+                "id": "unfiled",
+                "type": "folder",
+                "parentid": "places",
+                "dateAdded": 1_381_542_355_843u64,
+                "title": "Unfiled",
+                "children": ["bookmark1___"],
+            }]),
+        );
+        apply_incoming(
+            &api,
+            ServerTimestamp(1),
+            json!([{
+                "id": "bookmark1___",
+                "deleted": true,
+            },
+            {
+                "id": "unfiled",
+                "type": "folder",
+                "parentid": "places",
+                "dateAdded": 1_381_542_355_843u64,
+                "title": "Unfiled",
+                "children": [],
+            }]),
+        );
+
+        let seen = recorder.0.lock();
+        assert_eq!(seen.len(), 2);
+        assert!(seen
+            .iter()
+            .all(|(_, source)| *source == ConnectionType::Sync));
+        assert!(seen[0].0.contains(&PlacesChange::BookmarkInserted {
+            guid: "bookmark1___".into(),
+            parent_guid: BookmarkRootGuid::Unfiled.as_guid(),
+        }));
+        assert!(seen[1].0.contains(&PlacesChange::BookmarkDeleted {
+            guid: "bookmark1___".into(),
+        }));
+        drop(seen);
+        api.clear_change_observer();
+    }
+
     #[test]
     fn test_apply_complex_bookmark_tags() -> Result<()> {
         let api = new_mem_api();
@@ -2714,7 +2965,12 @@ mod tests {
             json!({"children" : [{"guid": "bookmarkAAAA", "url": "http://example.com/a?b=c&d=%s"}]}),
         );
 
-        let outgoing = fetch_outgoing_records(&db, &interrupt_scope, ServerTimestamp(0))?;
+        let outgoing = fetch_outgoing_records(
+            &db,
+            &interrupt_scope,
+            ServerTimestamp(0),
+            MAX_RECORD_PAYLOAD_BYTES,
+        )?;
         let record_for_a = outgoing
             .changes
             .iter()
@@ -2726,6 +2982,129 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_too_large_to_sync() -> Result<()> {
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        let long_url = format!("https://example.com/{}", "a".repeat(2000));
+        insert_local_json_tree(
+            &writer,
+            json!({
+                "guid": &BookmarkRootGuid::Unfiled.as_guid(),
+                "children": [
+                    {
+                        "guid": "bookmarkAAAA",
+                        "url": "https://example.com/a",
+                    },
+                    {
+                        "guid": "bookmarkBBBB",
+                        "url": long_url,
+                    },
+                ],
+            }),
+        );
+
+        let db_mutex = api.get_sync_connection().unwrap();
+        let db = db_mutex.lock();
+        let interrupt_scope = db.begin_interrupt_scope()?;
+        let mut merger = Merger::new(&db, &interrupt_scope, ServerTimestamp(0));
+        merger.merge()?;
+
+        let outgoing = fetch_outgoing_records(&db, &interrupt_scope, ServerTimestamp(0), 1024)?;
+        assert!(outgoing
+            .changes
+            .iter()
+            .any(|payload| payload.id == "bookmarkAAAA"));
+        assert!(!outgoing
+            .changes
+            .iter()
+            .any(|payload| payload.id == "bookmarkBBBB"));
+
+        let too_large = get_too_large_to_sync(&db)?;
+        assert_eq!(too_large.len(), 1);
+        assert_eq!(too_large[0].guid, "bookmarkBBBB");
+        assert!(too_large[0].payload_size >= 1024);
+
+        // Once the record fits, it's uploaded, and we forget it was too large.
+        let outgoing = fetch_outgoing_records(
+            &db,
+            &interrupt_scope,
+            ServerTimestamp(0),
+            MAX_RECORD_PAYLOAD_BYTES,
+        )?;
+        assert!(outgoing
+            .changes
+            .iter()
+            .any(|payload| payload.id == "bookmarkBBBB"));
+        assert!(get_too_large_to_sync(&db)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_too_large_folder_skips_descendants() -> Result<()> {
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        let children = (0..50)
+            .map(|i| json!({ "url": format!("https://example.com/{}", i) }))
+            .chain(std::iter::once(json!({
+                "guid": "folderBBBBBB",
+                "title": "B",
+                "children": [{
+                    "guid": "bookmarkCCCC",
+                    "url": "https://example.com/c",
+                }],
+            })))
+            .collect::<Vec<_>>();
+        insert_local_json_tree(
+            &writer,
+            json!({
+                "guid": &BookmarkRootGuid::Unfiled.as_guid(),
+                "children": [
+                    {
+                        "guid": "bookmarkDDDD",
+                        "url": "https://example.com/d",
+                    },
+                    {
+                        "guid": "folderAAAAAA",
+                        "title": "A",
+                        "children": children,
+                    },
+                ],
+            }),
+        );
+
+        let db_mutex = api.get_sync_connection().unwrap();
+        let db = db_mutex.lock();
+        let interrupt_scope = db.begin_interrupt_scope()?;
+        let mut merger = Merger::new(&db, &interrupt_scope, ServerTimestamp(0));
+        merger.merge()?;
+
+        let outgoing = fetch_outgoing_records(&db, &interrupt_scope, ServerTimestamp(0), 1024)?;
+        let ids = outgoing
+            .changes
+            .iter()
+            .map(|payload| payload.id.as_str())
+            .collect::<Vec<_>>();
+        assert!(ids.contains(&"unfiled"));
+        assert!(ids.contains(&"bookmarkDDDD"));
+        // Everything in the folder is left out along with it, including
+        // items in its subfolders. Only the roots, whose record IDs aren't
+        // GUIDs, and D are uploaded.
+        assert_eq!(
+            ids.iter()
+                .filter(|id| SyncGuid::from(**id).is_valid_for_places())
+                .collect::<Vec<_>>(),
+            vec![&"bookmarkDDDD"]
+        );
+
+        let too_large = get_too_large_to_sync(&db)?;
+        assert_eq!(too_large.len(), 1);
+        assert_eq!(too_large[0].guid, "folderAAAAAA");
+
+        Ok(())
+    }
+
     #[test]
     fn test_apply_query() {
         // should we add some more query variations here?
@@ -2,10 +2,14 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use super::schema;
+use super::{encryption, schema};
 use crate::api::places_api::ConnectionType;
 use crate::error::*;
-use interrupt_support::{SqlInterruptHandle, SqlInterruptScope};
+use crate::frecency::FrecencySettings;
+use crate::observer::{self, ChangeQueue};
+use interrupt_support::{
+    InterruptPriority, SqlInterruptHandle, SqlInterruptOperation, SqlInterruptScope,
+};
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
 use rusqlite::{self, Connection, Transaction};
@@ -33,6 +37,7 @@ lazy_static! {
 pub struct PlacesInitializer {
     api_id: usize,
     conn_type: ConnectionType,
+    encryption_key: Option<String>,
 }
 
 impl ConnectionInitializer for PlacesInitializer {
@@ -47,6 +52,13 @@ impl ConnectionInitializer for PlacesInitializer {
         Ok(schema::upgrade_from(tx, version)?)
     }
 
+    fn unlock(&self, conn: &Connection) -> open_database::Result<()> {
+        match &self.encryption_key {
+            Some(key) => encryption::unlock(conn, key),
+            None => Ok(()),
+        }
+    }
+
     fn prepare(&self, conn: &Connection) -> open_database::Result<()> {
         let initial_pragmas = "
             -- The value we use was taken from Desktop Firefox, and seems necessary to
@@ -103,6 +115,16 @@ pub struct PlacesDb {
     interrupt_handle: Arc<SqlInterruptHandle>,
     api_id: usize,
     pub(super) coop_tx_lock: Arc<Mutex<()>>,
+    frecency_settings: Arc<FrecencySettings>,
+    // Changes made on this connection which haven't been delivered to the
+    // API's observer yet. See the `observer` module.
+    pub(crate) pending_changes: Arc<Mutex<ChangeQueue>>,
+    // The key the database is encrypted with, if it is. Shared with the API,
+    // so that connections it opens after a rekey use the new key.
+    encryption_key: Arc<Mutex<Option<String>>>,
+    // Whether this connection was opened for a snapshot, in which case it's in
+    // a read transaction until it's closed. See the `tx` module.
+    pub(super) in_snapshot: bool,
 }
 
 impl PlacesDb {
@@ -112,6 +134,8 @@ impl PlacesDb {
         api_id: usize,
         coop_tx_lock: Arc<Mutex<()>>,
     ) -> Self {
+        let pending_changes = Arc::default();
+        observer::install_hooks(&db, &pending_changes);
         Self {
             interrupt_handle: Arc::new(SqlInterruptHandle::new(&db)),
             db,
@@ -119,6 +143,11 @@ impl PlacesDb {
             // The API sets this explicitly.
             api_id,
             coop_tx_lock,
+            // The API sets this for the connections which write.
+            frecency_settings: Default::default(),
+            pending_changes,
+            encryption_key: Default::default(),
+            in_snapshot: false,
         }
     }
 
@@ -128,9 +157,27 @@ impl PlacesDb {
         api_id: usize,
         coop_tx_lock: Arc<Mutex<()>>,
     ) -> Result<Self> {
-        let initializer = PlacesInitializer { api_id, conn_type };
+        Self::open_with_key(path, Default::default(), conn_type, api_id, coop_tx_lock)
+    }
+
+    /// Like `open`, but for a database encrypted with SQLCipher, if
+    /// `encryption_key` holds a key.
+    pub fn open_with_key(
+        path: impl AsRef<Path>,
+        encryption_key: Arc<Mutex<Option<String>>>,
+        conn_type: ConnectionType,
+        api_id: usize,
+        coop_tx_lock: Arc<Mutex<()>>,
+    ) -> Result<Self> {
+        let initializer = PlacesInitializer {
+            api_id,
+            conn_type,
+            encryption_key: encryption_key.lock().clone(),
+        };
         let conn = open_database_with_flags(path, conn_type.rusqlite_flags(), &initializer)?;
-        Ok(Self::with_connection(conn, conn_type, api_id, coop_tx_lock))
+        let mut db = Self::with_connection(conn, conn_type, api_id, coop_tx_lock);
+        db.encryption_key = encryption_key;
+        Ok(db)
     }
 
     #[cfg(test)]
@@ -140,6 +187,7 @@ impl PlacesDb {
         let initializer = PlacesInitializer {
             api_id: 0,
             conn_type,
+            encryption_key: None,
         };
         let conn = open_database::open_memory_database_with_flags(
             conn_type.rusqlite_flags(),
@@ -157,11 +205,53 @@ impl PlacesDb {
         Arc::clone(&self.interrupt_handle)
     }
 
+    pub(crate) fn set_frecency_settings(&mut self, frecency_settings: Arc<FrecencySettings>) {
+        self.frecency_settings = frecency_settings;
+    }
+
+    /// The settings frecencies are calculated with on this connection.
+    #[inline]
+    pub fn frecency_settings(&self) -> &FrecencySettings {
+        &self.frecency_settings
+    }
+
     #[inline]
     pub fn begin_interrupt_scope(&self) -> Result<SqlInterruptScope> {
         Ok(self.interrupt_handle.begin_interrupt_scope()?)
     }
 
+    /// Runs the work done on this thread until the returned value is dropped
+    /// at `priority`. See `SqlInterruptHandle::begin_operation()`.
+    #[inline]
+    pub fn begin_operation(&self, priority: InterruptPriority) -> SqlInterruptOperation {
+        self.interrupt_handle.begin_operation(priority)
+    }
+
+    /// Re-encrypts the database with `new_key`. Only the write connection can
+    /// do this, and every other connection to the database has to be closed
+    /// first. Connections opened afterwards use the new key.
+    pub fn rekey(&self, new_key: &str) -> Result<()> {
+        if self.conn_type != ConnectionType::ReadWrite {
+            return Err(Error::InvalidConnectionType);
+        }
+        let mut encryption_key = self.encryption_key.lock();
+        if encryption_key.is_none() {
+            return Err(Error::DatabaseNotEncrypted);
+        }
+        encryption::rekey(&self.db, new_key)?;
+        *encryption_key = Some(new_key.to_string());
+        Ok(())
+    }
+
+    /// Frees as much of the memory this connection holds as we can: SQLite's
+    /// page cache, and our cache of prepared statements. Both fill up again
+    /// as the connection is used.
+    pub fn release_memory(&self) -> Result<()> {
+        self.db.flush_prepared_statement_cache();
+        self.db.execute_batch("PRAGMA shrink_memory")?;
+        Ok(())
+    }
+
     #[inline]
     pub fn conn_type(&self) -> ConnectionType {
         self.conn_type
@@ -183,6 +273,9 @@ impl PlacesDb {
 
 impl Drop for PlacesDb {
     fn drop(&mut self) {
+        if let Err(e) = self.end_snapshot() {
+            log::warn!("Failed to end snapshot: {}", e);
+        }
         // In line with both the recommendations from SQLite and the behavior of places in
         // Database.cpp, we run `PRAGMA optimize` before closing the connection.
         let res = self.db.execute_batch("PRAGMA optimize(0x02);");
@@ -0,0 +1,156 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Support for encrypting the places database with SQLCipher. Most apps don't
+// encrypt it, so everything here only happens when the app opens the
+// database with a key.
+//
+// SQLCipher is only linked when the `sqlcipher` feature is enabled, as it is
+// in the megazords, so we check it's there before opening a database with a
+// key: plain SQLite ignores `PRAGMA key`, and would quietly write the
+// database unencrypted.
+
+use crate::error::*;
+use rusqlite::{Connection, ErrorCode};
+use sql_support::{open_database, ConnExt};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// Matches the `page_size` we use for unencrypted databases. SQLCipher needs
+// it set before it reads the database, and it has to be the same every time
+// the database is opened.
+const CIPHER_PAGE_SIZE: u32 = 32768;
+
+// Every unencrypted SQLite database starts with this. An encrypted one
+// starts with its salt.
+const PLAINTEXT_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Sets the key for `conn`, and checks that the database can be read with
+/// it. This has to happen before anything else reads the database.
+pub(crate) fn unlock(conn: &Connection, key: &str) -> open_database::Result<()> {
+    conn.set_pragma("key", key)?
+        .set_pragma("cipher_page_size", CIPHER_PAGE_SIZE)?;
+    match conn.query_one::<u32>("SELECT COUNT(*) FROM sqlite_master") {
+        Ok(_) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::NotADatabase => {
+            Err(open_database::Error::IncorrectKey)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns an error if SQLCipher isn't linked, so we can't encrypt the
+/// database.
+pub(crate) fn ensure_available() -> Result<()> {
+    let conn = Connection::open_in_memory()?;
+    match conn.try_query_one::<String, _>("PRAGMA cipher_version", [], false)? {
+        Some(_) => Ok(()),
+        None => Err(Error::EncryptionUnavailable),
+    }
+}
+
+/// Returns true if there's an unencrypted database at `path`. A missing or
+/// empty file isn't a database yet, so SQLCipher can create an encrypted one.
+pub(crate) fn is_plaintext_database(path: &Path) -> Result<bool> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    let mut header = [0u8; 16];
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(&header == PLAINTEXT_HEADER),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Encrypts the unencrypted database at `path` with `key`, by exporting it
+/// to an encrypted copy which then replaces it. Nothing else can have the
+/// database open.
+pub(crate) fn encrypt_plaintext_database(path: &Path, key: &str) -> Result<()> {
+    log::info!("Encrypting the places database");
+    let mut encrypted_path = path.as_os_str().to_owned();
+    encrypted_path.push(".encrypting");
+    let encrypted_path = PathBuf::from(encrypted_path);
+    // Left over from an export that didn't finish.
+    if encrypted_path.exists() {
+        fs::remove_file(&encrypted_path)?;
+    }
+    {
+        let conn = Connection::open(path)?;
+        // Move everything from the WAL into the database, so we export it,
+        // and so we can delete the WAL afterwards.
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        // `sqlcipher_export` doesn't copy the schema version.
+        let user_version = conn.query_one::<u32>("PRAGMA user_version")?;
+        conn.execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            rusqlite::params![encrypted_path.to_string_lossy(), key],
+        )?;
+        conn.execute_batch(&format!(
+            "PRAGMA encrypted.cipher_page_size = {};",
+            CIPHER_PAGE_SIZE
+        ))?;
+        conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+        conn.execute_batch(&format!(
+            "PRAGMA encrypted.user_version = {};
+             DETACH DATABASE encrypted;",
+            user_version
+        ))?;
+    }
+    fs::rename(&encrypted_path, path)?;
+    for suffix in ["-wal", "-shm"] {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(suffix);
+        if let Err(e) = fs::remove_file(PathBuf::from(sidecar)) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Re-encrypts the database `conn` is open on with `new_key`. SQLCipher
+/// rewrites every page, so no other connection can have the database open:
+/// SQLite only lets us switch out of WAL mode while we're the only
+/// connection, so we check that first.
+pub(crate) fn rekey(conn: &Connection, new_key: &str) -> Result<()> {
+    let mode = conn.query_row("PRAGMA journal_mode = DELETE", [], |row| {
+        row.get::<_, String>(0)
+    })?;
+    if !mode.eq_ignore_ascii_case("delete") {
+        return Err(Error::OtherConnectionsOpen);
+    }
+    let result = conn.pragma_update(None, "rekey", new_key);
+    conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+    Ok(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_plaintext_database() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("places.sqlite");
+        assert!(!is_plaintext_database(&path)?);
+
+        fs::write(&path, b"")?;
+        assert!(!is_plaintext_database(&path)?);
+
+        fs::remove_file(&path)?;
+        Connection::open(&path)?.execute_batch("CREATE TABLE t(x)")?;
+        assert!(is_plaintext_database(&path)?);
+
+        // What an encrypted database looks like: its salt, then pages which
+        // look random.
+        fs::write(&path, [0x5au8; 4096])?;
+        assert!(!is_plaintext_database(&path)?);
+        Ok(())
+    }
+}
@@ -11,12 +11,18 @@ use crate::types::SyncStatus;
 use rusqlite::Connection;
 use sql_support::ConnExt;
 
-pub const VERSION: u32 = 15;
+pub const VERSION: u32 = 25;
 
 // Shared schema and temp tables for the read-write and Sync connections.
 const CREATE_SHARED_SCHEMA_SQL: &str = include_str!("../../sql/create_shared_schema.sql");
 const CREATE_SHARED_TEMP_TABLES_SQL: &str = include_str!("../../sql/create_shared_temp_tables.sql");
 
+// Created separately from the shared schema, because older versions of
+// `moz_places_metadata` don't have a `guid` column until they're upgraded.
+const CREATE_METADATA_GUID_INDEX_SQL: &str =
+    "CREATE UNIQUE INDEX IF NOT EXISTS moz_places_metadata_guid_uniqueindex
+     ON moz_places_metadata(guid)";
+
 // Sync-specific temp tables and triggers.
 const CREATE_SYNC_TEMP_TABLES_SQL: &str = include_str!("../../sql/create_sync_temp_tables.sql");
 const CREATE_SYNC_TRIGGERS_SQL: &str = include_str!("../../sql/create_sync_triggers.sql");
@@ -72,6 +78,7 @@ fn update_origin_frecency_stats(op: &str) -> String {
 pub fn init(conn: &Connection) -> rusqlite::Result<()> {
     log::debug!("Initializing schema");
     conn.execute_batch(CREATE_SHARED_SCHEMA_SQL)?;
+    conn.execute_batch(CREATE_METADATA_GUID_INDEX_SQL)?;
     create_bookmark_roots(conn)?;
     Ok(())
 }
@@ -259,6 +266,55 @@ pub fn upgrade_from(db: &Connection, from: u32) -> rusqlite::Result<()> {
         ],
         || Ok(()),
     )?;
+    migration(db, from, 15, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?; // moz_bookmarks_import_sources.
+    migration(db, from, 16, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?; // parentindex.
+    migration(db, from, 17, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?; // moz_places_metadata_fetch_attempts.
+    migration(db, from, 18, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?; // moz_bookmarks_too_large_to_sync.
+    migration(db, from, 19, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?; // moz_pinned_sites.
+    migration(db, from, 20, &[CREATE_SHARED_SCHEMA_SQL], || {
+        // Syncing history metadata. If `moz_places_metadata` was created by
+        // an earlier migration, it already has the new columns.
+        let has_guid: bool = db.query_row(
+            "SELECT EXISTS(SELECT 1 FROM pragma_table_info('moz_places_metadata')
+                           WHERE name = 'guid')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_guid {
+            db.execute_batch(
+                "ALTER TABLE moz_places_metadata ADD COLUMN guid TEXT;
+                 ALTER TABLE moz_places_metadata
+                 ADD COLUMN sync_change_counter INTEGER NOT NULL DEFAULT 1;",
+            )?;
+        }
+        db.execute_batch(
+            "UPDATE moz_places_metadata SET guid = generate_guid() WHERE guid IS NULL",
+        )?;
+        db.execute_batch(CREATE_METADATA_GUID_INDEX_SQL)
+    })?;
+    migration(
+        db,
+        from,
+        21,
+        &["ALTER TABLE moz_historyvisits ADD COLUMN container_id TEXT"],
+        || Ok(()),
+    )?;
+    migration(db, from, 22, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?; // moz_bookmarks_trash.
+    migration(db, from, 23, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?; // moz_changes.
+    migration(db, from, 24, &[], || {
+        // If `moz_places_metadata` was created by an earlier migration, it
+        // already has the new column.
+        let has_session_id: bool = db.query_row(
+            "SELECT EXISTS(SELECT 1 FROM pragma_table_info('moz_places_metadata')
+                           WHERE name = 'session_id')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_session_id {
+            db.execute_batch("ALTER TABLE moz_places_metadata ADD COLUMN session_id TEXT")?;
+        }
+        Ok(())
+    })?;
 
     // Add more migrations here...
     Ok(())
@@ -831,4 +887,52 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_upgrade_schema_20_21() -> Result<()> {
+        let path = "file:test_upgrade_schema_20_21?mode=memory&cache=shared";
+
+        // Recreate `moz_places_metadata` as it was before it was synced.
+        let db = PlacesDb::open(path, ConnectionType::ReadWrite, 0, Default::default())
+            .expect("Should open first in-memory database with shared cache");
+        db.execute_batch(
+            "PRAGMA user_version = 20;
+             DROP TABLE moz_places_metadata;
+             CREATE TABLE moz_places_metadata (
+                 id INTEGER PRIMARY KEY,
+                 created_at INTEGER NOT NULL DEFAULT 0,
+                 updated_at INTEGER NOT NULL DEFAULT 0,
+                 place_id INTEGER NOT NULL,
+                 total_view_time INTEGER NOT NULL DEFAULT 0,
+                 search_query_id INTEGER,
+                 referrer_place_id INTEGER,
+                 document_type INTEGER NOT NULL DEFAULT 0,
+                 typing_time INTEGER NOT NULL DEFAULT 0,
+                 key_presses INTEGER NOT NULL DEFAULT 0
+             );
+             INSERT INTO moz_places(guid, url, url_hash)
+             VALUES('fake_guid_1_', 'https://example.com/', hash('https://example.com/'));
+             INSERT INTO moz_places_metadata(place_id, created_at, updated_at)
+             SELECT id, 1, 1 FROM moz_places WHERE guid = 'fake_guid_1_';",
+        )?;
+
+        let upgrade = PlacesDb::open(path, ConnectionType::ReadWrite, 0, Default::default())
+            .expect("Should open second in-memory database with shared cache");
+        assert_eq!(
+            get_current_schema_version(&upgrade)?,
+            VERSION,
+            "Should upgrade schema without errors"
+        );
+        // Existing metadata gets a guid, and will be uploaded.
+        assert_eq!(
+            select_simple_int(
+                &upgrade,
+                "SELECT sync_change_counter FROM moz_places_metadata
+                 WHERE length(guid) = 12"
+            ),
+            1
+        );
+
+        Ok(())
+    }
 }
@@ -22,6 +22,10 @@ enum PlacesTransactionRepr<'conn> {
     // Note: these might seem pointless, but can allow us to ensure consistency
     // between separate reads.
     ReadOnly(UncheckedTransaction<'conn>),
+    // A snapshot connection is already in a transaction, which this is nested
+    // in. Committing or rolling back does nothing - the snapshot's
+    // transaction ends when the connection is closed.
+    Snapshot(&'conn Connection),
 }
 
 impl<'conn> PlacesTransaction<'conn> {
@@ -62,6 +66,7 @@ impl<'conn> PlacesTransaction<'conn> {
             PlacesTransactionRepr::ChunkedWrite(t) => t.commit()?,
             PlacesTransactionRepr::UnchunkedWrite(t) => t.commit()?,
             PlacesTransactionRepr::ReadOnly(t) => t.commit()?,
+            PlacesTransactionRepr::Snapshot(_) => (),
         };
         Ok(())
     }
@@ -74,6 +79,7 @@ impl<'conn> PlacesTransaction<'conn> {
             PlacesTransactionRepr::ChunkedWrite(t) => t.rollback()?,
             PlacesTransactionRepr::UnchunkedWrite(t) => t.rollback()?,
             PlacesTransactionRepr::ReadOnly(t) => t.rollback()?,
+            PlacesTransactionRepr::Snapshot(_) => (),
         };
         Ok(())
     }
@@ -84,7 +90,8 @@ impl super::PlacesDb {
     ///
     /// - For Sync connections, begins a chunked coop transaction.
     /// - for ReadWrite connections, begins a normal coop transaction
-    /// - for ReadOnly connections, begins an unchecked transaction.
+    /// - for ReadOnly connections, begins an unchecked transaction, or nests
+    ///   in the snapshot for connections from `PlacesApi::open_snapshot()`.
     pub fn begin_transaction(&self) -> Result<PlacesTransaction<'_>> {
         Ok(PlacesTransaction(match self.conn_type() {
             ConnectionType::Sync => {
@@ -93,12 +100,41 @@ impl super::PlacesDb {
             ConnectionType::ReadWrite => {
                 PlacesTransactionRepr::UnchunkedWrite(self.coop_transaction()?)
             }
+            ConnectionType::ReadOnly if self.in_snapshot => {
+                PlacesTransactionRepr::Snapshot(&self.db)
+            }
             ConnectionType::ReadOnly => {
                 // Use an unchecked transaction with no locking.
                 PlacesTransactionRepr::ReadOnly(self.unchecked_transaction()?)
             }
         }))
     }
+
+    /// Begins the snapshot for a connection opened by
+    /// `PlacesApi::open_snapshot()`. It lasts until the connection is closed,
+    /// since other callers can't share the connection.
+    pub(crate) fn begin_snapshot(&mut self) -> Result<()> {
+        if self.conn_type() != ConnectionType::ReadOnly {
+            return Err(Error::InvalidConnectionType);
+        }
+        self.execute_batch("BEGIN DEFERRED")?;
+        // A deferred transaction doesn't take its snapshot until the first
+        // read, so read something now.
+        if let Err(e) = self.query_one::<i64>("SELECT COUNT(*) FROM moz_meta") {
+            self.execute_batch("ROLLBACK")?;
+            return Err(e.into());
+        }
+        self.in_snapshot = true;
+        Ok(())
+    }
+
+    // Called when the connection is closed.
+    pub(super) fn end_snapshot(&mut self) -> Result<()> {
+        if std::mem::take(&mut self.in_snapshot) && !self.is_autocommit() {
+            self.execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
 }
 
 impl<'conn> std::ops::Deref for PlacesTransaction<'conn> {
@@ -109,6 +145,7 @@ impl<'conn> std::ops::Deref for PlacesTransaction<'conn> {
             PlacesTransactionRepr::ChunkedWrite(t) => t,
             PlacesTransactionRepr::UnchunkedWrite(t) => t,
             PlacesTransactionRepr::ReadOnly(t) => t,
+            PlacesTransactionRepr::Snapshot(c) => c,
         }
     }
 }
@@ -119,3 +156,70 @@ impl<'conn> ConnExt for PlacesTransaction<'conn> {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::api::places_api::{ConnectionType, PlacesApi};
+    use crate::db::PlacesDb;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::{apply_observation, get_visit_count};
+    use crate::types::{VisitTransition, VisitTransitionSet};
+    use url::Url;
+
+    #[test]
+    fn test_snapshot() {
+        // Snapshots rely on WAL, so we need a real file rather than a shared
+        // memory database.
+        let dirname = tempfile::tempdir().unwrap();
+        let api = PlacesApi::new(dirname.path().join("places.sqlite")).expect("should open");
+        let mut writer = api
+            .open_connection(ConnectionType::ReadWrite)
+            .expect("should get writer");
+        assert!(writer.begin_snapshot().is_err());
+        let reader = api
+            .open_connection(ConnectionType::ReadOnly)
+            .expect("should get reader");
+
+        let visit = |path: &str| {
+            apply_observation(
+                &writer,
+                VisitObservation::new(
+                    Url::parse(&format!("https://example.com/{}", path)).unwrap(),
+                )
+                .with_visit_type(VisitTransition::Link),
+            )
+            .expect("should apply");
+        };
+        let count = |db: &PlacesDb| {
+            get_visit_count(db, VisitTransitionSet::empty(), None).expect("should count")
+        };
+
+        visit("a");
+        let snapshot = api.open_snapshot().expect("should open snapshot");
+        assert_eq!(count(&snapshot), 1);
+        visit("b");
+        assert_eq!(count(&snapshot), 1);
+        assert_eq!(count(&reader), 2);
+        // Transactions on the snapshot nest in it.
+        snapshot
+            .begin_transaction()
+            .expect("should begin")
+            .commit()
+            .expect("should commit");
+        assert_eq!(count(&snapshot), 1);
+
+        // Each snapshot has its own connection, so they don't affect each
+        // other.
+        let second = api.open_snapshot().expect("should open second snapshot");
+        visit("c");
+        assert_eq!(count(&second), 2);
+        assert_eq!(count(&snapshot), 1);
+        drop(second);
+        assert_eq!(count(&snapshot), 1);
+
+        // Closing the connection ends the snapshot.
+        assert!(!snapshot.is_autocommit());
+        drop(snapshot);
+        assert_eq!(count(&reader), 3);
+    }
+}
@@ -45,6 +45,11 @@ pub enum PlacesApiError {
     ///  - Attempting to insert a child under BookmarkRoot.Root,
     #[error("Invalid bookmark operation: {reason}")]
     InvalidBookmarkOperation { reason: String },
+
+    /// Thrown when opening an encrypted database with the wrong key, or
+    /// opening a database with a different key than it's already open with.
+    #[error("Incorrect database key: {reason}")]
+    IncorrectDatabaseKey { reason: String },
 }
 
 /// Error enum used internally
@@ -115,6 +120,21 @@ pub enum Error {
     #[error("Error opening database: {0}")]
     OpenDatabaseError(#[from] sql_support::open_database::Error),
 
+    #[error("The database is already open with a different key")]
+    DifferentDatabaseKey,
+
+    #[error("Invalid frecency settings: {0}")]
+    InvalidFrecencySettings(&'static str),
+
+    #[error("Can't encrypt the database, because SQLCipher isn't available")]
+    EncryptionUnavailable,
+
+    #[error("The database isn't encrypted")]
+    DatabaseNotEncrypted,
+
+    #[error("Other connections to the database are open")]
+    OtherConnectionsOpen,
+
     #[error("Invalid metadata observation: {0}")]
     InvalidMetadataObservation(#[from] InvalidMetadataObservation),
 }
@@ -208,6 +228,9 @@ impl GetErrorHandling for Error {
                     InvalidPlaceInfo::CannotUpdateRoot(..) => {
                         PlacesApiError::InvalidBookmarkOperation { reason: label }
                     }
+                    InvalidPlaceInfo::NoSuchUrl | InvalidPlaceInfo::InvalidTag => {
+                        PlacesApiError::InvalidBookmarkOperation { reason: label }
+                    }
                     _ => PlacesApiError::UnexpectedPlacesException { reason: label },
                 })
                 .report_error("places-invalid-place-info")
@@ -241,6 +264,25 @@ impl GetErrorHandling for Error {
                 })
                 .log_info()
             }
+            Error::OpenDatabaseError(sql_support::open_database::Error::IncorrectKey)
+            | Error::DifferentDatabaseKey => {
+                ErrorHandling::convert(PlacesApiError::IncorrectDatabaseKey {
+                    reason: self.to_string(),
+                })
+                .log_warning()
+            }
+            Error::InvalidFrecencySettings(..) => {
+                ErrorHandling::convert(PlacesApiError::UnexpectedPlacesException {
+                    reason: self.to_string(),
+                })
+                .log_warning()
+            }
+            Error::OtherConnectionsOpen => {
+                ErrorHandling::convert(PlacesApiError::PlacesConnectionBusy {
+                    reason: self.to_string(),
+                })
+                .log_warning()
+            }
             Error::Corruption(e) => {
                 ErrorHandling::convert(PlacesApiError::UnexpectedPlacesException {
                     reason: e.to_string(),
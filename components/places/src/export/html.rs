@@ -0,0 +1,313 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::api::places_api::{ConnectionType, PlacesApi};
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::storage::bookmarks::fetch::{fetch_tree, Item};
+use crate::storage::bookmarks::BookmarkRootGuid;
+use sql_support::ConnExt;
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+use types::Timestamp;
+
+/// Exports all bookmarks to `path`, in the `NETSCAPE-Bookmark-file-1` HTML
+/// format, which every browser knows how to import.
+///
+/// We follow desktop's layout: the children of the menu are written at the
+/// top level, and the toolbar and "other bookmarks" roots are written as
+/// folders with the attributes that tell browsers where to put them back.
+/// The mobile root doesn't have an equivalent attribute, so it's written as a
+/// plain folder, and only if it has any children.
+///
+/// Tags and the last modified dates of separators aren't exported, since the
+/// format has nowhere to put them.
+pub fn export_bookmarks(places_api: &PlacesApi, path: impl AsRef<std::path::Path>) -> Result<()> {
+    let conn = places_api.open_connection(ConnectionType::ReadOnly)?;
+    let mut writer = BufWriter::new(std::fs::File::create(path)?);
+    write_bookmarks(&conn, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+const HEADER: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file.
+     It will be read and overwritten.
+     DO NOT EDIT! -->
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks Menu</H1>
+
+<DL><p>
+"#;
+
+const FOOTER: &str = "</DL>\n";
+
+fn write_bookmarks(db: &PlacesDb, out: &mut impl Write) -> Result<()> {
+    let writer = HtmlWriter {
+        keywords: fetch_keywords(db)?,
+    };
+    out.write_all(HEADER.as_bytes())?;
+    if let Some(Item::Folder { f }) = fetch_tree(db, &BookmarkRootGuid::Menu.into())? {
+        writer.write_children(out, f.child_nodes.unwrap_or_default(), 1)?;
+    }
+    for (root, title, attribute) in [
+        (
+            BookmarkRootGuid::Toolbar,
+            "Bookmarks Toolbar",
+            Some("PERSONAL_TOOLBAR_FOLDER"),
+        ),
+        (
+            BookmarkRootGuid::Unfiled,
+            "Other Bookmarks",
+            Some("UNFILED_BOOKMARKS_FOLDER"),
+        ),
+        (BookmarkRootGuid::Mobile, "Mobile Bookmarks", None),
+    ] {
+        let folder = match fetch_tree(db, &root.into())? {
+            Some(Item::Folder { f }) => f,
+            _ => continue,
+        };
+        let children = folder.child_nodes.unwrap_or_default();
+        if attribute.is_none() && children.is_empty() {
+            continue;
+        }
+        writer.write_folder_start(
+            out,
+            title,
+            folder.date_added,
+            folder.last_modified,
+            attribute,
+            1,
+        )?;
+        writer.write_children(out, children, 2)?;
+        writer.write_folder_end(out, 1)?;
+    }
+    out.write_all(FOOTER.as_bytes())?;
+    Ok(())
+}
+
+// Keywords belong to URLs, not bookmarks, so every bookmark for a URL with a
+// keyword gets it.
+fn fetch_keywords(db: &PlacesDb) -> Result<HashMap<String, String>> {
+    let keywords = db.query_rows_and_then(
+        "SELECT h.url, k.keyword
+         FROM moz_keywords k
+         JOIN moz_places h ON h.id = k.place_id",
+        [],
+        |row| -> rusqlite::Result<_> { Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)) },
+    )?;
+    Ok(keywords.into_iter().collect())
+}
+
+struct HtmlWriter {
+    keywords: HashMap<String, String>,
+}
+
+impl HtmlWriter {
+    fn write_children(
+        &self,
+        out: &mut impl Write,
+        children: Vec<Item>,
+        depth: usize,
+    ) -> Result<()> {
+        for child in children {
+            self.write_item(out, child, depth)?;
+        }
+        Ok(())
+    }
+
+    fn write_item(&self, out: &mut impl Write, item: Item, depth: usize) -> Result<()> {
+        let indent = indent(depth);
+        match item {
+            Item::Bookmark { b } => {
+                write!(
+                    out,
+                    r#"{}<DT><A HREF="{}" ADD_DATE="{}" LAST_MODIFIED="{}""#,
+                    indent,
+                    escape(b.url.as_str()),
+                    seconds(b.date_added),
+                    seconds(b.last_modified),
+                )?;
+                if let Some(keyword) = self.keywords.get(b.url.as_str()) {
+                    write!(out, r#" SHORTCUTURL="{}""#, escape(keyword))?;
+                }
+                writeln!(out, ">{}</A>", escape(b.title.as_deref().unwrap_or("")))?;
+            }
+            Item::Separator { .. } => {
+                writeln!(out, "{}<HR>", indent)?;
+            }
+            Item::Folder { f } => {
+                self.write_folder_start(
+                    out,
+                    f.title.as_deref().unwrap_or(""),
+                    f.date_added,
+                    f.last_modified,
+                    None,
+                    depth,
+                )?;
+                self.write_children(out, f.child_nodes.unwrap_or_default(), depth + 1)?;
+                self.write_folder_end(out, depth)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_folder_start(
+        &self,
+        out: &mut impl Write,
+        title: &str,
+        date_added: Timestamp,
+        last_modified: Timestamp,
+        attribute: Option<&str>,
+        depth: usize,
+    ) -> Result<()> {
+        let indent = indent(depth);
+        write!(
+            out,
+            r#"{}<DT><H3 ADD_DATE="{}" LAST_MODIFIED="{}""#,
+            indent,
+            seconds(date_added),
+            seconds(last_modified),
+        )?;
+        if let Some(attribute) = attribute {
+            write!(out, r#" {}="true""#, attribute)?;
+        }
+        writeln!(out, ">{}</H3>", escape(title))?;
+        writeln!(out, "{}<DL><p>", indent)?;
+        Ok(())
+    }
+
+    fn write_folder_end(&self, out: &mut impl Write, depth: usize) -> Result<()> {
+        writeln!(out, "{}</DL><p>", indent(depth))?;
+        Ok(())
+    }
+}
+
+fn indent(depth: usize) -> String {
+    "    ".repeat(depth)
+}
+
+// The format uses seconds, not milliseconds.
+fn seconds(t: Timestamp) -> u64 {
+    t.as_millis() / 1000
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::{
+        insert_bookmark, BookmarkPosition, InsertableBookmark, InsertableFolder,
+        InsertableSeparator,
+    };
+    use url::Url;
+
+    #[test]
+    fn test_write_bookmarks() {
+        let conn = new_mem_connection();
+        let date = Some(Timestamp(1_500_000_000_000));
+        insert_bookmark(
+            &conn,
+            InsertableFolder {
+                parent_guid: BookmarkRootGuid::Menu.into(),
+                position: BookmarkPosition::Append,
+                date_added: date,
+                last_modified: date,
+                guid: None,
+                title: Some("Folder & <friends>".into()),
+                children: vec![InsertableBookmark {
+                    parent_guid: sync_guid::Guid::empty(),
+                    position: BookmarkPosition::Append,
+                    date_added: date,
+                    last_modified: date,
+                    guid: None,
+                    url: Url::parse("https://example.com/?a=1&b=\"2\"").unwrap(),
+                    title: Some("Example".into()),
+                }
+                .into()],
+            }
+            .into(),
+        )
+        .expect("should insert folder");
+        insert_bookmark(
+            &conn,
+            InsertableSeparator {
+                parent_guid: BookmarkRootGuid::Toolbar.into(),
+                position: BookmarkPosition::Append,
+                date_added: date,
+                last_modified: date,
+                guid: None,
+            }
+            .into(),
+        )
+        .expect("should insert separator");
+        insert_bookmark(
+            &conn,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Toolbar.into(),
+                position: BookmarkPosition::Append,
+                date_added: date,
+                last_modified: date,
+                guid: None,
+                url: Url::parse("https://mozilla.org/").unwrap(),
+                title: None,
+            }
+            .into(),
+        )
+        .expect("should insert bookmark");
+        conn.execute_batch(
+            "INSERT INTO moz_keywords(place_id, keyword)
+             SELECT id, 'moz' FROM moz_places WHERE url = 'https://mozilla.org/'",
+        )
+        .expect("should add keyword");
+        // The roots' dates are whenever the database was created.
+        conn.execute_batch(
+            "UPDATE moz_bookmarks SET dateAdded = 0, lastModified = 0
+             WHERE parent = (SELECT id FROM moz_bookmarks WHERE guid = 'root________')",
+        )
+        .expect("should reset root dates");
+
+        let mut out = Vec::new();
+        write_bookmarks(&conn, &mut out).expect("should write");
+        let html = String::from_utf8(out).expect("should be utf-8");
+        let body = html
+            .split_once("<H1>Bookmarks Menu</H1>\n\n")
+            .expect("should have header")
+            .1;
+        assert_eq!(
+            body,
+            r#"<DL><p>
+    <DT><H3 ADD_DATE="1500000000" LAST_MODIFIED="1500000000">Folder &amp; &lt;friends&gt;</H3>
+    <DL><p>
+        <DT><A HREF="https://example.com/?a=1&amp;b=%222%22" ADD_DATE="1500000000" LAST_MODIFIED="1500000000">Example</A>
+    </DL><p>
+    <DT><H3 ADD_DATE="0" LAST_MODIFIED="0" PERSONAL_TOOLBAR_FOLDER="true">Bookmarks Toolbar</H3>
+    <DL><p>
+        <HR>
+        <DT><A HREF="https://mozilla.org/" ADD_DATE="1500000000" LAST_MODIFIED="1500000000" SHORTCUTURL="moz"></A>
+    </DL><p>
+    <DT><H3 ADD_DATE="0" LAST_MODIFIED="0" UNFILED_BOOKMARKS_FOLDER="true">Other Bookmarks</H3>
+    <DL><p>
+    </DL><p>
+</DL>
+"#
+        );
+        assert!(html.starts_with("<!DOCTYPE NETSCAPE-Bookmark-file-1>\n"));
+    }
+}
@@ -4,33 +4,69 @@
 
 // This module implement the traits that make the FFI code easier to manage.
 
-use crate::api::matcher::{self, search_frecent, SearchParams};
-use crate::api::places_api::places_api_new;
+use crate::api::matcher::{
+    self, search_frecent, RestrictionTokens, SearchParams, ZeroPrefixSuggestion,
+    ZeroPrefixSuggestionKind,
+};
+use crate::api::places_api::{places_api_new, places_api_new_with_key};
+use crate::bookmark_sync::engine::{get_too_large_to_sync, BookmarkTooLargeToSync};
 use crate::error::{ApiResult, PlacesApiError};
+use crate::export::export_bookmarks_to_html;
+use crate::frecency::FrecencySettings;
 use crate::import::common::HistoryMigrationResult;
 use crate::import::fennec::import_pinned_sites;
-use crate::import::import_fennec_bookmarks;
+use crate::import::html::HtmlBookmarksImportResult;
+use crate::import::import_chrome_history;
+use crate::import::import_desktop_bookmarks_with_mappings;
+use crate::import::import_desktop_history;
+use crate::import::import_fennec_bookmarks_with_mappings;
 use crate::import::import_fennec_history;
-use crate::import::import_ios_bookmarks;
+use crate::import::import_html_bookmarks_with_mappings;
+use crate::import::import_ios_bookmarks_with_mappings;
 use crate::import::import_ios_history;
+use crate::import::import_visits;
+use crate::import::visits::ImportedVisit;
+use crate::observer::{self, PlacesChange, PlacesChangeObserver};
+use crate::spans;
 use crate::storage;
 use crate::storage::bookmarks;
+use crate::storage::bookmarks::duplicates::{self, DuplicateBookmarks, DuplicateBookmarksStrategy};
+use crate::storage::bookmarks::import_source::{
+    self, BookmarkImportRootMapping, BookmarkImportSource,
+};
 use crate::storage::bookmarks::BookmarkPosition;
+use crate::storage::changes::{self, ChangesSince};
+use crate::storage::deletion_policy::{self, HistoryDeletionPolicy};
+use crate::storage::history::HistoryDeletionSyncMode;
 use crate::storage::history_metadata::{
-    DocumentType, HistoryHighlight, HistoryHighlightWeights, HistoryMetadata,
+    DocumentType, HistoryHighlight, HistoryHighlightWeights, HistoryMetadata, HistoryMetadataGroup,
     HistoryMetadataObservation,
 };
-use crate::storage::{history, history_metadata, RunMaintenanceMetrics};
+use crate::storage::history_search::{
+    self, HistorySearchMatchMode, HistorySearchOptions, HistorySearchRanking, HistorySearchResult,
+};
+use crate::storage::page_metadata_queue::{self, PageMetadataKind};
+use crate::storage::pinned_sites::{self, PinnedSite};
+use crate::storage::targeting::{self, PlacesTargetingAttributes};
+use crate::storage::{
+    classify_url, history, history_metadata, tags, FrecencyUpdateMetrics, MaintenanceStepMetrics,
+    PlacesDataSummary, RowId, RunMaintenanceMetrics, UrlClassification,
+};
 use crate::types::VisitTransitionSet;
 use crate::ConnectionType;
 use crate::VisitObservation;
 use crate::VisitTransition;
 use crate::{PlacesApi, PlacesDb};
 use error_support::{handle_error, report_error};
-use interrupt_support::{register_interrupt, SqlInterruptHandle};
-use parking_lot::Mutex;
+use interrupt_support::{register_interrupt, InterruptPriority, SqlInterruptHandle};
+use parking_lot::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Weak};
 use sync15::client::Sync15StorageClientInit;
+use sync15::telemetry::ffi::{
+    EngineInfo, EventInfo, FailureReason, IncomingInfo, OutgoingInfo, ProblemInfo, SyncInfo,
+    SyncTelemetryPing, ValidationInfo,
+};
 use sync_guid::Guid;
 use types::Timestamp as PlacesTimestamp;
 use url::Url;
@@ -45,7 +81,7 @@ const SKIP_ONE_PAGE_FRECENCY_THRESHOLD: i64 = 101 + 1;
 type InsertableBookmarkItem = crate::storage::bookmarks::InsertableItem;
 type InsertableBookmarkFolder = crate::storage::bookmarks::InsertableFolder;
 type InsertableBookmarkSeparator = crate::storage::bookmarks::InsertableSeparator;
-use crate::storage::bookmarks::InsertableBookmark;
+use crate::storage::bookmarks::{BookmarkGuidMapping, InsertableBookmark, InsertableBookmarkTree};
 
 use crate::storage::bookmarks::BookmarkUpdateInfo;
 
@@ -53,7 +89,7 @@ use crate::storage::bookmarks::BookmarkUpdateInfo;
 type BookmarkItem = crate::storage::bookmarks::fetch::Item;
 type BookmarkFolder = crate::storage::bookmarks::fetch::Folder;
 type BookmarkSeparator = crate::storage::bookmarks::fetch::Separator;
-use crate::storage::bookmarks::fetch::BookmarkData;
+use crate::storage::bookmarks::fetch::{BookmarkData, BookmarksStatistics};
 
 impl UniffiCustomTypeConverter for Url {
     type Builtin = String;
@@ -168,6 +204,28 @@ impl PlacesApi {
         }
     }
 
+    // A read-only connection backed by `size` readers, so that queries made
+    // on different threads don't wait for each other.
+    fn new_read_only_pool(&self, size: u32) -> ApiResult<Arc<PlacesConnection>> {
+        handle_error! {
+            let dbs = self.open_read_only_pool(size as usize)?;
+            let connection = Arc::new(PlacesConnection::new_pool(dbs));
+            register_interrupt(Arc::<PlacesConnection>::downgrade(&connection));
+            Ok(connection)
+        }
+    }
+
+    // A read-only connection with its own snapshot, which ends when the
+    // connection is destroyed.
+    fn new_snapshot(&self) -> ApiResult<Arc<PlacesConnection>> {
+        handle_error! {
+            let db = self.open_snapshot()?;
+            let connection = Arc::new(PlacesConnection::new(db));
+            register_interrupt(Arc::<PlacesConnection>::downgrade(&connection));
+            Ok(connection)
+        }
+    }
+
     // NOTE: These methods are unused on Android but will remain needed for
     // iOS until we can move them to the sync manager and replace their existing
     // sync engines with ours
@@ -177,7 +235,7 @@ impl PlacesApi {
         access_token: String,
         sync_key: String,
         tokenserver_url: Url,
-    ) -> ApiResult<String> {
+    ) -> ApiResult<SyncTelemetryPing> {
         handle_error! {
             let root_sync_key = sync15::KeyBundle::from_ksync_base64(sync_key.as_str())?;
             let ping = self.sync_history(
@@ -188,7 +246,7 @@ impl PlacesApi {
                 },
                 &root_sync_key,
             )?;
-            Ok(serde_json::to_string(&ping).unwrap())
+            Ok((&ping).into())
         }
     }
 
@@ -198,7 +256,7 @@ impl PlacesApi {
         access_token: String,
         sync_key: String,
         tokenserver_url: Url,
-    ) -> ApiResult<String> {
+    ) -> ApiResult<SyncTelemetryPing> {
         handle_error! {
             let root_sync_key = sync15::KeyBundle::from_ksync_base64(sync_key.as_str())?;
             let ping = self.sync_bookmarks(
@@ -209,16 +267,17 @@ impl PlacesApi {
                 },
                 &root_sync_key,
             )?;
-            Ok(serde_json::to_string(&ping).unwrap())
+            Ok((&ping).into())
         }
     }
 
     fn places_pinned_sites_import_from_fennec(
         &self,
         db_path: String,
+        key: Option<String>,
     ) -> ApiResult<Vec<BookmarkItem>> {
         handle_error! {
-            let sites = import_pinned_sites(self, db_path.as_str())?
+            let sites = import_pinned_sites(self, db_path.as_str(), key.as_deref())?
                 .into_iter()
                 .map(BookmarkItem::from)
                 .collect();
@@ -226,23 +285,104 @@ impl PlacesApi {
         }
     }
 
-    fn places_history_import_from_fennec(&self, db_path: String) -> ApiResult<String> {
+    fn places_history_import_from_fennec(
+        &self,
+        db_path: String,
+        key: Option<String>,
+    ) -> ApiResult<String> {
         handle_error! {
-            let metrics = import_fennec_history(self, db_path.as_str())?;
+            let metrics = import_fennec_history(self, db_path.as_str(), key.as_deref())?;
             Ok(serde_json::to_string(&metrics)?)
         }
     }
 
-    fn places_bookmarks_import_from_fennec(&self, db_path: String) -> ApiResult<String> {
+    fn places_history_import_from_chrome(
+        &self,
+        db_path: String,
+    ) -> ApiResult<HistoryMigrationResult> {
+        handle_error! {
+            import_chrome_history(self, db_path.as_str())
+        }
+    }
+
+    fn places_history_import_from_desktop(
+        &self,
+        db_path: String,
+    ) -> ApiResult<HistoryMigrationResult> {
         handle_error! {
-            let metrics = import_fennec_bookmarks(self, db_path.as_str())?;
+            import_desktop_history(self, db_path.as_str())
+        }
+    }
+
+    fn places_history_import_visits(
+        &self,
+        visits: Vec<ImportedVisit>,
+    ) -> ApiResult<HistoryMigrationResult> {
+        handle_error! {
+            import_visits(self, visits)
+        }
+    }
+
+    fn places_bookmarks_import_from_fennec(
+        &self,
+        db_path: String,
+        mappings: Option<Vec<BookmarkImportRootMapping>>,
+        key: Option<String>,
+    ) -> ApiResult<String> {
+        handle_error! {
+            let metrics = import_fennec_bookmarks_with_mappings(
+                self,
+                db_path.as_str(),
+                &mappings.unwrap_or_default(),
+                key.as_deref(),
+            )?;
             Ok(serde_json::to_string(&metrics)?)
         }
     }
 
-    fn places_bookmarks_import_from_ios(&self, db_path: String) -> ApiResult<()> {
+    fn places_bookmarks_import_from_desktop(
+        &self,
+        db_path: String,
+        mappings: Option<Vec<BookmarkImportRootMapping>>,
+    ) -> ApiResult<String> {
         handle_error! {
-            import_ios_bookmarks(self, db_path.as_str())?;
+            let metrics = import_desktop_bookmarks_with_mappings(
+                self,
+                db_path.as_str(),
+                &mappings.unwrap_or_default(),
+            )?;
+            Ok(serde_json::to_string(&metrics)?)
+        }
+    }
+
+    fn places_bookmarks_import_from_ios(
+        &self,
+        db_path: String,
+        mappings: Option<Vec<BookmarkImportRootMapping>>,
+    ) -> ApiResult<()> {
+        handle_error! {
+            import_ios_bookmarks_with_mappings(
+                self,
+                db_path.as_str(),
+                &mappings.unwrap_or_default(),
+            )?;
+            Ok(())
+        }
+    }
+
+    fn places_bookmarks_import_from_html(
+        &self,
+        path: String,
+        mappings: Option<Vec<BookmarkImportRootMapping>>,
+    ) -> ApiResult<HtmlBookmarksImportResult> {
+        handle_error! {
+            import_html_bookmarks_with_mappings(self, path.as_str(), &mappings.unwrap_or_default())
+        }
+    }
+
+    fn bookmarks_export_to_html(&self, path: String) -> ApiResult<()> {
+        handle_error! {
+            export_bookmarks_to_html(self, path.as_str())?;
             Ok(())
         }
     }
@@ -255,26 +395,139 @@ impl PlacesApi {
     }
 }
 
+/// Returns the visits `get_visit_infos` would, a chunk at a time, so that
+/// apps can show them as they arrive, and stop early.
+pub struct VisitCursor {
+    conn: Arc<PlacesConnection>,
+    start_date: PlacesTimestamp,
+    end_date: PlacesTimestamp,
+    exclude_types: VisitTransitionSet,
+    container_id: Option<String>,
+    position: Mutex<CursorPosition>,
+}
+
+enum CursorPosition {
+    Start,
+    // The date and id of the last visit returned.
+    After(PlacesTimestamp, RowId),
+    Done,
+}
+
+impl VisitCursor {
+    // Each chunk is its own query, so the connection isn't tied up between
+    // them, and interrupting the connection only interrupts the chunk it's
+    // running.
+    fn next_chunk(&self, count: u32) -> ApiResult<Vec<HistoryVisitInfo>> {
+        handle_error! {
+            let mut position = self.position.lock();
+            let after = match *position {
+                CursorPosition::Start => None,
+                CursorPosition::After(date, id) => Some((date, id)),
+                CursorPosition::Done => return Ok(Vec::new()),
+            };
+            if count == 0 {
+                return Ok(Vec::new());
+            }
+            let (infos, last) = self.conn.with_conn("visit_cursor_next_chunk", |conn| {
+                history::get_visit_infos_after(
+                    conn,
+                    self.start_date,
+                    self.end_date,
+                    self.exclude_types,
+                    self.container_id.as_deref(),
+                    after,
+                    count,
+                )
+            })?;
+            *position = match last {
+                Some((date, id)) if infos.len() == count as usize => {
+                    CursorPosition::After(date, id)
+                }
+                _ => CursorPosition::Done,
+            };
+            Ok(infos)
+        }
+    }
+
+    fn finish(&self) {
+        *self.position.lock() = CursorPosition::Done;
+    }
+}
+
 pub struct PlacesConnection {
-    db: Mutex<PlacesDb>,
+    // A single connection, except for a read-only pool, where there's one
+    // for each reader.
+    dbs: Vec<Mutex<PlacesDb>>,
+    next_db: AtomicUsize,
     interrupt_handle: Arc<SqlInterruptHandle>,
+    conn_type: ConnectionType,
 }
 
 impl PlacesConnection {
     pub fn new(db: PlacesDb) -> Self {
+        Self::new_pool(vec![db])
+    }
+
+    fn new_pool(dbs: Vec<PlacesDb>) -> Self {
         Self {
-            interrupt_handle: db.new_interrupt_handle(),
-            db: Mutex::new(db),
+            interrupt_handle: Arc::new(SqlInterruptHandle::new_for_pool(
+                dbs.iter().map(PlacesDb::new_interrupt_handle).collect(),
+            )),
+            conn_type: dbs[0].conn_type(),
+            dbs: dbs.into_iter().map(Mutex::new).collect(),
+            next_db: AtomicUsize::new(0),
         }
     }
 
+    // Takes the first connection which isn't in use. If they all are, we wait
+    // for one, taking turns so that waiters are spread across the pool.
+    fn lock_db(&self) -> MutexGuard<'_, PlacesDb> {
+        self.dbs
+            .iter()
+            .find_map(Mutex::try_lock)
+            .unwrap_or_else(|| {
+                let i = self.next_db.fetch_add(1, Ordering::Relaxed) % self.dbs.len();
+                self.dbs[i].lock()
+            })
+    }
+
     // A helper that gets the connection from the mutex and converts errors.
-    fn with_conn<F, T>(&self, f: F) -> Result<T>
+    // `name` is the name of the API method, for its timing span. Once the
+    // connection is released, we tell the observer about any changes `f`
+    // made.
+    fn with_conn<F, T>(&self, name: &'static str, f: F) -> Result<T>
+    where
+        F: FnOnce(&PlacesDb) -> crate::error::Result<T>,
+    {
+        self.with_conn_at(name, InterruptPriority::Normal, f)
+    }
+
+    // Like `with_conn`, but for operations with a different priority, so that
+    // `SqlInterruptHandle::interrupt_at_or_below` can cancel background work
+    // without cancelling what the user is waiting on, or the other way
+    // around.
+    fn with_conn_at<F, T>(&self, name: &'static str, priority: InterruptPriority, f: F) -> Result<T>
     where
         F: FnOnce(&PlacesDb) -> crate::error::Result<T>,
     {
-        let conn = self.db.lock();
-        f(&conn)
+        let _span = spans::enter(name);
+        let conn = self.lock_db();
+        // The operation only starts once we have the connection. An operation
+        // waiting for it isn't running any queries, so it mustn't stop us
+        // interrupting the one that holds it.
+        let operation = conn.begin_operation(priority);
+        let result = f(&conn);
+        drop(operation);
+        let pending = observer::take_pending(&conn);
+        drop(conn);
+        if let Some(pending) = pending {
+            pending.deliver();
+        }
+        result
+    }
+
+    fn conn_type(&self) -> ConnectionType {
+        self.conn_type
     }
 
     // pass the SqlInterruptHandle as an object through Uniffi
@@ -282,9 +535,26 @@ impl PlacesConnection {
         Arc::clone(&self.interrupt_handle)
     }
 
+    // Releases the memory held by the connections which aren't running a
+    // query. We skip the busy ones rather than waiting for them, since this is
+    // called when the app is about to be killed for using too much memory.
+    fn on_memory_pressure(&self) -> ApiResult<()> {
+        handle_error! {
+            let _span = spans::enter("on_memory_pressure");
+            for db in &self.dbs {
+                if let Some(db) = db.try_lock() {
+                    db.release_memory()?;
+                }
+            }
+            Ok(())
+        }
+    }
+
     fn get_latest_history_metadata_for_url(&self, url: Url) -> ApiResult<Option<HistoryMetadata>> {
         handle_error! {
-            self.with_conn(|conn| history_metadata::get_latest_for_url(conn, &url))
+            self.with_conn("get_latest_history_metadata_for_url", |conn| {
+                history_metadata::get_latest_for_url(conn, &url)
+            })
         }
     }
 
@@ -294,24 +564,44 @@ impl PlacesConnection {
         end: PlacesTimestamp,
     ) -> ApiResult<Vec<HistoryMetadata>> {
         handle_error! {
-            self.with_conn(|conn| {
+            self.with_conn("get_history_metadata_between", |conn| {
                 history_metadata::get_between(conn, start.as_millis_i64(), end.as_millis_i64())
             })
         }
     }
 
+    fn get_history_metadata_grouped_by_search_term(
+        &self,
+        start: PlacesTimestamp,
+        end: PlacesTimestamp,
+    ) -> ApiResult<Vec<HistoryMetadataGroup>> {
+        handle_error! {
+            self.with_conn("get_history_metadata_grouped_by_search_term", |conn| {
+                history_metadata::get_grouped_by_search_term(
+                    conn,
+                    start.as_millis_i64(),
+                    end.as_millis_i64(),
+                )
+            })
+        }
+    }
+
     fn get_history_metadata_since(
         &self,
         start: PlacesTimestamp,
     ) -> ApiResult<Vec<HistoryMetadata>> {
         handle_error! {
-            self.with_conn(|conn| history_metadata::get_since(conn, start.as_millis_i64()))
+            self.with_conn("get_history_metadata_since", |conn| {
+                history_metadata::get_since(conn, start.as_millis_i64())
+            })
         }
     }
 
     fn query_history_metadata(&self, query: String, limit: i32) -> ApiResult<Vec<HistoryMetadata>> {
         handle_error! {
-            self.with_conn(|conn| history_metadata::query(conn, query.as_str(), limit))
+            self.with_conn("query_history_metadata", |conn| {
+                history_metadata::query(conn, query.as_str(), limit)
+            })
         }
     }
 
@@ -321,20 +611,72 @@ impl PlacesConnection {
         limit: i32,
     ) -> ApiResult<Vec<HistoryHighlight>> {
         handle_error! {
-            self.with_conn(|conn| history_metadata::get_highlights(conn, weights, limit))
+            self.with_conn("get_history_highlights", |conn| {
+                history_metadata::get_highlights(conn, weights, limit)
+            })
+        }
+    }
+
+    fn get_top_sites_prefetch_hints(&self, limit: u32) -> ApiResult<Vec<TopSitePrefetchHint>> {
+        handle_error! {
+            self.with_conn("get_top_sites_prefetch_hints", |conn| {
+                history::get_top_sites_prefetch_hints(conn, limit)
+            })
+        }
+    }
+
+    fn get_typed_urls(&self, limit: u32) -> ApiResult<Vec<TypedUrlInfo>> {
+        handle_error! {
+            self.with_conn("get_typed_urls", |conn| history::get_typed_urls(conn, limit))
+        }
+    }
+
+    fn get_zero_prefix_suggestions(&self, limit: u32) -> ApiResult<Vec<ZeroPrefixSuggestion>> {
+        handle_error! {
+            self.with_conn_at(
+                "get_zero_prefix_suggestions",
+                InterruptPriority::Interactive,
+                |conn| {
+                    matcher::get_zero_prefix_suggestions(
+                        conn,
+                        limit,
+                        FrecencyThresholdOption::SkipOneTimePages.value(),
+                    )
+                },
+            )
+        }
+    }
+
+    fn get_navigation_chain(&self, url: Url, depth: u32) -> ApiResult<Vec<HistoryMetadata>> {
+        handle_error! {
+            self.with_conn("get_navigation_chain", |conn| {
+                history_metadata::get_navigation_chain(conn, &url, depth)
+            })
         }
     }
 
     fn note_history_metadata_observation(&self, data: HistoryMetadataObservation) -> ApiResult<()> {
         handle_error! {
             // odd historical naming discrepency - public function is "note_*", impl is "apply_*"
-            self.with_conn(|conn| history_metadata::apply_metadata_observation(conn, data))
+            self.with_conn("note_history_metadata_observation", |conn| {
+                history_metadata::apply_metadata_observation(conn, data)
+            })
         }
     }
 
     fn metadata_delete_older_than(&self, older_than: PlacesTimestamp) -> ApiResult<()> {
         handle_error! {
-            self.with_conn(|conn| history_metadata::delete_older_than(conn, older_than.as_millis_i64()))
+            self.with_conn("metadata_delete_older_than", |conn| {
+                history_metadata::delete_older_than(conn, older_than.as_millis_i64())
+            })
+        }
+    }
+
+    fn metadata_delete_by_search_term(&self, search_term: String) -> ApiResult<()> {
+        handle_error! {
+            self.with_conn("metadata_delete_by_search_term", |conn| {
+                history_metadata::delete_by_search_term(conn, &search_term)
+            })
         }
     }
 
@@ -345,7 +687,7 @@ impl PlacesConnection {
         search_term: Option<String>,
     ) -> ApiResult<()> {
         handle_error! {
-            self.with_conn(|conn| {
+            self.with_conn("metadata_delete", |conn| {
                 history_metadata::delete_metadata(
                     conn,
                     &url,
@@ -359,11 +701,27 @@ impl PlacesConnection {
     /// Add an observation to the database.
     fn apply_observation(&self, visit: VisitObservation) -> ApiResult<()> {
         handle_error! {
-            self.with_conn(|conn| history::apply_observation(conn, visit))?;
+            self.with_conn("apply_observation", |conn| history::apply_observation(conn, visit))?;
             Ok(())
         }
     }
 
+    fn update_preview_image(&self, url: Url, preview_image_url: Url) -> ApiResult<bool> {
+        handle_error! {
+            self.with_conn("update_preview_image", |conn| {
+                history::update_preview_image(conn, &url, &preview_image_url)
+            })
+        }
+    }
+
+    /// Add many observations to the database, in a single transaction.
+    /// Returns the number of visits added.
+    fn apply_observations(&self, visits: Vec<VisitObservation>) -> ApiResult<u32> {
+        handle_error! {
+            self.with_conn("apply_observations", |conn| history::apply_observations(conn, visits))
+        }
+    }
+
     fn get_visited_urls_in_range(
         &self,
         start: PlacesTimestamp,
@@ -371,7 +729,7 @@ impl PlacesConnection {
         include_remote: bool,
     ) -> ApiResult<Vec<Url>> {
         handle_error! {
-            self.with_conn(|conn| {
+            self.with_conn("get_visited_urls_in_range", |conn| {
                 let urls = history::get_visited_urls(conn, start, end, include_remote)?
                     .iter()
                     // Turn the list of strings into valid Urls
@@ -387,15 +745,86 @@ impl PlacesConnection {
         start_date: PlacesTimestamp,
         end_date: PlacesTimestamp,
         exclude_types: VisitTransitionSet,
+        container_id: Option<String>,
     ) -> ApiResult<Vec<HistoryVisitInfo>> {
         handle_error! {
-            self.with_conn(|conn| history::get_visit_infos(conn, start_date, end_date, exclude_types))
+            self.with_conn("get_visit_infos", |conn| {
+                history::get_visit_infos(
+                    conn,
+                    start_date,
+                    end_date,
+                    exclude_types,
+                    container_id.as_deref(),
+                )
+            })
         }
     }
 
-    fn get_visit_count(&self, exclude_types: VisitTransitionSet) -> ApiResult<i64> {
+    fn open_visit_cursor(
+        self: Arc<Self>,
+        start_date: PlacesTimestamp,
+        end_date: PlacesTimestamp,
+        exclude_types: VisitTransitionSet,
+        container_id: Option<String>,
+    ) -> Arc<VisitCursor> {
+        Arc::new(VisitCursor {
+            conn: self,
+            start_date,
+            end_date,
+            exclude_types,
+            container_id,
+            position: Mutex::new(CursorPosition::Start),
+        })
+    }
+
+    fn get_visit_count(
+        &self,
+        exclude_types: VisitTransitionSet,
+        container_id: Option<String>,
+    ) -> ApiResult<i64> {
         handle_error! {
-            self.with_conn(|conn| history::get_visit_count(conn, exclude_types))
+            self.with_conn("get_visit_count", |conn| {
+                history::get_visit_count(conn, exclude_types, container_id.as_deref())
+            })
+        }
+    }
+
+    fn get_visit_count_per_day(
+        &self,
+        start: PlacesTimestamp,
+        end: PlacesTimestamp,
+        timezone_offset: i32,
+    ) -> ApiResult<Vec<VisitCountForDay>> {
+        handle_error! {
+            self.with_conn("get_visit_count_per_day", |conn| {
+                history::get_visit_count_per_day(conn, start, end, timezone_offset)
+            })
+        }
+    }
+
+    fn get_targeting_attributes(&self) -> ApiResult<PlacesTargetingAttributes> {
+        handle_error! {
+            self.with_conn("get_targeting_attributes", |conn| {
+                targeting::get_targeting_attributes(conn, PlacesTimestamp::now())
+            })
+        }
+    }
+
+    fn get_data_summary(&self) -> ApiResult<PlacesDataSummary> {
+        handle_error! {
+            self.with_conn("get_data_summary", storage::get_data_summary)
+        }
+    }
+
+    fn get_current_change_seq(&self) -> ApiResult<i64> {
+        handle_error! {
+            self.with_conn("get_current_change_seq", changes::get_current_change_seq)
+        }
+    }
+
+    fn get_changes_since(&self, seq: i64) -> ApiResult<ChangesSince> {
+        handle_error! {
+            self.with_conn("get_changes_since", |conn| changes::get_changes_since(conn, seq))
         }
     }
 
@@ -404,9 +833,18 @@ impl PlacesConnection {
         offset: i64,
         count: i64,
         exclude_types: VisitTransitionSet,
+        container_id: Option<String>,
     ) -> ApiResult<Vec<HistoryVisitInfo>> {
         handle_error! {
-            self.with_conn(|conn| history::get_visit_page(conn, offset, count, exclude_types))
+            self.with_conn("get_visit_page", |conn| {
+                history::get_visit_page(conn, offset, count, exclude_types, container_id.as_deref())
+            })
+        }
+    }
+
+    fn get_visit_details(&self, url: Url) -> ApiResult<Vec<HistoryVisitDetails>> {
+        handle_error! {
+            self.with_conn("get_visit_details", |conn| history::get_visit_details(conn, &url))
         }
     }
 
@@ -416,10 +854,18 @@ impl PlacesConnection {
         offset: i64,
         count: i64,
         exclude_types: VisitTransitionSet,
+        container_id: Option<String>,
     ) -> ApiResult<HistoryVisitInfosWithBound> {
         handle_error! {
-            self.with_conn(|conn| {
-                history::get_visit_page_with_bound(conn, bound, offset, count, exclude_types)
+            self.with_conn("get_visit_page_with_bound", |conn| {
+                history::get_visit_page_with_bound(
+                    conn,
+                    bound,
+                    offset,
+                    count,
+                    exclude_types,
+                    container_id.as_deref(),
+                )
             })
         }
     }
@@ -435,14 +881,16 @@ impl PlacesConnection {
                 .enumerate()
                 .filter_map(|(idx, s)| Url::parse(&s).ok().map(|url| (idx, url)))
                 .collect::<Vec<_>>();
-            self.with_conn(|conn| history::get_visited_into(conn, &url_idxs, &mut result))?;
+            self.with_conn("get_visited", |conn| {
+                history::get_visited_into(conn, &url_idxs, &mut result)
+            })?;
             Ok(result)
         }
     }
 
     fn delete_visits_for(&self, url: String) -> ApiResult<()> {
         handle_error! {
-            self.with_conn(|conn| {
+            self.with_conn("delete_visits_for", |conn| {
                 let guid = match Url::parse(&url) {
                     Ok(url) => history::url_to_guid(conn, &url)?,
                     Err(e) => {
@@ -458,15 +906,41 @@ impl PlacesConnection {
         }
     }
 
+    fn delete_visits_for_urls(&self, urls: Vec<String>) -> ApiResult<()> {
+        handle_error! {
+            self.with_conn("delete_visits_for_urls", |conn| {
+                history::delete_visits_for_urls(conn, &urls)
+            })
+        }
+    }
+
+    fn delete_visits_for_host(&self, host: String) -> ApiResult<()> {
+        handle_error! {
+            self.with_conn("delete_visits_for_host", |conn| {
+                history::delete_visits_for_host(conn, &host)
+            })
+        }
+    }
+
+    fn delete_everything_for_origin(&self, origin: Url) -> ApiResult<()> {
+        handle_error! {
+            self.with_conn("delete_everything_for_origin", |conn| {
+                history::delete_everything_for_origin(conn, &origin)
+            })
+        }
+    }
+
     fn delete_visits_between(&self, start: PlacesTimestamp, end: PlacesTimestamp) -> ApiResult<()> {
         handle_error! {
-            self.with_conn(|conn| history::delete_visits_between(conn, start, end))
+            self.with_conn("delete_visits_between", |conn| {
+                history::delete_visits_between(conn, start, end)
+            })
         }
     }
 
     fn delete_visit(&self, url: String, timestamp: PlacesTimestamp) -> ApiResult<()> {
         handle_error! {
-            self.with_conn(|conn| {
+            self.with_conn("delete_visit", |conn| {
                 match Url::parse(&url) {
                     Ok(url) => {
                         history::delete_place_visit_at_time(conn, &url, timestamp)?;
@@ -485,23 +959,31 @@ impl PlacesConnection {
         &self,
         num_items: i32,
         threshold_option: FrecencyThresholdOption,
+        options: Option<TopFrecentSiteOptions>,
     ) -> ApiResult<Vec<TopFrecentSiteInfo>> {
         handle_error! {
-            self.with_conn(|conn| {
+            self.with_conn("get_top_frecent_site_infos", |conn| {
                 crate::storage::history::get_top_frecent_site_infos(
                     conn,
                     num_items,
                     threshold_option.value(),
+                    &options.unwrap_or_default(),
                 )
             })
         }
     }
 
+    fn search_hosts(&self, prefix: String, limit: u32) -> ApiResult<Vec<HostInfo>> {
+        handle_error! {
+            self.with_conn("search_hosts", |conn| history::search_hosts(conn, &prefix, limit))
+        }
+    }
+
     // XXX - We probably need to document/name this a little better as it's specifically for
     // history and NOT bookmarks...
     fn wipe_local_history(&self) -> ApiResult<()> {
         handle_error! {
-            self.with_conn(history::wipe_local)
+            self.with_conn("wipe_local_history", history::wipe_local)
         }
     }
 
@@ -509,18 +991,28 @@ impl PlacesConnection {
     // sync metadata to only sync after most recent visit to prevent
     // further syncing of older data
     fn delete_everything_history(&self) -> ApiResult<()> {
+        self.delete_everything_history_with_sync_mode(HistoryDeletionSyncMode::LocalOnly)
+    }
+
+    // Like `delete_everything_history`, but the caller chooses whether the
+    // deletions are uploaded, or only the local Sync state is reset.
+    fn delete_everything_history_with_sync_mode(
+        &self,
+        mode: HistoryDeletionSyncMode,
+    ) -> ApiResult<()> {
         handle_error! {
-            // Do some extra work to track down #4856
-            let conn = self.db.lock();
-            let result = history::delete_everything(&conn);
-            if let Err(e) = &result {
-                if matches!(e,
-                    crate::error::Error::SqlError(rusqlite::Error::QueryReturnedNoRows)
-                ) {
-                    report_error!("SqlErrorQueryReturnedNoRows", "{}", e);
+            self.with_conn("delete_everything_history_with_sync_mode", |conn| {
+                let result = history::delete_everything_with_sync_mode(conn, mode);
+                // Do some extra work to track down #4856
+                if let Err(e) = &result {
+                    if matches!(e,
+                        crate::error::Error::SqlError(rusqlite::Error::QueryReturnedNoRows)
+                    ) {
+                        report_error!("SqlErrorQueryReturnedNoRows", "{}", e);
+                    }
                 }
-            }
-            result
+                result
+            })
         }
     }
 
@@ -528,42 +1020,148 @@ impl PlacesConnection {
     // should probably have this go away?
     fn prune_destructively(&self) -> ApiResult<()> {
         handle_error! {
-            self.with_conn(history::prune_destructively)
+            self.with_conn_at(
+                "prune_destructively",
+                InterruptPriority::Background,
+                history::prune_destructively,
+            )
         }
     }
 
     fn run_maintenance_prune(&self, db_size_limit: u32) -> ApiResult<RunMaintenanceMetrics> {
         handle_error! {
-            self.with_conn(|conn| storage::run_maintenance_prune(conn, db_size_limit))
+            self.with_conn_at(
+                "run_maintenance_prune",
+                InterruptPriority::Background,
+                |conn| storage::run_maintenance_prune(conn, db_size_limit),
+            )
+        }
+    }
+
+    fn run_maintenance_vacuum(&self) -> ApiResult<MaintenanceStepMetrics> {
+        handle_error! {
+            self.with_conn_at(
+                "run_maintenance_vacuum",
+                InterruptPriority::Background,
+                storage::run_maintenance_vacuum,
+            )
         }
     }
 
-    fn run_maintenance_vacuum(&self) -> ApiResult<()> {
+    fn run_maintenance_optimize(&self) -> ApiResult<MaintenanceStepMetrics> {
         handle_error! {
-            self.with_conn(storage::run_maintenance_vacuum)
+            self.with_conn_at(
+                "run_maintenance_optimize",
+                InterruptPriority::Background,
+                storage::run_maintenance_optimize,
+            )
         }
     }
 
-    fn run_maintenance_optimize(&self) -> ApiResult<()> {
+    fn run_maintenance_checkpoint(&self) -> ApiResult<MaintenanceStepMetrics> {
         handle_error! {
-            self.with_conn(storage::run_maintenance_optimize)
+            self.with_conn_at(
+                "run_maintenance_checkpoint",
+                InterruptPriority::Background,
+                storage::run_maintenance_checkpoint,
+            )
         }
     }
 
-    fn run_maintenance_checkpoint(&self) -> ApiResult<()> {
+    fn rekey_database(&self, new_key: String) -> ApiResult<()> {
         handle_error! {
-            self.with_conn(storage::run_maintenance_checkpoint)
+            self.with_conn("rekey_database", |conn| conn.rekey(&new_key))
         }
     }
 
-    fn query_autocomplete(&self, search: String, limit: i32) -> ApiResult<Vec<SearchResult>> {
+    fn run_pending_frecency_updates(
+        &self,
+        chunk_size: u32,
+        max_duration_ms: u32,
+    ) -> ApiResult<FrecencyUpdateMetrics> {
         handle_error! {
-            self.with_conn(|conn| {
+            self.with_conn("run_pending_frecency_updates", |conn| {
+                storage::run_pending_frecency_updates(
+                    conn,
+                    chunk_size,
+                    std::time::Duration::from_millis(max_duration_ms.into()),
+                )
+            })
+        }
+    }
+
+    fn set_history_deletion_policy(&self, policy: Option<HistoryDeletionPolicy>) -> ApiResult<()> {
+        handle_error! {
+            self.with_conn("set_history_deletion_policy", |conn| {
+                deletion_policy::set_deletion_policy(conn, policy.as_ref())
+            })
+        }
+    }
+
+    fn get_history_deletion_policy(&self) -> ApiResult<Option<HistoryDeletionPolicy>> {
+        handle_error! {
+            self.with_conn("get_history_deletion_policy", deletion_policy::get_deletion_policy)
+        }
+    }
+
+    fn get_pages_missing_metadata(
+        &self,
+        kind: PageMetadataKind,
+        limit: u32,
+    ) -> ApiResult<Vec<Url>> {
+        handle_error! {
+            self.with_conn("get_pages_missing_metadata", |conn| {
+                page_metadata_queue::get_pages_missing_metadata(conn, kind, limit)
+            })
+        }
+    }
+
+    fn mark_page_metadata_attempted(
+        &self,
+        kind: PageMetadataKind,
+        urls: Vec<Url>,
+    ) -> ApiResult<()> {
+        handle_error! {
+            self.with_conn("mark_page_metadata_attempted", |conn| {
+                page_metadata_queue::mark_attempted(conn, kind, &urls)
+            })
+        }
+    }
+
+    fn pin_site(&self, url: Url, title: Option<String>) -> ApiResult<()> {
+        handle_error! {
+            self.with_conn("pin_site", |conn| {
+                pinned_sites::pin_site(conn, &url, title.as_deref())
+            })
+        }
+    }
+
+    fn unpin_site(&self, url: Url) -> ApiResult<bool> {
+        handle_error! {
+            self.with_conn("unpin_site", |conn| pinned_sites::unpin_site(conn, &url))
+        }
+    }
+
+    fn get_pinned_sites(&self) -> ApiResult<Vec<PinnedSite>> {
+        handle_error! {
+            self.with_conn("get_pinned_sites", pinned_sites::get_pinned_sites)
+        }
+    }
+
+    fn query_autocomplete(
+        &self,
+        search: String,
+        limit: i32,
+        restriction_tokens: Option<RestrictionTokens>,
+    ) -> ApiResult<Vec<SearchResult>> {
+        handle_error! {
+            self.with_conn_at("query_autocomplete", InterruptPriority::Interactive, |conn| {
                 search_frecent(
                     conn,
                     SearchParams {
                         search_string: search,
                         limit: limit as u32,
+                        restriction_tokens,
                     },
                 )
                 .map(|search_results| search_results.into_iter().map(Into::into).collect())
@@ -571,9 +1169,21 @@ impl PlacesConnection {
         }
     }
 
+    fn search_history(
+        &self,
+        query: String,
+        options: HistorySearchOptions,
+    ) -> ApiResult<Vec<HistorySearchResult>> {
+        handle_error! {
+            self.with_conn_at("search_history", InterruptPriority::Interactive, |conn| {
+                history_search::search_history(conn, &query, &options)
+            })
+        }
+    }
+
     fn accept_result(&self, search_string: String, url: String) -> ApiResult<()> {
         handle_error! {
-            self.with_conn(|conn| {
+            self.with_conn("accept_result", |conn| {
                 match Url::parse(&url) {
                     Ok(url) => {
                         matcher::accept_result(conn, &search_string, &url)?;
@@ -588,15 +1198,27 @@ impl PlacesConnection {
         }
     }
 
+    fn delete_adaptive_history(&self) -> ApiResult<()> {
+        handle_error! {
+            self.with_conn("delete_adaptive_history", matcher::delete_adaptive_history)
+        }
+    }
+
     fn match_url(&self, query: String) -> ApiResult<Option<Url>> {
         handle_error! {
-            self.with_conn(|conn| matcher::match_url(conn, query))
+            self.with_conn_at(
+                "match_url",
+                InterruptPriority::Interactive,
+                |conn| matcher::match_url(conn, query),
+            )
         }
     }
 
     fn bookmarks_get_tree(&self, item_guid: &Guid) -> ApiResult<Option<BookmarkItem>> {
         handle_error! {
-            self.with_conn(|conn| bookmarks::fetch::fetch_tree(conn, item_guid))
+            self.with_conn("bookmarks_get_tree", |conn| {
+                bookmarks::fetch::fetch_tree(conn, item_guid)
+            })
         }
     }
 
@@ -606,16 +1228,29 @@ impl PlacesConnection {
         get_direct_children: bool,
     ) -> ApiResult<Option<BookmarkItem>> {
         handle_error! {
-            self.with_conn(|conn| {
+            self.with_conn("bookmarks_get_by_guid", |conn| {
                 let bookmark = bookmarks::fetch::fetch_bookmark(conn, guid, get_direct_children)?;
                 Ok(bookmark.map(BookmarkItem::from))
             })
         }
     }
 
+    fn bookmarks_get_children_page(
+        &self,
+        folder_guid: &Guid,
+        offset: u32,
+        limit: u32,
+    ) -> ApiResult<Option<Vec<BookmarkItem>>> {
+        handle_error! {
+            self.with_conn("bookmarks_get_children_page", |conn| {
+                bookmarks::fetch::fetch_children_page(conn, folder_guid, offset, limit)
+            })
+        }
+    }
+
     fn bookmarks_get_all_with_url(&self, url: String) -> ApiResult<Vec<BookmarkItem>> {
         handle_error! {
-            self.with_conn(|conn| {
+            self.with_conn("bookmarks_get_all_with_url", |conn| {
                 // XXX - We should return the exact type - ie, BookmarkData rather than BookmarkItem.
                 match Url::parse(&url) {
                     Ok(url) => Ok(bookmarks::fetch::fetch_bookmarks_by_url(conn, &url)?
@@ -634,7 +1269,7 @@ impl PlacesConnection {
 
     fn bookmarks_search(&self, query: String, limit: i32) -> ApiResult<Vec<BookmarkItem>> {
         handle_error! {
-            self.with_conn(|conn| {
+            self.with_conn("bookmarks_search", |conn| {
                 // XXX - We should return the exact type - ie, BookmarkData rather than BookmarkItem.
                 Ok(
                     bookmarks::fetch::search_bookmarks(conn, query.as_str(), limit as u32)?
@@ -646,9 +1281,29 @@ impl PlacesConnection {
         }
     }
 
+    fn bookmarks_count_by_parent(&self, folder_guid: &Guid) -> ApiResult<Option<u32>> {
+        handle_error! {
+            self.with_conn("bookmarks_count_by_parent", |conn| {
+                bookmarks::fetch::bookmarks_count_by_parent(conn, folder_guid)
+            })
+        }
+    }
+
+    fn bookmarks_count_all(&self) -> ApiResult<u32> {
+        handle_error! {
+            self.with_conn("bookmarks_count_all", bookmarks::fetch::bookmarks_count_all)
+        }
+    }
+
+    fn bookmarks_get_statistics(&self) -> ApiResult<BookmarksStatistics> {
+        handle_error! {
+            self.with_conn("bookmarks_get_statistics", bookmarks::fetch::bookmarks_statistics)
+        }
+    }
+
     fn bookmarks_get_recent(&self, limit: i32) -> ApiResult<Vec<BookmarkItem>> {
         handle_error! {
-            self.with_conn(|conn| {
+            self.with_conn("bookmarks_get_recent", |conn| {
                 // XXX - We should return the exact type - ie, BookmarkData rather than BookmarkItem.
                 Ok(bookmarks::fetch::recent_bookmarks(conn, limit as u32)?
                     .into_iter()
@@ -660,31 +1315,125 @@ impl PlacesConnection {
 
     fn bookmarks_delete(&self, id: Guid) -> ApiResult<bool> {
         handle_error! {
-            self.with_conn(|conn| bookmarks::delete_bookmark(conn, &id))
+            self.with_conn("bookmarks_delete", |conn| bookmarks::delete_bookmark(conn, &id))
+        }
+    }
+
+    fn bookmarks_restore(&self, guid: Guid) -> ApiResult<Option<Guid>> {
+        handle_error! {
+            self.with_conn("bookmarks_restore", |conn| {
+                bookmarks::trash::restore_bookmark(conn, &guid)
+            })
+        }
+    }
+
+    fn bookmarks_purge_trash(&self, older_than: PlacesTimestamp) -> ApiResult<u32> {
+        handle_error! {
+            self.with_conn("bookmarks_purge_trash", |conn| {
+                bookmarks::trash::purge_trash(conn, older_than)
+            })
         }
     }
 
     fn bookmarks_delete_everything(&self) -> ApiResult<()> {
         handle_error! {
-            self.with_conn(bookmarks::delete_everything)
+            self.with_conn("bookmarks_delete_everything", bookmarks::delete_everything)
+        }
+    }
+
+    fn bookmarks_get_imported(&self, source: BookmarkImportSource) -> ApiResult<Vec<BookmarkItem>> {
+        handle_error! {
+            self.with_conn("bookmarks_get_imported", |conn| {
+                import_source::fetch_imported_bookmarks(conn, source)
+            })
+        }
+    }
+
+    fn bookmarks_delete_imported(&self, source: BookmarkImportSource) -> ApiResult<u32> {
+        handle_error! {
+            self.with_conn("bookmarks_delete_imported", |conn| {
+                import_source::delete_imported_bookmarks(conn, source)
+            })
+        }
+    }
+
+    fn bookmarks_find_duplicates(&self) -> ApiResult<Vec<DuplicateBookmarks>> {
+        handle_error! {
+            self.with_conn("bookmarks_find_duplicates", duplicates::find_duplicates)
+        }
+    }
+
+    fn bookmarks_merge_duplicates(&self, strategy: DuplicateBookmarksStrategy) -> ApiResult<u32> {
+        handle_error! {
+            self.with_conn("bookmarks_merge_duplicates", |conn| {
+                duplicates::merge_duplicates(conn, strategy)
+            })
+        }
+    }
+
+    fn bookmarks_get_too_large_to_sync(&self) -> ApiResult<Vec<BookmarkTooLargeToSync>> {
+        handle_error! {
+            self.with_conn("bookmarks_get_too_large_to_sync", get_too_large_to_sync)
         }
     }
 
     fn bookmarks_get_url_for_keyword(&self, keyword: String) -> ApiResult<Option<Url>> {
         handle_error! {
-            self.with_conn(|conn| bookmarks::bookmarks_get_url_for_keyword(conn, keyword.as_str()))
+            self.with_conn("bookmarks_get_url_for_keyword", |conn| {
+                bookmarks::bookmarks_get_url_for_keyword(conn, keyword.as_str())
+            })
+        }
+    }
+
+    fn bookmarks_tag_url(&self, url: Url, tag: String) -> ApiResult<()> {
+        handle_error! {
+            self.with_conn("bookmarks_tag_url", |conn| tags::tag_url(conn, &url, &tag))
+        }
+    }
+
+    fn bookmarks_untag_url(&self, url: Url, tag: String) -> ApiResult<()> {
+        handle_error! {
+            self.with_conn("bookmarks_untag_url", |conn| tags::untag_url(conn, &url, &tag))
+        }
+    }
+
+    fn bookmarks_get_tags_for_url(&self, url: Url) -> ApiResult<Vec<String>> {
+        handle_error! {
+            self.with_conn("bookmarks_get_tags_for_url", |conn| tags::get_tags_for_url(conn, &url))
+        }
+    }
+
+    fn bookmarks_get_urls_with_tag(&self, tag: String) -> ApiResult<Vec<Url>> {
+        handle_error! {
+            self.with_conn("bookmarks_get_urls_with_tag", |conn| {
+                tags::get_urls_with_tag(conn, &tag)
+            })
         }
     }
 
     fn bookmarks_insert(&self, data: InsertableBookmarkItem) -> ApiResult<Guid> {
         handle_error! {
-            self.with_conn(|conn| bookmarks::insert_bookmark(conn, data))
+            self.with_conn("bookmarks_insert", |conn| bookmarks::insert_bookmark(conn, data))
+        }
+    }
+
+    fn bookmarks_insert_tree(
+        &self,
+        parent_guid: Guid,
+        tree: InsertableBookmarkTree,
+    ) -> ApiResult<Vec<BookmarkGuidMapping>> {
+        handle_error! {
+            self.with_conn("bookmarks_insert_tree", |conn| {
+                bookmarks::insert_tree(conn, &parent_guid, tree)
+            })
         }
     }
 
     fn bookmarks_update(&self, item: BookmarkUpdateInfo) -> ApiResult<()> {
         handle_error! {
-            self.with_conn(|conn| bookmarks::update_bookmark_from_info(conn, item))
+            self.with_conn("bookmarks_update", |conn| {
+                bookmarks::update_bookmark_from_info(conn, item)
+            })
         }
     }
 
@@ -694,7 +1443,11 @@ impl PlacesConnection {
         last_sync_timestamp: i64,
     ) -> ApiResult<HistoryMigrationResult> {
         handle_error! {
-            self.with_conn(|conn| import_ios_history(conn, &db_path, last_sync_timestamp))
+            self.with_conn_at(
+                "places_history_import_from_ios",
+                InterruptPriority::Background,
+                |conn| import_ios_history(conn, &db_path, last_sync_timestamp),
+            )
         }
     }
 }
@@ -714,7 +1467,27 @@ pub struct HistoryVisitInfo {
     pub is_hidden: bool,
     pub preview_image_url: Option<Url>,
     pub is_remote: bool,
+    /// The page the user came from, if we know it.
+    pub referrer_url: Option<Url>,
+    /// The contextual identity the visit was made in, or `None` for the
+    /// default one.
+    pub container_id: Option<String>,
 }
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct HistoryVisitDetails {
+    pub visit: HistoryVisitInfo,
+    /// The visits which led to this one, starting with the visit it came from
+    /// directly. Empty if we don't know where the visit came from.
+    pub sources: Vec<HistoryVisitInfo>,
+}
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VisitCountForDay {
+    /// The day, as `YYYY-MM-DD`.
+    pub date: String,
+    pub count: u32,
+}
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct HistoryVisitInfosWithBound {
     pub infos: Vec<HistoryVisitInfo>,
@@ -722,9 +1495,48 @@ pub struct HistoryVisitInfosWithBound {
     pub offset: i64,
 }
 
+/// A host the user has history for, and how often they visited it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostInfo {
+    /// The host, with its port if it isn't the default one.
+    pub host: String,
+    /// The number of visits to pages on the host, on any device.
+    pub visit_count: u32,
+}
+
 pub struct TopFrecentSiteInfo {
     pub url: Url,
     pub title: Option<String>,
+    pub frecency: i64,
+    /// The number of visits to the page, on any device.
+    pub visit_count: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TopFrecentSiteOptions {
+    /// Whether to only return the most frecent page for each site. Pages on
+    /// hosts which only differ by a `www.`, `m.` or `mobile.` prefix, or by
+    /// their scheme or port, are on the same site.
+    pub dedupe_origins: bool,
+    /// URLs and hosts to leave out, like sponsored or blocked sites. A URL
+    /// leaves out just that page, and a host leaves out every page on it and
+    /// its subdomains.
+    pub excluded: Vec<String>,
+}
+
+pub struct TopSitePrefetchHint {
+    pub origin: Url,
+    /// The pages the user most often went to next from a page on `origin`,
+    /// most often first.
+    pub next_urls: Vec<Url>,
+}
+
+pub struct TypedUrlInfo {
+    pub url: Url,
+    pub title: Option<String>,
+    /// How many times the user typed the URL, on any device.
+    pub typed_count: u32,
+    pub last_visit_date: PlacesTimestamp,
 }
 
 pub enum FrecencyThresholdOption {
@@ -790,4 +1602,140 @@ mod tests {
         let invalid_url = "http://1234.56.78.90".to_string();
         assert!(PlacesConnection::bookmarks_get_all_with_url(&conn, invalid_url).is_ok());
     }
+
+    #[test]
+    fn test_read_only_pool() {
+        let api = crate::api::places_api::test::new_mem_api();
+        let pool = PlacesConnection::new_pool(api.open_read_only_pool(2).expect("should open"));
+        assert_eq!(pool.dbs.len(), 2);
+
+        // A busy reader doesn't block queries on the others.
+        let busy = pool.dbs[0].lock();
+        let scope = busy.begin_interrupt_scope().expect("should begin scope");
+        let count = pool
+            .with_conn("test", |conn| {
+                assert!(pool.dbs[1].try_lock().is_none());
+                history::get_visit_count(conn, VisitTransitionSet::empty(), None)
+            })
+            .expect("should query");
+        assert_eq!(count, 0);
+
+        // Interrupting the pool interrupts every reader.
+        pool.new_interrupt_handle().interrupt();
+        assert!(scope.was_interrupted());
+    }
+
+    #[test]
+    fn test_interrupt_priority() {
+        let api = crate::api::places_api::test::new_mem_api();
+        let pool = PlacesConnection::new_pool(api.open_read_only_pool(2).expect("should open"));
+        let handle = pool.new_interrupt_handle();
+        pool.with_conn_at("test", InterruptPriority::Interactive, |interactive| {
+            let interactive_scope = interactive.begin_interrupt_scope()?;
+            pool.with_conn_at("test", InterruptPriority::Background, |background| {
+                let background_scope = background.begin_interrupt_scope()?;
+                handle.interrupt_at_or_below(InterruptPriority::Background);
+                assert!(background_scope.was_interrupted());
+                // The interactive query is still running...
+                assert!(!interactive_scope.was_interrupted());
+                Ok(())
+            })?;
+            // ...and still works, since each reader decides for itself
+            // whether to interrupt its connection.
+            history::get_visit_count(interactive, VisitTransitionSet::empty(), None)?;
+
+            // Scopes begun after an interrupt aren't affected by it.
+            let background_scope =
+                pool.with_conn_at("test", InterruptPriority::Background, |conn| {
+                    conn.begin_interrupt_scope()
+                })?;
+            assert!(!background_scope.was_interrupted());
+
+            handle.interrupt();
+            assert!(interactive_scope.was_interrupted());
+            assert!(background_scope.was_interrupted());
+            Ok(())
+        })
+        .expect("should run");
+    }
+
+    #[test]
+    fn test_interrupt_with_waiter() {
+        use sql_support::ConnExt;
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        let conn = Arc::new(PlacesConnection::new(new_mem_connection()));
+        let handle = conn.new_interrupt_handle();
+        let (started_tx, started_rx) = mpsc::channel();
+        let background = {
+            let conn = Arc::clone(&conn);
+            thread::spawn(move || {
+                conn.with_conn_at("test", InterruptPriority::Background, |db| {
+                    started_tx.send(()).unwrap();
+                    // Long enough that it only finishes early if we interrupt
+                    // the connection.
+                    let result = db.query_one::<i64>(
+                        "WITH RECURSIVE n(x) AS (
+                             SELECT 1 UNION ALL SELECT x + 1 FROM n WHERE x < 100000000
+                         )
+                         SELECT count(*) FROM n",
+                    );
+                    Ok(matches!(
+                        result,
+                        Err(rusqlite::Error::SqliteFailure(err, _))
+                            if err.code == rusqlite::ErrorCode::OperationInterrupted
+                    ))
+                })
+            })
+        };
+        started_rx.recv().unwrap();
+
+        // Queue an interactive operation behind the background one.
+        let interactive = {
+            let conn = Arc::clone(&conn);
+            thread::spawn(move || {
+                conn.with_conn_at("test", InterruptPriority::Interactive, |db| {
+                    history::get_visit_count(db, VisitTransitionSet::empty(), None)
+                })
+            })
+        };
+        thread::sleep(Duration::from_millis(100));
+
+        // The waiter doesn't hold the connection, so it doesn't stop us
+        // interrupting the background query. We keep trying in case the query
+        // hadn't started yet.
+        while !background.is_finished() {
+            handle.interrupt_at_or_below(InterruptPriority::Background);
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(background.join().unwrap().expect("should run"));
+        let count = interactive.join().unwrap().expect("should query");
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_on_memory_pressure() {
+        let api = crate::api::places_api::test::new_mem_api();
+        let pool = PlacesConnection::new_pool(api.open_read_only_pool(2).expect("should open"));
+        pool.with_conn("test", |conn| {
+            history::get_visit_count(conn, VisitTransitionSet::empty(), None)
+        })
+        .expect("should query");
+
+        // Busy readers are skipped.
+        let busy = pool.dbs[0].lock();
+        pool.on_memory_pressure().expect("should release memory");
+        drop(busy);
+        pool.on_memory_pressure().expect("should release memory");
+
+        // The readers still work afterwards.
+        let count = pool
+            .with_conn("test", |conn| {
+                history::get_visit_count(conn, VisitTransitionSet::empty(), None)
+            })
+            .expect("should query");
+        assert_eq!(count, 0);
+    }
 }
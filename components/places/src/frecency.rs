@@ -15,6 +15,8 @@ enum RedirectBonus {
     Normal,
 }
 
+/// The parameters frecency is calculated with, which an app can set when it
+/// creates the `PlacesApi`. The field comments name Desktop's matching prefs.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FrecencySettings {
     // TODO: These probably should not all be i32s...
@@ -78,6 +80,28 @@ impl Default for FrecencySettings {
 }
 
 impl FrecencySettings {
+    /// Frecency samples the `num_visits` most recent visits and buckets them
+    /// by age, so neither the sample size nor the cut-offs can be negative.
+    pub fn validate(&self) -> Result<()> {
+        if self.num_visits < 0 {
+            return Err(Error::InvalidFrecencySettings("num_visits is negative"));
+        }
+        if [
+            self.first_bucket_cutoff_days,
+            self.second_bucket_cutoff_days,
+            self.third_bucket_cutoff_days,
+            self.fourth_bucket_cutoff_days,
+        ]
+        .iter()
+        .any(|&days| days < 0)
+        {
+            return Err(Error::InvalidFrecencySettings(
+                "a bucket cut-off is negative",
+            ));
+        }
+        Ok(())
+    }
+
     // Note: in Places, `redirect` defaults to false.
     pub fn get_transition_bonus(
         &self,
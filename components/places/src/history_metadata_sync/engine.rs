@@ -0,0 +1,187 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::db::{PlacesDb, SharedPlacesDb};
+use crate::error::*;
+use crate::history_sync::HISTORY_TTL;
+use crate::spans;
+use crate::storage::history_metadata::history_metadata_sync::{
+    apply_incoming, fetch_outgoing, finish_outgoing, reset, wipe, OutgoingMetadata,
+};
+use crate::storage::{get_meta, put_meta};
+use interrupt_support::SqlInterruptScope;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use sync15::engine::{
+    CollSyncIds, CollectionRequest, EngineSyncAssociation, IncomingChangeset, OutgoingChangeset,
+    SyncEngine,
+};
+use sync15::{telemetry, Guid, Payload, ServerTimestamp};
+
+use super::record::HistoryMetadataSyncRecord;
+use super::{COLLECTION_SYNCID_META_KEY, GLOBAL_SYNCID_META_KEY, LAST_SYNC_META_KEY};
+
+const COLLECTION_NAME: &str = "historymetadata";
+
+fn do_apply_incoming(
+    db: &PlacesDb,
+    scope: &SqlInterruptScope,
+    inbound: IncomingChangeset,
+    telem: &mut telemetry::Engine,
+    change_counters: &mut HashMap<Guid, i64>,
+) -> Result<OutgoingChangeset> {
+    let timestamp = inbound.timestamp;
+    let mut incoming_telemetry = telemetry::EngineIncoming::new();
+    let mut records = Vec::with_capacity(inbound.changes.len());
+    for (payload, _) in inbound.changes {
+        match HistoryMetadataSyncRecord::from_payload(payload) {
+            Ok(record) => records.push(record),
+            Err(e) => {
+                log::warn!("Error deserializing incoming record: {}", e);
+                incoming_telemetry.failed(1);
+            }
+        }
+    }
+    let result = apply_incoming(db, records, scope, &mut incoming_telemetry);
+    telem.incoming(incoming_telemetry);
+    result?;
+    // write the timestamp now, so if we are interrupted creating outgoing
+    // changesets we don't need to re-reconcile what we just did.
+    put_meta(db, LAST_SYNC_META_KEY, &(timestamp.as_millis() as i64))?;
+
+    let mut outgoing = OutgoingChangeset::new(COLLECTION_NAME, timestamp);
+    change_counters.clear();
+    for item in fetch_outgoing(db)? {
+        let payload = match item {
+            OutgoingMetadata::Record {
+                record,
+                change_counter,
+            } => {
+                change_counters.insert(record.id.clone(), change_counter);
+                Payload::from_record(record)?.with_auto_field("ttl", Some(HISTORY_TTL))
+            }
+            OutgoingMetadata::Tombstone(guid) => Payload::new_tombstone_with_ttl(guid, HISTORY_TTL),
+        };
+        outgoing.changes.push(payload);
+    }
+    Ok(outgoing)
+}
+
+fn do_sync_finished(
+    db: &PlacesDb,
+    new_timestamp: ServerTimestamp,
+    records_synced: Vec<Guid>,
+    change_counters: &HashMap<Guid, i64>,
+) -> Result<()> {
+    log::info!(
+        "sync completed after uploading {} records",
+        records_synced.len()
+    );
+    finish_outgoing(db, &records_synced, change_counters)?;
+
+    // write timestamp to reflect what we just wrote.
+    put_meta(db, LAST_SYNC_META_KEY, &(new_timestamp.as_millis() as i64))?;
+    Ok(())
+}
+
+// Short-lived struct that's constructed each sync
+pub struct HistoryMetadataSyncEngine {
+    pub db: Arc<SharedPlacesDb>,
+    pub(crate) scope: SqlInterruptScope,
+    // The change counters of the records we're uploading, from
+    // `apply_incoming` to `sync_finished`.
+    change_counters: Mutex<HashMap<Guid, i64>>,
+}
+
+impl HistoryMetadataSyncEngine {
+    pub fn new(db: Arc<SharedPlacesDb>) -> Result<Self> {
+        Ok(Self {
+            scope: db.begin_interrupt_scope()?,
+            db,
+            change_counters: Mutex::default(),
+        })
+    }
+}
+
+impl SyncEngine for HistoryMetadataSyncEngine {
+    fn collection_name(&self) -> std::borrow::Cow<'static, str> {
+        COLLECTION_NAME.into()
+    }
+
+    fn apply_incoming(
+        &self,
+        inbound: Vec<IncomingChangeset>,
+        telem: &mut telemetry::Engine,
+    ) -> anyhow::Result<OutgoingChangeset> {
+        let _span = spans::enter("history_metadata_sync_apply_incoming");
+        assert_eq!(inbound.len(), 1, "history metadata only requests one item");
+        let inbound = inbound.into_iter().next().unwrap();
+        let conn = self.db.lock();
+        Ok(do_apply_incoming(
+            &conn,
+            &self.scope,
+            inbound,
+            telem,
+            &mut self.change_counters.lock(),
+        )?)
+    }
+
+    fn sync_finished(
+        &self,
+        new_timestamp: ServerTimestamp,
+        records_synced: Vec<Guid>,
+    ) -> anyhow::Result<()> {
+        let _span = spans::enter("history_metadata_sync_finished");
+        do_sync_finished(
+            &self.db.lock(),
+            new_timestamp,
+            records_synced,
+            &std::mem::take(&mut *self.change_counters.lock()),
+        )?;
+        Ok(())
+    }
+
+    fn get_collection_requests(
+        &self,
+        server_timestamp: ServerTimestamp,
+    ) -> anyhow::Result<Vec<CollectionRequest>> {
+        let conn = self.db.lock();
+        let since =
+            ServerTimestamp(get_meta::<i64>(&conn, LAST_SYNC_META_KEY)?.unwrap_or_default());
+        Ok(if since == server_timestamp {
+            vec![]
+        } else {
+            vec![CollectionRequest::new(COLLECTION_NAME)
+                .full()
+                .newer_than(since)]
+        })
+    }
+
+    fn get_sync_assoc(&self) -> anyhow::Result<EngineSyncAssociation> {
+        let conn = self.db.lock();
+        let global = get_meta(&conn, GLOBAL_SYNCID_META_KEY)?;
+        let coll = get_meta(&conn, COLLECTION_SYNCID_META_KEY)?;
+        Ok(if let (Some(global), Some(coll)) = (global, coll) {
+            EngineSyncAssociation::Connected(CollSyncIds { global, coll })
+        } else {
+            EngineSyncAssociation::Disconnected
+        })
+    }
+
+    fn reset(&self, assoc: &EngineSyncAssociation) -> anyhow::Result<()> {
+        reset(&self.db.lock(), assoc)?;
+        Ok(())
+    }
+
+    fn wipe(&self) -> anyhow::Result<()> {
+        wipe(&self.db.lock())?;
+        Ok(())
+    }
+
+    fn wipe_local(&self) -> anyhow::Result<()> {
+        // `wipe` doesn't write tombstones.
+        self.wipe()
+    }
+}
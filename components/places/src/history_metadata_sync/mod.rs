@@ -0,0 +1,17 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Syncs the history metadata table, so that search terms, referrers and
+// document types follow the user between devices. Each metadata entry is a
+// separate record; when both sides change an entry, the most recently updated
+// one wins.
+
+pub mod engine;
+pub mod record;
+
+pub use engine::HistoryMetadataSyncEngine;
+
+pub const LAST_SYNC_META_KEY: &str = "history_metadata_last_sync_time";
+pub const GLOBAL_SYNCID_META_KEY: &str = "history_metadata_global_sync_id";
+pub const COLLECTION_SYNCID_META_KEY: &str = "history_metadata_sync_id";
@@ -0,0 +1,53 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::error::*;
+use serde_derive::*;
+use sync_guid::Guid as SyncGuid;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryMetadataRecord {
+    pub id: SyncGuid,
+
+    pub url: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referrer_url: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_term: Option<String>,
+
+    // 0 for regular pages, 1 for media.
+    #[serde(default)]
+    pub document_type: u8,
+
+    // In milliseconds.
+    pub created_at: i64,
+    pub updated_at: i64,
+
+    #[serde(default)]
+    pub total_view_time: i32,
+}
+
+#[derive(Debug)]
+pub struct HistoryMetadataSyncRecord {
+    pub guid: SyncGuid,
+    pub record: Option<HistoryMetadataRecord>,
+}
+
+impl HistoryMetadataSyncRecord {
+    pub fn from_payload(payload: sync15::Payload) -> Result<Self> {
+        let guid = payload.id.clone();
+        let record = if payload.is_tombstone() {
+            None
+        } else {
+            Some(payload.into_record()?)
+        };
+        Ok(Self { guid, record })
+    }
+}
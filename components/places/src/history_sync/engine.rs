@@ -4,6 +4,7 @@
 
 use crate::db::{PlacesDb, SharedPlacesDb};
 use crate::error::*;
+use crate::spans;
 use crate::storage::history::{delete_everything, history_sync::reset};
 use crate::storage::{get_meta, put_meta};
 use interrupt_support::SqlInterruptScope;
@@ -88,6 +89,7 @@ impl SyncEngine for HistorySyncEngine {
         inbound: Vec<IncomingChangeset>,
         telem: &mut telemetry::Engine,
     ) -> anyhow::Result<OutgoingChangeset> {
+        let _span = spans::enter("history_sync_apply_incoming");
         assert_eq!(inbound.len(), 1, "history only requests one item");
         let inbound = inbound.into_iter().next().unwrap();
         let conn = self.db.lock();
@@ -99,6 +101,7 @@ impl SyncEngine for HistorySyncEngine {
         new_timestamp: ServerTimestamp,
         records_synced: Vec<Guid>,
     ) -> anyhow::Result<()> {
+        let _span = spans::enter("history_sync_finished");
         do_sync_finished(&self.db.lock(), new_timestamp, records_synced)?;
         Ok(())
     }
@@ -140,4 +143,10 @@ impl SyncEngine for HistorySyncEngine {
         delete_everything(&self.db.lock())?;
         Ok(())
     }
+
+    fn wipe_local(&self) -> anyhow::Result<()> {
+        // `delete_everything` resets the sync state instead of uploading the
+        // deletions.
+        self.wipe()
+    }
 }
@@ -7,6 +7,7 @@ use super::{HISTORY_TTL, MAX_OUTGOING_PLACES, MAX_VISITS};
 use crate::api::history::can_add_url;
 use crate::db::PlacesDb;
 use crate::error::*;
+use crate::observer;
 use crate::storage::{
     delete_pending_temp_tables,
     history::history_sync::{
@@ -266,6 +267,9 @@ pub fn apply_plan(
     // frecency and origin updates.
     delete_pending_temp_tables(db)?;
     tx.commit()?;
+    if let Some(pending) = observer::take_pending(db) {
+        pending.deliver();
+    }
     // It might make sense for fetch_outgoing to manage its own
     // begin_transaction - even though doesn't seem a large bottleneck
     // at this time, the fact we hold a single transaction for the entire call
@@ -794,6 +798,7 @@ mod tests {
             SearchParams {
                 search_string: "http://example.com".into(),
                 limit: 2,
+                restriction_tokens: None,
             },
         )?;
         assert_eq!(found.len(), 1);
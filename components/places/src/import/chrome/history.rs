@@ -0,0 +1,247 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::api::places_api::PlacesApi;
+use crate::bookmark_sync::engine::update_frecencies;
+use crate::error::*;
+use crate::import::common::{
+    attached_database, define_history_migration_functions, select_count, HistoryMigrationResult,
+};
+use crate::types::VisitTransition;
+use interrupt_support::InterruptPriority;
+use rusqlite::functions::{Context, FunctionFlags};
+use std::time::Instant;
+use types::Timestamp;
+use url::Url;
+
+/// This import is used for Android users migrating from Chrome. It reads
+/// Chrome's `History` database, which the app needs to have copied somewhere
+/// we can read it.
+///
+/// ### Basic process
+///
+/// - Attach Chrome's database.
+/// - Slurp the URLs into a temp table, to normalize (punycode) them.
+/// - Add any entries to moz_places that are needed.
+/// - Insert the visits, converting Chrome's timestamps and transitions to ours.
+/// - Update frecency for new items.
+/// - Cleanup (detach Chrome's database, etc).
+pub fn import(
+    places_api: &PlacesApi,
+    path: impl AsRef<std::path::Path>,
+) -> Result<HistoryMigrationResult> {
+    let url = crate::util::ensure_url_path(path)?;
+    do_import(places_api, url)
+}
+
+fn do_import(places_api: &PlacesApi, chrome_db_file_url: Url) -> Result<HistoryMigrationResult> {
+    let conn_mutex = places_api.get_sync_connection()?;
+    let conn = conn_mutex.lock();
+    let _operation = conn.begin_operation(InterruptPriority::Background);
+
+    let scope = conn.begin_interrupt_scope()?;
+
+    define_history_migration_functions(&conn)?;
+    conn.create_scalar_function(
+        "chrome_visit_transition",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        chrome_visit_transition,
+    )?;
+
+    let import_start = Instant::now();
+    log::trace!("Attaching database {}", chrome_db_file_url);
+    let auto_detach = attached_database(&conn, &chrome_db_file_url, "chrome", None)?;
+
+    let tx = conn.begin_transaction()?;
+
+    log::debug!("Counting Chrome history visits");
+    let num_total = select_count(&conn, &COUNT_CHROME_HISTORY_VISITS)?;
+
+    log::debug!("Creating and populating staging table");
+    conn.execute_batch(&CREATE_STAGING_TABLE)?;
+    conn.execute_batch(&FILL_STAGING)?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Populating missing entries in moz_places");
+    conn.execute_batch(&FILL_MOZ_PLACES)?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Inserting the history visits");
+    conn.execute_batch(&INSERT_HISTORY_VISITS)?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Marking new entries as having stale frecencies");
+    conn.execute(
+        &ADD_TO_STALE_FRECENCIES,
+        &[(":now", &Timestamp::now().as_millis())],
+    )?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Committing...");
+    tx.commit()?;
+
+    // Note: update_frecencies manages its own transaction, which is fine,
+    // since nothing that bad will happen if it is aborted.
+    log::debug!("Updating frecencies");
+    update_frecencies(&conn, &scope)?;
+
+    log::info!("Successfully imported history visits!");
+
+    log::debug!("Counting Places history visits");
+    let num_succeeded = select_count(&conn, &COUNT_PLACES_HISTORY_VISITS)?;
+    let num_failed = num_total.saturating_sub(num_succeeded);
+
+    auto_detach.execute_now()?;
+
+    let metrics = HistoryMigrationResult {
+        num_total,
+        num_succeeded,
+        num_failed,
+        total_duration: import_start.elapsed().as_millis() as u64,
+    };
+
+    Ok(metrics)
+}
+
+// Chrome's page transitions are a "core" type in the low byte, and qualifiers
+// in the high bits. See ui/base/page_transition_types.h in Chromium.
+const CHROME_CORE_MASK: u32 = 0xFF;
+const CHROME_CLIENT_REDIRECT: u32 = 0x4000_0000;
+const CHROME_SERVER_REDIRECT: u32 = 0x8000_0000;
+
+fn visit_transition_from_chrome(transition: u32) -> VisitTransition {
+    // Like us, Chrome marks the visit the redirect led *to*. Chrome doesn't
+    // know whether a server redirect was permanent, so we assume it wasn't.
+    if transition & (CHROME_CLIENT_REDIRECT | CHROME_SERVER_REDIRECT) != 0 {
+        return VisitTransition::RedirectTemporary;
+    }
+    match transition & CHROME_CORE_MASK {
+        // TYPED, GENERATED (a search suggestion), KEYWORD and
+        // KEYWORD_GENERATED are all things the user entered in the URL bar.
+        1 | 5 | 9 | 10 => VisitTransition::Typed,
+        // AUTO_BOOKMARK
+        2 => VisitTransition::Bookmark,
+        // AUTO_SUBFRAME
+        3 => VisitTransition::Embed,
+        // MANUAL_SUBFRAME
+        4 => VisitTransition::FramedLink,
+        // RELOAD
+        8 => VisitTransition::Reload,
+        // LINK, AUTO_TOPLEVEL, FORM_SUBMIT, and anything we don't know about.
+        _ => VisitTransition::Link,
+    }
+}
+
+#[inline(never)]
+fn chrome_visit_transition(ctx: &Context<'_>) -> rusqlite::Result<u8> {
+    // Chrome stores the transition as a signed 32-bit integer, so the
+    // qualifier bits can make it negative.
+    let transition = ctx.get::<i64>(0).unwrap_or(0) as u32;
+    Ok(visit_transition_from_chrome(transition) as u8)
+}
+
+lazy_static::lazy_static! {
+    // We use a staging table purely so that we can normalize URLs (and
+    // specifically, punycode them)
+    static ref CREATE_STAGING_TABLE: &'static str = "
+        CREATE TEMP TABLE temp.chromeHistoryStaging(
+            id INTEGER PRIMARY KEY,
+            url TEXT NOT NULL,
+            url_hash INTEGER NOT NULL,
+            title TEXT
+        ) WITHOUT ROWID;"
+    ;
+
+    static ref FILL_STAGING: &'static str = "
+        INSERT OR IGNORE INTO temp.chromeHistoryStaging(id, url, url_hash, title)
+            SELECT
+                u.id,
+                validate_url(u.url),
+                hash(validate_url(u.url)),
+                NULLIF(sanitize_utf8(u.title), '')
+            FROM chrome.urls u
+            WHERE validate_url(u.url) IS NOT NULL"
+    ;
+
+    // Insert any missing entries into moz_places that we'll need for this.
+    static ref FILL_MOZ_PLACES: &'static str =
+        "INSERT OR IGNORE INTO main.moz_places(guid, url, url_hash, title, frecency, sync_change_counter)
+            SELECT
+                IFNULL(
+                    (SELECT p.guid FROM main.moz_places p WHERE p.url_hash = t.url_hash AND p.url = t.url),
+                    generate_guid()
+                ),
+                t.url,
+                t.url_hash,
+                t.title,
+                -1,
+                1
+            FROM temp.chromeHistoryStaging t"
+    ;
+
+    // Insert history visits. Chrome's visit times are in microseconds since
+    // 1601-01-01, so we convert them to milliseconds since 1970-01-01. Visits
+    // which came from Sync are in `visit_source` with a source of 0; everything
+    // else happened on this device.
+    static ref INSERT_HISTORY_VISITS: &'static str =
+        "INSERT OR IGNORE INTO main.moz_historyvisits(from_visit, place_id, visit_date, visit_type, is_local)
+            SELECT
+                NULL, -- Chrome's redirect chains refer to its own visit ids, so we don't keep them.
+                (SELECT p.id FROM main.moz_places p WHERE p.url_hash = t.url_hash AND p.url = t.url),
+                sanitize_timestamp(v.visit_time / 1000 - 11644473600000),
+                chrome_visit_transition(v.transition),
+                NOT EXISTS(SELECT 1 FROM chrome.visit_source s WHERE s.id = v.id AND s.source = 0)
+            FROM chrome.visits v
+            JOIN temp.chromeHistoryStaging t ON t.id = v.url"
+    ;
+
+    // Adds newly modified places entries into the stale frecencies table
+    static ref ADD_TO_STALE_FRECENCIES: &'static str =
+        "INSERT OR IGNORE INTO main.moz_places_stale_frecencies(place_id, stale_at)
+            SELECT p.id, :now
+            FROM main.moz_places p
+            WHERE p.frecency = -1"
+    ;
+
+    // Count Chrome history visits
+    static ref COUNT_CHROME_HISTORY_VISITS: &'static str =
+        "SELECT COUNT(*) FROM chrome.visits"
+    ;
+
+    // Count Places history visits
+    static ref COUNT_PLACES_HISTORY_VISITS: &'static str =
+        "SELECT COUNT(*) FROM main.moz_historyvisits"
+    ;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visit_transition_from_chrome() {
+        assert_eq!(visit_transition_from_chrome(0), VisitTransition::Link);
+        assert_eq!(visit_transition_from_chrome(1), VisitTransition::Typed);
+        assert_eq!(visit_transition_from_chrome(2), VisitTransition::Bookmark);
+        assert_eq!(visit_transition_from_chrome(3), VisitTransition::Embed);
+        assert_eq!(visit_transition_from_chrome(8), VisitTransition::Reload);
+        // A link which started a redirect chain.
+        assert_eq!(
+            visit_transition_from_chrome(0x1000_0000),
+            VisitTransition::Link
+        );
+        // The end of a client redirect from a typed URL.
+        assert_eq!(
+            visit_transition_from_chrome(0x6000_0001),
+            VisitTransition::RedirectTemporary
+        );
+        // A server redirect, stored as a negative number.
+        assert_eq!(
+            visit_transition_from_chrome(-2_147_483_648i64 as u32),
+            VisitTransition::RedirectTemporary
+        );
+        assert_eq!(visit_transition_from_chrome(0xFE), VisitTransition::Link);
+    }
+}
@@ -29,19 +29,52 @@ pub mod sql_fns {
     use types::Timestamp;
     use url::Url;
 
-    fn sanitize_timestamp(ts: i64) -> Result<Timestamp> {
+    // The functions below are the implementations of our SQL functions.
+    // They're split from the `Context` wrappers so they can be called (and
+    // fuzzed - see `components/places/fuzz`) without going through SQLite.
+
+    pub fn sanitize_timestamp(ts: i64) -> Timestamp {
         let now = *NOW;
         let is_sane = |ts: Timestamp| -> bool { Timestamp::EARLIEST <= ts && ts <= now };
         let ts = Timestamp(u64::try_from(ts).unwrap_or(0));
         if is_sane(ts) {
-            return Ok(ts);
+            return ts;
         }
         // Maybe the timestamp was actually in μs?
         let ts = Timestamp(ts.as_millis() / 1000);
         if is_sane(ts) {
-            return Ok(ts);
+            return ts;
+        }
+        now
+    }
+
+    pub fn sanitize_float_timestamp_value(num: f64) -> Timestamp {
+        let ts = if num.is_normal() && num > 0.0 {
+            num.round() as i64
+        } else {
+            0
+        };
+        sanitize_timestamp(ts)
+    }
+
+    pub fn validate_url_value(val: ValueRef<'_>) -> Option<String> {
+        let href = if let ValueRef::Text(s) = val {
+            String::from_utf8_lossy(s).to_string()
+        } else {
+            return None;
+        };
+        if href.len() > URL_LENGTH_MAX {
+            return None;
+        }
+        Url::parse(&href).ok().map(Into::into)
+    }
+
+    pub fn sanitize_utf8_value(val: ValueRef<'_>) -> Option<String> {
+        match val {
+            ValueRef::Text(s) => Some(String::from_utf8_lossy(s).to_string()),
+            ValueRef::Null => None,
+            _ => Some("".to_owned()),
         }
-        Ok(now)
     }
 
     // Unfortunately dates for history visits in old iOS databases
@@ -51,69 +84,62 @@ pub mod sql_fns {
     // this function otherwise.
     #[inline(never)]
     pub fn sanitize_float_timestamp(ctx: &Context<'_>) -> Result<Timestamp> {
-        let ts = ctx
-            .get::<f64>(0)
-            .map(|num| {
-                if num.is_normal() && num > 0.0 {
-                    num.round() as i64
-                } else {
-                    0
-                }
-            })
-            .unwrap_or(0);
-        sanitize_timestamp(ts)
+        Ok(sanitize_float_timestamp_value(
+            ctx.get::<f64>(0).unwrap_or(0.0),
+        ))
     }
 
     #[inline(never)]
     pub fn sanitize_integer_timestamp(ctx: &Context<'_>) -> Result<Timestamp> {
-        sanitize_timestamp(ctx.get::<i64>(0).unwrap_or(0))
+        Ok(sanitize_timestamp(ctx.get::<i64>(0).unwrap_or(0)))
     }
 
     // Possibly better named as "normalize URL" - even in non-error cases, the
     // result string may not be the same href used passed as input.
     #[inline(never)]
     pub fn validate_url(ctx: &Context<'_>) -> Result<Option<String>> {
-        let val = ctx.get_raw(0);
-        let href = if let ValueRef::Text(s) = val {
-            String::from_utf8_lossy(s).to_string()
-        } else {
-            return Ok(None);
-        };
-        if href.len() > URL_LENGTH_MAX {
-            return Ok(None);
-        }
-        if let Ok(url) = Url::parse(&href) {
-            Ok(Some(url.into()))
-        } else {
-            Ok(None)
-        }
+        Ok(validate_url_value(ctx.get_raw(0)))
     }
 
     // Sanitize a text column into valid utf-8. Leave NULLs alone, but all other
     // types are converted to an empty string.
     #[inline(never)]
     pub fn sanitize_utf8(ctx: &Context<'_>) -> Result<Option<String>> {
-        let val = ctx.get_raw(0);
-        Ok(match val {
-            ValueRef::Text(s) => Some(String::from_utf8_lossy(s).to_string()),
-            ValueRef::Null => None,
-            _ => Some("".to_owned()),
-        })
+        Ok(sanitize_utf8_value(ctx.get_raw(0)))
     }
 }
 
+/// Attaches the database at `path` as `db_alias`, and returns a helper which
+/// detaches it again. If the database is encrypted with SQLCipher, `key` is
+/// its key. Otherwise, it's attached with an empty key: SQLCipher would use
+/// the key of our own database if it's encrypted.
 pub fn attached_database<'a>(
     conn: &'a PlacesDb,
     path: &Url,
     db_alias: &'static str,
+    key: Option<&str>,
 ) -> Result<ExecuteOnDrop<'a>> {
-    conn.execute(
-        "ATTACH DATABASE :path AS :db_alias",
-        named_params! {
-            ":path": path.as_str(),
-            ":db_alias": db_alias,
-        },
-    )?;
+    match key {
+        // The `KEY` clause is how SQLCipher spells `PRAGMA key` for attached
+        // databases. It has to be given when attaching, since SQLite reads
+        // the schema of the database straight away, which fails if it's
+        // encrypted and we don't know the key yet.
+        Some(key) => conn.execute(
+            "ATTACH DATABASE :path AS :db_alias KEY :key",
+            named_params! {
+                ":path": path.as_str(),
+                ":db_alias": db_alias,
+                ":key": key,
+            },
+        )?,
+        None => conn.execute(
+            "ATTACH DATABASE :path AS :db_alias KEY ''",
+            named_params! {
+                ":path": path.as_str(),
+                ":db_alias": db_alias,
+            },
+        )?,
+    };
     Ok(ExecuteOnDrop {
         conn,
         sql: format!("DETACH DATABASE {};", db_alias),
@@ -157,6 +183,24 @@ impl Drop for ExecuteOnDrop<'_> {
     }
 }
 
+/// Counts the bookmarks in the mirror whose keyword the merge applied. The
+/// bookmark importers use this before they clear the mirror.
+pub const COUNT_IMPORTED_KEYWORDS: &str = "
+SELECT COUNT(*) FROM main.moz_bookmarks_synced v
+WHERE v.keyword NOT NULL
+  AND EXISTS(SELECT 1 FROM main.moz_keywords k
+             WHERE k.keyword = v.keyword AND k.place_id = v.placeId)
+";
+
+/// Like `COUNT_IMPORTED_KEYWORDS`, but counts the tags on the bookmarks in
+/// the mirror which the merge applied.
+pub const COUNT_IMPORTED_TAGS: &str = "
+SELECT COUNT(*) FROM main.moz_bookmarks_synced_tag_relation r
+JOIN main.moz_bookmarks_synced v ON v.id = r.itemId
+WHERE EXISTS(SELECT 1 FROM main.moz_tags_relation t
+             WHERE t.tag_id = r.tagId AND t.place_id = v.placeId)
+";
+
 pub fn select_count(conn: &PlacesDb, stmt: &str) -> Result<u32> {
     let count: Result<Option<u32>> =
         conn.try_query_row(stmt, [], |row| Ok(row.get::<_, u32>(0)?), false);
@@ -211,3 +255,37 @@ pub fn define_history_migration_functions(c: &Connection) -> Result<()> {
     )?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::ConnectionType;
+    use crate::db::encryption;
+
+    #[test]
+    fn test_attach_encrypted_database() -> Result<()> {
+        // Only builds which link SQLCipher can read encrypted databases.
+        if encryption::ensure_available().is_err() {
+            return Ok(());
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("browser.db");
+        {
+            let source = Connection::open(&path)?;
+            source.pragma_update(None, "key", "secret")?;
+            source.execute_batch("CREATE TABLE t(x); INSERT INTO t(x) VALUES (1);")?;
+        }
+        let url = crate::util::ensure_url_path(&path)?;
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+
+        // SQLite reads the schema when attaching, so the wrong key fails
+        // straight away.
+        assert!(attached_database(&conn, &url, "source", None).is_err());
+        assert!(attached_database(&conn, &url, "source", Some("wrong")).is_err());
+
+        let detach = attached_database(&conn, &url, "source", Some("secret"))?;
+        assert_eq!(select_count(&conn, "SELECT COUNT(*) FROM source.t")?, 1);
+        detach.execute_now()?;
+        Ok(())
+    }
+}
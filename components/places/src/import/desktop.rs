@@ -0,0 +1,36 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+pub mod bookmarks;
+pub mod history;
+pub use bookmarks::import as import_bookmarks;
+pub use bookmarks::import_with_mappings as import_bookmarks_with_mappings;
+pub use history::import as import_history;
+
+use crate::db::PlacesDb;
+use crate::error::*;
+use sql_support::ConnExt;
+
+// The tables both imports read. Desktop has had them, with the columns we
+// use, for longer than any profile we're likely to see, so rather than
+// checking the schema version, we check that they're there, which also
+// catches files which aren't desktop databases at all.
+const REQUIRED_TABLES: &[&str] = &["moz_places", "moz_historyvisits", "moz_bookmarks"];
+
+fn check_desktop_db(conn: &PlacesDb) -> Result<()> {
+    for table in REQUIRED_TABLES {
+        let exists = conn.query_row_and_then_cachable(
+            "SELECT EXISTS(SELECT 1 FROM desktop.sqlite_master
+                           WHERE type = 'table' AND name = :name)",
+            &[(":name", table)],
+            |row| row.get::<_, bool>(0),
+            false,
+        )?;
+        if !exists {
+            let db_version = conn.query_one::<i64>("PRAGMA desktop.user_version")?;
+            return Err(Error::UnsupportedDatabaseVersion(db_version));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,555 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::api::places_api::PlacesApi;
+use crate::bookmark_sync::{
+    engine::{update_frecencies, Merger},
+    SyncedBookmarkKind,
+};
+use crate::db::db::PlacesDb;
+use crate::error::*;
+use crate::import::common::{
+    attached_database, select_count, ExecuteOnDrop, COUNT_IMPORTED_KEYWORDS, COUNT_IMPORTED_TAGS,
+};
+use crate::import::fennec::bookmarks::BookmarksMigrationResult;
+use crate::storage::bookmarks::{
+    bookmark_sync::create_synced_bookmark_roots,
+    import_source::{
+        move_mapped_roots_from_mirror, record_imported_from_mirror, BookmarkImportRootMapping,
+        BookmarkImportSource,
+    },
+};
+use crate::storage::tags::validate_tag;
+use crate::types::{BookmarkType, SyncStatus};
+use interrupt_support::{InterruptPriority, SqlInterruptScope};
+use sql_support::ConnExt;
+use std::time::Instant;
+use url::Url;
+
+/// This import is used for users moving from Firefox Desktop, who've copied
+/// its `places.sqlite` database somewhere we can read it.
+///
+/// Desktop's bookmarks are stored the way ours are, with the same root guids
+/// and item types, so it works much like the Fennec import.
+///
+/// ### Basic process
+///
+/// - Attach the desktop database.
+/// - Slurp the items under the four user roots into a temp table,
+///   "desktopBookmarksStaging". This leaves out the tags root (tags are
+///   imported with the bookmarks they're on), queries, which have `place:`
+///   URLs, and livemarks.
+/// - Add any entries to moz_places that are needed.
+/// - Fill the mirror, its tags and structure using the staging table.
+/// - Run a merge, like we do for Sync.
+/// - Fixup the data that was actually inserted, and clear the mirror.
+pub fn import(
+    places_api: &PlacesApi,
+    path: impl AsRef<std::path::Path>,
+) -> Result<BookmarksMigrationResult> {
+    import_with_mappings(places_api, path, &[])
+}
+
+/// Like `import`, but puts the children of the roots in `mappings` where
+/// they say, instead of in the matching local roots.
+pub fn import_with_mappings(
+    places_api: &PlacesApi,
+    path: impl AsRef<std::path::Path>,
+    mappings: &[BookmarkImportRootMapping],
+) -> Result<BookmarksMigrationResult> {
+    let url = crate::util::ensure_url_path(path)?;
+    do_import(places_api, url, mappings)
+}
+
+fn do_import(
+    places_api: &PlacesApi,
+    desktop_db_file_url: Url,
+    mappings: &[BookmarkImportRootMapping],
+) -> Result<BookmarksMigrationResult> {
+    let conn_mutex = places_api.get_sync_connection()?;
+    let conn = conn_mutex.lock();
+    let _operation = conn.begin_operation(InterruptPriority::Background);
+
+    let scope = conn.begin_interrupt_scope()?;
+
+    sql_fns::define_functions(&conn)?;
+
+    let import_start = Instant::now();
+    log::trace!("Attaching database {}", desktop_db_file_url);
+    let auto_detach = attached_database(&conn, &desktop_db_file_url, "desktop", None)?;
+    super::check_desktop_db(&conn)?;
+
+    // Desktop removed item annotations, which is where livemarks were kept,
+    // after it removed livemarks, so newer profiles don't have the table.
+    let has_item_annos = conn.query_one::<bool>(
+        "SELECT EXISTS(SELECT 1 FROM desktop.sqlite_master
+                       WHERE type = 'table' AND name = 'moz_items_annos')",
+    )?;
+
+    let tx = conn.begin_transaction()?;
+
+    let clear_mirror_on_drop = ExecuteOnDrop::new(&conn, WIPE_MIRROR.to_string());
+
+    log::debug!("Counting desktop bookmarks");
+    let num_total = select_count(&conn, COUNT_DESKTOP_BOOKMARKS)?;
+
+    // Clear the mirror now, since we're about to fill it with data from the
+    // desktop connection.
+    log::debug!("Clearing mirror to prepare for import");
+    conn.execute_batch(WIPE_MIRROR)?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Populating mirror with the bookmarks roots");
+    create_synced_bookmark_roots(&conn)?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Creating staging table");
+    conn.execute_batch(&CREATE_STAGING_TABLE)?;
+
+    log::debug!("Importing from desktop to staging table");
+    conn.execute_batch(&populate_staging_sql(has_item_annos))?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Populating missing entries in moz_places");
+    conn.execute_batch(&FILL_MOZ_PLACES)?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Populating mirror");
+    conn.execute_batch(&POPULATE_MIRROR)?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Populating mirror tags");
+    populate_mirror_tags(&conn, &scope)?;
+
+    log::debug!("Populating mirror structure");
+    conn.execute_batch(POPULATE_MIRROR_STRUCTURE)?;
+    scope.err_if_interrupted()?;
+
+    let mut merger = Merger::new(&conn, &scope, Default::default());
+    // We're already in a transaction.
+    merger.set_external_transaction(true);
+    log::debug!("Merging with local records");
+    merger.merge()?;
+    scope.err_if_interrupted()?;
+
+    // Update last modification time, sync status, etc
+    log::debug!("Fixing up bookmarks");
+    conn.execute_batch(&FIXUP_MOZ_BOOKMARKS)?;
+    scope.err_if_interrupted()?;
+    log::debug!("Recording the import source");
+    record_imported_from_mirror(&conn, BookmarkImportSource::Desktop)?;
+    scope.err_if_interrupted()?;
+    if !mappings.is_empty() {
+        log::debug!("Moving mapped roots");
+        move_mapped_roots_from_mirror(&conn, mappings, BookmarkImportSource::Desktop)?;
+        scope.err_if_interrupted()?;
+    }
+    // The mirror only has the desktop bookmarks, so we count what the merge
+    // applied before we clear it.
+    log::debug!("Counting imported keywords and tags");
+    let num_keywords = select_count(&conn, COUNT_IMPORTED_KEYWORDS)?;
+    let num_tags = select_count(&conn, COUNT_IMPORTED_TAGS)?;
+    log::debug!("Cleaning up mirror...");
+    clear_mirror_on_drop.execute_now()?;
+    log::debug!("Committing...");
+    tx.commit()?;
+
+    // Note: update_frecencies manages its own transaction, which is fine,
+    // since nothing that bad will happen if it is aborted.
+    log::debug!("Updating frecencies");
+    update_frecencies(&conn, &scope)?;
+
+    log::debug!("Counting imported bookmarks");
+    let num_succeeded = select_count(&conn, COUNT_IMPORTED_BOOKMARKS)?;
+    let num_failed = num_total.saturating_sub(num_succeeded);
+
+    auto_detach.execute_now()?;
+
+    let metrics = BookmarksMigrationResult {
+        num_total,
+        num_succeeded,
+        num_failed,
+        num_keywords,
+        num_tags,
+        total_duration: import_start.elapsed().as_millis(),
+    };
+    log::info!("Successfully imported bookmarks: {:?}", metrics);
+
+    Ok(metrics)
+}
+
+/// Adds the tags for the staged bookmarks to the mirror, so that the merger
+/// applies them to the URLs like it would for tags from Sync. Desktop keeps
+/// tags as folders under the tags root, named after the tag, holding a
+/// bookmark for each tagged URL.
+fn populate_mirror_tags(db: &PlacesDb, scope: &SqlInterruptScope) -> Result<()> {
+    let tagged = db.query_rows_and_then(
+        &format!(
+            "SELECT stage.guid, sanitize_utf8(t.title)
+             FROM temp.desktopBookmarksStaging stage
+             JOIN desktop.moz_bookmarks b ON b.id = stage.id
+             JOIN desktop.moz_bookmarks tb ON tb.fk = b.fk
+             JOIN desktop.moz_bookmarks t ON t.id = tb.parent
+             JOIN desktop.moz_bookmarks r ON r.id = t.parent
+             WHERE stage.type = {bookmark_type}
+               AND r.guid = 'tags________'",
+            bookmark_type = BookmarkType::Bookmark as u8,
+        ),
+        [],
+        |row| -> rusqlite::Result<(String, Option<String>)> { Ok((row.get(0)?, row.get(1)?)) },
+    )?;
+    for (guid, tag) in tagged {
+        scope.err_if_interrupted()?;
+        let tag = match tag
+            .as_deref()
+            .and_then(|t| validate_tag(t).ensure_valid().ok())
+        {
+            Some(t) => t.to_string(),
+            None => {
+                log::trace!("Ignoring invalid desktop tag: {:?}", tag);
+                continue;
+            }
+        };
+        db.execute_cached(
+            "INSERT OR IGNORE INTO moz_tags(tag, lastModified)
+             VALUES(:tag, now())",
+            &[(":tag", &tag)],
+        )?;
+        db.execute_cached(
+            "INSERT OR IGNORE INTO moz_bookmarks_synced_tag_relation(itemId, tagId)
+             VALUES((SELECT id FROM moz_bookmarks_synced
+                     WHERE guid = :guid),
+                    (SELECT id FROM moz_tags
+                     WHERE tag = :tag))",
+            &[(":guid", &guid), (":tag", &tag)],
+        )?;
+    }
+    Ok(())
+}
+
+// Desktop's item annotation for a livemark's feed. We don't support
+// livemarks, so we leave them, and their children, out.
+const LIVEMARK_FEED_ANNO: &str = "livemark/feedURI";
+
+// Stages the user roots, and everything under them, except queries and
+// livemarks. Desktop's timestamps are in microseconds. The roots are staged
+// without titles, since we have our own.
+fn populate_staging_sql(has_item_annos: bool) -> String {
+    format!(
+        "INSERT OR IGNORE INTO temp.desktopBookmarksStaging(
+            id,
+            guid,
+            type,
+            parent_guid,
+            pos,
+            title,
+            bmkUri,
+            keyword,
+            date_added,
+            modified
+        )
+        WITH RECURSIVE items(id, guid, parent_guid) AS (
+            SELECT b.id, b.guid, 'root________'
+            FROM desktop.moz_bookmarks b
+            WHERE b.guid IN ('menu________', 'toolbar_____', 'unfiled_____', 'mobile______')
+            UNION ALL
+            SELECT b.id, sanitize_utf8(b.guid), items.guid
+            FROM desktop.moz_bookmarks b
+            JOIN items ON b.parent = items.id
+            WHERE b.type IN ({bookmark_type}, {folder_type}, {separator_type})
+              {livemark_filter}
+        )
+        SELECT
+            i.id,
+            i.guid,
+            b.type,
+            i.parent_guid,
+            b.position,
+            CASE WHEN i.parent_guid = 'root________'
+                THEN NULL
+                ELSE sanitize_utf8(b.title)
+            END,
+            CASE WHEN b.type = {bookmark_type}
+                THEN validate_url(h.url)
+                ELSE NULL
+            END AS uri,
+            sanitize_utf8(k.keyword),
+            sanitize_timestamp(b.dateAdded / 1000),
+            max(sanitize_timestamp(b.dateAdded / 1000), sanitize_timestamp(b.lastModified / 1000))
+        FROM items i
+        JOIN desktop.moz_bookmarks b ON b.id = i.id
+        LEFT JOIN desktop.moz_places h ON h.id = b.fk
+        LEFT JOIN desktop.moz_keywords k ON k.place_id = b.fk AND k.post_data IS NULL
+        WHERE i.guid IS NOT NULL
+          AND (b.type != {bookmark_type} OR (uri IS NOT NULL AND uri NOT LIKE 'place:%'))",
+        bookmark_type = BookmarkType::Bookmark as u8,
+        folder_type = BookmarkType::Folder as u8,
+        separator_type = BookmarkType::Separator as u8,
+        livemark_filter = if has_item_annos {
+            format!(
+                "AND NOT EXISTS(SELECT 1 FROM desktop.moz_items_annos a
+                                JOIN desktop.moz_anno_attributes n ON n.id = a.anno_attribute_id
+                                WHERE a.item_id = b.id AND n.name = '{}')",
+                LIVEMARK_FEED_ANNO
+            )
+        } else {
+            String::new()
+        },
+    )
+}
+
+lazy_static::lazy_static! {
+    static ref CREATE_STAGING_TABLE: String = format!("
+        CREATE TEMP TABLE temp.desktopBookmarksStaging(
+            id INTEGER PRIMARY KEY,
+            guid TEXT NOT NULL UNIQUE,
+            type TINYINT NOT NULL
+                CHECK(type == {bookmark_type} OR type == {folder_type} OR type == {separator_type}),
+            parent_guid TEXT NOT NULL,
+            pos INT,
+            title TEXT,
+            bmkUri TEXT
+                CHECK(type != {bookmark_type} OR validate_url(bmkUri) == bmkUri),
+            keyword TEXT,
+            date_added INTEGER NOT NULL,
+            modified INTEGER NOT NULL
+        )",
+        bookmark_type = BookmarkType::Bookmark as u8,
+        folder_type = BookmarkType::Folder as u8,
+        separator_type = BookmarkType::Separator as u8,
+    );
+
+    // Insert any missing entries into moz_places that we'll need for this.
+    // No need to validate URLs here because we already did when filling the
+    // staging table.
+    static ref FILL_MOZ_PLACES: String = format!(
+        "INSERT OR IGNORE INTO main.moz_places(guid, url, url_hash, frecency)
+         SELECT IFNULL((SELECT p.guid FROM main.moz_places p
+                        WHERE p.url_hash = hash(b.bmkUri) AND p.url = b.bmkUri),
+                       generate_guid()),
+                b.bmkUri,
+                hash(b.bmkUri),
+                -1
+         FROM temp.desktopBookmarksStaging b
+         WHERE b.bmkUri IS NOT NULL
+           AND b.type = {bookmark_type}",
+        bookmark_type = BookmarkType::Bookmark as u8,
+    );
+
+    static ref POPULATE_MIRROR: String = format!(
+        "REPLACE INTO main.moz_bookmarks_synced(
+            guid,
+            parentGuid,
+            serverModified,
+            needsMerge,
+            validity,
+            isDeleted,
+            kind,
+            dateAdded,
+            title,
+            placeId,
+            keyword
+        )
+        SELECT
+            b.guid,
+            b.parent_guid,
+            b.modified,
+            1, -- needsMerge
+            1, -- VALIDITY_VALID
+            0, -- isDeleted
+            CASE b.type
+                WHEN {bookmark_type} THEN {bookmark_kind}
+                WHEN {folder_type} THEN {folder_kind}
+                WHEN {separator_type} THEN {separator_kind}
+                -- We filter out anything else when inserting into the stage table
+            END,
+            b.date_added,
+            b.title,
+            -- placeId
+            CASE WHEN b.bmkUri IS NULL
+            THEN NULL
+            ELSE (SELECT id FROM main.moz_places p
+                  WHERE p.url_hash = hash(b.bmkUri) AND p.url = b.bmkUri)
+            END,
+            -- Like Sync, we store keywords trimmed and lowercase.
+            NULLIF(lower(trim(b.keyword)), '')
+        FROM temp.desktopBookmarksStaging b",
+        bookmark_kind = SyncedBookmarkKind::Bookmark as u8,
+        folder_kind = SyncedBookmarkKind::Folder as u8,
+        separator_kind = SyncedBookmarkKind::Separator as u8,
+
+        bookmark_type = BookmarkType::Bookmark as u8,
+        folder_type = BookmarkType::Folder as u8,
+        separator_type = BookmarkType::Separator as u8,
+    );
+
+    static ref FIXUP_MOZ_BOOKMARKS: String = format!(
+        "UPDATE main.moz_bookmarks SET
+           syncStatus = {unknown},
+           syncChangeCounter = 1,
+           lastModified = IFNULL((SELECT stage.modified FROM temp.desktopBookmarksStaging stage
+                                  WHERE stage.guid = main.moz_bookmarks.guid),
+                                 lastModified)",
+        unknown = SyncStatus::Unknown as u8
+    );
+}
+
+const WIPE_MIRROR: &str = "DELETE FROM main.moz_bookmarks_synced;
+ DELETE FROM main.moz_bookmarks_synced_structure;";
+
+// The roots are already in the structure, in our order, from
+// `create_synced_bookmark_roots`.
+const POPULATE_MIRROR_STRUCTURE: &str = "
+REPLACE INTO main.moz_bookmarks_synced_structure(guid, parentGuid, position)
+    SELECT stage.guid, stage.parent_guid, stage.pos FROM temp.desktopBookmarksStaging stage
+    WHERE stage.parent_guid != 'root________';
+";
+
+// Count desktop bookmarks. We exclude the Places root, and the tags root and
+// everything under it, because this count is used for success metrics.
+const COUNT_DESKTOP_BOOKMARKS: &str = "
+    SELECT COUNT(*) FROM desktop.moz_bookmarks b
+    WHERE b.guid NOT IN ('root________', 'tags________')
+      AND b.parent NOT IN (
+          SELECT t.id FROM desktop.moz_bookmarks t
+          JOIN desktop.moz_bookmarks r ON r.id = t.parent OR r.id = t.id
+          WHERE r.guid = 'tags________'
+      )";
+
+// Count the staged bookmarks which made it into Places.
+const COUNT_IMPORTED_BOOKMARKS: &str = "
+    SELECT COUNT(*) FROM main.moz_bookmarks b
+    WHERE b.guid IN (SELECT guid FROM temp.desktopBookmarksStaging)";
+
+mod sql_fns {
+    use crate::import::common::sql_fns::{sanitize_integer_timestamp, sanitize_utf8, validate_url};
+    use rusqlite::{functions::FunctionFlags, Connection, Result};
+
+    pub(super) fn define_functions(c: &Connection) -> Result<()> {
+        c.create_scalar_function(
+            "validate_url",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            validate_url,
+        )?;
+        c.create_scalar_function(
+            "sanitize_timestamp",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            sanitize_integer_timestamp,
+        )?;
+        c.create_scalar_function(
+            "sanitize_utf8",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            sanitize_utf8,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_api;
+    use crate::api::places_api::ConnectionType;
+    use crate::storage::bookmarks::{
+        get_raw_bookmark, insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+    };
+    use rusqlite::Connection;
+    use sync_guid::Guid as SyncGuid;
+
+    // A desktop database with a bookmark on the toolbar, and one in the menu.
+    // Desktop's timestamps are in microseconds.
+    fn create_desktop_db(path: &std::path::Path) -> Result<()> {
+        let desktop = Connection::open(path)?;
+        desktop.execute_batch(
+            "CREATE TABLE moz_places(id INTEGER PRIMARY KEY, url TEXT);
+             CREATE TABLE moz_historyvisits(id INTEGER PRIMARY KEY, place_id INTEGER);
+             CREATE TABLE moz_keywords(id INTEGER PRIMARY KEY, keyword TEXT,
+                                       place_id INTEGER, post_data TEXT);
+             CREATE TABLE moz_bookmarks(id INTEGER PRIMARY KEY, type INTEGER, fk INTEGER,
+                                        parent INTEGER, position INTEGER, title TEXT,
+                                        dateAdded INTEGER, lastModified INTEGER, guid TEXT);
+             INSERT INTO moz_places(id, url)
+             VALUES(1, 'https://example.com/a'), (2, 'https://example.com/b');
+             INSERT INTO moz_bookmarks(id, type, fk, parent, position, title,
+                                       dateAdded, lastModified, guid)
+             VALUES(1, 2, NULL, 0, 0, '', 1600000000000000, 1600000000000000, 'root________'),
+                   (2, 2, NULL, 1, 0, 'menu', 1600000000000000, 1600000000000000, 'menu________'),
+                   (3, 2, NULL, 1, 1, 'toolbar', 1600000000000000, 1600000000000000, 'toolbar_____'),
+                   (4, 1, 1, 3, 0, 'A', 1600000000000000, 1600000000000000, 'bookmarkAAAA'),
+                   (5, 1, 2, 2, 0, 'B', 1600000000000000, 1600000000000000, 'bookmarkBBBB');",
+        )?;
+        Ok(())
+    }
+
+    fn parent_of(conn: &PlacesDb, guid: &str) -> Result<SyncGuid> {
+        Ok(get_raw_bookmark(conn, &guid.into())?
+            .expect("should exist")
+            .parent_guid
+            .expect("should have a parent"))
+    }
+
+    #[test]
+    fn test_import_with_mappings() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("places.sqlite");
+        create_desktop_db(&path)?;
+
+        let api = new_mem_api();
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        insert_bookmark(
+            &conn,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Toolbar.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: Some("bookmarkLLLL".into()),
+                url: Url::parse("https://example.com/local").unwrap(),
+                title: None,
+            }
+            .into(),
+        )?;
+        api.close_connection(conn)?;
+
+        let mappings = [BookmarkImportRootMapping {
+            source_root: BookmarkRootGuid::Toolbar.into(),
+            parent_guid: BookmarkRootGuid::Unfiled.into(),
+            folder_title: Some("Imported toolbar".into()),
+        }];
+        let result = import_with_mappings(&api, &path, &mappings)?;
+        assert_eq!(result.num_failed, 0);
+
+        let conn = api.open_connection(ConnectionType::ReadOnly)?;
+        // The toolbar bookmark is moved into a new folder in unfiled, but the
+        // local one stays on the toolbar.
+        let folder = parent_of(&conn, "bookmarkAAAA")?;
+        let raw_folder = get_raw_bookmark(&conn, &folder)?.expect("should exist");
+        assert_eq!(raw_folder.bookmark_type, BookmarkType::Folder);
+        assert_eq!(raw_folder.title.as_deref(), Some("Imported toolbar"));
+        assert_eq!(
+            raw_folder.parent_guid,
+            Some(BookmarkRootGuid::Unfiled.as_guid())
+        );
+        assert_eq!(
+            parent_of(&conn, "bookmarkLLLL")?,
+            BookmarkRootGuid::Toolbar.as_guid()
+        );
+        // Roots without a mapping are merged into the local roots.
+        assert_eq!(
+            parent_of(&conn, "bookmarkBBBB")?,
+            BookmarkRootGuid::Menu.as_guid()
+        );
+
+        // The new folder was imported too, so removing the import removes it.
+        let imported = crate::storage::bookmarks::import_source::fetch_imported_bookmarks(
+            &conn,
+            BookmarkImportSource::Desktop,
+        )?;
+        assert!(imported.iter().any(|item| item.guid() == &folder));
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::api::places_api::PlacesApi;
+use crate::bookmark_sync::engine::update_frecencies;
+use crate::error::*;
+use crate::import::common::{
+    attached_database, define_history_migration_functions, select_count, HistoryMigrationResult,
+};
+use crate::types::VisitTransition;
+use interrupt_support::InterruptPriority;
+use sql_support::ConnExt;
+use std::time::Instant;
+use types::Timestamp;
+use url::Url;
+
+/// This import is used for users moving from Firefox Desktop, who've copied
+/// its `places.sqlite` database somewhere we can read it.
+///
+/// ### Basic process
+///
+/// - Attach the desktop database.
+/// - Slurp the pages into a temp table, to normalize (punycode) them.
+/// - Add any entries to moz_places that are needed.
+/// - Insert the visits. Desktop uses the same visit types as us, but its
+///   dates are in microseconds.
+/// - Update frecency for new items.
+/// - Cleanup (detach the desktop database, etc).
+pub fn import(
+    places_api: &PlacesApi,
+    path: impl AsRef<std::path::Path>,
+) -> Result<HistoryMigrationResult> {
+    let url = crate::util::ensure_url_path(path)?;
+    do_import(places_api, url)
+}
+
+fn do_import(places_api: &PlacesApi, desktop_db_file_url: Url) -> Result<HistoryMigrationResult> {
+    let conn_mutex = places_api.get_sync_connection()?;
+    let conn = conn_mutex.lock();
+    let _operation = conn.begin_operation(InterruptPriority::Background);
+
+    let scope = conn.begin_interrupt_scope()?;
+
+    define_history_migration_functions(&conn)?;
+
+    let import_start = Instant::now();
+    log::trace!("Attaching database {}", desktop_db_file_url);
+    let auto_detach = attached_database(&conn, &desktop_db_file_url, "desktop", None)?;
+    super::check_desktop_db(&conn)?;
+
+    // Desktop only started recording where visits came from in Firefox 87,
+    // so older profiles don't have the column.
+    let has_visit_source = conn.query_one::<bool>(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('moz_historyvisits', 'desktop')
+                       WHERE name = 'source')",
+    )?;
+
+    let tx = conn.begin_transaction()?;
+
+    log::debug!("Counting desktop history visits");
+    let num_total = select_count(&conn, COUNT_DESKTOP_HISTORY_VISITS)?;
+
+    log::debug!("Creating and populating staging table");
+    conn.execute_batch(CREATE_STAGING_TABLE)?;
+    conn.execute_batch(FILL_STAGING)?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Populating missing entries in moz_places");
+    conn.execute_batch(FILL_MOZ_PLACES)?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Inserting the history visits");
+    let num_succeeded = conn.execute(&insert_history_visits_sql(has_visit_source), [])? as u32;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Marking new entries as having stale frecencies");
+    conn.execute(
+        ADD_TO_STALE_FRECENCIES,
+        &[(":now", &Timestamp::now().as_millis())],
+    )?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Committing...");
+    tx.commit()?;
+
+    // Note: update_frecencies manages its own transaction, which is fine,
+    // since nothing that bad will happen if it is aborted.
+    log::debug!("Updating frecencies");
+    update_frecencies(&conn, &scope)?;
+
+    log::info!("Successfully imported history visits!");
+
+    let num_failed = num_total.saturating_sub(num_succeeded);
+
+    auto_detach.execute_now()?;
+
+    let metrics = HistoryMigrationResult {
+        num_total,
+        num_succeeded,
+        num_failed,
+        total_duration: import_start.elapsed().as_millis() as u64,
+    };
+
+    Ok(metrics)
+}
+
+// Desktop marks visits from Sync with this `source`.
+const DESKTOP_SOURCE_SYNCED: u8 = 1;
+
+// Insert history visits. Desktop's visit dates are in microseconds. Its
+// `from_visit`s refer to its own visit ids, so we don't keep them. Visit
+// types we don't know about, like ones added after this was written, are
+// imported as links. Visits we already have, like ones from an earlier
+// import, are skipped.
+fn insert_history_visits_sql(has_visit_source: bool) -> String {
+    format!(
+        "INSERT INTO main.moz_historyvisits(from_visit, place_id, visit_date, visit_type, is_local)
+            SELECT NULL, place_id, visit_date, visit_type, is_local
+            FROM (SELECT
+                    (SELECT p.id FROM main.moz_places p
+                     WHERE p.url_hash = t.url_hash AND p.url = t.url) AS place_id,
+                    sanitize_timestamp(v.visit_date / 1000) AS visit_date,
+                    CASE WHEN v.visit_type BETWEEN {first_type} AND {last_type}
+                         THEN v.visit_type
+                         ELSE {link_type}
+                    END AS visit_type,
+                    {is_local} AS is_local
+                  FROM desktop.moz_historyvisits v
+                  JOIN temp.desktopHistoryStaging t ON t.id = v.place_id) n
+            WHERE NOT EXISTS(SELECT 1 FROM main.moz_historyvisits e
+                             WHERE e.place_id = n.place_id AND e.visit_date = n.visit_date)",
+        first_type = VisitTransition::Link as u8,
+        last_type = VisitTransition::Reload as u8,
+        link_type = VisitTransition::Link as u8,
+        is_local = if has_visit_source {
+            format!("v.source IS NOT {}", DESKTOP_SOURCE_SYNCED)
+        } else {
+            "1".to_string()
+        },
+    )
+}
+
+// We use a staging table purely so that we can normalize URLs (and
+// specifically, punycode them)
+const CREATE_STAGING_TABLE: &str = "
+    CREATE TEMP TABLE temp.desktopHistoryStaging(
+        id INTEGER PRIMARY KEY,
+        url TEXT NOT NULL,
+        url_hash INTEGER NOT NULL,
+        title TEXT
+    ) WITHOUT ROWID;";
+
+// Bookmarks of `place:` URLs are queries, which aren't history, so they're
+// left out, along with their pages.
+const FILL_STAGING: &str = "
+    INSERT OR IGNORE INTO temp.desktopHistoryStaging(id, url, url_hash, title)
+        SELECT
+            h.id,
+            validate_url(h.url),
+            hash(validate_url(h.url)),
+            NULLIF(sanitize_utf8(h.title), '')
+        FROM desktop.moz_places h
+        WHERE validate_url(h.url) IS NOT NULL
+          AND h.url NOT LIKE 'place:%'
+          AND EXISTS(SELECT 1 FROM desktop.moz_historyvisits v WHERE v.place_id = h.id)";
+
+// Insert any missing entries into moz_places that we'll need for this.
+const FILL_MOZ_PLACES: &str = "
+    INSERT OR IGNORE INTO main.moz_places(guid, url, url_hash, title, frecency, sync_change_counter)
+        SELECT
+            IFNULL(
+                (SELECT p.guid FROM main.moz_places p WHERE p.url_hash = t.url_hash AND p.url = t.url),
+                generate_guid()
+            ),
+            t.url,
+            t.url_hash,
+            t.title,
+            -1,
+            1
+        FROM temp.desktopHistoryStaging t";
+
+// Adds newly modified places entries into the stale frecencies table
+const ADD_TO_STALE_FRECENCIES: &str = "
+    INSERT OR IGNORE INTO main.moz_places_stale_frecencies(place_id, stale_at)
+        SELECT p.id, :now
+        FROM main.moz_places p
+        WHERE p.frecency = -1";
+
+// Count desktop history visits
+const COUNT_DESKTOP_HISTORY_VISITS: &str = "SELECT COUNT(*) FROM desktop.moz_historyvisits";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_api;
+    use crate::api::places_api::ConnectionType;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::apply_observation;
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_import_counts_new_visits() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("places.sqlite");
+        let desktop = Connection::open(&path)?;
+        // Desktop's visit dates are in microseconds.
+        desktop.execute_batch(
+            "CREATE TABLE moz_places(id INTEGER PRIMARY KEY, url TEXT, title TEXT);
+             CREATE TABLE moz_historyvisits(id INTEGER PRIMARY KEY, place_id INTEGER,
+                                            visit_date INTEGER, visit_type INTEGER);
+             CREATE TABLE moz_bookmarks(id INTEGER PRIMARY KEY);
+             INSERT INTO moz_places(id, url, title)
+             VALUES(1, 'https://example.com/', 'Example');
+             INSERT INTO moz_historyvisits(place_id, visit_date, visit_type)
+             VALUES(1, 1600000000000000, 1), (1, 1600000001000000, 1);",
+        )?;
+        drop(desktop);
+
+        let api = new_mem_api();
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        apply_observation(
+            &conn,
+            VisitObservation::new(Url::parse("https://example.com/").unwrap())
+                .with_at(Timestamp(1_600_000_000_000))
+                .with_visit_type(VisitTransition::Link),
+        )?;
+        api.close_connection(conn)?;
+
+        // We already have the first visit, so only the second is imported.
+        let result = import(&api, &path)?;
+        assert_eq!(result.num_total, 2);
+        assert_eq!(result.num_succeeded, 1);
+        assert_eq!(result.num_failed, 1);
+
+        // Importing again doesn't add any visits.
+        let result = import(&api, &path)?;
+        assert_eq!(result.num_succeeded, 0);
+
+        let conn = api.open_connection(ConnectionType::ReadOnly)?;
+        assert_eq!(
+            conn.query_one::<i64>("SELECT COUNT(*) FROM moz_historyvisits")?,
+            2
+        );
+        Ok(())
+    }
+}
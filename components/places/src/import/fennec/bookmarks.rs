@@ -9,9 +9,20 @@ use crate::bookmark_sync::{
 };
 use crate::db::db::PlacesDb;
 use crate::error::*;
-use crate::import::common::{attached_database, ExecuteOnDrop};
-use crate::storage::bookmarks::{bookmark_sync::create_synced_bookmark_roots, fetch::BookmarkData};
+use crate::import::common::{
+    attached_database, ExecuteOnDrop, COUNT_IMPORTED_KEYWORDS, COUNT_IMPORTED_TAGS,
+};
+use crate::storage::bookmarks::{
+    bookmark_sync::create_synced_bookmark_roots,
+    fetch::BookmarkData,
+    import_source::{
+        move_mapped_roots_from_mirror, record_imported_from_mirror, BookmarkImportRootMapping,
+        BookmarkImportSource,
+    },
+};
+use crate::storage::tags::validate_tag;
 use crate::types::SyncStatus;
+use interrupt_support::{InterruptPriority, SqlInterruptScope};
 use serde_derive::*;
 use sql_support::ConnExt;
 use std::time::Instant;
@@ -28,6 +39,11 @@ pub struct BookmarksMigrationResult {
     pub num_total: u32,
     pub num_succeeded: u32,
     pub num_failed: u32,
+    /// The number of bookmarks whose keyword we imported.
+    pub num_keywords: u32,
+    /// The number of tags we imported, counting each tag once for each
+    /// bookmark it's on.
+    pub num_tags: u32,
     pub total_duration: u128,
 }
 
@@ -37,25 +53,46 @@ fn select_count(conn: &PlacesDb, stmt: &str) -> u32 {
     count.unwrap().unwrap()
 }
 
+/// Imports the bookmarks from Fennec's `browser.db` at `path`. If the database
+/// is encrypted with SQLCipher, `key` is its key.
 pub fn import(
     places_api: &PlacesApi,
     path: impl AsRef<std::path::Path>,
+    key: Option<&str>,
+) -> Result<BookmarksMigrationResult> {
+    import_with_mappings(places_api, path, &[], key)
+}
+
+/// Like `import`, but puts the children of the roots in `mappings` where
+/// they say, instead of in the matching local roots.
+pub fn import_with_mappings(
+    places_api: &PlacesApi,
+    path: impl AsRef<std::path::Path>,
+    mappings: &[BookmarkImportRootMapping],
+    key: Option<&str>,
 ) -> Result<BookmarksMigrationResult> {
     let url = crate::util::ensure_url_path(path)?;
-    do_import(places_api, url)
+    do_import(places_api, url, mappings, key)
 }
 
 pub fn import_pinned_sites(
     places_api: &PlacesApi,
     path: impl AsRef<std::path::Path>,
+    key: Option<&str>,
 ) -> Result<Vec<BookmarkData>> {
     let url = crate::util::ensure_url_path(path)?;
-    do_pinned_sites_import(places_api, url)
+    do_pinned_sites_import(places_api, url, key)
 }
 
-fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<BookmarksMigrationResult> {
+fn do_import(
+    places_api: &PlacesApi,
+    fennec_db_file_url: Url,
+    mappings: &[BookmarkImportRootMapping],
+    key: Option<&str>,
+) -> Result<BookmarksMigrationResult> {
     let conn_mutex = places_api.get_sync_connection()?;
     let conn = conn_mutex.lock();
+    let _operation = conn.begin_operation(InterruptPriority::Background);
 
     let scope = conn.begin_interrupt_scope()?;
 
@@ -68,7 +105,7 @@ fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<Bookmark
 
     let import_start = Instant::now();
     log::trace!("Attaching database {}", fennec_db_file_url);
-    let auto_detach = attached_database(&conn, &fennec_db_file_url, "fennec")?;
+    let auto_detach = attached_database(&conn, &fennec_db_file_url, "fennec", key)?;
 
     let db_version = conn.db.query_one::<i64>("PRAGMA fennec.user_version")?;
     if db_version < FENNEC_DB_VERSION {
@@ -107,6 +144,9 @@ fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<Bookmark
     conn.execute_batch(&POPULATE_MIRROR)?;
     scope.err_if_interrupted()?;
 
+    log::debug!("Populating mirror tags");
+    populate_mirror_tags(&conn, &scope)?;
+
     // Ideally we could just do this right after `CREATE_AND_POPULATE_STAGING`,
     // but we have constraints on the mirror structure that prevent this (and
     // there's probably nothing bad that can happen in this case anyway). We
@@ -127,6 +167,19 @@ fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<Bookmark
     log::debug!("Fixing up bookmarks");
     conn.execute_batch(&FIXUP_MOZ_BOOKMARKS)?;
     scope.err_if_interrupted()?;
+    log::debug!("Recording the import source");
+    record_imported_from_mirror(&conn, BookmarkImportSource::Fennec)?;
+    scope.err_if_interrupted()?;
+    if !mappings.is_empty() {
+        log::debug!("Moving mapped roots");
+        move_mapped_roots_from_mirror(&conn, mappings, BookmarkImportSource::Fennec)?;
+        scope.err_if_interrupted()?;
+    }
+    // The mirror only has the Fennec bookmarks, so we count what the merge
+    // applied before we clear it.
+    log::debug!("Counting imported keywords and tags");
+    let num_keywords = select_count(&conn, COUNT_IMPORTED_KEYWORDS);
+    let num_tags = select_count(&conn, COUNT_IMPORTED_TAGS);
     log::debug!("Cleaning up mirror...");
     clear_mirror_on_drop.execute_now()?;
     log::debug!("Committing...");
@@ -149,6 +202,8 @@ fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<Bookmark
         num_total,
         num_succeeded,
         num_failed,
+        num_keywords,
+        num_tags,
         total_duration: import_start.elapsed().as_millis(),
     };
     log::info!("Successfully imported bookmarks: {:?}", metrics);
@@ -159,15 +214,17 @@ fn do_import(places_api: &PlacesApi, fennec_db_file_url: Url) -> Result<Bookmark
 fn do_pinned_sites_import(
     places_api: &PlacesApi,
     fennec_db_file_url: Url,
+    key: Option<&str>,
 ) -> Result<Vec<BookmarkData>> {
     let conn_mutex = places_api.get_sync_connection()?;
     let conn = conn_mutex.lock();
+    let _operation = conn.begin_operation(InterruptPriority::Background);
     let scope = conn.begin_interrupt_scope()?;
 
     sql_fns::define_functions(&conn)?;
 
     log::trace!("Attaching database {}", fennec_db_file_url);
-    let auto_detach = attached_database(&conn, &fennec_db_file_url, "fennec")?;
+    let auto_detach = attached_database(&conn, &fennec_db_file_url, "fennec", key)?;
 
     let db_version = conn.db.query_one::<i64>("PRAGMA fennec.user_version")?;
     if db_version < FENNEC_DB_VERSION {
@@ -257,7 +314,8 @@ lazy_static::lazy_static! {
             ELSE (SELECT id FROM main.moz_places p
                   WHERE p.url_hash = hash(b.bmkUri) AND p.url = b.bmkUri)
             END,
-            b.keyword
+            -- Like Sync, we store keywords trimmed and lowercase.
+            NULLIF(lower(trim(b.keyword)), '')
         FROM fennecBookmarksStaging b",
         bookmark_kind = SyncedBookmarkKind::Bookmark as u8,
         folder_kind = SyncedBookmarkKind::Folder as u8,
@@ -414,6 +472,64 @@ lazy_static::lazy_static! {
     ;
 }
 
+/// Fennec stores a bookmark's tags as a JSON array of strings, exactly as it
+/// received them from Sync. Invalid and duplicate tags are dropped, as is the
+/// whole list if it isn't an array.
+fn parse_fennec_tags(raw: &str) -> Vec<String> {
+    let values = match serde_json::from_str::<serde_json::Value>(raw) {
+        Ok(serde_json::Value::Array(values)) => values,
+        _ => {
+            log::trace!("Fennec bookmark has unexpected tags: {:?}", raw);
+            return Vec::new();
+        }
+    };
+    let mut tags: Vec<String> = Vec::with_capacity(values.len());
+    for value in &values {
+        if let Some(tag) = value
+            .as_str()
+            .and_then(|s| validate_tag(s).ensure_valid().ok())
+        {
+            if !tags.iter().any(|t| t == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+    }
+    tags
+}
+
+/// Adds the tags for the staged bookmarks to the mirror, so that the merger
+/// applies them to the URLs like it would for tags from Sync.
+fn populate_mirror_tags(db: &PlacesDb, scope: &SqlInterruptScope) -> Result<()> {
+    let tagged = db.query_rows_and_then(
+        &format!(
+            "SELECT guid, tags FROM temp.fennecBookmarksStaging
+             WHERE type = {fennec_bookmark_type} AND tags NOT NULL",
+            fennec_bookmark_type = FennecBookmarkType::Bookmark as u8,
+        ),
+        [],
+        |row| -> rusqlite::Result<(String, String)> { Ok((row.get(0)?, row.get(1)?)) },
+    )?;
+    for (guid, raw_tags) in tagged {
+        scope.err_if_interrupted()?;
+        for tag in parse_fennec_tags(&raw_tags) {
+            db.execute_cached(
+                "INSERT OR IGNORE INTO moz_tags(tag, lastModified)
+                 VALUES(:tag, now())",
+                &[(":tag", &tag)],
+            )?;
+            db.execute_cached(
+                "INSERT OR IGNORE INTO moz_bookmarks_synced_tag_relation(itemId, tagId)
+                 VALUES((SELECT id FROM moz_bookmarks_synced
+                         WHERE guid = :guid),
+                        (SELECT id FROM moz_tags
+                         WHERE tag = :tag))",
+                &[(":guid", &guid), (":tag", &tag)],
+            )?;
+        }
+    }
+    Ok(())
+}
+
 fn bookmark_data_from_fennec_pinned(
     row: &rusqlite::Row<'_>,
 ) -> std::result::Result<Option<BookmarkData>, rusqlite::Error> {
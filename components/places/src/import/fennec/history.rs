@@ -8,6 +8,7 @@ use crate::error::*;
 use crate::import::common::{
     attached_database, define_history_migration_functions, select_count, HistoryMigrationResult,
 };
+use interrupt_support::InterruptPriority;
 use sql_support::ConnExt;
 use std::time::Instant;
 use url::Url;
@@ -16,17 +17,25 @@ use url::Url;
 // However, 36 was quite easy to obtain test databases for, and it shipped with quite an old ESR version (52).
 const FENNEC_DB_VERSION: i64 = 34;
 
+/// Imports the history from Fennec's `browser.db` at `path`. If the database is
+/// encrypted with SQLCipher, `key` is its key.
 pub fn import(
     places_api: &PlacesApi,
     path: impl AsRef<std::path::Path>,
+    key: Option<&str>,
 ) -> Result<HistoryMigrationResult> {
     let url = crate::util::ensure_url_path(path)?;
-    do_import(places_api, url)
+    do_import(places_api, url, key)
 }
 
-fn do_import(places_api: &PlacesApi, android_db_file_url: Url) -> Result<HistoryMigrationResult> {
+fn do_import(
+    places_api: &PlacesApi,
+    android_db_file_url: Url,
+    key: Option<&str>,
+) -> Result<HistoryMigrationResult> {
     let conn_mutex = places_api.get_sync_connection()?;
     let conn = conn_mutex.lock();
+    let _operation = conn.begin_operation(InterruptPriority::Background);
 
     let scope = conn.begin_interrupt_scope()?;
 
@@ -39,7 +48,7 @@ fn do_import(places_api: &PlacesApi, android_db_file_url: Url) -> Result<History
 
     let import_start = Instant::now();
     log::trace!("Attaching database {}", android_db_file_url);
-    let auto_detach = attached_database(&conn, &android_db_file_url, "fennec")?;
+    let auto_detach = attached_database(&conn, &android_db_file_url, "fennec", key)?;
 
     let db_version = conn.db.query_one::<i64>("PRAGMA fennec.user_version")?;
     if db_version < FENNEC_DB_VERSION {
@@ -0,0 +1,929 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::api::places_api::{ConnectionType, PlacesApi};
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::import::common::sql_fns::sanitize_timestamp;
+use crate::observer;
+use crate::storage::bookmarks::import_source::{
+    record_imported_tree, BookmarkImportRootMapping, BookmarkImportSource, ImportDestinations,
+};
+use crate::storage::bookmarks::{
+    insert_bookmark_in_tx, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+    InsertableFolder, InsertableItem, InsertableSeparator,
+};
+use crate::storage::{delete_pending_temp_tables, URL_LENGTH_MAX};
+use interrupt_support::InterruptPriority;
+use rusqlite::named_params;
+use sql_support::ConnExt;
+use std::collections::HashSet;
+use sync_guid::Guid as SyncGuid;
+use types::Timestamp;
+use url::Url;
+
+/// The title of the folder each import goes into.
+const IMPORTED_FOLDER_TITLE: &str = "Imported";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HtmlBookmarksImportResult {
+    /// The number of bookmarks inserted. Folders and separators aren't
+    /// counted.
+    pub num_inserted: u32,
+    /// The number of bookmarks skipped, because their URL was already
+    /// bookmarked, or isn't valid.
+    pub num_skipped: u32,
+}
+
+/// Imports the bookmarks in `path`, a file in the `NETSCAPE-Bookmark-file-1`
+/// HTML format which every browser can export.
+///
+/// Everything goes into a new "Imported" folder in "Other Bookmarks",
+/// keeping the folder structure of the file. Bookmarks for URLs which are
+/// already bookmarked, including earlier in the same file, are skipped, so
+/// importing the same file twice doesn't duplicate anything. Folders which
+/// that leaves without any bookmarks are skipped too, and if nothing is left
+/// at all, no folder is created.
+///
+/// Keywords (`SHORTCUTURL`) and dates are imported; other attributes, like
+/// icons and tags, are ignored.
+pub fn import_bookmarks(
+    places_api: &PlacesApi,
+    path: impl AsRef<std::path::Path>,
+) -> Result<HtmlBookmarksImportResult> {
+    import_bookmarks_with_mappings(places_api, path, &[])
+}
+
+/// Like `import_bookmarks`, but puts the bookmarks from the roots in
+/// `mappings` where they say, instead of in the "Imported" folder. Files
+/// mark the toolbar and "Other Bookmarks" folders with attributes, and
+/// everything else in the file is from the menu. This is also how we import
+/// bookmarks from Chrome, which exports the same format.
+pub fn import_bookmarks_with_mappings(
+    places_api: &PlacesApi,
+    path: impl AsRef<std::path::Path>,
+    mappings: &[BookmarkImportRootMapping],
+) -> Result<HtmlBookmarksImportResult> {
+    let contents = std::fs::read(path)?;
+    let items = parse(&String::from_utf8_lossy(&contents));
+
+    let conn_mutex = places_api.get_sync_connection()?;
+    let conn = conn_mutex.lock();
+    let operation = conn.begin_operation(InterruptPriority::Background);
+    let result = insert_items(&conn, items, mappings);
+    drop(operation);
+    let pending = observer::take_pending(&conn);
+    drop(conn);
+    // Like the other importers, we use the sync connection, but the
+    // bookmarks are the user's, not ones sync applied.
+    if let Some(pending) = pending {
+        pending.with_source(ConnectionType::ReadWrite).deliver();
+    }
+    result
+}
+
+fn insert_items(
+    db: &PlacesDb,
+    items: Vec<ParsedItem>,
+    mappings: &[BookmarkImportRootMapping],
+) -> Result<HtmlBookmarksImportResult> {
+    let tx = db.begin_transaction()?;
+    let mut destinations = ImportDestinations::new(mappings)?;
+    let mut importer = Importer {
+        db,
+        seen_urls: HashSet::new(),
+        keywords: Vec::new(),
+        result: HtmlBookmarksImportResult::default(),
+    };
+    let mut inserted = false;
+    for (mapping, items) in split_roots(items, destinations.mappings()) {
+        let children = importer.convert_children(items)?;
+        if children.is_empty() {
+            continue;
+        }
+        inserted = true;
+        let mapping = match mapping {
+            Some(mapping) => mapping,
+            None => {
+                let folder_guid = insert_bookmark_in_tx(
+                    db,
+                    InsertableFolder {
+                        parent_guid: BookmarkRootGuid::Unfiled.into(),
+                        position: BookmarkPosition::Append,
+                        date_added: None,
+                        last_modified: None,
+                        guid: None,
+                        title: Some(IMPORTED_FOLDER_TITLE.into()),
+                        children,
+                    }
+                    .into(),
+                )?;
+                record_imported_tree(db, &folder_guid, BookmarkImportSource::Html)?;
+                continue;
+            }
+        };
+        let parent_guid = destinations.folder_for(db, mapping)?;
+        for mut child in children {
+            child.set_parent_guid(parent_guid.clone());
+            let guid = insert_bookmark_in_tx(db, child)?;
+            record_imported_tree(db, &guid, BookmarkImportSource::Html)?;
+        }
+    }
+    if inserted {
+        // Keywords are unique, so a keyword which is already used for
+        // another URL isn't imported.
+        for (url, keyword) in &importer.keywords {
+            db.execute_cached(
+                "INSERT OR IGNORE INTO moz_keywords(keyword, place_id)
+                 SELECT :keyword, id FROM moz_places
+                 WHERE url_hash = hash(:url) AND url = :url",
+                named_params! { ":keyword": keyword, ":url": url.as_str() },
+            )?;
+        }
+        destinations.record_imported(db, BookmarkImportSource::Html)?;
+        delete_pending_temp_tables(db)?;
+    }
+    tx.commit()?;
+    log::info!(
+        "Imported {} bookmarks from HTML, skipped {}",
+        importer.result.num_inserted,
+        importer.result.num_skipped
+    );
+    Ok(importer.result)
+}
+
+/// Splits the top-level items by the root they're from, paired with the
+/// mapping for that root. The items from roots without a mapping stay
+/// together, in the order they're in the file, with the toolbar and "Other
+/// Bookmarks" as folders, like they are without any mappings.
+fn split_roots(
+    items: Vec<ParsedItem>,
+    mappings: &[BookmarkImportRootMapping],
+) -> Vec<(Option<&BookmarkImportRootMapping>, Vec<ParsedItem>)> {
+    let menu_mapping = mapping_for(mappings, BookmarkRootGuid::Menu);
+    let mut menu = Vec::new();
+    let mut unmapped = Vec::new();
+    let mut mapped = Vec::new();
+    for item in items {
+        match item {
+            ParsedItem::Folder {
+                root: Some(root),
+                children,
+                ..
+            } if mapping_for(mappings, root).is_some() => {
+                mapped.push((mapping_for(mappings, root), children))
+            }
+            ParsedItem::Folder { root: Some(_), .. } => unmapped.push(item),
+            _ if menu_mapping.is_some() => menu.push(item),
+            _ => unmapped.push(item),
+        }
+    }
+    let mut groups = vec![(None, unmapped), (menu_mapping, menu)];
+    groups.extend(mapped);
+    groups
+}
+
+fn mapping_for(
+    mappings: &[BookmarkImportRootMapping],
+    root: BookmarkRootGuid,
+) -> Option<&BookmarkImportRootMapping> {
+    mappings.iter().find(|mapping| mapping.source_root == root)
+}
+
+struct Importer<'a> {
+    db: &'a PlacesDb,
+    seen_urls: HashSet<Url>,
+    keywords: Vec<(Url, String)>,
+    result: HtmlBookmarksImportResult,
+}
+
+impl Importer<'_> {
+    fn convert_children(&mut self, items: Vec<ParsedItem>) -> Result<Vec<InsertableItem>> {
+        let mut children = Vec::with_capacity(items.len());
+        for item in items {
+            if let Some(child) = self.convert(item)? {
+                children.push(child);
+            }
+        }
+        // Separators alone don't make a folder worth keeping.
+        if children
+            .iter()
+            .all(|child| matches!(child, InsertableItem::Separator { .. }))
+        {
+            children.clear();
+        }
+        Ok(children)
+    }
+
+    fn convert(&mut self, item: ParsedItem) -> Result<Option<InsertableItem>> {
+        Ok(match item {
+            ParsedItem::Bookmark {
+                url,
+                title,
+                date_added,
+                last_modified,
+                keyword,
+            } => {
+                let url = match Url::parse(&url) {
+                    Ok(url) if url.as_str().len() <= URL_LENGTH_MAX => url,
+                    _ => {
+                        self.result.num_skipped += 1;
+                        return Ok(None);
+                    }
+                };
+                if !self.seen_urls.insert(url.clone()) || self.is_bookmarked(&url)? {
+                    self.result.num_skipped += 1;
+                    return Ok(None);
+                }
+                self.result.num_inserted += 1;
+                if let Some(keyword) = keyword {
+                    self.keywords.push((url.clone(), keyword));
+                }
+                Some(
+                    InsertableBookmark {
+                        parent_guid: SyncGuid::empty(),
+                        position: BookmarkPosition::Append,
+                        date_added,
+                        last_modified,
+                        guid: None,
+                        url,
+                        title: Some(title),
+                    }
+                    .into(),
+                )
+            }
+            ParsedItem::Separator => Some(
+                InsertableSeparator {
+                    parent_guid: SyncGuid::empty(),
+                    position: BookmarkPosition::Append,
+                    date_added: None,
+                    last_modified: None,
+                    guid: None,
+                }
+                .into(),
+            ),
+            ParsedItem::Folder {
+                title,
+                date_added,
+                last_modified,
+                children,
+                ..
+            } => {
+                let children = self.convert_children(children)?;
+                if children.is_empty() {
+                    None
+                } else {
+                    Some(
+                        InsertableFolder {
+                            parent_guid: SyncGuid::empty(),
+                            position: BookmarkPosition::Append,
+                            date_added,
+                            last_modified,
+                            guid: None,
+                            title: Some(title),
+                            children,
+                        }
+                        .into(),
+                    )
+                }
+            }
+        })
+    }
+
+    fn is_bookmarked(&self, url: &Url) -> Result<bool> {
+        Ok(self
+            .db
+            .try_query_row(
+                "SELECT 1 FROM moz_bookmarks b
+                 JOIN moz_places h ON h.id = b.fk
+                 WHERE h.url_hash = hash(:url) AND h.url = :url",
+                named_params! { ":url": url.as_str() },
+                |row| -> rusqlite::Result<_> { row.get::<_, i64>(0) },
+                true,
+            )?
+            .is_some())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ParsedItem {
+    Bookmark {
+        url: String,
+        title: String,
+        date_added: Option<Timestamp>,
+        last_modified: Option<Timestamp>,
+        keyword: Option<String>,
+    },
+    Separator,
+    Folder {
+        title: String,
+        date_added: Option<Timestamp>,
+        last_modified: Option<Timestamp>,
+        /// The root this folder stands for, if it's marked as the toolbar or
+        /// "Other Bookmarks".
+        root: Option<BookmarkRootGuid>,
+        children: Vec<ParsedItem>,
+    },
+}
+
+struct FolderHeader {
+    title: String,
+    date_added: Option<Timestamp>,
+    last_modified: Option<Timestamp>,
+    root: Option<BookmarkRootGuid>,
+}
+
+/// Parses the bookmarks out of a bookmarks file. The format is loose, and
+/// files written by different browsers vary, so we don't try to validate
+/// it: we only look at the `<H3>`, `<A>`, `<HR>` and `<DL>` tags, and
+/// ignore everything else. A folder is an `<H3>`, followed by a `<DL>` with
+/// its children. The children of the `<DL>`s which don't belong to a folder,
+/// like the one around the whole file, go into the enclosing folder.
+fn parse(html: &str) -> Vec<ParsedItem> {
+    let mut tokenizer = Tokenizer { html, pos: 0 };
+    // The enclosing `<DL>`s, innermost last. The first entry collects the
+    // top-level items.
+    let mut stack: Vec<(Option<FolderHeader>, Vec<ParsedItem>)> = vec![(None, Vec::new())];
+    // A folder whose `<DL>` we haven't seen yet.
+    let mut pending_folder: Option<FolderHeader> = None;
+    while let Some(tag) = tokenizer.next_tag() {
+        match tag.name.as_str() {
+            "H3" => {
+                flush_empty_folder(&mut stack, pending_folder.take());
+                pending_folder = Some(FolderHeader {
+                    title: tokenizer.text_until("H3"),
+                    date_added: tag.timestamp("ADD_DATE"),
+                    last_modified: tag.timestamp("LAST_MODIFIED"),
+                    root: tag.root(),
+                });
+            }
+            "DL" => stack.push((pending_folder.take(), Vec::new())),
+            "/DL" => {
+                flush_empty_folder(&mut stack, pending_folder.take());
+                if stack.len() > 1 {
+                    let (header, children) = stack.pop().unwrap();
+                    close_level(&mut stack, header, children);
+                }
+            }
+            "A" => {
+                flush_empty_folder(&mut stack, pending_folder.take());
+                let title = tokenizer.text_until("A");
+                if let Some(url) = tag.attribute("HREF") {
+                    current_items(&mut stack).push(ParsedItem::Bookmark {
+                        url,
+                        title,
+                        date_added: tag.timestamp("ADD_DATE"),
+                        last_modified: tag.timestamp("LAST_MODIFIED"),
+                        keyword: tag.attribute("SHORTCUTURL").filter(|k| !k.is_empty()),
+                    });
+                }
+            }
+            "HR" => {
+                flush_empty_folder(&mut stack, pending_folder.take());
+                current_items(&mut stack).push(ParsedItem::Separator);
+            }
+            _ => {}
+        }
+    }
+    // Close anything left open by a truncated file.
+    flush_empty_folder(&mut stack, pending_folder.take());
+    while stack.len() > 1 {
+        let (header, children) = stack.pop().unwrap();
+        close_level(&mut stack, header, children);
+    }
+    stack.pop().map(|(_, items)| items).unwrap_or_default()
+}
+
+fn current_items(stack: &mut [(Option<FolderHeader>, Vec<ParsedItem>)]) -> &mut Vec<ParsedItem> {
+    // The stack always has the top level.
+    &mut stack.last_mut().unwrap().1
+}
+
+fn close_level(
+    stack: &mut [(Option<FolderHeader>, Vec<ParsedItem>)],
+    header: Option<FolderHeader>,
+    children: Vec<ParsedItem>,
+) {
+    let items = current_items(stack);
+    match header {
+        Some(header) => items.push(ParsedItem::Folder {
+            title: header.title,
+            date_added: header.date_added,
+            last_modified: header.last_modified,
+            root: header.root,
+            children,
+        }),
+        None => items.extend(children),
+    }
+}
+
+fn flush_empty_folder(
+    stack: &mut [(Option<FolderHeader>, Vec<ParsedItem>)],
+    header: Option<FolderHeader>,
+) {
+    if header.is_some() {
+        close_level(stack, header, Vec::new());
+    }
+}
+
+struct Tag {
+    /// Upper-cased, with a leading `/` for closing tags.
+    name: String,
+    /// Upper-cased names, and unescaped values.
+    attributes: Vec<(String, String)>,
+}
+
+impl Tag {
+    fn attribute(&self, name: &str) -> Option<String> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.clone())
+    }
+
+    // Only folders at the top level of the file are really roots, but
+    // browsers don't write the attributes anywhere else.
+    fn root(&self) -> Option<BookmarkRootGuid> {
+        if self.attribute("PERSONAL_TOOLBAR_FOLDER").is_some() {
+            Some(BookmarkRootGuid::Toolbar)
+        } else if self.attribute("UNFILED_BOOKMARKS_FOLDER").is_some() {
+            Some(BookmarkRootGuid::Unfiled)
+        } else {
+            None
+        }
+    }
+
+    // Dates in the file are in seconds.
+    fn timestamp(&self, name: &str) -> Option<Timestamp> {
+        let seconds = self.attribute(name)?.trim().parse::<i64>().ok()?;
+        if seconds <= 0 {
+            return None;
+        }
+        Some(sanitize_timestamp(seconds.saturating_mul(1000)))
+    }
+}
+
+struct Tokenizer<'a> {
+    html: &'a str,
+    pos: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn rest(&self) -> &'a str {
+        &self.html[self.pos..]
+    }
+
+    /// Returns the next tag, skipping text, comments and doctypes.
+    fn next_tag(&mut self) -> Option<Tag> {
+        loop {
+            let start = self.pos + self.rest().find('<')?;
+            self.pos = start + 1;
+            if self.rest().starts_with("!--") {
+                self.pos = match self.rest().find("-->") {
+                    Some(end) => self.pos + end + 3,
+                    None => self.html.len(),
+                };
+                continue;
+            }
+            let name_len = self
+                .rest()
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '/'))
+                .unwrap_or(self.rest().len());
+            if name_len == 0 {
+                continue;
+            }
+            let name = self.rest()[..name_len].to_ascii_uppercase();
+            self.pos += name_len;
+            let attributes = self.attributes();
+            return Some(Tag { name, attributes });
+        }
+    }
+
+    /// Reads the attributes up to the end of the current tag.
+    fn attributes(&mut self) -> Vec<(String, String)> {
+        let mut attributes = Vec::new();
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            let mut chars = trimmed.chars();
+            match chars.next() {
+                None => return attributes,
+                Some('>') => {
+                    self.pos += 1;
+                    return attributes;
+                }
+                Some(_) => {}
+            }
+            let name_len = trimmed
+                .find(|c: char| c.is_whitespace() || c == '=' || c == '>')
+                .unwrap_or(trimmed.len());
+            // A stray `/` or quote - skip it, so we always make progress.
+            let name_len = name_len.max(1);
+            let name = trimmed[..name_len].to_ascii_uppercase();
+            self.pos += name_len;
+            let rest = self.rest();
+            let after_name = rest.trim_start();
+            if !after_name.starts_with('=') {
+                attributes.push((name, String::new()));
+                continue;
+            }
+            let value_start = after_name[1..].trim_start();
+            self.pos += rest.len() - value_start.len();
+            let value = match value_start.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let value_len = value_start[1..]
+                        .find(quote)
+                        .unwrap_or(value_start.len() - 1);
+                    self.pos += value_len + 2;
+                    &value_start[1..1 + value_len]
+                }
+                _ => {
+                    let value_len = value_start
+                        .find(|c: char| c.is_whitespace() || c == '>')
+                        .unwrap_or(value_start.len());
+                    self.pos += value_len;
+                    &value_start[..value_len]
+                }
+            };
+            self.pos = self.pos.min(self.html.len());
+            attributes.push((name, unescape(value)));
+        }
+    }
+
+    /// Reads the text up to the closing tag for `name`, and skips past it.
+    /// Any other tags in between are dropped.
+    fn text_until(&mut self, name: &str) -> String {
+        let closing = format!("</{}", name);
+        let rest = self.rest();
+        // Tag names are ASCII, so we can compare bytes without upper-casing
+        // the rest of the file for every element. A match starts with `<`,
+        // so it's always on a character boundary.
+        let end = rest
+            .as_bytes()
+            .windows(closing.len())
+            .position(|window| window.eq_ignore_ascii_case(closing.as_bytes()))
+            .unwrap_or(rest.len());
+        let raw = &rest[..end];
+        self.pos += end;
+        if end < rest.len() {
+            self.pos += closing.len();
+            self.attributes();
+        }
+        let mut text = String::with_capacity(raw.len());
+        let mut in_tag = false;
+        for c in raw.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' if in_tag => in_tag = false,
+                c if !in_tag => text.push(c),
+                _ => {}
+            }
+        }
+        unescape(text.trim())
+    }
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        unescaped.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').and_then(|semi| {
+            let c = match &rest[1..semi] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => '\u{a0}',
+                entity => {
+                    let code = match entity.strip_prefix('#')? {
+                        hex if hex.starts_with(&['x', 'X'][..]) => {
+                            u32::from_str_radix(&hex[1..], 16).ok()?
+                        }
+                        dec => dec.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, semi + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                unescaped.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_api;
+    use crate::observer::{PlacesChange, PlacesChangeObserver};
+    use crate::storage::bookmarks::fetch::{fetch_tree, Item};
+    use crate::storage::bookmarks::import_source::fetch_imported_bookmarks;
+    use crate::storage::bookmarks::{bookmarks_get_url_for_keyword, insert_bookmark};
+    use std::sync::{Arc, Mutex};
+
+    const FILE: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file.
+     It will be read and overwritten.
+     DO NOT EDIT! -->
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks Menu</H1>
+
+<DL><p>
+    <DT><H3 ADD_DATE="1500000000" LAST_MODIFIED="1500000001">Folder &amp; &lt;friends&gt;</H3>
+    <DL><p>
+        <DT><A HREF="https://example.com/?a=1&amp;b=2" ADD_DATE="1500000000" LAST_MODIFIED="1500000000" SHORTCUTURL="ex">Example</A>
+        <HR>
+        <DT><A HREF="https://example.com/?a=1&amp;b=2">Duplicate</A>
+    </DL><p>
+    <DT><H3>Only duplicates</H3>
+    <DL><p>
+        <DT><A HREF="https://mozilla.org/">Mozilla</A>
+        <HR>
+    </DL><p>
+    <dt><a href='https://example.org/' add_date=1500000000>Lower &#x26; &#39;case&#39;</a>
+    <DT><A HREF="not a url">Invalid</A>
+</DL>
+"#;
+
+    #[test]
+    fn test_parse() {
+        let items = parse(FILE);
+        assert_eq!(items.len(), 4);
+        assert_eq!(
+            items[0],
+            ParsedItem::Folder {
+                title: "Folder & <friends>".into(),
+                date_added: Some(Timestamp(1_500_000_000_000)),
+                last_modified: Some(Timestamp(1_500_000_001_000)),
+                root: None,
+                children: vec![
+                    ParsedItem::Bookmark {
+                        url: "https://example.com/?a=1&b=2".into(),
+                        title: "Example".into(),
+                        date_added: Some(Timestamp(1_500_000_000_000)),
+                        last_modified: Some(Timestamp(1_500_000_000_000)),
+                        keyword: Some("ex".into()),
+                    },
+                    ParsedItem::Separator,
+                    ParsedItem::Bookmark {
+                        url: "https://example.com/?a=1&b=2".into(),
+                        title: "Duplicate".into(),
+                        date_added: None,
+                        last_modified: None,
+                        keyword: None,
+                    },
+                ],
+            }
+        );
+        assert_eq!(
+            items[2],
+            ParsedItem::Bookmark {
+                url: "https://example.org/".into(),
+                title: "Lower & 'case'".into(),
+                date_added: Some(Timestamp(1_500_000_000_000)),
+                last_modified: None,
+                keyword: None,
+            }
+        );
+
+        // Truncated files keep what we've seen so far.
+        let items = parse(
+            "<DL><DT><H3>Empty</H3><DT><H3>Open</H3><DL><DT><A HREF=\"https://example.com/\">",
+        );
+        assert_eq!(items.len(), 2);
+        assert!(matches!(&items[0], ParsedItem::Folder { children, .. } if children.is_empty()));
+        assert!(matches!(&items[1], ParsedItem::Folder { children, .. } if children.len() == 1));
+    }
+
+    #[derive(Default)]
+    struct Sources(Mutex<Vec<ConnectionType>>);
+
+    impl PlacesChangeObserver for Arc<Sources> {
+        fn on_changes(&self, _changes: Vec<PlacesChange>, source: ConnectionType) {
+            self.0.lock().unwrap().push(source);
+        }
+    }
+
+    #[test]
+    fn test_import() {
+        let api = new_mem_api();
+        let path = std::env::temp_dir().join(format!(
+            "places-html-import-{}.html",
+            SyncGuid::random().as_str()
+        ));
+        std::fs::write(&path, FILE).expect("should write file");
+
+        // Mozilla is already bookmarked.
+        {
+            let conn = api
+                .open_connection(ConnectionType::ReadWrite)
+                .expect("should get writer");
+            insert_bookmark(
+                &conn,
+                InsertableBookmark {
+                    parent_guid: BookmarkRootGuid::Mobile.into(),
+                    position: BookmarkPosition::Append,
+                    date_added: None,
+                    last_modified: None,
+                    guid: None,
+                    url: Url::parse("https://mozilla.org/").unwrap(),
+                    title: None,
+                }
+                .into(),
+            )
+            .expect("should insert");
+        }
+
+        let sources = Arc::new(Sources::default());
+        api.set_change_observer(Box::new(Arc::clone(&sources)));
+        assert_eq!(
+            import_bookmarks(&api, &path).expect("should import"),
+            HtmlBookmarksImportResult {
+                num_inserted: 2,
+                num_skipped: 3,
+            }
+        );
+        // Imported bookmarks are reported as the user's, not sync's.
+        assert_eq!(*sources.0.lock().unwrap(), vec![ConnectionType::ReadWrite]);
+        api.clear_change_observer();
+        // Importing again skips everything, and doesn't add another folder.
+        assert_eq!(
+            import_bookmarks(&api, &path).expect("should import"),
+            HtmlBookmarksImportResult {
+                num_inserted: 0,
+                num_skipped: 5,
+            }
+        );
+        std::fs::remove_file(&path).ok();
+
+        let conn = api
+            .open_connection(ConnectionType::ReadOnly)
+            .expect("should get reader");
+        let unfiled =
+            match fetch_tree(&conn, &BookmarkRootGuid::Unfiled.into()).expect("should fetch") {
+                Some(Item::Folder { f }) => f,
+                _ => panic!("should have unfiled root"),
+            };
+        let children = unfiled.child_nodes.expect("should have children");
+        assert_eq!(children.len(), 1);
+        let imported = match &children[0] {
+            Item::Folder { f } => f,
+            _ => panic!("should be a folder"),
+        };
+        assert_eq!(imported.title.as_deref(), Some(IMPORTED_FOLDER_TITLE));
+        let imported_children = imported.child_nodes.as_ref().expect("should have children");
+        assert_eq!(imported_children.len(), 2);
+        match &imported_children[0] {
+            Item::Folder { f } => {
+                assert_eq!(f.title.as_deref(), Some("Folder & <friends>"));
+                assert_eq!(f.date_added, Timestamp(1_500_000_000_000));
+                // The trailing separator is kept, since the folder has a
+                // bookmark.
+                assert_eq!(f.child_nodes.as_ref().map(Vec::len), Some(2));
+            }
+            _ => panic!("should be a folder"),
+        }
+        assert_eq!(
+            bookmarks_get_url_for_keyword(&conn, "ex").expect("should query"),
+            Some(Url::parse("https://example.com/?a=1&b=2").unwrap())
+        );
+        // The folder, the subfolder, its bookmark and separator, and the
+        // other bookmark.
+        assert_eq!(
+            fetch_imported_bookmarks(&conn, BookmarkImportSource::Html)
+                .expect("should fetch")
+                .len(),
+            5
+        );
+    }
+
+    fn children_of(conn: &PlacesDb, guid: &SyncGuid) -> Vec<Item> {
+        match fetch_tree(conn, guid).expect("should fetch") {
+            Some(Item::Folder { f }) => f.child_nodes.unwrap_or_default(),
+            _ => panic!("should be a folder"),
+        }
+    }
+
+    fn titles(items: &[Item]) -> Vec<&str> {
+        items
+            .iter()
+            .map(|item| match item {
+                Item::Bookmark { b } => b.title.as_deref().unwrap_or(""),
+                Item::Folder { f } => f.title.as_deref().unwrap_or(""),
+                Item::Separator { .. } => "-",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_import_with_mappings() {
+        let file = r#"<DL><p>
+    <DT><A HREF="https://example.com/menu">Menu bookmark</A>
+    <DT><H3 PERSONAL_TOOLBAR_FOLDER="true">Bookmarks bar</H3>
+    <DL><p>
+        <DT><A HREF="https://example.com/toolbar">Toolbar bookmark</A>
+        <DT><H3>Toolbar folder</H3>
+        <DL><p>
+            <DT><A HREF="https://example.com/nested">Nested</A>
+        </DL><p>
+    </DL><p>
+    <DT><H3 UNFILED_BOOKMARKS_FOLDER="true">Other bookmarks</H3>
+    <DL><p>
+        <DT><A HREF="https://example.com/other">Other bookmark</A>
+    </DL><p>
+</DL>
+"#;
+        let items = parse(file);
+        assert!(matches!(
+            &items[1],
+            ParsedItem::Folder {
+                root: Some(BookmarkRootGuid::Toolbar),
+                ..
+            }
+        ));
+        assert!(matches!(
+            &items[2],
+            ParsedItem::Folder {
+                root: Some(BookmarkRootGuid::Unfiled),
+                ..
+            }
+        ));
+
+        let api = new_mem_api();
+        let conn_mutex = api.get_sync_connection().expect("should get connection");
+        let conn = conn_mutex.lock();
+        let mappings = vec![
+            BookmarkImportRootMapping {
+                source_root: BookmarkRootGuid::Menu.into(),
+                parent_guid: BookmarkRootGuid::Menu.into(),
+                folder_title: None,
+            },
+            BookmarkImportRootMapping {
+                source_root: BookmarkRootGuid::Toolbar.into(),
+                parent_guid: BookmarkRootGuid::Toolbar.into(),
+                folder_title: Some("Imported from Chrome".into()),
+            },
+        ];
+        assert_eq!(
+            insert_items(&conn, items, &mappings).expect("should import"),
+            HtmlBookmarksImportResult {
+                num_inserted: 4,
+                num_skipped: 0,
+            }
+        );
+
+        assert_eq!(
+            titles(&children_of(&conn, &BookmarkRootGuid::Menu.into())),
+            vec!["Menu bookmark"]
+        );
+        let toolbar = children_of(&conn, &BookmarkRootGuid::Toolbar.into());
+        assert_eq!(titles(&toolbar), vec!["Imported from Chrome"]);
+        assert_eq!(
+            titles(&children_of(&conn, toolbar[0].guid())),
+            vec!["Toolbar bookmark", "Toolbar folder"]
+        );
+        // The unmapped root keeps its folder, in the "Imported" folder.
+        let unfiled = children_of(&conn, &BookmarkRootGuid::Unfiled.into());
+        assert_eq!(titles(&unfiled), vec![IMPORTED_FOLDER_TITLE]);
+        assert_eq!(
+            titles(&children_of(&conn, unfiled[0].guid())),
+            vec!["Other bookmarks"]
+        );
+        // Everything we added was recorded, including the new folders, but
+        // not the roots.
+        assert_eq!(
+            fetch_imported_bookmarks(&conn, BookmarkImportSource::Html)
+                .expect("should fetch")
+                .len(),
+            8
+        );
+
+        // Mapping the root of the tree isn't allowed.
+        assert!(insert_items(
+            &conn,
+            Vec::new(),
+            &[BookmarkImportRootMapping {
+                source_root: BookmarkRootGuid::Root.into(),
+                parent_guid: BookmarkRootGuid::Menu.into(),
+                folder_title: None,
+            }],
+        )
+        .is_err());
+    }
+}
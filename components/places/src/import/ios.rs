@@ -5,4 +5,5 @@
 pub mod bookmarks;
 pub mod history;
 pub use bookmarks::import as import_bookmarks;
+pub use bookmarks::import_with_mappings as import_bookmarks_with_mappings;
 pub use history::import as import_history;
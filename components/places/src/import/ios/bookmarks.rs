@@ -9,7 +9,12 @@ use crate::bookmark_sync::{
 };
 use crate::error::*;
 use crate::import::common::{attached_database, ExecuteOnDrop};
+use crate::storage::bookmarks::import_source::{
+    move_mapped_roots_from_mirror, record_imported_from_mirror, BookmarkImportRootMapping,
+    BookmarkImportSource,
+};
 use crate::types::SyncStatus;
+use interrupt_support::InterruptPriority;
 use rusqlite::named_params;
 use sql_support::ConnExt;
 use std::collections::HashMap;
@@ -76,13 +81,28 @@ use url::Url;
 /// - Update frecency for new items.
 /// - Cleanup (Delete mirror and mirror structure, detach iOS database, etc).
 pub fn import(places_api: &PlacesApi, path: impl AsRef<std::path::Path>) -> Result<()> {
+    import_with_mappings(places_api, path, &[])
+}
+
+/// Like `import`, but puts the children of the roots in `mappings` where
+/// they say, instead of in the matching local roots.
+pub fn import_with_mappings(
+    places_api: &PlacesApi,
+    path: impl AsRef<std::path::Path>,
+    mappings: &[BookmarkImportRootMapping],
+) -> Result<()> {
     let url = crate::util::ensure_url_path(path)?;
-    do_import(places_api, url)
+    do_import(places_api, url, mappings)
 }
 
-fn do_import(places_api: &PlacesApi, ios_db_file_url: Url) -> Result<()> {
+fn do_import(
+    places_api: &PlacesApi,
+    ios_db_file_url: Url,
+    mappings: &[BookmarkImportRootMapping],
+) -> Result<()> {
     let conn_mutex = places_api.get_sync_connection()?;
     let conn = conn_mutex.lock();
+    let _operation = conn.begin_operation(InterruptPriority::Background);
 
     let scope = conn.begin_interrupt_scope()?;
 
@@ -94,7 +114,7 @@ fn do_import(places_api: &PlacesApi, ios_db_file_url: Url) -> Result<()> {
     // ios_db_file_url.query_pairs_mut().append_pair("mode", "ro");
 
     log::trace!("Attaching database {}", ios_db_file_url);
-    let auto_detach = attached_database(&conn, &ios_db_file_url, "ios")?;
+    let auto_detach = attached_database(&conn, &ios_db_file_url, "ios", None)?;
 
     let tx = conn.begin_transaction()?;
 
@@ -149,6 +169,14 @@ fn do_import(places_api: &PlacesApi, ios_db_file_url: Url) -> Result<()> {
     log::debug!("Fixing up bookmarks");
     conn.execute_batch(&FIXUP_MOZ_BOOKMARKS)?;
     scope.err_if_interrupted()?;
+    log::debug!("Recording the import source");
+    record_imported_from_mirror(&conn, BookmarkImportSource::Ios)?;
+    scope.err_if_interrupted()?;
+    if !mappings.is_empty() {
+        log::debug!("Moving mapped roots");
+        move_mapped_roots_from_mirror(&conn, mappings, BookmarkImportSource::Ios)?;
+        scope.err_if_interrupted()?;
+    }
     log::debug!("Cleaning up mirror...");
     clear_mirror_on_drop.execute_now()?;
     log::debug!("Committing...");
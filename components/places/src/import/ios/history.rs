@@ -56,7 +56,7 @@ fn do_import(
     // ios_db_file_url.query_pairs_mut().append_pair("mode", "ro");
     let import_start = Instant::now();
     log::info!("Attaching database {}", ios_db_file_url);
-    let auto_detach = attached_database(conn, &ios_db_file_url, "ios")?;
+    let auto_detach = attached_database(conn, &ios_db_file_url, "ios", None)?;
     let tx = conn.begin_transaction()?;
     let num_total = select_count(conn, &COUNT_IOS_HISTORY_VISITS)?;
     log::info!("The number of visits is: {:?}", num_total);
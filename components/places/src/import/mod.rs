@@ -2,11 +2,24 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+pub mod chrome;
+pub use chrome::import_history as import_chrome_history;
 pub mod common;
+pub mod desktop;
+pub use desktop::import_bookmarks as import_desktop_bookmarks;
+pub use desktop::import_bookmarks_with_mappings as import_desktop_bookmarks_with_mappings;
+pub use desktop::import_history as import_desktop_history;
 pub mod fennec;
+pub mod html;
 pub use fennec::import_bookmarks as import_fennec_bookmarks;
+pub use fennec::import_bookmarks_with_mappings as import_fennec_bookmarks_with_mappings;
 pub use fennec::import_history as import_fennec_history;
 pub use fennec::import_pinned_sites as import_fennec_pinned_sites;
+pub use html::import_bookmarks as import_html_bookmarks;
+pub use html::import_bookmarks_with_mappings as import_html_bookmarks_with_mappings;
 pub mod ios;
 pub use ios::import_bookmarks as import_ios_bookmarks;
+pub use ios::import_bookmarks_with_mappings as import_ios_bookmarks_with_mappings;
 pub use ios::import_history as import_ios_history;
+pub mod visits;
+pub use visits::import_visits;
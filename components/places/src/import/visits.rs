@@ -0,0 +1,248 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::api::places_api::PlacesApi;
+use crate::bookmark_sync::engine::update_frecencies;
+use crate::error::*;
+use crate::import::common::{define_history_migration_functions, HistoryMigrationResult};
+use crate::storage::{can_record_history, TITLE_LENGTH_MAX};
+use crate::types::VisitTransition;
+use crate::util::slice_up_to;
+use interrupt_support::InterruptPriority;
+use rusqlite::named_params;
+use std::time::Instant;
+use types::Timestamp;
+use url::Url;
+
+/// A visit from a browser we don't have an importer for, which the app has
+/// already read and converted to our types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedVisit {
+    pub url: Url,
+    pub title: Option<String>,
+    pub visit_date: Timestamp,
+    pub visit_type: VisitTransition,
+}
+
+/// Imports `visits`, all or nothing, as visits made on this device. Visits to
+/// URLs we wouldn't record in history, and visits we already have (to the
+/// same URL at the same time), are skipped and counted as failed. Pages we
+/// don't have yet take the title of their latest visit that has one.
+///
+/// Like the other history importers, this goes through a staging table, and
+/// doesn't tell observers about each visit.
+pub fn import_visits(
+    places_api: &PlacesApi,
+    visits: Vec<ImportedVisit>,
+) -> Result<HistoryMigrationResult> {
+    let conn_mutex = places_api.get_sync_connection()?;
+    let conn = conn_mutex.lock();
+    let _operation = conn.begin_operation(InterruptPriority::Background);
+
+    let scope = conn.begin_interrupt_scope()?;
+    define_history_migration_functions(&conn)?;
+
+    let import_start = Instant::now();
+    let num_total = visits.len() as u32;
+
+    let tx = conn.begin_transaction()?;
+
+    log::debug!("Creating and populating staging table");
+    conn.execute_batch(CREATE_STAGING_TABLE)?;
+    {
+        let mut stmt = conn.prepare(FILL_STAGING)?;
+        for visit in visits {
+            if !can_record_history(&visit.url) {
+                continue;
+            }
+            stmt.execute(named_params! {
+                ":url": visit.url.as_str(),
+                ":title": visit.title.as_deref().map(|title| slice_up_to(title, TITLE_LENGTH_MAX)),
+                ":visit_date": visit.visit_date,
+                ":visit_type": visit.visit_type as u8,
+            })?;
+            scope.err_if_interrupted()?;
+        }
+    }
+
+    log::debug!("Populating missing entries in moz_places");
+    conn.execute_batch(FILL_MOZ_PLACES)?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Inserting the history visits");
+    let num_succeeded = conn.execute(INSERT_HISTORY_VISITS, [])? as u32;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Marking visited pages as changed");
+    conn.execute_batch(BUMP_CHANGE_COUNTERS)?;
+    conn.execute(
+        ADD_TO_STALE_FRECENCIES,
+        named_params! { ":now": Timestamp::now() },
+    )?;
+    conn.execute_batch(DROP_STAGING_TABLE)?;
+    scope.err_if_interrupted()?;
+
+    log::debug!("Committing...");
+    tx.commit()?;
+
+    // Like the other importers, we update frecencies outside the import's
+    // transaction: if we're interrupted, maintenance will catch up.
+    log::debug!("Updating frecencies");
+    update_frecencies(&conn, &scope)?;
+
+    Ok(HistoryMigrationResult {
+        num_total,
+        num_succeeded,
+        num_failed: num_total.saturating_sub(num_succeeded),
+        total_duration: import_start.elapsed().as_millis() as u64,
+    })
+}
+
+const CREATE_STAGING_TABLE: &str = "
+    CREATE TEMP TABLE temp.importedVisitsStaging(
+        url TEXT NOT NULL,
+        url_hash INTEGER NOT NULL,
+        title TEXT,
+        visit_date INTEGER NOT NULL,
+        visit_type INTEGER NOT NULL
+    );
+    CREATE INDEX temp.importedVisitsStagingUrl ON importedVisitsStaging(url_hash, url);";
+
+const FILL_STAGING: &str = "
+    INSERT INTO temp.importedVisitsStaging(url, url_hash, title, visit_date, visit_type)
+    VALUES(:url, hash(:url), NULLIF(:title, ''), :visit_date, :visit_type)";
+
+// Inserting an existing page's GUID is ignored, so this only adds the pages
+// we don't have.
+const FILL_MOZ_PLACES: &str = "
+    INSERT OR IGNORE INTO main.moz_places(guid, url, url_hash, title, frecency)
+        SELECT
+            IFNULL(
+                (SELECT p.guid FROM main.moz_places p WHERE p.url_hash = t.url_hash AND p.url = t.url),
+                generate_guid()
+            ),
+            t.url,
+            t.url_hash,
+            (SELECT s.title FROM temp.importedVisitsStaging s
+             WHERE s.url_hash = t.url_hash AND s.url = t.url AND s.title NOT NULL
+             ORDER BY s.visit_date DESC
+             LIMIT 1),
+            -1
+        FROM temp.importedVisitsStaging t
+        GROUP BY t.url";
+
+// A visit we already have, or one that's in the batch twice, is only
+// inserted once.
+const INSERT_HISTORY_VISITS: &str = "
+    INSERT INTO main.moz_historyvisits(from_visit, place_id, visit_date, visit_type, is_local)
+        SELECT NULL, p.id, t.visit_date, MIN(t.visit_type), 1
+        FROM temp.importedVisitsStaging t
+        JOIN main.moz_places p ON p.url_hash = t.url_hash AND p.url = t.url
+        WHERE NOT EXISTS(SELECT 1 FROM main.moz_historyvisits v
+                         WHERE v.place_id = p.id AND v.visit_date = t.visit_date)
+        GROUP BY p.id, t.visit_date";
+
+// Adding visits to a page changes it for Sync.
+const BUMP_CHANGE_COUNTERS: &str = "
+    UPDATE main.moz_places SET sync_change_counter = sync_change_counter + 1
+    WHERE id IN (SELECT p.id FROM main.moz_places p
+                 JOIN temp.importedVisitsStaging t
+                   ON p.url_hash = t.url_hash AND p.url = t.url)";
+
+const ADD_TO_STALE_FRECENCIES: &str = "
+    INSERT OR REPLACE INTO main.moz_places_stale_frecencies(place_id, stale_at)
+        SELECT DISTINCT p.id, :now
+        FROM main.moz_places p
+        JOIN temp.importedVisitsStaging t ON p.url_hash = t.url_hash AND p.url = t.url";
+
+const DROP_STAGING_TABLE: &str = "DROP TABLE temp.importedVisitsStaging";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_api;
+    use crate::api::places_api::ConnectionType;
+    use crate::storage::fetch_page_info;
+    use crate::storage::history::get_visit_infos;
+    use crate::types::VisitTransitionSet;
+
+    fn visit(
+        url: &str,
+        title: Option<&str>,
+        at: u64,
+        visit_type: VisitTransition,
+    ) -> ImportedVisit {
+        ImportedVisit {
+            url: Url::parse(url).unwrap(),
+            title: title.map(String::from),
+            visit_date: Timestamp(at),
+            visit_type,
+        }
+    }
+
+    #[test]
+    fn test_import_visits() {
+        let api = new_mem_api();
+        let visits = vec![
+            visit(
+                "https://example.com/",
+                Some("Old"),
+                1000,
+                VisitTransition::Typed,
+            ),
+            visit(
+                "https://example.com/",
+                Some("New"),
+                3000,
+                VisitTransition::Link,
+            ),
+            visit("https://example.com/", None, 4000, VisitTransition::Link),
+            // In the batch twice.
+            visit("https://mozilla.org/", None, 2000, VisitTransition::Link),
+            visit("https://mozilla.org/", None, 2000, VisitTransition::Link),
+            // We don't record these in history.
+            visit("about:blank", None, 2000, VisitTransition::Link),
+        ];
+        let result = import_visits(&api, visits.clone()).expect("should import");
+        assert_eq!(result.num_total, 6);
+        assert_eq!(result.num_succeeded, 4);
+        assert_eq!(result.num_failed, 2);
+
+        let conn = api
+            .open_connection(ConnectionType::ReadOnly)
+            .expect("should get reader");
+        let infos = get_visit_infos(
+            &conn,
+            Timestamp(0),
+            Timestamp(5000),
+            VisitTransitionSet::empty(),
+            None,
+        )
+        .expect("should get visits");
+        assert_eq!(
+            infos
+                .iter()
+                .map(|info| (info.url.as_str(), info.timestamp.0, info.visit_type))
+                .collect::<Vec<_>>(),
+            vec![
+                ("https://example.com/", 1000, VisitTransition::Typed),
+                ("https://mozilla.org/", 2000, VisitTransition::Link),
+                ("https://example.com/", 3000, VisitTransition::Link),
+                ("https://example.com/", 4000, VisitTransition::Link),
+            ]
+        );
+        let page = fetch_page_info(&conn, &Url::parse("https://example.com/").unwrap())
+            .expect("should fetch")
+            .expect("should exist")
+            .page;
+        assert_eq!(page.title, "New");
+        assert_eq!(page.visit_count_local, 3);
+        assert!(page.frecency > 0);
+
+        // Importing the same visits again doesn't add any.
+        let result = import_visits(&api, visits).expect("should import again");
+        assert_eq!(result.num_succeeded, 0);
+        assert_eq!(result.num_failed, 6);
+    }
+}
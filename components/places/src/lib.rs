@@ -11,15 +11,21 @@ pub mod types;
 // Making these all pub for now while we flesh out the API.
 pub mod bookmark_sync;
 pub mod db;
+pub mod export;
 pub mod ffi;
 pub mod frecency;
 pub mod hash;
+pub mod history_metadata_sync;
 pub mod history_sync;
 // match_impl is pub mostly for benchmarks (which have to run as a separate pseudo-crate).
 pub mod import;
 pub mod match_impl;
 pub mod observation;
+pub mod observer;
+mod spans;
 pub mod storage;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 #[cfg(test)]
 mod tests;
 mod util;
@@ -27,7 +33,9 @@ mod util;
 pub use crate::api::apply_observation;
 #[cfg(test)]
 pub use crate::api::places_api::test;
-pub use crate::api::places_api::{get_registered_sync_engine, ConnectionType, PlacesApi};
+pub use crate::api::places_api::{
+    create_sync_engine, get_registered_sync_engine, ConnectionType, PlacesApi,
+};
 
 pub use crate::db::PlacesDb;
 pub use crate::error::*;
@@ -31,6 +31,9 @@ pub enum MatchBehavior {
     /// Match only the beginning of each search term using a case sensitive
     /// comparator
     BeginningCaseSensitive = 5,
+    /// Match each search term as a whole word, starting and ending on a word
+    /// boundary.
+    WholeWord = 6,
 }
 
 impl FromSql for MatchBehavior {
@@ -43,6 +46,7 @@ impl FromSql for MatchBehavior {
             3 => MatchBehavior::Beginning,
             4 => MatchBehavior::AnywhereUnmodified,
             5 => MatchBehavior::BeginningCaseSensitive,
+            6 => MatchBehavior::WholeWord,
             _ => return Err(FromSqlError::InvalidType),
         })
     }
@@ -326,6 +330,25 @@ fn find_beginning_case_sensitive(token: &str, source: &str) -> bool {
     source.starts_with(token)
 }
 
+// Unlike the other search functions, this isn't a port from desktop, so it
+// doesn't bother with the fast paths.
+fn find_whole_word(token: &str, source: &str) -> bool {
+    assert!(!token.is_empty(), "Don't search for an empty token");
+    let token = token.to_lowercase();
+    let source = source.to_lowercase();
+    source.match_indices(&token).any(|(start, _)| {
+        let end = start + token.len();
+        !source[..start]
+            .chars()
+            .next_back()
+            .map_or(false, char::is_alphanumeric)
+            && !source[end..]
+                .chars()
+                .next()
+                .map_or(false, char::is_alphanumeric)
+    })
+}
+
 // I can't wait for Rust 2018 when lifetime annotations are automatic.
 pub struct AutocompleteMatch<'search, 'url, 'title, 'tags> {
     pub search_str: &'search str,
@@ -346,6 +369,7 @@ impl<'search, 'url, 'title, 'tags> AutocompleteMatch<'search, 'url, 'title, 'tag
             MatchBehavior::Anywhere | MatchBehavior::AnywhereUnmodified => find_anywhere,
             MatchBehavior::Beginning => find_beginning,
             MatchBehavior::BeginningCaseSensitive => find_beginning_case_sensitive,
+            MatchBehavior::WholeWord => find_whole_word,
             _ => find_on_boundary,
         }
     }
@@ -439,6 +463,17 @@ impl<'search, 'url, 'title, 'tags> AutocompleteMatch<'search, 'url, 'title, 'tag
 mod test {
     use super::*;
 
+    #[test]
+    fn test_find_whole_word() {
+        assert!(find_whole_word("moz", "Moz wiki"));
+        assert!(find_whole_word("wiki", "moz-wiki.example.com/"));
+        assert!(find_whole_word("example.com", "www.example.com/page"));
+        assert!(!find_whole_word("moz", "Mozilla"));
+        assert!(!find_whole_word("zilla", "Mozilla"));
+        // Later matches are found even when the first isn't a whole word.
+        assert!(find_whole_word("fox", "foxes and a fox"));
+    }
+
     #[test]
     fn test_is_ascii_lower_alpha() {
         // just check exhaustively
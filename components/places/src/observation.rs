@@ -31,6 +31,9 @@ pub struct VisitObservation {
     pub referrer: Option<Url>,
     pub is_remote: Option<bool>,
     pub preview_image_url: Option<Url>,
+    /// The contextual identity the visit was made in, or `None` for the
+    /// default one.
+    pub container_id: Option<String>,
 }
 
 impl VisitObservation {
@@ -46,6 +49,7 @@ impl VisitObservation {
             referrer: None,
             is_remote: None,
             preview_image_url: None,
+            container_id: None,
         }
     }
 
@@ -97,6 +101,11 @@ impl VisitObservation {
         self
     }
 
+    pub fn with_container_id(mut self, v: impl Into<Option<String>>) -> Self {
+        self.container_id = v.into();
+        self
+    }
+
     // Other helpers which can be derived.
     pub fn get_redirect_frecency_boost(&self) -> bool {
         self.is_redirect_source.is_some()
@@ -0,0 +1,307 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Lets apps observe changes to history and bookmarks, instead of polling for
+// them.
+//
+// Storage functions and sync note each change they make on the connection
+// which made it. When the transaction they were made in commits, the changes
+// are queued for delivery, and when it rolls back, they're thrown away. This
+// is done with SQLite's commit and rollback hooks, so it works the same for
+// every type of connection and transaction. Once the API call or sync which
+// made the changes has released the connection, we hand the committed changes
+// to the observer registered on the `PlacesApi`, if there is one, along with
+// the type of the connection.
+//
+// We only note changes when an observer is registered, so apps which don't
+// observe don't pay for it.
+
+use crate::api::places_api::ConnectionType;
+use crate::db::PlacesDb;
+use crate::types::VisitTransition;
+use lazy_static::lazy_static;
+use parking_lot::{Mutex, RwLock};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::sync::Arc;
+use sync_guid::Guid as SyncGuid;
+use types::Timestamp;
+use url::Url;
+
+/// Implemented by the app to hear about changes to history and bookmarks.
+pub trait PlacesChangeObserver: Send + Sync {
+    /// Called with the changes made by a single API call or sync, after
+    /// they've been committed. `source` is the type of the connection which
+    /// made them; changes applied by sync come from a `Sync` connection.
+    ///
+    /// This is called on the thread which made the changes. Don't sync from
+    /// here, since the sync connection is still in use.
+    fn on_changes(&self, changes: Vec<PlacesChange>, source: ConnectionType);
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlacesChange {
+    VisitAdded {
+        url: Url,
+        visit_time: Timestamp,
+        visit_type: VisitTransition,
+    },
+    VisitDeleted {
+        url: Url,
+        visit_time: Timestamp,
+    },
+    VisitsDeletedBetween {
+        start: Timestamp,
+        end: Timestamp,
+    },
+    /// All the visits to a page were deleted. The page itself stays around
+    /// if it's bookmarked.
+    PageDeleted {
+        url: Url,
+    },
+    /// All history was deleted.
+    HistoryCleared,
+    BookmarkInserted {
+        guid: SyncGuid,
+        parent_guid: SyncGuid,
+    },
+    BookmarkUpdated {
+        guid: SyncGuid,
+    },
+    /// A bookmark, folder or separator was deleted. If it was a folder, its
+    /// children were deleted too, but we don't note them separately.
+    BookmarkDeleted {
+        guid: SyncGuid,
+    },
+}
+
+lazy_static! {
+    // Each API has at most one observer, shared across all its connections.
+    // This hashmap indexes them by the "api id" of the API.
+    static ref OBSERVERS: RwLock<HashMap<usize, Arc<dyn PlacesChangeObserver>>> =
+        RwLock::new(HashMap::new());
+}
+
+pub(crate) fn set_observer(api_id: usize, observer: Box<dyn PlacesChangeObserver>) {
+    OBSERVERS.write().insert(api_id, Arc::from(observer));
+}
+
+pub(crate) fn clear_observer(api_id: usize) {
+    OBSERVERS.write().remove(&api_id);
+}
+
+/// The changes noted on a connection which haven't been delivered yet.
+#[derive(Debug, Default)]
+pub(crate) struct ChangeQueue {
+    // Changes made by the transaction which is open now.
+    uncommitted: Vec<PlacesChange>,
+    // Changes which were committed, waiting for `take_pending`.
+    committed: Vec<PlacesChange>,
+}
+
+/// Moves changes to the committed queue when their transaction commits, and
+/// throws them away when it rolls back. Called once, when the connection is
+/// opened.
+pub(crate) fn install_hooks(conn: &Connection, queue: &Arc<Mutex<ChangeQueue>>) {
+    let on_commit = Arc::clone(queue);
+    conn.commit_hook(Some(move || {
+        let mut queue = on_commit.lock();
+        let uncommitted = std::mem::take(&mut queue.uncommitted);
+        queue.committed.extend(uncommitted);
+        // Returning `true` would turn the commit into a rollback.
+        false
+    }));
+    let on_rollback = Arc::clone(queue);
+    conn.rollback_hook(Some(move || {
+        on_rollback.lock().uncommitted.clear();
+    }));
+}
+
+/// Returns `true` if someone is observing the changes made by `db`, so that
+/// callers can skip working out changes nobody will hear about.
+pub(crate) fn is_observed(db: &PlacesDb) -> bool {
+    OBSERVERS.read().contains_key(&db.api_id())
+}
+
+/// Notes a change made by `db`, to be delivered by `take_pending` once it's
+/// committed.
+pub(crate) fn note(db: &PlacesDb, change: PlacesChange) {
+    if is_observed(db) {
+        let mut queue = db.pending_changes.lock();
+        // Outside of a transaction, each statement commits as it runs, so
+        // the change has already been committed.
+        if db.is_autocommit() {
+            queue.committed.push(change);
+        } else {
+            queue.uncommitted.push(change);
+        }
+    }
+}
+
+/// Takes the changes committed on `db`, if there are any and someone is
+/// observing them. The caller should deliver them once it's released the
+/// connection, so that the observer can use it.
+#[must_use]
+pub(crate) fn take_pending(db: &PlacesDb) -> Option<PendingChanges> {
+    let changes = std::mem::take(&mut db.pending_changes.lock().committed);
+    if changes.is_empty() {
+        return None;
+    }
+    let observer = OBSERVERS.read().get(&db.api_id()).cloned()?;
+    Some(PendingChanges {
+        observer,
+        changes,
+        source: db.conn_type(),
+    })
+}
+
+pub(crate) struct PendingChanges {
+    observer: Arc<dyn PlacesChangeObserver>,
+    changes: Vec<PlacesChange>,
+    source: ConnectionType,
+}
+
+impl PendingChanges {
+    /// Reports the changes as coming from a different type of connection,
+    /// for callers which use one connection to make changes on behalf of
+    /// another.
+    pub(crate) fn with_source(mut self, source: ConnectionType) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub(crate) fn deliver(self) {
+        self.observer.on_changes(self.changes, self.source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_api;
+    use crate::observation::VisitObservation;
+    use crate::storage::bookmarks::{
+        delete_bookmark, insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+    };
+    use crate::storage::history::{apply_observation, delete_visits_for};
+    use parking_lot::Mutex;
+
+    #[derive(Default)]
+    struct TestObserver {
+        seen: Mutex<Vec<(Vec<PlacesChange>, ConnectionType)>>,
+    }
+
+    impl PlacesChangeObserver for Arc<TestObserver> {
+        fn on_changes(&self, changes: Vec<PlacesChange>, source: ConnectionType) {
+            self.seen.lock().push((changes, source));
+        }
+    }
+
+    fn deliver(db: &PlacesDb) {
+        if let Some(pending) = take_pending(db) {
+            pending.deliver();
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let api = new_mem_api();
+        let conn = api
+            .open_connection(ConnectionType::ReadWrite)
+            .expect("should get writer");
+
+        // Nothing is noted until there's an observer.
+        let url = Url::parse("https://example.com/").unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone())
+                .with_visit_type(VisitTransition::Link)
+                .with_at(Timestamp(1000)),
+        )
+        .expect("should apply");
+        assert!(take_pending(&conn).is_none());
+
+        let observer = Arc::new(TestObserver::default());
+        api.set_change_observer(Box::new(Arc::clone(&observer)));
+
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone())
+                .with_visit_type(VisitTransition::Typed)
+                .with_at(Timestamp(2000)),
+        )
+        .expect("should apply");
+        // Observations which don't add a visit aren't changes we report.
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone()).with_title("Example".to_string()),
+        )
+        .expect("should apply");
+        let guid = insert_bookmark(
+            &conn,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: url.clone(),
+                title: None,
+            }
+            .into(),
+        )
+        .expect("should insert");
+        deliver(&conn);
+
+        // Changes are only delivered once they're committed, and are thrown
+        // away if they're rolled back, however the transaction ends.
+        let tx = conn.begin_transaction().expect("should begin");
+        note(&conn, PlacesChange::HistoryCleared);
+        assert!(take_pending(&conn).is_none());
+        tx.rollback().expect("should roll back");
+        deliver(&conn);
+        {
+            let _tx = conn.begin_transaction().expect("should begin");
+            note(&conn, PlacesChange::HistoryCleared);
+        }
+        deliver(&conn);
+
+        assert!(delete_bookmark(&conn, &guid).expect("should delete"));
+        let page_guid = crate::storage::history::url_to_guid(&conn, &url)
+            .expect("should query")
+            .expect("should exist");
+        delete_visits_for(&conn, &page_guid).expect("should delete");
+        deliver(&conn);
+
+        assert_eq!(
+            *observer.seen.lock(),
+            vec![
+                (
+                    vec![
+                        PlacesChange::VisitAdded {
+                            url: url.clone(),
+                            visit_time: Timestamp(2000),
+                            visit_type: VisitTransition::Typed,
+                        },
+                        PlacesChange::BookmarkInserted {
+                            guid: guid.clone(),
+                            parent_guid: BookmarkRootGuid::Unfiled.into(),
+                        },
+                    ],
+                    ConnectionType::ReadWrite
+                ),
+                (
+                    vec![
+                        PlacesChange::BookmarkDeleted { guid },
+                        PlacesChange::PageDeleted { url },
+                    ],
+                    ConnectionType::ReadWrite
+                ),
+            ]
+        );
+
+        api.clear_change_observer();
+        assert!(!OBSERVERS.read().contains_key(&conn.api_id()));
+    }
+}
@@ -0,0 +1,57 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Timing spans for API calls and sync phases, to help work out where a slow
+// call spends its time on a real device.
+//
+// When the `tracing` feature is enabled, each span is a `tracing` span, and
+// we emit an event with its duration when it ends. We don't install a
+// subscriber, so `tracing`'s `log` feature turns these into `log` records,
+// which reach the app through the same log forwarding as everything else.
+// Apps can install their own subscriber if they want more than that.
+//
+// When the feature is disabled, spans compile to nothing.
+
+#[cfg(feature = "tracing")]
+pub(crate) struct Span {
+    name: &'static str,
+    start: std::time::Instant,
+    // Dropped after our `Drop` impl runs, so the timing event is recorded
+    // inside the span.
+    _entered: tracing::span::EnteredSpan,
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Span {
+    fn drop(&mut self) {
+        tracing::debug!(
+            target: "places::spans",
+            elapsed_ms = self.start.elapsed().as_millis() as u64,
+            "{} finished",
+            self.name
+        );
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Span;
+
+/// Enters a span named `name`, which lasts until the returned value is
+/// dropped.
+#[cfg(feature = "tracing")]
+#[must_use]
+pub(crate) fn enter(name: &'static str) -> Span {
+    Span {
+        name,
+        start: std::time::Instant::now(),
+        _entered: tracing::debug_span!(target: "places::spans", "places", name).entered(),
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+#[must_use]
+pub(crate) fn enter(_name: &'static str) -> Span {
+    Span
+}
@@ -10,12 +10,14 @@ use crate::bookmark_sync::engine::{
 };
 use crate::db::PlacesDb;
 use crate::error::*;
+use crate::observer::{self, PlacesChange};
 use crate::types::{BookmarkType, SyncStatus};
 use rusqlite::{self, Connection, Row};
 #[cfg(test)]
 use serde_json::{self, json};
 use sql_support::{self, ConnExt};
 use std::cmp::{max, min};
+use std::collections::HashSet;
 use sync15::engine::EngineSyncAssociation;
 use sync_guid::Guid as SyncGuid;
 use types::Timestamp;
@@ -24,9 +26,14 @@ use url::Url;
 pub use root_guid::{BookmarkRootGuid, USER_CONTENT_ROOTS};
 
 mod conversions;
+pub mod duplicates;
 pub mod fetch;
+pub mod import_source;
 pub mod json_tree;
+#[cfg(test)]
+mod proptests;
 mod root_guid;
+pub mod trash;
 
 fn create_root(
     db: &Connection,
@@ -242,7 +249,7 @@ impl InsertableItem {
     impl_common_bookmark_getter!(guid, Option<SyncGuid>);
 
     // We allow a setter for parent_guid and timestamps to help when inserting a tree.
-    fn set_parent_guid(&mut self, guid: SyncGuid) {
+    pub(crate) fn set_parent_guid(&mut self, guid: SyncGuid) {
         match self {
             InsertableItem::Bookmark { b } => b.parent_guid = guid,
             InsertableItem::Separator { s } => s.parent_guid = guid,
@@ -265,6 +272,38 @@ impl InsertableItem {
             InsertableItem::Folder { f } => f.date_added = Some(ts),
         }
     }
+
+    fn set_position(&mut self, pos: BookmarkPosition) {
+        match self {
+            InsertableItem::Bookmark { b } => b.position = pos,
+            InsertableItem::Separator { s } => s.position = pos,
+            InsertableItem::Folder { f } => f.position = pos,
+        }
+    }
+
+    fn set_guid(&mut self, guid: SyncGuid) {
+        match self {
+            InsertableItem::Bookmark { b } => b.guid = Some(guid),
+            InsertableItem::Separator { s } => s.guid = Some(guid),
+            InsertableItem::Folder { f } => f.guid = Some(guid),
+        }
+    }
+}
+
+/// A tree of items to insert under an existing folder with `insert_tree`,
+/// for example when restoring a backup. The parent guids of the items are
+/// ignored, since the tree's structure says where they go.
+#[derive(Debug, Clone)]
+pub struct InsertableBookmarkTree {
+    pub children: Vec<InsertableItem>,
+}
+
+/// The guid an item in an `InsertableBookmarkTree` was inserted with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookmarkGuidMapping {
+    /// The guid the item had in the tree, if it had one.
+    pub requested_guid: Option<SyncGuid>,
+    pub guid: SyncGuid,
 }
 
 pub fn insert_bookmark(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid> {
@@ -278,13 +317,84 @@ pub fn insert_bookmark(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid> {
     result
 }
 
+/// Inserts all the items in `tree` under `parent_guid`, in a single
+/// transaction. Items keep the guids they have in the tree, unless they're
+/// invalid or already used, in which case they get new ones, as do items
+/// without a guid. Returns the guid of each item, in the order they appear
+/// in the tree, with the children of a folder following the folder.
+pub fn insert_tree(
+    db: &PlacesDb,
+    parent_guid: &SyncGuid,
+    tree: InsertableBookmarkTree,
+) -> Result<Vec<BookmarkGuidMapping>> {
+    let tx = db.begin_transaction()?;
+    let result = insert_tree_in_tx(db, parent_guid, tree);
+    super::delete_pending_temp_tables(db)?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result
+}
+
+fn insert_tree_in_tx(
+    db: &PlacesDb,
+    parent_guid: &SyncGuid,
+    tree: InsertableBookmarkTree,
+) -> Result<Vec<BookmarkGuidMapping>> {
+    let mut mappings = Vec::new();
+    let mut used = HashSet::new();
+    for mut item in tree.children {
+        assign_tree_guids(db, &mut item, &mut used, &mut mappings)?;
+        item.set_parent_guid(parent_guid.clone());
+        insert_bookmark_in_tx(db, item)?;
+    }
+    Ok(mappings)
+}
+
+// Gives `item` and its descendants the guids they'll be inserted with, and
+// clears the parent guids of the descendants, so they're inserted into their
+// (possibly renamed) parent.
+fn assign_tree_guids(
+    db: &PlacesDb,
+    item: &mut InsertableItem,
+    used: &mut HashSet<SyncGuid>,
+    mappings: &mut Vec<BookmarkGuidMapping>,
+) -> Result<()> {
+    let requested_guid = item.guid().clone();
+    let guid = match requested_guid {
+        Some(ref guid)
+            if guid.is_valid_for_places()
+                && guid.is_valid_for_sync_server()
+                && !used.contains(guid)
+                && get_raw_bookmark(db, guid)?.is_none() =>
+        {
+            guid.clone()
+        }
+        _ => SyncGuid::random(),
+    };
+    used.insert(guid.clone());
+    item.set_guid(guid.clone());
+    mappings.push(BookmarkGuidMapping {
+        requested_guid,
+        guid,
+    });
+    if let InsertableItem::Folder { f } = item {
+        for child in &mut f.children {
+            child.set_parent_guid(SyncGuid::empty());
+            assign_tree_guids(db, child, used, mappings)?;
+        }
+    }
+    Ok(())
+}
+
 pub fn maybe_truncate_title<'a>(t: &Option<&'a str>) -> Option<&'a str> {
     use super::TITLE_LENGTH_MAX;
     use crate::util::slice_up_to;
     t.map(|title| slice_up_to(title, TITLE_LENGTH_MAX))
 }
 
-fn insert_bookmark_in_tx(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid> {
+pub(crate) fn insert_bookmark_in_tx(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid> {
     // find the row ID of the parent.
     if bm.parent_guid() == BookmarkRootGuid::Root {
         return Err(InvalidPlaceInfo::CannotUpdateRoot(BookmarkRootGuid::Root).into());
@@ -409,14 +519,24 @@ fn insert_bookmark_in_tx(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid>
         WHERE id = :parent_id";
     db.execute_cached(sql_counter, &[(":parent_id", &parent.row_id)])?;
 
+    observer::note(
+        db,
+        PlacesChange::BookmarkInserted {
+            guid: guid.clone(),
+            parent_guid: parent.guid,
+        },
+    );
     Ok(guid)
 }
 
 /// Delete the specified bookmark. Returns true if a bookmark with the guid
 /// existed and was deleted, false otherwise.
+/// Deletes an item and its descendants, keeping a copy in the trash so that
+/// they can be restored with `trash::restore_bookmark`.
 pub fn delete_bookmark(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
     let tx = db.begin_transaction()?;
-    let result = delete_bookmark_in_tx(db, guid);
+    let result = trash::add_to_trash(db, guid, Timestamp::now())
+        .and_then(|_| delete_bookmark_in_tx(db, guid));
     match result {
         Ok(_) => tx.commit()?,
         Err(_) => tx.rollback()?,
@@ -450,6 +570,7 @@ fn delete_bookmark_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
         &[(":id", &record.row_id)],
     )?;
     super::delete_pending_temp_tables(db)?;
+    observer::note(db, PlacesChange::BookmarkDeleted { guid: guid.clone() });
     Ok(true)
 }
 
@@ -581,12 +702,14 @@ pub fn update_bookmark(db: &PlacesDb, guid: &SyncGuid, item: &UpdatableItem) ->
         .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
     let result = update_bookmark_in_tx(db, guid, item, existing);
     super::delete_pending_temp_tables(db)?;
-    // Note: `tx` automatically rolls back on drop if we don't commit
-    tx.commit()?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
     result
 }
 
-fn update_bookmark_in_tx(
+pub(crate) fn update_bookmark_in_tx(
     db: &PlacesDb,
     guid: &SyncGuid,
     item: &UpdatableItem,
@@ -647,6 +770,12 @@ fn update_bookmark_in_tx(
             if new_parent.bookmark_type != BookmarkType::Folder {
                 return Err(InvalidPlaceInfo::InvalidParent(new_parent_guid.to_string()).into());
             }
+            // A folder can't be moved into itself or any of its descendants.
+            if raw.bookmark_type == BookmarkType::Folder
+                && is_descendant_or_self(db, new_parent.row_id, raw.row_id)?
+            {
+                return Err(InvalidPlaceInfo::InvalidParent(new_parent_guid.to_string()).into());
+            }
             parent_id = new_parent.row_id;
             update_old_parent_status = true;
             update_new_parent_status = true;
@@ -740,6 +869,7 @@ fn update_bookmark_in_tx(
         set_ancestors_last_modified(db, parent_id, now)?;
         db.execute_cached(sql_counter, &[(":parent_id", &parent_id)])?;
     }
+    observer::note(db, PlacesChange::BookmarkUpdated { guid: guid.clone() });
     Ok(())
 }
 
@@ -791,12 +921,60 @@ pub fn bookmarks_get_url_for_keyword(db: &PlacesDb, keyword: &str) -> Result<Opt
     }
 }
 
-/// Erases all bookmarks and resets all Sync metadata.
+/// Renumbers the children of any folder whose positions have gaps or
+/// duplicates, keeping their order. Returns the number of items which were
+/// moved.
+pub fn repair_positions(db: &PlacesDb) -> Result<usize> {
+    // This runs on every maintenance, and almost always finds nothing, so we
+    // only look for broken folders here. The query is answered from
+    // `parentindex` without sorting anything; we only read and renumber the
+    // children of folders which need it.
+    let broken_folders = db.query_rows_and_then(
+        "SELECT parent FROM moz_bookmarks
+         WHERE parent NOT NULL
+         GROUP BY parent
+         HAVING MIN(position) <> 0
+             OR MAX(position) <> COUNT(*) - 1
+             OR COUNT(DISTINCT position) <> COUNT(*)",
+        [],
+        |row| row.get::<_, RowId>(0),
+    )?;
+    if broken_folders.is_empty() {
+        return Ok(0);
+    }
+    let tx = db.begin_transaction()?;
+    let mut moved = 0;
+    for parent in broken_folders {
+        let children = db.query_rows_and_then_cached(
+            "SELECT id, position FROM moz_bookmarks
+             WHERE parent = :parent
+             ORDER BY position, id",
+            rusqlite::named_params! { ":parent": parent },
+            |row| -> rusqlite::Result<_> { Ok((row.get::<_, RowId>(0)?, row.get::<_, u32>(1)?)) },
+        )?;
+        for (position, (id, old_position)) in children.into_iter().enumerate() {
+            let position = position as u32;
+            if position != old_position {
+                db.execute_cached(
+                    "UPDATE moz_bookmarks SET position = :position WHERE id = :id",
+                    rusqlite::named_params! { ":position": position, ":id": id },
+                )?;
+                moved += 1;
+            }
+        }
+    }
+    tx.commit()?;
+    log::warn!("Repaired the positions of {} bookmarks", moved);
+    Ok(moved)
+}
+
+/// Erases all bookmarks, including the trash, and resets all Sync metadata.
 pub fn delete_everything(db: &PlacesDb) -> Result<()> {
     let tx = db.begin_transaction()?;
     db.execute_batch(&format!(
         "DELETE FROM moz_bookmarks
-         WHERE guid NOT IN ('{}', '{}', '{}', '{}', '{}');",
+         WHERE guid NOT IN ('{}', '{}', '{}', '{}', '{}');
+         DELETE FROM moz_bookmarks_trash;",
         BookmarkRootGuid::Root.as_str(),
         BookmarkRootGuid::Menu.as_str(),
         BookmarkRootGuid::Mobile.as_str(),
@@ -884,6 +1062,23 @@ const RAW_BOOKMARK_SQL: &str = "
     LEFT JOIN moz_places h ON h.id = b.fk
 ";
 
+// Returns true if `item_id` is `ancestor_id`, or somewhere below it.
+fn is_descendant_or_self(db: &PlacesDb, item_id: RowId, ancestor_id: RowId) -> Result<bool> {
+    Ok(db.query_row_and_then_cachable(
+        "WITH RECURSIVE
+         descendants(id) AS (
+            SELECT :ancestor_id
+            UNION ALL
+            SELECT b.id FROM moz_bookmarks b
+            JOIN descendants d ON b.parent = d.id
+         )
+         SELECT EXISTS(SELECT 1 FROM descendants WHERE id = :item_id)",
+        &[(":ancestor_id", &ancestor_id), (":item_id", &item_id)],
+        |row| row.get::<_, bool>(0),
+        true,
+    )?)
+}
+
 pub(crate) fn get_raw_bookmark(db: &PlacesDb, guid: &SyncGuid) -> Result<Option<RawBookmark>> {
     // sql is based on fetchBookmark() in Desktop's Bookmarks.jsm, with 'fk' added
     // and title's NULLIF handling.
@@ -1009,7 +1204,9 @@ mod tests {
     use crate::api::places_api::test::new_mem_connection;
     use crate::db::PlacesDb;
     use crate::storage::get_meta;
-    use crate::tests::{append_invalid_bookmark, assert_json_tree, insert_json_tree};
+    use crate::tests::{
+        append_invalid_bookmark, assert_json_tree, check_positions, insert_json_tree,
+    };
     use json_tree::*;
     use pretty_assertions::assert_eq;
     use serde_json::Value;
@@ -1022,6 +1219,42 @@ mod tests {
             .position
     }
 
+    #[test]
+    fn test_repair_positions() -> Result<()> {
+        let conn = new_mem_connection();
+        let guids = (0..4)
+            .map(|i| {
+                insert_bookmark(
+                    &conn,
+                    InsertableBookmark {
+                        parent_guid: BookmarkRootGuid::Unfiled.into(),
+                        position: BookmarkPosition::Append,
+                        date_added: None,
+                        last_modified: None,
+                        guid: None,
+                        url: Url::parse(&format!("https://www.example.com/{}", i))?,
+                        title: None,
+                    }
+                    .into(),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(repair_positions(&conn)?, 0);
+
+        // A gap, and a duplicate.
+        for (guid, position) in guids.iter().zip([0, 2, 5, 5]) {
+            conn.execute(
+                "UPDATE moz_bookmarks SET position = :position WHERE guid = :guid",
+                rusqlite::named_params! { ":position": position, ":guid": guid },
+            )?;
+        }
+        assert_eq!(repair_positions(&conn)?, 3);
+        check_positions(&conn);
+        let positions: Vec<u32> = guids.iter().map(|guid| get_pos(&conn, guid)).collect();
+        assert_eq!(positions, vec![0, 1, 2, 3]);
+        Ok(())
+    }
+
     #[test]
     fn test_bookmark_url_for_keyword() -> Result<()> {
         let conn = new_mem_connection();
@@ -1138,6 +1371,98 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_insert_tree() -> Result<()> {
+        let conn = new_mem_connection();
+        let bookmark = |guid: Option<&str>, url: &str| InsertableItem::Bookmark {
+            b: InsertableBookmark {
+                parent_guid: SyncGuid::empty(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: guid.map(SyncGuid::from),
+                url: Url::parse(url).unwrap(),
+                title: None,
+            },
+        };
+        let existing = insert_bookmark(
+            &conn,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: Some("bookmarkAAAA".into()),
+                url: Url::parse("https://example.com/existing")?,
+                title: None,
+            }
+            .into(),
+        )?;
+
+        let tree = InsertableBookmarkTree {
+            children: vec![
+                InsertableFolder {
+                    // Ignored, like all the parent guids in a tree.
+                    parent_guid: BookmarkRootGuid::Mobile.into(),
+                    position: BookmarkPosition::Append,
+                    date_added: None,
+                    last_modified: None,
+                    guid: Some("folderAAAAAA".into()),
+                    title: Some("Restored".into()),
+                    children: vec![
+                        // Already in the database.
+                        bookmark(Some("bookmarkAAAA"), "https://example.com/a"),
+                        bookmark(Some("bookmarkBBBB"), "https://example.com/b"),
+                    ],
+                }
+                .into(),
+                // Already in the tree.
+                bookmark(Some("bookmarkBBBB"), "https://example.com/c"),
+                bookmark(None, "https://example.com/d"),
+            ],
+        };
+        let mappings = insert_tree(&conn, &BookmarkRootGuid::Unfiled.into(), tree)?;
+        assert_eq!(
+            mappings
+                .iter()
+                .map(|m| m.requested_guid.as_ref().map(SyncGuid::as_str))
+                .collect::<Vec<_>>(),
+            vec![
+                Some("folderAAAAAA"),
+                Some("bookmarkAAAA"),
+                Some("bookmarkBBBB"),
+                Some("bookmarkBBBB"),
+                None,
+            ]
+        );
+        assert_eq!(mappings[0].guid, "folderAAAAAA");
+        assert_ne!(mappings[1].guid, existing);
+        assert_eq!(mappings[2].guid, "bookmarkBBBB");
+        assert_ne!(mappings[3].guid, "bookmarkBBBB");
+
+        let parent_of = |guid: &SyncGuid| {
+            get_raw_bookmark(&conn, guid)
+                .expect("should work")
+                .expect("should exist")
+                .parent_guid
+                .expect("should have a parent")
+        };
+        assert_eq!(parent_of(&mappings[0].guid), BookmarkRootGuid::Unfiled);
+        assert_eq!(parent_of(&mappings[1].guid), "folderAAAAAA");
+        assert_eq!(parent_of(&mappings[2].guid), "folderAAAAAA");
+        assert_eq!(parent_of(&mappings[3].guid), BookmarkRootGuid::Unfiled);
+        assert_eq!(parent_of(&mappings[4].guid), BookmarkRootGuid::Unfiled);
+        assert_eq!(get_pos(&conn, &mappings[4].guid), 3);
+
+        // Trees can't be inserted into the root.
+        let tree = InsertableBookmarkTree {
+            children: vec![bookmark(Some("bookmarkCCCC"), "https://example.com/e")],
+        };
+        assert!(insert_tree(&conn, &BookmarkRootGuid::Root.into(), tree).is_err());
+        assert!(get_raw_bookmark(&conn, &"bookmarkCCCC".into())?.is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_insert_titles() -> Result<()> {
         let conn = new_mem_connection();
@@ -1792,6 +2117,55 @@ mod tests {
             .into(),
         )
         .expect_err("can't move to the root");
+
+        // A move of a folder into itself
+        update_bookmark(
+            &conn,
+            &"folder1_____".into(),
+            &UpdatableFolder {
+                location: UpdateTreeLocation::Parent {
+                    guid: "folder1_____".into(),
+                    pos: BookmarkPosition::Append,
+                },
+                ..Default::default()
+            }
+            .into(),
+        )
+        .expect_err("can't move a folder into itself");
+
+        // A move of a folder into its descendant
+        insert_bookmark(
+            &conn,
+            InsertableFolder {
+                parent_guid: "folder1_____".into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: Some("folder2_____".into()),
+                title: None,
+                children: vec![],
+            }
+            .into(),
+        )
+        .expect("should insert");
+        update_bookmark(
+            &conn,
+            &"folder1_____".into(),
+            &UpdatableFolder {
+                location: UpdateTreeLocation::Parent {
+                    guid: "folder2_____".into(),
+                    pos: BookmarkPosition::Append,
+                },
+                ..Default::default()
+            }
+            .into(),
+        )
+        .expect_err("can't move a folder into its descendant");
+        check_positions(&conn);
+        let folder2 = get_raw_bookmark(&conn, &"folder2_____".into())
+            .expect("should work")
+            .expect("should exist");
+        assert_eq!(folder2.parent_guid, Some("folder1_____".into()));
     }
 
     #[test]
@@ -0,0 +1,320 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Syncing between devices, and importing from other browsers, can leave
+// users with several bookmarks for the same URL. These functions find them,
+// and merge each group into one bookmark. Importing the same bookmarks twice
+// leaves copies side by side in the same folder, which we can remove without
+// asking which to keep.
+
+use super::fetch::BookmarkData;
+use super::{delete_bookmark_in_tx, trash, RawBookmark, RAW_BOOKMARK_SQL};
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::types::BookmarkType;
+use sql_support::ConnExt;
+use types::Timestamp;
+use url::Url;
+
+/// The bookmarks for a URL which is bookmarked more than once, oldest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateBookmarks {
+    pub url: Url,
+    pub bookmarks: Vec<BookmarkData>,
+}
+
+/// Which of a group of duplicate bookmarks to keep, by when they were added.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DuplicateBookmarksStrategy {
+    KeepOldest,
+    KeepNewest,
+}
+
+/// Returns the URLs which are bookmarked more than once, with their
+/// bookmarks.
+pub fn find_duplicates(db: &PlacesDb) -> Result<Vec<DuplicateBookmarks>> {
+    let scope = db.begin_interrupt_scope()?;
+    let raws = db.query_rows_and_then_cached(
+        &format!(
+            "{}
+             WHERE b.type = {bookmark_type}
+               AND b.fk IN (SELECT fk FROM moz_bookmarks
+                            WHERE type = {bookmark_type}
+                            GROUP BY fk
+                            HAVING COUNT(*) > 1)
+             ORDER BY b.fk, b.dateAdded, b.id",
+            RAW_BOOKMARK_SQL,
+            bookmark_type = BookmarkType::Bookmark as u8,
+        ),
+        [],
+        |row| -> Result<_> {
+            scope.err_if_interrupted()?;
+            RawBookmark::from_row(row)
+        },
+    )?;
+    let mut groups: Vec<DuplicateBookmarks> = Vec::new();
+    for bookmark in raws.into_iter().filter_map(bookmark_data) {
+        match groups.last_mut() {
+            Some(group) if group.url == bookmark.url => group.bookmarks.push(bookmark),
+            _ => groups.push(DuplicateBookmarks {
+                url: bookmark.url.clone(),
+                bookmarks: vec![bookmark],
+            }),
+        }
+    }
+    Ok(groups)
+}
+
+/// Returns the bookmarks with the same URL and title as an earlier bookmark
+/// in the same folder, in the order of the tree.
+pub fn find_duplicate_siblings(db: &PlacesDb) -> Result<Vec<BookmarkData>> {
+    let scope = db.begin_interrupt_scope()?;
+    let raws = db.query_rows_and_then_cached(
+        &format!(
+            "{}
+             WHERE b.type = {bookmark_type}
+               AND EXISTS(SELECT 1 FROM moz_bookmarks o
+                          WHERE o.type = b.type
+                            AND o.parent = b.parent
+                            AND o.fk = b.fk
+                            AND IFNULL(o.title, '') = IFNULL(b.title, '')
+                            AND o.position < b.position)
+             ORDER BY b.parent, b.position",
+            RAW_BOOKMARK_SQL,
+            bookmark_type = BookmarkType::Bookmark as u8,
+        ),
+        [],
+        |row| -> Result<_> {
+            scope.err_if_interrupted()?;
+            RawBookmark::from_row(row)
+        },
+    )?;
+    Ok(raws.into_iter().filter_map(bookmark_data).collect())
+}
+
+// Bookmarks always have a URL and a parent, so this only returns `None` for
+// a broken tree.
+fn bookmark_data(raw: RawBookmark) -> Option<BookmarkData> {
+    Some(BookmarkData {
+        guid: raw.guid,
+        parent_guid: raw.parent_guid?,
+        position: raw.position,
+        date_added: raw.date_added,
+        last_modified: raw.date_modified,
+        url: raw.url?,
+        title: raw.title,
+    })
+}
+
+/// Keeps one bookmark for each URL which is bookmarked more than once, and
+/// deletes the others, in one transaction. Deleted bookmarks go into the
+/// trash, and their folders are marked as changed for Sync. Returns how many
+/// bookmarks were deleted.
+pub fn merge_duplicates(db: &PlacesDb, strategy: DuplicateBookmarksStrategy) -> Result<u32> {
+    let tx = db.begin_transaction()?;
+    let result = merge_duplicates_in_tx(db, strategy);
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result
+}
+
+fn merge_duplicates_in_tx(db: &PlacesDb, strategy: DuplicateBookmarksStrategy) -> Result<u32> {
+    let now = Timestamp::now();
+    let mut num_deleted = 0;
+    for group in find_duplicates(db)? {
+        let mut bookmarks = group.bookmarks.into_iter();
+        match strategy {
+            DuplicateBookmarksStrategy::KeepOldest => bookmarks.next(),
+            DuplicateBookmarksStrategy::KeepNewest => bookmarks.next_back(),
+        };
+        for bookmark in bookmarks {
+            if delete_duplicate_in_tx(db, &bookmark, now)? {
+                num_deleted += 1;
+            }
+        }
+    }
+    Ok(num_deleted)
+}
+
+/// Deletes the bookmarks returned by `find_duplicate_siblings`, keeping the
+/// first of each set of copies, in one transaction. As with
+/// `merge_duplicates`, they go into the trash. Returns how many bookmarks
+/// were deleted.
+pub fn delete_duplicate_siblings(db: &PlacesDb) -> Result<u32> {
+    let tx = db.begin_transaction()?;
+    let result = delete_duplicate_siblings_in_tx(db);
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result
+}
+
+fn delete_duplicate_siblings_in_tx(db: &PlacesDb) -> Result<u32> {
+    let now = Timestamp::now();
+    let mut num_deleted = 0;
+    for bookmark in find_duplicate_siblings(db)? {
+        if delete_duplicate_in_tx(db, &bookmark, now)? {
+            num_deleted += 1;
+        }
+    }
+    Ok(num_deleted)
+}
+
+// Moves `bookmark` to the trash, and marks its folder as changed for Sync.
+fn delete_duplicate_in_tx(db: &PlacesDb, bookmark: &BookmarkData, now: Timestamp) -> Result<bool> {
+    trash::add_to_trash(db, &bookmark.guid, now)?;
+    if !delete_bookmark_in_tx(db, &bookmark.guid)? {
+        return Ok(false);
+    }
+    db.execute_cached(
+        "UPDATE moz_bookmarks SET syncChangeCounter = syncChangeCounter + 1
+         WHERE guid = :guid",
+        &[(":guid", &bookmark.parent_guid)],
+    )?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::{
+        get_raw_bookmark, insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+    };
+    use sync_guid::Guid as SyncGuid;
+
+    fn insert(db: &PlacesDb, parent: BookmarkRootGuid, url: &str, date_added: u64) -> SyncGuid {
+        insert_bookmark(
+            db,
+            InsertableBookmark {
+                parent_guid: parent.into(),
+                position: BookmarkPosition::Append,
+                date_added: Some(Timestamp(date_added)),
+                last_modified: None,
+                guid: None,
+                url: Url::parse(url).unwrap(),
+                title: None,
+            }
+            .into(),
+        )
+        .expect("should insert")
+    }
+
+    fn change_counter(db: &PlacesDb, guid: &SyncGuid) -> u32 {
+        get_raw_bookmark(db, guid)
+            .expect("should fetch")
+            .expect("should exist")
+            ._sync_change_counter
+    }
+
+    #[test]
+    fn test_duplicates() -> Result<()> {
+        let db = new_mem_connection();
+        let newest = insert(&db, BookmarkRootGuid::Menu, "https://example.com/", 3000);
+        let oldest = insert(&db, BookmarkRootGuid::Toolbar, "https://example.com/", 1000);
+        let middle = insert(&db, BookmarkRootGuid::Unfiled, "https://example.com/", 2000);
+        let other_old = insert(&db, BookmarkRootGuid::Mobile, "https://mozilla.org/", 1000);
+        let other_new = insert(&db, BookmarkRootGuid::Mobile, "https://mozilla.org/", 2000);
+        insert(&db, BookmarkRootGuid::Menu, "https://example.org/", 1000);
+
+        let groups = find_duplicates(&db)?;
+        assert_eq!(
+            groups
+                .iter()
+                .map(|group| (
+                    group.url.as_str(),
+                    group
+                        .bookmarks
+                        .iter()
+                        .map(|b| b.guid.clone())
+                        .collect::<Vec<_>>()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "https://example.com/",
+                    vec![oldest.clone(), middle.clone(), newest.clone()]
+                ),
+                (
+                    "https://mozilla.org/",
+                    vec![other_old.clone(), other_new.clone()]
+                ),
+            ]
+        );
+
+        let menu_counter = change_counter(&db, &BookmarkRootGuid::Menu.as_guid());
+        assert_eq!(
+            merge_duplicates(&db, DuplicateBookmarksStrategy::KeepOldest)?,
+            3
+        );
+        assert!(find_duplicates(&db)?.is_empty());
+        assert!(get_raw_bookmark(&db, &oldest)?.is_some());
+        assert!(get_raw_bookmark(&db, &other_old)?.is_some());
+        for guid in [&newest, &middle, &other_new] {
+            assert!(get_raw_bookmark(&db, guid)?.is_none());
+        }
+        assert!(change_counter(&db, &BookmarkRootGuid::Menu.as_guid()) > menu_counter);
+        // Merged bookmarks can be restored from the trash.
+        assert!(trash::restore_bookmark(&db, &newest)?.is_some());
+
+        assert_eq!(
+            merge_duplicates(&db, DuplicateBookmarksStrategy::KeepNewest)?,
+            1
+        );
+        assert!(get_raw_bookmark(&db, &newest)?.is_some());
+        assert!(get_raw_bookmark(&db, &oldest)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_siblings() -> Result<()> {
+        let db = new_mem_connection();
+        let insert_titled = |parent: BookmarkRootGuid, url: &str, title: &str| {
+            insert_bookmark(
+                &db,
+                InsertableBookmark {
+                    parent_guid: parent.into(),
+                    position: BookmarkPosition::Append,
+                    date_added: None,
+                    last_modified: None,
+                    guid: None,
+                    url: Url::parse(url).unwrap(),
+                    title: Some(title.to_string()),
+                }
+                .into(),
+            )
+            .expect("should insert")
+        };
+        let first = insert_titled(BookmarkRootGuid::Menu, "https://example.com/", "Example");
+        let copy = insert_titled(BookmarkRootGuid::Menu, "https://example.com/", "Example");
+        // A different title, or a different folder, isn't a copy.
+        let renamed = insert_titled(BookmarkRootGuid::Menu, "https://example.com/", "Renamed");
+        let elsewhere = insert_titled(BookmarkRootGuid::Toolbar, "https://example.com/", "Example");
+        let second_copy = insert_titled(BookmarkRootGuid::Menu, "https://example.com/", "Example");
+
+        assert_eq!(
+            find_duplicate_siblings(&db)?
+                .into_iter()
+                .map(|b| b.guid)
+                .collect::<Vec<_>>(),
+            vec![copy.clone(), second_copy.clone()]
+        );
+
+        let menu_counter = change_counter(&db, &BookmarkRootGuid::Menu.as_guid());
+        assert_eq!(delete_duplicate_siblings(&db)?, 2);
+        assert!(find_duplicate_siblings(&db)?.is_empty());
+        for guid in [&first, &renamed, &elsewhere] {
+            assert!(get_raw_bookmark(&db, guid)?.is_some());
+        }
+        for guid in [&copy, &second_copy] {
+            assert!(get_raw_bookmark(&db, guid)?.is_none());
+        }
+        assert!(change_counter(&db, &BookmarkRootGuid::Menu.as_guid()) > menu_counter);
+        assert!(trash::restore_bookmark(&db, &copy)?.is_some());
+        Ok(())
+    }
+}
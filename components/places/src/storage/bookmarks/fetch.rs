@@ -305,6 +305,100 @@ pub fn fetch_bookmark(
     fetch_tree_with_depth(db, item_guid, &depth)
 }
 
+/// Fetches up to `limit` children of a folder, starting at `offset`, in
+/// position order. This lets UIs render huge folders without loading the
+/// whole subtree, like `fetch_tree` does.
+///
+/// Child folders are returned without their `child_guids` or `child_nodes`;
+/// callers fetch pages of those as needed. Returns `None` if there is no item
+/// with `folder_guid`, and an error if the item isn't a folder.
+pub fn fetch_children_page(
+    db: &PlacesDb,
+    folder_guid: &SyncGuid,
+    offset: u32,
+    limit: u32,
+) -> Result<Option<Vec<Item>>> {
+    let _tx = db.begin_transaction()?;
+    let folder = match get_raw_bookmark(db, folder_guid)? {
+        Some(folder) => folder,
+        None => return Ok(None),
+    };
+    if folder.bookmark_type != BookmarkType::Folder {
+        return Err(InvalidPlaceInfo::InvalidParent(folder_guid.to_string()).into());
+    }
+    let scope = db.begin_interrupt_scope()?;
+    Ok(Some(
+        db.query_rows_into_cached::<Vec<Option<Item>>, _, _, _, _>(
+            &CHILDREN_PAGE_QUERY,
+            rusqlite::named_params! {
+                ":parent": folder.row_id,
+                ":limit": limit,
+                ":offset": offset,
+            },
+            |row| -> Result<_> {
+                scope.err_if_interrupted()?;
+                child_from_row(row, folder_guid)
+            },
+        )?
+        .into_iter()
+        .flatten()
+        .collect(),
+    ))
+}
+
+fn child_from_row(row: &Row<'_>, parent_guid: &SyncGuid) -> Result<Option<Item>> {
+    let guid: SyncGuid = row.get("guid")?;
+    let position = row.get("position")?;
+    let date_added = row.get("dateAdded")?;
+    let last_modified = row.get("lastModified")?;
+    let title = row.get("title")?;
+    let url = row.get::<_, Option<String>>("url")?;
+    // Like `json_tree::fetch_tree`, we skip bookmarks with invalid URLs.
+    Ok(Some(
+        match BookmarkType::from_u8_with_valid_url(row.get("type")?, || url.is_some()) {
+            BookmarkType::Bookmark => match Url::parse(url.as_deref().unwrap_or_default()) {
+                Ok(url) => BookmarkData {
+                    guid,
+                    parent_guid: parent_guid.clone(),
+                    position,
+                    date_added,
+                    last_modified,
+                    url,
+                    title,
+                }
+                .into(),
+                Err(e) => {
+                    log::warn!(
+                        "ignoring malformed bookmark {} - invalid URL: {:?}",
+                        guid,
+                        e
+                    );
+                    return Ok(None);
+                }
+            },
+            BookmarkType::Separator => Separator {
+                guid,
+                parent_guid: parent_guid.clone(),
+                position,
+                date_added,
+                last_modified,
+            }
+            .into(),
+            BookmarkType::Folder => Folder {
+                guid,
+                parent_guid: Some(parent_guid.clone()),
+                position,
+                title,
+                date_added,
+                last_modified,
+                child_guids: None,
+                child_nodes: None,
+            }
+            .into(),
+        },
+    ))
+}
+
 fn bookmark_from_row(row: &Row<'_>) -> Result<Option<BookmarkData>> {
     Ok(
         match row
@@ -360,7 +454,122 @@ pub fn recent_bookmarks(db: &PlacesDb, limit: u32) -> Result<Vec<BookmarkData>>
         .collect())
 }
 
+/// Returns the number of bookmarks in a folder and all its subfolders,
+/// without fetching the tree. Folders and separators aren't counted. Returns
+/// `None` if there is no item with `folder_guid`, and an error if the item
+/// isn't a folder.
+pub fn bookmarks_count_by_parent(db: &PlacesDb, folder_guid: &SyncGuid) -> Result<Option<u32>> {
+    let _tx = db.begin_transaction()?;
+    let folder = match get_raw_bookmark(db, folder_guid)? {
+        Some(folder) => folder,
+        None => return Ok(None),
+    };
+    if folder.bookmark_type != BookmarkType::Folder {
+        return Err(InvalidPlaceInfo::InvalidParent(folder_guid.to_string()).into());
+    }
+    Ok(Some(db.query_row_and_then_cachable(
+        &COUNT_BY_PARENT_QUERY,
+        &[(":parent", &folder.row_id)],
+        |row| row.get(0),
+        true,
+    )?))
+}
+
+/// Returns the number of bookmarks in the whole tree. Folders and separators
+/// aren't counted.
+pub fn bookmarks_count_all(db: &PlacesDb) -> Result<u32> {
+    Ok(db.query_row_and_then_cachable(
+        "SELECT COUNT(*) FROM moz_bookmarks WHERE type = :type",
+        &[(":type", &BookmarkType::Bookmark)],
+        |row| row.get(0),
+        true,
+    )?)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BookmarksStatistics {
+    /// The number of bookmarks, folders and separators, not including the
+    /// roots.
+    pub total_items: u32,
+    pub folders: u32,
+    pub separators: u32,
+    /// How deeply the deepest item is nested. Items in a root, like the
+    /// bookmarks menu, are at depth 1, and a tree with nothing but the roots
+    /// has a depth of 0.
+    pub max_depth: u32,
+}
+
+/// Returns statistics about the shape of the whole tree, without fetching it.
+pub fn bookmarks_statistics(db: &PlacesDb) -> Result<BookmarksStatistics> {
+    Ok(db.query_row_and_then_cachable(
+        &STATISTICS_QUERY,
+        [],
+        |row| -> rusqlite::Result<_> {
+            Ok(BookmarksStatistics {
+                total_items: row.get("total_items")?,
+                folders: row.get("folders")?,
+                separators: row.get("separators")?,
+                max_depth: row.get("max_depth")?,
+            })
+        },
+        true,
+    )?)
+}
+
 lazy_static::lazy_static! {
+    static ref COUNT_BY_PARENT_QUERY: String = format!(
+        "WITH RECURSIVE
+         descendants(id, type) AS (
+           SELECT id, type FROM moz_bookmarks
+           WHERE parent = :parent
+           UNION ALL
+           SELECT b.id, b.type FROM moz_bookmarks b
+           JOIN descendants d ON d.id = b.parent
+           WHERE d.type = {folder_type}
+         )
+         SELECT COUNT(*) FROM descendants
+         WHERE type = {bookmark_type}",
+        folder_type = BookmarkType::Folder as u8,
+        bookmark_type = BookmarkType::Bookmark as u8,
+    );
+
+    // The roots are at depth 0, and aren't counted.
+    static ref STATISTICS_QUERY: String = format!(
+        "WITH RECURSIVE
+         descendants(id, type, depth) AS (
+           SELECT id, type, 0 FROM moz_bookmarks
+           WHERE parent = (SELECT id FROM moz_bookmarks WHERE guid = '{root_guid}')
+           UNION ALL
+           SELECT b.id, b.type, d.depth + 1 FROM moz_bookmarks b
+           JOIN descendants d ON d.id = b.parent
+           WHERE d.type = {folder_type}
+         )
+         SELECT COUNT(*) AS total_items,
+                IFNULL(SUM(type = {folder_type}), 0) AS folders,
+                IFNULL(SUM(type = {separator_type}), 0) AS separators,
+                IFNULL(MAX(depth), 0) AS max_depth
+         FROM descendants
+         WHERE depth > 0",
+        root_guid = BookmarkRootGuid::Root.as_str(),
+        folder_type = BookmarkType::Folder as u8,
+        separator_type = BookmarkType::Separator as u8,
+    );
+
+    static ref CHILDREN_PAGE_QUERY: &'static str =
+        "SELECT
+            b.guid,
+            b.position,
+            b.dateAdded,
+            b.lastModified,
+            b.type,
+            NULLIF(b.title, '') AS title,
+            h.url AS url
+        FROM moz_bookmarks b
+        LEFT JOIN moz_places h ON h.id = b.fk
+        WHERE b.parent = :parent
+        ORDER BY b.position
+        LIMIT :limit OFFSET :offset";
+
     pub static ref SEARCH_QUERY: String = format!(
         "SELECT
             b.guid,
@@ -788,6 +997,186 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_fetch_children_page() -> Result<()> {
+        let conns = new_mem_connections();
+
+        insert_json_tree(
+            &conns.write,
+            json!({
+                "guid": BookmarkRootGuid::Mobile.as_guid(),
+                "children": [
+                    {
+                        "guid": "bookmark1___",
+                        "url": "https://www.example1.com/"
+                    },
+                    {
+                        "guid": "folder1_____",
+                        "title": "A folder",
+                        "children": [
+                            {
+                                "guid": "bookmark2___",
+                                "url": "https://www.example2.com/"
+                            },
+                        ]
+                    },
+                    {
+                        "guid": "separator1__",
+                        "type": BookmarkType::Separator as u8,
+                    },
+                    {
+                        "guid": "bookmark3___",
+                        "url": "https://www.example3.com/"
+                    },
+                ]
+            }),
+        );
+
+        append_invalid_bookmark(
+            &conns.write,
+            BookmarkRootGuid::Mobile.guid(),
+            "invalid url",
+            "badurl",
+        );
+
+        let page = fetch_children_page(&conns.read, BookmarkRootGuid::Mobile.guid(), 1, 2)?
+            .expect("mobile should exist");
+        assert_eq!(page.len(), 2);
+        match &page[0] {
+            Item::Folder { f } => {
+                assert_eq!(f.guid, SyncGuid::from("folder1_____"));
+                assert_eq!(f.parent_guid.as_ref().unwrap(), BookmarkRootGuid::Mobile);
+                assert_eq!(f.position, 1);
+                assert_eq!(f.title.as_deref(), Some("A folder"));
+                // We don't fetch the children of child folders.
+                assert!(f.child_guids.is_none());
+                assert!(f.child_nodes.is_none());
+            }
+            _ => panic!("expect a folder"),
+        }
+        match &page[1] {
+            Item::Separator { s } => {
+                assert_eq!(s.guid, SyncGuid::from("separator1__"));
+                assert_eq!(s.position, 2);
+            }
+            _ => panic!("expect a separator"),
+        }
+
+        // The invalid bookmark is skipped, so the last page is short.
+        let page = fetch_children_page(&conns.read, BookmarkRootGuid::Mobile.guid(), 3, 2)?
+            .expect("mobile should exist");
+        assert_eq!(page.len(), 1);
+        match &page[0] {
+            Item::Bookmark { b } => {
+                assert_eq!(b.guid, SyncGuid::from("bookmark3___"));
+                assert_eq!(b.position, 3);
+                assert_eq!(b.url, Url::parse("https://www.example3.com/").unwrap());
+            }
+            _ => panic!("expect a bookmark"),
+        }
+
+        assert!(
+            fetch_children_page(&conns.read, BookmarkRootGuid::Mobile.guid(), 5, 2)?
+                .expect("mobile should exist")
+                .is_empty()
+        );
+        assert!(fetch_children_page(&conns.read, &SyncGuid::from("nonexistent_"), 0, 2)?.is_none());
+        assert!(fetch_children_page(&conns.read, &SyncGuid::from("bookmark1___"), 0, 2).is_err());
+
+        Ok(())
+    }
+    #[test]
+    fn test_counts_and_statistics() -> Result<()> {
+        let conns = new_mem_connections();
+        assert_eq!(bookmarks_count_all(&conns.read)?, 0);
+        assert_eq!(
+            bookmarks_statistics(&conns.read)?,
+            BookmarksStatistics::default()
+        );
+
+        insert_json_tree(
+            &conns.write,
+            json!({
+                "guid": BookmarkRootGuid::Menu.as_guid(),
+                "children": [
+                    {
+                        "guid": "bookmark1___",
+                        "url": "https://www.example1.com/"
+                    },
+                    {
+                        "guid": "folder1_____",
+                        "title": "A folder",
+                        "children": [
+                            {
+                                "guid": "bookmark2___",
+                                "url": "https://www.example2.com/"
+                            },
+                            {
+                                "guid": "folder2_____",
+                                "title": "A subfolder",
+                                "children": [
+                                    {
+                                        "guid": "bookmark3___",
+                                        "url": "https://www.example3.com/"
+                                    },
+                                ]
+                            },
+                        ]
+                    },
+                    {
+                        "guid": "separator1__",
+                        "type": BookmarkType::Separator as u8,
+                    },
+                ]
+            }),
+        );
+        insert_json_tree(
+            &conns.write,
+            json!({
+                "guid": BookmarkRootGuid::Mobile.as_guid(),
+                "children": [
+                    {
+                        "guid": "bookmark4___",
+                        "url": "https://www.example4.com/"
+                    },
+                ]
+            }),
+        );
+
+        assert_eq!(bookmarks_count_all(&conns.read)?, 4);
+        assert_eq!(
+            bookmarks_count_by_parent(&conns.read, BookmarkRootGuid::Menu.guid())?,
+            Some(3)
+        );
+        assert_eq!(
+            bookmarks_count_by_parent(&conns.read, &SyncGuid::from("folder1_____"))?,
+            Some(2)
+        );
+        assert_eq!(
+            bookmarks_count_by_parent(&conns.read, BookmarkRootGuid::Root.guid())?,
+            Some(4)
+        );
+        assert_eq!(
+            bookmarks_count_by_parent(&conns.read, BookmarkRootGuid::Toolbar.guid())?,
+            Some(0)
+        );
+        assert!(bookmarks_count_by_parent(&conns.read, &SyncGuid::from("nonexistent_"))?.is_none());
+        assert!(bookmarks_count_by_parent(&conns.read, &SyncGuid::from("bookmark1___")).is_err());
+
+        assert_eq!(
+            bookmarks_statistics(&conns.read)?,
+            BookmarksStatistics {
+                total_items: 7,
+                folders: 2,
+                separators: 1,
+                max_depth: 3,
+            }
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_recent() -> Result<()> {
         let conns = new_mem_connections();
@@ -0,0 +1,397 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Bookmarks created by an importer remember where they came from, so that
+// users can review, or remove, everything a botched import added.
+
+use super::fetch::{fetch_bookmark, Item};
+use super::{
+    delete_bookmark_in_tx, get_raw_bookmark, insert_bookmark_in_tx, update_bookmark_in_tx,
+    BookmarkPosition, BookmarkRootGuid, BookmarkUpdateInfo, InsertableFolder,
+};
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::types::BookmarkType;
+use rusqlite::types::{ToSql, ToSqlOutput};
+use sql_support::ConnExt;
+use std::collections::HashMap;
+use sync_guid::Guid as SyncGuid;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BookmarkImportSource {
+    Fennec = 1,
+    Ios = 2,
+    Chrome = 3,
+    Html = 4,
+    Desktop = 5,
+}
+
+impl ToSql for BookmarkImportSource {
+    #[inline]
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(*self as u8))
+    }
+}
+
+/// Where an importer puts the children of one of the imported roots, instead
+/// of in the matching local root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BookmarkImportRootMapping {
+    /// The imported root, like the toolbar.
+    pub source_root: SyncGuid,
+    /// The folder the root's children go into.
+    pub parent_guid: SyncGuid,
+    /// If set, the root's children go into a new folder with this title,
+    /// appended to `parent_guid`, instead. Roots mapped to the same parent
+    /// and title share the new folder.
+    pub folder_title: Option<String>,
+}
+
+/// The folders the children of mapped roots go into, which we create as
+/// they're needed, so that mapping a root without any children doesn't leave
+/// an empty folder behind.
+pub(crate) struct ImportDestinations<'a> {
+    mappings: &'a [BookmarkImportRootMapping],
+    created: HashMap<(SyncGuid, String), SyncGuid>,
+}
+
+impl<'a> ImportDestinations<'a> {
+    pub(crate) fn new(mappings: &'a [BookmarkImportRootMapping]) -> Result<Self> {
+        for mapping in mappings {
+            match BookmarkRootGuid::from_guid(&mapping.source_root) {
+                Some(BookmarkRootGuid::Root) | None => {
+                    return Err(InvalidPlaceInfo::InvalidGuid.into())
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(Self {
+            mappings,
+            created: HashMap::new(),
+        })
+    }
+
+    pub(crate) fn mappings(&self) -> &'a [BookmarkImportRootMapping] {
+        self.mappings
+    }
+
+    /// Returns the guid of the folder the children of `mapping`'s root go
+    /// into, creating it if it's new.
+    pub(crate) fn folder_for(
+        &mut self,
+        db: &PlacesDb,
+        mapping: &BookmarkImportRootMapping,
+    ) -> Result<SyncGuid> {
+        let title = match &mapping.folder_title {
+            Some(title) => title,
+            None => return Ok(mapping.parent_guid.clone()),
+        };
+        let key = (mapping.parent_guid.clone(), title.clone());
+        if let Some(guid) = self.created.get(&key) {
+            return Ok(guid.clone());
+        }
+        let guid = insert_bookmark_in_tx(
+            db,
+            InsertableFolder {
+                parent_guid: mapping.parent_guid.clone(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                title: Some(title.clone()),
+                children: Vec::new(),
+            }
+            .into(),
+        )?;
+        self.created.insert(key, guid.clone());
+        Ok(guid)
+    }
+
+    /// Records that the folders we created, and everything in them, were
+    /// imported from `source`.
+    pub(crate) fn record_imported(
+        &self,
+        db: &PlacesDb,
+        source: BookmarkImportSource,
+    ) -> Result<()> {
+        for guid in self.created.values() {
+            record_imported_tree(db, guid, source)?;
+        }
+        Ok(())
+    }
+}
+
+/// Moves the children of each mapped root in the mirror out of the local
+/// root they were merged into, and into the folder they're mapped to. Like
+/// `record_imported_from_mirror`, the importers call this after merging the
+/// mirror with the local tree, and before clearing the mirror.
+///
+/// Items which were already in the local root before the import, and which
+/// weren't in the mirror, stay where they are.
+pub(crate) fn move_mapped_roots_from_mirror(
+    db: &PlacesDb,
+    mappings: &[BookmarkImportRootMapping],
+    source: BookmarkImportSource,
+) -> Result<()> {
+    let mut destinations = ImportDestinations::new(mappings)?;
+    for mapping in mappings {
+        let children = db.query_rows_and_then(
+            "SELECT s.guid
+             FROM moz_bookmarks_synced_structure s
+             JOIN moz_bookmarks_synced v ON v.guid = s.guid
+             WHERE s.parentGuid = :root AND NOT v.isDeleted
+             ORDER BY s.position",
+            &[(":root", &mapping.source_root)],
+            |row| row.get::<_, SyncGuid>(0),
+        )?;
+        for guid in children {
+            let raw = match get_raw_bookmark(db, &guid)? {
+                Some(raw) if raw.parent_guid.as_ref() == Some(&mapping.source_root) => raw,
+                _ => continue,
+            };
+            let parent_guid = destinations.folder_for(db, mapping)?;
+            let (guid, updatable) = BookmarkUpdateInfo {
+                guid,
+                title: None,
+                url: None,
+                parent_guid: Some(parent_guid),
+                position: None,
+            }
+            .into_updatable(raw.bookmark_type)?;
+            update_bookmark_in_tx(db, &guid, &updatable, raw)?;
+        }
+    }
+    crate::storage::delete_pending_temp_tables(db)?;
+    destinations.record_imported(db, source)
+}
+
+/// Records that every item in the mirror was imported from `source`. The
+/// importers call this after merging the mirror with the local tree, and
+/// before clearing the mirror. Items which were already imported keep their
+/// original source.
+pub(crate) fn record_imported_from_mirror(
+    db: &PlacesDb,
+    source: BookmarkImportSource,
+) -> Result<()> {
+    db.execute_cached(
+        &format!(
+            "INSERT OR IGNORE INTO moz_bookmarks_import_sources(bookmark_id, source)
+             SELECT b.id, :source
+             FROM moz_bookmarks_synced v
+             JOIN moz_bookmarks b ON b.guid = v.guid
+             WHERE NOT v.isDeleted
+               AND v.guid NOT IN ('{}', '{}', '{}', '{}', '{}')",
+            BookmarkRootGuid::Root.as_str(),
+            BookmarkRootGuid::Menu.as_str(),
+            BookmarkRootGuid::Mobile.as_str(),
+            BookmarkRootGuid::Toolbar.as_str(),
+            BookmarkRootGuid::Unfiled.as_str(),
+        ),
+        &[(":source", &source)],
+    )?;
+    Ok(())
+}
+
+/// Records that `root_guid`, and everything in it, was imported from
+/// `source`. Used by importers which insert a tree of their own, instead of
+/// merging through the mirror.
+pub(crate) fn record_imported_tree(
+    db: &PlacesDb,
+    root_guid: &SyncGuid,
+    source: BookmarkImportSource,
+) -> Result<()> {
+    db.execute_cached(
+        "WITH RECURSIVE
+         descendants(id) AS (
+           SELECT id FROM moz_bookmarks WHERE guid = :root_guid
+           UNION ALL
+           SELECT b.id FROM moz_bookmarks b
+           JOIN descendants d ON b.parent = d.id
+         )
+         INSERT OR IGNORE INTO moz_bookmarks_import_sources(bookmark_id, source)
+         SELECT id, :source FROM descendants",
+        rusqlite::named_params! { ":root_guid": root_guid, ":source": source },
+    )?;
+    Ok(())
+}
+
+fn imported_guids(
+    db: &PlacesDb,
+    source: BookmarkImportSource,
+    only_empty_folders: bool,
+) -> Result<Vec<SyncGuid>> {
+    Ok(db.query_rows_and_then_cached(
+        "SELECT b.guid
+         FROM moz_bookmarks_import_sources i
+         JOIN moz_bookmarks b ON b.id = i.bookmark_id
+         WHERE i.source = :source
+           AND (NOT :only_empty_folders
+                OR (b.type = :folder_type
+                    AND NOT EXISTS(SELECT 1 FROM moz_bookmarks c WHERE c.parent = b.id)))
+         ORDER BY b.parent, b.position",
+        rusqlite::named_params! {
+            ":source": source,
+            ":only_empty_folders": only_empty_folders,
+            ":folder_type": BookmarkType::Folder as u8,
+        },
+        |row| row.get::<_, SyncGuid>(0),
+    )?)
+}
+
+/// Fetches every item imported from `source`. Folders are returned without
+/// their children.
+pub fn fetch_imported_bookmarks(db: &PlacesDb, source: BookmarkImportSource) -> Result<Vec<Item>> {
+    let mut items = Vec::new();
+    for guid in imported_guids(db, source, false)? {
+        if let Some(item) = fetch_bookmark(db, &guid, false)? {
+            items.push(item);
+        }
+    }
+    Ok(items)
+}
+
+/// Deletes the bookmarks and separators imported from `source`, then the
+/// imported folders which that leaves empty. Folders which still have
+/// children - ie, which the user has since added to - are kept. Returns the
+/// number of items deleted.
+pub fn delete_imported_bookmarks(db: &PlacesDb, source: BookmarkImportSource) -> Result<u32> {
+    let tx = db.begin_transaction()?;
+    let mut num_deleted = 0;
+    for guid in imported_guids(db, source, false)? {
+        let is_folder = matches!(
+            super::get_raw_bookmark(db, &guid)?,
+            Some(raw) if raw.bookmark_type == BookmarkType::Folder
+        );
+        if !is_folder && delete_bookmark_in_tx(db, &guid)? {
+            num_deleted += 1;
+        }
+    }
+    // Deleting a folder may leave its parent empty, so keep going until
+    // there's nothing left to delete.
+    loop {
+        let empty_folders = imported_guids(db, source, true)?;
+        if empty_folders.is_empty() {
+            break;
+        }
+        for guid in empty_folders {
+            if delete_bookmark_in_tx(db, &guid)? {
+                num_deleted += 1;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(num_deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::{
+        insert_bookmark, BookmarkPosition, InsertableBookmark, InsertableFolder,
+    };
+    use url::Url;
+
+    fn mark_imported(db: &PlacesDb, guid: &SyncGuid, source: BookmarkImportSource) {
+        db.execute_cached(
+            "INSERT INTO moz_bookmarks_import_sources(bookmark_id, source)
+             SELECT id, :source FROM moz_bookmarks WHERE guid = :guid",
+            rusqlite::named_params! { ":source": source, ":guid": guid },
+        )
+        .expect("should mark as imported");
+    }
+
+    fn insert_folder(db: &PlacesDb, parent_guid: SyncGuid) -> SyncGuid {
+        insert_bookmark(
+            db,
+            InsertableFolder {
+                parent_guid,
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                title: Some("folder".into()),
+                children: vec![],
+            }
+            .into(),
+        )
+        .expect("should insert folder")
+    }
+
+    fn insert(db: &PlacesDb, parent_guid: SyncGuid, url: &str) -> SyncGuid {
+        insert_bookmark(
+            db,
+            InsertableBookmark {
+                parent_guid,
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse(url).unwrap(),
+                title: None,
+            }
+            .into(),
+        )
+        .expect("should insert bookmark")
+    }
+
+    #[test]
+    fn test_imported_bookmarks() {
+        let conn = new_mem_connection();
+        let unfiled: SyncGuid = BookmarkRootGuid::Unfiled.into();
+
+        // An imported folder the user has since added to, and one they
+        // haven't.
+        let kept_folder = insert_folder(&conn, unfiled.clone());
+        let imported_in_kept = insert(&conn, kept_folder.clone(), "https://example.com/1");
+        let users = insert(&conn, kept_folder.clone(), "https://example.com/2");
+        let empty_folder = insert_folder(&conn, unfiled.clone());
+        let nested_folder = insert_folder(&conn, empty_folder.clone());
+        let imported_in_nested = insert(&conn, nested_folder.clone(), "https://example.com/3");
+        let from_ios = insert(&conn, unfiled, "https://example.com/4");
+        for guid in [
+            &kept_folder,
+            &imported_in_kept,
+            &empty_folder,
+            &nested_folder,
+            &imported_in_nested,
+        ] {
+            mark_imported(&conn, guid, BookmarkImportSource::Fennec);
+        }
+        mark_imported(&conn, &from_ios, BookmarkImportSource::Ios);
+
+        let imported =
+            fetch_imported_bookmarks(&conn, BookmarkImportSource::Fennec).expect("should fetch");
+        assert_eq!(imported.len(), 5);
+        assert!(
+            fetch_imported_bookmarks(&conn, BookmarkImportSource::Chrome)
+                .expect("should fetch")
+                .is_empty()
+        );
+
+        assert_eq!(
+            delete_imported_bookmarks(&conn, BookmarkImportSource::Fennec).expect("should delete"),
+            4
+        );
+        for guid in [&kept_folder, &users, &from_ios] {
+            assert!(fetch_bookmark(&conn, guid, false)
+                .expect("should fetch")
+                .is_some());
+        }
+        for guid in [
+            &imported_in_kept,
+            &empty_folder,
+            &nested_folder,
+            &imported_in_nested,
+        ] {
+            assert!(fetch_bookmark(&conn, guid, false)
+                .expect("should fetch")
+                .is_none());
+        }
+        let remaining =
+            fetch_imported_bookmarks(&conn, BookmarkImportSource::Fennec).expect("should fetch");
+        assert_eq!(remaining.len(), 1);
+    }
+}
@@ -0,0 +1,285 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Property-based tests for the bookmark tree. We generate random sequences of
+// inserts, moves, reorders and deletes, and check that whatever happens, the
+// tree is never left in an invalid state. Operations are allowed to fail (eg,
+// moving a folder into one of its own descendants), but a failed operation
+// must not leave any trace.
+
+use super::json_tree::{fetch_tree, FetchDepth};
+use super::*;
+use crate::api::places_api::{test::new_mem_api, ConnectionType};
+use crate::bookmark_sync::BookmarksSyncEngine;
+use crate::tests::check_positions;
+use proptest::prelude::*;
+use sync15::engine::{IncomingChangeset, SyncEngine};
+use sync15::{telemetry, ServerTimestamp};
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Bookmark,
+    Folder,
+    Separator,
+}
+
+// Items and parents are indexes which are resolved, modulo the number of
+// candidates, against the tree as it exists when the operation is applied.
+#[derive(Clone, Debug)]
+enum Op {
+    Insert {
+        kind: Kind,
+        parent: usize,
+        pos: Option<u32>,
+    },
+    Move {
+        item: usize,
+        parent: usize,
+        pos: Option<u32>,
+    },
+    Reorder {
+        item: usize,
+        pos: Option<u32>,
+    },
+    Delete {
+        item: usize,
+    },
+}
+
+fn position() -> impl Strategy<Value = Option<u32>> {
+    // Include positions past the end, which are treated as "append".
+    proptest::option::of(0u32..12)
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let kind = prop_oneof![
+        3 => Just(Kind::Bookmark),
+        2 => Just(Kind::Folder),
+        1 => Just(Kind::Separator),
+    ];
+    prop_oneof![
+        4 => (kind, any::<usize>(), position())
+            .prop_map(|(kind, parent, pos)| Op::Insert { kind, parent, pos }),
+        2 => (any::<usize>(), any::<usize>(), position())
+            .prop_map(|(item, parent, pos)| Op::Move { item, parent, pos }),
+        2 => (any::<usize>(), position()).prop_map(|(item, pos)| Op::Reorder { item, pos }),
+        1 => any::<usize>().prop_map(|item| Op::Delete { item }),
+    ]
+}
+
+fn to_position(pos: Option<u32>) -> BookmarkPosition {
+    match pos {
+        Some(pos) => BookmarkPosition::Specific { pos },
+        None => BookmarkPosition::Append,
+    }
+}
+
+fn query_guids(db: &PlacesDb, sql: &str) -> Vec<SyncGuid> {
+    db.query_rows_and_then(sql, [], |row| row.get::<_, SyncGuid>(0))
+        .expect("should work")
+}
+
+// Folders we can add children to. This excludes the root.
+fn folders(db: &PlacesDb) -> Vec<SyncGuid> {
+    query_guids(
+        db,
+        "SELECT guid FROM moz_bookmarks WHERE type = 2 AND guid <> 'root________' ORDER BY id",
+    )
+}
+
+// Items we can move, reorder or delete. This excludes all roots.
+fn user_items(db: &PlacesDb) -> Vec<SyncGuid> {
+    query_guids(
+        db,
+        "SELECT guid FROM moz_bookmarks
+         WHERE guid NOT IN ('root________', 'menu________', 'toolbar_____',
+                            'unfiled_____', 'mobile______')
+         ORDER BY id",
+    )
+}
+
+fn apply_op(db: &PlacesDb, op: &Op, n: usize) -> Result<()> {
+    let folders = folders(db);
+    let items = user_items(db);
+    match op {
+        Op::Insert { kind, parent, pos } => {
+            let parent_guid = folders[parent % folders.len()].clone();
+            let position = to_position(*pos);
+            let item: InsertableItem = match kind {
+                Kind::Bookmark => InsertableBookmark {
+                    parent_guid,
+                    position,
+                    date_added: None,
+                    last_modified: None,
+                    guid: None,
+                    url: Url::parse(&format!("https://example.com/{}", n % 7)).unwrap(),
+                    title: Some(format!("bookmark {}", n)),
+                }
+                .into(),
+                Kind::Folder => InsertableFolder {
+                    parent_guid,
+                    position,
+                    date_added: None,
+                    last_modified: None,
+                    guid: None,
+                    title: Some(format!("folder {}", n)),
+                    children: vec![],
+                }
+                .into(),
+                Kind::Separator => InsertableSeparator {
+                    parent_guid,
+                    position,
+                    date_added: None,
+                    last_modified: None,
+                    guid: None,
+                }
+                .into(),
+            };
+            insert_bookmark(db, item).map(|_| ())
+        }
+        Op::Move { item, parent, pos } if !items.is_empty() => {
+            let guid = &items[item % items.len()];
+            let location = UpdateTreeLocation::Parent {
+                guid: folders[parent % folders.len()].clone(),
+                pos: to_position(*pos),
+            };
+            update_bookmark(db, guid, &updatable(db, guid, location))
+        }
+        Op::Reorder { item, pos } if !items.is_empty() => {
+            let guid = &items[item % items.len()];
+            let location = UpdateTreeLocation::Position {
+                pos: to_position(*pos),
+            };
+            update_bookmark(db, guid, &updatable(db, guid, location))
+        }
+        Op::Delete { item } if !items.is_empty() => {
+            delete_bookmark(db, &items[item % items.len()]).map(|_| ())
+        }
+        // Nothing to move, reorder or delete.
+        _ => Ok(()),
+    }
+}
+
+fn updatable(db: &PlacesDb, guid: &SyncGuid, location: UpdateTreeLocation) -> UpdatableItem {
+    let raw = get_raw_bookmark(db, guid)
+        .expect("should work")
+        .expect("should exist");
+    match raw.bookmark_type {
+        BookmarkType::Bookmark => UpdatableBookmark {
+            location,
+            ..Default::default()
+        }
+        .into(),
+        BookmarkType::Folder => UpdatableFolder {
+            location,
+            ..Default::default()
+        }
+        .into(),
+        BookmarkType::Separator => UpdatableSeparator { location }.into(),
+    }
+}
+
+fn tree_snapshot(db: &PlacesDb) -> serde_json::Value {
+    let (tree, _, _) = fetch_tree(db, BookmarkRootGuid::Root.guid(), &FetchDepth::Deepest)
+        .expect("should fetch")
+        .expect("root should exist");
+    serde_json::to_value(tree).expect("should serialize")
+}
+
+fn check_invariants(db: &PlacesDb) {
+    // Positions are contiguous and start at zero.
+    check_positions(db);
+
+    // Every item other than the root has a parent, and it's a folder.
+    let orphans = query_guids(
+        db,
+        "SELECT b.guid FROM moz_bookmarks b
+         WHERE b.guid <> 'root________'
+           AND NOT EXISTS(SELECT 1 FROM moz_bookmarks p
+                          WHERE p.id = b.parent AND p.type = 2)",
+    );
+    assert!(orphans.is_empty(), "orphaned items: {:?}", orphans);
+
+    // Every item is reachable from the root - ie, there are no cycles.
+    let unreachable = query_guids(
+        db,
+        "WITH RECURSIVE
+         reachable(id) AS (
+            SELECT id FROM moz_bookmarks WHERE guid = 'root________'
+            UNION
+            SELECT b.id FROM moz_bookmarks b
+            JOIN reachable r ON b.parent = r.id
+         )
+         SELECT guid FROM moz_bookmarks WHERE id NOT IN reachable",
+    );
+    assert!(
+        unreachable.is_empty(),
+        "unreachable items: {:?}",
+        unreachable
+    );
+
+    // The roots are intact and in the right place.
+    let root = get_raw_bookmark(db, BookmarkRootGuid::Root.guid())
+        .expect("should work")
+        .expect("root should exist");
+    assert_eq!(root.parent_id, None);
+    for (i, guid) in USER_CONTENT_ROOTS.iter().enumerate() {
+        let raw = get_raw_bookmark(db, guid.guid())
+            .expect("should work")
+            .unwrap_or_else(|| panic!("{:?} should exist", guid));
+        assert_eq!(raw.bookmark_type, BookmarkType::Folder);
+        assert_eq!(raw.parent_id, Some(root.row_id));
+        assert_eq!(raw.position as usize, i);
+    }
+}
+
+fn sync(engine: &BookmarksSyncEngine, ts: i64) -> Vec<SyncGuid> {
+    let incoming = IncomingChangeset::new(engine.collection_name(), ServerTimestamp(ts));
+    let outgoing = engine
+        .apply_incoming(vec![incoming], &mut telemetry::Engine::new("bookmarks"))
+        .expect("should apply");
+    let guids: Vec<SyncGuid> = outgoing.changes.into_iter().map(|p| p.id).collect();
+    engine
+        .sync_finished(ServerTimestamp(ts), guids.clone())
+        .expect("should finish");
+    guids
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn prop_tree_ops_keep_invariants(ops in proptest::collection::vec(op(), 1..40)) {
+        let conn = new_mem_api().open_connection(ConnectionType::ReadWrite).unwrap();
+        for (n, op) in ops.iter().enumerate() {
+            let before = tree_snapshot(&conn);
+            if apply_op(&conn, op, n).is_err() {
+                // A failed operation must not change anything.
+                prop_assert_eq!(&before, &tree_snapshot(&conn), "failed {:?} changed the tree", op);
+            }
+            check_invariants(&conn);
+        }
+    }
+
+    #[test]
+    fn prop_sync_is_idempotent(ops in proptest::collection::vec(op(), 1..30)) {
+        let api = new_mem_api();
+        let conn = api.open_connection(ConnectionType::ReadWrite).unwrap();
+        for (n, op) in ops.iter().enumerate() {
+            let _ = apply_op(&conn, op, n);
+        }
+        let engine = BookmarksSyncEngine::new(api.get_sync_connection().unwrap()).unwrap();
+
+        // The first sync uploads everything...
+        sync(&engine, 1000);
+        let after_first = tree_snapshot(&conn);
+        check_invariants(&conn);
+
+        // ...after which syncing again is a no-op.
+        let uploaded = sync(&engine, 2000);
+        prop_assert!(uploaded.is_empty(), "second sync uploaded {:?}", uploaded);
+        prop_assert_eq!(&after_first, &tree_snapshot(&conn));
+        check_invariants(&conn);
+    }
+}
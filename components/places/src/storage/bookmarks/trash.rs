@@ -0,0 +1,225 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Bookmarks deleted with `delete_bookmark` go into the trash, so that users
+// can get them back after deleting them by mistake. Maintenance purges items
+// which have been in the trash for longer than `TRASH_RETENTION`.
+//
+// Trashed items are deleted from `moz_bookmarks`, and we keep a copy of the
+// tree they headed, instead of moving them under a hidden root. That way,
+// Sync uploads the deletions as usual, and none of the queries on the tree
+// have to skip the trash. Restoring the tree inserts the items again, with
+// their old guids, which Sync sees as new items.
+
+use super::json_tree::{fetch_tree, BookmarkTreeNode, FetchDepth};
+use super::{
+    get_raw_bookmark, insert_tree_in_tx, BookmarkPosition, BookmarkRootGuid,
+    InsertableBookmarkTree, InsertableItem,
+};
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::types::BookmarkType;
+use sql_support::ConnExt;
+use std::time::Duration;
+use sync_guid::Guid as SyncGuid;
+use types::Timestamp;
+
+/// How long items stay in the trash before maintenance purges them.
+pub const TRASH_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+// Copies `guid` and its descendants into the trash, before they're deleted.
+pub(super) fn add_to_trash(db: &PlacesDb, guid: &SyncGuid, now: Timestamp) -> Result<()> {
+    let (tree, parent_guid, position) = match fetch_tree(db, guid, &FetchDepth::Deepest)? {
+        Some((tree, Some(parent_guid), position)) => (tree, parent_guid, position),
+        // Roots can't be deleted, and missing items don't need to be trashed.
+        _ => return Ok(()),
+    };
+    db.execute_cached(
+        "INSERT OR REPLACE INTO moz_bookmarks_trash(guid, parent_guid, position, tree, date_deleted)
+         VALUES(:guid, :parent_guid, :position, :tree, :date_deleted)",
+        rusqlite::named_params! {
+            ":guid": guid,
+            ":parent_guid": parent_guid,
+            ":position": position,
+            ":tree": serde_json::to_string(&tree)?,
+            ":date_deleted": now,
+        },
+    )?;
+    Ok(())
+}
+
+/// Restores a deleted item, and its descendants, from the trash. It goes back
+/// where it was, unless its folder has been deleted too, in which case it's
+/// appended to "Other Bookmarks". Items keep their old guids, unless they've
+/// been taken since, in which case they get new ones. Returns the guid the
+/// item was restored with, or `None` if it isn't in the trash.
+pub fn restore_bookmark(db: &PlacesDb, guid: &SyncGuid) -> Result<Option<SyncGuid>> {
+    let tx = db.begin_transaction()?;
+    let result = restore_bookmark_in_tx(db, guid);
+    crate::storage::delete_pending_temp_tables(db)?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result
+}
+
+fn restore_bookmark_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<Option<SyncGuid>> {
+    let trashed = db.try_query_row(
+        "SELECT parent_guid, position, tree FROM moz_bookmarks_trash
+         WHERE guid = :guid",
+        &[(":guid", guid)],
+        |row| -> Result<_> {
+            Ok((
+                SyncGuid::from(row.get::<_, String>("parent_guid")?),
+                row.get::<_, u32>("position")?,
+                row.get::<_, String>("tree")?,
+            ))
+        },
+        true,
+    )?;
+    let (parent_guid, position, tree) = match trashed {
+        Some(trashed) => trashed,
+        None => return Ok(None),
+    };
+    let tree: BookmarkTreeNode = serde_json::from_str(&tree)?;
+    let mut item = InsertableItem::from(tree);
+    let parent_exists = match get_raw_bookmark(db, &parent_guid)? {
+        Some(parent) => {
+            parent.bookmark_type == BookmarkType::Folder
+                && parent_guid != BookmarkRootGuid::Root.as_guid()
+        }
+        None => false,
+    };
+    let parent_guid = if parent_exists {
+        item.set_position(BookmarkPosition::Specific { pos: position });
+        parent_guid
+    } else {
+        BookmarkRootGuid::Unfiled.as_guid()
+    };
+    let mappings = insert_tree_in_tx(
+        db,
+        &parent_guid,
+        InsertableBookmarkTree {
+            children: vec![item],
+        },
+    )?;
+    db.execute_cached(
+        "DELETE FROM moz_bookmarks_trash WHERE guid = :guid",
+        &[(":guid", guid)],
+    )?;
+    Ok(mappings.into_iter().next().map(|mapping| mapping.guid))
+}
+
+/// Permanently removes items deleted before `older_than` from the trash.
+/// Returns how many were removed.
+pub fn purge_trash(db: &PlacesDb, older_than: Timestamp) -> Result<u32> {
+    let purged = db.execute_cached(
+        "DELETE FROM moz_bookmarks_trash WHERE date_deleted < :older_than",
+        &[(":older_than", &older_than)],
+    )?;
+    Ok(purged as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::{
+        delete_bookmark, delete_everything, fetch::fetch_bookmark, insert_bookmark,
+        InsertableBookmark, InsertableFolder,
+    };
+    use url::Url;
+
+    fn insert_folder(db: &PlacesDb, parent_guid: SyncGuid, title: &str) -> SyncGuid {
+        insert_bookmark(
+            db,
+            InsertableFolder {
+                parent_guid,
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                title: Some(title.to_owned()),
+                children: vec![],
+            }
+            .into(),
+        )
+        .expect("should insert folder")
+    }
+
+    fn insert_url(db: &PlacesDb, parent_guid: SyncGuid, url: &str) -> SyncGuid {
+        insert_bookmark(
+            db,
+            InsertableBookmark {
+                parent_guid,
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse(url).unwrap(),
+                title: None,
+            }
+            .into(),
+        )
+        .expect("should insert bookmark")
+    }
+
+    fn child_guids(db: &PlacesDb, guid: &SyncGuid) -> Vec<SyncGuid> {
+        match fetch_tree(db, guid, &FetchDepth::Deepest).unwrap() {
+            Some((BookmarkTreeNode::Folder { f }, _, _)) => {
+                f.children.iter().map(|c| c.guid().clone()).collect()
+            }
+            _ => panic!("should be a folder"),
+        }
+    }
+
+    #[test]
+    fn test_trash() {
+        let conn = new_mem_connection();
+        let unfiled = BookmarkRootGuid::Unfiled.as_guid();
+        let first = insert_url(&conn, unfiled.clone(), "https://example.com/1");
+        let folder = insert_folder(&conn, unfiled.clone(), "folder");
+        let child = insert_url(&conn, folder.clone(), "https://example.com/2");
+        let last = insert_url(&conn, unfiled.clone(), "https://example.com/3");
+
+        // Deleting a folder trashes it along with its children.
+        assert!(delete_bookmark(&conn, &folder).expect("should delete"));
+        assert!(fetch_bookmark(&conn, &child, false).unwrap().is_none());
+        assert_eq!(
+            child_guids(&conn, &unfiled),
+            vec![first.clone(), last.clone()]
+        );
+
+        assert_eq!(
+            restore_bookmark(&conn, &folder).expect("should restore"),
+            Some(folder.clone())
+        );
+        assert_eq!(
+            child_guids(&conn, &unfiled),
+            vec![first, folder.clone(), last.clone()]
+        );
+        assert_eq!(child_guids(&conn, &folder), vec![child.clone()]);
+        // It's no longer in the trash.
+        assert_eq!(restore_bookmark(&conn, &folder).unwrap(), None);
+
+        // If its folder has gone too, a restored item goes into unfiled.
+        delete_bookmark(&conn, &child).expect("should delete");
+        delete_bookmark(&conn, &folder).expect("should delete");
+        restore_bookmark(&conn, &child).expect("should restore");
+        assert_eq!(child_guids(&conn, &unfiled).last(), Some(&child));
+
+        // Purging only removes items deleted before the cutoff.
+        delete_bookmark(&conn, &last).expect("should delete");
+        let later = Timestamp(Timestamp::now().as_millis() + 1);
+        assert_eq!(purge_trash(&conn, Timestamp(0)).unwrap(), 0);
+        assert_eq!(purge_trash(&conn, later).unwrap(), 2);
+        assert_eq!(restore_bookmark(&conn, &last).unwrap(), None);
+
+        // Erasing everything empties the trash.
+        delete_bookmark(&conn, &child).expect("should delete");
+        delete_everything(&conn).expect("should delete everything");
+        assert_eq!(restore_bookmark(&conn, &child).unwrap(), None);
+    }
+}
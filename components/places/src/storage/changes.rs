@@ -0,0 +1,224 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Apps which keep their own index of pages and bookmarks, like the iOS
+// Spotlight index, can ask which ones changed since they last looked,
+// instead of walking everything again. Triggers record the changes in
+// `moz_changes`, with a sequence number that only goes up.
+//
+// An app starts by noting `get_current_change_seq`, and then indexing
+// everything. After that, `get_changes_since` the last sequence number it saw
+// returns what to update. Changes made before `moz_changes` was added aren't
+// recorded, so an app needs to index everything once.
+//
+// Each page and bookmark only has one row, so the table doesn't grow with
+// the number of changes - except for deleted items, which would stay
+// forever. Maintenance forgets them once they've been deleted for longer
+// than `DELETED_CHANGE_RETENTION`, so an app which hasn't looked for that
+// long should index everything again.
+
+use crate::db::PlacesDb;
+use crate::error::*;
+use sql_support::ConnExt;
+use std::time::Duration;
+use sync_guid::Guid as SyncGuid;
+use types::Timestamp;
+
+const KIND_PAGE: u8 = 1;
+const KIND_BOOKMARK: u8 = 2;
+
+/// How long maintenance remembers deleted pages and bookmarks for.
+pub const DELETED_CHANGE_RETENTION: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+/// The pages and bookmarks which changed after a sequence number.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangesSince {
+    /// The sequence number of the last change, to pass to the next call.
+    pub seq: i64,
+    pub changed_pages: Vec<SyncGuid>,
+    pub deleted_pages: Vec<SyncGuid>,
+    pub changed_bookmarks: Vec<SyncGuid>,
+    pub deleted_bookmarks: Vec<SyncGuid>,
+}
+
+/// Returns the sequence number of the last change, or 0 if nothing has
+/// changed yet.
+pub fn get_current_change_seq(db: &PlacesDb) -> Result<i64> {
+    Ok(db.query_one("SELECT IFNULL(MAX(seq), 0) FROM moz_changes")?)
+}
+
+/// Returns the pages and bookmarks which changed after `seq`, in the order
+/// they last changed. Items which were deleted after they changed are only
+/// returned as deleted.
+pub fn get_changes_since(db: &PlacesDb, seq: i64) -> Result<ChangesSince> {
+    let mut changes = ChangesSince {
+        seq,
+        ..ChangesSince::default()
+    };
+    let rows = db.query_rows_and_then_cached(
+        "SELECT seq, guid, kind, is_deleted FROM moz_changes
+         WHERE seq > :seq
+         ORDER BY seq",
+        &[(":seq", &seq)],
+        |row| -> rusqlite::Result<_> {
+            Ok((
+                row.get::<_, i64>("seq")?,
+                row.get::<_, SyncGuid>("guid")?,
+                row.get::<_, u8>("kind")?,
+                row.get::<_, bool>("is_deleted")?,
+            ))
+        },
+    )?;
+    for (row_seq, guid, kind, is_deleted) in rows {
+        changes.seq = row_seq;
+        let guids = match (kind, is_deleted) {
+            (KIND_PAGE, false) => &mut changes.changed_pages,
+            (KIND_PAGE, true) => &mut changes.deleted_pages,
+            (KIND_BOOKMARK, false) => &mut changes.changed_bookmarks,
+            (KIND_BOOKMARK, true) => &mut changes.deleted_bookmarks,
+            _ => continue,
+        };
+        guids.push(guid);
+    }
+    Ok(changes)
+}
+
+/// Forgets the pages and bookmarks which were deleted before `older_than`.
+pub fn prune_deleted_changes(db: &PlacesDb, older_than: Timestamp) -> Result<()> {
+    db.execute_cached(
+        "DELETE FROM moz_changes
+         WHERE is_deleted AND date_changed < :older_than",
+        &[(":older_than", &older_than)],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+    use crate::storage::bookmarks::{
+        delete_bookmark, insert_bookmark, update_bookmark, BookmarkPosition, BookmarkRootGuid,
+        InsertableBookmark, UpdatableBookmark,
+    };
+    use crate::storage::fetch_page_info;
+    use crate::storage::history::{apply_observation, delete_visits_for};
+    use crate::types::VisitTransition;
+    use url::Url;
+
+    #[test]
+    fn test_changes_since() -> Result<()> {
+        let conn = new_mem_connection();
+        let start = get_current_change_seq(&conn)?;
+        assert_eq!(get_changes_since(&conn, start)?.seq, start);
+
+        let url = Url::parse("https://example.com/").unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone()).with_visit_type(VisitTransition::Link),
+        )?;
+        let page_guid = fetch_page_info(&conn, &url)?
+            .expect("should exist")
+            .page
+            .guid;
+        let bookmark_guid = insert_bookmark(
+            &conn,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: url.clone(),
+                title: None,
+            }
+            .into(),
+        )?;
+        let changes = get_changes_since(&conn, start)?;
+        assert_eq!(changes.changed_pages, vec![page_guid.clone()]);
+        assert_eq!(changes.changed_bookmarks, vec![bookmark_guid.clone()]);
+        assert!(changes.deleted_pages.is_empty());
+        assert_eq!(changes.seq, get_current_change_seq(&conn)?);
+
+        // More visits to the page don't change anything we index.
+        let seq = changes.seq;
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone()).with_visit_type(VisitTransition::Link),
+        )?;
+        assert_eq!(
+            get_changes_since(&conn, seq)?,
+            ChangesSince {
+                seq,
+                ..ChangesSince::default()
+            }
+        );
+
+        // Retitling the bookmark does.
+        update_bookmark(
+            &conn,
+            &bookmark_guid,
+            &UpdatableBookmark {
+                title: Some("Example".into()),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        let changes = get_changes_since(&conn, seq)?;
+        assert_eq!(changes.changed_bookmarks, vec![bookmark_guid.clone()]);
+        assert!(changes.changed_pages.is_empty());
+
+        // Deleted items are only returned as deleted.
+        delete_bookmark(&conn, &bookmark_guid)?;
+        delete_visits_for(&conn, &page_guid)?;
+        let changes = get_changes_since(&conn, start)?;
+        assert!(changes.changed_pages.is_empty());
+        assert!(changes.changed_bookmarks.is_empty());
+        assert_eq!(changes.deleted_pages, vec![page_guid]);
+        assert_eq!(changes.deleted_bookmarks, vec![bookmark_guid]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_deleted_changes() -> Result<()> {
+        let conn = new_mem_connection();
+        let start = get_current_change_seq(&conn)?;
+        let insert = |url: &str| {
+            insert_bookmark(
+                &conn,
+                InsertableBookmark {
+                    parent_guid: BookmarkRootGuid::Unfiled.into(),
+                    position: BookmarkPosition::Append,
+                    date_added: None,
+                    last_modified: None,
+                    guid: None,
+                    url: Url::parse(url).unwrap(),
+                    title: None,
+                }
+                .into(),
+            )
+        };
+        let kept = insert("https://example.com/kept")?;
+        let deleted = insert("https://example.com/deleted")?;
+        delete_bookmark(&conn, &deleted)?;
+
+        // Recent deletions are remembered.
+        let before = Timestamp(Timestamp::now().as_millis() - 1000);
+        prune_deleted_changes(&conn, before)?;
+        assert_eq!(
+            get_changes_since(&conn, start)?.deleted_bookmarks,
+            vec![deleted.clone()]
+        );
+
+        // Old ones are forgotten, but items which still exist are kept, however
+        // old their changes are.
+        let after = Timestamp(Timestamp::now().as_millis() + 1000);
+        prune_deleted_changes(&conn, after)?;
+        let changes = get_changes_since(&conn, start)?;
+        assert!(changes.deleted_bookmarks.is_empty());
+        assert!(changes.changed_bookmarks.contains(&kept));
+        Ok(())
+    }
+}
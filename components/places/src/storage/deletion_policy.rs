@@ -0,0 +1,193 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Support for "automatically delete browsing data" settings. The app
+// registers a policy, which we store in the database, and every maintenance
+// run enforces it. This means the data is deleted even if the app was killed
+// before it got a chance to clean up when it quit.
+//
+// The policy is for this device only, so nothing here writes tombstones: the
+// deletions aren't synced to other devices.
+
+use super::{delete_meta, get_meta, history, history_metadata, put_meta};
+use crate::db::PlacesDb;
+use crate::error::*;
+use serde_derive::*;
+use sql_support::ConnExt;
+use types::Timestamp;
+
+const DELETION_POLICY_META_KEY: &str = "history_deletion_policy";
+
+const ONE_DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// What to delete each time maintenance runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryDeletionPolicy {
+    /// Delete things older than this many days. `Some(0)` deletes
+    /// everything from before maintenance runs, for "delete browsing data on
+    /// quit" settings. If `None`, nothing is deleted.
+    pub max_age_days: Option<u32>,
+    /// Visits, and the pages which no longer have any visits or bookmarks.
+    pub history: bool,
+    /// History metadata (view times, search terms, etc).
+    pub metadata: bool,
+    /// Preview images for pages.
+    pub previews: bool,
+}
+
+/// Sets the policy enforced by `run_maintenance_prune`. `None` removes any
+/// existing policy.
+pub fn set_deletion_policy(db: &PlacesDb, policy: Option<&HistoryDeletionPolicy>) -> Result<()> {
+    match policy {
+        Some(policy) => put_meta(
+            db,
+            DELETION_POLICY_META_KEY,
+            &serde_json::to_string(policy)?,
+        ),
+        None => delete_meta(db, DELETION_POLICY_META_KEY),
+    }
+}
+
+pub fn get_deletion_policy(db: &PlacesDb) -> Result<Option<HistoryDeletionPolicy>> {
+    get_meta::<String>(db, DELETION_POLICY_META_KEY)?
+        .map(|json| Ok(serde_json::from_str(&json)?))
+        .transpose()
+}
+
+/// Deletes whatever the registered policy says should be deleted. Returns
+/// whether there was a policy to enforce.
+pub fn enforce_deletion_policy(db: &PlacesDb, now: Timestamp) -> Result<bool> {
+    let policy = match get_deletion_policy(db)? {
+        Some(policy) => policy,
+        None => return Ok(false),
+    };
+    let cutoff = match policy.max_age_days {
+        Some(days) => Timestamp(now.0.saturating_sub(u64::from(days) * ONE_DAY_MS)),
+        None => return Ok(true),
+    };
+    if policy.history {
+        history::prune_visits_before_locally(db, cutoff)?;
+    }
+    if policy.metadata {
+        history_metadata::delete_older_than(db, cutoff.as_millis_i64())?;
+    }
+    if policy.previews {
+        db.execute_cached(
+            "UPDATE moz_places SET preview_image_url = NULL
+             WHERE preview_image_url IS NOT NULL
+               AND MAX(last_visit_date_local, last_visit_date_remote) < :cutoff",
+            &[(":cutoff", &cutoff.as_millis_i64())],
+        )?;
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+    use crate::storage::fetch_page_info;
+    use crate::types::VisitTransition;
+    use url::Url;
+
+    fn visit(conn: &PlacesDb, url: &Url, at: Timestamp) {
+        history::apply_observation(
+            conn,
+            VisitObservation::new(url.clone())
+                .with_at(at)
+                .with_visit_type(VisitTransition::Link)
+                .with_preview_image_url(Url::parse("https://example.com/preview.png").unwrap()),
+        )
+        .expect("should apply");
+    }
+
+    #[test]
+    fn test_deletion_policy() {
+        let conn = new_mem_connection();
+        let now = Timestamp::now();
+        assert_eq!(get_deletion_policy(&conn).expect("should work"), None);
+        assert!(!enforce_deletion_policy(&conn, now).expect("should work"));
+
+        let old = Url::parse("https://example.com/old").unwrap();
+        let new = Url::parse("https://example.com/new").unwrap();
+        visit(&conn, &old, Timestamp(now.0 - 10 * ONE_DAY_MS));
+        visit(&conn, &new, Timestamp(now.0 - 1000));
+
+        // Only previews older than a week.
+        let policy = HistoryDeletionPolicy {
+            max_age_days: Some(7),
+            previews: true,
+            ..Default::default()
+        };
+        set_deletion_policy(&conn, Some(&policy)).expect("should set");
+        assert_eq!(
+            get_deletion_policy(&conn).expect("should work"),
+            Some(policy)
+        );
+        assert!(enforce_deletion_policy(&conn, now).expect("should work"));
+        let old_info = fetch_page_info(&conn, &old)
+            .expect("should work")
+            .expect("should still exist");
+        assert_eq!(old_info.page.preview_image_url, None);
+        let new_info = fetch_page_info(&conn, &new)
+            .expect("should work")
+            .expect("should exist");
+        assert!(new_info.page.preview_image_url.is_some());
+
+        // Without a maximum age, nothing is deleted.
+        set_deletion_policy(
+            &conn,
+            Some(&HistoryDeletionPolicy {
+                history: true,
+                ..Default::default()
+            }),
+        )
+        .expect("should set");
+        enforce_deletion_policy(&conn, now).expect("should work");
+        assert!(fetch_page_info(&conn, &old).expect("should work").is_some());
+
+        // History older than a week. The page was synced, but the deletion
+        // is local, so it doesn't get a tombstone.
+        conn.execute_batch("UPDATE moz_places SET sync_status = 2")
+            .expect("should mark as synced");
+        set_deletion_policy(
+            &conn,
+            Some(&HistoryDeletionPolicy {
+                max_age_days: Some(7),
+                history: true,
+                ..Default::default()
+            }),
+        )
+        .expect("should set");
+        enforce_deletion_policy(&conn, now).expect("should work");
+        assert!(fetch_page_info(&conn, &old).expect("should work").is_none());
+        assert!(fetch_page_info(&conn, &new).expect("should work").is_some());
+        for table in ["moz_places_tombstones", "moz_historyvisit_tombstones"] {
+            assert_eq!(
+                conn.query_one::<i64>(&format!("SELECT COUNT(*) FROM {}", table))
+                    .expect("should work"),
+                0,
+                "{} should be empty",
+                table
+            );
+        }
+
+        // A maximum age of 0 days deletes everything, for "delete on quit".
+        set_deletion_policy(
+            &conn,
+            Some(&HistoryDeletionPolicy {
+                max_age_days: Some(0),
+                history: true,
+                ..Default::default()
+            }),
+        )
+        .expect("should set");
+        enforce_deletion_policy(&conn, now).expect("should work");
+        assert!(fetch_page_info(&conn, &new).expect("should work").is_none());
+
+        set_deletion_policy(&conn, None).expect("should remove");
+        assert_eq!(get_deletion_policy(&conn).expect("should work"), None);
+    }
+}
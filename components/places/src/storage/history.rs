@@ -7,13 +7,17 @@ mod actions;
 use super::{fetch_page_info, new_page_info, PageInfo, RowId};
 use crate::db::PlacesDb;
 use crate::error::Result;
-use crate::ffi::{HistoryVisitInfo, HistoryVisitInfosWithBound, TopFrecentSiteInfo};
+use crate::ffi::{
+    HistoryVisitDetails, HistoryVisitInfo, HistoryVisitInfosWithBound, HostInfo,
+    TopFrecentSiteInfo, TopFrecentSiteOptions, TopSitePrefetchHint, TypedUrlInfo, VisitCountForDay,
+};
 use crate::frecency;
 use crate::hash;
 use crate::history_sync::engine::{
     COLLECTION_SYNCID_META_KEY, GLOBAL_SYNCID_META_KEY, LAST_SYNC_META_KEY,
 };
 use crate::observation::VisitObservation;
+use crate::observer::{self, PlacesChange};
 use crate::storage::{
     delete_meta, delete_pending_temp_tables, get_meta, history_metadata, put_meta,
 };
@@ -24,7 +28,7 @@ use rusqlite::types::ToSql;
 use rusqlite::Result as RusqliteResult;
 use rusqlite::Row;
 use sql_support::{self, ConnExt};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 use sync15::engine::EngineSyncAssociation;
 use sync_guid::Guid as SyncGuid;
@@ -37,7 +41,7 @@ use url::Url;
 ///
 /// This allows us to avoid these visits trickling back in as other devices
 /// add visits to them remotely.
-static DELETION_HIGH_WATER_MARK_META_KEY: &str = "history_deleted_hwm";
+pub(crate) static DELETION_HIGH_WATER_MARK_META_KEY: &str = "history_deleted_hwm";
 
 /// Returns the RowId of a new visit in moz_historyvisits, or None if no new visit was added.
 pub fn apply_observation(db: &PlacesDb, visit_ob: VisitObservation) -> Result<Option<RowId>> {
@@ -50,14 +54,57 @@ pub fn apply_observation(db: &PlacesDb, visit_ob: VisitObservation) -> Result<Op
     Ok(result)
 }
 
+/// Applies many observations in a single transaction - eg, when restoring
+/// history. Frecencies are recalculated once for each page at the end,
+/// rather than after every visit. Returns the number of visits added.
+pub fn apply_observations(db: &PlacesDb, visit_obs: Vec<VisitObservation>) -> Result<u32> {
+    breadcrumb!("apply_observations: begin_transaction");
+    let tx = db.begin_transaction()?;
+    let mut num_visits = 0;
+    // The pages which need their frecency updated, and the redirect boost
+    // from the last observation for each.
+    let mut stale_pages: HashMap<RowId, bool> = HashMap::new();
+    for visit_ob in &visit_obs {
+        let (visit_row_id, stale_page) = apply_observation_without_frecency(db, visit_ob)?;
+        if visit_row_id.is_some() {
+            num_visits += 1;
+        }
+        if let Some(page_id) = stale_page {
+            stale_pages.insert(page_id, visit_ob.get_redirect_frecency_boost());
+        }
+    }
+    for (page_id, redirect_boost) in stale_pages {
+        update_frecency(db, page_id, Some(redirect_boost))?;
+    }
+    delete_pending_temp_tables(db)?;
+    tx.commit()?;
+    breadcrumb!("apply_observations: commit");
+    Ok(num_visits)
+}
+
 /// Returns the RowId of a new visit in moz_historyvisits, or None if no new visit was added.
 pub fn apply_observation_direct(
     db: &PlacesDb,
     visit_ob: VisitObservation,
 ) -> Result<Option<RowId>> {
-    // Don't insert urls larger than our length max.
-    if visit_ob.url.as_str().len() > super::URL_LENGTH_MAX {
-        return Ok(None);
+    let (visit_row_id, stale_page) = apply_observation_without_frecency(db, &visit_ob)?;
+    // This needs to happen after the other updates.
+    if let Some(page_id) = stale_page {
+        update_frecency(db, page_id, Some(visit_ob.get_redirect_frecency_boost()))?;
+    }
+    Ok(visit_row_id)
+}
+
+/// Applies an observation, except for updating the page's frecency. Returns
+/// the RowId of the new visit, if one was added, and the page, if its
+/// frecency needs to be updated.
+fn apply_observation_without_frecency(
+    db: &PlacesDb,
+    visit_ob: &VisitObservation,
+) -> Result<(Option<RowId>, Option<RowId>)> {
+    // Don't insert urls we don't record, like ones larger than our length max.
+    if !super::can_record_history(&visit_ob.url) {
+        return Ok((None, None));
     }
     // Make sure we have a valid preview URL - it should parse, and not exceed max size.
     // In case the URL is too long, ignore it and proceed with the rest of the observation.
@@ -108,7 +155,27 @@ pub fn apply_observation_direct(
 
             let at = visit_ob.at.unwrap_or_else(Timestamp::now);
             let is_remote = visit_ob.is_remote.unwrap_or(false);
-            let row_id = add_visit(db, page_info.row_id, None, at, visit_type, !is_remote)?;
+            let from_visit = match visit_ob.referrer {
+                Some(ref referrer) => find_referring_visit(db, referrer, at)?,
+                None => None,
+            };
+            let row_id = add_visit(
+                db,
+                page_info.row_id,
+                from_visit,
+                at,
+                visit_type,
+                !is_remote,
+                visit_ob.container_id.as_deref(),
+            )?;
+            observer::note(
+                db,
+                PlacesChange::VisitAdded {
+                    url: visit_ob.url.clone(),
+                    visit_time: at,
+                    visit_type,
+                },
+            );
             // a new visit implies new frecency except in error cases.
             if !visit_ob.is_error.unwrap_or(false) {
                 update_frec = true;
@@ -144,21 +211,13 @@ pub fn apply_observation_direct(
         );
         db.execute(&sql, &params[..])?;
     }
-    // This needs to happen after the other updates.
-    if update_frec {
-        update_frecency(
-            db,
-            page_info.row_id,
-            Some(visit_ob.get_redirect_frecency_boost()),
-        )?;
-    }
-    Ok(visit_row_id)
+    Ok((visit_row_id, update_frec.then(|| page_info.row_id)))
 }
 
 pub fn update_frecency(db: &PlacesDb, id: RowId, redirect_boost: Option<bool>) -> Result<()> {
     let score = frecency::calculate_frecency(
         db.conn(),
-        &frecency::DEFAULT_FRECENCY_SETTINGS,
+        db.frecency_settings(),
         id.0, // TODO: calculate_frecency should take a RowId here.
         redirect_boost,
     )?;
@@ -177,6 +236,25 @@ pub fn update_frecency(db: &PlacesDb, id: RowId, redirect_boost: Option<bool>) -
     Ok(())
 }
 
+/// Sets the preview image of the page at `url`, replacing any it already had.
+/// Returns false if we don't know about the page, or if `preview_image_url` is
+/// too long to store. Unlike observing a preview image, this never adds a page.
+pub fn update_preview_image(db: &PlacesDb, url: &Url, preview_image_url: &Url) -> Result<bool> {
+    if preview_image_url.as_str().len() > super::URL_LENGTH_MAX {
+        return Ok(false);
+    }
+    let changes = db.execute_cached(
+        "UPDATE moz_places
+         SET preview_image_url = :preview_image_url
+         WHERE url_hash = hash(:url) AND url = :url",
+        &[
+            (":preview_image_url", &preview_image_url.as_str()),
+            (":url", &url.as_str()),
+        ],
+    )?;
+    Ok(changes > 0)
+}
+
 /// Indicates if and when a URL's frecency was marked as stale.
 pub fn frecency_stale_at(db: &PlacesDb, url: &Url) -> Result<Option<Timestamp>> {
     let result = db.try_query_row(
@@ -191,6 +269,23 @@ pub fn frecency_stale_at(db: &PlacesDb, url: &Url) -> Result<Option<Timestamp>>
     Ok(result)
 }
 
+// Returns the most recent visit to `referrer` at or before `at`, which we take
+// to be the visit that led to a new visit at `at`.
+fn find_referring_visit(db: &PlacesDb, referrer: &Url, at: Timestamp) -> Result<Option<RowId>> {
+    Ok(db.try_query_row(
+        "SELECT v.id
+         FROM moz_historyvisits v
+         JOIN moz_places h ON h.id = v.place_id
+         WHERE h.url_hash = hash(:url) AND h.url = :url
+           AND v.visit_date <= :at
+         ORDER BY v.visit_date DESC
+         LIMIT 1",
+        &[(":url", &referrer.as_str() as &dyn ToSql), (":at", &at)],
+        |row| -> RusqliteResult<_> { row.get::<_, RowId>(0) },
+        true,
+    )?)
+}
+
 // Add a single visit - you must know the page rowid. Does not update the
 // page info - if you are calling this, you will also need to update the
 // parent page with an updated change counter etc.
@@ -201,10 +296,11 @@ fn add_visit(
     visit_date: Timestamp,
     visit_type: VisitTransition,
     is_local: bool,
+    container_id: Option<&str>,
 ) -> Result<RowId> {
     let sql = "INSERT INTO moz_historyvisits
-            (from_visit, place_id, visit_date, visit_type, is_local)
-        VALUES (:from_visit, :page_id, :visit_date, :visit_type, :is_local)";
+            (from_visit, place_id, visit_date, visit_type, is_local, container_id)
+        VALUES (:from_visit, :page_id, :visit_date, :visit_type, :is_local, :container_id)";
     db.execute_cached(
         sql,
         &[
@@ -213,6 +309,7 @@ fn add_visit(
             (":visit_date", &visit_date),
             (":visit_type", &visit_type),
             (":is_local", &is_local),
+            (":container_id", &container_id),
         ],
     )?;
     let rid = db.conn().last_insert_rowid();
@@ -251,6 +348,15 @@ pub fn href_to_guid(db: &PlacesDb, url: &str) -> Result<Option<SyncGuid>> {
 /// Internal function for deleting a page, creating a tombstone if necessary.
 /// Assumes a transaction is already set up by the caller.
 fn delete_visits_for_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
+    delete_page_history_in_tx(db, guid)?;
+    delete_pending_temp_tables(db)?;
+    Ok(())
+}
+
+// Does the work of `delete_visits_for_in_tx`, but leaves the origin updates
+// staged in the temp tables, so that callers deleting many pages can apply
+// them once at the end.
+fn delete_page_history_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
     // We only create tombstones for history which exists and with sync_status
     // == SyncStatus::Normal
     let to_clean = db.conn().try_query_row(
@@ -319,7 +425,6 @@ fn delete_visits_for_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
         }
         None => {}
     }
-    delete_pending_temp_tables(db)?;
     Ok(())
 }
 
@@ -374,17 +479,173 @@ fn delete_page(db: &PlacesDb, page_id: RowId) -> Result<()> {
 pub fn delete_visits_for(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
     breadcrumb!("delete_visits_for: begin_transaction");
     let tx = db.begin_transaction()?;
+    note_page_deleted(db, guid)?;
     let result = delete_visits_for_in_tx(db, guid);
     tx.commit()?;
     breadcrumb!("delete_visits_for: commit");
     result
 }
 
+/// Deletes all history for pages on `host` and its subdomains, so that
+/// deleting `example.com` also deletes `www.example.com`, whatever their
+/// scheme or port. Like `delete_visits_for`, this deletes the pages' visits
+/// and history metadata, and the pages themselves unless they're bookmarked,
+/// writing tombstones so the deletions are synced.
+///
+/// We don't store icons, so apps should delete any they keep for the host
+/// themselves.
+pub fn delete_visits_for_host(db: &PlacesDb, host: &str) -> Result<()> {
+    let host = match normalize_host(host) {
+        Some(host) => host,
+        None => {
+            log::warn!("Invalid host passed to delete_visits_for_host");
+            return Ok(());
+        }
+    };
+    breadcrumb!("delete_visits_for_host: begin_transaction");
+    let tx = db.begin_transaction()?;
+    let origins = db.query_rows_and_then(
+        "SELECT id, host FROM moz_origins",
+        [],
+        |row| -> rusqlite::Result<_> { Ok((row.get::<_, RowId>(0)?, row.get::<_, String>(1)?)) },
+    )?;
+    let origin_ids: Vec<RowId> = origins
+        .into_iter()
+        .filter(|(_, origin_host)| is_host_or_subdomain(strip_port(origin_host), &host))
+        .map(|(id, _)| id)
+        .collect();
+    let mut guids = Vec::new();
+    sql_support::each_chunk(&origin_ids, |chunk, _| -> Result<()> {
+        guids.extend(db.query_rows_and_then(
+            &format!(
+                "SELECT guid FROM moz_places WHERE origin_id IN ({})",
+                sql_support::repeat_sql_vars(chunk.len()),
+            ),
+            rusqlite::params_from_iter(chunk),
+            |row| row.get::<_, SyncGuid>(0),
+        )?);
+        Ok(())
+    })?;
+    delete_visits_for_pages_in_tx(db, &guids)?;
+    tx.commit()?;
+    breadcrumb!("delete_visits_for_host: commit");
+    Ok(())
+}
+
+/// Like `delete_visits_for_host`, but only deletes history for pages with
+/// the same scheme, host and port as `origin`. Subdomains aren't included.
+pub fn delete_everything_for_origin(db: &PlacesDb, origin: &Url) -> Result<()> {
+    breadcrumb!("delete_everything_for_origin: begin_transaction");
+    let tx = db.begin_transaction()?;
+    let guids = db.query_rows_and_then(
+        "SELECT h.guid
+         FROM moz_places h
+         JOIN moz_origins o ON o.id = h.origin_id
+         WHERE o.prefix = get_prefix(:url)
+           AND o.host = get_host_and_port(:url)",
+        &[(":url", &origin.as_str())],
+        |row| row.get::<_, SyncGuid>(0),
+    )?;
+    delete_visits_for_pages_in_tx(db, &guids)?;
+    tx.commit()?;
+    breadcrumb!("delete_everything_for_origin: commit");
+    Ok(())
+}
+
+/// Deletes all history for each of `urls`, like `delete_visits_for` does for
+/// one page, but in a single transaction, and updating the pages' origins
+/// once at the end. URLs we don't have history for are ignored.
+pub fn delete_visits_for_urls(db: &PlacesDb, urls: &[String]) -> Result<()> {
+    // Like the URLs we store, the ones we're given should be normalized, but
+    // we match invalid ones as they are, like `href_to_guid`.
+    let urls: Vec<String> = urls
+        .iter()
+        .map(|url| match Url::parse(url) {
+            Ok(url) => url.into(),
+            Err(e) => {
+                log::warn!("Invalid URL passed to delete_visits_for_urls, {}", e);
+                url.clone()
+            }
+        })
+        .collect();
+    breadcrumb!("delete_visits_for_urls: begin_transaction");
+    let tx = db.begin_transaction()?;
+    let mut guids = Vec::new();
+    sql_support::each_chunk(&urls, |chunk, _| -> Result<()> {
+        // Each URL is used twice, so we number the variables.
+        let vars = (1..=chunk.len()).map(|i| format!("?{}", i));
+        guids.extend(db.query_rows_and_then(
+            &format!(
+                "SELECT guid FROM moz_places
+                 WHERE url_hash IN ({}) AND url IN ({})",
+                vars.clone()
+                    .map(|var| format!("hash({})", var))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                vars.collect::<Vec<_>>().join(","),
+            ),
+            rusqlite::params_from_iter(chunk),
+            |row| row.get::<_, SyncGuid>(0),
+        )?);
+        Ok(())
+    })?;
+    delete_visits_for_pages_in_tx(db, &guids)?;
+    tx.commit()?;
+    breadcrumb!("delete_visits_for_urls: commit");
+    Ok(())
+}
+
+fn delete_visits_for_pages_in_tx(db: &PlacesDb, guids: &[SyncGuid]) -> Result<()> {
+    for guid in guids {
+        note_page_deleted(db, guid)?;
+        delete_page_history_in_tx(db, guid)?;
+    }
+    delete_pending_temp_tables(db)?;
+    Ok(())
+}
+
+// Parsing a host as part of a URL normalizes its case and encoding, in the
+// same way as the URLs we store.
+fn normalize_host(host: &str) -> Option<String> {
+    let url = Url::parse(&format!("http://{}/", host.trim_end_matches('.'))).ok()?;
+    url.host_str().map(str::to_string)
+}
+
+// `moz_origins.host` includes the port, if it isn't the default.
+fn strip_port(host_and_port: &str) -> &str {
+    match host_and_port.rsplit_once(':') {
+        Some((host, port)) if !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()) => host,
+        _ => host_and_port,
+    }
+}
+
+fn is_host_or_subdomain(candidate: &str, host: &str) -> bool {
+    candidate == host
+        || candidate
+            .strip_suffix(host)
+            .map_or(false, |subdomain| subdomain.ends_with('.'))
+}
+
+fn note_page_deleted(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
+    let url = db.try_query_one::<String, _>(
+        "SELECT url FROM moz_places WHERE guid = :guid",
+        &[(":guid", guid)],
+        true,
+    )?;
+    // We validate URLs before they're added, so this should never skip
+    // anything.
+    if let Some(url) = url.and_then(|url| Url::parse(&url).ok()) {
+        observer::note(db, PlacesChange::PageDeleted { url });
+    }
+    Ok(())
+}
+
 /// Delete all visits in a date range.
 pub fn delete_visits_between(db: &PlacesDb, start: Timestamp, end: Timestamp) -> Result<()> {
     breadcrumb!("delete_visits_between: begin_transaction");
     let tx = db.begin_transaction()?;
     delete_visits_between_in_tx(db, start, end)?;
+    observer::note(db, PlacesChange::VisitsDeletedBetween { start, end });
     tx.commit()?;
     breadcrumb!("delete_visits_between: commit");
     Ok(())
@@ -402,6 +663,15 @@ pub fn delete_place_visit_at_time_by_href(
     breadcrumb!("delete_place_visit_at_time_by_href: begin_transaction");
     let tx = db.begin_transaction()?;
     delete_place_visit_at_time_in_tx(db, place, visit)?;
+    if let Ok(url) = Url::parse(place) {
+        observer::note(
+            db,
+            PlacesChange::VisitDeleted {
+                url,
+                visit_time: visit,
+            },
+        );
+    }
     tx.commit()?;
     breadcrumb!("delete_place_visit_at_time_by_href: commit");
     Ok(())
@@ -412,21 +682,77 @@ pub fn prune_destructively(db: &PlacesDb) -> Result<()> {
     wipe_local(db)
 }
 
-pub fn prune_older_visits(db: &PlacesDb) -> Result<()> {
+/// Prunes some of the oldest visits, and returns the number of pages which
+/// were removed because they had no visits left.
+pub fn prune_older_visits(db: &PlacesDb) -> Result<u32> {
     breadcrumb!("prune_older_visits: begin_transaction");
     let tx = db.begin_transaction()?;
     // Prune 6 items at a time, which matches desktops "small limit" value
     let limit: usize = 6;
 
-    let result = DbAction::apply_all(
-        db,
-        db_actions_from_visits_to_delete(find_visits_to_prune(db, limit, Timestamp::now())?),
-    );
+    let visits_to_delete = find_visits_to_prune(db, limit, Timestamp::now())?;
+    let page_ids: HashSet<RowId> = visits_to_delete.iter().map(|v| v.page_id).collect();
+    let result = DbAction::apply_all(db, db_actions_from_visits_to_delete(visits_to_delete))
+        .and_then(|_| count_missing_pages(db, &page_ids));
     tx.commit()?;
     breadcrumb!("prune_older_visits: commit");
     result
 }
 
+/// Deletes the visits before `cutoff`, and the pages left without visits or
+/// bookmarks, for local retention policies. Unlike the other ways of deleting
+/// history, this doesn't write tombstones, so the deletions aren't synced to
+/// other devices.
+pub fn prune_visits_before_locally(db: &PlacesDb, cutoff: Timestamp) -> Result<()> {
+    breadcrumb!("prune_visits_before_locally: begin_transaction");
+    let tx = db.begin_transaction()?;
+    let page_ids: Vec<RowId> = db.query_rows_and_then(
+        "SELECT DISTINCT place_id FROM moz_historyvisits WHERE visit_date < :cutoff",
+        &[(":cutoff", &cutoff)],
+        |row| row.get(0),
+    )?;
+    db.execute_cached(
+        "DELETE FROM moz_historyvisits WHERE visit_date < :cutoff",
+        &[(":cutoff", &cutoff)],
+    )?;
+    sql_support::each_chunk(&page_ids, |chunk, _| -> Result<()> {
+        let pages: Vec<PageToClean> = db.query_rows_and_then(
+            &format!(
+                "SELECT id,
+                    (foreign_count != 0) AS has_foreign,
+                    ((last_visit_date_local + last_visit_date_remote) != 0) as has_visits,
+                    sync_status
+                FROM moz_places
+                WHERE id IN ({})",
+                sql_support::repeat_sql_vars(chunk.len()),
+            ),
+            rusqlite::params_from_iter(chunk),
+            PageToClean::from_row,
+        )?;
+        cleanup_pages_impl(db, &pages, false)
+    })?;
+    delete_pending_temp_tables(db)?;
+    tx.commit()?;
+    breadcrumb!("prune_visits_before_locally: commit");
+    Ok(())
+}
+
+fn count_missing_pages(db: &PlacesDb, page_ids: &HashSet<RowId>) -> Result<u32> {
+    let mut missing = 0;
+    for page_id in page_ids {
+        let exists = db.query_row_and_then_cachable(
+            "SELECT EXISTS(SELECT 1 FROM moz_places WHERE id = :id)",
+            &[(":id", page_id)],
+            |row| row.get::<_, bool>(0),
+            true,
+        )?;
+        if !exists {
+            missing += 1;
+        }
+    }
+    Ok(missing)
+}
+
 fn find_visits_to_prune(db: &PlacesDb, limit: usize, now: Timestamp) -> Result<Vec<VisitToDelete>> {
     // Start with the exotic visits
     let mut to_delete: HashSet<_> = find_exotic_visits_to_prune(db, limit, now)?
@@ -506,6 +832,7 @@ pub fn wipe_local(db: &PlacesDb) -> Result<()> {
     breadcrumb!("apply_observation: begin_transaction");
     let tx = db.begin_transaction()?;
     wipe_local_in_tx(db)?;
+    observer::note(db, PlacesChange::HistoryCleared);
     tx.commit()?;
     breadcrumb!("apply_observation: commit");
     // Note: SQLite cannot VACUUM within a transaction.
@@ -514,13 +841,13 @@ pub fn wipe_local(db: &PlacesDb) -> Result<()> {
 }
 
 fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
-    use crate::frecency::DEFAULT_FRECENCY_SETTINGS;
     // breadcrumb to track down #4856
     breadcrumb!("places: wipe_local_in_tx");
     db.execute_all(&[
         "DELETE FROM moz_places WHERE foreign_count == 0",
         "DELETE FROM moz_places_metadata",
         "DELETE FROM moz_places_metadata_search_queries",
+        "DELETE FROM moz_places_metadata_tombstones",
         "DELETE FROM moz_historyvisits",
         "DELETE FROM moz_places_tombstones",
         "DELETE FROM moz_inputhistory AS i WHERE NOT EXISTS(
@@ -537,7 +864,7 @@ fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
                                  ELSE {unvisited_bookmark_frec}
                             END),
                 sync_change_counter = 0"#,
-            unvisited_bookmark_frec = DEFAULT_FRECENCY_SETTINGS.unvisited_bookmark_bonus
+            unvisited_bookmark_frec = db.frecency_settings().unvisited_bookmark_bonus
         ),
     ])?;
 
@@ -552,8 +879,35 @@ fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
     Ok(())
 }
 
-#[allow(unreachable_code)]
+/// What deleting all history should do about Sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryDeletionSyncMode {
+    /// Deletes history on this device only. Nothing is uploaded: the local
+    /// Sync state is reset, so the next sync is treated like the first, and
+    /// history from other devices will come back - apart from visits older
+    /// than the deletion, which we ignore.
+    LocalOnly,
+    /// Deletes history everywhere. Tombstones are written for every synced
+    /// visit and page, and the next sync uploads them, so they're removed
+    /// from the server and from other devices.
+    Propagate,
+}
+
+/// Deletes all history on this device, without telling the server. This is
+/// `delete_everything_with_sync_mode(db, HistoryDeletionSyncMode::LocalOnly)`.
 pub fn delete_everything(db: &PlacesDb) -> Result<()> {
+    delete_everything_with_sync_mode(db, HistoryDeletionSyncMode::LocalOnly)
+}
+
+/// Deletes all history, and either resets the local Sync state or arranges
+/// for the deletions to be uploaded, depending on `mode`. Either way, we
+/// refuse to sync incoming visits older than the deletion, so they don't
+/// trickle back in from other devices.
+#[allow(unreachable_code)]
+pub fn delete_everything_with_sync_mode(
+    db: &PlacesDb,
+    mode: HistoryDeletionSyncMode,
+) -> Result<()> {
     // breadcrumb to track down #4856
     breadcrumb!("places history delete_everything: begin transaction");
     let tx = db.begin_transaction()?;
@@ -572,11 +926,41 @@ pub fn delete_everything(db: &PlacesDb) -> Result<()> {
         .max(most_recent_known_visit_time);
 
     put_meta(db, DELETION_HIGH_WATER_MARK_META_KEY, &new_mark)?;
+    observer::note(db, PlacesChange::HistoryCleared);
 
-    wipe_local_in_tx(db)?;
-
-    // Remove Sync metadata, too.
-    reset_in_tx(db, &EngineSyncAssociation::Disconnected)?;
+    match mode {
+        HistoryDeletionSyncMode::LocalOnly => {
+            wipe_local_in_tx(db)?;
+            // Remove Sync metadata, too.
+            reset_in_tx(db, &EngineSyncAssociation::Disconnected)?;
+        }
+        HistoryDeletionSyncMode::Propagate => {
+            // This writes tombstones for the visits and pages, and keeps the
+            // Sync metadata so that the next sync uploads them.
+            delete_visits_between_in_tx(db, Timestamp(0), new_mark)?;
+            // That leaves pages which didn't have any visits, and metadata
+            // outside the range.
+            db.execute_all(&[
+                "INSERT OR IGNORE INTO moz_places_metadata_tombstones(guid)
+                 SELECT guid FROM moz_places_metadata",
+                "DELETE FROM moz_places_metadata",
+                "DELETE FROM moz_places_metadata_search_queries",
+                &format!(
+                    "INSERT OR IGNORE INTO moz_places_tombstones(guid)
+                     SELECT guid FROM moz_places
+                     WHERE foreign_count = 0 AND sync_status = {}",
+                    SyncStatus::Normal as u8
+                ),
+                "DELETE FROM moz_places WHERE foreign_count = 0",
+                "DELETE FROM moz_inputhistory AS i WHERE NOT EXISTS(
+                     SELECT 1 FROM moz_places h
+                     WHERE h.id = i.place_id)",
+                "DELETE FROM moz_origins
+                 WHERE id NOT IN (SELECT origin_id FROM moz_places)",
+            ])?;
+            delete_pending_temp_tables(db)?;
+        }
+    }
 
     tx.commit()?;
     // breadcrumb to track down #4856
@@ -710,6 +1094,12 @@ impl PageToClean {
 /// are no more foreign keys such as bookmarks) or updating
 /// their frecency.
 fn cleanup_pages(db: &PlacesDb, pages: &[PageToClean]) -> Result<()> {
+    cleanup_pages_impl(db, pages, true)
+}
+
+// Like `cleanup_pages`, but only writes tombstones for the removed pages if
+// `tombstones` is true.
+fn cleanup_pages_impl(db: &PlacesDb, pages: &[PageToClean], tombstones: bool) -> Result<()> {
     // breadcrumb to track down #4856
     breadcrumb!("places cleanup_pages()");
     // desktop does this frecency work using a function in a single sql
@@ -735,20 +1125,22 @@ fn cleanup_pages(db: &PlacesDb, pages: &[PageToClean]) -> Result<()> {
         .collect();
     sql_support::each_chunk(&remove_ids, |chunk, _| -> Result<()> {
         // tombstones first.
-        db.conn().execute(
-            &format!(
-                "
+        if tombstones {
+            db.conn().execute(
+                &format!(
+                    "
                 INSERT OR IGNORE INTO moz_places_tombstones (guid)
                 SELECT guid FROM moz_places
                 WHERE id in ({ids}) AND sync_status = {status}
                     AND foreign_count = 0
                     AND last_visit_date_local = 0
                     AND last_visit_date_remote = 0",
-                ids = sql_support::repeat_sql_vars(chunk.len()),
-                status = SyncStatus::Normal as u8,
-            ),
-            rusqlite::params_from_iter(chunk),
-        )?;
+                    ids = sql_support::repeat_sql_vars(chunk.len()),
+                    status = SyncStatus::Normal as u8,
+                ),
+                rusqlite::params_from_iter(chunk),
+            )?;
+        }
         db.conn().execute(
             &format!(
                 "
@@ -969,6 +1361,7 @@ pub mod history_sync {
 
             visits_to_skip.reserve(visits.len());
 
+            let mut typed_added = 0;
             for visit in visits {
                 let timestamp = Timestamp::from(visit.date);
                 // Don't insert visits that have been locally deleted.
@@ -977,12 +1370,42 @@ pub mod history_sync {
                 }
                 let transition = VisitTransition::from_primitive(visit.transition)
                     .expect("these should already be validated");
-                add_visit(db, page_info.row_id, None, timestamp, transition, false)?;
+                add_visit(
+                    db,
+                    page_info.row_id,
+                    None,
+                    timestamp,
+                    transition,
+                    false,
+                    None,
+                )?;
+                observer::note(
+                    db,
+                    PlacesChange::VisitAdded {
+                        url: url.clone(),
+                        visit_time: timestamp,
+                        visit_type: transition,
+                    },
+                );
+                if transition == VisitTransition::Typed {
+                    typed_added += 1;
+                }
                 // Make sure that even if a history entry weirdly has the same visit
                 // twice, we don't insert it twice. (This avoids us needing to
                 // recompute visits_to_skip in each step of the iteration)
                 visits_to_skip.insert(timestamp);
             }
+            // Typed visits on other devices count too - and we need to know
+            // before updating the frecency.
+            if typed_added > 0 {
+                db.execute_cached(
+                    "UPDATE moz_places SET typed = typed + :typed_added WHERE id = :row_id",
+                    rusqlite::named_params! {
+                        ":typed_added": typed_added,
+                        ":row_id": page_info.row_id,
+                    },
+                )?;
+            }
         }
         // XXX - we really need a better story for frecency-boost than
         // Option<bool> - None vs Some(false) is confusing. We should use an enum.
@@ -1031,6 +1454,7 @@ pub mod history_sync {
     pub fn apply_synced_deletion(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
         // breadcrumb to track down #4856
         breadcrumb!("places apply_synced_deletion: {}", guid);
+        note_page_deleted(db, guid)?;
         db.execute_cached(
             "DELETE FROM moz_places WHERE guid = :guid",
             &[(":guid", guid)],
@@ -1260,11 +1684,26 @@ where
 ///
 /// This is done so that the FFI can more easily support returning
 /// false when asked if it's visited an invalid URL.
+///
+/// Consumers check tens of thousands of URLs at once (eg, when restoring a
+/// session), which is more than we can bind in one statement. We insert the
+/// URLs into a temp table, in chunks, and then look them all up with a single
+/// join on the `url_hash` index. Temp tables live in memory, and can be
+/// written by read-only connections.
 pub fn get_visited_into(
     db: &PlacesDb,
     urls_idxs: &[(usize, Url)],
     result: &mut [bool],
 ) -> Result<()> {
+    // The table might have rows left over from a lookup that failed.
+    db.execute_batch(
+        "CREATE TEMP TABLE IF NOT EXISTS temp.visitedLookup(
+             fetch_url_index INTEGER PRIMARY KEY,
+             url_hash INTEGER NOT NULL,
+             url TEXT NOT NULL
+         );
+         DELETE FROM temp.visitedLookup;",
+    )?;
     sql_support::each_chunk_mapped(
         urls_idxs,
         |(_, url)| url.as_str(),
@@ -1273,25 +1712,31 @@ pub fn get_visited_into(
                 let (idx, url) = &urls_idxs[i + offset];
                 write!(f, "({},{},?)", *idx, hash::hash_url(url.as_str()))
             });
-            let sql = format!(
-                "WITH to_fetch(fetch_url_index, url_hash, url) AS (VALUES {})
-                 SELECT fetch_url_index
-                 FROM moz_places h
-                 JOIN to_fetch f ON h.url_hash = f.url_hash
-                   AND h.url = f.url",
-                values_with_idx
-            );
-            let mut stmt = db.prepare(&sql)?;
-            for idx_r in stmt.query_and_then(
+            db.execute(
+                &format!(
+                    "INSERT INTO temp.visitedLookup(fetch_url_index, url_hash, url)
+                     VALUES {}",
+                    values_with_idx
+                ),
                 rusqlite::params_from_iter(chunk),
-                |row| -> rusqlite::Result<_> { Ok(row.get::<_, i64>(0)? as usize) },
-            )? {
-                let idx = idx_r?;
-                result[idx] = true;
-            }
+            )?;
             Ok(())
         },
     )?;
+    let mut stmt = db.prepare_cached(
+        "SELECT f.fetch_url_index
+         FROM temp.visitedLookup f
+         JOIN moz_places h ON h.url_hash = f.url_hash
+           AND h.url = f.url",
+    )?;
+    for idx_r in stmt.query_and_then([], |row| -> rusqlite::Result<_> {
+        Ok(row.get::<_, i64>(0)? as usize)
+    })? {
+        let idx = idx_r?;
+        result[idx] = true;
+    }
+    drop(stmt);
+    db.execute_batch("DELETE FROM temp.visitedLookup")?;
     Ok(())
 }
 
@@ -1325,10 +1770,42 @@ pub fn get_visited_urls(
     )?)
 }
 
+/// Returns up to `limit` of the hosts the user has pages for whose name starts
+/// with `prefix`, with or without a leading `www.`, most visited first. An
+/// empty prefix matches every host. This is for settings, where the user picks
+/// sites to add exceptions for.
+pub fn search_hosts(db: &PlacesDb, prefix: &str, limit: u32) -> Result<Vec<HostInfo>> {
+    let prefix = prefix.trim().to_lowercase();
+    db.query_rows_and_then_cached(
+        "SELECT o.host AS host,
+                SUM(h.visit_count_local + h.visit_count_remote) AS visit_count
+         FROM moz_origins o
+         JOIN moz_places h ON h.origin_id = o.id
+         WHERE substr(o.host, 1, length(:prefix)) = :prefix
+            OR substr(o.host, 1, length(:prefix) + 4) = 'www.' || :prefix
+         GROUP BY o.host
+         ORDER BY visit_count DESC, o.host
+         LIMIT :limit",
+        rusqlite::named_params! {
+            ":prefix": prefix,
+            ":limit": limit,
+        },
+        |row| -> Result<_> {
+            Ok(HostInfo {
+                host: row.get("host")?,
+                visit_count: row.get("visit_count")?,
+            })
+        },
+    )
+}
+
+/// Returns up to `num_items` of the most frecent pages, for top sites. If
+/// `num_items` is negative, all of them are returned.
 pub fn get_top_frecent_site_infos(
     db: &PlacesDb,
     num_items: i32,
     frecency_threshold: i64,
+    options: &TopFrecentSiteOptions,
 ) -> Result<Vec<TopFrecentSiteInfo>> {
     // Get the complement of the visit types that should be excluded.
     let allowed_types = VisitTransitionSet::for_specific(&[
@@ -1341,8 +1818,26 @@ pub fn get_top_frecent_site_infos(
     ])
     .complement();
 
-    let infos = db.query_rows_and_then_cached(
-        "SELECT h.frecency, h.title, h.url
+    let mut excluded_urls = HashSet::new();
+    let mut excluded_hosts = Vec::new();
+    for excluded in &options.excluded {
+        match Url::parse(excluded) {
+            Ok(url) => {
+                excluded_urls.insert(String::from(url));
+            }
+            Err(_) => match normalize_host(excluded) {
+                Some(host) => excluded_hosts.push(host),
+                None => log::warn!("Ignoring invalid top site exclusion"),
+            },
+        }
+    }
+    // We can't filter in SQL, so we read pages until we have enough.
+    let limit = usize::try_from(num_items).ok();
+    let mut sites = HashSet::new();
+    let mut infos = Vec::new();
+    let mut stmt = db.prepare_cached(
+        "SELECT h.frecency, h.title, h.url,
+                h.visit_count_local + h.visit_count_remote AS visit_count
         FROM moz_places h
         WHERE EXISTS (
             SELECT v.visit_type
@@ -1354,56 +1849,306 @@ pub fn get_top_frecent_site_infos(
               AND h.frecency >= :frecency_threshold AND
               NOT h.hidden
         )
-        ORDER BY h.frecency DESC
-        LIMIT :limit",
+        ORDER BY h.frecency DESC",
+    )?;
+    let rows = stmt.query_and_then(
         rusqlite::named_params! {
-            ":limit": num_items,
             ":allowed_types": allowed_types,
             ":frecency_threshold": frecency_threshold,
         },
         TopFrecentSiteInfo::from_row,
     )?;
+    for info in rows {
+        if limit.map_or(false, |limit| infos.len() >= limit) {
+            break;
+        }
+        let info = info?;
+        let host = info.url.host_str().unwrap_or_default();
+        if excluded_urls.contains(info.url.as_str())
+            || excluded_hosts
+                .iter()
+                .any(|excluded| is_host_or_subdomain(host, excluded))
+        {
+            continue;
+        }
+        if options.dedupe_origins && !sites.insert(site_for_host(host).to_string()) {
+            continue;
+        }
+        infos.push(info);
+    }
     Ok(infos)
 }
 
+// Mobile and `www.` hosts are usually the same site as the host without the
+// prefix.
+fn site_for_host(host: &str) -> &str {
+    ["www.", "m.", "mobile."]
+        .iter()
+        .find_map(|prefix| host.strip_prefix(prefix))
+        .unwrap_or(host)
+}
+
+/// Returns the pages the user has typed the URL of most often, for URL bar
+/// suggestions which prefer them. Pages typed the same number of times are
+/// ordered by frecency.
+pub fn get_typed_urls(db: &PlacesDb, limit: u32) -> Result<Vec<TypedUrlInfo>> {
+    db.query_rows_and_then_cached(
+        "SELECT url, title, typed,
+                MAX(last_visit_date_local, last_visit_date_remote) AS last_visit_date
+         FROM moz_places
+         WHERE typed > 0 AND NOT hidden
+         ORDER BY typed DESC, frecency DESC
+         LIMIT :limit",
+        rusqlite::named_params! {
+            ":limit": limit,
+        },
+        TypedUrlInfo::from_row,
+    )
+}
+
+// The most URLs `get_top_sites_prefetch_hints` returns for each origin.
+const MAX_PREFETCH_HINT_URLS: u32 = 3;
+
+/// Returns the `limit` web origins with the highest frecency, each with the
+/// pages the user most often went to next from one of its pages, by following
+/// a link or a redirect. Browsers can use these to preconnect to, or prefetch,
+/// the pages a top site is likely to lead to.
+///
+/// Only the visit straight after a page on the origin counts, and pages the
+/// user only reloaded, downloaded, or loaded in a frame aren't returned.
+pub fn get_top_sites_prefetch_hints(db: &PlacesDb, limit: u32) -> Result<Vec<TopSitePrefetchHint>> {
+    let allowed_types = VisitTransitionSet::for_specific(&[
+        VisitTransition::Download,
+        VisitTransition::Embed,
+        VisitTransition::FramedLink,
+        VisitTransition::Reload,
+    ])
+    .complement();
+    let origins = db.query_rows_and_then_cached(
+        "SELECT id, prefix || host AS origin
+         FROM moz_origins
+         WHERE prefix IN ('http://', 'https://') AND frecency > 0
+         ORDER BY frecency DESC
+         LIMIT :limit",
+        rusqlite::named_params! {
+            ":limit": limit,
+        },
+        |row| -> Result<(RowId, String)> { Ok((row.get("id")?, row.get("origin")?)) },
+    )?;
+    origins
+        .into_iter()
+        .map(|(origin_id, origin)| {
+            let next_urls = db.query_rows_and_then_cached(
+                "SELECT h.url
+                 FROM moz_historyvisits v
+                 JOIN moz_historyvisits fv
+                   ON fv.id = v.from_visit
+                 JOIN moz_places fh
+                   ON fh.id = fv.place_id
+                 JOIN moz_places h
+                   ON h.id = v.place_id
+                 WHERE fh.origin_id = :origin_id
+                   AND h.id <> fh.id
+                   AND (SUBSTR(h.url, 1, 6) == 'https:' OR SUBSTR(h.url, 1, 5) == 'http:')
+                   AND ((1 << v.visit_type) & :allowed_types) != 0
+                   AND NOT h.hidden
+                 GROUP BY h.id
+                 ORDER BY COUNT(*) DESC, MAX(v.visit_date) DESC
+                 LIMIT :limit",
+                rusqlite::named_params! {
+                    ":origin_id": origin_id,
+                    ":allowed_types": allowed_types,
+                    ":limit": MAX_PREFETCH_HINT_URLS,
+                },
+                |row| -> Result<Url> { Ok(Url::parse(&row.get::<_, String>("url")?)?) },
+            )?;
+            Ok(TopSitePrefetchHint {
+                origin: Url::parse(&origin)?,
+                next_urls,
+            })
+        })
+        .collect()
+}
+
+/// Returns the visits between `start` and `end`. If `container_id` is given,
+/// only visits made in that container are returned; otherwise, visits in
+/// every container are. The other `get_visit_*` functions filter the same way.
 pub fn get_visit_infos(
     db: &PlacesDb,
     start: Timestamp,
     end: Timestamp,
     exclude_types: VisitTransitionSet,
+    container_id: Option<&str>,
 ) -> Result<Vec<HistoryVisitInfo>> {
     let allowed_types = exclude_types.complement();
     let infos = db.query_rows_and_then_cached(
         "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden, h.preview_image_url,
-                v.is_local
+                v.is_local, v.container_id, fh.url AS referrer_url
          FROM moz_places h
          JOIN moz_historyvisits v
            ON h.id = v.place_id
+         LEFT JOIN moz_historyvisits fv
+           ON fv.id = v.from_visit
+         LEFT JOIN moz_places fh
+           ON fh.id = fv.place_id
          WHERE v.visit_date BETWEEN :start AND :end
            AND ((1 << visit_type) & :allowed_types) != 0 AND
            NOT h.hidden
+           AND (:container_id IS NULL OR v.container_id = :container_id)
          ORDER BY v.visit_date",
         rusqlite::named_params! {
             ":start": start,
             ":end": end,
             ":allowed_types": allowed_types,
+            ":container_id": container_id,
         },
         HistoryVisitInfo::from_row,
     )?;
     Ok(infos)
 }
 
-pub fn get_visit_count(db: &PlacesDb, exclude_types: VisitTransitionSet) -> Result<i64> {
-    let count = if exclude_types.is_empty() {
+/// Returns up to `limit` of the visits `get_visit_infos` would return, which
+/// come after the visit at `after`, along with where the last one is.
+/// Visits are ordered by date, then by id, so that a query can pick up from
+/// a visit, however many other visits were made at the same time, and isn't
+/// thrown by visits being added or removed in between.
+pub fn get_visit_infos_after(
+    db: &PlacesDb,
+    start: Timestamp,
+    end: Timestamp,
+    exclude_types: VisitTransitionSet,
+    container_id: Option<&str>,
+    after: Option<(Timestamp, RowId)>,
+    limit: u32,
+) -> Result<(Vec<HistoryVisitInfo>, Option<(Timestamp, RowId)>)> {
+    let allowed_types = exclude_types.complement();
+    let (after_date, after_id) = match after {
+        Some((date, id)) => (Some(date), Some(id)),
+        None => (None, None),
+    };
+    let mut last = after;
+    let infos = db.query_rows_and_then_cached(
+        "SELECT v.id, h.url, h.title, v.visit_date, v.visit_type, h.hidden, h.preview_image_url,
+                v.is_local, v.container_id, fh.url AS referrer_url
+         FROM moz_places h
+         JOIN moz_historyvisits v
+           ON h.id = v.place_id
+         LEFT JOIN moz_historyvisits fv
+           ON fv.id = v.from_visit
+         LEFT JOIN moz_places fh
+           ON fh.id = fv.place_id
+         WHERE v.visit_date BETWEEN :start AND :end
+           AND ((1 << v.visit_type) & :allowed_types) != 0 AND
+           NOT h.hidden
+           AND (:container_id IS NULL OR v.container_id = :container_id)
+           AND (:after_date IS NULL OR v.visit_date > :after_date
+                OR (v.visit_date = :after_date AND v.id > :after_id))
+         ORDER BY v.visit_date, v.id
+         LIMIT :limit",
+        rusqlite::named_params! {
+            ":start": start,
+            ":end": end,
+            ":allowed_types": allowed_types,
+            ":container_id": container_id,
+            ":after_date": after_date,
+            ":after_id": after_id,
+            ":limit": limit,
+        },
+        |row| -> Result<_> {
+            let info = HistoryVisitInfo::from_row(row)?;
+            last = Some((info.timestamp, row.get("id")?));
+            Ok(info)
+        },
+    )?;
+    Ok((infos, last))
+}
+
+/// Returns every visit to `url`, most recent first, along with the chain of
+/// visits which led to each one, so apps can show how the user got there.
+/// The chain follows each visit's referrer, including redirect sources, and
+/// stops after `MAX_VISIT_SOURCES` visits.
+pub fn get_visit_details(db: &PlacesDb, url: &Url) -> Result<Vec<HistoryVisitDetails>> {
+    let visits = db.query_rows_and_then_cached(
+        "SELECT v.id, h.url, h.title, v.visit_date, v.visit_type, h.hidden,
+                h.preview_image_url, v.is_local, v.container_id, fh.url AS referrer_url
+         FROM moz_places h
+         JOIN moz_historyvisits v
+           ON h.id = v.place_id
+         LEFT JOIN moz_historyvisits fv
+           ON fv.id = v.from_visit
+         LEFT JOIN moz_places fh
+           ON fh.id = fv.place_id
+         WHERE h.url_hash = hash(:url) AND h.url = :url
+         ORDER BY v.visit_date DESC, v.id DESC",
+        rusqlite::named_params! {
+            ":url": url.as_str(),
+        },
+        |row| -> Result<_> { Ok((row.get::<_, RowId>("id")?, HistoryVisitInfo::from_row(row)?)) },
+    )?;
+    visits
+        .into_iter()
+        .map(|(visit_id, visit)| {
+            Ok(HistoryVisitDetails {
+                visit,
+                sources: get_visit_sources(db, visit_id)?,
+            })
+        })
+        .collect()
+}
+
+const MAX_VISIT_SOURCES: u32 = 20;
+
+// Follows `from_visit` back from the visit with `visit_id`, returning the
+// visit it came from directly first.
+fn get_visit_sources(db: &PlacesDb, visit_id: RowId) -> Result<Vec<HistoryVisitInfo>> {
+    db.query_rows_and_then_cached(
+        "WITH RECURSIVE
+         sources(id, depth) AS (
+           SELECT from_visit, 1 FROM moz_historyvisits
+           WHERE id = :visit_id
+           UNION ALL
+           SELECT v.from_visit, s.depth + 1 FROM moz_historyvisits v
+           JOIN sources s ON s.id = v.id
+           WHERE v.from_visit IS NOT NULL
+             AND s.depth < :max_sources
+         )
+         SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden, h.preview_image_url,
+                v.is_local, v.container_id, fh.url AS referrer_url
+         FROM sources s
+         JOIN moz_historyvisits v
+           ON v.id = s.id
+         JOIN moz_places h
+           ON h.id = v.place_id
+         LEFT JOIN moz_historyvisits fv
+           ON fv.id = v.from_visit
+         LEFT JOIN moz_places fh
+           ON fh.id = fv.place_id
+         ORDER BY s.depth",
+        rusqlite::named_params! {
+            ":visit_id": visit_id,
+            ":max_sources": MAX_VISIT_SOURCES,
+        },
+        HistoryVisitInfo::from_row,
+    )
+}
+
+pub fn get_visit_count(
+    db: &PlacesDb,
+    exclude_types: VisitTransitionSet,
+    container_id: Option<&str>,
+) -> Result<i64> {
+    let count = if exclude_types.is_empty() && container_id.is_none() {
         db.query_one::<i64>("SELECT COUNT(*) FROM moz_historyvisits")?
     } else {
         let allowed_types = exclude_types.complement();
         db.query_row_and_then_cachable(
             "SELECT COUNT(*)
              FROM moz_historyvisits
-             WHERE ((1 << visit_type) & :allowed_types) != 0",
+             WHERE ((1 << visit_type) & :allowed_types) != 0
+               AND (:container_id IS NULL OR container_id = :container_id)",
             rusqlite::named_params! {
                 ":allowed_types": allowed_types,
+                ":container_id": container_id,
             },
             |r| r.get(0),
             true,
@@ -1412,21 +2157,61 @@ pub fn get_visit_count(db: &PlacesDb, exclude_types: VisitTransitionSet) -> Resu
     Ok(count)
 }
 
+/// Returns the number of visits on each day between `start` and `end`, in
+/// date order, for drawing activity graphs. `timezone_offset` is the user's
+/// offset from UTC, in minutes east (so, -300 in New York in the winter),
+/// which decides the day a visit falls on. Days without any visits are left
+/// out. Like `get_visit_infos`, visits to hidden pages aren't counted.
+pub fn get_visit_count_per_day(
+    db: &PlacesDb,
+    start: Timestamp,
+    end: Timestamp,
+    timezone_offset: i32,
+) -> Result<Vec<VisitCountForDay>> {
+    Ok(db.query_rows_and_then_cached(
+        "SELECT date((v.visit_date + :offset) / 1000, 'unixepoch') AS day,
+                COUNT(*) AS count
+         FROM moz_historyvisits v
+         JOIN moz_places h ON h.id = v.place_id
+         WHERE v.visit_date BETWEEN :start AND :end
+           AND NOT h.hidden
+         GROUP BY day
+         ORDER BY day",
+        rusqlite::named_params! {
+            ":offset": i64::from(timezone_offset) * 60 * 1000,
+            ":start": start,
+            ":end": end,
+        },
+        |row| -> RusqliteResult<_> {
+            Ok(VisitCountForDay {
+                date: row.get("day")?,
+                count: row.get("count")?,
+            })
+        },
+    )?)
+}
+
 pub fn get_visit_page(
     db: &PlacesDb,
     offset: i64,
     count: i64,
     exclude_types: VisitTransitionSet,
+    container_id: Option<&str>,
 ) -> Result<Vec<HistoryVisitInfo>> {
     let allowed_types = exclude_types.complement();
     let infos = db.query_rows_and_then_cached(
         "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden, h.preview_image_url,
-                v.is_local
+                v.is_local, v.container_id, fh.url AS referrer_url
          FROM moz_places h
          JOIN moz_historyvisits v
            ON h.id = v.place_id
+         LEFT JOIN moz_historyvisits fv
+           ON fv.id = v.from_visit
+         LEFT JOIN moz_places fh
+           ON fh.id = fv.place_id
          WHERE ((1 << v.visit_type) & :allowed_types) != 0 AND
                NOT h.hidden
+               AND (:container_id IS NULL OR v.container_id = :container_id)
          ORDER BY v.visit_date DESC, v.id
          LIMIT :count
          OFFSET :offset",
@@ -1434,6 +2219,7 @@ pub fn get_visit_page(
             ":count": count,
             ":offset": offset,
             ":allowed_types": allowed_types,
+            ":container_id": container_id,
         },
         HistoryVisitInfo::from_row,
     )?;
@@ -1446,17 +2232,23 @@ pub fn get_visit_page_with_bound(
     offset: i64,
     count: i64,
     exclude_types: VisitTransitionSet,
+    container_id: Option<&str>,
 ) -> Result<HistoryVisitInfosWithBound> {
     let allowed_types = exclude_types.complement();
     let infos = db.query_rows_and_then_cached(
         "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden, h.preview_image_url,
-                v.is_local
+                v.is_local, v.container_id, fh.url AS referrer_url
          FROM moz_places h
          JOIN moz_historyvisits v
            ON h.id = v.place_id
+         LEFT JOIN moz_historyvisits fv
+           ON fv.id = v.from_visit
+         LEFT JOIN moz_places fh
+           ON fh.id = fv.place_id
          WHERE ((1 << v.visit_type) & :allowed_types) != 0 AND
                NOT h.hidden
                AND v.visit_date <= :bound
+               AND (:container_id IS NULL OR v.container_id = :container_id)
          ORDER BY v.visit_date DESC, v.id
          LIMIT :count
          OFFSET :offset",
@@ -1465,6 +2257,7 @@ pub fn get_visit_page_with_bound(
             ":bound": bound,
             ":count": count,
             ":offset": offset,
+            ":container_id": container_id,
         },
         HistoryVisitInfo::from_row,
     )?;
@@ -1720,6 +2513,187 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_get_top_sites_prefetch_hints() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let now = Timestamp::now();
+        let home = Url::parse("https://news.example.com/")?;
+        apply_observation(
+            &conn,
+            VisitObservation::new(home.clone())
+                .with_visit_type(VisitTransition::Typed)
+                .with_at(Some(Timestamp(now.0 - 10))),
+        )?;
+        for (i, (url, visit_type)) in [
+            ("https://news.example.com/a", VisitTransition::Link),
+            ("https://other.example.org/b", VisitTransition::Link),
+            ("https://news.example.com/a", VisitTransition::Link),
+            ("https://ads.example.net/", VisitTransition::Embed),
+            ("https://news.example.com/", VisitTransition::Reload),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url)?)
+                    .with_visit_type(visit_type)
+                    .with_at(Some(Timestamp(now.0 - 9 + i as u64)))
+                    .with_referrer(home.clone()),
+            )?;
+        }
+        apply_observation(
+            &conn,
+            VisitObservation::new(Url::parse("https://mozilla.org/")?)
+                .with_visit_type(VisitTransition::Typed)
+                .with_at(Some(now)),
+        )?;
+
+        let hints = get_top_sites_prefetch_hints(&conn, 10)?
+            .into_iter()
+            .map(|hint| {
+                (
+                    hint.origin.to_string(),
+                    hint.next_urls
+                        .iter()
+                        .map(Url::to_string)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            hints["https://news.example.com/"],
+            vec!["https://news.example.com/a", "https://other.example.org/b"]
+        );
+        assert!(hints["https://mozilla.org/"].is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_top_frecent_site_infos_options() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let now = Timestamp::now();
+        // Visited more often, so more frecent, towards the top of the list.
+        for (url, times) in [
+            ("https://www.example.com/", 6),
+            ("https://m.example.com/", 5),
+            ("http://example.com/a", 4),
+            ("https://sponsor.example.org/", 3),
+            ("https://shop.sponsor.example.org/", 3),
+            ("https://mozilla.org/blocked", 2),
+            ("https://mozilla.org/", 1),
+        ] {
+            for i in 0..times {
+                apply_observation(
+                    &conn,
+                    VisitObservation::new(Url::parse(url)?)
+                        .with_visit_type(VisitTransition::Link)
+                        .with_at(Some(Timestamp(now.0 - i))),
+                )?;
+            }
+        }
+        let top_sites = |num_items, options: &TopFrecentSiteOptions| -> Result<Vec<String>> {
+            Ok(get_top_frecent_site_infos(&conn, num_items, 0, options)?
+                .into_iter()
+                .map(|info| info.url.to_string())
+                .collect())
+        };
+
+        let all = get_top_frecent_site_infos(&conn, -1, 0, &TopFrecentSiteOptions::default())?;
+        assert_eq!(all.len(), 7);
+        assert_eq!(all[0].url.as_str(), "https://www.example.com/");
+        assert_eq!(all[0].visit_count, 6);
+        assert!(all[0].frecency > all[6].frecency);
+
+        let options = TopFrecentSiteOptions {
+            dedupe_origins: true,
+            excluded: vec![
+                "SPONSOR.example.org".to_string(),
+                "https://mozilla.org/blocked".to_string(),
+            ],
+        };
+        assert_eq!(
+            top_sites(10, &options)?,
+            vec!["https://www.example.com/", "https://mozilla.org/"]
+        );
+        // The limit applies after filtering.
+        assert_eq!(
+            top_sites(2, &options)?,
+            vec!["https://www.example.com/", "https://mozilla.org/"]
+        );
+        assert_eq!(top_sites(0, &options)?, Vec::<String>::new());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_typed_urls() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let now = Timestamp::now();
+        for (url, visit_type, times) in [
+            (
+                "https://www.example.com/typed-once",
+                VisitTransition::Typed,
+                1,
+            ),
+            (
+                "https://www.example.com/typed-twice",
+                VisitTransition::Typed,
+                2,
+            ),
+            ("https://www.example.com/linked", VisitTransition::Link, 3),
+        ] {
+            for i in 0..times {
+                apply_observation(
+                    &conn,
+                    VisitObservation::new(Url::parse(url)?)
+                        .with_visit_type(visit_type)
+                        .with_at(Some(Timestamp(now.0 - i))),
+                )?;
+            }
+        }
+        let typed_urls = |limit| -> Result<Vec<(String, u32)>> {
+            Ok(get_typed_urls(&conn, limit)?
+                .into_iter()
+                .map(|info| (info.url.to_string(), info.typed_count))
+                .collect())
+        };
+        assert_eq!(
+            typed_urls(10)?,
+            vec![
+                ("https://www.example.com/typed-twice".to_string(), 2),
+                ("https://www.example.com/typed-once".to_string(), 1),
+            ]
+        );
+
+        // Typed visits from other devices count too.
+        let url = Url::parse("https://www.example.com/typed-once")?;
+        apply_synced_visits(
+            &conn,
+            &SyncGuid::random(),
+            &url,
+            &None,
+            &[
+                HistoryRecordVisit {
+                    date: Timestamp(now.0 - 1000).into(),
+                    transition: VisitTransition::Typed as u8,
+                },
+                HistoryRecordVisit {
+                    date: Timestamp(now.0 - 2000).into(),
+                    transition: VisitTransition::Typed as u8,
+                },
+                HistoryRecordVisit {
+                    date: Timestamp(now.0 - 3000).into(),
+                    transition: VisitTransition::Link as u8,
+                },
+            ],
+        )?;
+        assert_eq!(
+            typed_urls(1)?,
+            vec![("https://www.example.com/typed-once".to_string(), 3)]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_get_visited() -> Result<()> {
         let _ = env_logger::try_init();
@@ -1801,6 +2775,97 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_search_hosts() -> Result<()> {
+        use crate::storage::bookmarks::{
+            self, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+        };
+
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        for (url, visits) in [
+            ("https://www.example.com/a", 2),
+            ("http://www.example.com/b", 1),
+            ("https://example.org/", 1),
+            ("https://example.org:8080/", 1),
+            ("https://notexample.com/", 5),
+        ] {
+            for _ in 0..visits {
+                apply_observation(
+                    &conn,
+                    VisitObservation::new(Url::parse(url).unwrap())
+                        .with_visit_type(VisitTransition::Link),
+                )?;
+            }
+        }
+        // A bookmarked page the user hasn't visited still makes its host
+        // known.
+        bookmarks::insert_bookmark(
+            &conn,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("https://example.net/").unwrap(),
+                title: None,
+            }
+            .into(),
+        )?;
+
+        let hosts = |prefix: &str, limit: u32| -> Result<Vec<(String, u32)>> {
+            Ok(search_hosts(&conn, prefix, limit)?
+                .into_iter()
+                .map(|info| (info.host, info.visit_count))
+                .collect())
+        };
+        assert_eq!(
+            hosts(" Example", 10)?,
+            vec![
+                // Both schemes count towards the host.
+                ("www.example.com".to_string(), 3),
+                ("example.org".to_string(), 1),
+                ("example.org:8080".to_string(), 1),
+                ("example.net".to_string(), 0),
+            ]
+        );
+        assert_eq!(
+            hosts("example.org", 1)?,
+            vec![("example.org".to_string(), 1)]
+        );
+        assert_eq!(hosts("", 1)?, vec![("notexample.com".to_string(), 5)]);
+        assert_eq!(hosts("", 10)?.len(), 5);
+        assert!(hosts("mozilla", 10)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_visited_many() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        for i in (0..100).map(|i| i * 997) {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(&format!("https://example.com/{}", i)).unwrap())
+                    .with_visit_type(VisitTransition::Link),
+            )?;
+        }
+        // More URLs than we can bind in a single statement.
+        let count = sql_support::default_max_variable_number() + 1000;
+        let urls = (0..count)
+            .map(|i| Url::parse(&format!("https://example.com/{}", i)).unwrap())
+            .collect::<Vec<_>>();
+        let visited = get_visited(&conn, urls)?;
+        assert_eq!(visited.len(), count);
+        for (i, did_see) in visited.into_iter().enumerate() {
+            assert_eq!(did_see, i % 997 == 0 && i < 100 * 997, "idx {}", i);
+        }
+        // The lookup table is emptied afterwards, so a second lookup only
+        // sees its own URLs.
+        let visited = get_visited(&conn, vec![Url::parse("https://example.com/1").unwrap()])?;
+        assert_eq!(visited, vec![false]);
+        Ok(())
+    }
+
     #[test]
     fn test_get_visited_into() {
         let _ = env_logger::try_init();
@@ -2251,6 +3316,110 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_delete_by_host_and_origin() -> Result<()> {
+        let db = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let hrefs = [
+            "https://example.com/a",
+            "http://example.com/b",
+            "https://www.example.com/c",
+            "https://example.com:8080/d",
+            "https://notexample.com/e",
+            "https://example.com.au/f",
+        ];
+        for href in hrefs {
+            apply_observation(
+                &db,
+                VisitObservation::new(Url::parse(href)?).with_visit_type(VisitTransition::Link),
+            )?;
+        }
+        db.execute_batch(&format!(
+            "UPDATE moz_places SET sync_status = {}",
+            SyncStatus::Normal as u8
+        ))?;
+        let remaining = |db: &PlacesDb| -> Result<Vec<String>> {
+            Ok(
+                db.query_rows_and_then("SELECT url FROM moz_places ORDER BY url", [], |row| {
+                    row.get::<_, String>(0)
+                })?,
+            )
+        };
+
+        delete_everything_for_origin(&db, &Url::parse("https://example.com/some/page")?)?;
+        assert_eq!(
+            remaining(&db)?,
+            vec![
+                "http://example.com/b",
+                "https://example.com.au/f",
+                "https://example.com:8080/d",
+                "https://notexample.com/e",
+                "https://www.example.com/c",
+            ]
+        );
+        assert_eq!(get_tombstone_count(&db), 1);
+
+        // Hosts are normalized, and include subdomains and all ports.
+        delete_visits_for_host(&db, "EXAMPLE.com.")?;
+        assert_eq!(
+            remaining(&db)?,
+            vec!["https://example.com.au/f", "https://notexample.com/e"]
+        );
+        assert_eq!(get_tombstone_count(&db), 4);
+
+        // Invalid hosts don't match anything.
+        delete_visits_for_host(&db, "not a host/")?;
+        assert_eq!(remaining(&db)?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_visits_for_urls() -> Result<()> {
+        let db = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        for href in [
+            "https://example.com/a",
+            "https://example.com/b",
+            "https://www.example.com/c",
+            "https://mozilla.org/",
+        ] {
+            apply_observation(
+                &db,
+                VisitObservation::new(Url::parse(href)?).with_visit_type(VisitTransition::Link),
+            )?;
+        }
+        db.execute_batch(&format!(
+            "UPDATE moz_places SET sync_status = {}",
+            SyncStatus::Normal as u8
+        ))?;
+
+        delete_visits_for_urls(
+            &db,
+            &[
+                // URLs are normalized before we look them up.
+                "HTTPS://EXAMPLE.COM/a".to_string(),
+                "https://example.com/b".to_string(),
+                "https://example.com/b".to_string(),
+                "https://www.example.com/c".to_string(),
+                "https://example.com/unknown".to_string(),
+                "not a url".to_string(),
+            ],
+        )?;
+        assert_eq!(
+            db.query_rows_and_then("SELECT url FROM moz_places", [], |row| {
+                row.get::<_, String>(0)
+            })?,
+            vec!["https://mozilla.org/"]
+        );
+        assert_eq!(get_tombstone_count(&db), 3);
+        // The origins of the deleted pages are gone too.
+        assert_eq!(
+            db.query_rows_and_then("SELECT host FROM moz_origins", [], |row| {
+                row.get::<_, String>(0)
+            })?,
+            vec!["mozilla.org"]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_tombstones() -> Result<()> {
         let _ = env_logger::try_init();
@@ -2821,6 +3990,69 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_delete_everything_propagate() -> Result<()> {
+        use crate::storage::bookmarks::{
+            self, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+        };
+        let _ = env_logger::try_init();
+        let mut conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+
+        let synced = get_observed_page(&mut conn, "http://example.com/synced")?;
+        let bookmarked = get_observed_page(&mut conn, "http://example.com/bookmarked")?;
+        conn.execute_cached(
+            &format!(
+                "UPDATE moz_places SET sync_status = {}",
+                (SyncStatus::Normal as u8)
+            ),
+            [],
+        )?;
+        let unsynced = get_observed_page(&mut conn, "http://example.com/unsynced")?;
+        bookmarks::insert_bookmark(
+            &conn,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: bookmarked.url.clone(),
+                title: None,
+            }
+            .into(),
+        )?;
+        put_meta(&conn, LAST_SYNC_META_KEY, &12345)?;
+
+        delete_everything_with_sync_mode(&conn, HistoryDeletionSyncMode::Propagate)?;
+
+        let page_tombstones =
+            conn.query_rows_and_then("SELECT guid FROM moz_places_tombstones", [], |row| {
+                row.get::<_, SyncGuid>(0)
+            })?;
+        assert_eq!(page_tombstones, vec![synced.guid.clone()]);
+        assert!(fetch_page_info(&conn, &synced.url)?.is_none());
+        assert!(fetch_page_info(&conn, &unsynced.url)?.is_none());
+        // The bookmarked page is kept, but its visit is deleted everywhere.
+        assert!(fetch_page_info(&conn, &bookmarked.url)?.is_some());
+        let count_rows = |table: &str| -> Result<i64> {
+            Ok(conn.query_row(
+                &format!("SELECT COUNT(*) FROM {} WHERE place_id = :place_id", table),
+                &[(":place_id", &bookmarked.row_id)],
+                |row| row.get(0),
+            )?)
+        };
+        assert_eq!(count_rows("moz_historyvisits")?, 0);
+        assert_eq!(count_rows("moz_historyvisit_tombstones")?, 1);
+        // The Sync state isn't reset, so the tombstones are uploaded next time.
+        assert_eq!(get_meta::<i64>(&conn, LAST_SYNC_META_KEY)?, Some(12345));
+        assert!(get_meta::<Timestamp>(&conn, DELETION_HIGH_WATER_MARK_META_KEY)?.is_some());
+
+        // Whereas the local-only mode resets it.
+        delete_everything_with_sync_mode(&conn, HistoryDeletionSyncMode::LocalOnly)?;
+        assert_eq!(get_meta::<i64>(&conn, LAST_SYNC_META_KEY)?, Some(0));
+        Ok(())
+    }
+
     // See https://github.com/mozilla-mobile/fenix/issues/8531#issuecomment-590498878.
     #[test]
     fn test_delete_everything_deletes_origins() {
@@ -2871,6 +4103,63 @@ mod tests {
         assert_eq!(origins, &["example1.com", "example2.com",]);
     }
 
+    #[test]
+    fn test_apply_observations() -> Result<()> {
+        let batched = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let one_by_one = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let now = Timestamp::now();
+        let observations = || -> Result<Vec<VisitObservation>> {
+            Ok(vec![
+                VisitObservation::new(Url::parse("https://example1.com/a")?)
+                    .with_visit_type(VisitTransition::Link)
+                    .with_at(Timestamp(now.0 - 3000)),
+                VisitObservation::new(Url::parse("https://example1.com/a")?)
+                    .with_visit_type(VisitTransition::Typed)
+                    .with_at(Timestamp(now.0 - 2000)),
+                VisitObservation::new(Url::parse("https://example2.com/b")?)
+                    .with_visit_type(VisitTransition::Link)
+                    .with_at(Timestamp(now.0 - 1000)),
+                // Only a title, so no visit.
+                VisitObservation::new(Url::parse("https://example2.com/b")?)
+                    .with_title(Some("B".to_string())),
+            ])
+        };
+        assert_eq!(apply_observations(&batched, observations()?)?, 3);
+        for visit_ob in observations()? {
+            apply_observation(&one_by_one, visit_ob)?;
+        }
+
+        // Both should end up with the same pages, frecencies and origins.
+        let pages = |conn: &PlacesDb| -> Result<Vec<(String, Option<String>, i64)>> {
+            Ok(conn.query_rows_and_then(
+                "SELECT url, title, frecency FROM moz_places ORDER BY url",
+                [],
+                |row| -> rusqlite::Result<_> { Ok((row.get(0)?, row.get(1)?, row.get(2)?)) },
+            )?)
+        };
+        let origins = |conn: &PlacesDb| -> Result<Vec<(String, i64)>> {
+            Ok(conn.query_rows_and_then(
+                "SELECT host, frecency FROM moz_origins ORDER BY host",
+                [],
+                |row| -> rusqlite::Result<_> { Ok((row.get(0)?, row.get(1)?)) },
+            )?)
+        };
+        let batched_pages = pages(&batched)?;
+        assert_eq!(batched_pages.len(), 2);
+        assert!(batched_pages.iter().all(|(_, _, frecency)| *frecency > 0));
+        assert_eq!(batched_pages, pages(&one_by_one)?);
+        let batched_origins = origins(&batched)?;
+        assert_eq!(
+            batched_origins
+                .iter()
+                .map(|(host, _)| host.as_str())
+                .collect::<Vec<_>>(),
+            &["example1.com", "example2.com"]
+        );
+        assert_eq!(batched_origins, origins(&one_by_one)?);
+        Ok(())
+    }
+
     #[test]
     fn test_preview_url() {
         let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
@@ -2947,6 +4236,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_update_preview_image() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let image = Url::parse("https://www.example.com/image.png").unwrap();
+
+        // Unknown pages aren't added.
+        assert!(!update_preview_image(&conn, &url, &image)?);
+        assert!(fetch_page_info(&conn, &url)?.is_none());
+
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone()).with_visit_type(VisitTransition::Link),
+        )?;
+        assert!(update_preview_image(&conn, &url, &image)?);
+        let infos = get_visit_page(&conn, 0, 1, VisitTransitionSet::empty(), None)?;
+        assert_eq!(infos[0].preview_image_url, Some(image));
+
+        let too_long = Url::parse(&format!(
+            "https://www.example.com/{}",
+            "x".repeat(super::super::URL_LENGTH_MAX)
+        ))
+        .unwrap();
+        assert!(!update_preview_image(&conn, &url, &too_long)?);
+        Ok(())
+    }
+
     #[test]
     fn test_long_strings() {
         let _ = env_logger::try_init();
@@ -3004,6 +4320,230 @@ mod tests {
         assert!(title.starts_with(&db_title));
     }
 
+    #[test]
+    fn test_get_visit_details() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let search = Url::parse("https://example.com/search?q=fox")?;
+        let redirect = Url::parse("https://example.com/r")?;
+        let page = Url::parse("https://example.com/fox")?;
+        let now = Timestamp::now().as_millis();
+
+        apply_observation(
+            &conn,
+            VisitObservation::new(search.clone())
+                .with_visit_type(VisitTransition::Typed)
+                .with_at(Timestamp(now - 3000)),
+        )?;
+        apply_observation(
+            &conn,
+            VisitObservation::new(redirect.clone())
+                .with_visit_type(VisitTransition::Link)
+                .with_is_redirect_source(true)
+                .with_referrer(search.clone())
+                .with_at(Timestamp(now - 2000)),
+        )?;
+        apply_observation(
+            &conn,
+            VisitObservation::new(page.clone())
+                .with_visit_type(VisitTransition::RedirectTemporary)
+                .with_referrer(redirect.clone())
+                .with_at(Timestamp(now - 1000)),
+        )?;
+        // We don't know where this one came from.
+        apply_observation(
+            &conn,
+            VisitObservation::new(page.clone())
+                .with_visit_type(VisitTransition::Link)
+                .with_at(Timestamp(now)),
+        )?;
+
+        let details = get_visit_details(&conn, &page)?;
+        assert_eq!(details.len(), 2);
+        assert_eq!(details[0].visit.timestamp, Timestamp(now));
+        assert_eq!(details[0].visit.referrer_url, None);
+        assert!(details[0].sources.is_empty());
+        assert_eq!(details[1].visit.referrer_url, Some(redirect.clone()));
+        assert_eq!(
+            details[1]
+                .sources
+                .iter()
+                .map(|source| (source.url.clone(), source.referrer_url.clone()))
+                .collect::<Vec<_>>(),
+            vec![(redirect, Some(search.clone())), (search.clone(), None)]
+        );
+
+        // Visit pages show where each visit came from, too.
+        let infos = get_visit_infos(
+            &conn,
+            Timestamp(now - 1000),
+            Timestamp(now - 1000),
+            VisitTransitionSet::empty(),
+            None,
+        )?;
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].url, page);
+
+        // Deleting a visit forgets it as the source of later visits.
+        delete_place_visit_at_time(&conn, &search, Timestamp(now - 3000))?;
+        let details = get_visit_details(&conn, &page)?;
+        assert_eq!(details[1].sources.len(), 1);
+        assert_eq!(details[1].sources[0].referrer_url, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_container_visits() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let url = Url::parse("https://example.com/").unwrap();
+        for (at, container_id) in [(1000, None), (2000, Some("work")), (3000, Some("personal"))] {
+            apply_observation(
+                &conn,
+                VisitObservation::new(url.clone())
+                    .with_visit_type(VisitTransition::Link)
+                    .with_at(Timestamp(at))
+                    .with_container_id(container_id.map(String::from)),
+            )?;
+        }
+
+        let all = get_visit_page(&conn, 0, 10, VisitTransitionSet::empty(), None)?;
+        assert_eq!(
+            all.iter()
+                .map(|v| v.container_id.as_deref())
+                .collect::<Vec<_>>(),
+            vec![Some("personal"), Some("work"), None]
+        );
+        assert_eq!(
+            get_visit_count(&conn, VisitTransitionSet::empty(), None)?,
+            3
+        );
+
+        let work = get_visit_infos(
+            &conn,
+            Timestamp(0),
+            Timestamp(5000),
+            VisitTransitionSet::empty(),
+            Some("work"),
+        )?;
+        assert_eq!(work.len(), 1);
+        assert_eq!(work[0].timestamp, Timestamp(2000));
+        assert_eq!(
+            get_visit_count(&conn, VisitTransitionSet::empty(), Some("personal"))?,
+            1
+        );
+        let page = get_visit_page_with_bound(
+            &conn,
+            5000,
+            0,
+            10,
+            VisitTransitionSet::empty(),
+            Some("work"),
+        )?;
+        assert_eq!(page.infos.len(), 1);
+        assert!(
+            get_visit_page(&conn, 0, 10, VisitTransitionSet::empty(), Some("school"))?.is_empty()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_visit_infos_after() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        // Several visits at the same time shouldn't be skipped or repeated
+        // when a chunk ends between them.
+        for (i, at) in [1000, 2000, 2000, 2000, 3000, 4000].iter().enumerate() {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(&format!("https://example.com/{}", i)).unwrap())
+                    .with_visit_type(VisitTransition::Link)
+                    .with_at(Timestamp(*at)),
+            )?;
+        }
+        let all = get_visit_infos(
+            &conn,
+            Timestamp(0),
+            Timestamp(5000),
+            VisitTransitionSet::empty(),
+            None,
+        )?;
+        assert_eq!(all.len(), 6);
+
+        let mut chunked = Vec::new();
+        let mut after = None;
+        loop {
+            let (infos, last) = get_visit_infos_after(
+                &conn,
+                Timestamp(0),
+                Timestamp(5000),
+                VisitTransitionSet::empty(),
+                None,
+                after,
+                2,
+            )?;
+            if infos.is_empty() {
+                assert_eq!(last, after);
+                break;
+            }
+            assert!(infos.len() <= 2);
+            chunked.extend(infos);
+            after = last;
+        }
+        assert!(chunked
+            .windows(2)
+            .all(|pair| pair[0].timestamp <= pair[1].timestamp));
+        // `get_visit_infos` doesn't say which order visits made at the same
+        // time come in.
+        let mut chunked_urls = chunked.iter().map(|v| v.url.clone()).collect::<Vec<_>>();
+        let mut all_urls = all.iter().map(|v| v.url.clone()).collect::<Vec<_>>();
+        chunked_urls.sort();
+        all_urls.sort();
+        assert_eq!(chunked_urls, all_urls);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_visit_count_per_day() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let url = Url::parse("https://example.com/").unwrap();
+        // 2021-01-01T23:30:00Z, 2021-01-02T00:30:00Z, 2021-01-02T12:00:00Z
+        // and 2021-01-05T12:00:00Z.
+        for at in [
+            1_609_543_800_000,
+            1_609_547_400_000,
+            1_609_588_800_000,
+            1_609_848_000_000,
+        ] {
+            apply_observation(
+                &conn,
+                VisitObservation::new(url.clone())
+                    .with_visit_type(VisitTransition::Link)
+                    .with_at(Timestamp(at)),
+            )?;
+        }
+        let counts = |timezone_offset| -> Result<Vec<(String, u32)>> {
+            Ok(get_visit_count_per_day(
+                &conn,
+                Timestamp(1_609_459_200_000),
+                Timestamp(1_609_804_800_000),
+                timezone_offset,
+            )?
+            .into_iter()
+            .map(|day| (day.date, day.count))
+            .collect())
+        };
+        // The last visit is after `end`.
+        assert_eq!(
+            counts(0)?,
+            vec![("2021-01-01".to_string(), 1), ("2021-01-02".to_string(), 2)]
+        );
+        // An hour ahead of UTC, the first two visits are on the same day.
+        assert_eq!(counts(60)?, vec![("2021-01-02".to_string(), 3)]);
+        assert_eq!(
+            counts(-60)?,
+            vec![("2021-01-01".to_string(), 2), ("2021-01-02".to_string(), 1)]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_get_visit_page_with_bound() {
         use std::time::SystemTime;
@@ -3112,9 +4652,15 @@ mod tests {
         }
 
         // test when offset fall on a point where visited_date changes
-        let infos_with_bound =
-            get_visit_page_with_bound(&conn, now_i64 - 200_000, 8, 2, VisitTransitionSet::empty())
-                .unwrap();
+        let infos_with_bound = get_visit_page_with_bound(
+            &conn,
+            now_i64 - 200_000,
+            8,
+            2,
+            VisitTransitionSet::empty(),
+            None,
+        )
+        .unwrap();
         let infos = infos_with_bound.infos;
         assert_eq!(infos[0].title.as_ref().unwrap().as_str(), "older 1",);
         assert!(infos[0].is_remote); // "older 1" is remote
@@ -3124,18 +4670,30 @@ mod tests {
         assert_eq!(infos_with_bound.offset, 1,);
 
         // test when offset fall on one item before visited_date changes
-        let infos_with_bound =
-            get_visit_page_with_bound(&conn, now_i64 - 200_000, 7, 1, VisitTransitionSet::empty())
-                .unwrap();
+        let infos_with_bound = get_visit_page_with_bound(
+            &conn,
+            now_i64 - 200_000,
+            7,
+            1,
+            VisitTransitionSet::empty(),
+            None,
+        )
+        .unwrap();
         assert_eq!(
             infos_with_bound.infos[0].url,
             Url::parse("https://www.example.com/9").unwrap(),
         );
 
         // test when offset fall on one item after visited_date changes
-        let infos_with_bound =
-            get_visit_page_with_bound(&conn, now_i64 - 200_000, 9, 1, VisitTransitionSet::empty())
-                .unwrap();
+        let infos_with_bound = get_visit_page_with_bound(
+            &conn,
+            now_i64 - 200_000,
+            9,
+            1,
+            VisitTransitionSet::empty(),
+            None,
+        )
+        .unwrap();
         assert_eq!(
             infos_with_bound.infos[0].title.as_ref().unwrap().as_str(),
             "older 2",
@@ -3146,9 +4704,15 @@ mod tests {
         let mut bound = now_i64 - 199_000;
         let mut offset = 1;
         for _i in 0..4 {
-            let infos_with_bound =
-                get_visit_page_with_bound(&conn, bound, offset, count, VisitTransitionSet::empty())
-                    .unwrap();
+            let infos_with_bound = get_visit_page_with_bound(
+                &conn,
+                bound,
+                offset,
+                count,
+                VisitTransitionSet::empty(),
+                None,
+            )
+            .unwrap();
             assert_eq!(
                 infos_with_bound.infos[0].title.as_ref().unwrap().as_str(),
                 "same time",
@@ -3166,7 +4730,8 @@ mod tests {
 
         // when bound is now and offset is zero
         let infos_with_bound =
-            get_visit_page_with_bound(&conn, now_i64, 0, 2, VisitTransitionSet::empty()).unwrap();
+            get_visit_page_with_bound(&conn, now_i64, 0, 2, VisitTransitionSet::empty(), None)
+                .unwrap();
         assert_eq!(
             infos_with_bound.infos[0].title.as_ref().unwrap().as_str(),
             "more recent 1",
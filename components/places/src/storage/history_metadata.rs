@@ -8,6 +8,7 @@ use crate::RowId;
 use error_support::{breadcrumb, redact_url};
 use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use sql_support::ConnExt;
+use std::collections::HashMap;
 use std::vec::Vec;
 use sync_guid::Guid as SyncGuid;
 use types::Timestamp;
@@ -78,6 +79,10 @@ pub struct HistoryMetadataObservation {
     pub document_type: Option<DocumentType>,
     pub referrer_url: Option<String>,
     pub title: Option<String>,
+    /// Identifies the tab, or other session, the page is being viewed in.
+    /// Observations from different sessions are recorded separately, so view
+    /// time from several tabs on the same URL isn't lumped together.
+    pub metadata_session_id: Option<String>,
 }
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct HistoryMetadata {
@@ -92,6 +97,18 @@ pub struct HistoryMetadata {
     pub referrer_url: Option<String>,
 }
 
+/// The metadata for the pages the user reached from the same search term.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryMetadataGroup {
+    pub search_term: String,
+    /// Most recently updated first.
+    pub metadata: Vec<HistoryMetadata>,
+    /// The sum of the members' view times.
+    pub total_view_time: i64,
+    /// When the most recently updated member was updated.
+    pub last_updated_at: i64,
+}
+
 impl HistoryMetadata {
     pub(crate) fn from_row(row: &rusqlite::Row<'_>) -> Result<Self> {
         let created_at: Timestamp = row.get("created_at")?;
@@ -258,6 +275,7 @@ struct HistoryMetadataCompoundKey {
     place_entry: PlaceEntry,
     referrer_entry: Option<PlaceEntry>,
     search_query_entry: Option<SearchQueryEntry>,
+    session_id: Option<String>,
 }
 
 struct MetadataObservation {
@@ -305,12 +323,14 @@ impl HistoryMetadataCompoundKey {
                             place_id IS :place_id AND
                             referrer_place_id IS :referrer_place_id AND
                             search_query_id IS :search_query_id AND
+                            session_id IS :session_id AND
                             updated_at >= :newer_than
                         ORDER BY updated_at DESC LIMIT 1",
                     rusqlite::named_params! {
                         ":place_id": id,
                         ":search_query_id": search_query_id,
                         ":referrer_place_id": referrer_place_id,
+                        ":session_id": self.session_id,
                         ":newer_than": newer_than
                     },
                     true,
@@ -407,6 +427,13 @@ lazy_static! {
         common_select_sql = COMMON_METADATA_SELECT,
         max_limit = MAX_QUERY_RESULTS
     );
+    static ref GET_BETWEEN_WITH_SEARCH_TERM_SQL: String = format!(
+        "{common_select_sql}
+        WHERE updated_at BETWEEN :start AND :end
+          AND m.search_query_id IS NOT NULL
+        ORDER BY updated_at DESC, metadata_id DESC",
+        common_select_sql = COMMON_METADATA_SELECT
+    );
     static ref GET_SINCE_SQL: String = format!(
         "{common_select_sql}
         WHERE updated_at >= :start
@@ -437,6 +464,90 @@ pub fn get_latest_for_url(db: &PlacesDb, url: &Url) -> Result<Option<HistoryMeta
     Ok(metadata)
 }
 
+// Steps to the entry for the page the user came from to reach `current`.
+const PREVIOUS_IN_CHAIN_SQL: &str = "
+    WHERE p.url_hash = hash(:url) AND p.url = :url AND m.id < :id
+    ORDER BY m.id DESC
+    LIMIT 1";
+
+// Steps to the first entry for a page the user went to from `current`.
+const NEXT_IN_CHAIN_SQL: &str = "
+    WHERE o.url_hash = hash(:url) AND o.url = :url AND m.id > :id
+    ORDER BY m.id ASC
+    LIMIT 1";
+
+fn get_chain_step(
+    db: &PlacesDb,
+    where_sql: &str,
+    url: &str,
+    id: i64,
+) -> Result<Option<(i64, HistoryMetadata)>> {
+    db.try_query_row(
+        &format!("{}{}", COMMON_METADATA_SELECT, where_sql),
+        rusqlite::named_params! {
+            ":url": url,
+            ":id": id,
+        },
+        |row| -> Result<_> { Ok((row.get("metadata_id")?, HistoryMetadata::from_row(row)?)) },
+        true,
+    )
+}
+
+/// Reconstructs how the user arrived at `url`, and where they went from
+/// there, by following the referrers of metadata entries. Starting from the
+/// latest entry for `url`, we follow up to `depth` referrers backwards, and
+/// up to `depth` pages which were opened from the chain forwards - where a
+/// page was used to open several others, we follow the first one.
+///
+/// Returns the chain oldest first, including the entry for `url`, or an empty
+/// list if we don't have metadata for `url`. Since entries are only ever
+/// followed to older entries backwards and newer ones forwards, cycles
+/// (eg, A -> B -> A) are walked as separate steps and always end.
+pub fn get_navigation_chain(db: &PlacesDb, url: &Url, depth: u32) -> Result<Vec<HistoryMetadata>> {
+    let start = db.try_query_row(
+        GET_LATEST_SQL.as_str(),
+        &[(":url", &url.as_str())],
+        |row| -> Result<_> { Ok((row.get("metadata_id")?, HistoryMetadata::from_row(row)?)) },
+        true,
+    )?;
+    let (start_id, start) = match start {
+        Some(start) => start,
+        None => return Ok(vec![]),
+    };
+
+    let mut previous = Vec::new();
+    let (mut id, mut current) = (start_id, start.clone());
+    while previous.len() < depth as usize {
+        let referrer_url = match &current.referrer_url {
+            Some(referrer_url) => referrer_url.clone(),
+            None => break,
+        };
+        match get_chain_step(db, PREVIOUS_IN_CHAIN_SQL, &referrer_url, id)? {
+            Some((prev_id, prev)) => {
+                id = prev_id;
+                current = prev.clone();
+                previous.push(prev);
+            }
+            None => break,
+        }
+    }
+
+    let mut chain: Vec<HistoryMetadata> = previous.into_iter().rev().collect();
+    chain.push(start.clone());
+    let (mut id, mut current) = (start_id, start);
+    for _ in 0..depth {
+        match get_chain_step(db, NEXT_IN_CHAIN_SQL, &current.url, id)? {
+            Some((next_id, next)) => {
+                id = next_id;
+                current = next.clone();
+                chain.push(next);
+            }
+            None => break,
+        }
+    }
+    Ok(chain)
+}
+
 pub fn get_between(db: &PlacesDb, start: i64, end: i64) -> Result<Vec<HistoryMetadata>> {
     db.query_rows_and_then_cached(
         GET_BETWEEN_SQL.as_str(),
@@ -448,6 +559,48 @@ pub fn get_between(db: &PlacesDb, start: i64, end: i64) -> Result<Vec<HistoryMet
     )
 }
 
+/// Groups the metadata updated between `start` and `end` by the search term
+/// the user came from, leaving out metadata without one. Search terms are
+/// stored in lowercase, so differently cased searches are grouped together.
+/// The most recently updated groups come first.
+pub fn get_grouped_by_search_term(
+    db: &PlacesDb,
+    start: i64,
+    end: i64,
+) -> Result<Vec<HistoryMetadataGroup>> {
+    let metadata = db.query_rows_and_then_cached(
+        GET_BETWEEN_WITH_SEARCH_TERM_SQL.as_str(),
+        rusqlite::named_params! {
+            ":start": start,
+            ":end": end,
+        },
+        HistoryMetadata::from_row,
+    )?;
+    let mut groups: Vec<HistoryMetadataGroup> = Vec::new();
+    let mut group_indexes: HashMap<String, usize> = HashMap::new();
+    // The metadata is in order of when it was updated, so the first member of
+    // each group is the most recent one, and the groups end up in order too.
+    for m in metadata {
+        let search_term = match &m.search_term {
+            Some(term) => term.clone(),
+            None => continue,
+        };
+        let index = *group_indexes.entry(search_term.clone()).or_insert_with(|| {
+            groups.push(HistoryMetadataGroup {
+                search_term,
+                metadata: Vec::new(),
+                total_view_time: 0,
+                last_updated_at: m.updated_at,
+            });
+            groups.len() - 1
+        });
+        let group = &mut groups[index];
+        group.total_view_time += i64::from(m.total_view_time);
+        group.metadata.push(m);
+    }
+    Ok(groups)
+}
+
 pub fn get_since(db: &PlacesDb, start: i64) -> Result<Vec<HistoryMetadata>> {
     db.query_rows_and_then_cached(
         GET_SINCE_SQL.as_str(),
@@ -495,6 +648,12 @@ pub fn delete_older_than(db: &PlacesDb, older_than: i64) -> Result<()> {
 }
 
 pub fn delete_between(db: &PlacesDb, start: i64, end: i64) -> Result<()> {
+    db.execute_cached(
+        "INSERT OR IGNORE INTO moz_places_metadata_tombstones(guid)
+         SELECT guid FROM moz_places_metadata
+         WHERE updated_at > :start and updated_at < :end",
+        &[(":start", &start), (":end", &end)],
+    )?;
     db.execute_cached(
         "DELETE FROM moz_places_metadata
         WHERE updated_at > :start and updated_at < :end",
@@ -505,6 +664,12 @@ pub fn delete_between(db: &PlacesDb, start: i64, end: i64) -> Result<()> {
 
 /// Delete all metadata for the specified place id.
 pub fn delete_all_metadata_for_page(db: &PlacesDb, place_id: RowId) -> Result<()> {
+    db.execute_cached(
+        "INSERT OR IGNORE INTO moz_places_metadata_tombstones(guid)
+         SELECT guid FROM moz_places_metadata
+         WHERE place_id = :place_id",
+        &[(":place_id", &place_id)],
+    )?;
     db.execute_cached(
         "DELETE FROM moz_places_metadata
          WHERE place_id = :place_id",
@@ -513,6 +678,32 @@ pub fn delete_all_metadata_for_page(db: &PlacesDb, place_id: RowId) -> Result<()
     Ok(())
 }
 
+/// Delete all metadata for pages the user came to from a search for
+/// `search_term`, whatever the page. Terms are matched ignoring case, as
+/// they're stored. Highlights are calculated from the metadata, so the pages
+/// no longer count towards them.
+pub fn delete_by_search_term(db: &PlacesDb, search_term: &str) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    let params = &[(":term", &search_term.to_lowercase())];
+    tx.execute_cached(
+        "INSERT OR IGNORE INTO moz_places_metadata_tombstones(guid)
+         SELECT m.guid FROM moz_places_metadata m
+         JOIN moz_places_metadata_search_queries s ON s.id = m.search_query_id
+         WHERE s.term = :term",
+        params,
+    )?;
+    // The trigger on `moz_places_metadata` removes the search term, once
+    // there's no metadata left for it.
+    tx.execute_cached(
+        "DELETE FROM moz_places_metadata
+         WHERE search_query_id = (SELECT id FROM moz_places_metadata_search_queries
+                                  WHERE term = :term)",
+        params,
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
 pub fn delete_metadata(
     db: &PlacesDb,
     url: &Url,
@@ -561,14 +752,25 @@ pub fn delete_metadata(
         }
     };
 
-    let sql = format!(
-        "DELETE FROM moz_places_metadata WHERE {} AND {} AND {}",
+    let where_clause = format!(
+        "{} AND {} AND {}",
         place_entry.to_where_arg("place_id"),
         referrer_entry.to_where_arg("referrer_place_id"),
         search_query_entry.to_where_arg("search_query_id")
     );
 
-    tx.execute_cached(&sql, [])?;
+    tx.execute_cached(
+        &format!(
+            "INSERT OR IGNORE INTO moz_places_metadata_tombstones(guid)
+             SELECT guid FROM moz_places_metadata WHERE {}",
+            where_clause
+        ),
+        [],
+    )?;
+    tx.execute_cached(
+        &format!("DELETE FROM moz_places_metadata WHERE {}", where_clause),
+        [],
+    )?;
     tx.commit()?;
 
     Ok(())
@@ -634,6 +836,9 @@ fn apply_metadata_observation_impl(
         place_entry,
         referrer_entry,
         search_query_entry,
+        session_id: observation
+            .metadata_session_id
+            .filter(|session_id| !session_id.is_empty()),
     };
 
     let observation = MetadataObservation {
@@ -660,7 +865,8 @@ fn apply_metadata_observation_impl(
                         SET
                             document_type = :document_type,
                             total_view_time = total_view_time + :view_time_delta,
-                            updated_at = :updated_at
+                            updated_at = :updated_at,
+                            sync_change_counter = sync_change_counter + 1
                         WHERE id = :id",
                         rusqlite::named_params! {
                             ":id": metadata_id,
@@ -679,7 +885,8 @@ fn apply_metadata_observation_impl(
                             moz_places_metadata
                         SET
                             total_view_time = total_view_time + :view_time_delta,
-                            updated_at = :updated_at
+                            updated_at = :updated_at,
+                            sync_change_counter = sync_change_counter + 1
                         WHERE id = :id",
                         rusqlite::named_params! {
                             ":id": metadata_id,
@@ -717,9 +924,9 @@ fn insert_metadata_in_tx(
     let place_id = key.place_entry.get_or_insert(tx)?;
 
     let sql = "INSERT INTO moz_places_metadata
-        (place_id, created_at, updated_at, total_view_time, search_query_id, document_type, referrer_place_id)
+        (place_id, created_at, updated_at, total_view_time, search_query_id, document_type, referrer_place_id, session_id, guid)
     VALUES
-        (:place_id, :created_at, :updated_at, :total_view_time, :search_query_id, :document_type, :referrer_place_id)";
+        (:place_id, :created_at, :updated_at, :total_view_time, :search_query_id, :document_type, :referrer_place_id, :session_id, generate_guid())";
 
     tx.execute_cached(
         sql,
@@ -729,6 +936,7 @@ fn insert_metadata_in_tx(
             (":updated_at", &now),
             (":search_query_id", &search_query_id),
             (":referrer_place_id", &referrer_place_id),
+            (":session_id", &key.session_id),
             (
                 ":document_type",
                 &observation.document_type.unwrap_or(DocumentType::Regular),
@@ -740,6 +948,284 @@ fn insert_metadata_in_tx(
     Ok(())
 }
 
+// Support for Sync - in its own module, like `history::history_sync`.
+pub mod history_metadata_sync {
+    use super::*;
+    use crate::history_metadata_sync::record::{HistoryMetadataRecord, HistoryMetadataSyncRecord};
+    use crate::history_metadata_sync::{
+        COLLECTION_SYNCID_META_KEY, GLOBAL_SYNCID_META_KEY, LAST_SYNC_META_KEY,
+    };
+    use crate::storage::history::DELETION_HIGH_WATER_MARK_META_KEY;
+    use crate::storage::{delete_meta, delete_pending_temp_tables, get_meta, put_meta};
+    use interrupt_support::Interruptee;
+    use std::collections::HashMap;
+    use sync15::engine::EngineSyncAssociation;
+    use sync15::telemetry;
+
+    enum IncomingOutcome {
+        Applied,
+        // We kept our newer local version, which we'll upload.
+        KeptLocal,
+        Skipped,
+    }
+
+    /// Applies incoming records and tombstones in a single transaction.
+    pub fn apply_incoming(
+        db: &PlacesDb,
+        records: Vec<HistoryMetadataSyncRecord>,
+        scope: &impl Interruptee,
+        telem: &mut telemetry::EngineIncoming,
+    ) -> Result<()> {
+        // Don't let metadata for history the user cleared come back from
+        // other devices.
+        let high_water_mark: i64 =
+            get_meta(db, DELETION_HIGH_WATER_MARK_META_KEY)?.unwrap_or_default();
+        let tx = db.begin_transaction()?;
+        for HistoryMetadataSyncRecord { guid, record } in records {
+            scope.err_if_interrupted()?;
+            let outcome = match record {
+                Some(record) if record.updated_at <= high_water_mark => IncomingOutcome::Skipped,
+                Some(record) => apply_record(&tx, record)?,
+                None => {
+                    apply_tombstone(&tx, &guid)?;
+                    IncomingOutcome::Applied
+                }
+            };
+            match outcome {
+                IncomingOutcome::Applied => telem.applied(1),
+                IncomingOutcome::KeptLocal => telem.reconciled(1),
+                IncomingOutcome::Skipped => {}
+            }
+        }
+        delete_pending_temp_tables(db)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn apply_record(
+        tx: &PlacesTransaction<'_>,
+        record: HistoryMetadataRecord,
+    ) -> Result<IncomingOutcome> {
+        if Url::parse(&record.url).is_err() {
+            log::warn!("Ignoring history metadata {} with invalid url", record.id);
+            return Ok(IncomingOutcome::Skipped);
+        }
+        // If we deleted it locally, our tombstone wins, and will be uploaded.
+        if tx
+            .try_query_one::<i64, _>(
+                "SELECT 1 FROM moz_places_metadata_tombstones WHERE guid = :guid",
+                &[(":guid", &record.id)],
+                true,
+            )?
+            .is_some()
+        {
+            return Ok(IncomingOutcome::Skipped);
+        }
+        let local = tx.try_query_row(
+            "SELECT id, updated_at, sync_change_counter
+             FROM moz_places_metadata
+             WHERE guid = :guid",
+            &[(":guid", &record.id)],
+            |row| -> rusqlite::Result<(i64, i64, i64)> {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            },
+            true,
+        )?;
+        if let Some((_, updated_at, change_counter)) = local {
+            // Both sides changed; whichever was updated last wins.
+            if change_counter > 0 && updated_at > record.updated_at {
+                return Ok(IncomingOutcome::KeptLocal);
+            }
+        }
+
+        let place_id =
+            PlaceEntry::fetch(&record.url, tx, record.title.clone())?.get_or_insert(tx)?;
+        let referrer_place_id = match record.referrer_url {
+            Some(referrer_url) if Url::parse(&referrer_url).is_ok() => {
+                Some(PlaceEntry::fetch(&referrer_url, tx, None)?.get_or_insert(tx)?)
+            }
+            _ => None,
+        }
+        // A page can't refer to itself.
+        .filter(|&id| id != place_id);
+        let search_query_id = match record.search_term {
+            Some(search_term) if !search_term.is_empty() => {
+                Some(SearchQueryEntry::from(&search_term, tx)?.get_or_insert(tx)?)
+            }
+            _ => None,
+        };
+        let document_type = match record.document_type {
+            1 => DocumentType::Media,
+            _ => DocumentType::Regular,
+        };
+        let params = rusqlite::named_params! {
+            ":guid": record.id,
+            ":place_id": place_id,
+            ":referrer_place_id": referrer_place_id,
+            ":search_query_id": search_query_id,
+            ":document_type": document_type,
+            ":created_at": record.created_at,
+            ":updated_at": record.updated_at,
+            ":total_view_time": record.total_view_time.max(0),
+        };
+        if local.is_some() {
+            tx.execute_cached(
+                "UPDATE moz_places_metadata SET
+                    place_id = :place_id,
+                    referrer_place_id = :referrer_place_id,
+                    search_query_id = :search_query_id,
+                    document_type = :document_type,
+                    created_at = :created_at,
+                    updated_at = :updated_at,
+                    total_view_time = :total_view_time,
+                    sync_change_counter = 0
+                 WHERE guid = :guid",
+                params,
+            )?;
+        } else {
+            tx.execute_cached(
+                "INSERT INTO moz_places_metadata
+                    (guid, place_id, referrer_place_id, search_query_id, document_type,
+                     created_at, updated_at, total_view_time, sync_change_counter)
+                 VALUES
+                    (:guid, :place_id, :referrer_place_id, :search_query_id, :document_type,
+                     :created_at, :updated_at, :total_view_time, 0)",
+                params,
+            )?;
+        }
+        Ok(IncomingOutcome::Applied)
+    }
+
+    fn apply_tombstone(tx: &PlacesTransaction<'_>, guid: &SyncGuid) -> Result<()> {
+        tx.execute_cached(
+            "DELETE FROM moz_places_metadata WHERE guid = :guid",
+            &[(":guid", guid)],
+        )?;
+        tx.execute_cached(
+            "DELETE FROM moz_places_metadata_tombstones WHERE guid = :guid",
+            &[(":guid", guid)],
+        )?;
+        Ok(())
+    }
+
+    #[derive(Debug)]
+    pub enum OutgoingMetadata {
+        Record {
+            record: HistoryMetadataRecord,
+            // The change counter when we fetched the record, so that
+            // `finish_outgoing` doesn't lose changes made during the sync.
+            change_counter: i64,
+        },
+        Tombstone(SyncGuid),
+    }
+
+    pub fn fetch_outgoing(db: &PlacesDb) -> Result<Vec<OutgoingMetadata>> {
+        let mut outgoing = db.query_rows_and_then_cached(
+            "SELECT m.guid, m.created_at, m.updated_at, m.total_view_time,
+                    m.document_type, m.sync_change_counter,
+                    p.url, p.title, r.url AS referrer_url, s.term AS search_term
+             FROM moz_places_metadata m
+             JOIN moz_places p ON p.id = m.place_id
+             LEFT JOIN moz_places r ON r.id = m.referrer_place_id
+             LEFT JOIN moz_places_metadata_search_queries s ON s.id = m.search_query_id
+             WHERE m.sync_change_counter > 0",
+            [],
+            |row| -> Result<_> {
+                let total_view_time: i64 = row.get("total_view_time")?;
+                Ok(OutgoingMetadata::Record {
+                    record: HistoryMetadataRecord {
+                        id: row.get("guid")?,
+                        url: row.get("url")?,
+                        title: row.get("title")?,
+                        referrer_url: row.get("referrer_url")?,
+                        search_term: row.get("search_term")?,
+                        document_type: row.get("document_type")?,
+                        created_at: row.get("created_at")?,
+                        updated_at: row.get("updated_at")?,
+                        total_view_time: i32::try_from(total_view_time).unwrap_or(i32::MAX),
+                    },
+                    change_counter: row.get("sync_change_counter")?,
+                })
+            },
+        )?;
+        outgoing.extend(db.query_rows_and_then_cached(
+            "SELECT guid FROM moz_places_metadata_tombstones",
+            [],
+            |row| -> Result<_> { Ok(OutgoingMetadata::Tombstone(row.get(0)?)) },
+        )?);
+        Ok(outgoing)
+    }
+
+    /// Marks the records we uploaded as synced. `change_counters` are the
+    /// counters returned by `fetch_outgoing`; records which changed again
+    /// since then stay marked for the next sync.
+    pub fn finish_outgoing(
+        db: &PlacesDb,
+        synced: &[SyncGuid],
+        change_counters: &HashMap<SyncGuid, i64>,
+    ) -> Result<()> {
+        let tx = db.begin_transaction()?;
+        for guid in synced {
+            match change_counters.get(guid) {
+                Some(change_counter) => {
+                    db.execute_cached(
+                        "UPDATE moz_places_metadata
+                         SET sync_change_counter = MAX(sync_change_counter - :delta, 0)
+                         WHERE guid = :guid",
+                        rusqlite::named_params! {
+                            ":guid": guid,
+                            ":delta": change_counter,
+                        },
+                    )?;
+                }
+                None => {
+                    db.execute_cached(
+                        "DELETE FROM moz_places_metadata_tombstones WHERE guid = :guid",
+                        &[(":guid", guid)],
+                    )?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Marks all metadata as needing to be uploaded, and resets the last
+    /// sync time and sync IDs.
+    pub(crate) fn reset(db: &PlacesDb, assoc: &EngineSyncAssociation) -> Result<()> {
+        let tx = db.begin_transaction()?;
+        db.execute_all(&[
+            "UPDATE moz_places_metadata SET sync_change_counter = 1",
+            "DELETE FROM moz_places_metadata_tombstones",
+        ])?;
+        put_meta(db, LAST_SYNC_META_KEY, &0)?;
+        match assoc {
+            EngineSyncAssociation::Disconnected => {
+                delete_meta(db, GLOBAL_SYNCID_META_KEY)?;
+                delete_meta(db, COLLECTION_SYNCID_META_KEY)?;
+            }
+            EngineSyncAssociation::Connected(ids) => {
+                put_meta(db, GLOBAL_SYNCID_META_KEY, &ids.global)?;
+                put_meta(db, COLLECTION_SYNCID_META_KEY, &ids.coll)?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Deletes all metadata locally, without uploading tombstones.
+    pub(crate) fn wipe(db: &PlacesDb) -> Result<()> {
+        let tx = db.begin_transaction()?;
+        db.execute_all(&[
+            "DELETE FROM moz_places_metadata",
+            "DELETE FROM moz_places_metadata_search_queries",
+            "DELETE FROM moz_places_metadata_tombstones",
+        ])?;
+        tx.commit()?;
+        Ok(())
+    }
+} // end of sync module.
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -859,6 +1345,7 @@ mod tests {
                     document_type: $document_type,
                     referrer_url: $referrer_url.map(|s: &str| s.to_string()),
                     title: $title.map(|s: &str| s.to_string()),
+                    metadata_session_id: None,
                 },
             )
             .unwrap();
@@ -889,6 +1376,203 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sync_metadata() {
+        use super::history_metadata_sync::*;
+        use crate::history_metadata_sync::record::{
+            HistoryMetadataRecord, HistoryMetadataSyncRecord,
+        };
+        use interrupt_support::NeverInterrupts;
+        use std::collections::HashMap;
+        use sync15::telemetry;
+
+        fn outgoing_records(conn: &PlacesDb) -> Vec<(HistoryMetadataRecord, i64)> {
+            fetch_outgoing(conn)
+                .expect("should fetch")
+                .into_iter()
+                .filter_map(|item| match item {
+                    OutgoingMetadata::Record {
+                        record,
+                        change_counter,
+                    } => Some((record, change_counter)),
+                    OutgoingMetadata::Tombstone(_) => None,
+                })
+                .collect()
+        }
+
+        fn outgoing_tombstones(conn: &PlacesDb) -> Vec<SyncGuid> {
+            fetch_outgoing(conn)
+                .expect("should fetch")
+                .into_iter()
+                .filter_map(|item| match item {
+                    OutgoingMetadata::Tombstone(guid) => Some(guid),
+                    OutgoingMetadata::Record { .. } => None,
+                })
+                .collect()
+        }
+
+        fn apply(
+            conn: &PlacesDb,
+            records: Vec<HistoryMetadataSyncRecord>,
+        ) -> telemetry::EngineIncoming {
+            let mut telem = telemetry::EngineIncoming::new();
+            apply_incoming(conn, records, &NeverInterrupts, &mut telem).expect("should apply");
+            telem
+        }
+
+        let conn = PlacesDb::open_in_memory(ConnectionType::Sync).unwrap();
+
+        // Local metadata is uploaded until it's synced.
+        note_observation!(&conn,
+            url "https://www.mozilla.org/",
+            view_time Some(2000),
+            search_term Some("mozilla"),
+            document_type Some(DocumentType::Media),
+            referrer_url Some("https://www.google.com/search?q=mozilla"),
+            title None
+        );
+        let outgoing = outgoing_records(&conn);
+        assert_eq!(outgoing.len(), 1);
+        let (local, change_counter) = outgoing[0].clone();
+        assert_eq!(change_counter, 1);
+        assert_eq!(local.url, "https://www.mozilla.org/");
+        assert_eq!(local.search_term.as_deref(), Some("mozilla"));
+        assert_eq!(
+            local.referrer_url.as_deref(),
+            Some("https://www.google.com/search?q=mozilla")
+        );
+        assert_eq!(local.document_type, 1);
+        assert_eq!(local.total_view_time, 2000);
+
+        // Changes made after we fetched the record are uploaded next time.
+        note_observation!(&conn,
+            url "https://www.mozilla.org/",
+            view_time Some(1000),
+            search_term Some("mozilla"),
+            document_type None,
+            referrer_url Some("https://www.google.com/search?q=mozilla"),
+            title None
+        );
+        finish_outgoing(
+            &conn,
+            &[local.id.clone()],
+            &HashMap::from([(local.id.clone(), change_counter)]),
+        )
+        .expect("should finish");
+        assert_eq!(outgoing_records(&conn)[0].1, 1);
+        finish_outgoing(
+            &conn,
+            &[local.id.clone()],
+            &HashMap::from([(local.id.clone(), 1)]),
+        )
+        .expect("should finish");
+        assert!(outgoing_records(&conn).is_empty());
+
+        // New incoming records are applied, and aren't uploaded again.
+        let remote = HistoryMetadataRecord {
+            id: SyncGuid::random(),
+            url: "https://example.com/".into(),
+            title: Some("Example".into()),
+            referrer_url: Some("https://example.com/".into()),
+            search_term: Some("Example".into()),
+            document_type: 0,
+            created_at: 1000,
+            updated_at: 2000,
+            total_view_time: 500,
+        };
+        let telem = apply(
+            &conn,
+            vec![HistoryMetadataSyncRecord {
+                guid: remote.id.clone(),
+                record: Some(remote.clone()),
+            }],
+        );
+        assert_eq!(telem.get_applied(), 1);
+        let applied = get_latest_for_url(&conn, &Url::parse("https://example.com/").unwrap())
+            .expect("should query")
+            .expect("should exist");
+        assert_eq!(applied.title.as_deref(), Some("Example"));
+        assert_eq!(applied.search_term.as_deref(), Some("example"));
+        // A page can't refer to itself.
+        assert_eq!(applied.referrer_url, None);
+        assert_eq!(applied.updated_at, 2000);
+        assert!(outgoing_records(&conn).is_empty());
+
+        // If both sides changed, the most recent update wins.
+        conn.execute(
+            "UPDATE moz_places_metadata
+             SET total_view_time = 600, updated_at = 3000, sync_change_counter = 1
+             WHERE guid = ?",
+            [&remote.id],
+        )
+        .expect("should update");
+        let telem = apply(
+            &conn,
+            vec![HistoryMetadataSyncRecord {
+                guid: remote.id.clone(),
+                record: Some(HistoryMetadataRecord {
+                    total_view_time: 700,
+                    updated_at: 2500,
+                    ..remote.clone()
+                }),
+            }],
+        );
+        assert_eq!(telem.get_reconciled(), 1);
+        assert_eq!(outgoing_records(&conn)[0].0.total_view_time, 600);
+        apply(
+            &conn,
+            vec![HistoryMetadataSyncRecord {
+                guid: remote.id.clone(),
+                record: Some(HistoryMetadataRecord {
+                    total_view_time: 800,
+                    updated_at: 3500,
+                    ..remote.clone()
+                }),
+            }],
+        );
+        assert!(outgoing_records(&conn).is_empty());
+        let applied = get_latest_for_url(&conn, &Url::parse("https://example.com/").unwrap())
+            .expect("should query")
+            .expect("should exist");
+        assert_eq!(applied.total_view_time, 800);
+
+        // Deleting metadata uploads a tombstone, and the deleted record
+        // doesn't come back.
+        delete_metadata(
+            &conn,
+            &Url::parse("https://example.com/").unwrap(),
+            None,
+            Some("example"),
+        )
+        .expect("should delete");
+        assert_eq!(outgoing_tombstones(&conn), vec![remote.id.clone()]);
+        apply(
+            &conn,
+            vec![HistoryMetadataSyncRecord {
+                guid: remote.id.clone(),
+                record: Some(remote.clone()),
+            }],
+        );
+        assert!(
+            get_latest_for_url(&conn, &Url::parse("https://example.com/").unwrap())
+                .expect("should query")
+                .is_none()
+        );
+        finish_outgoing(&conn, &[remote.id.clone()], &HashMap::new()).expect("should finish");
+        assert!(outgoing_tombstones(&conn).is_empty());
+
+        // Incoming tombstones delete local metadata.
+        apply(
+            &conn,
+            vec![HistoryMetadataSyncRecord {
+                guid: local.id.clone(),
+                record: None,
+            }],
+        );
+        assert_table_size!(&conn, "moz_places_metadata", 0);
+        assert!(fetch_outgoing(&conn).expect("should fetch").is_empty());
+    }
+
     #[test]
     fn test_note_observation() {
         let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
@@ -1115,6 +1799,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_note_observation_session_ids() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("memory db");
+        let observe = |session_id: Option<&str>, view_time| {
+            apply_metadata_observation(
+                &conn,
+                HistoryMetadataObservation {
+                    url: String::from("https://www.mozilla.org/"),
+                    view_time: Some(view_time),
+                    search_term: None,
+                    document_type: None,
+                    referrer_url: None,
+                    title: None,
+                    metadata_session_id: session_id.map(String::from),
+                },
+            )
+            .expect("should apply");
+        };
+        // Two tabs on the same page accumulate view time separately...
+        observe(Some("tab-1"), 1000);
+        observe(Some("tab-2"), 2000);
+        observe(Some("tab-1"), 3000);
+        assert_table_size!(&conn, "moz_places_metadata", 2);
+        let view_times = conn
+            .query_rows_and_then(
+                "SELECT total_view_time FROM moz_places_metadata ORDER BY session_id",
+                [],
+                |row| row.get::<_, i32>(0),
+            )
+            .expect("should select");
+        assert_eq!(view_times, vec![4000, 2000]);
+
+        // ...and separately from observations without a session.
+        observe(None, 500);
+        observe(Some(""), 500);
+        assert_table_size!(&conn, "moz_places_metadata", 3);
+    }
+
     #[test]
     fn test_note_observation_invalid_view_time() {
         let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("memory db");
@@ -1137,7 +1859,8 @@ mod tests {
                 search_term: None,
                 document_type: None,
                 referrer_url: None,
-                title: None
+                title: None,
+                metadata_session_id: None,
             }
         )
         .is_err());
@@ -1151,7 +1874,8 @@ mod tests {
                 search_term: None,
                 document_type: None,
                 referrer_url: None,
-                title: None
+                title: None,
+                metadata_session_id: None,
             }
         )
         .is_ok());
@@ -1203,6 +1927,164 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_grouped_by_search_term() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("memory db");
+        assert!(get_grouped_by_search_term(&conn, 0, i64::MAX)
+            .unwrap()
+            .is_empty());
+
+        let beginning = Timestamp::now().as_millis() as i64;
+        note_observation!(&conn,
+            url "https://www.mozilla.org/",
+            view_time Some(1000),
+            search_term Some("Mozilla"),
+            document_type Some(DocumentType::Regular),
+            referrer_url None,
+            title None
+        );
+        thread::sleep(time::Duration::from_millis(10));
+        note_observation!(&conn,
+            url "https://www.example.com/",
+            view_time Some(500),
+            search_term Some("example"),
+            document_type Some(DocumentType::Regular),
+            referrer_url None,
+            title None
+        );
+        thread::sleep(time::Duration::from_millis(10));
+        note_observation!(&conn,
+            url "https://developer.mozilla.org/",
+            view_time Some(2000),
+            search_term Some("mozilla"),
+            document_type Some(DocumentType::Regular),
+            referrer_url None,
+            title None
+        );
+        note_observation!(&conn,
+            url "https://news.website/",
+            view_time Some(3000),
+            search_term None,
+            document_type Some(DocumentType::Regular),
+            referrer_url None,
+            title None
+        );
+        let end = Timestamp::now().as_millis() as i64;
+
+        let groups = get_grouped_by_search_term(&conn, beginning, end).unwrap();
+        assert_eq!(
+            groups
+                .iter()
+                .map(|g| (
+                    g.search_term.as_str(),
+                    g.metadata
+                        .iter()
+                        .map(|m| m.url.as_str())
+                        .collect::<Vec<_>>(),
+                    g.total_view_time,
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "mozilla",
+                    vec!["https://developer.mozilla.org/", "https://www.mozilla.org/"],
+                    3000
+                ),
+                ("example", vec!["https://www.example.com/"], 500),
+            ]
+        );
+        assert_eq!(groups[0].last_updated_at, groups[0].metadata[0].updated_at);
+
+        // Only metadata updated in the range counts.
+        let groups =
+            get_grouped_by_search_term(&conn, beginning, groups[1].last_updated_at).unwrap();
+        assert_eq!(
+            groups
+                .iter()
+                .map(|g| (g.search_term.as_str(), g.metadata.len()))
+                .collect::<Vec<_>>(),
+            vec![("example", 1), ("mozilla", 1)]
+        );
+    }
+
+    #[test]
+    fn test_get_navigation_chain() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("memory db");
+        let chain_urls = |url: &str, depth: u32| -> Vec<String> {
+            get_navigation_chain(&conn, &Url::parse(url).unwrap(), depth)
+                .expect("should work")
+                .into_iter()
+                .map(|m| m.url)
+                .collect()
+        };
+        assert!(chain_urls("https://www.example.com/", 5).is_empty());
+
+        // search -> article -> related -> search, and separately the article
+        // was opened from the news site.
+        for (url, referrer_url) in [
+            ("https://www.example.com/", None),
+            ("https://news.website/", None),
+            (
+                "https://news.website/article",
+                Some("https://news.website/"),
+            ),
+            (
+                "https://www.example.com/related",
+                Some("https://news.website/article"),
+            ),
+            (
+                "https://www.example.com/",
+                Some("https://www.example.com/related"),
+            ),
+            (
+                "https://www.example.com/other",
+                Some("https://news.website/"),
+            ),
+        ] {
+            note_observation!(&conn,
+                url url,
+                view_time Some(1000),
+                search_term None,
+                document_type Some(DocumentType::Regular),
+                referrer_url referrer_url,
+                title None
+            );
+        }
+
+        assert_eq!(
+            chain_urls("https://news.website/article", 5),
+            vec![
+                "https://news.website/",
+                "https://news.website/article",
+                "https://www.example.com/related",
+                "https://www.example.com/",
+            ]
+        );
+        assert_eq!(
+            chain_urls("https://www.example.com/related", 1),
+            vec![
+                "https://news.website/article",
+                "https://www.example.com/related",
+                "https://www.example.com/",
+            ]
+        );
+        // We start from the latest entry for the page, which was opened from
+        // the related page, not the first one.
+        assert_eq!(
+            chain_urls("https://www.example.com/", 10),
+            vec![
+                "https://news.website/",
+                "https://news.website/article",
+                "https://www.example.com/related",
+                "https://www.example.com/",
+            ]
+        );
+        assert_eq!(
+            chain_urls("https://www.example.com/", 0),
+            vec!["https://www.example.com/"]
+        );
+    }
+
     #[test]
     fn test_get_since() {
         let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("memory db");
@@ -1733,6 +2615,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_delete_by_search_term() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("memory db");
+
+        for (url, search_term) in [
+            ("http://mozilla.com/1", "Cute Cat"),
+            ("http://mozilla.com/2", "cute cat"),
+            ("http://mozilla.com/3", "dogs"),
+        ] {
+            note_observation!(&conn,
+                url url,
+                view_time Some(20000),
+                search_term Some(search_term),
+                document_type Some(DocumentType::Regular),
+                referrer_url None,
+                title None
+            );
+        }
+
+        // Deleting a term nothing was found with does nothing.
+        delete_by_search_term(&conn, "birds").expect("delete worked");
+        assert_eq!(3, get_since(&conn, 0).expect("get worked").len());
+
+        delete_by_search_term(&conn, "CUTE cat").expect("delete worked");
+        let remaining = get_since(&conn, 0).expect("get worked");
+        assert_eq!(1, remaining.len());
+        assert_eq!("http://mozilla.com/3", remaining[0].url);
+        assert_table_size!(&conn, "moz_places_metadata_search_queries", 1);
+        assert_table_size!(&conn, "moz_places_metadata_tombstones", 2);
+
+        // The pages don't count towards highlights any more.
+        let highlights = get_highlights(
+            &conn,
+            HistoryHighlightWeights {
+                view_time: 1.0,
+                frequency: 1.0,
+            },
+            10,
+        )
+        .expect("got highlights");
+        assert_eq!(
+            highlights
+                .iter()
+                .map(|h| h.url.as_str())
+                .collect::<Vec<_>>(),
+            vec!["http://mozilla.com/3"]
+        );
+    }
+
     #[test]
     fn test_metadata_deletes_do_not_affect_places() {
         let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("memory db");
@@ -1796,7 +2727,7 @@ mod tests {
 
         // Check the DB is what we expect before deleting.
         assert_eq!(
-            get_visit_count(&conn, VisitTransitionSet::empty()).unwrap(),
+            get_visit_count(&conn, VisitTransitionSet::empty(), None).unwrap(),
             1
         );
         let place_guid = url_to_guid(&conn, &url)
@@ -1836,7 +2767,7 @@ mod tests {
 
         // Check the DB is what we expect before deleting.
         assert_eq!(
-            get_visit_count(&conn, VisitTransitionSet::empty()).unwrap(),
+            get_visit_count(&conn, VisitTransitionSet::empty(), None).unwrap(),
             1
         );
         let place_guid = url_to_guid(&conn, &url)
@@ -1868,7 +2799,7 @@ mod tests {
 
         // Check the DB is what we expect before deleting.
         assert_eq!(
-            get_visit_count(&conn, VisitTransitionSet::empty()).unwrap(),
+            get_visit_count(&conn, VisitTransitionSet::empty(), None).unwrap(),
             0
         );
         let place_guid = url_to_guid(&conn, &url)
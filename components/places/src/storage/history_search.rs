@@ -0,0 +1,236 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Searching visited pages by title and URL, for a full history search UI.
+// Unlike `query_autocomplete`, which is tuned for the URL bar, the caller
+// picks how results are matched and ranked, and can include hidden pages.
+
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::match_impl::{MatchBehavior, SearchBehavior};
+use sql_support::ConnExt;
+use types::Timestamp;
+use url::Url;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HistorySearchRanking {
+    Frecency,
+    /// Most recently visited first.
+    Recency,
+    /// Most visited first. Pages visited the same number of times are
+    /// ordered by frecency.
+    VisitCount,
+}
+
+impl HistorySearchRanking {
+    fn order_by(self) -> &'static str {
+        match self {
+            HistorySearchRanking::Frecency => "h.frecency DESC",
+            HistorySearchRanking::Recency => "last_visit_date DESC",
+            HistorySearchRanking::VisitCount => "visit_count DESC, h.frecency DESC",
+        }
+    }
+}
+
+/// How each word in the query must match a page's title or URL.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HistorySearchMatchMode {
+    /// The start of a word.
+    Prefix,
+    /// Anywhere.
+    Substring,
+    /// A whole word.
+    WholeWord,
+}
+
+impl From<HistorySearchMatchMode> for MatchBehavior {
+    fn from(mode: HistorySearchMatchMode) -> Self {
+        match mode {
+            HistorySearchMatchMode::Prefix => MatchBehavior::Boundary,
+            HistorySearchMatchMode::Substring => MatchBehavior::Anywhere,
+            HistorySearchMatchMode::WholeWord => MatchBehavior::WholeWord,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistorySearchOptions {
+    pub ranking: HistorySearchRanking,
+    pub match_mode: HistorySearchMatchMode,
+    /// Whether to include pages which are hidden from history, like the
+    /// sources of redirects.
+    pub include_hidden: bool,
+    pub limit: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistorySearchResult {
+    pub url: Url,
+    pub title: Option<String>,
+    pub last_visit_date: Timestamp,
+    pub visit_count: u32,
+    pub frecency: i64,
+}
+
+impl HistorySearchResult {
+    fn from_row(row: &rusqlite::Row<'_>) -> Result<Self> {
+        let url: String = row.get("url")?;
+        Ok(Self {
+            url: Url::parse(&url)?,
+            title: row.get("title")?,
+            last_visit_date: row.get("last_visit_date")?,
+            visit_count: row.get("visit_count")?,
+            frecency: row.get("frecency")?,
+        })
+    }
+}
+
+/// Returns visited pages whose title or URL match every word in `query`,
+/// ranked as `options` asks. An empty query matches nothing.
+pub fn search_history(
+    db: &PlacesDb,
+    query: &str,
+    options: &HistorySearchOptions,
+) -> Result<Vec<HistorySearchResult>> {
+    if query.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let scope = db.begin_interrupt_scope()?;
+    db.query_rows_and_then_cached(
+        &format!(
+            "SELECT h.url, h.title, h.frecency,
+                    h.visit_count_local + h.visit_count_remote AS visit_count,
+                    MAX(h.last_visit_date_local, h.last_visit_date_remote) AS last_visit_date
+             FROM moz_places h
+             WHERE (h.visit_count_local + h.visit_count_remote) > 0
+               AND (:include_hidden OR NOT h.hidden)
+               AND AUTOCOMPLETE_MATCH(:query, h.url, h.title, NULL,
+                                      h.visit_count_local + h.visit_count_remote,
+                                      h.typed, 0, NULL, :match_behavior,
+                                      {search_behavior})
+             ORDER BY {order_by}, h.id
+             LIMIT :limit",
+            search_behavior = SearchBehavior::HISTORY.bits(),
+            order_by = options.ranking.order_by(),
+        ),
+        rusqlite::named_params! {
+            ":query": query,
+            ":include_hidden": options.include_hidden,
+            ":match_behavior": MatchBehavior::from(options.match_mode),
+            ":limit": options.limit,
+        },
+        |row| -> Result<_> {
+            scope.err_if_interrupted()?;
+            HistorySearchResult::from_row(row)
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::apply_observation;
+    use crate::types::VisitTransition;
+
+    fn visit(conn: &PlacesDb, url: &str, title: &str, at: u64) {
+        apply_observation(
+            conn,
+            VisitObservation::new(Url::parse(url).unwrap())
+                .with_title(title.to_string())
+                .with_visit_type(VisitTransition::Link)
+                .with_at(Timestamp(at)),
+        )
+        .expect("should apply");
+    }
+
+    fn search(conn: &PlacesDb, query: &str, options: &HistorySearchOptions) -> Vec<String> {
+        search_history(conn, query, options)
+            .expect("should search")
+            .into_iter()
+            .map(|result| result.url.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_search_history() {
+        let conn = new_mem_connection();
+        let now = Timestamp::now().as_millis();
+        visit(
+            &conn,
+            "https://example.com/fox",
+            "The quick fox",
+            now - 3000,
+        );
+        visit(
+            &conn,
+            "https://example.com/fox",
+            "The quick fox",
+            now - 2000,
+        );
+        visit(&conn, "https://example.com/foxes", "Foxes", now - 1000);
+        visit(&conn, "https://example.org/firefox", "Firefox", now);
+
+        let mut options = HistorySearchOptions {
+            ranking: HistorySearchRanking::Recency,
+            match_mode: HistorySearchMatchMode::Substring,
+            include_hidden: false,
+            limit: 10,
+        };
+        assert_eq!(
+            search(&conn, "fox", &options),
+            vec![
+                "https://example.org/firefox",
+                "https://example.com/foxes",
+                "https://example.com/fox",
+            ]
+        );
+        // Every word has to match.
+        assert_eq!(
+            search(&conn, "fox example.com", &options),
+            vec!["https://example.com/foxes", "https://example.com/fox"]
+        );
+        assert!(search(&conn, "  ", &options).is_empty());
+
+        options.match_mode = HistorySearchMatchMode::Prefix;
+        options.ranking = HistorySearchRanking::VisitCount;
+        assert_eq!(
+            search(&conn, "fox", &options),
+            vec!["https://example.com/fox", "https://example.com/foxes"]
+        );
+
+        options.match_mode = HistorySearchMatchMode::WholeWord;
+        assert_eq!(
+            search(&conn, "fox", &options),
+            vec!["https://example.com/fox"]
+        );
+
+        let result = &search_history(&conn, "quick", &options).expect("should search")[0];
+        assert_eq!(result.title.as_deref(), Some("The quick fox"));
+        assert_eq!(result.visit_count, 2);
+        assert_eq!(result.last_visit_date, Timestamp(now - 2000));
+    }
+
+    #[test]
+    fn test_search_history_hidden() {
+        let conn = new_mem_connection();
+        visit(&conn, "https://example.com/", "Example", Timestamp::now().0);
+        conn.execute_batch("UPDATE moz_places SET hidden = 1")
+            .expect("should hide");
+
+        let mut options = HistorySearchOptions {
+            ranking: HistorySearchRanking::Frecency,
+            match_mode: HistorySearchMatchMode::Substring,
+            include_hidden: false,
+            limit: 10,
+        };
+        assert!(search(&conn, "example", &options).is_empty());
+        options.include_hidden = true;
+        assert_eq!(
+            search(&conn, "example", &options),
+            vec!["https://example.com/"]
+        );
+    }
+}
@@ -6,23 +6,32 @@
 // API and the database.
 
 pub mod bookmarks;
+pub mod changes;
+pub mod deletion_policy;
 pub mod history;
 pub mod history_metadata;
+pub mod history_search;
+pub mod page_metadata_queue;
+pub mod pinned_sites;
 pub mod tags;
+pub mod targeting;
 
+use crate::api::history::can_add_url;
 use crate::db::PlacesDb;
 use crate::error::{Error, InvalidPlaceInfo, Result};
 use crate::ffi::HistoryVisitInfo;
 use crate::ffi::TopFrecentSiteInfo;
-use crate::frecency::{calculate_frecency, DEFAULT_FRECENCY_SETTINGS};
+use crate::ffi::TypedUrlInfo;
+use crate::frecency::calculate_frecency;
 use crate::types::{SyncStatus, VisitTransition};
 use interrupt_support::SqlInterruptScope;
 use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use rusqlite::Result as RusqliteResult;
 use rusqlite::Row;
 use serde_derive::*;
-use sql_support::{self, ConnExt};
+use sql_support::{self, maintenance, ConnExt};
 use std::fmt;
+use std::time::{Duration, Instant};
 use sync_guid::Guid as SyncGuid;
 use types::Timestamp;
 use url::Url;
@@ -33,6 +42,47 @@ pub const TITLE_LENGTH_MAX: usize = 4096;
 pub const TAG_LENGTH_MAX: usize = 100;
 // pub const DESCRIPTION_LENGTH_MAX: usize = 256;
 
+/// What Places does with a URL, so that UI like a bookmark star or a history
+/// toggle can match what storage will do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlClassification {
+    /// Whether visits to the URL are recorded in history.
+    pub can_record_history: bool,
+    /// Whether the URL can be bookmarked.
+    pub can_bookmark: bool,
+    /// The URL as Places stores it - for example, with its host lowercased
+    /// and punycoded - or `None` if it isn't a valid URL.
+    pub normalized_url: Option<String>,
+}
+
+/// Classifies `url` the same way storage does when recording visits to it
+/// and bookmarking it.
+pub fn classify_url(url: &str) -> UrlClassification {
+    match Url::parse(url) {
+        Ok(url) => UrlClassification {
+            can_record_history: can_record_history(&url),
+            can_bookmark: can_bookmark(&url),
+            normalized_url: Some(url.into()),
+        },
+        Err(_) => UrlClassification {
+            can_record_history: false,
+            can_bookmark: false,
+            normalized_url: None,
+        },
+    }
+}
+
+// Whether visits to `url` are recorded. Observations of other URLs are
+// silently ignored.
+pub(crate) fn can_record_history(url: &Url) -> bool {
+    url.as_str().len() <= URL_LENGTH_MAX && matches!(can_add_url(url), Ok(true))
+}
+
+// Whether `url` can be bookmarked. Inserting bookmarks of other URLs fails.
+pub(crate) fn can_bookmark(url: &Url) -> bool {
+    url.as_str().len() <= URL_LENGTH_MAX
+}
+
 // Typesafe way to manage RowIds. Does it make sense? A better way?
 #[derive(
     Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Deserialize, Serialize, Default, Hash,
@@ -162,7 +212,7 @@ fn new_page_info(db: &PlacesDb, url: &Url, new_guid: Option<SyncGuid>) -> Result
         None => SyncGuid::random(),
     };
     let url_str = url.as_str();
-    if url_str.len() > URL_LENGTH_MAX {
+    if !can_bookmark(url) {
         // Generally callers check this first (bookmarks don't, history does).
         return Err(Error::InvalidPlaceInfo(InvalidPlaceInfo::UrlTooLong));
     }
@@ -197,6 +247,7 @@ impl HistoryVisitInfo {
         let visit_date: Timestamp = row.get("visit_date")?;
         let url: String = row.get("url")?;
         let preview_image_url: Option<String> = row.get("preview_image_url")?;
+        let referrer_url: Option<String> = row.get("referrer_url")?;
         Ok(Self {
             url: Url::parse(&url)?,
             title: row.get("title")?,
@@ -208,6 +259,11 @@ impl HistoryVisitInfo {
                 None => None,
             },
             is_remote: !row.get("is_local")?,
+            referrer_url: match referrer_url {
+                Some(s) => Some(Url::parse(&s)?),
+                None => None,
+            },
+            container_id: row.get("container_id")?,
         })
     }
 }
@@ -218,6 +274,20 @@ impl TopFrecentSiteInfo {
         Ok(Self {
             url: Url::parse(&url)?,
             title: row.get("title")?,
+            frecency: row.get("frecency")?,
+            visit_count: row.get("visit_count")?,
+        })
+    }
+}
+
+impl TypedUrlInfo {
+    pub(crate) fn from_row(row: &rusqlite::Row<'_>) -> Result<Self> {
+        let url: String = row.get("url")?;
+        Ok(Self {
+            url: Url::parse(&url)?,
+            title: row.get("title")?,
+            typed_count: row.get("typed")?,
+            last_visit_date: row.get("last_visit_date")?,
         })
     }
 }
@@ -226,8 +296,14 @@ pub struct RunMaintenanceMetrics {
     pub pruned_visits: bool,
     pub db_size_before: u32,
     pub db_size_after: u32,
+    /// The number of pages removed because their last visit was pruned.
+    pub pages_pruned: u32,
+    pub duration_ms: u64,
 }
 
+// The other maintenance steps are the same for all our databases.
+pub use sql_support::maintenance::MaintenanceStepMetrics;
+
 /// Run maintenance on the places DB (prune step)
 ///
 /// The `run_maintenance_*()` functions are intended to be run during idle time and will take steps
@@ -237,17 +313,37 @@ pub struct RunMaintenanceMetrics {
 ///
 /// db_size_limit is the approximate storage limit in bytes.  If the database is using more space
 /// than this, some older visits will be deleted to free up space.  Pass in a 0 to skip this.
+///
+/// This also enforces the history deletion policy, if one has been set with
+/// `set_history_deletion_policy()`, repairs the positions of bookmarks,
+/// purges bookmarks which have been in the trash for too long, and decays the
+/// results the user has accepted from autocomplete.
 pub fn run_maintenance_prune(conn: &PlacesDb, db_size_limit: u32) -> Result<RunMaintenanceMetrics> {
+    let start = Instant::now();
+    let now = Timestamp::now();
+    deletion_policy::enforce_deletion_policy(conn, now)?;
+    bookmarks::repair_positions(conn)?;
+    if let Some(older_than) = now.checked_sub(bookmarks::trash::TRASH_RETENTION) {
+        bookmarks::trash::purge_trash(conn, older_than)?;
+    }
+    if let Some(older_than) = now.checked_sub(changes::DELETED_CHANGE_RETENTION) {
+        changes::prune_deleted_changes(conn, older_than)?;
+    }
+    crate::api::matcher::decay_adaptive_history(conn, now)?;
     let db_size_before = conn.get_db_size()?;
     let should_prune = db_size_limit > 0 && db_size_before > db_size_limit;
-    if should_prune {
-        history::prune_older_visits(conn)?;
-    }
+    let pages_pruned = if should_prune {
+        history::prune_older_visits(conn)?
+    } else {
+        0
+    };
     let db_size_after = conn.get_db_size()?;
     Ok(RunMaintenanceMetrics {
         pruned_visits: should_prune,
         db_size_before,
         db_size_after,
+        pages_pruned,
+        duration_ms: start.elapsed().as_millis() as u64,
     })
 }
 
@@ -257,9 +353,8 @@ pub fn run_maintenance_prune(conn: &PlacesDb, db_size_limit: u32) -> Result<RunM
 /// to clean up / shrink the database.  They're split up so that we can time each one in the
 /// Kotlin wrapper code (This is needed because we only have access to the Glean API in Kotlin and
 /// it supports a stop-watch style API, not recording specific values).
-pub fn run_maintenance_vacuum(conn: &PlacesDb) -> Result<()> {
-    conn.execute_one("VACUUM")?;
-    Ok(())
+pub fn run_maintenance_vacuum(conn: &PlacesDb) -> Result<MaintenanceStepMetrics> {
+    Ok(maintenance::vacuum(conn)?)
 }
 
 /// Run maintenance on the places DB (optimize step)
@@ -268,9 +363,8 @@ pub fn run_maintenance_vacuum(conn: &PlacesDb) -> Result<()> {
 /// to clean up / shrink the database.  They're split up so that we can time each one in the
 /// Kotlin wrapper code (This is needed because we only have access to the Glean API in Kotlin and
 /// it supports a stop-watch style API, not recording specific values).
-pub fn run_maintenance_optimize(conn: &PlacesDb) -> Result<()> {
-    conn.execute_one("PRAGMA optimize")?;
-    Ok(())
+pub fn run_maintenance_optimize(conn: &PlacesDb) -> Result<MaintenanceStepMetrics> {
+    Ok(maintenance::optimize(conn)?)
 }
 
 /// Run maintenance on the places DB (checkpoint step)
@@ -279,9 +373,44 @@ pub fn run_maintenance_optimize(conn: &PlacesDb) -> Result<()> {
 /// to clean up / shrink the database.  They're split up so that we can time each one in the
 /// Kotlin wrapper code (This is needed because we only have access to the Glean API in Kotlin and
 /// it supports a stop-watch style API, not recording specific values).
-pub fn run_maintenance_checkpoint(conn: &PlacesDb) -> Result<()> {
-    conn.execute_one("PRAGMA wal_checkpoint(PASSIVE)")?;
-    Ok(())
+pub fn run_maintenance_checkpoint(conn: &PlacesDb) -> Result<MaintenanceStepMetrics> {
+    Ok(maintenance::checkpoint(conn)?)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlacesDataSummary {
+    pub visit_count: i64,
+    /// The number of pages with at least one visit.
+    pub page_count: i64,
+    /// The number of bookmarks, not including folders and separators.
+    pub bookmark_count: i64,
+    pub metadata_count: i64,
+    /// The approximate size of the database, in bytes.
+    pub db_size: u32,
+}
+
+/// Returns how much data places has, for a "manage data" screen.
+pub fn get_data_summary(conn: &PlacesDb) -> Result<PlacesDataSummary> {
+    let mut summary = conn.query_row_and_then_cachable(
+        "SELECT
+            (SELECT COUNT(*) FROM moz_historyvisits),
+            (SELECT COUNT(DISTINCT place_id) FROM moz_historyvisits),
+            (SELECT COUNT(*) FROM moz_bookmarks WHERE type = 1),
+            (SELECT COUNT(*) FROM moz_places_metadata)",
+        [],
+        |row| -> Result<_> {
+            Ok(PlacesDataSummary {
+                visit_count: row.get(0)?,
+                page_count: row.get(1)?,
+                bookmark_count: row.get(2)?,
+                metadata_count: row.get(3)?,
+                db_size: 0,
+            })
+        },
+        true,
+    )?;
+    summary.db_size = conn.get_db_size()?;
+    Ok(summary)
 }
 
 pub fn update_all_frecencies_at_once(db: &PlacesDb, scope: &SqlInterruptScope) -> Result<()> {
@@ -299,7 +428,7 @@ pub fn update_all_frecencies_at_once(db: &PlacesDb, scope: &SqlInterruptScope) -
             scope.err_if_interrupted()?;
             Ok((
                 *places_id,
-                calculate_frecency(db, &DEFAULT_FRECENCY_SETTINGS, *places_id, Some(false))?,
+                calculate_frecency(db, db.frecency_settings(), *places_id, Some(false))?,
             ))
         })
         .collect::<Result<Vec<(i64, i32)>>>()?;
@@ -338,6 +467,60 @@ pub fn update_all_frecencies_at_once(db: &PlacesDb, scope: &SqlInterruptScope) -
     Ok(())
 }
 
+pub struct FrecencyUpdateMetrics {
+    pub num_updated: u32,
+    pub num_remaining: u32,
+}
+
+/// Recalculates stale frecencies in chunks of `chunk_size`, until there are
+/// none left or `max_duration` has passed. Unlike `update_frecencies`, each
+/// chunk is committed separately, so this can run in the background after an
+/// import without holding up other writes for long. Interrupting the
+/// connection stops it between chunks; the chunks it already finished stay
+/// done.
+pub fn run_pending_frecency_updates(
+    db: &PlacesDb,
+    chunk_size: u32,
+    max_duration: Duration,
+) -> Result<FrecencyUpdateMetrics> {
+    let scope = db.begin_interrupt_scope()?;
+    let start = Instant::now();
+    let chunk_size = chunk_size.max(1);
+    let mut num_updated = 0;
+    loop {
+        scope.err_if_interrupted()?;
+        let tx = db.begin_transaction()?;
+        let place_ids = db.query_rows_and_then_cached(
+            "SELECT place_id FROM moz_places_stale_frecencies
+             ORDER BY stale_at DESC
+             LIMIT :limit",
+            &[(":limit", &chunk_size)],
+            |row| row.get::<_, i64>(0),
+        )?;
+        for place_id in &place_ids {
+            let frecency = calculate_frecency(db, db.frecency_settings(), *place_id, Some(false))?;
+            db.execute_cached(
+                "UPDATE moz_places SET frecency = :frecency WHERE id = :id",
+                &[(":frecency", &frecency as &dyn ToSql), (":id", place_id)],
+            )?;
+            db.execute_cached(
+                "DELETE FROM moz_places_stale_frecencies WHERE place_id = :id",
+                &[(":id", place_id)],
+            )?;
+        }
+        tx.commit()?;
+        num_updated += place_ids.len() as u32;
+        if place_ids.len() < chunk_size as usize || start.elapsed() >= max_duration {
+            break;
+        }
+    }
+    let num_remaining = db.query_one("SELECT COUNT(*) FROM moz_places_stale_frecencies")?;
+    Ok(FrecencyUpdateMetrics {
+        num_updated,
+        num_remaining,
+    })
+}
+
 pub(crate) fn put_meta(db: &PlacesDb, key: &str, value: &dyn ToSql) -> Result<()> {
     db.execute_cached(
         "REPLACE INTO moz_meta (key, value) VALUES (:key, :value)",
@@ -374,6 +557,57 @@ pub fn delete_pending_temp_tables(conn: &PlacesDb) -> Result<()> {
 mod tests {
     use super::*;
     use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+
+    #[test]
+    fn test_classify_url() {
+        assert_eq!(
+            classify_url("HTTPS://EXAMPLE.com/a b"),
+            UrlClassification {
+                can_record_history: true,
+                can_bookmark: true,
+                normalized_url: Some("https://example.com/a%20b".to_string()),
+            }
+        );
+        assert_eq!(
+            classify_url("not a url"),
+            UrlClassification {
+                can_record_history: false,
+                can_bookmark: false,
+                normalized_url: None,
+            }
+        );
+
+        // Storage does what the classification says.
+        let conn = new_mem_connection();
+        let long_url = format!("https://example.com/{}", "x".repeat(URL_LENGTH_MAX));
+        let classification = classify_url(&long_url);
+        assert!(!classification.can_record_history);
+        assert!(!classification.can_bookmark);
+        let url = Url::parse(&long_url).unwrap();
+        assert_eq!(
+            history::apply_observation(
+                &conn,
+                VisitObservation::new(url.clone()).with_visit_type(VisitTransition::Link)
+            )
+            .expect("should ignore"),
+            None
+        );
+        assert!(bookmarks::insert_bookmark(
+            &conn,
+            bookmarks::InsertableBookmark {
+                parent_guid: bookmarks::BookmarkRootGuid::Unfiled.into(),
+                position: bookmarks::BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url,
+                title: None,
+            }
+            .into(),
+        )
+        .is_err());
+    }
 
     #[test]
     fn test_meta() {
@@ -396,4 +630,147 @@ mod tests {
             .is_none());
         delete_meta(&conn, "foo").expect("delete non-existing should work");
     }
+
+    #[test]
+    fn test_run_pending_frecency_updates() {
+        let conn = new_mem_connection();
+        for i in 0..3 {
+            let url = Url::parse(&format!("https://example.com/{}", i)).unwrap();
+            history::apply_observation(
+                &conn,
+                VisitObservation::new(url).with_visit_type(VisitTransition::Link),
+            )
+            .expect("should apply");
+        }
+        conn.execute_batch(
+            "UPDATE moz_places SET frecency = -1;
+             INSERT INTO moz_places_stale_frecencies(place_id, stale_at)
+             SELECT id, 0 FROM moz_places",
+        )
+        .expect("should mark stale");
+
+        // We always finish at least one chunk, even if we're out of time.
+        let metrics = run_pending_frecency_updates(&conn, 2, Duration::from_millis(0))
+            .expect("should update");
+        assert_eq!((metrics.num_updated, metrics.num_remaining), (2, 1));
+
+        let metrics =
+            run_pending_frecency_updates(&conn, 2, Duration::from_secs(60)).expect("should update");
+        assert_eq!((metrics.num_updated, metrics.num_remaining), (1, 0));
+        assert_eq!(
+            conn.query_one::<u32>("SELECT COUNT(*) FROM moz_places WHERE frecency <= 0")
+                .expect("should count"),
+            0
+        );
+
+        let metrics =
+            run_pending_frecency_updates(&conn, 2, Duration::from_secs(60)).expect("should update");
+        assert_eq!((metrics.num_updated, metrics.num_remaining), (0, 0));
+    }
+
+    #[test]
+    fn test_run_maintenance_metrics() {
+        let conn = new_mem_connection();
+        for (url, at) in [
+            ("https://example.com/a", Timestamp(1000)),
+            ("https://example.com/b", Timestamp(2000)),
+            ("https://example.com/c", Timestamp(3000)),
+            // A recent visit keeps its page around.
+            ("https://example.com/c", Timestamp::now()),
+        ] {
+            history::apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_visit_type(VisitTransition::Link)
+                    .with_at(at),
+            )
+            .expect("should apply");
+        }
+
+        let metrics = run_maintenance_prune(&conn, 0).expect("should run");
+        assert!(!metrics.pruned_visits);
+        assert_eq!(metrics.pages_pruned, 0);
+
+        let metrics = run_maintenance_prune(&conn, 1).expect("should run");
+        assert!(metrics.pruned_visits);
+        assert_eq!(metrics.pages_pruned, 2);
+        assert_eq!(
+            conn.query_one::<u32>("SELECT COUNT(*) FROM moz_historyvisits")
+                .expect("should count"),
+            1
+        );
+
+        let metrics = run_maintenance_vacuum(&conn).expect("should vacuum");
+        assert_eq!(metrics.freelist_count_after, 0);
+        run_maintenance_optimize(&conn).expect("should optimize");
+        run_maintenance_checkpoint(&conn).expect("should checkpoint");
+    }
+
+    #[test]
+    fn test_get_data_summary() {
+        let conn = new_mem_connection();
+        let empty = get_data_summary(&conn).expect("should summarize");
+        assert_eq!(
+            (
+                empty.visit_count,
+                empty.page_count,
+                empty.bookmark_count,
+                empty.metadata_count
+            ),
+            (0, 0, 0, 0)
+        );
+        assert!(empty.db_size > 0);
+
+        for (url, at) in [
+            ("https://example.com/", 1000),
+            ("https://example.com/", 2000),
+            ("https://mozilla.org/", 3000),
+        ] {
+            history::apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_visit_type(VisitTransition::Link)
+                    .with_at(Timestamp(at)),
+            )
+            .expect("should apply");
+        }
+        bookmarks::insert_bookmark(
+            &conn,
+            bookmarks::InsertableBookmark {
+                parent_guid: bookmarks::BookmarkRootGuid::Unfiled.into(),
+                position: bookmarks::BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("https://firefox.com/").unwrap(),
+                title: None,
+            }
+            .into(),
+        )
+        .expect("should insert");
+        history_metadata::apply_metadata_observation(
+            &conn,
+            history_metadata::HistoryMetadataObservation {
+                url: "https://example.com/".into(),
+                view_time: Some(1000),
+                search_term: None,
+                document_type: None,
+                referrer_url: None,
+                title: None,
+                metadata_session_id: None,
+            },
+        )
+        .expect("should apply");
+
+        let summary = get_data_summary(&conn).expect("should summarize");
+        assert_eq!(
+            (
+                summary.visit_count,
+                summary.page_count,
+                summary.bookmark_count,
+                summary.metadata_count
+            ),
+            (3, 2, 1, 1)
+        );
+    }
 }
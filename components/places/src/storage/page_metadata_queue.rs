@@ -0,0 +1,183 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// A work queue for apps which fill in missing page metadata - titles, icons
+// and preview images - in the background. The app asks for pages which are
+// missing some kind of metadata, tries to fetch it, and marks the pages as
+// attempted, whether or not it succeeded. Pages which were attempted recently
+// aren't returned again, so a page which can't be fetched doesn't block the
+// rest of the queue.
+
+use crate::db::PlacesDb;
+use crate::error::*;
+use rusqlite::types::{ToSql, ToSqlOutput};
+use sql_support::ConnExt;
+use types::Timestamp;
+use url::Url;
+
+/// Pages attempted more recently than this aren't returned again.
+const RETRY_AFTER_MS: u64 = 7 * 24 * 60 * 60 * 1000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PageMetadataKind {
+    /// Pages without a title.
+    Title = 1,
+    /// We don't store icons, so every page is missing one until the app
+    /// marks it as attempted. Apps keep the icons they fetch themselves.
+    Icon = 2,
+    /// Pages without a preview image URL.
+    PreviewImage = 3,
+}
+
+impl PageMetadataKind {
+    fn missing_condition(self) -> &'static str {
+        match self {
+            PageMetadataKind::Title => "IFNULL(h.title, '') = ''",
+            PageMetadataKind::Icon => "1",
+            PageMetadataKind::PreviewImage => "h.preview_image_url IS NULL",
+        }
+    }
+}
+
+impl ToSql for PageMetadataKind {
+    #[inline]
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(*self as u8))
+    }
+}
+
+/// Returns up to `limit` URLs of pages missing `kind` metadata, which haven't
+/// been attempted recently, most frecent first. Hidden pages, like the
+/// sources of redirects, aren't worth fetching, so they're skipped.
+pub fn get_pages_missing_metadata(
+    db: &PlacesDb,
+    kind: PageMetadataKind,
+    limit: u32,
+) -> Result<Vec<Url>> {
+    let retry_before = Timestamp::now().0.saturating_sub(RETRY_AFTER_MS);
+    let urls = db.query_rows_and_then_cached(
+        &format!(
+            "SELECT h.url
+             FROM moz_places h
+             WHERE NOT h.hidden
+               AND {missing}
+               AND NOT EXISTS(SELECT 1 FROM moz_places_metadata_fetch_attempts a
+                              WHERE a.place_id = h.id
+                                AND a.kind = :kind
+                                AND a.attempted_at > :retry_before)
+             ORDER BY h.frecency DESC
+             LIMIT :limit",
+            missing = kind.missing_condition()
+        ),
+        rusqlite::named_params! {
+            ":kind": kind,
+            ":retry_before": retry_before,
+            ":limit": limit,
+        },
+        |row| row.get::<_, String>(0),
+    )?;
+    // We validate URLs before they're added, so this should never skip
+    // anything.
+    Ok(urls
+        .into_iter()
+        .filter_map(|url| Url::parse(&url).ok())
+        .collect())
+}
+
+/// Records that the app tried to fetch `kind` metadata for `urls`. URLs which
+/// aren't in places are ignored.
+pub fn mark_attempted(db: &PlacesDb, kind: PageMetadataKind, urls: &[Url]) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    let now = Timestamp::now();
+    for url in urls {
+        db.execute_cached(
+            "INSERT OR REPLACE INTO moz_places_metadata_fetch_attempts(place_id, kind, attempted_at)
+             SELECT id, :kind, :now
+             FROM moz_places
+             WHERE url_hash = hash(:url) AND url = :url",
+            rusqlite::named_params! {
+                ":kind": kind,
+                ":now": now,
+                ":url": url.as_str(),
+            },
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::apply_observation;
+    use crate::types::VisitTransition;
+
+    fn visit(conn: &PlacesDb, url: &str, title: Option<&str>) -> Url {
+        let url = Url::parse(url).unwrap();
+        let mut obs = VisitObservation::new(url.clone()).with_visit_type(VisitTransition::Link);
+        if let Some(title) = title {
+            obs = obs.with_title(title.to_string());
+        }
+        apply_observation(conn, obs).expect("should apply");
+        url
+    }
+
+    #[test]
+    fn test_pages_missing_metadata() {
+        let conn = new_mem_connection();
+        let titled = visit(&conn, "https://example.com/titled", Some("Titled"));
+        let untitled = visit(&conn, "https://example.com/untitled", None);
+
+        assert_eq!(
+            get_pages_missing_metadata(&conn, PageMetadataKind::Title, 10).expect("should work"),
+            vec![untitled.clone()]
+        );
+        let mut missing_icons =
+            get_pages_missing_metadata(&conn, PageMetadataKind::Icon, 10).expect("should work");
+        missing_icons.sort();
+        assert_eq!(missing_icons, vec![titled.clone(), untitled.clone()]);
+        assert_eq!(
+            get_pages_missing_metadata(&conn, PageMetadataKind::Icon, 1)
+                .expect("should work")
+                .len(),
+            1
+        );
+
+        // Attempting one kind of metadata doesn't affect the others.
+        mark_attempted(
+            &conn,
+            PageMetadataKind::Title,
+            &[
+                untitled.clone(),
+                Url::parse("https://example.com/unknown").unwrap(),
+            ],
+        )
+        .expect("should mark");
+        assert!(
+            get_pages_missing_metadata(&conn, PageMetadataKind::Title, 10)
+                .expect("should work")
+                .is_empty()
+        );
+        assert_eq!(
+            get_pages_missing_metadata(&conn, PageMetadataKind::PreviewImage, 10)
+                .expect("should work")
+                .len(),
+            2
+        );
+
+        // Old attempts are retried.
+        conn.execute_cached(
+            "UPDATE moz_places_metadata_fetch_attempts SET attempted_at = 0",
+            [],
+        )
+        .expect("should update");
+        assert_eq!(
+            get_pages_missing_metadata(&conn, PageMetadataKind::Title, 10).expect("should work"),
+            vec![untitled]
+        );
+    }
+}
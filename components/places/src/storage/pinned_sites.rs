@@ -0,0 +1,137 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Sites the user pinned to their top sites. Fennec stored these as bookmarks
+// in a special folder, and `import_pinned_sites` still returns them that way,
+// but they aren't bookmarks: they don't show up in the bookmarks tree, and
+// they aren't synced.
+//
+// Pinning a site keeps its page around when history is cleared, like
+// bookmarking it does.
+
+use super::{fetch_page_info, new_page_info};
+use crate::db::PlacesDb;
+use crate::error::*;
+use sql_support::ConnExt;
+use types::Timestamp;
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PinnedSite {
+    pub url: Url,
+    pub title: Option<String>,
+    pub date_added: Timestamp,
+}
+
+/// Pins `url` after the other pinned sites. If it's already pinned, it keeps
+/// its position, and its title is replaced with `title`.
+pub fn pin_site(db: &PlacesDb, url: &Url, title: Option<&str>) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    let place_id = match fetch_page_info(db, url)? {
+        Some(info) => info.page.row_id,
+        None => new_page_info(db, url, None)?.row_id,
+    };
+    db.execute_cached(
+        "INSERT INTO moz_pinned_sites(place_id, title, position, date_added)
+         VALUES(:place_id, :title,
+                (SELECT IFNULL(MAX(position) + 1, 0) FROM moz_pinned_sites),
+                :date_added)
+         ON CONFLICT(place_id) DO UPDATE SET
+           title = excluded.title",
+        rusqlite::named_params! {
+            ":place_id": place_id,
+            ":title": title,
+            ":date_added": Timestamp::now(),
+        },
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Unpins `url`. Returns `false` if it wasn't pinned.
+pub fn unpin_site(db: &PlacesDb, url: &Url) -> Result<bool> {
+    let changes = db.execute_cached(
+        "DELETE FROM moz_pinned_sites
+         WHERE place_id = (SELECT id FROM moz_places
+                           WHERE url_hash = hash(:url) AND url = :url)",
+        &[(":url", &url.as_str())],
+    )?;
+    Ok(changes > 0)
+}
+
+/// Returns the pinned sites, in the order they were pinned.
+pub fn get_pinned_sites(db: &PlacesDb) -> Result<Vec<PinnedSite>> {
+    db.query_rows_and_then_cached(
+        "SELECT h.url, p.title, p.date_added
+         FROM moz_pinned_sites p
+         JOIN moz_places h ON h.id = p.place_id
+         ORDER BY p.position",
+        [],
+        |row| -> Result<_> {
+            Ok(PinnedSite {
+                url: Url::parse(&row.get::<_, String>("url")?)?,
+                title: row.get("title")?,
+                date_added: row.get("date_added")?,
+            })
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::{apply_observation, delete_everything};
+    use crate::types::VisitTransition;
+
+    #[test]
+    fn test_pinned_sites() {
+        let conn = new_mem_connection();
+        let visited = Url::parse("https://example.com/").unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(visited.clone()).with_visit_type(VisitTransition::Link),
+        )
+        .expect("should apply");
+        let unvisited = Url::parse("https://mozilla.org/").unwrap();
+
+        pin_site(&conn, &visited, Some("Example")).expect("should pin");
+        pin_site(&conn, &unvisited, None).expect("should pin");
+        // Pinning again only changes the title.
+        pin_site(&conn, &visited, Some("Renamed")).expect("should pin again");
+        let sites = get_pinned_sites(&conn).expect("should get");
+        assert_eq!(
+            sites
+                .iter()
+                .map(|s| (s.url.as_str(), s.title.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("https://example.com/", Some("Renamed")),
+                ("https://mozilla.org/", None),
+            ]
+        );
+
+        // Clearing history keeps pinned sites.
+        delete_everything(&conn).expect("should delete");
+        assert_eq!(get_pinned_sites(&conn).expect("should get").len(), 2);
+
+        assert!(unpin_site(&conn, &visited).expect("should unpin"));
+        assert!(!unpin_site(&conn, &visited).expect("should unpin"));
+        assert_eq!(
+            get_pinned_sites(&conn).expect("should get"),
+            vec![PinnedSite {
+                url: unvisited.clone(),
+                title: None,
+                date_added: sites[1].date_added,
+            }]
+        );
+        // Unpinning a site lets its page be removed along with history.
+        assert!(unpin_site(&conn, &unvisited).expect("should unpin"));
+        delete_everything(&conn).expect("should delete");
+        assert!(fetch_page_info(&conn, &unvisited)
+            .expect("should fetch")
+            .is_none());
+    }
+}
@@ -364,4 +364,51 @@ mod tests {
             .expect("should work")
             .expect("should exist");
     }
+
+    #[test]
+    fn test_tags_mark_bookmarks_changed() {
+        use crate::storage::bookmarks::{
+            get_raw_bookmark, insert_bookmark, BookmarkPosition, BookmarkRootGuid,
+            InsertableBookmark,
+        };
+
+        let conn = new_mem_connection();
+        let url = Url::parse("http://example.com").expect("valid url");
+        let guid = insert_bookmark(
+            &conn,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: url.clone(),
+                title: None,
+            }
+            .into(),
+        )
+        .expect("should insert");
+        let change_counter = || {
+            get_raw_bookmark(&conn, &guid)
+                .expect("should work")
+                .expect("should exist")
+                ._sync_change_counter
+        };
+        let initial = change_counter();
+
+        // Tags are uploaded with the bookmark, so changing them should mark
+        // it for upload.
+        tag_url(&conn, &url, "tag").expect("should work");
+        assert_eq!(change_counter(), initial + 1);
+        untag_url(&conn, &url, "tag").expect("should work");
+        assert_eq!(change_counter(), initial + 2);
+
+        assert!(tag_url(&conn, &url, " ").is_err());
+        assert!(tag_url(
+            &conn,
+            &Url::parse("http://example.com/not-bookmarked").expect("valid url"),
+            "tag"
+        )
+        .is_err());
+    }
 }
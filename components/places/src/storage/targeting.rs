@@ -0,0 +1,139 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Coarse attributes about how the user uses their bookmarks and history, for
+// targeting experiments (eg, at heavy or light users). These are deliberately
+// vague - counts are rounded down to one of a handful of buckets - so that
+// nothing identifying leaves places.
+
+use crate::db::PlacesDb;
+use crate::error::*;
+use sql_support::ConnExt;
+use types::Timestamp;
+
+const ONE_DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// The number of days we look at to work out how active the user is.
+const ACTIVE_DAYS_WINDOW: i64 = 28;
+
+/// Counts are rounded down to the nearest of these.
+const BUCKETS: &[i64] = &[0, 1, 5, 10, 25, 50, 100, 250, 500, 1000];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlacesTargetingAttributes {
+    /// The number of bookmarks (not including folders and separators).
+    pub bookmark_count: i64,
+    /// The number of days since the oldest visit in history.
+    pub history_days: i64,
+    /// The number of the last 28 days with at least one local visit.
+    pub active_days: i64,
+}
+
+fn coarsen(n: i64) -> i64 {
+    BUCKETS
+        .iter()
+        .rev()
+        .find(|bucket| n >= **bucket)
+        .copied()
+        .unwrap_or_default()
+}
+
+pub fn get_targeting_attributes(
+    db: &PlacesDb,
+    now: Timestamp,
+) -> Result<PlacesTargetingAttributes> {
+    let now = now.as_millis_i64();
+    let bookmark_count =
+        db.query_one::<i64>("SELECT COUNT(*) FROM moz_bookmarks WHERE type = 1")?;
+    let oldest_visit =
+        db.query_one::<Option<i64>>("SELECT MIN(visit_date) FROM moz_historyvisits")?;
+    let active_days = db.query_row_and_then_cachable(
+        "SELECT COUNT(DISTINCT (:now - visit_date) / :one_day)
+         FROM moz_historyvisits
+         WHERE is_local AND visit_date > :since AND visit_date <= :now",
+        rusqlite::named_params! {
+            ":now": now,
+            ":one_day": ONE_DAY_MS,
+            ":since": now - ACTIVE_DAYS_WINDOW * ONE_DAY_MS,
+        },
+        |r| r.get::<_, i64>(0),
+        true,
+    )?;
+    Ok(PlacesTargetingAttributes {
+        bookmark_count: coarsen(bookmark_count),
+        history_days: oldest_visit
+            .map(|oldest| coarsen((now - oldest).max(0) / ONE_DAY_MS))
+            .unwrap_or_default(),
+        // Already small enough to be coarse.
+        active_days,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::{
+        insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+    };
+    use crate::storage::history::apply_observation;
+    use crate::{VisitObservation, VisitTransition};
+    use url::Url;
+
+    #[test]
+    fn test_coarsen() {
+        assert_eq!(coarsen(0), 0);
+        assert_eq!(coarsen(3), 1);
+        assert_eq!(coarsen(99), 50);
+        assert_eq!(coarsen(100), 100);
+        assert_eq!(coarsen(123_456), 1000);
+    }
+
+    #[test]
+    fn test_targeting_attributes() {
+        let conn = new_mem_connection();
+        let now = Timestamp::now();
+        assert_eq!(
+            get_targeting_attributes(&conn, now).expect("should work"),
+            PlacesTargetingAttributes::default()
+        );
+
+        for i in 0..12 {
+            insert_bookmark(
+                &conn,
+                InsertableBookmark {
+                    parent_guid: BookmarkRootGuid::Unfiled.into(),
+                    position: BookmarkPosition::Append,
+                    date_added: None,
+                    last_modified: None,
+                    guid: None,
+                    url: Url::parse(&format!("https://example.com/{}", i)).unwrap(),
+                    title: None,
+                }
+                .into(),
+            )
+            .expect("should insert");
+        }
+        // Two visits today, one 3 days ago, and one 40 days ago.
+        let url = Url::parse("https://www.example.com").unwrap();
+        for (days_ago, ms_ago) in [(0, 1000), (0, 2000), (3, 0), (40, 0)] {
+            let at = Timestamp((now.as_millis_i64() - days_ago * ONE_DAY_MS - ms_ago) as u64);
+            apply_observation(
+                &conn,
+                VisitObservation::new(url.clone())
+                    .with_at(at)
+                    .with_visit_type(VisitTransition::Link),
+            )
+            .expect("should apply");
+        }
+        assert_eq!(
+            get_targeting_attributes(&conn, now).expect("should work"),
+            PlacesTargetingAttributes {
+                bookmark_count: 10,
+                history_days: 25,
+                active_days: 2,
+            }
+        );
+    }
+}
@@ -0,0 +1,416 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Support for generating large, realistic places databases for benchmarks
+//! and integration tests. Only available with the `test-support` feature.
+//!
+//! Generation is entirely deterministic: the same [`ProfileConfig`] (including
+//! the seed) always produces the same profile, so timings can be compared
+//! between builds and machines. The only exception is that timestamps are
+//! relative to the time the profile was generated.
+
+use crate::db::PlacesDb;
+use crate::error::Result;
+use crate::observation::VisitObservation;
+use crate::storage::bookmarks::{
+    insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark, InsertableFolder,
+    InsertableItem, InsertableSeparator,
+};
+use crate::storage::history::apply_observation_direct;
+use crate::storage::history_metadata::{
+    apply_metadata_observation, DocumentType, HistoryMetadataObservation,
+};
+use crate::types::VisitTransition;
+use rusqlite::named_params;
+use sql_support::ConnExt;
+use sync_guid::Guid as SyncGuid;
+use types::Timestamp;
+use url::Url;
+
+const ONE_DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Describes the profile to generate. The defaults produce a profile roughly
+/// the size of a heavy mobile user's.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfileConfig {
+    /// Seed for the generator.
+    pub seed: u64,
+    /// The number of distinct hosts; visits are skewed towards a few of them.
+    pub num_hosts: usize,
+    /// The number of visits to generate. Many visits will be to the same page.
+    pub num_visits: usize,
+    /// Visits are spread over this many days before now.
+    pub days_of_history: u64,
+    /// The number of folders in each folder, including the user content roots.
+    pub folders_per_folder: usize,
+    /// How deep to nest folders below the user content roots.
+    pub folder_depth: usize,
+    /// The number of bookmarks in each folder.
+    pub bookmarks_per_folder: usize,
+    /// One in this many bookmarks is followed by a separator. 0 means never.
+    pub separator_frequency: usize,
+    /// Per-mille of visited pages which also get history metadata.
+    pub metadata_per_mille: u32,
+    /// The number of history (`moz_places_tombstones`) tombstones to create.
+    pub num_history_tombstones: usize,
+    /// The number of bookmark (`moz_bookmarks_deleted`) tombstones to create.
+    pub num_bookmark_tombstones: usize,
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            num_hosts: 2_000,
+            num_visits: 50_000,
+            days_of_history: 90,
+            folders_per_folder: 3,
+            folder_depth: 2,
+            bookmarks_per_folder: 20,
+            separator_frequency: 10,
+            metadata_per_mille: 100,
+            num_history_tombstones: 100,
+            num_bookmark_tombstones: 20,
+        }
+    }
+}
+
+/// What we generated, so callers have realistic inputs for the APIs they
+/// want to exercise.
+#[derive(Clone, Debug, Default)]
+pub struct GeneratedProfile {
+    /// Every URL we recorded a visit for, in the order the visits were
+    /// generated (so it contains duplicates).
+    pub visited_urls: Vec<Url>,
+    /// The GUIDs of every folder we created.
+    pub folder_guids: Vec<SyncGuid>,
+    /// The number of bookmarks (not folders or separators) we created.
+    pub num_bookmarks: usize,
+    /// The URLs we recorded metadata for.
+    pub metadata_urls: Vec<Url>,
+}
+
+// splitmix64. It's tiny, fast and good enough for generating test data, and
+// it means we don't need to depend on `rand` (which also doesn't promise
+// stable output between versions).
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Returns a value in `0..n`, or 0 if `n` is 0.
+    fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            0
+        } else {
+            self.next_u64() % n
+        }
+    }
+
+    // Returns a value in `0..n`, skewed heavily towards the low end.
+    fn skewed_below(&mut self, n: u64) -> u64 {
+        let bound = self.below(n) + 1;
+        self.below(bound)
+    }
+
+    fn guid(&mut self) -> SyncGuid {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+        let s: String = (0..12)
+            .map(|_| ALPHABET[self.below(ALPHABET.len() as u64) as usize] as char)
+            .collect();
+        SyncGuid::from(s)
+    }
+}
+
+const WORDS: &[&str] = &[
+    "news", "recipes", "weather", "sports", "travel", "review", "guide", "music", "video", "rust",
+    "firefox", "mozilla", "privacy", "garden", "cooking", "science", "history", "maps",
+];
+
+struct Generator<'a> {
+    rng: Rng,
+    config: &'a ProfileConfig,
+    now: u64,
+}
+
+impl<'a> Generator<'a> {
+    fn word(&mut self) -> &'static str {
+        WORDS[self.rng.below(WORDS.len() as u64) as usize]
+    }
+
+    fn title(&mut self) -> String {
+        format!("{} {} {}", self.word(), self.word(), self.word())
+    }
+
+    fn url(&mut self) -> Url {
+        let host = self.rng.skewed_below(self.config.num_hosts.max(1) as u64);
+        let scheme = if host % 10 == 0 { "http" } else { "https" };
+        let sub = if host % 3 == 0 { "www." } else { "" };
+        let path = self.rng.skewed_below(500);
+        let word = self.word();
+        Url::parse(&format!(
+            "{}://{}{}-site{}.example.com/{}/{}",
+            scheme, sub, word, host, word, path
+        ))
+        .expect("generated urls are valid")
+    }
+
+    fn timestamp(&mut self) -> Timestamp {
+        let range = self.config.days_of_history.max(1) * ONE_DAY_MS;
+        Timestamp(self.now - self.rng.below(range))
+    }
+
+    fn visit_type(&mut self) -> VisitTransition {
+        match self.rng.below(20) {
+            0..=1 => VisitTransition::Typed,
+            2 => VisitTransition::Bookmark,
+            3 => VisitTransition::RedirectTemporary,
+            4 => VisitTransition::Reload,
+            _ => VisitTransition::Link,
+        }
+    }
+
+    fn history(&mut self, db: &PlacesDb, profile: &mut GeneratedProfile) -> Result<()> {
+        let tx = db.begin_transaction()?;
+        for _ in 0..self.config.num_visits {
+            let url = self.url();
+            let obs = VisitObservation::new(url.clone())
+                .with_title(self.title())
+                .with_visit_type(self.visit_type())
+                .with_at(self.timestamp());
+            apply_observation_direct(db, obs)?;
+            profile.visited_urls.push(url);
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn metadata(&mut self, db: &PlacesDb, profile: &mut GeneratedProfile) -> Result<()> {
+        for url in &profile.visited_urls {
+            if self.rng.below(1000) >= self.config.metadata_per_mille as u64 {
+                continue;
+            }
+            let search_term = if self.rng.below(4) == 0 {
+                Some(self.word().to_string())
+            } else {
+                None
+            };
+            let document_type = if self.rng.below(10) == 0 {
+                DocumentType::Media
+            } else {
+                DocumentType::Regular
+            };
+            apply_metadata_observation(
+                db,
+                HistoryMetadataObservation {
+                    url: url.to_string(),
+                    view_time: Some(self.rng.below(10 * 60 * 1000) as i32),
+                    search_term,
+                    document_type: Some(document_type),
+                    referrer_url: None,
+                    title: None,
+                    metadata_session_id: None,
+                },
+            )?;
+            profile.metadata_urls.push(url.clone());
+        }
+        Ok(())
+    }
+
+    fn folder(
+        &mut self,
+        parent_guid: SyncGuid,
+        depth: usize,
+        profile: &mut GeneratedProfile,
+    ) -> InsertableFolder {
+        let guid = self.rng.guid();
+        profile.folder_guids.push(guid.clone());
+        let mut children = Vec::new();
+        for i in 0..self.config.bookmarks_per_folder {
+            let url = self.url();
+            let title = self.title();
+            children.push(InsertableItem::from(InsertableBookmark {
+                parent_guid: guid.clone(),
+                position: BookmarkPosition::Append,
+                date_added: Some(self.timestamp()),
+                last_modified: None,
+                guid: None,
+                url,
+                title: Some(title),
+            }));
+            profile.num_bookmarks += 1;
+            let frequency = self.config.separator_frequency;
+            if frequency != 0 && (i + 1) % frequency == 0 {
+                children.push(
+                    InsertableSeparator {
+                        parent_guid: guid.clone(),
+                        position: BookmarkPosition::Append,
+                        date_added: None,
+                        last_modified: None,
+                        guid: None,
+                    }
+                    .into(),
+                );
+            }
+        }
+        if depth < self.config.folder_depth {
+            for _ in 0..self.config.folders_per_folder {
+                let child = self.folder(guid.clone(), depth + 1, profile);
+                children.push(child.into());
+            }
+        }
+        InsertableFolder {
+            parent_guid,
+            position: BookmarkPosition::Append,
+            date_added: Some(self.timestamp()),
+            last_modified: None,
+            guid: Some(guid),
+            title: Some(self.title()),
+            children,
+        }
+    }
+
+    fn bookmarks(&mut self, db: &PlacesDb, profile: &mut GeneratedProfile) -> Result<()> {
+        for root in &[
+            BookmarkRootGuid::Menu,
+            BookmarkRootGuid::Toolbar,
+            BookmarkRootGuid::Unfiled,
+            BookmarkRootGuid::Mobile,
+        ] {
+            for _ in 0..self.config.folders_per_folder {
+                let folder = self.folder(root.as_guid(), 1, profile);
+                insert_bookmark(db, folder.into())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn tombstones(&mut self, db: &PlacesDb) -> Result<()> {
+        let tx = db.begin_transaction()?;
+        for _ in 0..self.config.num_history_tombstones {
+            db.execute_cached(
+                "INSERT OR IGNORE INTO moz_places_tombstones(guid) VALUES(:guid)",
+                named_params! { ":guid": self.rng.guid() },
+            )?;
+        }
+        for _ in 0..self.config.num_bookmark_tombstones {
+            db.execute_cached(
+                "INSERT OR IGNORE INTO moz_bookmarks_deleted(guid, dateRemoved)
+                 VALUES(:guid, :date_removed)",
+                named_params! {
+                    ":guid": self.rng.guid(),
+                    ":date_removed": self.timestamp(),
+                },
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// Populates `db`, which should be empty, with a synthetic profile.
+pub fn generate_profile(db: &PlacesDb, config: &ProfileConfig) -> Result<GeneratedProfile> {
+    let mut generator = Generator {
+        rng: Rng(config.seed),
+        config,
+        now: Timestamp::now().as_millis(),
+    };
+    let mut profile = GeneratedProfile::default();
+    generator.history(db, &mut profile)?;
+    generator.metadata(db, &mut profile)?;
+    generator.bookmarks(db, &mut profile)?;
+    generator.tombstones(db)?;
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::fetch::fetch_tree;
+
+    fn small_config(seed: u64) -> ProfileConfig {
+        ProfileConfig {
+            seed,
+            num_hosts: 20,
+            num_visits: 200,
+            folders_per_folder: 2,
+            folder_depth: 2,
+            bookmarks_per_folder: 5,
+            separator_frequency: 2,
+            metadata_per_mille: 200,
+            num_history_tombstones: 5,
+            num_bookmark_tombstones: 5,
+            ..ProfileConfig::default()
+        }
+    }
+
+    fn count(db: &PlacesDb, sql: &str) -> i64 {
+        db.query_one(sql).expect("should work")
+    }
+
+    #[test]
+    fn test_generate_is_deterministic() {
+        let db1 = new_mem_connection();
+        let db2 = new_mem_connection();
+        let p1 = generate_profile(&db1, &small_config(7)).expect("should generate");
+        let p2 = generate_profile(&db2, &small_config(7)).expect("should generate");
+        assert_eq!(p1.visited_urls, p2.visited_urls);
+        assert_eq!(p1.folder_guids, p2.folder_guids);
+        assert_eq!(p1.metadata_urls, p2.metadata_urls);
+        for sql in &[
+            "SELECT COUNT(*) FROM moz_places",
+            "SELECT COUNT(*) FROM moz_historyvisits",
+            "SELECT COUNT(*) FROM moz_bookmarks",
+            "SELECT COUNT(*) FROM moz_places_metadata",
+            "SELECT COUNT(*) FROM moz_places_tombstones",
+            "SELECT COUNT(*) FROM moz_bookmarks_deleted",
+        ] {
+            assert_eq!(count(&db1, sql), count(&db2, sql), "{}", sql);
+        }
+
+        let db3 = new_mem_connection();
+        let p3 = generate_profile(&db3, &small_config(8)).expect("should generate");
+        assert_ne!(p1.visited_urls, p3.visited_urls);
+    }
+
+    #[test]
+    fn test_generate_shape() {
+        let db = new_mem_connection();
+        let config = small_config(1);
+        let profile = generate_profile(&db, &config).expect("should generate");
+        assert_eq!(profile.visited_urls.len(), config.num_visits);
+        assert_eq!(
+            count(&db, "SELECT COUNT(*) FROM moz_historyvisits"),
+            config.num_visits as i64
+        );
+        // 4 roots with 2 folders each, each of which has 2 subfolders.
+        assert_eq!(profile.folder_guids.len(), 4 * (2 + 2 * 2));
+        assert_eq!(profile.num_bookmarks, profile.folder_guids.len() * 5);
+        assert_eq!(
+            count(&db, "SELECT COUNT(*) FROM moz_bookmarks WHERE type = 1"),
+            profile.num_bookmarks as i64
+        );
+        assert!(count(&db, "SELECT COUNT(*) FROM moz_bookmarks WHERE type = 3") > 0);
+        assert_eq!(
+            count(&db, "SELECT COUNT(*) FROM moz_places_tombstones"),
+            config.num_history_tombstones as i64
+        );
+        assert_eq!(
+            count(&db, "SELECT COUNT(*) FROM moz_bookmarks_deleted"),
+            config.num_bookmark_tombstones as i64
+        );
+        // The tree must be valid.
+        crate::tests::check_positions(&db);
+        fetch_tree(&db, BookmarkRootGuid::Root.guid())
+            .expect("should fetch")
+            .expect("should exist");
+    }
+}
@@ -60,4 +60,9 @@ pub enum PushError {
 
     #[error("Error opening database: {0}")]
     OpenDatabaseError(#[from] sql_support::open_database::Error),
+
+    /// A message couldn't be decrypted, so it was quarantined to be retried
+    /// with [`crate::PushManager::retry_quarantined_messages`].
+    #[error("Message quarantined: {0}")]
+    MessageQuarantinedError(String),
 }
@@ -8,7 +8,15 @@ use sql_support::{open_database, ConnExt};
 
 use crate::error::{PushError, Result};
 
-use super::{record::PushRecord, schema};
+use super::{
+    record::{PushRecord, QuarantinedMessage},
+    schema,
+    types::Timestamp,
+};
+
+/// The most messages we keep in quarantine. Once it's full, the oldest
+/// messages are dropped to make room.
+const MAX_QUARANTINED_MESSAGES: u32 = 100;
 
 // TODO: Add broadcasts storage
 
@@ -25,8 +33,24 @@ pub trait Storage {
 
     fn get_channel_list(&self) -> Result<Vec<String>>;
 
+    fn get_all_records(&self) -> Result<Vec<PushRecord>>;
+
     fn update_endpoint(&self, channel_id: &str, endpoint: &str) -> Result<bool>;
 
+    // Messages we couldn't decrypt.
+    fn quarantine_message(
+        &self,
+        chid: &str,
+        body: &str,
+        encoding: &str,
+        salt: Option<&str>,
+        dh: Option<&str>,
+    ) -> Result<()>;
+    fn get_quarantined_messages(&self) -> Result<Vec<QuarantinedMessage>>;
+    fn delete_quarantined_message(&self, id: i64) -> Result<()>;
+    fn delete_quarantined_messages_for(&self, chid: &str) -> Result<()>;
+    fn delete_all_quarantined_messages(&self) -> Result<()>;
+
     // Some of our "meta" keys are more important than others, so they get special helpers.
     fn get_uaid(&self) -> Result<Option<String>>;
     fn set_uaid(&self, uaid: &str) -> Result<()>;
@@ -203,6 +227,15 @@ impl Storage for PushDb {
         )
     }
 
+    fn get_all_records(&self) -> Result<Vec<PushRecord>> {
+        let query = format!(
+            "SELECT {common_cols}
+             FROM push_record ORDER BY ctime",
+            common_cols = schema::COMMON_COLS,
+        );
+        self.query_rows_and_then(&query, [], PushRecord::from_row)
+    }
+
     fn update_endpoint(&self, channel_id: &str, endpoint: &str) -> Result<bool> {
         log::debug!("updating endpoint for '{}' to '{}'", channel_id, endpoint);
         let affected_rows = self.execute(
@@ -216,6 +249,74 @@ impl Storage for PushDb {
         Ok(affected_rows == 1)
     }
 
+    fn quarantine_message(
+        &self,
+        chid: &str,
+        body: &str,
+        encoding: &str,
+        salt: Option<&str>,
+        dh: Option<&str>,
+    ) -> Result<()> {
+        log::debug!("quarantining undecryptable message for channel '{}'", chid);
+        self.execute(
+            "INSERT INTO push_quarantine
+                 (channel_id, body, encoding, salt, dh, ctime)
+             VALUES
+                 (:channel_id, :body, :encoding, :salt, :dh, :ctime)",
+            &[
+                (
+                    ":channel_id",
+                    &Self::normalize_uuid(chid) as &dyn rusqlite::ToSql,
+                ),
+                (":body", &body),
+                (":encoding", &encoding),
+                (":salt", &salt),
+                (":dh", &dh),
+                (":ctime", &Timestamp::now()),
+            ],
+        )?;
+        // Keep the quarantine bounded, so a subscription whose messages can
+        // never be decrypted doesn't fill up the disk.
+        self.execute(
+            "DELETE FROM push_quarantine
+             WHERE id NOT IN (SELECT id FROM push_quarantine
+                              ORDER BY id DESC
+                              LIMIT :max)",
+            &[(":max", &MAX_QUARANTINED_MESSAGES)],
+        )?;
+        Ok(())
+    }
+
+    fn get_quarantined_messages(&self) -> Result<Vec<QuarantinedMessage>> {
+        self.query_rows_and_then(
+            "SELECT id, channel_id, body, encoding, salt, dh, ctime
+             FROM push_quarantine ORDER BY id",
+            [],
+            QuarantinedMessage::from_row,
+        )
+    }
+
+    fn delete_quarantined_message(&self, id: i64) -> Result<()> {
+        self.execute(
+            "DELETE FROM push_quarantine WHERE id = :id",
+            &[(":id", &id)],
+        )?;
+        Ok(())
+    }
+
+    fn delete_quarantined_messages_for(&self, chid: &str) -> Result<()> {
+        self.execute(
+            "DELETE FROM push_quarantine WHERE channel_id = :chid",
+            &[(":chid", &Self::normalize_uuid(chid))],
+        )?;
+        Ok(())
+    }
+
+    fn delete_all_quarantined_messages(&self) -> Result<()> {
+        self.execute("DELETE FROM push_quarantine", [])?;
+        Ok(())
+    }
+
     // A couple of helpers to get/set "well known" meta keys.
     fn get_uaid(&self) -> Result<Option<String>> {
         self.get_meta("uaid")
@@ -354,6 +455,20 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn all_records() -> Result<()> {
+        let db = get_db()?;
+        assert!(db.get_all_records()?.is_empty());
+        let rec = prec(&get_uuid()?);
+        let mut rec2 = prec(&get_uuid()?);
+        rec2.scope = "https://example.com/2".to_owned();
+        rec2.ctime.0 += 1;
+        db.put_record(&rec)?;
+        db.put_record(&rec2)?;
+        assert_eq!(db.get_all_records()?, vec![rec, rec2]);
+        Ok(())
+    }
+
     #[test]
     fn meta() -> Result<()> {
         use super::Storage;
@@ -368,6 +483,45 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn quarantine() -> Result<()> {
+        let db = get_db()?;
+        let chid = "deadbeef-0000-0000-0000-decafbad12345678";
+        db.quarantine_message(chid, "body", "aes128gcm", Some(""), None)?;
+        let messages = db.get_quarantined_messages()?;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].channel_id, PushDb::normalize_uuid(chid));
+        assert_eq!(messages[0].body, "body");
+        assert_eq!(messages[0].encoding, "aes128gcm");
+        assert_eq!(messages[0].salt.as_deref(), Some(""));
+        assert_eq!(messages[0].dh, None);
+
+        // The oldest messages are dropped once the quarantine is full.
+        for i in 0..super::MAX_QUARANTINED_MESSAGES {
+            db.quarantine_message(
+                &get_uuid()?,
+                &format!("body-{}", i),
+                "aes128gcm",
+                None,
+                None,
+            )?;
+        }
+        let messages = db.get_quarantined_messages()?;
+        assert_eq!(messages.len(), super::MAX_QUARANTINED_MESSAGES as usize);
+        assert_eq!(messages[0].body, "body-0");
+
+        db.delete_quarantined_message(messages[0].id)?;
+        db.quarantine_message(chid, "body", "aes128gcm", None, None)?;
+        db.delete_quarantined_messages_for(chid)?;
+        assert_eq!(
+            db.get_quarantined_messages()?.len(),
+            super::MAX_QUARANTINED_MESSAGES as usize - 1
+        );
+        db.delete_all_quarantined_messages()?;
+        assert!(db.get_quarantined_messages()?.is_empty());
+        Ok(())
+    }
+
     #[test]
     fn dash() -> Result<()> {
         let db = get_db()?;
@@ -11,5 +11,5 @@ mod types;
 
 pub use self::{
     db::{PushDb as Store, Storage},
-    record::PushRecord,
+    record::{PushRecord, QuarantinedMessage},
 };
@@ -59,3 +59,30 @@ impl PushRecord {
         })
     }
 }
+
+/// A message we couldn't decrypt, with everything needed to try again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuarantinedMessage {
+    pub id: i64,
+    pub channel_id: ChannelID,
+    pub body: String,
+    pub encoding: String,
+    pub salt: Option<String>,
+    pub dh: Option<String>,
+    /// When the message was quarantined.
+    pub ctime: Timestamp,
+}
+
+impl QuarantinedMessage {
+    pub(crate) fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(QuarantinedMessage {
+            id: row.get("id")?,
+            channel_id: row.get("channel_id")?,
+            body: row.get("body")?,
+            encoding: row.get("encoding")?,
+            salt: row.get("salt")?,
+            dh: row.get("dh")?,
+            ctime: row.get("ctime")?,
+        })
+    }
+}
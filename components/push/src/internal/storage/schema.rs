@@ -10,7 +10,7 @@ pub struct PushConnectionInitializer;
 
 impl open_database::ConnectionInitializer for PushConnectionInitializer {
     const NAME: &'static str = "push db";
-    const END_VERSION: u32 = 3;
+    const END_VERSION: u32 = 4;
 
     // This is such a simple database that we do almost nothing!
     // * We have no foreign keys, so `PRAGMA foreign_keys = ON;` is pointless.
@@ -49,6 +49,8 @@ impl open_database::ConnectionInitializer for PushConnectionInitializer {
                 );
                 db.execute_batch(&sql)?;
             }
+            // Added the `push_quarantine` table.
+            3 => db.execute_batch(CREATE_TABLE_PUSH_SQL)?,
             other => {
                 log::warn!(
                     "Loaded future schema version {} (we only understand version {}). \
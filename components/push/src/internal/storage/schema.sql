@@ -17,6 +17,20 @@ IF NOT EXISTS push_record
     CHECK(length(scope) > 0)
 );
 
+-- Messages we couldn't decrypt, kept so that they can be retried once the
+-- subscription's keys have caught up.
+CREATE TABLE
+IF NOT EXISTS push_quarantine
+(
+    id                 INTEGER  PRIMARY KEY,
+    channel_id         TEXT     NOT NULL,
+    body               TEXT     NOT NULL,
+    encoding           TEXT     NOT NULL,
+    salt               TEXT,
+    dh                 TEXT,
+    ctime              INTEGER  NOT NULL
+);
+
 CREATE TABLE
 IF NOT EXISTS meta_data
 (
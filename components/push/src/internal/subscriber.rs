@@ -14,7 +14,8 @@ use crate::internal::config::PushConfiguration;
 use crate::internal::crypto::{Crypto, Cryptography, KeyV1 as Key};
 use crate::internal::storage::{PushRecord, Storage, Store};
 use crate::{
-    DispatchInfo, KeyInfo, PushSubscriptionChanged, SubscriptionInfo, SubscriptionResponse,
+    DecryptedMessage, DispatchInfo, EndpointChange, KeyInfo, PushSubscription,
+    PushSubscriptionChanged, SubscriptionInfo, SubscriptionResponse,
 };
 
 const UPDATE_RATE_LIMITER_INTERVAL: u64 = 24 * 60 * 60; // 500 calls per 24 hours.
@@ -60,10 +61,31 @@ impl From<PushRecord> for DispatchInfo {
     }
 }
 
+impl TryFrom<PushRecord> for PushSubscription {
+    type Error = PushError;
+
+    fn try_from(record: PushRecord) -> Result<Self> {
+        Ok(PushSubscription {
+            subscription_info: SubscriptionInfo {
+                endpoint: record.endpoint,
+                keys: Key::deserialize(&record.key)?.into(),
+            },
+            channel_id: record.channel_id,
+            scope: record.scope,
+            app_server_key: record.app_server_key,
+            created_at: record.ctime.0 as i64,
+        })
+    }
+}
+
 pub struct PushManager {
     config: PushConfiguration,
     pub store: Store,
     update_rate_limiter: PersistedRateLimiter,
+    // Endpoint changes which haven't been handed to the observer yet. The
+    // public `PushManager` takes these after releasing its lock, so the
+    // observer can safely call back into it.
+    endpoint_changes: Vec<EndpointChange>,
 }
 
 impl PushManager {
@@ -81,9 +103,27 @@ impl PushManager {
                 UPDATE_RATE_LIMITER_INTERVAL,
                 UPDATE_RATE_LIMITER_MAX_CALLS,
             ),
+            endpoint_changes: Vec::new(),
         })
     }
 
+    /// Takes the endpoint changes since the last call, for the observer.
+    pub fn take_endpoint_changes(&mut self) -> Vec<EndpointChange> {
+        std::mem::take(&mut self.endpoint_changes)
+    }
+
+    // Records the subscriptions we lost, which the consumer will need to
+    // subscribe to again.
+    fn record_lost(&mut self, records: Vec<PushRecord>) {
+        self.endpoint_changes
+            .extend(records.into_iter().map(|record| EndpointChange {
+                channel_id: record.channel_id,
+                scope: record.scope,
+                old_endpoint: record.endpoint,
+                new_subscription: None,
+            }));
+    }
+
     pub fn make_connection(&self) -> Result<ConnectHttp> {
         let uaid = self.store.get_uaid()?;
         let auth = self.store.get_auth()?;
@@ -122,7 +162,17 @@ impl PushManager {
             )
                 .into());
         }
+        self.register(channel_id, scope, server_key)
+    }
 
+    // Gets a new subscription from the server and stores it, replacing any
+    // existing record for `channel_id`.
+    fn register(
+        &mut self,
+        channel_id: &str,
+        scope: &str,
+        server_key: Option<&str>,
+    ) -> Result<SubscriptionResponse> {
         let mut conn = self.make_connection()?;
         let info = conn.subscribe(channel_id, server_key)?;
         log::debug!("server returned subscription info: {:?}", info);
@@ -148,7 +198,9 @@ impl PushManager {
                 "Got new new UAID of '{}' - deleting all existing records",
                 info.uaid
             );
+            let lost = self.store.get_all_records()?;
             self.store.delete_all_records()?;
+            self.record_lost(lost);
             self.store.set_uaid(&info.uaid)?;
             self.store.set_auth(new_auth)?;
         }
@@ -193,13 +245,79 @@ impl PushManager {
             ));
         }
         conn.unsubscribe(channel_id)?;
+        self.store.delete_quarantined_messages_for(channel_id)?;
         self.store.delete_record(channel_id)
     }
 
+    pub fn unsubscribe_channels(&mut self, channel_ids: &[String]) -> Result<Vec<String>> {
+        let conn = self.make_connection()?;
+        if conn.uaid.is_none() {
+            return Err(PushError::GeneralError(
+                "No subscriptions created yet.".into(),
+            ));
+        }
+        let mut unsubscribed = Vec::with_capacity(channel_ids.len());
+        for channel_id in channel_ids {
+            // Channels we don't know about can't be unsubscribed from, but that's not a reason
+            // to stop unsubscribing from the others.
+            if self.store.get_record(channel_id)?.is_none() {
+                log::debug!("not unsubscribing from unknown channel '{}'", channel_id);
+                continue;
+            }
+            conn.unsubscribe(channel_id)?;
+            self.store.delete_quarantined_messages_for(channel_id)?;
+            if self.store.delete_record(channel_id)? {
+                unsubscribed.push(channel_id.clone());
+            }
+        }
+        Ok(unsubscribed)
+    }
+
+    pub fn update_app_server_key(
+        &mut self,
+        channel_id: &str,
+        app_server_key: Option<&str>,
+    ) -> Result<SubscriptionResponse> {
+        let app_server_key = app_server_key.filter(|key| !key.is_empty());
+        let record = self
+            .store
+            .get_record(channel_id)?
+            .ok_or_else(|| PushError::RecordNotFoundError(channel_id.to_owned()))?;
+        if record.app_server_key.as_deref() == app_server_key {
+            return self.subscribe(&record.channel_id, &record.scope, app_server_key);
+        }
+        // The server ties the endpoint to the key, so the only way to change the key is
+        // to subscribe again, which gives us a new endpoint. We register the channel
+        // again before touching the old record, so that if the server fails we still
+        // have the old subscription. The new registration reuses the channel ID, so
+        // there's nothing left to unsubscribe from afterwards.
+        log::info!(
+            "app server key changed for '{}' - resubscribing",
+            record.scope
+        );
+        let response = self.register(&record.channel_id, &record.scope, app_server_key)?;
+        self.endpoint_changes.push(EndpointChange {
+            channel_id: record.channel_id,
+            scope: record.scope,
+            old_endpoint: record.endpoint,
+            new_subscription: Some(response.clone()),
+        });
+        Ok(response)
+    }
+
+    pub fn list_subscriptions(&self) -> Result<Vec<PushSubscription>> {
+        self.store
+            .get_all_records()?
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect()
+    }
+
     pub fn unsubscribe_all(&mut self) -> Result<()> {
         // make connection before deleting, because deletion kills our uaid/auth meta.
         let mut conn = self.make_connection()?;
         self.store.delete_all_records()?;
+        self.store.delete_all_quarantined_messages()?;
         conn.unsubscribe_all()?;
         Ok(())
     }
@@ -239,15 +357,17 @@ impl PushManager {
             return Ok(Vec::new());
         }
 
-        let mut subscriptions: Vec<PushSubscriptionChanged> = Vec::new();
+        let mut lost = Vec::new();
         for channel in channels {
             if let Some(record) = self.store.get_record_by_chid(&channel)? {
-                subscriptions.push(record.into());
+                lost.push(record);
             }
         }
         // we wipe all existing subscriptions and the UAID if there is a mismatch; the next
         // `subscribe()` call will get a new UAID.
         self.store.delete_all_records()?;
+        let subscriptions = lost.iter().cloned().map(Into::into).collect();
+        self.record_lost(lost);
         Ok(subscriptions)
     }
 
@@ -258,6 +378,62 @@ impl PushManager {
         encoding: &str,
         salt: Option<&str>,
         dh: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        match self.try_decrypt(chid, body, encoding, salt, dh) {
+            Err(e @ (PushError::CryptoError(_) | PushError::RecordNotFoundError(_))) => {
+                // The message may have raced with a change to the subscription's keys, eg,
+                // it was encrypted with keys we haven't stored yet. Rather than dropping it,
+                // keep it so that the consumer can retry once it's re-registered.
+                self.store
+                    .quarantine_message(chid, body, encoding, salt, dh)?;
+                Err(PushError::MessageQuarantinedError(e.to_string()))
+            }
+            result => result,
+        }
+    }
+
+    /// Tries to decrypt the quarantined messages again, returning the ones which
+    /// now decrypt. Messages which still can't be decrypted stay in quarantine.
+    pub fn retry_quarantined_messages(&self) -> Result<Vec<DecryptedMessage>> {
+        let mut decrypted = Vec::new();
+        for message in self.store.get_quarantined_messages()? {
+            match self.try_decrypt(
+                &message.channel_id,
+                &message.body,
+                &message.encoding,
+                message.salt.as_deref(),
+                message.dh.as_deref(),
+            ) {
+                Ok(data) => {
+                    self.store.delete_quarantined_message(message.id)?;
+                    decrypted.push(DecryptedMessage {
+                        channel_id: message.channel_id,
+                        data: data.into_iter().map(|ub| ub as i8).collect(),
+                    });
+                }
+                Err(PushError::CryptoError(_)) | Err(PushError::RecordNotFoundError(_)) => {
+                    log::debug!(
+                        "still can't decrypt quarantined message for '{}'",
+                        message.channel_id
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(decrypted)
+    }
+
+    pub fn clear_quarantined_messages(&self) -> Result<()> {
+        self.store.delete_all_quarantined_messages()
+    }
+
+    fn try_decrypt(
+        &self,
+        chid: &str,
+        body: &str,
+        encoding: &str,
+        salt: Option<&str>,
+        dh: Option<&str>,
     ) -> Result<Vec<u8>> {
         let val = self
             .store
@@ -334,6 +510,115 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_quarantine() -> Result<()> {
+        use rc_crypto::ece;
+        rc_crypto::ensure_initialized();
+        let data_string = b"Mary had a little lamb, with some nice mint jelly";
+        let mut pm = get_test_manager()?;
+        let resp = pm.subscribe(TEST_CHANNEL_ID, "test-scope", None)?;
+        let key_info = resp.subscription_info.keys;
+        let remote_pub = base64::decode_config(&key_info.p256dh, base64::URL_SAFE_NO_PAD).unwrap();
+        let auth = base64::decode_config(&key_info.auth, base64::URL_SAFE_NO_PAD).unwrap();
+        let ciphertext = ece::encrypt(&remote_pub, &auth, data_string).unwrap();
+        let body = base64::encode_config(&ciphertext, base64::URL_SAFE_NO_PAD);
+
+        // Act like the message arrived before we'd stored the subscription.
+        assert!(pm.unsubscribe(TEST_CHANNEL_ID)?);
+        assert!(matches!(
+            pm.decrypt(TEST_CHANNEL_ID, &body, "aes128gcm", None, None),
+            Err(PushError::MessageQuarantinedError(_))
+        ));
+        // Messages which can't be decrypted with the keys we have are quarantined too.
+        assert!(matches!(
+            pm.decrypt(TEST_CHANNEL_ID, "garbage", "aes128gcm", None, None),
+            Err(PushError::MessageQuarantinedError(_))
+        ));
+        assert!(pm.retry_quarantined_messages()?.is_empty());
+
+        // The test server always hands out the same keys, so subscribing again
+        // lets us decrypt the first message.
+        pm.subscribe(TEST_CHANNEL_ID, "test-scope", None)?;
+        let decrypted = pm.retry_quarantined_messages()?;
+        assert_eq!(decrypted.len(), 1);
+        assert_eq!(decrypted[0].channel_id, TEST_CHANNEL_ID);
+        assert_eq!(
+            decrypted[0].data,
+            data_string.iter().map(|b| *b as i8).collect::<Vec<_>>()
+        );
+        assert!(pm.retry_quarantined_messages()?.is_empty());
+        assert_eq!(pm.store.get_quarantined_messages()?.len(), 1);
+
+        pm.clear_quarantined_messages()?;
+        assert!(pm.store.get_quarantined_messages()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_manage_subscriptions() -> Result<()> {
+        let mut pm = get_test_manager()?;
+        assert!(pm.list_subscriptions()?.is_empty());
+        let resp = pm.subscribe(TEST_CHANNEL_ID, "test-scope", None)?;
+        let subs = pm.list_subscriptions()?;
+        assert_eq!(subs.len(), 1);
+        assert_eq!(subs[0].channel_id, TEST_CHANNEL_ID);
+        assert_eq!(subs[0].scope, "test-scope");
+        assert_eq!(subs[0].app_server_key, None);
+        assert_eq!(
+            subs[0].subscription_info.endpoint,
+            resp.subscription_info.endpoint
+        );
+        assert_eq!(subs[0].subscription_info.keys, resp.subscription_info.keys);
+
+        // Changing the key resubscribes.
+        let resp = pm.update_app_server_key(TEST_CHANNEL_ID, Some("new-key"))?;
+        assert_eq!(resp.channel_id, TEST_CHANNEL_ID);
+        let subs = pm.list_subscriptions()?;
+        assert_eq!(subs.len(), 1);
+        assert_eq!(subs[0].scope, "test-scope");
+        assert_eq!(subs[0].app_server_key, Some("new-key".to_owned()));
+        assert!(matches!(
+            pm.update_app_server_key("unknown", None),
+            Err(PushError::RecordNotFoundError(_))
+        ));
+
+        // Unknown channels are skipped.
+        let unsubscribed =
+            pm.unsubscribe_channels(&["unknown".to_owned(), TEST_CHANNEL_ID.to_owned()])?;
+        assert_eq!(unsubscribed, vec![TEST_CHANNEL_ID.to_owned()]);
+        assert!(pm.list_subscriptions()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_endpoint_changes() -> Result<()> {
+        let mut pm = get_test_manager()?;
+        let resp = pm.subscribe(TEST_CHANNEL_ID, "test-scope", None)?;
+        assert!(pm.take_endpoint_changes().is_empty());
+
+        let new_resp = pm.update_app_server_key(TEST_CHANNEL_ID, Some("new-key"))?;
+        // The test server always fails to verify, so we lose the subscription.
+        pm.verify_connection()?;
+        let changes = pm.take_endpoint_changes();
+        assert!(pm.take_endpoint_changes().is_empty());
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].channel_id, TEST_CHANNEL_ID);
+        assert_eq!(changes[0].scope, "test-scope");
+        assert_eq!(changes[0].old_endpoint, resp.subscription_info.endpoint);
+        assert_eq!(
+            changes[0]
+                .new_subscription
+                .as_ref()
+                .unwrap()
+                .subscription_info
+                .keys,
+            new_resp.subscription_info.keys
+        );
+        assert_eq!(changes[1].channel_id, TEST_CHANNEL_ID);
+        assert!(changes[1].new_subscription.is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_wipe_uaid() -> Result<()> {
         let mut pm = get_test_manager()?;
@@ -183,7 +183,7 @@
 uniffi_macros::include_scaffolding!("push");
 // All implementation detail lives in the `internal` module
 mod internal;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 mod error;
 use error::*;
 
@@ -210,6 +210,9 @@ pub struct PushManager {
     // and moving the mutex down to ensure `internal::PushManager`
     // is Sync + Send
     internal: Mutex<internal::PushManager>,
+    // Kept outside of `internal` so that we can call the observer without
+    // holding that lock, which lets the observer call back into us.
+    observer: Mutex<Option<Arc<dyn PushSubscriptionObserver>>>,
 }
 
 impl PushManager {
@@ -261,9 +264,33 @@ impl PushManager {
         };
         Ok(Self {
             internal: Mutex::new(internal::PushManager::new(config)?),
+            observer: Mutex::new(None),
         })
     }
 
+    // Runs `f` with the internal manager, then tells the observer about any
+    // endpoint changes once the lock has been released. Changes are reported
+    // even if `f` fails, since it may have lost subscriptions before failing.
+    fn with_endpoint_changes<T>(
+        &self,
+        f: impl FnOnce(&mut internal::PushManager) -> Result<T>,
+    ) -> Result<T> {
+        let (result, changes) = {
+            let mut internal = self.internal.lock().unwrap();
+            let result = f(&mut internal);
+            (result, internal.take_endpoint_changes())
+        };
+        if !changes.is_empty() {
+            let observer = self.observer.lock().unwrap().clone();
+            if let Some(observer) = observer {
+                for change in changes {
+                    observer.on_endpoint_changed(change);
+                }
+            }
+        }
+        result
+    }
+
     /// Subscribes to a new channel and gets the Subscription Info block
     ///
     /// # Arguments
@@ -288,10 +315,9 @@ impl PushManager {
         scope: &str,
         server_key: &Option<String>,
     ) -> Result<SubscriptionResponse> {
-        self.internal
-            .lock()
-            .unwrap()
-            .subscribe(channel_id, scope, server_key.as_deref())
+        self.with_endpoint_changes(|internal| {
+            internal.subscribe(channel_id, scope, server_key.as_deref())
+        })
     }
 
     /// Unsubscribe from given channelID, ending that subscription for the user.
@@ -311,6 +337,27 @@ impl PushManager {
         self.internal.lock().unwrap().unsubscribe(channel_id)
     }
 
+    /// Unsubscribe from some of the channels, leaving the others alone.
+    ///
+    /// # Arguments
+    ///   - `channel_ids` - Channel IDs (UUID) for the subscriptions to remove
+    ///
+    /// # Returns
+    /// The channel IDs which were unsubscribed. Channels which we don't have a
+    /// subscription for are skipped.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - The PushManager does not contain a valid UAID
+    ///   - An error occurred sending an unsubscribe request to the autopush server
+    ///   - An error occurred accessing the PushManager's persisted storage
+    pub fn unsubscribe_channels(&self, channel_ids: Vec<String>) -> Result<Vec<String>> {
+        self.internal
+            .lock()
+            .unwrap()
+            .unsubscribe_channels(&channel_ids)
+    }
+
     /// Unsubscribe all channels for the user
     ///
     /// # Errors
@@ -359,7 +406,7 @@ impl PushManager {
     ///   - An error occurred sending an channel list retrieval request to the autopush server
     ///   - An error occurred accessing the PushManager's persisted storage
     pub fn verify_connection(&self) -> Result<Vec<PushSubscriptionChanged>> {
-        self.internal.lock().unwrap().verify_connection()
+        self.with_endpoint_changes(|internal| internal.verify_connection())
     }
 
     /// Decrypts a raw push message.
@@ -383,6 +430,13 @@ impl PushManager {
     ///   - There are no records associated with the UAID the [`PushManager`] contains
     ///   - An error occurred while decrypting the message
     ///   - An error occurred accessing the PushManager's persisted storage
+    ///
+    /// If there's no subscription for `channel_id`, or the message can't be
+    /// decrypted with its keys, the message is quarantined and a
+    /// [`PushError::MessageQuarantinedError`] is returned. This usually means
+    /// the message raced with a change to the subscription's keys, so the
+    /// message can be retried with [`PushManager::retry_quarantined_messages`]
+    /// once the subscription is up to date.
     pub fn decrypt(
         &self,
         channel_id: &str,
@@ -404,6 +458,73 @@ impl PushManager {
         Ok(decrypted.into_iter().map(|ub| ub as i8).collect())
     }
 
+    /// Tries to decrypt the messages which [`PushManager::decrypt`] quarantined
+    /// again, eg, after re-registering or subscribing again.
+    ///
+    /// # Returns
+    /// The messages which can now be decrypted, oldest first. They're removed
+    /// from the quarantine, while messages which still can't be decrypted
+    /// stay there until they're cleared or pushed out by newer messages.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - An error occurred accessing the PushManager's persisted storage
+    pub fn retry_quarantined_messages(&self) -> Result<Vec<DecryptedMessage>> {
+        self.internal.lock().unwrap().retry_quarantined_messages()
+    }
+
+    /// Drops all the quarantined messages.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - An error occurred accessing the PushManager's persisted storage
+    pub fn clear_quarantined_messages(&self) -> Result<()> {
+        self.internal.lock().unwrap().clear_quarantined_messages()
+    }
+
+    /// Lists the active subscriptions, oldest first.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - An error occurred accessing the persisted storage
+    pub fn list_subscriptions(&self) -> Result<Vec<PushSubscription>> {
+        self.internal.lock().unwrap().list_subscriptions()
+    }
+
+    /// Changes the VAPID public key of an existing subscription.
+    ///
+    /// The push server ties a subscription's endpoint to its key, so this
+    /// subscribes again, which gives the subscription a new endpoint and new
+    /// keys. The new subscription info must be sent to whoever sends push
+    /// messages to this subscription. If the key hasn't changed, the existing
+    /// subscription is returned.
+    ///
+    /// # Arguments
+    ///   - `channel_id` - Channel ID (UUID) of the subscription to change
+    ///   - `app_server_key` - The new VAPID public key, or null to remove the key
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - There is no subscription for `channel_id`
+    ///   - An error occurred sending a request to the autopush server
+    ///   - An error occurred accessing the PushManager's persisted storage
+    pub fn update_app_server_key(
+        &self,
+        channel_id: &str,
+        app_server_key: &Option<String>,
+    ) -> Result<SubscriptionResponse> {
+        self.with_endpoint_changes(|internal| {
+            internal.update_app_server_key(channel_id, app_server_key.as_deref())
+        })
+    }
+
+    /// Sets the observer which is told when the endpoint of a subscription
+    /// changes, so that the consumer can keep the registrations on its
+    /// servers up to date. This replaces any existing observer.
+    pub fn set_subscription_observer(&self, observer: Box<dyn PushSubscriptionObserver>) {
+        *self.observer.lock().unwrap() = Some(Arc::from(observer));
+    }
+
     /// Get the dispatch info for a given subscription channel
     ///
     /// # Arguments
@@ -464,6 +585,48 @@ pub struct SubscriptionResponse {
     pub subscription_info: SubscriptionInfo,
 }
 
+/// A quarantined message which could be decrypted, returned from
+/// [`PushManager::retry_quarantined_messages`]
+#[derive(Debug, Clone)]
+pub struct DecryptedMessage {
+    pub channel_id: String,
+    /// The decrypted message body, as signed bytes like [`PushManager::decrypt`]
+    pub data: Vec<i8>,
+}
+
+/// An active subscription, returned from [`PushManager::list_subscriptions`]
+#[derive(Debug, Clone)]
+pub struct PushSubscription {
+    pub channel_id: String,
+    pub scope: String,
+    pub subscription_info: SubscriptionInfo,
+    pub app_server_key: Option<String>,
+    /// When the subscription was created, in milliseconds since the epoch.
+    pub created_at: i64,
+}
+
+/// Describes a change to the endpoint of a subscription, passed to
+/// [`PushSubscriptionObserver::on_endpoint_changed`]
+#[derive(Debug, Clone)]
+pub struct EndpointChange {
+    pub channel_id: String,
+    pub scope: String,
+    pub old_endpoint: String,
+    /// The new subscription info, or `None` if the subscription was lost and
+    /// the consumer should subscribe again.
+    pub new_subscription: Option<SubscriptionResponse>,
+}
+
+/// Told when the endpoint of a subscription changes. Endpoints change when
+/// the push server loses our subscriptions (eg, when we get a new UAID) and
+/// when the app server key of a subscription is changed.
+///
+/// The application implements this via a UniFFI callback interface, then
+/// calls [`PushManager::set_subscription_observer`].
+pub trait PushSubscriptionObserver: Send + Sync {
+    fn on_endpoint_changed(&self, change: EndpointChange);
+}
+
 /// An dictionary describing the push subscription that changed, the caller
 /// will receive a list of [`PushSubscriptionChanged`] when calling
 /// [`PushManager::verify_connection`], one entry for each channel that the
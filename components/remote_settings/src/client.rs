@@ -0,0 +1,139 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Fetches the records of a single collection. This is the small subset of
+//! the Remote Settings API that we need: we ask for all the records, and use
+//! the `ETag` from the last response to have the server tell us when they
+//! haven't changed.
+
+use crate::error::{RemoteSettingsError, Result};
+use crate::RemoteSettingsConfig;
+use serde_derive::*;
+use std::time::Duration;
+use url::Url;
+use viaduct::{header_names, status_codes, Request, Response};
+
+const HEADER_BACKOFF: &str = "Backoff";
+const HEADER_RETRY_AFTER: &str = "Retry-After";
+
+/// A record in a collection. The fields other than the id and the time it
+/// was last modified depend on the collection.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RemoteSettingsRecord {
+    pub id: String,
+    pub last_modified: u64,
+    #[serde(flatten)]
+    pub fields: RsJsonObject,
+}
+
+pub type RsJsonObject = serde_json::Map<String, serde_json::Value>;
+
+#[derive(Deserialize)]
+struct RecordsResponse {
+    data: Vec<RemoteSettingsRecord>,
+}
+
+/// Parses the body of a [`FetchResult::Changed`] into records.
+pub fn parse_records(body: &str) -> Result<Vec<RemoteSettingsRecord>> {
+    Ok(serde_json::from_str::<RecordsResponse>(body)?.data)
+}
+
+#[derive(Debug)]
+pub enum FetchResult {
+    /// The records haven't changed since the response with the `ETag` we
+    /// sent.
+    NotModified,
+    /// The body of the response, which components can keep as it is, and
+    /// its `ETag`, to send next time.
+    Changed { body: String, etag: Option<String> },
+}
+
+#[derive(Debug)]
+pub struct FetchResponse {
+    pub result: Result<FetchResult>,
+    /// How long the server asked us to wait before making any more requests,
+    /// which it can ask for along with any response, successful or not. It's
+    /// up to the caller to wait.
+    pub backoff: Option<Duration>,
+}
+
+/// Fetches collections from one bucket. The [`SubscriptionManager`](
+/// crate::SubscriptionManager) uses this for the collections components
+/// subscribe to, and components which keep their own copy of a collection,
+/// like Nimbus, can use it directly.
+pub struct Client {
+    base_url: Url,
+    bucket_name: String,
+}
+
+impl Client {
+    pub fn new(config: RemoteSettingsConfig) -> Result<Self> {
+        Ok(Self {
+            base_url: Url::parse(&config.server_url)?,
+            bucket_name: config.bucket_name,
+        })
+    }
+
+    /// Fetches all the records in `collection`, unless they haven't changed
+    /// since the response with `etag`.
+    pub fn fetch_collection(&self, collection: &str, etag: Option<&str>) -> Result<FetchResponse> {
+        let path = format!(
+            "v1/buckets/{}/collections/{}/records",
+            self.bucket_name, collection
+        );
+        let mut req = Request::get(self.base_url.join(&path)?);
+        if let Some(etag) = etag {
+            req = req.header(header_names::IF_NONE_MATCH, etag.to_string())?;
+        }
+        let resp = req.send()?;
+        Ok(FetchResponse {
+            backoff: backoff_hint(&resp),
+            result: parse_response(&resp, etag.is_some()),
+        })
+    }
+}
+
+fn parse_response(resp: &Response, sent_etag: bool) -> Result<FetchResult> {
+    if resp.status == status_codes::NOT_MODIFIED {
+        // We only send `If-None-Match` when we have records to keep using.
+        return if sent_etag {
+            Ok(FetchResult::NotModified)
+        } else {
+            Err(RemoteSettingsError::ResponseError(
+                "Unexpected 304 response".to_string(),
+            ))
+        };
+    }
+    if !resp.is_success() {
+        return Err(RemoteSettingsError::ResponseError(format!(
+            "{}: {}",
+            resp.status,
+            resp.text()
+        )));
+    }
+    Ok(FetchResult::Changed {
+        body: resp.text().to_string(),
+        etag: resp
+            .headers
+            .get(header_names::ETAG)
+            .map(ToString::to_string),
+    })
+}
+
+fn backoff_hint(resp: &Response) -> Option<Duration> {
+    let extract_backoff_header = |header| -> u64 {
+        resp.headers
+            .get_as::<u64, _>(header)
+            .transpose()
+            .unwrap_or_default() // Ignore number parsing errors.
+            .unwrap_or(0)
+    };
+    // In practice these two headers are mutually exclusive.
+    let backoff = extract_backoff_header(HEADER_BACKOFF);
+    let retry_after = extract_backoff_header(HEADER_RETRY_AFTER);
+    match backoff.max(retry_after) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
@@ -0,0 +1,55 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A client for the Remote Settings collections that components download
+//! their data from.
+//!
+//! Rather than each component polling its own collections, components
+//! subscribe to them with a [`SubscriptionManager`], which the application
+//! asks to refresh whichever collections are due. The manager honors the
+//! server's requests to back off, which apply to all of its collections, and
+//! only tells a component about a collection when its records have changed.
+//!
+//! Components which keep their own copy of a collection, like Nimbus, can
+//! instead fetch it themselves with a [`Client`].
+
+mod client;
+mod error;
+mod subscriptions;
+
+use client::RsJsonObject;
+pub use client::{parse_records, Client, FetchResponse, FetchResult, RemoteSettingsRecord};
+pub use error::{RemoteSettingsError, Result};
+pub use subscriptions::{CollectionListener, SubscriptionManager};
+
+uniffi_macros::include_scaffolding!("remote_settings");
+
+impl UniffiCustomTypeConverter for RsJsonObject {
+    type Builtin = String;
+
+    fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+        Ok(serde_json::from_str(&val)?)
+    }
+
+    fn from_custom(obj: Self) -> Self::Builtin {
+        serde_json::Value::Object(obj).to_string()
+    }
+}
+
+/// Where the collections live.
+#[derive(Debug, Clone)]
+pub struct RemoteSettingsConfig {
+    pub server_url: String,
+    pub bucket_name: String,
+}
+
+impl RemoteSettingsConfig {
+    /// The `main` bucket on `server_url`, which is where most collections live.
+    pub fn with_server_url(server_url: impl Into<String>) -> Self {
+        Self {
+            server_url: server_url.into(),
+            bucket_name: "main".to_string(),
+        }
+    }
+}
@@ -0,0 +1,376 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::client::{parse_records, Client, FetchResult};
+use crate::error::{RemoteSettingsError, Result};
+use crate::{RemoteSettingsConfig, RemoteSettingsRecord};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Told about the records in a collection a component has subscribed to.
+pub trait CollectionListener: Send + Sync {
+    /// Called with all the records in `collection`, when the component
+    /// subscribes if they've already been fetched, and then whenever they
+    /// change.
+    fn on_collection_changed(&self, collection: String, records: Vec<RemoteSettingsRecord>);
+}
+
+/// Keeps track of the collections components have subscribed to, and
+/// refreshes them for all the components at once.
+///
+/// The manager doesn't have a timer of its own: the application calls
+/// [`refresh_due`](SubscriptionManager::refresh_due) when it's a good time to
+/// use the network, and can use [`time_until_next_refresh`](
+/// SubscriptionManager::time_until_next_refresh) to schedule that.
+pub struct SubscriptionManager {
+    client: Client,
+    state: Mutex<ManagerState>,
+}
+
+#[derive(Default)]
+struct ManagerState {
+    subscriptions: BTreeMap<String, Subscription>,
+    // When the server asks us to back off, it's for all our requests, not
+    // just the one it answered.
+    backoff_until: Option<Instant>,
+}
+
+struct Subscription {
+    // The shortest interval any of the components asked for.
+    refresh_interval: Duration,
+    next_refresh_at: Instant,
+    etag: Option<String>,
+    records: Option<Arc<Vec<RemoteSettingsRecord>>>,
+    // The listeners of the components subscribed to the collection, by the
+    // name of the component.
+    listeners: BTreeMap<String, Arc<dyn CollectionListener>>,
+}
+
+impl SubscriptionManager {
+    pub fn new(config: RemoteSettingsConfig) -> Result<Self> {
+        Ok(Self {
+            client: Client::new(config)?,
+            state: Mutex::default(),
+        })
+    }
+
+    /// Subscribes `component` to `collection`, which is refreshed at least
+    /// every `refresh_interval`. A new collection is due to be refreshed
+    /// straight away. If the records have already been fetched for another
+    /// component, `listener` is called with them before this returns.
+    /// Subscribing a component to a collection again replaces its listener.
+    pub fn subscribe(
+        &self,
+        component: &str,
+        collection: &str,
+        refresh_interval: Duration,
+        listener: Box<dyn CollectionListener>,
+    ) {
+        let listener: Arc<dyn CollectionListener> = Arc::from(listener);
+        let records = {
+            let mut state = self.state.lock().unwrap();
+            let subscription = state
+                .subscriptions
+                .entry(collection.to_string())
+                .or_insert_with(|| Subscription {
+                    refresh_interval,
+                    next_refresh_at: Instant::now(),
+                    etag: None,
+                    records: None,
+                    listeners: BTreeMap::new(),
+                });
+            if refresh_interval < subscription.refresh_interval {
+                subscription.next_refresh_at = subscription
+                    .next_refresh_at
+                    .min(Instant::now() + refresh_interval);
+                subscription.refresh_interval = refresh_interval;
+            }
+            subscription
+                .listeners
+                .insert(component.to_string(), listener.clone());
+            subscription.records.clone()
+        };
+        if let Some(records) = records {
+            listener.on_collection_changed(collection.to_string(), records.to_vec());
+        }
+    }
+
+    /// Unsubscribes `component` from `collection`. Collections which no
+    /// component is subscribed to are no longer refreshed.
+    pub fn unsubscribe(&self, component: &str, collection: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(subscription) = state.subscriptions.get_mut(collection) {
+            subscription.listeners.remove(component);
+            if subscription.listeners.is_empty() {
+                state.subscriptions.remove(collection);
+            }
+        }
+    }
+
+    /// Returns when the next collection is due to be refreshed, allowing for
+    /// any backoff the server asked for, or `None` if there aren't any
+    /// subscriptions.
+    pub fn next_refresh_at(&self) -> Option<Instant> {
+        let state = self.state.lock().unwrap();
+        let next_refresh_at = state
+            .subscriptions
+            .values()
+            .map(|subscription| subscription.next_refresh_at)
+            .min()?;
+        Some(match state.backoff_until {
+            Some(backoff_until) => next_refresh_at.max(backoff_until),
+            None => next_refresh_at,
+        })
+    }
+
+    /// Returns how long it is until [`next_refresh_at`](
+    /// SubscriptionManager::next_refresh_at), which is zero if a collection is
+    /// already due.
+    pub fn time_until_next_refresh(&self) -> Option<Duration> {
+        self.next_refresh_at()
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Refreshes the collections which are due now. See [`refresh_due_at`](
+    /// SubscriptionManager::refresh_due_at).
+    pub fn refresh_due(&self) -> Result<()> {
+        self.refresh_due_at(Instant::now())
+    }
+
+    /// Refreshes the collections which are due at `now`, and tells their
+    /// listeners about the ones that changed. Failing to fetch a collection
+    /// is logged, and it's tried again after its refresh interval. If the
+    /// server asks us to back off, the collections we haven't fetched yet
+    /// stay due, and this returns a `BackoffError`, as it does if we're
+    /// still backing off from an earlier request.
+    pub fn refresh_due_at(&self, now: Instant) -> Result<()> {
+        let due = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(backoff_until) = state.backoff_until {
+                if backoff_until > now {
+                    return Err(RemoteSettingsError::BackoffError(
+                        (backoff_until - now).as_secs(),
+                    ));
+                }
+                state.backoff_until = None;
+            }
+            state
+                .subscriptions
+                .iter()
+                .filter(|(_, subscription)| subscription.next_refresh_at <= now)
+                .map(|(collection, subscription)| (collection.clone(), subscription.etag.clone()))
+                .collect::<Vec<_>>()
+        };
+        // We don't hold the lock while we're fetching, so components can
+        // subscribe and unsubscribe in the meantime.
+        for (collection, etag) in due {
+            let response = self.client.fetch_collection(&collection, etag.as_deref());
+            let mut state = self.state.lock().unwrap();
+            let (result, backoff) = match response {
+                Ok(response) => (response.result, response.backoff),
+                Err(e) => (Err(e), None),
+            };
+            if let Some(backoff) = backoff {
+                state.backoff_until = Some(now + backoff);
+            }
+            // Everyone might have unsubscribed while we were fetching.
+            let changed = match (state.subscriptions.get_mut(&collection), result) {
+                (None, _) => None,
+                (Some(subscription), result) => {
+                    subscription.next_refresh_at = now + subscription.refresh_interval;
+                    match result.and_then(|result| match result {
+                        FetchResult::Changed { body, etag } => {
+                            Ok(Some((parse_records(&body)?, etag)))
+                        }
+                        FetchResult::NotModified => Ok(None),
+                    }) {
+                        Ok(Some((records, etag))) => {
+                            let records = Arc::new(records);
+                            subscription.etag = etag;
+                            subscription.records = Some(records.clone());
+                            Some((records, subscription.listeners.clone()))
+                        }
+                        Ok(None) => None,
+                        Err(e) => {
+                            log::warn!("Failed to refresh {}: {}", collection, e);
+                            None
+                        }
+                    }
+                }
+            };
+            let backoff_until = state.backoff_until;
+            drop(state);
+            if let Some((records, listeners)) = changed {
+                for listener in listeners.values() {
+                    listener.on_collection_changed(collection.clone(), records.to_vec());
+                }
+            }
+            if let Some(backoff_until) = backoff_until {
+                return Err(RemoteSettingsError::BackoffError(
+                    (backoff_until - now).as_secs(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, Matcher};
+
+    #[derive(Clone, Default)]
+    struct RecordingListener {
+        calls: Arc<Mutex<Vec<(String, Vec<String>)>>>,
+    }
+
+    impl RecordingListener {
+        fn take_calls(&self) -> Vec<(String, Vec<String>)> {
+            std::mem::take(&mut *self.calls.lock().unwrap())
+        }
+    }
+
+    impl CollectionListener for RecordingListener {
+        fn on_collection_changed(&self, collection: String, records: Vec<RemoteSettingsRecord>) {
+            self.calls.lock().unwrap().push((
+                collection,
+                records.into_iter().map(|record| record.id).collect(),
+            ));
+        }
+    }
+
+    fn records_path(collection: &str) -> String {
+        format!("/v1/buckets/main/collections/{}/records", collection)
+    }
+
+    #[test]
+    fn test_subscriptions() {
+        viaduct_reqwest::use_reqwest_backend();
+        let manager =
+            SubscriptionManager::new(RemoteSettingsConfig::with_server_url(mockito::server_url()))
+                .unwrap();
+        assert_eq!(manager.next_refresh_at(), None);
+
+        let suggest = RecordingListener::default();
+        let search = RecordingListener::default();
+        let hour = Duration::from_secs(60 * 60);
+        manager.subscribe(
+            "suggest",
+            "test-subs-shared",
+            hour,
+            Box::new(suggest.clone()),
+        );
+        manager.subscribe(
+            "search",
+            "test-subs-shared",
+            hour * 2,
+            Box::new(search.clone()),
+        );
+
+        let first = mock("GET", records_path("test-subs-shared").as_str())
+            .match_header("if-none-match", Matcher::Missing)
+            .with_status(200)
+            .with_header("etag", "\"1\"")
+            .with_body(r#"{"data": [{"id": "a", "last_modified": 1, "title": "A"}]}"#)
+            .create();
+        let now = Instant::now();
+        manager.refresh_due_at(now).expect("should refresh");
+        first.expect(1).assert();
+        // Both components get the records, with one request.
+        let expected = vec![("test-subs-shared".to_string(), vec!["a".to_string()])];
+        assert_eq!(suggest.take_calls(), expected);
+        assert_eq!(search.take_calls(), expected);
+        // The collection is refreshed as often as the component that asked
+        // for the shortest interval wants.
+        assert_eq!(manager.next_refresh_at(), Some(now + hour));
+
+        // Nothing's due until then.
+        manager.refresh_due_at(now).expect("should do nothing");
+        first.expect(1).assert();
+
+        // Components which subscribe later get the records straight away.
+        let nimbus = RecordingListener::default();
+        manager.subscribe("nimbus", "test-subs-shared", hour, Box::new(nimbus.clone()));
+        assert_eq!(nimbus.take_calls(), expected);
+
+        // Listeners aren't called if the records haven't changed.
+        let unchanged = mock("GET", records_path("test-subs-shared").as_str())
+            .match_header("if-none-match", "\"1\"")
+            .with_status(304)
+            .create();
+        manager.refresh_due_at(now + hour).expect("should refresh");
+        unchanged.expect(1).assert();
+        assert!(suggest.take_calls().is_empty());
+
+        manager.unsubscribe("suggest", "test-subs-shared");
+        manager.unsubscribe("search", "test-subs-shared");
+        assert!(manager.next_refresh_at().is_some());
+        manager.unsubscribe("nimbus", "test-subs-shared");
+        assert_eq!(manager.next_refresh_at(), None);
+    }
+
+    #[test]
+    fn test_backoff() {
+        viaduct_reqwest::use_reqwest_backend();
+        let manager =
+            SubscriptionManager::new(RemoteSettingsConfig::with_server_url(mockito::server_url()))
+                .unwrap();
+        let listener = RecordingListener::default();
+        let hour = Duration::from_secs(60 * 60);
+        manager.subscribe(
+            "suggest",
+            "test-backoff-a",
+            hour,
+            Box::new(listener.clone()),
+        );
+        manager.subscribe(
+            "suggest",
+            "test-backoff-b",
+            hour,
+            Box::new(listener.clone()),
+        );
+
+        let a = mock("GET", records_path("test-backoff-a").as_str())
+            .with_status(200)
+            .with_header("Backoff", "60")
+            .with_body(r#"{"data": []}"#)
+            .create();
+        let b = mock("GET", records_path("test-backoff-b").as_str())
+            .with_status(200)
+            .with_body(r#"{"data": []}"#)
+            .create();
+        let now = Instant::now();
+        assert!(matches!(
+            manager.refresh_due_at(now),
+            Err(RemoteSettingsError::BackoffError(60))
+        ));
+        // The response that asked us to back off still counts...
+        a.expect(1).assert();
+        assert_eq!(
+            listener.take_calls(),
+            vec![("test-backoff-a".to_string(), vec![])]
+        );
+        // ...but we don't make any more requests until the backoff is over.
+        b.expect(0).assert();
+        assert_eq!(
+            manager.next_refresh_at(),
+            Some(now + Duration::from_secs(60))
+        );
+        assert!(matches!(
+            manager.refresh_due_at(now + Duration::from_secs(30)),
+            Err(RemoteSettingsError::BackoffError(30))
+        ));
+
+        manager
+            .refresh_due_at(now + Duration::from_secs(60))
+            .expect("should refresh");
+        b.expect(1).assert();
+        assert_eq!(
+            listener.take_calls(),
+            vec![("test-backoff-b".to_string(), vec![])]
+        );
+    }
+}
@@ -33,6 +33,12 @@ pub use reporting::{
     unset_application_error_reporter, ApplicationErrorReporter,
 };
 
+mod storage_health;
+pub use storage_health::{
+    get_storage_health, record_database_corruption, record_database_migration,
+    record_database_open_failure, record_database_opened, DatabaseHealth, MigrationTiming,
+};
+
 mod handling;
 pub use handling::{convert_log_report_error, ErrorHandling, ErrorReporting, GetErrorHandling};
 
@@ -0,0 +1,125 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// We only keep this many migrations for each database - older ones are
+/// dropped.
+const MAX_MIGRATIONS: usize = 10;
+
+/// How long a schema migration took.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationTiming {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub duration_ms: u64,
+}
+
+/// What we know about the health of one SQLite database since the process
+/// started. Components report these as they open their databases, and the
+/// embedder can fetch them all with `get_storage_health()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatabaseHealth {
+    /// The name the component uses for the database (eg, "places").
+    pub name: String,
+    pub successful_opens: u32,
+    pub open_failures: u32,
+    /// How many times the database was found to be corrupt (and was replaced
+    /// with a new, empty one).
+    pub corruption_events: u32,
+    /// The most recent error opening the database.
+    pub last_error: Option<String>,
+    /// The most recent schema migrations, oldest first.
+    pub migrations: Vec<MigrationTiming>,
+}
+
+lazy_static::lazy_static! {
+    static ref STORAGE_HEALTH: Mutex<BTreeMap<String, DatabaseHealth>> = Mutex::new(BTreeMap::new());
+}
+
+fn with_health(name: &str, f: impl FnOnce(&mut DatabaseHealth)) {
+    let mut health = STORAGE_HEALTH.lock();
+    let entry = health
+        .entry(name.to_string())
+        .or_insert_with(|| DatabaseHealth {
+            name: name.to_string(),
+            ..Default::default()
+        });
+    f(entry)
+}
+
+pub fn record_database_opened(name: &str) {
+    with_health(name, |health| health.successful_opens += 1);
+}
+
+pub fn record_database_open_failure(name: &str, error: &str) {
+    with_health(name, |health| {
+        health.open_failures += 1;
+        health.last_error = Some(error.to_string());
+    });
+}
+
+pub fn record_database_corruption(name: &str) {
+    with_health(name, |health| health.corruption_events += 1);
+}
+
+pub fn record_database_migration(name: &str, from_version: u32, to_version: u32, took: Duration) {
+    with_health(name, |health| {
+        if health.migrations.len() == MAX_MIGRATIONS {
+            health.migrations.remove(0);
+        }
+        health.migrations.push(MigrationTiming {
+            from_version,
+            to_version,
+            duration_ms: took.as_millis() as u64,
+        });
+    });
+}
+
+/// Returns the health of every database which has been opened (or failed to
+/// open) since the process started, sorted by name.
+pub fn get_storage_health() -> Vec<DatabaseHealth> {
+    STORAGE_HEALTH.lock().values().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(name: &str) -> DatabaseHealth {
+        get_storage_health()
+            .into_iter()
+            .find(|health| health.name == name)
+            .expect("should have health")
+    }
+
+    #[test]
+    fn test_storage_health() {
+        // Other tests may record health at the same time, so we use a name
+        // no one else does.
+        let name = "test_storage_health db";
+        record_database_open_failure(name, "disk I/O error");
+        record_database_corruption(name);
+        record_database_opened(name);
+        for version in 0..12 {
+            record_database_migration(name, version, version + 1, Duration::from_millis(5));
+        }
+        let health = get(name);
+        assert_eq!(health.successful_opens, 1);
+        assert_eq!(health.open_failures, 1);
+        assert_eq!(health.corruption_events, 1);
+        assert_eq!(health.last_error.as_deref(), Some("disk I/O error"));
+        assert_eq!(health.migrations.len(), MAX_MIGRATIONS);
+        assert_eq!(
+            health.migrations[0],
+            MigrationTiming {
+                from_version: 2,
+                to_version: 3,
+                duration_ms: 5,
+            }
+        );
+    }
+}
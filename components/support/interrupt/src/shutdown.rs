@@ -41,6 +41,14 @@ lazy_static::lazy_static! {
 /// Initiate shutdown mode
 pub fn shutdown() {
     IN_SHUTDOWN.store(true, Ordering::Relaxed);
+    interrupt_all_registered();
+}
+
+/// Interrupt everything running on every registered `SqlInterruptHandle`, in every component.
+///
+/// Unlike `shutdown()`, this doesn't enter shutdown mode, so new operations can still begin
+/// afterwards.
+pub fn interrupt_all_registered() {
     for weak in REGISTERED_INTERRUPTS.lock().iter() {
         if let Some(interrupt) = weak.upgrade() {
             interrupt.as_ref().as_ref().interrupt()
@@ -3,13 +3,37 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::{in_shutdown, Interrupted, Interruptee};
+use parking_lot::Mutex;
 use rusqlite::{Connection, InterruptHandle};
+use std::cell::Cell;
 use std::fmt;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
 };
 
+/// How important an operation is, which decides whether it's cancelled by
+/// `SqlInterruptHandle::interrupt_at_or_below()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum InterruptPriority {
+    /// Work that can be done later, like maintenance and imports.
+    Background = 0,
+    /// The default, for operations that don't declare a priority.
+    Normal = 1,
+    /// Work the user is waiting on as they type, like autocomplete.
+    Interactive = 2,
+}
+
+const PRIORITY_COUNT: usize = 3;
+
+thread_local! {
+    // The priority of the operation running on this thread, which scopes
+    // begun on this thread inherit. Operations run on the thread that
+    // called into us, so this is simpler than passing the priority down to
+    // every function that begins a scope.
+    static CURRENT_PRIORITY: Cell<InterruptPriority> = Cell::new(InterruptPriority::Normal);
+}
+
 /// Interrupt operations that use SQL
 ///
 /// Typical usage of this type:
@@ -21,25 +45,57 @@ use std::sync::{
 ///   - The wrapper calls `begin_interrupt_scope()` at the start of each operation.  The code that
 ///     performs the operation periodically calls `err_if_interrupted()`.
 ///   - Finally, the wrapper class implements `AsRef<SqlInterruptHandle>` and calls
-///     `register_interrupt()`.  This causes all operations to be interrupted when we enter
-///     shutdown mode.
+///     `register_interrupt()`, or registers its `Arc<SqlInterruptHandle>` directly.  This causes
+///     all operations to be interrupted when we enter shutdown mode.
 pub struct SqlInterruptHandle {
-    db_handle: InterruptHandle,
-    // Counter that we increment on each interrupt() call.
-    // We use Ordering::Relaxed to read/write to this variable.  This is safe because we're
-    // basically using it as a flag and don't need stronger synchronization guarentees.
-    interrupt_counter: Arc<AtomicUsize>,
+    // The connection we interrupt, or `None` for a pool's handle; see `new_for_pool`.
+    db_handle: Option<InterruptHandle>,
+    // The handles for each connection in a pool, which decide for themselves whether to
+    // interrupt their connection.
+    pool_members: Vec<Arc<SqlInterruptHandle>>,
+    // A counter for each priority, which we increment when operations at that priority are
+    // interrupted.
+    // We use Ordering::Relaxed to read/write to these variables.  This is safe because we're
+    // basically using them as flags and don't need stronger synchronization guarentees.
+    interrupt_counters: Arc<[AtomicUsize; PRIORITY_COUNT]>,
+    // The number of operations running at each priority. This is locked while we decide whether
+    // to interrupt the connections and do so, so that a higher-priority operation can't start
+    // in between.
+    running: Arc<Mutex<[usize; PRIORITY_COUNT]>>,
 }
 
 impl SqlInterruptHandle {
     #[inline]
     pub fn new(conn: &Connection) -> Self {
         Self {
-            db_handle: conn.get_interrupt_handle(),
-            interrupt_counter: Arc::new(AtomicUsize::new(0)),
+            db_handle: Some(conn.get_interrupt_handle()),
+            pool_members: Vec::new(),
+            interrupt_counters: Default::default(),
+            running: Default::default(),
+        }
+    }
+
+    /// Create a handle for a pool of connections, from the handles for each of them.  Calling
+    /// `interrupt_at_or_below()` calls it on each member, so whether a connection's queries are
+    /// interrupted only depends on the operations running on that connection.
+    ///
+    /// Operations and scopes should be begun with the member handle for the connection they
+    /// run on.
+    pub fn new_for_pool(pool_members: Vec<Arc<SqlInterruptHandle>>) -> Self {
+        Self {
+            db_handle: None,
+            pool_members,
+            interrupt_counters: Default::default(),
+            running: Default::default(),
         }
     }
 
+    /// Begin an operation at `priority`, which lasts until the returned value is dropped.
+    /// Interrupt scopes begun on this thread during the operation have its priority.
+    pub fn begin_operation(&self, priority: InterruptPriority) -> SqlInterruptOperation {
+        SqlInterruptOperation::new(Arc::clone(&self.running), priority)
+    }
+
     /// Begin an interrupt scope that will be interrupted by this handle
     ///
     /// Returns Err(Interrupted) if we're in shutdown mode
@@ -48,29 +104,88 @@ impl SqlInterruptHandle {
         if in_shutdown() {
             Err(Interrupted)
         } else {
-            Ok(SqlInterruptScope::new(Arc::clone(&self.interrupt_counter)))
+            let priority = CURRENT_PRIORITY.with(Cell::get);
+            Ok(SqlInterruptScope::new(
+                Arc::clone(&self.interrupt_counters),
+                priority,
+            ))
         }
     }
 
     /// Interrupt all interrupt scopes created by this handle
     #[inline]
     pub fn interrupt(&self) {
-        self.interrupt_counter.fetch_add(1, Ordering::Relaxed);
-        self.db_handle.interrupt();
+        self.interrupt_at_or_below(InterruptPriority::Interactive)
+    }
+
+    /// Interrupt the interrupt scopes created by this handle with `priority` or lower.
+    ///
+    /// SQLite can only interrupt everything running on a connection, so we only interrupt the
+    /// queries themselves if no operation with a higher priority is running.  Otherwise,
+    /// lower-priority operations stop the next time they check their scope.
+    pub fn interrupt_at_or_below(&self, priority: InterruptPriority) {
+        let level = priority as usize;
+        for counter in &self.interrupt_counters[..=level] {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(db_handle) = &self.db_handle {
+            let running = self.running.lock();
+            let higher_running = running[level + 1..].iter().any(|count| *count > 0);
+            if !higher_running {
+                db_handle.interrupt();
+            }
+        }
+        for member in &self.pool_members {
+            member.interrupt_at_or_below(priority);
+        }
     }
 }
 
 impl fmt::Debug for SqlInterruptHandle {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("SqlInterruptHandle")
-            .field(
-                "interrupt_counter",
-                &self.interrupt_counter.load(Ordering::Relaxed),
-            )
+            .field("interrupt_counters", &self.interrupt_counters)
+            .field("running", &self.running)
             .finish()
     }
 }
 
+// Lets components which keep an `Arc<SqlInterruptHandle>` register it directly.
+impl AsRef<SqlInterruptHandle> for SqlInterruptHandle {
+    fn as_ref(&self) -> &SqlInterruptHandle {
+        self
+    }
+}
+
+/// An operation running at a priority, returned by `SqlInterruptHandle::begin_operation()`.
+#[derive(Debug)]
+pub struct SqlInterruptOperation {
+    running: Arc<Mutex<[usize; PRIORITY_COUNT]>>,
+    priority: InterruptPriority,
+    // The priority of the operation this one is nested in, if any, which we restore when
+    // this one ends.
+    outer_priority: InterruptPriority,
+}
+
+impl SqlInterruptOperation {
+    fn new(running: Arc<Mutex<[usize; PRIORITY_COUNT]>>, priority: InterruptPriority) -> Self {
+        running.lock()[priority as usize] += 1;
+        let outer_priority = CURRENT_PRIORITY.with(|current| current.replace(priority));
+        Self {
+            running,
+            priority,
+            outer_priority,
+        }
+    }
+}
+
+impl Drop for SqlInterruptOperation {
+    fn drop(&mut self) {
+        self.running.lock()[self.priority as usize] -= 1;
+        CURRENT_PRIORITY.with(|current| current.set(self.outer_priority));
+    }
+}
+
 /// Check if an operation has been interrupted
 ///
 /// This is used by the rust code to check if an operation should fail because it was interrupted.
@@ -78,15 +193,20 @@ impl fmt::Debug for SqlInterruptHandle {
 #[derive(Debug)]
 pub struct SqlInterruptScope {
     start_value: usize,
-    interrupt_counter: Arc<AtomicUsize>,
+    interrupt_counters: Arc<[AtomicUsize; PRIORITY_COUNT]>,
+    priority: InterruptPriority,
 }
 
 impl SqlInterruptScope {
-    fn new(interrupt_counter: Arc<AtomicUsize>) -> Self {
-        let start_value = interrupt_counter.load(Ordering::Relaxed);
+    fn new(
+        interrupt_counters: Arc<[AtomicUsize; PRIORITY_COUNT]>,
+        priority: InterruptPriority,
+    ) -> Self {
+        let start_value = interrupt_counters[priority as usize].load(Ordering::Relaxed);
         Self {
             start_value,
-            interrupt_counter,
+            interrupt_counters,
+            priority,
         }
     }
 
@@ -94,13 +214,13 @@ impl SqlInterruptScope {
     //
     // This should only be used for testing purposes.
     pub fn dummy() -> Self {
-        Self::new(Arc::new(AtomicUsize::new(0)))
+        Self::new(Default::default(), InterruptPriority::Normal)
     }
 
     /// Check if scope has been interrupted
     #[inline]
     pub fn was_interrupted(&self) -> bool {
-        self.interrupt_counter.load(Ordering::Relaxed) != self.start_value
+        self.interrupt_counters[self.priority as usize].load(Ordering::Relaxed) != self.start_value
     }
 
     /// Return Err(Interrupted) if we were interrupted
@@ -0,0 +1,33 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// `interrupt_all_registered()` interrupts every handle in the process, so this
+// lives in its own test binary, where it can't interrupt other tests.
+
+use interrupt_support::{
+    in_shutdown, interrupt_all_registered, register_interrupt, SqlInterruptHandle,
+};
+use rusqlite::Connection;
+use std::sync::Arc;
+
+#[test]
+fn test_interrupt_all_registered() {
+    let conn = Connection::open_in_memory().unwrap();
+    let handle = Arc::new(SqlInterruptHandle::new(&conn));
+    register_interrupt(Arc::<SqlInterruptHandle>::downgrade(&handle));
+
+    let scope = handle.begin_interrupt_scope().unwrap();
+    assert!(!scope.was_interrupted());
+    interrupt_all_registered();
+    assert!(scope.was_interrupted());
+
+    // Unlike `shutdown()`, new operations can still begin.
+    assert!(!in_shutdown());
+    let scope = handle.begin_interrupt_scope().unwrap();
+    assert!(!scope.was_interrupted());
+
+    // Handles which have been dropped are skipped.
+    drop(handle);
+    interrupt_all_registered();
+}
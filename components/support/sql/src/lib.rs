@@ -7,6 +7,7 @@
 
 mod conn_ext;
 mod each_chunk;
+pub mod maintenance;
 mod maybe_cached;
 pub mod open_database;
 mod repeat;
@@ -0,0 +1,203 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/// Use this module to keep a database from growing without bound.
+///
+/// Maintenance is meant to be run during idle time. It has four steps:
+///    - Prune: the component deletes data it can do without. What that is
+///      depends on the component, so it's part of its `MaintenancePolicy`.
+///    - Vacuum: rebuilds the database file, returning free pages to the file
+///      system. This rewrites everything, so `run_maintenance()` only does it
+///      when enough of the file is free, as decided by the policy.
+///    - Optimize: `PRAGMA optimize`, which updates the statistics the query
+///      planner uses.
+///    - Checkpoint: moves what's in the write-ahead log into the database, so
+///      the log doesn't keep growing.
+///
+/// Components which time each step separately (like places does, from
+/// Kotlin) can call the step functions themselves.
+use crate::ConnExt;
+use rusqlite::{Connection, Result};
+use std::time::Instant;
+
+/// What one of the maintenance steps after pruning did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MaintenanceStepMetrics {
+    /// The number of unused pages in the database file, before and after the
+    /// step. Only vacuuming returns them to the file system.
+    pub freelist_count_before: u32,
+    pub freelist_count_after: u32,
+    pub duration_ms: u64,
+}
+
+/// What `run_maintenance()` did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MaintenanceMetrics {
+    /// The number of items the policy pruned.
+    pub pruned: u32,
+    /// The approximate size of the database, in bytes, before and after.
+    pub db_size_before: u32,
+    pub db_size_after: u32,
+    /// `None` if the database didn't need vacuuming.
+    pub vacuum: Option<MaintenanceStepMetrics>,
+    pub optimize: MaintenanceStepMetrics,
+    pub checkpoint: MaintenanceStepMetrics,
+    pub duration_ms: u64,
+}
+
+pub trait MaintenancePolicy {
+    // Name to display in the logs
+    const NAME: &'static str;
+
+    // Vacuum when at least this percentage of the database file is free
+    // pages.
+    const VACUUM_FREE_PERCENT: u32 = 25;
+
+    // Deletes data the component can do without, and returns how many items
+    // it deleted. By default, nothing is deleted.
+    fn prune(&self, _conn: &Connection) -> Result<u32> {
+        Ok(0)
+    }
+}
+
+/// Runs all the maintenance steps on `conn`, as `policy` says.
+pub fn run_maintenance<P: MaintenancePolicy>(
+    conn: &Connection,
+    policy: &P,
+) -> Result<MaintenanceMetrics> {
+    let start = Instant::now();
+    let db_size_before = conn.get_db_size()?;
+    let pruned = policy.prune(conn)?;
+    let vacuum = if should_vacuum(conn, P::VACUUM_FREE_PERCENT)? {
+        Some(vacuum(conn)?)
+    } else {
+        None
+    };
+    let optimize = optimize(conn)?;
+    let checkpoint = checkpoint(conn)?;
+    let metrics = MaintenanceMetrics {
+        pruned,
+        db_size_before,
+        db_size_after: conn.get_db_size()?,
+        vacuum,
+        optimize,
+        checkpoint,
+        duration_ms: start.elapsed().as_millis() as u64,
+    };
+    log::info!(
+        "{}: maintenance pruned {} items and {} in {}ms; size {} -> {} bytes",
+        P::NAME,
+        metrics.pruned,
+        if metrics.vacuum.is_some() {
+            "vacuumed"
+        } else {
+            "didn't vacuum"
+        },
+        metrics.duration_ms,
+        metrics.db_size_before,
+        metrics.db_size_after,
+    );
+    Ok(metrics)
+}
+
+/// Rebuilds the database file, returning free pages to the file system.
+pub fn vacuum(conn: &Connection) -> Result<MaintenanceStepMetrics> {
+    run_step(conn, "VACUUM")
+}
+
+/// Updates the statistics the query planner uses.
+pub fn optimize(conn: &Connection) -> Result<MaintenanceStepMetrics> {
+    run_step(conn, "PRAGMA optimize")
+}
+
+/// Moves what it can from the write-ahead log into the database, without
+/// waiting for readers.
+pub fn checkpoint(conn: &Connection) -> Result<MaintenanceStepMetrics> {
+    run_step(conn, "PRAGMA wal_checkpoint(PASSIVE)")
+}
+
+fn get_freelist_count(conn: &Connection) -> Result<u32> {
+    conn.query_one("SELECT * FROM pragma_freelist_count()")
+}
+
+fn should_vacuum(conn: &Connection, free_percent: u32) -> Result<bool> {
+    let page_count: u32 = conn.query_one("SELECT * FROM pragma_page_count()")?;
+    let freelist_count = get_freelist_count(conn)?;
+    Ok(freelist_count > 0
+        && u64::from(freelist_count) * 100 >= u64::from(page_count) * u64::from(free_percent))
+}
+
+// Runs `sql` as a maintenance step, and records what it did.
+fn run_step(conn: &Connection, sql: &str) -> Result<MaintenanceStepMetrics> {
+    let start = Instant::now();
+    let freelist_count_before = get_freelist_count(conn)?;
+    conn.execute_one(sql)?;
+    Ok(MaintenanceStepMetrics {
+        freelist_count_before,
+        freelist_count_after: get_freelist_count(conn)?,
+        duration_ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TestPolicy;
+
+    impl MaintenancePolicy for TestPolicy {
+        const NAME: &'static str = "test";
+
+        fn prune(&self, conn: &Connection) -> Result<u32> {
+            Ok(conn.execute("DELETE FROM items WHERE id > 1000", [])? as u32)
+        }
+    }
+
+    fn new_db(dir: &tempfile::TempDir) -> Connection {
+        let conn = Connection::open(dir.path().join("maintenance.db")).unwrap();
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE items(id INTEGER PRIMARY KEY, data TEXT);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000)
+             INSERT INTO items(id, data) SELECT i, hex(randomblob(250)) FROM n;",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_run_maintenance() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = new_db(&dir);
+        let metrics = run_maintenance(&conn, &TestPolicy).unwrap();
+        assert_eq!(metrics.pruned, 1000);
+        // Pruning half the items freed enough pages to vacuum.
+        let vacuum = metrics.vacuum.expect("should vacuum");
+        assert!(vacuum.freelist_count_before > 0);
+        assert_eq!(vacuum.freelist_count_after, 0);
+        assert!(metrics.db_size_after < metrics.db_size_before);
+
+        // There's nothing left to prune, and no free pages, so nothing to vacuum.
+        let metrics = run_maintenance(&conn, &TestPolicy).unwrap();
+        assert_eq!(metrics.pruned, 0);
+        assert_eq!(metrics.vacuum, None);
+    }
+
+    #[test]
+    fn test_default_policy() {
+        struct DefaultPolicy;
+        impl MaintenancePolicy for DefaultPolicy {
+            const NAME: &'static str = "default";
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let conn = new_db(&dir);
+        conn.execute("DELETE FROM items WHERE id <= 100", [])
+            .unwrap();
+        // 5% of the pages are free, which isn't enough to vacuum.
+        let metrics = run_maintenance(&conn, &DefaultPolicy).unwrap();
+        assert_eq!(metrics.pruned, 0);
+        assert_eq!(metrics.vacuum, None);
+        assert_eq!(metrics.db_size_before, metrics.db_size_after);
+    }
+}
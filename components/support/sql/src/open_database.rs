@@ -11,7 +11,9 @@
 ///      - Extra preparation/finishing steps, for example setting up SQLite functions
 ///
 ///    - Call open_database() in your database constructor:
-///      - The first method called is `prepare()`.  This is executed outside of a transaction
+///      - The first method called is `unlock()`, which sets the key of an encrypted database
+///        before anything reads it.  Most databases aren't encrypted and don't implement it.
+///      - Next is `prepare()`.  This is executed outside of a transaction
 ///        and is suitable for executing pragmas (eg, `PRAGMA journal_mode=wal`), defining
 ///        functions, etc.
 ///      - If the database file is not present and the connection is writable, open_database()
@@ -27,11 +29,16 @@
 ///
 ///  See the autofill DB code for an example.
 ///
+///  Opening a database also records its health (open failures, corruption and how long
+///  migrations took) under `ConnectionInitializer::NAME`, so the embedder can see it via
+///  `error_support::get_storage_health()`.
+///
 use crate::ConnExt;
 use rusqlite::{
     Connection, Error as RusqliteError, ErrorCode, OpenFlags, Transaction, TransactionBehavior,
 };
 use std::path::Path;
+use std::time::Instant;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -40,6 +47,10 @@ pub enum Error {
     IncompatibleVersion(u32),
     #[error("Error executing SQL: {0}")]
     SqlError(#[from] rusqlite::Error),
+    // Returned by `ConnectionInitializer::unlock()`. Unlike `SqlError`, this
+    // is never treated as corruption, so we don't delete the database.
+    #[error("The database key is incorrect")]
+    IncorrectKey,
     // `.0` is the original `Error` in string form.
     #[error("Failed to recover a corrupt database ('{0}') due to an error deleting the file: {1}")]
     RecoveryError(String, std::io::Error),
@@ -61,6 +72,14 @@ pub trait ConnectionInitializer {
     // Upgrade schema from version -> version + 1
     fn upgrade_from(&self, conn: &Transaction<'_>, version: u32) -> Result<()>;
 
+    // Runs immediately after the connection is opened, before anything reads
+    // the database, for all types of connections. This is where a SQLCipher
+    // key is set. Return `Error::IncorrectKey` if the database can't be read
+    // with it.
+    fn unlock(&self, _conn: &Connection) -> Result<()> {
+        Ok(())
+    }
+
     // Runs immediately after creation for all types of connections. If writable,
     // will *not* be in the transaction created for the "only writable" functions above.
     fn prepare(&self, _conn: &Connection) -> Result<()> {
@@ -93,11 +112,25 @@ pub fn open_database_with_flags<CI: ConnectionInitializer, P: AsRef<Path>>(
     open_flags: OpenFlags,
     connection_initializer: &CI,
 ) -> Result<Connection> {
-    do_open_database_with_flags(&path, open_flags, connection_initializer).or_else(|e| {
-        // See if we can recover from the error and try a second time
-        try_handle_db_failure(&path, open_flags, connection_initializer, e)?;
-        do_open_database_with_flags(&path, open_flags, connection_initializer)
-    })
+    // In-memory databases start out empty every time, so there's nothing
+    // interesting to say about their health.
+    let record_health = !is_in_memory(path.as_ref());
+    let result =
+        do_open_database_with_flags(&path, open_flags, connection_initializer).or_else(|e| {
+            // See if we can recover from the error and try a second time
+            try_handle_db_failure(&path, open_flags, connection_initializer, record_health, e)?;
+            do_open_database_with_flags(&path, open_flags, connection_initializer)
+        });
+    if record_health {
+        // Each open counts once, however many attempts it took.
+        match &result {
+            Ok(_) => error_support::record_database_opened(CI::NAME),
+            // Not finding a database we weren't asked to create is expected.
+            Err(e) if is_missing_database(path.as_ref(), open_flags, e) => (),
+            Err(e) => error_support::record_database_open_failure(CI::NAME, &e.to_string()),
+        }
+    }
+    result
 }
 
 fn do_open_database_with_flags<CI: ConnectionInitializer, P: AsRef<Path>>(
@@ -108,6 +141,7 @@ fn do_open_database_with_flags<CI: ConnectionInitializer, P: AsRef<Path>>(
     // Try running the migration logic with an existing file
     log::debug!("{}: opening database", CI::NAME);
     let mut conn = Connection::open_with_flags(path, open_flags)?;
+    connection_initializer.unlock(&conn)?;
     log::debug!("{}: checking if initialization is necessary", CI::NAME);
     let run_init = should_init(&conn)?;
 
@@ -130,7 +164,14 @@ fn do_open_database_with_flags<CI: ConnectionInitializer, P: AsRef<Path>>(
                     CI::NAME,
                     current_version + 1
                 );
+                let start = Instant::now();
                 connection_initializer.upgrade_from(&tx, current_version)?;
+                error_support::record_database_migration(
+                    CI::NAME,
+                    current_version,
+                    current_version + 1,
+                    start.elapsed(),
+                );
                 current_version += 1;
             }
         }
@@ -169,11 +210,10 @@ fn try_handle_db_failure<CI: ConnectionInitializer, P: AsRef<Path>>(
     path: P,
     open_flags: OpenFlags,
     _connection_initializer: &CI,
+    record_health: bool,
     err: Error,
 ) -> Result<()> {
-    if !open_flags.contains(OpenFlags::SQLITE_OPEN_CREATE)
-        && matches!(err, Error::SqlError(rusqlite::Error::SqliteFailure(code, _)) if code.code == rusqlite::ErrorCode::CannotOpen)
-    {
+    if is_missing_database(path.as_ref(), open_flags, &err) {
         log::info!(
             "{}: database doesn't exist, but we weren't requested to create it",
             CI::NAME
@@ -200,6 +240,9 @@ fn try_handle_db_failure<CI: ConnectionInitializer, P: AsRef<Path>>(
             "{}: the database is fatally damaged; deleting and starting fresh",
             CI::NAME
         );
+        if record_health {
+            error_support::record_database_corruption(CI::NAME);
+        }
         // Note we explicitly decline to move the path to, say ".corrupt", as it's difficult to
         // identify any value there - actually getting our hands on the file from a mobile device
         // is tricky and it would just take up disk space forever.
@@ -212,6 +255,20 @@ fn try_handle_db_failure<CI: ConnectionInitializer, P: AsRef<Path>>(
     }
 }
 
+// Returns true if `err` is because the database doesn't exist, and we weren't
+// asked to create it. Failing to open a database which does exist is a real
+// failure, even if SQLite reports it the same way.
+fn is_missing_database(path: &Path, open_flags: OpenFlags, err: &Error) -> bool {
+    !open_flags.contains(OpenFlags::SQLITE_OPEN_CREATE)
+        && matches!(err, Error::SqlError(rusqlite::Error::SqliteFailure(code, _)) if code.code == rusqlite::ErrorCode::CannotOpen)
+        && !path.exists()
+}
+
+fn is_in_memory(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path == ":memory:" || (path.starts_with("file:") && path.contains("mode=memory"))
+}
+
 fn should_init(conn: &Connection) -> Result<bool> {
     Ok(conn.query_one::<u32>("SELECT COUNT(*) FROM sqlite_master")? == 0)
 }
@@ -532,5 +589,79 @@ mod test {
         let metadata = std::fs::metadata(path).unwrap();
         // just check the file is no longer what it was before.
         assert_ne!(metadata.len(), 7);
+        // Other tests use the same name, but none of them leave the count at 0.
+        let health = error_support::get_storage_health()
+            .into_iter()
+            .find(|health| health.name == "test db")
+            .unwrap();
+        assert!(health.corruption_events > 0);
+        assert!(health.successful_opens > 0);
+    }
+
+    // Uses its own name, so that other tests running at the same time don't
+    // change its health.
+    struct HealthTestInitializer;
+
+    impl ConnectionInitializer for HealthTestInitializer {
+        const NAME: &'static str = "health test db";
+        const END_VERSION: u32 = 1;
+
+        fn init(&self, conn: &Transaction<'_>) -> Result<()> {
+            conn.execute_batch("CREATE TABLE my_table(col);")?;
+            Ok(())
+        }
+
+        fn upgrade_from(&self, _conn: &Transaction<'_>, version: u32) -> Result<()> {
+            panic!("Unexpected version: {}", version);
+        }
+    }
+
+    #[test]
+    fn test_open_records_health() {
+        let health = || {
+            error_support::get_storage_health()
+                .into_iter()
+                .find(|health| health.name == HealthTestInitializer::NAME)
+        };
+        let tempdir = tempfile::tempdir().unwrap();
+
+        // In-memory databases aren't recorded.
+        open_memory_database(&HealthTestInitializer).unwrap();
+        // Nor is a missing database we weren't asked to create.
+        open_database_with_flags(
+            tempdir.path().join("missing.db"),
+            OpenFlags::SQLITE_OPEN_READ_WRITE,
+            &HealthTestInitializer,
+        )
+        .unwrap_err();
+        assert_eq!(health(), None);
+
+        // A database which can't be read is a failure, which is only recorded
+        // once.
+        let not_a_database = tempdir.path().join("not-a-database.db");
+        std::fs::write(&not_a_database, b"not sql").unwrap();
+        open_database_with_flags(
+            &not_a_database,
+            OpenFlags::SQLITE_OPEN_READ_ONLY,
+            &HealthTestInitializer,
+        )
+        .unwrap_err();
+        assert_eq!(health().unwrap().open_failures, 1);
+
+        // So is one which exists, but can't be opened.
+        open_database_with_flags(
+            tempdir.path(),
+            OpenFlags::SQLITE_OPEN_READ_WRITE,
+            &HealthTestInitializer,
+        )
+        .unwrap_err();
+        assert_eq!(health().unwrap().open_failures, 2);
+
+        // A corrupt database is replaced, and then opens successfully.
+        open_database(&not_a_database, &HealthTestInitializer).unwrap();
+        let health = health().unwrap();
+        assert_eq!(health.open_failures, 2);
+        assert_eq!(health.corruption_events, 1);
+        assert_eq!(health.successful_opens, 1);
     }
 }
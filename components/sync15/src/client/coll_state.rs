@@ -267,6 +267,10 @@ mod tests {
         fn wipe(&self) -> Result<()> {
             unreachable!("these tests shouldn't call these");
         }
+
+        fn wipe_local(&self) -> Result<()> {
+            unreachable!("these tests shouldn't call these");
+        }
     }
 
     #[test]
@@ -19,33 +19,59 @@ pub fn encrypt_outgoing(o: OutgoingChangeset, key: &KeyBundle) -> Result<Vec<Enc
         .collect()
 }
 
+/// How many records we ask for at a time when an engine wants all the
+/// records, so that we only ever hold one page of the server's response in
+/// memory.
+const FETCH_PAGE_SIZE: usize = 1000;
+
 pub fn fetch_incoming(
     client: &Sync15StorageClient,
     state: &mut CollState,
     collection_request: &CollectionRequest,
 ) -> Result<IncomingChangeset> {
     let collection = collection_request.collection.clone();
-    let (records, timestamp) = match client.get_encrypted_records(collection_request)? {
-        Sync15ClientResponse::Success {
-            record,
-            last_modified,
-            ..
-        } => (record, last_modified),
-        other => return Err(other.create_storage_error()),
+    // Engines which set a limit only want that many records, so we only page
+    // through the collection for requests without one.
+    let paged = collection_request.limit == 0;
+    let mut request = collection_request.clone();
+    if paged {
+        request.limit = FETCH_PAGE_SIZE;
+    }
+    let mut changes = Vec::new();
+    let mut xius = None;
+    let timestamp = loop {
+        let response = client.stream_encrypted_records(&request, xius, |record| {
+            // if we see a HMAC error, we've made an explicit decision to
+            // NOT handle it here, but restart the global state machine.
+            // That should cause us to re-read crypto/keys and things should
+            // work (although if for some reason crypto/keys was updated but
+            // not all storage was wiped we are probably screwed.)
+            let decrypted = record.decrypt(&state.key)?;
+            changes.push(decrypted.into_timestamped_payload());
+            Ok(())
+        })?;
+        let (page, last_modified) = match response {
+            Sync15ClientResponse::Success {
+                record,
+                last_modified,
+                ..
+            } => (record, last_modified),
+            other => return Err(other.create_storage_error()),
+        };
+        match page.next_offset {
+            // If the collection changes while we're paging through it, the
+            // server fails the next request, and we'll try again next sync.
+            Some(offset) if paged => {
+                request.offset = Some(offset);
+                xius = Some(last_modified);
+            }
+            _ => break last_modified,
+        }
     };
     // xxx - duplication below of `timestamp` smells wrong
     state.last_modified = timestamp;
     let mut result = IncomingChangeset::new(collection, timestamp);
-    result.changes.reserve(records.len());
-    for record in records {
-        // if we see a HMAC error, we've made an explicit decision to
-        // NOT handle it here, but restart the global state machine.
-        // That should cause us to re-read crypto/keys and things should
-        // work (although if for some reason crypto/keys was updated but
-        // not all storage was wiped we are probably screwed.)
-        let decrypted = record.decrypt(&state.key)?;
-        result.changes.push(decrypted.into_timestamped_payload());
-    }
+    result.changes = changes;
     Ok(result)
 }
 
@@ -32,7 +32,8 @@ pub(crate) use request::InfoConfiguration;
 pub(crate) use state::GlobalState;
 pub use status::{ServiceStatus, SyncResult};
 pub use storage_client::{
-    SetupStorageClient, Sync15ClientResponse, Sync15StorageClient, Sync15StorageClientInit,
+    CollectionPage, SetupStorageClient, Sync15ClientResponse, Sync15StorageClient,
+    Sync15StorageClientInit,
 };
 pub use sync_multiple::{
     sync_multiple, sync_multiple_with_command_processor, MemoryCachedState, SyncRequestInfo,
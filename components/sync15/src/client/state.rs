@@ -36,6 +36,19 @@ const DEFAULT_ENGINES: &[(&str, usize)] = &[
     ("tabs", 1),
 ];
 
+/// Engines which aren't in a fresh `meta/global` record, and are only added
+/// to it when the user enables them with `engines_to_state_change`.
+const OPT_IN_ENGINES: &[(&str, usize)] = &[("historymetadata", 1)];
+
+fn default_and_enabled_engines(
+    user_changes: Option<&HashMap<String, bool>>,
+) -> impl Iterator<Item = &'static (&'static str, usize)> + '_ {
+    let enabled = OPT_IN_ENGINES
+        .iter()
+        .filter(move |(name, _)| user_changes.and_then(|c| c.get(*name)) == Some(&true));
+    DEFAULT_ENGINES.iter().chain(enabled)
+}
+
 // Declined engines to include in a fresh `meta/global` record.
 const DEFAULT_DECLINED: &[&str] = &[];
 
@@ -171,12 +184,16 @@ pub struct GlobalState {
     pub keys_timestamp: ServerTimestamp,
 }
 
-/// Creates a fresh `meta/global` record, using the default engine selections,
-/// and declined engines from our PersistedGlobalState.
-fn new_global(pgs: &PersistedGlobalState) -> MetaGlobalRecord {
+/// Creates a fresh `meta/global` record, using the default engine selections
+/// and any opt-in engines in `user_changes`, and declined engines from our
+/// PersistedGlobalState.
+fn new_global(
+    pgs: &PersistedGlobalState,
+    user_changes: Option<&HashMap<String, bool>>,
+) -> MetaGlobalRecord {
     let sync_id = Guid::random();
     let mut engines: HashMap<String, _> = HashMap::new();
-    for (name, version) in DEFAULT_ENGINES.iter() {
+    for (name, version) in default_and_enabled_engines(user_changes) {
         let sync_id = Guid::random();
         engines.insert(
             (*name).to_string(),
@@ -202,9 +219,12 @@ fn new_global(pgs: &PersistedGlobalState) -> MetaGlobalRecord {
     }
 }
 
-fn fixup_meta_global(global: &mut MetaGlobalRecord) -> bool {
+fn fixup_meta_global(
+    global: &mut MetaGlobalRecord,
+    user_changes: Option<&HashMap<String, bool>>,
+) -> bool {
     let mut changed_any = false;
-    for &(name, version) in DEFAULT_ENGINES.iter() {
+    for &(name, version) in default_and_enabled_engines(user_changes) {
         let had_engine = global.engines.contains_key(name);
         let should_have_engine = !global.declined.iter().any(|c| c == name);
         if had_engine != should_have_engine {
@@ -381,7 +401,7 @@ impl<'a> SetupStateMachine<'a> {
                                 false
                             };
                             // If there are missing syncIds, we need to fix those as well
-                            let fixed_ids = if fixup_meta_global(&mut global) {
+                            let fixed_ids = if fixup_meta_global(&mut global, self.engine_updates) {
                                 log::info!(
                                     "Uploading corrected meta/global with timestamp {:?}",
                                     global_timestamp,
@@ -503,7 +523,7 @@ impl<'a> SetupStateMachine<'a> {
 
                 self.changes_needed = Some(computed.changes_needed);
 
-                let new_global = new_global(self.pgs);
+                let new_global = new_global(self.pgs, self.engine_updates);
 
                 self.client
                     .put_meta_global(ServerTimestamp::default(), &new_global)?;
@@ -978,6 +998,26 @@ mod tests {
     fn string_map<T: Clone>(s: &[(&str, T)]) -> HashMap<String, T> {
         s.iter().map(|v| (v.0.to_string(), v.1.clone())).collect()
     }
+    #[test]
+    fn test_opt_in_engines() {
+        let pgs = PersistedGlobalState::V2 { declined: None };
+        let mut global = new_global(&pgs, None);
+        assert!(!global.engines.contains_key("historymetadata"));
+        assert!(!fixup_meta_global(&mut global, None));
+
+        // Enabling an opt-in engine adds it.
+        let changes = string_map(&[("historymetadata", true)]);
+        assert!(fixup_meta_global(&mut global, Some(&changes)));
+        assert!(global.engines.contains_key("historymetadata"));
+        assert!(new_global(&pgs, Some(&changes))
+            .engines
+            .contains_key("historymetadata"));
+
+        // Once it's there, we leave it alone.
+        assert!(!fixup_meta_global(&mut global, None));
+        assert!(global.engines.contains_key("historymetadata"));
+    }
+
     #[test]
     fn test_engine_states() {
         assert_eq!(
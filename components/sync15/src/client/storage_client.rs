@@ -11,7 +11,9 @@ use crate::error::{self, Error, ErrorResponse};
 use crate::record_types::MetaGlobalRecord;
 use crate::ServerTimestamp;
 use crate::{BsoRecord, EncryptedBso};
+use serde::de::{self as serde_de, Deserializer, SeqAccess, Visitor};
 use serde_json::Value;
+use std::fmt;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicU32, Ordering};
 use url::Url;
@@ -48,6 +50,19 @@ impl<T> Sync15ClientResponse<T> {
     pub fn from_response(resp: Response, backoff_listener: &BackoffListener) -> error::Result<Self>
     where
         for<'a> T: serde::de::Deserialize<'a>,
+    {
+        Self::from_response_with(resp, backoff_listener, |resp| Ok(resp.json()?))
+    }
+
+    /// Like `from_response`, but uses `decode` to get the record out of a
+    /// successful response.
+    fn from_response_with<F>(
+        resp: Response,
+        backoff_listener: &BackoffListener,
+        decode: F,
+    ) -> error::Result<Self>
+    where
+        F: FnOnce(&Response) -> error::Result<T>,
     {
         let route: String = resp.url.path().into();
         // Android seems to respect retry_after even on success requests, so we
@@ -71,7 +86,7 @@ impl<T> Sync15ClientResponse<T> {
         }
 
         Ok(if resp.is_success() {
-            let record: T = resp.json()?;
+            let record = decode(&resp)?;
             let last_modified = resp
                 .headers
                 .get(header_names::X_LAST_MODIFIED)
@@ -119,6 +134,17 @@ impl<T> Sync15ClientResponse<T> {
     }
 }
 
+/// A page of records fetched with [`Sync15StorageClient::stream_encrypted_records`].
+/// The records themselves are handed to a callback as they're decoded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CollectionPage {
+    /// How many records were in the page.
+    pub count: usize,
+    /// The offset to fetch the next page with, if the request had a limit and
+    /// there are more records.
+    pub next_offset: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Sync15StorageClientInit {
     pub key_id: String,
@@ -285,6 +311,44 @@ impl Sync15StorageClient {
         self.collection_request(Method::Get, collection_request)
     }
 
+    /// Like `get_encrypted_records`, but hands each record to `on_record` as
+    /// soon as it's decoded, instead of decoding all of them into a `Vec`
+    /// first, which keeps peak memory down when fetching a large collection.
+    ///
+    /// If `xius` is given, the server fails the request if the collection
+    /// changed since then; that's how we make sure the pages of a collection
+    /// we fetch are consistent.
+    pub fn stream_encrypted_records<F>(
+        &self,
+        collection_request: &CollectionRequest,
+        xius: Option<ServerTimestamp>,
+        mut on_record: F,
+    ) -> error::Result<Sync15ClientResponse<CollectionPage>>
+    where
+        F: FnMut(EncryptedBso) -> error::Result<()>,
+    {
+        let url = build_collection_request_url(
+            Url::parse(&self.tsc.api_endpoint()?)?,
+            collection_request,
+        )?;
+        let mut req = self.build_request(Method::Get, url)?;
+        if let Some(xius) = xius {
+            req = req.header(header_names::X_IF_UNMODIFIED_SINCE, format!("{}", xius))?;
+        }
+        log::trace!("request: GET {} ({:?})", req.url.path(), req.url.query());
+        let resp = req.send()?;
+        Sync15ClientResponse::from_response_with(resp, &self.backoff, |resp| {
+            let count = decode_records(&resp.body, &mut on_record)?;
+            Ok(CollectionPage {
+                count,
+                next_offset: resp
+                    .headers
+                    .get(header_names::X_WEAVE_NEXT_OFFSET)
+                    .map(ToOwned::to_owned),
+            })
+        })
+    }
+
     #[inline]
     fn authorized(&self, req: Request) -> error::Result<Request> {
         let hawk_header_value = self.tsc.authorization(&req)?;
@@ -440,6 +504,56 @@ impl<'a> BatchPoster for PostWrapper<'a> {
     }
 }
 
+/// Decodes a JSON array of records from `body`, handing each one to
+/// `on_record` as it goes. Returns how many records there were.
+fn decode_records<F>(body: &[u8], on_record: &mut F) -> error::Result<usize>
+where
+    F: FnMut(EncryptedBso) -> error::Result<()>,
+{
+    struct RecordsVisitor<'a, F> {
+        on_record: &'a mut F,
+        // `on_record` can fail with errors that serde can't carry, so we stash
+        // them here and return them once serde gives up.
+        error: &'a mut Option<Error>,
+    }
+
+    impl<'de, 'a, F> Visitor<'de> for RecordsVisitor<'a, F>
+    where
+        F: FnMut(EncryptedBso) -> error::Result<()>,
+    {
+        type Value = usize;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("an array of records")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<usize, A::Error> {
+            let mut count = 0;
+            while let Some(record) = seq.next_element::<EncryptedBso>()? {
+                if let Err(e) = (self.on_record)(record) {
+                    *self.error = Some(e);
+                    return Err(serde_de::Error::custom("failed to handle record"));
+                }
+                count += 1;
+            }
+            Ok(count)
+        }
+    }
+
+    let mut error = None;
+    let mut de = serde_json::Deserializer::from_slice(body);
+    let result = de.deserialize_seq(RecordsVisitor {
+        on_record,
+        error: &mut error,
+    });
+    if let Some(e) = error {
+        return Err(e);
+    }
+    let count = result?;
+    de.end()?;
+    Ok(count)
+}
+
 fn build_collection_request_url(mut base_url: Url, r: &CollectionRequest) -> error::Result<Url> {
     base_url
         .path_segments_mut()
@@ -479,6 +593,9 @@ fn build_collection_request_url(mut base_url: Url, r: &CollectionRequest) -> err
     if let Some(o) = r.order {
         pairs.append_pair("sort", o.as_str());
     }
+    if let Some(offset) = &r.offset {
+        pairs.append_pair("offset", offset);
+    }
     pairs.finish();
     drop(pairs);
 
@@ -570,5 +687,47 @@ mod test {
         .unwrap();
         assert_eq!(complex.as_str(),
             "https://example.com/sync/storage/specific?full=1&limit=10&older=9876.54&newer=1234.56&sort=oldest");
+
+        let paged = build_collection_request_url(
+            base,
+            &CollectionRequest::new("paged")
+                .full()
+                .limit(1000)
+                .offset(Some("1000".into())),
+        )
+        .unwrap();
+        assert_eq!(
+            paged.as_str(),
+            "https://example.com/sync/storage/paged?full=1&limit=1000&offset=1000"
+        );
+    }
+
+    #[test]
+    fn test_decode_records() {
+        let body = br#"[
+            {"id": "record1", "modified": 1234.5, "payload": "{\"IV\": \"\", \"hmac\": \"\", \"ciphertext\": \"\"}"},
+            {"id": "record2", "modified": 1234.5, "payload": "{\"IV\": \"\", \"hmac\": \"\", \"ciphertext\": \"\"}"}
+        ]"#;
+        let mut ids = Vec::new();
+        let count = decode_records(body, &mut |record: EncryptedBso| {
+            ids.push(record.id.to_string());
+            Ok(())
+        })
+        .expect("should decode");
+        assert_eq!(count, 2);
+        assert_eq!(ids, vec!["record1", "record2"]);
+
+        // Errors from the callback are returned as they are, and stop decoding.
+        let mut seen = 0;
+        let err = decode_records(body, &mut |_| {
+            seen += 1;
+            Err(Error::RecordTooLargeError)
+        })
+        .expect_err("should fail");
+        assert!(matches!(err, Error::RecordTooLargeError));
+        assert_eq!(seen, 1);
+
+        assert!(decode_records(b"{}", &mut |_| Ok(())).is_err());
+        assert!(decode_records(b"[] []", &mut |_| Ok(())).is_err());
     }
 }
@@ -73,6 +73,7 @@ pub fn synchronize_with_clients_engine(
     };
 
     let new_timestamp = incoming.last().expect("must have >= 1").timestamp;
+    engine.set_max_record_payload_bytes(coll_state.config.max_record_payload_bytes);
     let mut outgoing = engine.apply_incoming(incoming, telem_engine)?;
 
     interruptee.err_if_interrupted()?;
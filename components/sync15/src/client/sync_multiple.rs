@@ -150,6 +150,7 @@ pub fn sync_multiple_with_command_processor(
         mem_cached_state,
         saw_auth_error: false,
         ignore_soft_backoff: req_info.is_user_action,
+        reason: req_info.reason,
     };
     match driver.sync() {
         Ok(()) => {
@@ -182,6 +183,8 @@ pub fn sync_multiple_with_command_processor(
 pub struct SyncRequestInfo<'a> {
     pub engines_to_state_change: Option<&'a HashMap<String, bool>>,
     pub is_user_action: bool,
+    /// Why the sync is happening, which we record in the telemetry.
+    pub reason: Option<&'a str>,
 }
 
 // The sync multiple driver
@@ -198,6 +201,7 @@ struct SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
     mem_cached_state: &'mcs mut MemoryCachedState,
     ignore_soft_backoff: bool,
     saw_auth_error: bool,
+    reason: Option<&'info str>,
 }
 
 impl<'info, 'res, 'pgs, 'mcs> SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
@@ -293,6 +297,9 @@ impl<'info, 'res, 'pgs, 'mcs> SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
         clients: Option<&clients_engine::Engine<'_>>,
     ) -> telemetry::SyncTelemetry {
         let mut telem_sync = telemetry::SyncTelemetry::new();
+        if let Some(reason) = self.reason {
+            telem_sync.reason(reason.to_string());
+        }
         for engine in self.engines {
             let name = engine.collection_name();
             if self
@@ -14,6 +14,9 @@ pub struct CollectionRequest {
     pub order: Option<RequestOrder>,
     pub commit: bool,
     pub batch: Option<String>,
+    /// The `X-Weave-Next-Offset` from the previous page, when fetching a
+    /// collection in pages of `limit` records.
+    pub offset: Option<String>,
 }
 
 impl CollectionRequest {
@@ -32,6 +35,7 @@ impl CollectionRequest {
             order: None,
             commit: false,
             batch: None,
+            offset: None,
         }
     }
 
@@ -81,6 +85,12 @@ impl CollectionRequest {
         self
     }
 
+    #[inline]
+    pub fn offset(mut self, offset: Option<String>) -> CollectionRequest {
+        self.offset = offset;
+        self
+    }
+
     #[inline]
     pub fn commit(mut self, v: bool) -> CollectionRequest {
         self.commit = v;
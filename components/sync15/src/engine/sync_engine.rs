@@ -41,6 +41,7 @@ pub enum SyncEngineId {
     Addresses,
     CreditCards,
     History,
+    HistoryMetadata,
 }
 
 impl SyncEngineId {
@@ -55,6 +56,7 @@ impl SyncEngineId {
             Self::Addresses,
             Self::CreditCards,
             Self::History,
+            Self::HistoryMetadata,
         ]
         .into_iter()
     }
@@ -64,6 +66,7 @@ impl SyncEngineId {
         match self {
             Self::Passwords => "passwords",
             Self::History => "history",
+            Self::HistoryMetadata => "historymetadata",
             Self::Bookmarks => "bookmarks",
             Self::Tabs => "tabs",
             Self::Addresses => "addresses",
@@ -85,6 +88,7 @@ impl TryFrom<&str> for SyncEngineId {
         match value {
             "passwords" => Ok(Self::Passwords),
             "history" => Ok(Self::History),
+            "historymetadata" => Ok(Self::HistoryMetadata),
             "bookmarks" => Ok(Self::Bookmarks),
             "tabs" => Ok(Self::Tabs),
             "addresses" => Ok(Self::Addresses),
@@ -149,6 +153,11 @@ pub trait SyncEngine {
         unimplemented!("This engine does not support local encryption");
     }
 
+    /// Tells the engine the largest record payload the server accepts, from
+    /// its `info/configuration`. This is called before `apply_incoming`, so
+    /// engines can leave out outgoing records which would fail the upload.
+    fn set_max_record_payload_bytes(&self, _max_bytes: usize) {}
+
     /// `inbound` is a vector to support the case where
     /// `get_collection_requests` returned multiple requests. The changesets are
     /// in the same order as the requests were -- e.g. if `vec![req_a, req_b]`
@@ -200,6 +209,11 @@ pub trait SyncEngine {
     fn reset(&self, assoc: &EngineSyncAssociation) -> Result<()>;
 
     fn wipe(&self) -> Result<()>;
+
+    /// Deletes all local data, without recording the deletions to upload, so
+    /// the data on the server and on other devices is left alone. Unlike
+    /// `wipe`, this doesn't write tombstones.
+    fn wipe_local(&self) -> Result<()>;
 }
 
 #[cfg(test)]
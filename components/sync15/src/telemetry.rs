@@ -585,6 +585,9 @@ pub struct SyncTelemetry {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "failureReason")]
     failure: Option<SyncFailure>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    why: Option<String>,
 }
 
 impl SyncTelemetry {
@@ -592,6 +595,12 @@ impl SyncTelemetry {
         Default::default()
     }
 
+    /// Records why the sync happened, for example because the user asked
+    /// for it, or because it was scheduled.
+    pub fn reason(&mut self, why: String) {
+        self.why = Some(why);
+    }
+
     pub fn engine(&mut self, mut e: Engine) {
         e.finished();
         self.engines.push(e);
@@ -664,11 +673,13 @@ mod sync_tests {
         s.engine(e1);
         s.engine(e2);
         s.failure(SyncFailure::Http { code: 500 });
+        s.reason("user".into());
         s.finished();
         assert_json(
             &s,
             serde_json::json!({
                 "when": 0.0,
+                "why": "user",
                 "engines": [{
                     "name": "test_engine",
                     "when": 0.0,
@@ -822,3 +833,272 @@ impl<'a> From<&'a Error> for SyncFailure {
         }
     }
 }
+
+/// Plain versions of the ping types, with public fields, for components which
+/// return the ping over an FFI instead of as JSON. They mirror the JSON
+/// payload, so fields which are left out of the JSON when they're empty are
+/// zero or empty here.
+pub mod ffi {
+    use std::collections::HashMap;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct SyncTelemetryPing {
+        pub version: u32,
+        pub uid: Option<String>,
+        pub events: Vec<EventInfo>,
+        pub syncs: Vec<SyncInfo>,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct SyncInfo {
+        /// When the sync started, in seconds since the epoch.
+        pub at: f64,
+        /// How long the sync took, in milliseconds.
+        pub took: u64,
+        pub engines: Vec<EngineInfo>,
+        pub failure_reason: Option<FailureReason>,
+        pub why: Option<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct EngineInfo {
+        pub name: String,
+        pub at: f64,
+        pub took: u64,
+        pub incoming: Option<IncomingInfo>,
+        pub outgoing: Vec<OutgoingInfo>,
+        pub failure_reason: Option<FailureReason>,
+        pub validation: Option<ValidationInfo>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct IncomingInfo {
+        pub applied: u32,
+        pub failed: u32,
+        pub new_failed: u32,
+        pub reconciled: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct OutgoingInfo {
+        pub sent: u32,
+        pub failed: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ValidationInfo {
+        pub version: u32,
+        pub problems: Vec<ProblemInfo>,
+        pub failure_reason: Option<FailureReason>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ProblemInfo {
+        pub name: String,
+        pub count: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum FailureReason {
+        Shutdown,
+        Other { error: String },
+        Unexpected { error: String },
+        Auth { from: String },
+        Http { code: u16 },
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct EventInfo {
+        pub obj: String,
+        pub method: String,
+        pub value: Option<String>,
+        pub extra: HashMap<String, String>,
+    }
+
+    // `Stopwatch`es are finished before they're added to a ping, so we only
+    // see an unfinished one if the ping is converted too early.
+    fn when_took(sw: &super::Stopwatch) -> (f64, u64) {
+        match sw {
+            super::Stopwatch::Finished(wt) => (wt.when, wt.took),
+            super::Stopwatch::Started(..) => (0.0, 0),
+        }
+    }
+
+    impl From<&super::SyncTelemetryPing> for SyncTelemetryPing {
+        fn from(ping: &super::SyncTelemetryPing) -> Self {
+            Self {
+                version: ping.version,
+                uid: ping.uid.clone(),
+                events: ping.events.iter().map(Into::into).collect(),
+                syncs: ping.syncs.iter().map(Into::into).collect(),
+            }
+        }
+    }
+
+    impl From<&super::SyncTelemetry> for SyncInfo {
+        fn from(sync: &super::SyncTelemetry) -> Self {
+            let (at, took) = when_took(&sync.when_took);
+            Self {
+                at,
+                took,
+                engines: sync.engines.iter().map(Into::into).collect(),
+                failure_reason: sync.failure.as_ref().map(Into::into),
+                why: sync.why.clone(),
+            }
+        }
+    }
+
+    impl From<&super::Engine> for EngineInfo {
+        fn from(engine: &super::Engine) -> Self {
+            let (at, took) = when_took(&engine.when_took);
+            Self {
+                name: engine.name.clone(),
+                at,
+                took,
+                incoming: engine
+                    .incoming
+                    .as_ref()
+                    .filter(|_| !super::EngineIncoming::is_empty(&engine.incoming))
+                    .map(|inc| IncomingInfo {
+                        applied: inc.applied,
+                        failed: inc.failed,
+                        new_failed: inc.new_failed,
+                        reconciled: inc.reconciled,
+                    }),
+                outgoing: engine
+                    .outgoing
+                    .iter()
+                    .map(|out| OutgoingInfo {
+                        sent: out.sent as u32,
+                        failed: out.failed as u32,
+                    })
+                    .collect(),
+                failure_reason: engine.failure.as_ref().map(Into::into),
+                validation: engine.validation.as_ref().map(|v| ValidationInfo {
+                    version: v.version,
+                    problems: v
+                        .problems
+                        .iter()
+                        .map(|p| ProblemInfo {
+                            name: p.name.to_string(),
+                            count: p.count as u32,
+                        })
+                        .collect(),
+                    failure_reason: v.failure.as_ref().map(Into::into),
+                }),
+            }
+        }
+    }
+
+    impl From<&super::SyncFailure> for FailureReason {
+        fn from(failure: &super::SyncFailure) -> Self {
+            match failure {
+                super::SyncFailure::Shutdown => FailureReason::Shutdown,
+                super::SyncFailure::Other { error } => FailureReason::Other {
+                    error: error.clone(),
+                },
+                super::SyncFailure::Unexpected { error } => FailureReason::Unexpected {
+                    error: error.clone(),
+                },
+                super::SyncFailure::Auth { from } => FailureReason::Auth {
+                    from: from.to_string(),
+                },
+                super::SyncFailure::Http { code } => FailureReason::Http { code: *code },
+            }
+        }
+    }
+
+    impl From<&super::Event> for EventInfo {
+        fn from(event: &super::Event) -> Self {
+            Self {
+                obj: event.object.to_string(),
+                method: event.method.to_string(),
+                value: event.value.map(str::to_string),
+                extra: event
+                    .extra
+                    .iter()
+                    .flatten()
+                    .map(|(k, v)| (k.to_string(), v.clone()))
+                    .collect(),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::{
+            Engine, EngineIncoming, EngineOutgoing, Event, SyncFailure, SyncTelemetry,
+        };
+        use super::*;
+
+        #[test]
+        fn test_from_ping() {
+            let mut inc = EngineIncoming::new();
+            inc.applied(2);
+            let mut out = EngineOutgoing::new();
+            out.sent(3);
+            let mut engine = Engine::new("bookmarks");
+            engine.incoming(inc);
+            engine.outgoing(out);
+            engine.failure(SyncFailure::Auth { from: "storage" });
+            // An empty incoming record isn't in the JSON, so it isn't here either.
+            let mut empty = Engine::new("history");
+            empty.incoming(EngineIncoming::new());
+            let mut s = SyncTelemetry::new();
+            s.engine(engine);
+            s.engine(empty);
+            s.reason("user".into());
+            let mut p = super::super::SyncTelemetryPing::new();
+            p.uid("user-id".into());
+            p.sync(s);
+            p.event(Event::new("foo", "bar").extra("k", "v".into()));
+
+            assert_eq!(
+                SyncTelemetryPing::from(&p),
+                SyncTelemetryPing {
+                    version: 1,
+                    uid: Some("user-id".into()),
+                    events: vec![EventInfo {
+                        obj: "foo".into(),
+                        method: "bar".into(),
+                        value: None,
+                        extra: [("k".to_string(), "v".to_string())].into_iter().collect(),
+                    }],
+                    syncs: vec![SyncInfo {
+                        at: 0.0,
+                        took: 0,
+                        engines: vec![
+                            EngineInfo {
+                                name: "bookmarks".into(),
+                                at: 0.0,
+                                took: 0,
+                                incoming: Some(IncomingInfo {
+                                    applied: 2,
+                                    failed: 0,
+                                    new_failed: 0,
+                                    reconciled: 0,
+                                }),
+                                outgoing: vec![OutgoingInfo { sent: 3, failed: 0 }],
+                                failure_reason: Some(FailureReason::Auth {
+                                    from: "storage".into()
+                                }),
+                                validation: None,
+                            },
+                            EngineInfo {
+                                name: "history".into(),
+                                at: 0.0,
+                                took: 0,
+                                incoming: None,
+                                outgoing: vec![],
+                                failure_reason: None,
+                                validation: None,
+                            },
+                        ],
+                        failure_reason: None,
+                        why: Some("user".into()),
+                    }],
+                }
+            );
+        }
+    }
+}
@@ -8,12 +8,13 @@
 pub mod error;
 pub mod manager;
 mod types;
+mod wipe;
 
 pub use error::{Result, SyncManagerError};
 use sync15::DeviceType;
 pub use types::*;
 
-use manager::SyncManager;
+use manager::{SyncManager, WipeProgressListener};
 use parking_lot::Mutex;
 
 lazy_static::lazy_static! {
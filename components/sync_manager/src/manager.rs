@@ -3,12 +3,16 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::error::*;
-use crate::types::{ServiceStatus, SyncEngineSelection, SyncParams, SyncReason, SyncResult};
+use crate::types::{
+    LocalDataStores, ServiceStatus, SyncEngineSelection, SyncParams, SyncReason, SyncResult,
+};
+use crate::wipe;
 use crate::{reset, reset_all, wipe};
 use error_support::breadcrumb;
 use parking_lot::Mutex;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
+use std::path::Path;
 use std::time::SystemTime;
 use sync15::client::{
     sync_multiple_with_command_processor, MemoryCachedState, Sync15StorageClientInit,
@@ -17,6 +21,13 @@ use sync15::client::{
 use sync15::clients_engine::{Command, CommandProcessor, CommandStatus, Settings};
 use sync15::engine::{EngineSyncAssociation, SyncEngine, SyncEngineId};
 
+/// Told about the progress of [SyncManager::wipe_local_data].
+pub trait WipeProgressListener: Send + Sync {
+    /// Called after each store has been wiped (or failed to wipe), with the
+    /// store name, how many stores have been processed and the total.
+    fn on_progress(&self, store: String, completed: u32, total: u32);
+}
+
 #[derive(Default)]
 pub struct SyncManager {
     mem_cached_state: Mutex<Option<MemoryCachedState>>,
@@ -34,6 +45,7 @@ impl SyncManager {
     fn get_engine(engine_id: &SyncEngineId) -> Option<Box<dyn SyncEngine>> {
         match engine_id {
             SyncEngineId::History => places::get_registered_sync_engine(engine_id),
+            SyncEngineId::HistoryMetadata => places::get_registered_sync_engine(engine_id),
             SyncEngineId::Bookmarks => places::get_registered_sync_engine(engine_id),
             SyncEngineId::Addresses => autofill::get_registered_sync_engine(engine_id),
             SyncEngineId::CreditCards => autofill::get_registered_sync_engine(engine_id),
@@ -63,6 +75,38 @@ impl SyncManager {
         Ok(())
     }
 
+    /// Deletes all local data in the given stores, for "delete my data on
+    /// this device" flows. We open each store ourselves, so this works whether
+    /// or not the engines have been registered, or sync set up at all. Stores
+    /// are wiped one at a time, and each store's data is deleted in a single
+    /// transaction, so a store is never left half-wiped.
+    ///
+    /// The stores which still need wiping are recorded in the file at
+    /// `state_path`, which is removed once everything has been wiped. If this
+    /// is interrupted (eg, the app is killed), apps should check
+    /// [SyncManager::is_wipe_local_data_pending] on the next startup and call
+    /// this again, which picks up where the last attempt left off.
+    ///
+    /// Engines are also disconnected from sync, and wiped without recording
+    /// tombstones, so the data on the server and on other devices is left
+    /// alone. If wiping a store fails, we still try to wipe the rest, then
+    /// return the first error.
+    pub fn wipe_local_data(
+        &self,
+        stores: LocalDataStores,
+        state_path: String,
+        listener: Box<dyn WipeProgressListener>,
+    ) -> Result<()> {
+        breadcrumb!("SyncManager wipe_local_data()");
+        wipe::wipe_local_data(&stores, Path::new(&state_path), &*listener)
+    }
+
+    /// Whether a previous call to [SyncManager::wipe_local_data] with this
+    /// `state_path` didn't finish.
+    pub fn is_wipe_local_data_pending(&self, state_path: String) -> bool {
+        wipe::is_wipe_pending(Path::new(&state_path))
+    }
+
     /// Disconnect engines from sync, deleting/resetting the sync-related data
     pub fn disconnect(&self) {
         breadcrumb!("SyncManager disconnect()");
@@ -160,6 +204,7 @@ impl SyncManager {
             Some(SyncRequestInfo {
                 engines_to_state_change: engines_to_change,
                 is_user_action: matches!(params.reason, SyncReason::User),
+                reason: Some(params.reason.telemetry_name()),
             }),
         );
         *state = Some(mem_cached_state);
@@ -319,6 +364,22 @@ impl CommandProcessor for SyncClient {
 #[cfg(test)]
 mod test {
     use super::*;
+    use places::storage::bookmarks::{
+        insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+    };
+    use places::{ConnectionType, PlacesApi, VisitObservation};
+    use sql_support::ConnExt;
+    use std::sync::Arc;
+    use url::Url;
+
+    #[derive(Clone, Default)]
+    struct RecordingListener(Arc<Mutex<Vec<(String, u32, u32)>>>);
+
+    impl WipeProgressListener for RecordingListener {
+        fn on_progress(&self, store: String, completed: u32, total: u32) {
+            self.0.lock().push((store, completed, total));
+        }
+    }
 
     #[test]
     fn test_engine_id_sanity() {
@@ -326,4 +387,153 @@ mod test {
             assert_eq!(engine_id, SyncEngineId::try_from(engine_id.name()).unwrap());
         }
     }
+
+    #[test]
+    fn test_wipe_local_data_leaves_nothing_to_upload() {
+        // Nothing is registered with the sync manager, as if the user never
+        // set up sync.
+        let dir = tempfile::tempdir().unwrap();
+        let places_path = dir.path().join("places.sqlite");
+        let logins_path = dir.path().join("logins.sqlite");
+        let state_path = dir.path().join("wipe-state.json");
+        let places_api = PlacesApi::new(&places_path).unwrap();
+        let logins = logins::LoginStore::new(&logins_path).unwrap();
+
+        // Data which has already been synced. `wipe()` would replace all of
+        // it with tombstones, which the next sync would upload.
+        let conn = places_api
+            .open_connection(ConnectionType::ReadWrite)
+            .unwrap();
+        let url = Url::parse("https://example.com/").unwrap();
+        places::apply_observation(&conn, VisitObservation::new(url.clone())).unwrap();
+        insert_bookmark(
+            &conn,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url,
+                title: None,
+            }
+            .into(),
+        )
+        .unwrap();
+        conn.execute_batch(
+            "UPDATE moz_places SET sync_status = 2, sync_change_counter = 0;
+             UPDATE moz_bookmarks SET syncStatus = 2, syncChangeCounter = 0;",
+        )
+        .unwrap();
+        logins
+            .db
+            .lock()
+            .execute_batch(
+                "INSERT INTO loginsM(guid, origin, httpRealm, timeCreated,
+                                     timePasswordChanged, secFields, server_modified)
+                 VALUES ('login0000001', 'https://example.com', 'realm', 1, 1, '', 1)",
+            )
+            .unwrap();
+
+        let listener = RecordingListener::default();
+        let manager = SyncManager::new();
+        manager
+            .wipe_local_data(
+                LocalDataStores {
+                    places_path: Some(places_path.to_string_lossy().into()),
+                    logins_path: Some(logins_path.to_string_lossy().into()),
+                    ..LocalDataStores::default()
+                },
+                state_path.to_string_lossy().into(),
+                Box::new(listener.clone()),
+            )
+            .unwrap();
+        assert!(!manager.is_wipe_local_data_pending(state_path.to_string_lossy().into()));
+
+        fn count(conn: &impl ConnExt, sql: &str) -> i64 {
+            conn.query_one(sql).unwrap()
+        }
+        for table in [
+            "moz_places_tombstones",
+            "moz_historyvisit_tombstones",
+            "moz_bookmarks_deleted",
+        ] {
+            assert_eq!(
+                count(&conn, &format!("SELECT COUNT(*) FROM {}", table)),
+                0,
+                "{} should be empty",
+                table
+            );
+        }
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM moz_places WHERE sync_change_counter > 0"
+            ),
+            0
+        );
+        assert_eq!(
+            count(
+                &conn,
+                &format!(
+                    "SELECT COUNT(*) FROM moz_bookmarks WHERE guid = '{}'",
+                    BookmarkRootGuid::Unfiled.as_str()
+                )
+            ),
+            1
+        );
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM moz_bookmarks"), 5);
+        let logins_db = logins.db.lock();
+        assert_eq!(count(&*logins_db, "SELECT COUNT(*) FROM loginsL"), 0);
+        assert_eq!(count(&*logins_db, "SELECT COUNT(*) FROM loginsM"), 0);
+
+        assert_eq!(
+            *listener.0.lock(),
+            vec![("logins".to_string(), 1, 2), ("places".to_string(), 2, 2)]
+        );
+    }
+
+    #[test]
+    fn test_wipe_local_data_resumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let places_path = dir.path().join("places.sqlite");
+        let logins_path = dir.path().join("logins.sqlite");
+        let state_path = dir.path().join("wipe-state.json");
+        let logins = logins::LoginStore::new(&logins_path).unwrap();
+        logins
+            .db
+            .lock()
+            .execute_batch(
+                "INSERT INTO loginsM(guid, origin, httpRealm, timeCreated,
+                                     timePasswordChanged, secFields, server_modified)
+                 VALUES ('login0000001', 'https://example.com', 'realm', 1, 1, '', 1)",
+            )
+            .unwrap();
+        // A previous wipe which was interrupted after wiping logins. (The
+        // login we just added means we can tell it isn't wiped again).
+        std::fs::write(&state_path, r#"{"remaining":["places"]}"#).unwrap();
+
+        let manager = SyncManager::new();
+        assert!(manager.is_wipe_local_data_pending(state_path.to_string_lossy().into()));
+        let listener = RecordingListener::default();
+        manager
+            .wipe_local_data(
+                LocalDataStores {
+                    places_path: Some(places_path.to_string_lossy().into()),
+                    logins_path: Some(logins_path.to_string_lossy().into()),
+                    ..LocalDataStores::default()
+                },
+                state_path.to_string_lossy().into(),
+                Box::new(listener.clone()),
+            )
+            .unwrap();
+        assert!(!manager.is_wipe_local_data_pending(state_path.to_string_lossy().into()));
+        assert_eq!(*listener.0.lock(), vec![("places".to_string(), 1, 1)]);
+        let count: i64 = logins
+            .db
+            .lock()
+            .query_one("SELECT COUNT(*) FROM loginsM")
+            .unwrap();
+        assert_eq!(count, 1);
+    }
 }
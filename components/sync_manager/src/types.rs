@@ -28,6 +28,17 @@ pub struct SyncParams {
     pub device_settings: DeviceSettings,
 }
 
+// The paths of the local databases to delete in `wipe_local_data`. Stores
+// the app doesn't use are left as `None`.
+#[derive(Debug, Default)]
+pub struct LocalDataStores {
+    pub places_path: Option<String>,
+    pub logins_path: Option<String>,
+    pub autofill_path: Option<String>,
+    pub tabs_path: Option<String>,
+    pub webext_storage_path: Option<String>,
+}
+
 #[derive(Debug)]
 pub enum SyncReason {
     Scheduled,
@@ -35,6 +46,22 @@ pub enum SyncReason {
     PreSleep,
     Startup,
     EnabledChange,
+    // Another device told us there's something to sync, via a push message.
+    Push,
+}
+
+impl SyncReason {
+    // The name we record in the telemetry.
+    pub(crate) fn telemetry_name(&self) -> &'static str {
+        match self {
+            SyncReason::Scheduled => "scheduled",
+            SyncReason::User => "user",
+            SyncReason::PreSleep => "pre-sleep",
+            SyncReason::Startup => "startup",
+            SyncReason::EnabledChange => "enabled-change",
+            SyncReason::Push => "push",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,171 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Deletes the local data in each of the app's stores, for "delete my data
+//! on this device" flows. See [crate::manager::SyncManager::wipe_local_data].
+//!
+//! We open the stores ourselves, rather than using the engines registered
+//! with the sync manager, so that stores are wiped even if the user never set
+//! up sync, and so that stores which aren't synced by the manager (like
+//! WebExtension storage) are included.
+//!
+//! While a wipe is in progress, we keep a list of the stores which haven't
+//! been wiped yet in a state file. If the app is killed part way through,
+//! calling `wipe_local_data` again picks up where it left off.
+
+use crate::error::*;
+use crate::manager::WipeProgressListener;
+use crate::types::LocalDataStores;
+use serde_derive::*;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use sync15::engine::{BridgedEngine, EngineSyncAssociation, SyncEngine, SyncEngineId};
+
+type WipeFn = fn(&str) -> anyhow::Result<()>;
+
+// Each store, with how to wipe it, in the order we wipe them.
+const STORES: &[(&str, WipeFn)] = &[
+    ("logins", wipe_logins),
+    ("tabs", wipe_tabs),
+    ("places", wipe_places),
+    ("autofill", wipe_autofill),
+    ("webext-storage", wipe_webext_storage),
+];
+
+fn store_path<'a>(stores: &'a LocalDataStores, name: &str) -> Option<&'a str> {
+    match name {
+        "logins" => stores.logins_path.as_deref(),
+        "tabs" => stores.tabs_path.as_deref(),
+        "places" => stores.places_path.as_deref(),
+        "autofill" => stores.autofill_path.as_deref(),
+        "webext-storage" => stores.webext_storage_path.as_deref(),
+        _ => None,
+    }
+}
+
+// What we keep in the state file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct WipeState {
+    // The names of the stores which haven't been wiped yet.
+    remaining: Vec<String>,
+}
+
+fn read_state(state_path: &Path) -> Option<WipeState> {
+    let data = fs::read(state_path).ok()?;
+    match serde_json::from_slice(&data) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            // Start again, which is safe since wiping a store twice is fine.
+            log::warn!("Ignoring corrupt wipe state: {}", e);
+            None
+        }
+    }
+}
+
+// Writes the state to a temporary file, then renames it over the old one, so
+// that we never leave a half-written state file behind.
+fn write_state(state_path: &Path, state: &WipeState) -> Result<()> {
+    let temp_path = state_path.with_extension("tmp");
+    fs::write(&temp_path, serde_json::to_vec(state)?).map_err(anyhow::Error::from)?;
+    fs::rename(&temp_path, state_path).map_err(anyhow::Error::from)?;
+    Ok(())
+}
+
+pub(crate) fn is_wipe_pending(state_path: &Path) -> bool {
+    state_path.exists()
+}
+
+pub(crate) fn wipe_local_data(
+    stores: &LocalDataStores,
+    state_path: &Path,
+    listener: &dyn WipeProgressListener,
+) -> Result<()> {
+    let mut state = read_state(state_path).unwrap_or_else(|| WipeState {
+        remaining: STORES.iter().map(|(name, _)| name.to_string()).collect(),
+    });
+    // Skip stores the app no longer has.
+    state
+        .remaining
+        .retain(|name| store_path(stores, name).is_some());
+    write_state(state_path, &state)?;
+
+    let to_wipe: Vec<_> = STORES
+        .iter()
+        .filter(|(name, _)| state.remaining.iter().any(|r| r == name))
+        .collect();
+    let total = to_wipe.len() as u32;
+    let mut first_error = None;
+    for (completed, (name, wipe)) in to_wipe.into_iter().enumerate() {
+        let path = store_path(stores, name).expect("should only wipe stores with paths");
+        match wipe(path) {
+            Ok(()) => {
+                state.remaining.retain(|r| r != name);
+                write_state(state_path, &state)?;
+            }
+            Err(e) => {
+                error_support::report_error!(
+                    "sync-manager-wipe-local-data",
+                    "Failed to wipe {}: {}",
+                    name,
+                    e
+                );
+                first_error.get_or_insert(e);
+            }
+        }
+        listener.on_progress(name.to_string(), completed as u32 + 1, total);
+    }
+    match first_error {
+        Some(e) => Err(e.into()),
+        None => {
+            fs::remove_file(state_path).map_err(anyhow::Error::from)?;
+            Ok(())
+        }
+    }
+}
+
+// Disconnects `engine` from sync, and deletes its data without recording
+// tombstones, so the data on the server is left alone. Each engine deletes
+// its data in a single transaction.
+fn wipe_engine(engine: &dyn SyncEngine) -> anyhow::Result<()> {
+    engine.reset(&EngineSyncAssociation::Disconnected)?;
+    engine.wipe_local()
+}
+
+fn wipe_logins(path: &str) -> anyhow::Result<()> {
+    // This also deletes the sync metadata.
+    logins::LoginStore::new(path)?.wipe_local()?;
+    Ok(())
+}
+
+fn wipe_tabs(path: &str) -> anyhow::Result<()> {
+    let store = Arc::new(tabs::TabsStore::new(path));
+    wipe_engine(&tabs::TabsEngine::new(store))
+}
+
+fn wipe_places(path: &str) -> anyhow::Result<()> {
+    let api = places::PlacesApi::new(path)?;
+    for engine_id in [
+        SyncEngineId::Bookmarks,
+        SyncEngineId::History,
+        SyncEngineId::HistoryMetadata,
+    ] {
+        wipe_engine(&*places::create_sync_engine(&api, &engine_id)?)?;
+    }
+    Ok(())
+}
+
+fn wipe_autofill(path: &str) -> anyhow::Result<()> {
+    let store = Arc::new(autofill::db::store::Store::new(path)?);
+    wipe_engine(&*Arc::clone(&store).create_addresses_sync_engine())?;
+    wipe_engine(&*store.create_credit_cards_sync_engine())
+}
+
+fn wipe_webext_storage(path: &str) -> anyhow::Result<()> {
+    // The bridged engine's `wipe` only deletes local data and sync metadata.
+    webext_storage::store::Store::new(path)?
+        .bridged_engine()
+        .wipe()?;
+    Ok(())
+}
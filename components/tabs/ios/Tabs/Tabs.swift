@@ -26,6 +26,47 @@ open class TabsStorage {
         }
     }
 
+    /// Queue a command for another device. Returns false if the command is already queued.
+    open func addRemoteCommand(deviceId: String, command: RemoteCommand) throws -> Bool {
+        return try queue.sync {
+            return try self.store.addRemoteCommand(deviceId: deviceId, command: command)
+        }
+    }
+
+    open func removeRemoteCommand(deviceId: String, command: RemoteCommand) throws -> Bool {
+        return try queue.sync {
+            return try self.store.removeRemoteCommand(deviceId: deviceId, command: command)
+        }
+    }
+
+    /// Get the queued commands which still need to be sent.
+    open func getUnsentCommands() throws -> [PendingCommand] {
+        return try queue.sync {
+            return try self.store.getUnsentCommands()
+        }
+    }
+
+    /// Get all the queued commands which haven't expired, including those already sent.
+    open func getPendingCommands() throws -> [PendingCommand] {
+        return try queue.sync {
+            return try self.store.getPendingCommands()
+        }
+    }
+
+    open func setCommandSent(deviceId: String, command: RemoteCommand) throws -> Bool {
+        return try queue.sync {
+            return try self.store.setCommandSent(deviceId: deviceId, command: command)
+        }
+    }
+
+    /// Record that sending a command failed, so it will be retried. Returns false if
+    /// the command has failed too many times and has been dropped.
+    open func setCommandFailed(deviceId: String, command: RemoteCommand) throws -> Bool {
+        return try queue.sync {
+            return try self.store.setCommandFailed(deviceId: deviceId, command: command)
+        }
+    }
+
     open func reset() throws {
         try queue.sync {
             try self.store.reset()
@@ -23,11 +23,31 @@ const CREATE_SCHEMA_SQL: &str = "
     );
 ";
 
+// Commands for other devices (eg, "close this tab") which are waiting to be
+// sent. Unlike the tabs table, these are created locally, so must survive a
+// restart. Sent commands are kept (with `time_sent` set) until they expire,
+// so the app can show them as pending until the other device acts on them.
+const CREATE_REMOTE_COMMANDS_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS remote_tab_commands (
+        id INTEGER PRIMARY KEY,
+        device_id TEXT NOT NULL,
+        -- A `RemoteCommand` kind - see `storage.rs`.
+        kind INTEGER NOT NULL,
+        url TEXT NOT NULL,
+        time_requested INTEGER NOT NULL,
+        -- NULL until the command has been sent.
+        time_sent INTEGER,
+        -- How many times sending the command has failed.
+        failed_attempts INTEGER NOT NULL DEFAULT 0,
+        UNIQUE(device_id, kind, url)
+    );
+";
+
 pub struct TabsMigrationLogin;
 
 impl MigrationLogic for TabsMigrationLogin {
     const NAME: &'static str = "tabs storage db";
-    const END_VERSION: u32 = 1;
+    const END_VERSION: u32 = 2;
 
     fn prepare(&self, conn: &Connection) -> MigrationResult<()> {
         let initial_pragmas = "
@@ -47,11 +67,18 @@ impl MigrationLogic for TabsMigrationLogin {
     fn init(&self, db: &Transaction<'_>) -> MigrationResult<()> {
         log::debug!("Creating schema");
         db.execute_batch(CREATE_SCHEMA_SQL)?;
+        db.execute_batch(CREATE_REMOTE_COMMANDS_SQL)?;
         Ok(())
     }
 
-    fn upgrade_from(&self, _db: &Transaction<'_>, version: u32) -> MigrationResult<()> {
-        Err(MigrationError::IncompatibleVersion(version))
+    fn upgrade_from(&self, db: &Transaction<'_>, version: u32) -> MigrationResult<()> {
+        match version {
+            1 => {
+                db.execute_batch(CREATE_REMOTE_COMMANDS_SQL)?;
+                Ok(())
+            }
+            _ => Err(MigrationError::IncompatibleVersion(version)),
+        }
     }
 }
 
@@ -59,6 +86,7 @@ impl MigrationLogic for TabsMigrationLogin {
 mod tests {
     use super::*;
     use crate::storage::TabsStorage;
+    use sql_support::open_database::test_utils::MigratedDatabaseFile;
 
     #[test]
     fn test_create_schema_twice() {
@@ -67,4 +95,26 @@ mod tests {
         conn.execute_batch(CREATE_SCHEMA_SQL)
             .expect("should allow running twice");
     }
+
+    #[test]
+    fn test_upgrade_from_v1() {
+        let db_file = MigratedDatabaseFile::new(
+            TabsMigrationLogin,
+            "CREATE TABLE tabs (payload TEXT NOT NULL);
+             INSERT INTO tabs (payload) VALUES ('{}');
+             PRAGMA user_version = 1;",
+        );
+        db_file.run_all_upgrades();
+        let conn = db_file.open();
+        let num_tabs: i64 = conn
+            .query_row("SELECT COUNT(*) FROM tabs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(num_tabs, 1);
+        let num_commands: i64 = conn
+            .query_row("SELECT COUNT(*) FROM remote_tab_commands", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(num_commands, 0);
+    }
 }
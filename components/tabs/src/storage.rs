@@ -36,6 +36,10 @@ pub struct ClientRemoteTabs {
         skip_serializing_if = "devicetype_is_unknown"
     )]
     pub device_type: DeviceType,
+    /// When this client last uploaded its tabs, according to the server. Used
+    /// to order clients, and isn't part of the Sync record.
+    #[serde(default)]
+    pub last_modified: i64, // In ms.
     pub remote_tabs: Vec<RemoteTab>,
 }
 
@@ -159,6 +163,9 @@ impl TabsStorage {
         None
     }
 
+    /// Returns the tabs for every remote client. Clients which synced most
+    /// recently come first, and each client's tabs are in the order they were
+    /// last used, most recent first, so every platform shows them the same way.
     pub fn get_remote_tabs(&mut self) -> Option<Vec<ClientRemoteTabs>> {
         match self.open_if_exists() {
             Err(e) => {
@@ -176,7 +183,10 @@ impl TabsStorage {
                     [],
                     |row| -> Result<_> { Ok(serde_json::from_str(&row.get::<_, String>(0)?)?) },
                 ) {
-                    Ok(crts) => Some(crts),
+                    Ok(mut crts) => {
+                        sort_remote_tabs(&mut crts);
+                        Some(crts)
+                    }
                     Err(e) => {
                         error_support::report_error!(
                             "tabs-read-remote",
@@ -225,6 +235,253 @@ impl TabsStorage {
     }
 }
 
+// Ties are broken by id, so that clients which synced at the same time (or
+// which we stored before we recorded when they synced) stay in a stable order.
+fn sort_remote_tabs(crts: &mut [ClientRemoteTabs]) {
+    crts.sort_by(|a, b| {
+        b.last_modified
+            .cmp(&a.last_modified)
+            .then_with(|| a.client_id.cmp(&b.client_id))
+    });
+    for crt in crts {
+        // `sort_by` is stable, so tabs used at the same time keep the order
+        // the client sent them in.
+        crt.remote_tabs
+            .sort_by(|a, b| b.last_used.cmp(&a.last_used));
+    }
+}
+
+/// A command for another device, which we queue until the app manages to send
+/// it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RemoteCommand {
+    SendTab { url: String },
+    CloseTab { url: String },
+}
+
+impl RemoteCommand {
+    // The values stored in the `kind` column - don't change these!
+    fn kind(&self) -> i64 {
+        match self {
+            Self::SendTab { .. } => 1,
+            Self::CloseTab { .. } => 2,
+        }
+    }
+
+    fn url(&self) -> &str {
+        match self {
+            Self::SendTab { url } | Self::CloseTab { url } => url,
+        }
+    }
+
+    fn from_kind(kind: i64, url: String) -> Option<Self> {
+        match kind {
+            1 => Some(Self::SendTab { url }),
+            2 => Some(Self::CloseTab { url }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingCommand {
+    pub device_id: String,
+    pub command: RemoteCommand,
+    pub time_requested: i64, // In ms.
+    pub time_sent: Option<i64>,
+    pub failed_attempts: u32,
+}
+
+// Commands which haven't been sent after this long are dropped - by then the
+// user has probably forgotten about them, or done it themselves.
+const REMOTE_COMMAND_TTL_MS: i64 = 2 * 24 * 60 * 60 * 1000;
+// Commands which have failed to send this many times are dropped.
+const REMOTE_COMMAND_MAX_ATTEMPTS: u32 = 5;
+
+fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+// The outgoing command queue. Commands are added when the user asks for them,
+// and the app then sends whatever `get_unsent_commands()` returns, reporting
+// back whether each one was sent. Note that unlike the remote tabs, these
+// are local data, so they're not touched by resetting or wiping the engine.
+impl TabsStorage {
+    /// Queues a command. Returns false if the same command is already waiting
+    /// to be sent.
+    pub fn add_remote_command(&mut self, device_id: &str, command: &RemoteCommand) -> Result<bool> {
+        self.add_remote_command_at(device_id, command, now_ms())
+    }
+
+    pub(crate) fn add_remote_command_at(
+        &mut self,
+        device_id: &str,
+        command: &RemoteCommand,
+        now: i64,
+    ) -> Result<bool> {
+        let conn = self.open_or_create()?;
+        // Re-adding a command which was already sent queues it again.
+        let changes = conn.execute_cached(
+            "INSERT INTO remote_tab_commands (device_id, kind, url, time_requested)
+             VALUES (:device_id, :kind, :url, :now)
+             ON CONFLICT(device_id, kind, url) DO UPDATE
+             SET time_requested = :now, time_sent = NULL, failed_attempts = 0
+             WHERE time_sent IS NOT NULL",
+            rusqlite::named_params! {
+                ":device_id": device_id,
+                ":kind": command.kind(),
+                ":url": command.url(),
+                ":now": now,
+            },
+        )?;
+        Ok(changes != 0)
+    }
+
+    /// Removes a queued command, whether it was sent or not. Returns false if
+    /// there was no such command.
+    pub fn remove_remote_command(
+        &mut self,
+        device_id: &str,
+        command: &RemoteCommand,
+    ) -> Result<bool> {
+        let conn = match self.open_if_exists()? {
+            Some(conn) => conn,
+            None => return Ok(false),
+        };
+        let changes = conn.execute_cached(
+            "DELETE FROM remote_tab_commands
+             WHERE device_id = :device_id AND kind = :kind AND url = :url",
+            rusqlite::named_params! {
+                ":device_id": device_id,
+                ":kind": command.kind(),
+                ":url": command.url(),
+            },
+        )?;
+        Ok(changes != 0)
+    }
+
+    /// Returns the commands which haven't been sent yet, oldest first, after
+    /// dropping any which have expired.
+    pub fn get_unsent_commands(&mut self) -> Result<Vec<PendingCommand>> {
+        self.get_unsent_commands_at(now_ms())
+    }
+
+    pub(crate) fn get_unsent_commands_at(&mut self, now: i64) -> Result<Vec<PendingCommand>> {
+        Ok(self
+            .get_commands_at(now)?
+            .into_iter()
+            .filter(|command| command.time_sent.is_none())
+            .collect())
+    }
+
+    /// Returns all the queued commands, sent or not, which haven't expired.
+    pub fn get_pending_commands(&mut self) -> Result<Vec<PendingCommand>> {
+        self.get_commands_at(now_ms())
+    }
+
+    fn get_commands_at(&mut self, now: i64) -> Result<Vec<PendingCommand>> {
+        let conn = match self.open_if_exists()? {
+            Some(conn) => conn,
+            None => return Ok(vec![]),
+        };
+        conn.execute_cached(
+            "DELETE FROM remote_tab_commands WHERE time_requested < :cutoff",
+            rusqlite::named_params! { ":cutoff": now - REMOTE_COMMAND_TTL_MS },
+        )?;
+        let rows = conn.query_rows_and_then_cached(
+            "SELECT device_id, kind, url, time_requested, time_sent, failed_attempts
+             FROM remote_tab_commands
+             ORDER BY time_requested, id",
+            [],
+            |row| -> Result<Option<PendingCommand>> {
+                // Skip (rather than fail on) commands of a kind we don't know
+                // about - they must have been added by a newer version.
+                let command = match RemoteCommand::from_kind(row.get("kind")?, row.get("url")?) {
+                    Some(command) => command,
+                    None => return Ok(None),
+                };
+                Ok(Some(PendingCommand {
+                    device_id: row.get("device_id")?,
+                    command,
+                    time_requested: row.get("time_requested")?,
+                    time_sent: row.get("time_sent")?,
+                    failed_attempts: row.get("failed_attempts")?,
+                }))
+            },
+        )?;
+        Ok(rows.into_iter().flatten().collect())
+    }
+
+    /// Records that a command was sent. Returns false if the command isn't
+    /// queued (eg, because it expired).
+    pub fn set_command_sent(&mut self, device_id: &str, command: &RemoteCommand) -> Result<bool> {
+        self.set_command_sent_at(device_id, command, now_ms())
+    }
+
+    pub(crate) fn set_command_sent_at(
+        &mut self,
+        device_id: &str,
+        command: &RemoteCommand,
+        now: i64,
+    ) -> Result<bool> {
+        let conn = match self.open_if_exists()? {
+            Some(conn) => conn,
+            None => return Ok(false),
+        };
+        let changes = conn.execute_cached(
+            "UPDATE remote_tab_commands SET time_sent = :now
+             WHERE device_id = :device_id AND kind = :kind AND url = :url",
+            rusqlite::named_params! {
+                ":device_id": device_id,
+                ":kind": command.kind(),
+                ":url": command.url(),
+                ":now": now,
+            },
+        )?;
+        Ok(changes != 0)
+    }
+
+    /// Records that sending a command failed, so it will be returned by
+    /// `get_unsent_commands()` again to be retried. After
+    /// `REMOTE_COMMAND_MAX_ATTEMPTS` failures we give up and remove it, in
+    /// which case this returns false.
+    pub fn set_command_failed(&mut self, device_id: &str, command: &RemoteCommand) -> Result<bool> {
+        let conn = match self.open_if_exists()? {
+            Some(conn) => conn,
+            None => return Ok(false),
+        };
+        let tx = conn.unchecked_transaction()?;
+        let updated = tx.execute_cached(
+            "UPDATE remote_tab_commands SET failed_attempts = failed_attempts + 1
+             WHERE device_id = :device_id AND kind = :kind AND url = :url",
+            rusqlite::named_params! {
+                ":device_id": device_id,
+                ":kind": command.kind(),
+                ":url": command.url(),
+            },
+        )?;
+        let dropped = tx.execute_cached(
+            "DELETE FROM remote_tab_commands
+             WHERE device_id = :device_id AND kind = :kind AND url = :url
+               AND failed_attempts >= :max_attempts",
+            rusqlite::named_params! {
+                ":device_id": device_id,
+                ":kind": command.kind(),
+                ":url": command.url(),
+                ":max_attempts": REMOTE_COMMAND_MAX_ATTEMPTS,
+            },
+        )?;
+        tx.commit()?;
+        if dropped != 0 {
+            log::warn!("Giving up on a remote command after too many failures");
+        }
+        Ok(updated != 0 && dropped == 0)
+    }
+}
+
 fn is_url_syncable(url: &str) -> bool {
     url.len() <= URI_LENGTH_MAX
         && !(url.starts_with("about:")
@@ -334,4 +591,162 @@ mod tests {
             ])
         );
     }
+
+    #[test]
+    fn test_remote_tabs_order() {
+        let mut storage = TabsStorage::new_with_mem_path("test_remote_tabs_order");
+        let tab = |title: &str, last_used| RemoteTab {
+            title: title.to_owned(),
+            url_history: vec!["https://example.com".to_owned()],
+            icon: None,
+            last_used,
+        };
+        let client = |client_id: &str, last_modified, remote_tabs| ClientRemoteTabs {
+            client_id: client_id.to_owned(),
+            client_name: client_id.to_owned(),
+            device_type: DeviceType::Unknown,
+            last_modified,
+            remote_tabs,
+        };
+        storage
+            .replace_remote_tabs(vec![
+                client("old", 1000, vec![tab("a", 1), tab("b", 3), tab("c", 2)]),
+                client("new-b", 2000, vec![]),
+                client("new-a", 2000, vec![tab("d", 5), tab("e", 5)]),
+            ])
+            .unwrap();
+        let crts = storage.get_remote_tabs().unwrap();
+        assert_eq!(
+            crts.iter()
+                .map(|crt| (
+                    crt.client_id.as_str(),
+                    crt.last_modified,
+                    crt.remote_tabs
+                        .iter()
+                        .map(|t| t.title.as_str())
+                        .collect::<Vec<_>>()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("new-a", 2000, vec!["d", "e"]),
+                ("new-b", 2000, vec![]),
+                ("old", 1000, vec!["b", "c", "a"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remote_commands() {
+        let mut storage = TabsStorage::new_with_mem_path("test_remote_commands");
+        // No database yet means no commands.
+        assert_eq!(storage.get_unsent_commands_at(0).unwrap(), vec![]);
+
+        let close = RemoteCommand::CloseTab {
+            url: "https://example.com".to_string(),
+        };
+        let send = RemoteCommand::SendTab {
+            url: "https://example.com".to_string(),
+        };
+        assert!(storage
+            .add_remote_command_at("device-1", &close, 1000)
+            .unwrap());
+        assert!(storage
+            .add_remote_command_at("device-1", &send, 2000)
+            .unwrap());
+        assert!(storage
+            .add_remote_command_at("device-2", &close, 3000)
+            .unwrap());
+        // Already queued.
+        assert!(!storage
+            .add_remote_command_at("device-1", &close, 4000)
+            .unwrap());
+
+        let unsent = storage.get_unsent_commands_at(5000).unwrap();
+        assert_eq!(
+            unsent
+                .iter()
+                .map(|c| (c.device_id.as_str(), &c.command, c.time_requested))
+                .collect::<Vec<_>>(),
+            vec![
+                ("device-1", &close, 1000),
+                ("device-1", &send, 2000),
+                ("device-2", &close, 3000),
+            ]
+        );
+
+        // Sent commands are no longer "unsent", but are still pending.
+        assert!(storage
+            .set_command_sent_at("device-1", &close, 6000)
+            .unwrap());
+        let unsent = storage.get_unsent_commands_at(7000).unwrap();
+        assert_eq!(unsent.len(), 2);
+        assert!(unsent.iter().all(|c| c.time_sent.is_none()));
+        let pending = storage.get_commands_at(7000).unwrap();
+        assert_eq!(pending.len(), 3);
+        assert_eq!(pending[0].time_sent, Some(6000));
+
+        // Adding a sent command again queues it again.
+        assert!(storage
+            .add_remote_command_at("device-1", &close, 8000)
+            .unwrap());
+        let pending = storage.get_commands_at(9000).unwrap();
+        assert_eq!(pending.len(), 3);
+        assert_eq!(pending[2].command, close);
+        assert_eq!(pending[2].time_sent, None);
+
+        assert!(storage.remove_remote_command("device-2", &close).unwrap());
+        assert!(!storage.remove_remote_command("device-2", &close).unwrap());
+        assert_eq!(storage.get_unsent_commands_at(9000).unwrap().len(), 2);
+
+        // Everything expires eventually.
+        assert_eq!(
+            storage
+                .get_unsent_commands_at(8000 + REMOTE_COMMAND_TTL_MS)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            storage
+                .get_unsent_commands_at(8001 + REMOTE_COMMAND_TTL_MS)
+                .unwrap(),
+            vec![]
+        );
+        assert!(!storage.set_command_sent("device-1", &close).unwrap());
+    }
+
+    #[test]
+    fn test_remote_command_failures() {
+        let mut storage = TabsStorage::new_with_mem_path("test_remote_command_failures");
+        let command = RemoteCommand::SendTab {
+            url: "https://example.com".to_string(),
+        };
+        assert!(storage.add_remote_command("device", &command).unwrap());
+        for attempt in 1..REMOTE_COMMAND_MAX_ATTEMPTS {
+            assert!(storage.set_command_failed("device", &command).unwrap());
+            let unsent = storage.get_unsent_commands().unwrap();
+            assert_eq!(unsent.len(), 1);
+            assert_eq!(unsent[0].failed_attempts, attempt);
+        }
+        // We give up after too many failures.
+        assert!(!storage.set_command_failed("device", &command).unwrap());
+        assert_eq!(storage.get_unsent_commands().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_remote_commands_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_name = dir.path().join("test_remote_commands_persist.db");
+        let command = RemoteCommand::CloseTab {
+            url: "https://example.com".to_string(),
+        };
+        let mut storage = TabsStorage::new(&db_name);
+        storage.add_remote_command("device", &command).unwrap();
+        drop(storage);
+
+        let mut storage = TabsStorage::new(&db_name);
+        let unsent = storage.get_unsent_commands().unwrap();
+        assert_eq!(unsent.len(), 1);
+        assert_eq!(unsent[0].command, command);
+    }
 }
@@ -2,7 +2,9 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::storage::{ClientRemoteTabs, RemoteTab, TabsStorage};
+use crate::error::{ApiResult, Result};
+use crate::storage::{ClientRemoteTabs, PendingCommand, RemoteCommand, RemoteTab, TabsStorage};
+use error_support::handle_error;
 use std::path::Path;
 use std::sync::Mutex;
 
@@ -38,4 +40,44 @@ impl TabsStore {
     pub fn remote_tabs(&self) -> Option<Vec<ClientRemoteTabs>> {
         self.storage.lock().unwrap().get_remote_tabs()
     }
+
+    pub fn add_remote_command(&self, device_id: &str, command: &RemoteCommand) -> ApiResult<bool> {
+        handle_error! {
+            self.storage.lock().unwrap().add_remote_command(device_id, command)
+        }
+    }
+
+    pub fn remove_remote_command(
+        &self,
+        device_id: &str,
+        command: &RemoteCommand,
+    ) -> ApiResult<bool> {
+        handle_error! {
+            self.storage.lock().unwrap().remove_remote_command(device_id, command)
+        }
+    }
+
+    pub fn get_unsent_commands(&self) -> ApiResult<Vec<PendingCommand>> {
+        handle_error! {
+            self.storage.lock().unwrap().get_unsent_commands()
+        }
+    }
+
+    pub fn get_pending_commands(&self) -> ApiResult<Vec<PendingCommand>> {
+        handle_error! {
+            self.storage.lock().unwrap().get_pending_commands()
+        }
+    }
+
+    pub fn set_command_sent(&self, device_id: &str, command: &RemoteCommand) -> ApiResult<bool> {
+        handle_error! {
+            self.storage.lock().unwrap().set_command_sent(device_id, command)
+        }
+    }
+
+    pub fn set_command_failed(&self, device_id: &str, command: &RemoteCommand) -> ApiResult<bool> {
+        handle_error! {
+            self.storage.lock().unwrap().set_command_failed(device_id, command)
+        }
+    }
 }
@@ -154,7 +154,7 @@ impl BridgedEngine for BridgedEngineImpl {
                         continue;
                     }
                 };
-                records.push(record);
+                records.push((record, inc.modified));
             }
 
             let mut sync_impl = self.sync_impl.lock().unwrap();
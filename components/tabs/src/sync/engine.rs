@@ -43,20 +43,23 @@ impl ClientRemoteTabs {
         client_id: String,
         remote_client: &RemoteClient,
         record: TabsRecord,
+        last_modified: ServerTimestamp,
     ) -> Self {
         Self {
             client_id,
             client_name: remote_client.device_name.clone(),
             device_type: remote_client.device_type.unwrap_or(DeviceType::Unknown),
+            last_modified: last_modified.as_millis(),
             remote_tabs: record.tabs.iter().map(RemoteTab::from_record_tab).collect(),
         }
     }
 
-    fn from_record(client_id: String, record: TabsRecord) -> Self {
+    fn from_record(client_id: String, record: TabsRecord, last_modified: ServerTimestamp) -> Self {
         Self {
             client_id,
             client_name: record.client_name,
             device_type: DeviceType::Unknown,
+            last_modified: last_modified.as_millis(),
             remote_tabs: record.tabs.iter().map(RemoteTab::from_record_tab).collect(),
         }
     }
@@ -120,11 +123,14 @@ impl TabsSyncImpl {
         Ok(())
     }
 
-    pub fn apply_incoming(&mut self, inbound: Vec<TabsRecord>) -> Result<Option<TabsRecord>> {
+    pub fn apply_incoming(
+        &mut self,
+        inbound: Vec<(TabsRecord, ServerTimestamp)>,
+    ) -> Result<Option<TabsRecord>> {
         let local_id = self.local_id.clone();
         let mut remote_tabs = Vec::with_capacity(inbound.len());
 
-        for record in inbound {
+        for (record, last_modified) in inbound {
             if record.id == local_id {
                 // That's our own record, ignore it.
                 continue;
@@ -139,6 +145,7 @@ impl TabsSyncImpl {
                         .to_owned(),
                     remote_client,
                     record,
+                    last_modified,
                 )
             } else {
                 // A record with a device that's not in our remote clients seems unlikely, but
@@ -149,7 +156,7 @@ impl TabsSyncImpl {
                     "Storing tabs from a client that doesn't appear in the devices list: {}",
                     id,
                 );
-                ClientRemoteTabs::from_record(id, record)
+                ClientRemoteTabs::from_record(id, record, last_modified)
             };
             remote_tabs.push(crt);
         }
@@ -175,6 +182,7 @@ impl TabsSyncImpl {
                 client_id: local_id,
                 client_name,
                 device_type,
+                last_modified: 0,
                 remote_tabs: local_tabs.to_vec(),
             };
             log::trace!("outgoing {:?}", local_record);
@@ -257,7 +265,7 @@ impl SyncEngine for TabsEngine {
 
         for incoming in inbound.changes {
             let record = match TabsRecord::from_payload(incoming.0) {
-                Ok(record) => record,
+                Ok(record) => (record, incoming.1),
                 Err(e) => {
                     log::warn!("Error deserializing incoming record: {}", e);
                     incoming_telemetry.failed(1);
@@ -317,6 +325,11 @@ impl SyncEngine for TabsEngine {
     fn wipe(&self) -> anyhow::Result<()> {
         Ok(self.sync_impl.lock().unwrap().wipe()?)
     }
+
+    fn wipe_local(&self) -> anyhow::Result<()> {
+        // Tabs don't have tombstones, so wiping is always local.
+        self.wipe()
+    }
 }
 
 impl crate::TabsStore {
@@ -4,12 +4,12 @@
 
 use crate::error::*;
 use crate::schema;
-use interrupt_support::{SqlInterruptHandle, SqlInterruptScope};
+use interrupt_support::{register_interrupt, SqlInterruptHandle, SqlInterruptScope};
 use rusqlite::types::{FromSql, ToSql};
 use rusqlite::Connection;
 use rusqlite::OpenFlags;
 use sql_support::open_database::open_database_with_flags;
-use sql_support::ConnExt;
+use sql_support::{maintenance::MaintenancePolicy, ConnExt};
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 use std::result;
@@ -52,8 +52,10 @@ impl StorageDb {
             | OpenFlags::SQLITE_OPEN_READ_WRITE;
 
         let conn = open_database_with_flags(db_path, flags, &schema::WebExtMigrationLogin)?;
+        let interrupt_handle = Arc::new(SqlInterruptHandle::new(&conn));
+        register_interrupt(Arc::<SqlInterruptHandle>::downgrade(&interrupt_handle));
         Ok(Self {
-            interrupt_handle: Arc::new(SqlInterruptHandle::new(&conn)),
+            interrupt_handle,
             writer: conn,
         })
     }
@@ -105,6 +107,22 @@ impl DerefMut for StorageDb {
     }
 }
 
+// Once a deletion has been synced, its tombstone isn't needed: the next sync
+// treats an extension without local data the same as one with a synced
+// tombstone.
+pub(crate) struct StorageMaintenancePolicy;
+
+impl MaintenancePolicy for StorageMaintenancePolicy {
+    const NAME: &'static str = "webext-storage";
+
+    fn prune(&self, conn: &Connection) -> rusqlite::Result<u32> {
+        Ok(conn.execute(
+            "DELETE FROM storage_sync_data WHERE data IS NULL AND sync_change_counter = 0",
+            [],
+        )? as u32)
+    }
+}
+
 pub(crate) mod sql_fns {
     use rusqlite::{functions::Context, Result};
     use sync_guid::Guid as SyncGuid;
@@ -0,0 +1,221 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::error::*;
+use crate::migration::do_insert;
+use rusqlite::Transaction;
+use serde_json::Value;
+use std::path::Path;
+
+// Imports data from the storage backends Gecko used before ours. Like the
+// kinto migration in `migration.rs`, this is a one-shot import which ignores
+// quotas and writes everything in a single transaction.
+//
+// We accept two layouts:
+// * A directory in the layout of Gecko's JSON backend, which is a
+//   subdirectory for each extension, named after its id, with a `storage.js`
+//   file holding a JSON object of the extension's keys and values.
+// * A single JSON file holding an object whose keys are extension ids and
+//   whose values are objects of each extension's keys and values. We can't
+//   read Gecko's IndexedDB databases, which use the structured clone format,
+//   so Gecko writes them out in this form for us.
+//
+// We follow the same error strategy as the migration: if we can't read an
+// extension's data, we record why in its result and carry on with the others,
+// but failing to write fails the import.
+
+// The name of the file each extension's data lives in, in the directory
+// layout.
+const LEGACY_STORAGE_FILE_NAME: &str = "storage.js";
+
+/// The result of importing a single extension's data.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LegacyImportResult {
+    pub ext_id: String,
+    /// The number of keys we imported, which is zero if we failed to read
+    /// the extension's data.
+    pub entries: usize,
+    /// Why we couldn't read the extension's data, if we couldn't.
+    pub error: Option<String>,
+}
+
+pub fn import_legacy_storage(tx: &Transaction<'_>, path: &Path) -> Result<Vec<LegacyImportResult>> {
+    let mut results = Vec::new();
+    for (ext_id, data) in read_legacy_storage(path)? {
+        let result = match data {
+            Ok(vals) => {
+                let entries = if vals.is_empty() {
+                    0
+                } else {
+                    do_insert(tx, &ext_id, vals)?
+                };
+                LegacyImportResult {
+                    ext_id,
+                    entries,
+                    error: None,
+                }
+            }
+            Err(error) => {
+                log::warn!("Failed to read legacy storage for {}: {}", ext_id, error);
+                LegacyImportResult {
+                    ext_id,
+                    entries: 0,
+                    error: Some(error),
+                }
+            }
+        };
+        results.push(result);
+    }
+    results.sort_by(|a, b| a.ext_id.cmp(&b.ext_id));
+    log::info!("imported legacy storage for {} extensions", results.len());
+    Ok(results)
+}
+
+type LegacyValues = std::result::Result<Vec<(String, Value)>, String>;
+
+// Reads the keys and values for each extension at `path`. Failing to read
+// `path` itself is an error, but failing to read an extension's data is
+// returned with its id.
+fn read_legacy_storage(path: &Path) -> Result<Vec<(String, LegacyValues)>> {
+    if !path.is_dir() {
+        let contents = std::fs::read_to_string(path)?;
+        return Ok(match serde_json::from_str(&contents)? {
+            Value::Object(exts) => exts
+                .into_iter()
+                .map(|(ext_id, data)| (ext_id, values_from_json(data)))
+                .collect(),
+            _ => {
+                log::warn!("Legacy storage dump isn't a JSON object");
+                Vec::new()
+            }
+        });
+    }
+    let mut exts = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let ext_id = match entry.file_name().into_string() {
+            Ok(ext_id) => ext_id,
+            Err(name) => {
+                log::trace!("skipping non-UTF-8 directory name {:?}", name);
+                continue;
+            }
+        };
+        let file = entry.path().join(LEGACY_STORAGE_FILE_NAME);
+        if !file.is_file() {
+            log::trace!("{} has no legacy storage", ext_id);
+            continue;
+        }
+        let data = std::fs::read_to_string(&file)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| serde_json::from_str(&contents).map_err(|e| e.to_string()))
+            .and_then(values_from_json);
+        exts.push((ext_id, data));
+    }
+    Ok(exts)
+}
+
+fn values_from_json(data: Value) -> LegacyValues {
+    match data {
+        Value::Object(map) => Ok(map.into_iter().collect()),
+        _ => Err("storage isn't a JSON object".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api;
+    use crate::db::test::new_mem_db;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    fn write_ext(dir: &Path, ext_id: &str, contents: &str) {
+        let ext_dir = dir.join(ext_id);
+        std::fs::create_dir(&ext_dir).expect("should create dir");
+        std::fs::write(ext_dir.join(LEGACY_STORAGE_FILE_NAME), contents).expect("should write");
+    }
+
+    #[test]
+    fn test_import_directory() {
+        let tmpdir = tempdir().unwrap();
+        write_ext(
+            tmpdir.path(),
+            "good@example.com",
+            r#"{"a": 1, "b": [true]}"#,
+        );
+        write_ext(tmpdir.path(), "empty@example.com", "{}");
+        write_ext(tmpdir.path(), "array@example.com", "[1, 2]");
+        write_ext(tmpdir.path(), "corrupt@example.com", r#"{"a": "#);
+        // Extensions without any storage are skipped.
+        std::fs::create_dir(tmpdir.path().join("none@example.com")).unwrap();
+
+        let mut db = new_mem_db();
+        let tx = db.transaction().expect("tx should work");
+        let results = import_legacy_storage(&tx, tmpdir.path()).expect("should import");
+        tx.commit().expect("should commit");
+
+        assert_eq!(
+            results
+                .iter()
+                .map(|r| (r.ext_id.as_str(), r.entries, r.error.is_some()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("array@example.com", 0, true),
+                ("corrupt@example.com", 0, true),
+                ("empty@example.com", 0, false),
+                ("good@example.com", 2, false),
+            ]
+        );
+        assert_eq!(
+            api::get(&db, "good@example.com", json!(null)).expect("should get"),
+            json!({"a": 1, "b": [true]})
+        );
+        assert_eq!(
+            api::get(&db, "array@example.com", json!(null)).expect("should get"),
+            json!({})
+        );
+    }
+
+    #[test]
+    fn test_import_dump() {
+        let tmpdir = tempdir().unwrap();
+        let path = tmpdir.path().join("dump.json");
+        std::fs::write(
+            &path,
+            json!({
+                "ext-a": {"a": "x"},
+                "ext-b": "not an object",
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let store = crate::store::test::new_mem_store();
+        let results = store.import_legacy_storage(&path).expect("should import");
+        assert_eq!(
+            results,
+            vec![
+                LegacyImportResult {
+                    ext_id: "ext-a".to_string(),
+                    entries: 1,
+                    error: None,
+                },
+                LegacyImportResult {
+                    ext_id: "ext-b".to_string(),
+                    entries: 0,
+                    error: Some("storage isn't a JSON object".to_string()),
+                },
+            ]
+        );
+        assert_eq!(
+            store.get("ext-a", json!(null)).expect("should get"),
+            json!({"a": "x"})
+        );
+
+        // A missing file fails the whole import.
+        assert!(store
+            .import_legacy_storage(tmpdir.path().join("missing.json"))
+            .is_err());
+    }
+}
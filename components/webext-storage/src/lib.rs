@@ -9,11 +9,13 @@ mod api;
 mod db;
 pub mod error;
 mod ffi;
+mod legacy_import;
 mod migration;
 mod schema;
 pub mod store;
 mod sync;
 
+pub use legacy_import::LegacyImportResult;
 pub use migration::MigrationInfo;
 
 // We publish some constants from non-public modules.
@@ -24,3 +26,4 @@ pub use api::SYNC_QUOTA_BYTES;
 pub use api::SYNC_QUOTA_BYTES_PER_ITEM;
 
 pub use api::UsageInfo;
+pub use store::StorageChangeObserver;
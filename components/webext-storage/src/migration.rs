@@ -207,7 +207,11 @@ fn read_rows(filename: &Path) -> (Vec<LegacyRow>, MigrationInfo) {
 /// strange things), chooses an arbitrary one. Returns the number of entries
 /// inserted, which could be different from `vals.len()` if multiple entries in
 /// `vals` have the same key.
-fn do_insert(tx: &Transaction<'_>, ext_id: &str, vals: Vec<(String, Value)>) -> Result<usize> {
+pub(crate) fn do_insert(
+    tx: &Transaction<'_>,
+    ext_id: &str,
+    vals: Vec<(String, Value)>,
+) -> Result<usize> {
     let mut map = Map::with_capacity(vals.len());
     for (key, val) in vals {
         map.insert(key, val);
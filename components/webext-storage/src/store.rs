@@ -3,16 +3,18 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::api::{self, StorageChanges};
-use crate::db::StorageDb;
+use crate::db::{StorageDb, StorageMaintenancePolicy};
 use crate::error::*;
+use crate::legacy_import::{import_legacy_storage, LegacyImportResult};
 use crate::migration::{migrate, MigrationInfo};
 use crate::sync;
 use std::path::Path;
 use std::result;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use interrupt_support::SqlInterruptHandle;
 use serde_json::Value as JsonValue;
+use sql_support::maintenance::{self, MaintenanceMetrics};
 
 /// A store is used to access `storage.sync` data. It manages an underlying
 /// database connection, and exposes methods for reading and writing storage
@@ -27,6 +29,18 @@ use serde_json::Value as JsonValue;
 /// `Store::new()` (or `webext_store_new()`, from the FFI) once.
 pub struct Store {
     db: StorageDb,
+    observer: Mutex<Option<Arc<dyn StorageChangeObserver>>>,
+}
+
+/// Receives the changes made to each extension's storage area, so that the
+/// browser can dispatch `storage.onChanged` events without diffing the whole
+/// area. This includes changes made by this store (`set`, `remove` and `clear`)
+/// and changes applied by sync.
+pub trait StorageChangeObserver: Send + Sync {
+    /// Called after changes for `ext_id` are committed. `changes` is the JSON
+    /// serialization of a `StorageChanges`, which is an object in the same
+    /// shape as the `changes` argument to `storage.onChanged` listeners.
+    fn on_changed(&self, ext_id: &str, changes: &str);
 }
 
 impl Store {
@@ -35,6 +49,7 @@ impl Store {
     pub fn new(db_path: impl AsRef<Path>) -> Result<Self> {
         Ok(Self {
             db: StorageDb::new(db_path)?,
+            observer: Mutex::new(None),
         })
     }
 
@@ -43,6 +58,7 @@ impl Store {
     pub fn new_memory(db_path: &str) -> Result<Self> {
         Ok(Self {
             db: StorageDb::new_memory(db_path)?,
+            observer: Mutex::new(None),
         })
     }
 
@@ -51,12 +67,32 @@ impl Store {
         self.db.interrupt_handle()
     }
 
+    /// Sets the observer which is told about changes to storage areas,
+    /// replacing any existing one. Pass `None` to stop observing.
+    pub fn set_change_observer(&self, observer: Option<Arc<dyn StorageChangeObserver>>) {
+        *self.observer.lock().unwrap() = observer;
+    }
+
+    fn notify_changed(&self, ext_id: &str, changes: &StorageChanges) {
+        if changes.is_empty() {
+            return;
+        }
+        let observer = self.observer.lock().unwrap().clone();
+        if let Some(observer) = observer {
+            match serde_json::to_string(changes) {
+                Ok(changes) => observer.on_changed(ext_id, &changes),
+                Err(e) => log::warn!("Failed to serialize storage changes: {}", e),
+            }
+        }
+    }
+
     /// Sets one or more JSON key-value pairs for an extension ID. Returns a
     /// list of changes, with existing and new values for each key in `val`.
     pub fn set(&self, ext_id: &str, val: JsonValue) -> Result<StorageChanges> {
         let tx = self.db.unchecked_transaction()?;
         let result = api::set(&tx, ext_id, val)?;
         tx.commit()?;
+        self.notify_changed(ext_id, &result);
         Ok(result)
     }
 
@@ -94,6 +130,7 @@ impl Store {
         let tx = self.db.unchecked_transaction()?;
         let result = api::remove(&tx, ext_id, keys)?;
         tx.commit()?;
+        self.notify_changed(ext_id, &result);
         Ok(result)
     }
 
@@ -104,6 +141,7 @@ impl Store {
         let tx = self.db.unchecked_transaction()?;
         let result = api::clear(&tx, ext_id)?;
         tx.commit()?;
+        self.notify_changed(ext_id, &result);
         Ok(result)
     }
 
@@ -113,15 +151,20 @@ impl Store {
         api::get_bytes_in_use(&self.db, ext_id, keys)
     }
 
-    /// Returns a bridged sync engine for Desktop for this store.
+    /// Returns a bridged sync engine for Desktop for this store. Changes
+    /// applied by the engine are reported to the change observer.
     pub fn bridged_engine(&self) -> sync::BridgedEngine<'_> {
-        sync::BridgedEngine::new(&self.db)
+        let observer = self.observer.lock().unwrap().clone();
+        sync::BridgedEngine::new(&self.db).with_observer(observer)
     }
 
     /// Closes the store and its database connection. See the docs for
     /// `StorageDb::close` for more details on when this can fail.
     pub fn close(self) -> result::Result<(), (Store, Error)> {
-        self.db.close().map_err(|(db, err)| (Store { db }, err))
+        let observer = self.observer;
+        self.db
+            .close()
+            .map_err(|(db, err)| (Store { db, observer }, err))
     }
 
     /// Gets the changes which the current sync applied. Should be used
@@ -150,6 +193,29 @@ impl Store {
         Ok(())
     }
 
+    /// Imports data from Gecko's older storage backends at `path`, which is
+    /// either a directory in the layout of the JSON backend, or a JSON dump of
+    /// each extension's data. See the `legacy_import` module for the details.
+    /// Returns the result for each extension we found, in order of their ids.
+    ///
+    /// Like `migrate`, this ignores quotas and replaces any data the
+    /// extensions already have in this store.
+    pub fn import_legacy_storage(&self, path: impl AsRef<Path>) -> Result<Vec<LegacyImportResult>> {
+        let tx = self.db.unchecked_transaction()?;
+        let results = import_legacy_storage(&tx, path.as_ref())?;
+        tx.commit()?;
+        Ok(results)
+    }
+
+    /// Prunes synced tombstones, and vacuums and optimizes the database when
+    /// it needs it. Should be called during idle time.
+    pub fn run_maintenance(&self) -> Result<MaintenanceMetrics> {
+        Ok(maintenance::run_maintenance(
+            &self.db,
+            &StorageMaintenancePolicy,
+        )?)
+    }
+
     /// Read-and-delete (e.g. `take` in rust parlance, see Option::take)
     /// operation for any MigrationInfo stored in this database.
     pub fn take_migration_info(&self) -> Result<Option<MigrationInfo>> {
@@ -163,6 +229,7 @@ impl Store {
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use serde_json::json;
     #[test]
     fn test_send() {
         fn ensure_send<T: Send>() {}
@@ -173,6 +240,107 @@ pub mod test {
     pub fn new_mem_store() -> Store {
         Store {
             db: crate::db::test::new_mem_db(),
+            observer: Mutex::new(None),
         }
     }
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        changes: Mutex<Vec<(String, JsonValue)>>,
+    }
+
+    impl StorageChangeObserver for RecordingObserver {
+        fn on_changed(&self, ext_id: &str, changes: &str) {
+            self.changes.lock().unwrap().push((
+                ext_id.to_string(),
+                serde_json::from_str(changes).expect("changes should be JSON"),
+            ));
+        }
+    }
+
+    #[test]
+    fn test_change_observer() -> Result<()> {
+        let store = new_mem_store();
+        let observer = Arc::new(RecordingObserver::default());
+        store.set_change_observer(Some(observer.clone()));
+
+        store.set("ext-a", json!({"a": 1, "b": 2}))?;
+        store.set("ext-a", json!({"a": 3}))?;
+        store.remove("ext-a", json!("b"))?;
+        // Removing a key that doesn't exist isn't a change.
+        store.remove("ext-a", json!("missing"))?;
+        store.clear("ext-a")?;
+        assert_eq!(
+            std::mem::take(&mut *observer.changes.lock().unwrap()),
+            vec![
+                (
+                    "ext-a".to_string(),
+                    json!({"a": {"newValue": 1}, "b": {"newValue": 2}})
+                ),
+                (
+                    "ext-a".to_string(),
+                    json!({"a": {"oldValue": 1, "newValue": 3}})
+                ),
+                ("ext-a".to_string(), json!({"b": {"oldValue": 2}})),
+                ("ext-a".to_string(), json!({"a": {"oldValue": 3}})),
+            ]
+        );
+
+        store.set_change_observer(None);
+        store.set("ext-a", json!({"a": 1}))?;
+        assert!(observer.changes.lock().unwrap().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_change_observer_sync() -> Result<()> {
+        use sync15::engine::{BridgedEngine, IncomingEnvelope};
+
+        let store = new_mem_store();
+        let observer = Arc::new(RecordingObserver::default());
+        store.set_change_observer(Some(observer.clone()));
+
+        let engine = store.bridged_engine();
+        engine.sync_started()?;
+        let record = json!({
+            "id": "guid",
+            "extId": "ext-a",
+            "data": r#"{"a": "remote"}"#,
+        });
+        let envelope: IncomingEnvelope = serde_json::from_value(json!({
+            "id": "guid",
+            "modified": 0,
+            "payload": record.to_string(),
+        }))?;
+        engine.store_incoming(&[envelope])?;
+        engine.apply()?;
+        engine.sync_finished()?;
+
+        assert_eq!(
+            *observer.changes.lock().unwrap(),
+            vec![("ext-a".to_string(), json!({"a": {"newValue": "remote"}}))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_maintenance() -> Result<()> {
+        let store = new_mem_store();
+        store.set("ext-a", json!({"a": 1}))?;
+        store.db.execute_batch(
+            "INSERT INTO storage_sync_data(ext_id, data, sync_change_counter)
+             VALUES ('ext-synced', NULL, 0), ('ext-unsynced', NULL, 1);",
+        )?;
+        let metrics = store.run_maintenance()?;
+        // Only the synced tombstone is pruned.
+        assert_eq!(metrics.pruned, 1);
+        let ext_ids: Vec<String> = store
+            .db
+            .prepare("SELECT ext_id FROM storage_sync_data ORDER BY ext_id")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        assert_eq!(ext_ids, vec!["ext-a", "ext-unsynced"]);
+        assert_eq!(store.get("ext-a", JsonValue::Null)?, json!({"a": 1}));
+        Ok(())
+    }
 }
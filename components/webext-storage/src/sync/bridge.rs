@@ -3,12 +3,15 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use rusqlite::Transaction;
+use std::sync::Arc;
 use sync15::engine::{ApplyResults, IncomingEnvelope, OutgoingEnvelope};
 use sync_guid::Guid as SyncGuid;
 
 use crate::db::{delete_meta, get_meta, put_meta, StorageDb};
 use crate::error::{Error, Result};
 use crate::schema;
+use crate::store::StorageChangeObserver;
+use crate::sync::get_synced_changes;
 use crate::sync::incoming::{apply_actions, get_incoming, plan_incoming, stage_incoming};
 use crate::sync::outgoing::{get_outgoing, record_uploaded, stage_outgoing};
 
@@ -21,12 +24,29 @@ const SYNC_ID_META_KEY: &str = "sync_id";
 /// should eventually rename and unify with this trait (#2841).
 pub struct BridgedEngine<'a> {
     db: &'a StorageDb,
+    observer: Option<Arc<dyn StorageChangeObserver>>,
 }
 
 impl<'a> BridgedEngine<'a> {
     /// Creates a bridged engine for syncing.
     pub fn new(db: &'a StorageDb) -> Self {
-        BridgedEngine { db }
+        BridgedEngine { db, observer: None }
+    }
+
+    /// Sets the observer to tell about changes applied by the engine.
+    pub fn with_observer(mut self, observer: Option<Arc<dyn StorageChangeObserver>>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    // Tells the observer about the changes stashed by the last `apply`.
+    fn notify_applied(&self) -> Result<()> {
+        if let Some(observer) = &self.observer {
+            for change in get_synced_changes(self.db)? {
+                observer.on_changed(&change.ext_id, &change.changes);
+            }
+        }
+        Ok(())
     }
 
     fn do_reset(&self, tx: &Transaction<'_>) -> Result<()> {
@@ -111,6 +131,7 @@ impl<'a> sync15::engine::BridgedEngine for BridgedEngine<'a> {
         apply_actions(&tx, actions, &signal)?;
         stage_outgoing(&tx)?;
         tx.commit()?;
+        self.notify_applied()?;
 
         let outgoing = get_outgoing(self.db, &signal)?
             .into_iter()
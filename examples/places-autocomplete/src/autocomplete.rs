@@ -213,7 +213,7 @@ fn import_places(
 mod autocomplete {
     use super::*;
     use interrupt_support::SqlInterruptHandle;
-    use places::api::matcher::{search_frecent, SearchParams, SearchResult};
+    use places::api::matcher::{search_frecent, RestrictionTokens, SearchParams, SearchResult};
     use places::Error;
     use rusqlite::{Error as RusqlError, ErrorCode};
     use std::sync::{
@@ -514,6 +514,7 @@ mod autocomplete {
                             autocompleter.query(SearchParams {
                                 search_string: query_str.clone(),
                                 limit: 10,
+                                restriction_tokens: Some(RestrictionTokens::default()),
                             })?;
                         }
                     }
@@ -596,6 +597,7 @@ mod autocomplete {
                         autocompleter.query(SearchParams {
                             search_string: query_str.clone(),
                             limit: 10,
+                            restriction_tokens: Some(RestrictionTokens::default()),
                         })?;
                     } else {
                         pending_change = true;
@@ -624,6 +626,7 @@ mod autocomplete {
                     autocompleter.query(SearchParams {
                         search_string: query_str.clone(),
                         limit: 10,
+                        restriction_tokens: Some(RestrictionTokens::default()),
                     })?;
                 }
             }
@@ -0,0 +1,85 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Populates a database with a seeded, synthetic profile (see
+// `places::test_support`) and times some of the operations our consumers call
+// most often. The same seed always produces the same profile, so numbers can
+// be compared across machines and builds.
+
+use places::api::matcher::{search_frecent, SearchParams};
+use places::storage::bookmarks::{fetch::fetch_tree, BookmarkRootGuid};
+use places::storage::history::{get_top_frecent_site_infos, get_visited};
+use places::test_support::{generate_profile, ProfileConfig};
+use places::PlacesDb;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+#[derive(Clone, Debug)]
+pub struct BenchmarkOptions {
+    pub profile: ProfileConfig,
+    pub iterations: usize,
+}
+
+fn time<T>(name: &str, iterations: usize, mut f: impl FnMut() -> places::Result<T>) -> Result<()> {
+    let mut times: Vec<Duration> = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        f()?;
+        times.push(start.elapsed());
+    }
+    times.sort();
+    println!(
+        "{:<28} min {:>10?}  median {:>10?}  max {:>10?}",
+        name,
+        times[0],
+        times[times.len() / 2],
+        times[times.len() - 1]
+    );
+    Ok(())
+}
+
+/// Populates `db`, which should be empty, and prints timings for each operation.
+pub fn run_benchmark(db: &PlacesDb, opts: BenchmarkOptions) -> Result<()> {
+    let iterations = opts.iterations.max(1);
+    println!("Generating profile: {:?}", opts.profile);
+    let start = Instant::now();
+    let profile = generate_profile(db, &opts.profile)?;
+    println!(
+        "Profile generated in {:?}: {} visits, {} bookmarks in {} folders",
+        start.elapsed(),
+        profile.visited_urls.len(),
+        profile.num_bookmarks,
+        profile.folder_guids.len()
+    );
+
+    time("search_frecent", iterations, || {
+        search_frecent(
+            db,
+            SearchParams {
+                search_string: "example".into(),
+                limit: 10,
+                restriction_tokens: None,
+            },
+        )
+    })?;
+    time("get_top_frecent_site_infos", iterations, || {
+        get_top_frecent_site_infos(db, 25, 0)
+    })?;
+    time("get_visited", iterations, || {
+        get_visited(
+            db,
+            profile
+                .visited_urls
+                .iter()
+                .take(10_000)
+                .cloned()
+                .collect::<Vec<_>>(),
+        )
+    })?;
+    time("fetch_tree", iterations, || {
+        fetch_tree(db, BookmarkRootGuid::Root.guid())
+    })?;
+    Ok(())
+}
@@ -6,12 +6,14 @@
 
 use cli_support::fxa_creds::{get_cli_fxa, get_default_fxa_config};
 use places::storage::bookmarks::{
+    duplicates,
     json_tree::{
         fetch_tree, insert_tree, BookmarkNode, BookmarkTreeNode, FetchDepth, FolderNode,
         SeparatorNode,
     },
     BookmarkRootGuid,
 };
+use places::test_support::ProfileConfig;
 use places::types::BookmarkType;
 use places::{ConnectionType, PlacesApi, PlacesDb};
 use serde_derive::*;
@@ -27,6 +29,9 @@ use viaduct_reqwest::use_reqwest_backend;
 
 use anyhow::Result;
 
+mod benchmark;
+mod validate;
+
 // A struct in the format of desktop with a union of all fields.
 #[derive(Debug, Default, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
@@ -143,6 +148,36 @@ fn run_ios_import_history(conn: &PlacesDb, filename: String) -> Result<()> {
     Ok(())
 }
 
+fn run_fennec_import_bookmarks(api: &PlacesApi, filename: String) -> Result<()> {
+    println!("fennec import bookmarks from {}", filename);
+    let res = places::import::import_fennec_bookmarks(api, filename, None)?;
+    println!("Import finished!, results: {:?}", res);
+    Ok(())
+}
+
+fn run_fennec_import_history(api: &PlacesApi, filename: String) -> Result<()> {
+    println!("fennec import history from {}", filename);
+    let res = places::import::import_fennec_history(api, filename, None)?;
+    println!("Import finished!, results: {:?}", res);
+    Ok(())
+}
+
+// Finds bookmarks which have the same parent, url and title as an earlier
+// sibling, and (unless `dry_run` is set) deletes the later copies.
+fn run_dedupe_bookmarks(db: &PlacesDb, dry_run: bool) -> Result<()> {
+    let dupes = duplicates::find_duplicate_siblings(db)?;
+    for dupe in &dupes {
+        println!("duplicate bookmark {} ({})", dupe.guid, dupe.url);
+    }
+    if dry_run {
+        println!("Found {} duplicate(s) - nothing was deleted", dupes.len());
+    } else {
+        let num_deleted = duplicates::delete_duplicate_siblings(db)?;
+        println!("Moved {} duplicate(s) to the trash", num_deleted);
+    }
+    Ok(())
+}
+
 fn run_native_import(db: &PlacesDb, filename: String) -> Result<()> {
     println!("import from {}", filename);
 
@@ -366,6 +401,85 @@ enum Command {
         /// Imports bookmarks from a desktop export
         input_file: String,
     },
+
+    #[structopt(name = "import-fennec-bookmarks")]
+    /// Import bookmarks from a Fennec browser.db
+    ImportFennecBookmarks {
+        #[structopt(name = "input-file", long, short = "i")]
+        /// The name of the file to read.
+        input_file: String,
+    },
+
+    #[structopt(name = "import-fennec-history")]
+    /// Import history from a Fennec browser.db
+    ImportFennecHistory {
+        #[structopt(name = "input-file", long, short = "i")]
+        /// The name of the file to read.
+        input_file: String,
+    },
+
+    #[structopt(name = "dedupe-bookmarks")]
+    /// Remove bookmarks with the same url and title as an earlier sibling
+    DedupeBookmarks {
+        /// Only report the duplicates, don't delete them.
+        #[structopt(name = "dry-run", long)]
+        dry_run: bool,
+    },
+
+    #[structopt(name = "validate")]
+    /// Check the database for corruption and invalid bookmark trees
+    Validate,
+
+    #[structopt(name = "benchmark")]
+    /// Generate a synthetic profile and time common operations against it.
+    /// The database specified by --database_path is ignored unless
+    /// --output-db is used.
+    Benchmark {
+        /// Seed for the profile generator; the same seed generates the same profile.
+        #[structopt(name = "seed", long, default_value = "1")]
+        seed: u64,
+
+        /// Number of visits to generate
+        #[structopt(name = "visits", long, default_value = "50000")]
+        visits: usize,
+
+        /// Number of distinct hosts to generate visits to
+        #[structopt(name = "hosts", long, default_value = "2000")]
+        hosts: usize,
+
+        /// Number of bookmarks to generate in each folder
+        #[structopt(name = "bookmarks-per-folder", long, default_value = "20")]
+        bookmarks_per_folder: usize,
+
+        /// How deeply to nest bookmark folders
+        #[structopt(name = "folder-depth", long, default_value = "2")]
+        folder_depth: usize,
+
+        /// Number of times to run each operation
+        #[structopt(name = "iterations", long, default_value = "10")]
+        iterations: usize,
+
+        /// Write the generated profile here instead of a temporary database.
+        /// The file must not already exist.
+        #[structopt(name = "output-db", long)]
+        output_db: Option<String>,
+    },
+}
+
+fn run_benchmark(output_db: Option<String>, opts: benchmark::BenchmarkOptions) -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = match output_db {
+        Some(path) => {
+            if std::path::Path::new(&path).exists() {
+                anyhow::bail!("{} already exists", path);
+            }
+            std::path::PathBuf::from(path)
+        }
+        None => dir.path().join("places.sqlite"),
+    };
+    let api = PlacesApi::new(&path)?;
+    let db = api.open_connection(ConnectionType::ReadWrite)?;
+    benchmark::run_benchmark(&db, opts)
 }
 
 fn main() -> Result<()> {
@@ -374,11 +488,39 @@ fn main() -> Result<()> {
         cli_support::init_trace_logging();
     }
 
+    // The benchmark always uses a fresh database, so don't touch the user's.
+    if let Command::Benchmark {
+        seed,
+        visits,
+        hosts,
+        bookmarks_per_folder,
+        folder_depth,
+        iterations,
+        ref output_db,
+    } = opts.cmd
+    {
+        return run_benchmark(
+            output_db.clone(),
+            benchmark::BenchmarkOptions {
+                profile: ProfileConfig {
+                    seed,
+                    num_visits: visits,
+                    num_hosts: hosts,
+                    bookmarks_per_folder,
+                    folder_depth,
+                    ..ProfileConfig::default()
+                },
+                iterations,
+            },
+        );
+    }
+
     let db_path = opts.database_path;
     let api = PlacesApi::new(&db_path)?;
     let db = api.open_connection(ConnectionType::ReadWrite)?;
     // Needed to make the get_registered_sync_engine() calls work.
     api.clone().register_with_sync_manager();
+    api.clone().register_history_metadata_with_sync_manager();
 
     ctrlc::set_handler(move || {
         println!("\nCTRL-C detected, enabling shutdown mode\n");
@@ -409,5 +551,12 @@ fn main() -> Result<()> {
         Command::ImportIosBookmarks { input_file } => run_ios_import_bookmarks(&api, input_file),
         Command::ImportDesktopBookmarks { input_file } => run_desktop_import(&db, input_file),
         Command::ImportIosHistory { input_file } => run_ios_import_history(&db, input_file),
+        Command::ImportFennecBookmarks { input_file } => {
+            run_fennec_import_bookmarks(&api, input_file)
+        }
+        Command::ImportFennecHistory { input_file } => run_fennec_import_history(&api, input_file),
+        Command::DedupeBookmarks { dry_run } => run_dedupe_bookmarks(&db, dry_run),
+        Command::Validate => validate::run_validate(&db).map(|_| ()),
+        Command::Benchmark { .. } => unreachable!("handled above"),
     }
 }
@@ -0,0 +1,119 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Sanity checks for a places database. These are mostly the same checks our
+// tests perform (see `check_positions` in the places tests), but run against
+// a real profile so we can tell whether a reported problem is caused by a
+// corrupt database or by a bug in our code.
+
+use places::storage::bookmarks::{fetch::fetch_tree, BookmarkRootGuid, USER_CONTENT_ROOTS};
+use places::PlacesDb;
+use sql_support::ConnExt;
+
+use anyhow::Result;
+
+/// Runs all checks, printing each problem found. Returns the number of problems.
+pub fn run_validate(db: &PlacesDb) -> Result<usize> {
+    let mut problems = 0;
+    problems += check_integrity(db)?;
+    problems += check_foreign_keys(db)?;
+    problems += check_roots(db)?;
+    problems += check_positions(db)?;
+    problems += check_orphans(db)?;
+    problems += check_tree_fetch(db)?;
+    if problems == 0 {
+        println!("No problems found");
+    } else {
+        println!("Found {} problem(s)", problems);
+    }
+    Ok(problems)
+}
+
+fn check_integrity(db: &PlacesDb) -> Result<usize> {
+    let results =
+        db.query_rows_and_then("PRAGMA integrity_check", [], |row| row.get::<_, String>(0))?;
+    Ok(results
+        .into_iter()
+        .filter(|r| r != "ok")
+        .inspect(|r| println!("integrity_check: {}", r))
+        .count())
+}
+
+fn check_foreign_keys(db: &PlacesDb) -> Result<usize> {
+    let results = db.query_rows_and_then("PRAGMA foreign_key_check", [], |row| {
+        places::Result::Ok((row.get::<_, String>(0)?, row.get::<_, Option<i64>>(1)?))
+    })?;
+    for (table, rowid) in &results {
+        println!("foreign_key_check: table {} row {:?}", table, rowid);
+    }
+    Ok(results.len())
+}
+
+fn check_roots(db: &PlacesDb) -> Result<usize> {
+    let mut problems = 0;
+    let roots = std::iter::once(&BookmarkRootGuid::Root).chain(USER_CONTENT_ROOTS.iter());
+    for root in roots {
+        let exists = db.query_row_and_then_cachable(
+            "SELECT EXISTS(SELECT 1 FROM moz_bookmarks WHERE guid = :guid AND type = 2)",
+            &[(":guid", &root.as_str())],
+            |row| row.get::<_, bool>(0),
+            false,
+        )?;
+        if !exists {
+            println!("roots: missing root folder {}", root.as_str());
+            problems += 1;
+        }
+    }
+    Ok(problems)
+}
+
+// Detects holes or duplicates in child positions - see `check_positions` in
+// the places tests for how this works.
+fn check_positions(db: &PlacesDb) -> Result<usize> {
+    let sql = "
+        WITH bad_parents(pid) as (
+            SELECT parent
+            FROM moz_bookmarks
+            GROUP BY parent
+            HAVING (SUM(DISTINCT position + 1) - (count(*) * (count(*) + 1) / 2)) <> 0
+        )
+        SELECT guid FROM moz_bookmarks
+        WHERE id in bad_parents";
+    let parents = db.query_rows_and_then(sql, [], |row| row.get::<_, String>(0))?;
+    for guid in &parents {
+        println!("positions: folder {} has invalid child positions", guid);
+    }
+    Ok(parents.len())
+}
+
+fn check_orphans(db: &PlacesDb) -> Result<usize> {
+    let sql = "
+        SELECT b.guid FROM moz_bookmarks b
+        WHERE b.guid <> :root
+          AND NOT EXISTS(SELECT 1 FROM moz_bookmarks p
+                         WHERE p.id = b.parent AND p.type = 2)";
+    let orphans =
+        db.query_rows_and_then(sql, &[(":root", &BookmarkRootGuid::Root.as_str())], |row| {
+            row.get::<_, String>(0)
+        })?;
+    for guid in &orphans {
+        println!("orphans: item {} has no valid parent folder", guid);
+    }
+    Ok(orphans.len())
+}
+
+// Fetching the entire tree exercises the same code paths the FFI uses.
+fn check_tree_fetch(db: &PlacesDb) -> Result<usize> {
+    match fetch_tree(db, BookmarkRootGuid::Root.guid()) {
+        Ok(Some(_)) => Ok(0),
+        Ok(None) => {
+            println!("tree: the root folder couldn't be fetched");
+            Ok(1)
+        }
+        Err(e) => {
+            println!("tree: failed to fetch the tree: {}", e);
+            Ok(1)
+        }
+    }
+}
@@ -4,6 +4,8 @@
 
 package mozilla.appservices
 
+import com.sun.jna.Library
+import com.sun.jna.Native
 import org.mozilla.appservices.full_megazord.BuildConfig
 
 class Megazord {
@@ -13,5 +15,27 @@ class Megazord {
             System.setProperty("mozilla.appservices.megazord.library", "megazord")
             System.setProperty("mozilla.appservices.megazord.version", BuildConfig.LIBRARY_VERSION)
         }
+
+        /**
+         * Interrupts everything running on the databases of every component,
+         * and stops new operations from starting, so that shutting the app
+         * down doesn't wait on long-running queries. There's no way back, so
+         * this should only be called when the app is about to exit.
+         */
+        @JvmStatic
+        fun shutdown() {
+            LibMegazord.INSTANCE.megazord_shutdown()
+        }
     }
 }
+
+@Suppress("FunctionNaming")
+internal interface LibMegazord : Library {
+    companion object {
+        internal val INSTANCE: LibMegazord by lazy {
+            Native.load<LibMegazord>("megazord", LibMegazord::class.java)
+        }
+    }
+
+    fun megazord_shutdown()
+}
@@ -17,6 +17,7 @@ pub use nimbus;
 pub use places;
 pub use push;
 pub use rc_log_ffi;
+pub use remote_settings;
 pub use sync_manager;
 pub use tabs;
 pub use viaduct;
@@ -42,6 +43,15 @@ pub extern "C" fn full_megazord_get_version() -> *const c_char {
     VERSION_PTR.0
 }
 
+/// Interrupts everything running on the databases of every component, and
+/// stops new operations from starting, so that shutting the app down doesn't
+/// wait on long-running queries. There's no way back, so this should only be
+/// called when the app is about to exit.
+#[no_mangle]
+pub extern "C" fn megazord_shutdown() {
+    interrupt_support::shutdown()
+}
+
 // This is set by gradle, but wouldn't be set otherwise. If it is unset,
 // we'll return null from this function, which will cause the megazord
 // version checker to throw. Separated as a constant to make it clear that
@@ -14,5 +14,10 @@
 #import "nimbusFFI.h"
 #import "placesFFI.h"
 #import "pushFFI.h"
+#import "remote_settingsFFI.h"
 #import "tabsFFI.h"
 #import "errorFFI.h"
+
+// Interrupts everything running in every component, and stops new operations
+// from starting. See `megazord_shutdown()` in `src/lib.rs`.
+void megazord_shutdown(void);
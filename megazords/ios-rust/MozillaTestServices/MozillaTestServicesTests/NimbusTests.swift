@@ -287,6 +287,8 @@ class NimbusTests: XCTestCase {
 
         // Record a valid exposure event in Glean that matches the featureId from the test experiment
         nimbus.recordExposureEvent(featureId: "aboutwelcome")
+        nimbus.recordExposureEvent(featureId: "aboutwelcome")
+        nimbus.flushExposureEvents()
 
         // Use the Glean test API to check that the valid event is present
         XCTAssertNotNil(GleanMetrics.NimbusEvents.exposure.testGetValue(), "Event must have a value")
@@ -294,6 +296,7 @@ class NimbusTests: XCTestCase {
         XCTAssertEqual(1, exposureEvents.count, "Event count must match")
         let exposureEventExtras = exposureEvents.first!.extra
         XCTAssertEqual("secure-gold", exposureEventExtras!["experiment"], "Experiment slug must match")
+        XCTAssertEqual("2", exposureEventExtras!["count"], "Exposure count must match")
         XCTAssertTrue(
             exposureEventExtras!["branch"] == "control" || exposureEventExtras!["branch"] == "treatment",
             "Experiment branch must match"
@@ -302,6 +305,7 @@ class NimbusTests: XCTestCase {
         // Attempt to record an event for a non-existent or feature we are not enrolled in an
         // experiment in to ensure nothing is recorded.
         nimbus.recordExposureEvent(featureId: "not-a-feature")
+        nimbus.flushExposureEvents()
 
         // Verify the invalid event was ignored by checking again that the valid event is still the only
         // event, and that it hasn't changed any of its extra properties.
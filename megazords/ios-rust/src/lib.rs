@@ -14,6 +14,16 @@ pub use nimbus;
 pub use places;
 pub use push;
 pub use rc_log_ffi;
+pub use remote_settings;
 pub use sync15;
 pub use tabs;
 pub use viaduct_reqwest;
+
+/// Interrupts everything running on the databases of every component, and
+/// stops new operations from starting, so that shutting the app down doesn't
+/// wait on long-running queries. There's no way back, so this should only be
+/// called when the app is about to exit.
+#[no_mangle]
+pub extern "C" fn megazord_shutdown() {
+    interrupt_support::shutdown()
+}
@@ -5,12 +5,25 @@
 #![warn(rust_2018_idioms)]
 
 mod database;
+mod generated;
 mod matching;
 
 use criterion::{criterion_group, criterion_main};
 use database::{bench_match_url, bench_search_frecent};
+use generated::{
+    bench_generated_apply_observation, bench_generated_fetch_tree, bench_generated_get_visited,
+    bench_generated_search_frecent, bench_generated_top_frecent,
+};
 use matching::bench_match_anywhere;
 
 criterion_group!(bench_db, bench_search_frecent, bench_match_url);
+criterion_group!(
+    bench_generated,
+    bench_generated_search_frecent,
+    bench_generated_top_frecent,
+    bench_generated_get_visited,
+    bench_generated_apply_observation,
+    bench_generated_fetch_tree
+);
 criterion_group!(bench_mem, bench_match_anywhere);
-criterion_main!(bench_db, bench_mem);
+criterion_main!(bench_db, bench_generated, bench_mem);
@@ -92,6 +92,7 @@ pub fn bench_search_frecent(c: &mut Criterion) {
             SearchParams {
                 search_string: "mozilla".into(),
                 limit: 10,
+                restriction_tokens: None,
             },
         )
         .unwrap()
@@ -102,6 +103,7 @@ pub fn bench_search_frecent(c: &mut Criterion) {
             SearchParams {
                 search_string: "blog.mozilla.org".into(),
                 limit: 10,
+                restriction_tokens: None,
             },
         )
         .unwrap()
@@ -112,6 +114,7 @@ pub fn bench_search_frecent(c: &mut Criterion) {
             SearchParams {
                 search_string: "https://hg.mozilla.org/mozilla-central".into(),
                 limit: 10,
+                restriction_tokens: None,
             },
         )
         .unwrap()
@@ -0,0 +1,158 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Benchmarks against a profile from `places::test_support`, which is much
+// larger than the one in `database.rs` and is shaped more like a real user's.
+// The profile is generated from a fixed seed, so results are comparable
+// between runs.
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput};
+use places::api::{
+    matcher::{search_frecent, SearchParams},
+    places_api::ConnectionType,
+};
+use places::storage::bookmarks::{fetch::fetch_tree, BookmarkRootGuid};
+use places::storage::history::{apply_observation, get_top_frecent_site_infos, get_visited};
+use places::test_support::{generate_profile, GeneratedProfile, ProfileConfig};
+use places::{PlacesDb, VisitObservation, VisitTransition};
+use std::rc::Rc;
+use types::Timestamp;
+use url::Url;
+
+const BURST_SIZE: usize = 100;
+
+pub struct GeneratedDb {
+    // Needs to be here so that the dir isn't deleted.
+    _dir: tempfile::TempDir,
+    pub db: PlacesDb,
+    pub profile: GeneratedProfile,
+}
+
+impl GeneratedDb {
+    pub fn new() -> Rc<Self> {
+        use std::sync::Arc;
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("places.sqlite");
+        let db = PlacesDb::open(
+            &file,
+            ConnectionType::ReadWrite,
+            0,
+            Arc::new(parking_lot::Mutex::new(())),
+        )
+        .unwrap();
+        println!("Generating test profile...");
+        let profile = generate_profile(&db, &ProfileConfig::default()).unwrap();
+        println!("Done generating test profile");
+        Rc::new(Self {
+            _dir: dir,
+            db,
+            profile,
+        })
+    }
+}
+
+pub fn bench_generated_search_frecent(c: &mut Criterion) {
+    let test_db = GeneratedDb::new();
+    for (name, search_string) in [
+        ("generated search_frecent word", "news"),
+        ("generated search_frecent origin", "news-site1.example.com"),
+        (
+            "generated search_frecent url",
+            "https://www.news-site3.example.com/",
+        ),
+    ] {
+        let test_db = test_db.clone();
+        c.bench_function(name, move |b| {
+            b.iter(|| {
+                search_frecent(
+                    &test_db.db,
+                    SearchParams {
+                        search_string: search_string.into(),
+                        limit: 10,
+                        restriction_tokens: None,
+                    },
+                )
+                .unwrap()
+            })
+        });
+    }
+}
+
+pub fn bench_generated_top_frecent(c: &mut Criterion) {
+    let test_db = GeneratedDb::new();
+    c.bench_function("generated get_top_frecent_site_infos", move |b| {
+        b.iter(|| get_top_frecent_site_infos(&test_db.db, 25, 0).unwrap())
+    });
+}
+
+// The larger sizes are what we see when a session with many tabs is
+// restored. They're reported as URLs per second, so it's easy to see whether
+// the cost per URL grows with the size of the set.
+pub fn bench_generated_get_visited(c: &mut Criterion) {
+    let test_db = GeneratedDb::new();
+    let mut group = c.benchmark_group("generated get_visited");
+    for size in [1_000, 10_000, 100_000] {
+        // Half of these are known, half are not.
+        let urls: Vec<Url> = test_db
+            .profile
+            .visited_urls
+            .iter()
+            .cycle()
+            .take(size / 2)
+            .cloned()
+            .chain(
+                (0..size / 2).map(|i| Url::parse(&format!("https://unvisited{}.com/", i)).unwrap()),
+            )
+            .collect();
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &urls, |b, urls| {
+            b.iter_batched(
+                || urls.clone(),
+                |urls| get_visited(&test_db.db, urls).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+pub fn bench_generated_apply_observation(c: &mut Criterion) {
+    let test_db = GeneratedDb::new();
+    c.bench_function("generated apply_observation burst", move |b| {
+        let mut n = 0;
+        b.iter_batched(
+            || {
+                // A mix of revisits and new pages, as happens when a user
+                // restores a session.
+                (0..BURST_SIZE)
+                    .map(|i| {
+                        n += 1;
+                        let url = if i % 2 == 0 {
+                            test_db.profile.visited_urls[n % test_db.profile.visited_urls.len()]
+                                .clone()
+                        } else {
+                            Url::parse(&format!("https://burst.example.com/{}", n)).unwrap()
+                        };
+                        VisitObservation::new(url)
+                            .with_visit_type(VisitTransition::Link)
+                            .with_at(Timestamp::now())
+                    })
+                    .collect::<Vec<_>>()
+            },
+            |observations| {
+                for obs in observations {
+                    apply_observation(&test_db.db, obs).unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+pub fn bench_generated_fetch_tree(c: &mut Criterion) {
+    let test_db = GeneratedDb::new();
+    c.bench_function("generated bookmark fetch_tree", move |b| {
+        b.iter(|| fetch_tree(&test_db.db, BookmarkRootGuid::Root.guid()).unwrap())
+    });
+}
@@ -0,0 +1,130 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use places::api::places_api::{ConnectionType, PlacesApi};
+use places::storage::fetch_page_info;
+use places::{Result, VisitTransition};
+use rusqlite::Connection;
+use sql_support::ConnExt;
+use std::path::Path;
+use tempfile::tempdir;
+use url::Url;
+
+// Chrome's timestamps are microseconds since 1601-01-01.
+const CHROME_EPOCH_OFFSET_MS: i64 = 11_644_473_600_000;
+
+fn empty_chrome_db(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(include_str!("./chrome_history_schema.sql"))?;
+    Ok(conn)
+}
+
+fn insert_url(conn: &Connection, id: i64, url: &str, title: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO urls(id, url, title, last_visit_time) VALUES(:id, :url, :title, 0)",
+        rusqlite::named_params! { ":id": id, ":url": url, ":title": title },
+    )?;
+    Ok(())
+}
+
+fn insert_visit(
+    conn: &Connection,
+    id: i64,
+    url_id: i64,
+    date_ms: i64,
+    transition: i64,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO visits(id, url, visit_time, transition)
+         VALUES(:id, :url, :visit_time, :transition)",
+        rusqlite::named_params! {
+            ":id": id,
+            ":url": url_id,
+            ":visit_time": (date_ms + CHROME_EPOCH_OFFSET_MS) * 1000,
+            ":transition": transition,
+        },
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_import() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    let tmpdir = tempdir().unwrap();
+    let chrome_path = tmpdir.path().join("History");
+    let chrome_db = empty_chrome_db(&chrome_path)?;
+
+    insert_url(&chrome_db, 1, "https://example.com/", "Example")?;
+    insert_url(&chrome_db, 2, "https://example.com/redirected", "")?;
+    insert_url(&chrome_db, 3, "http://💖.com/💖", "Sparkle")?;
+    insert_url(&chrome_db, 4, "not a url", "Invalid")?;
+
+    // A typed visit, and a link visit from another device.
+    insert_visit(&chrome_db, 1, 1, 1_565_117_389_897, 0x3000_0001)?;
+    insert_visit(&chrome_db, 2, 1, 1_565_117_389_898, 0)?;
+    chrome_db.execute("INSERT INTO visit_source(id, source) VALUES(2, 0)", [])?;
+    // The end of a server redirect chain, which Chrome stores as a negative
+    // number.
+    insert_visit(
+        &chrome_db,
+        3,
+        2,
+        1_565_117_389_899,
+        0xA000_0000u32 as i32 as i64,
+    )?;
+    insert_visit(&chrome_db, 4, 3, 1_565_117_389_900, 8)?;
+    insert_visit(&chrome_db, 5, 4, 1_565_117_389_901, 0)?;
+
+    let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
+    let metrics = places::import::import_chrome_history(&places_api, chrome_path)?;
+    assert_eq!(metrics.num_total, 5);
+    assert_eq!(metrics.num_succeeded, 4);
+    assert_eq!(metrics.num_failed, 1);
+
+    let conn = places_api.open_connection(ConnectionType::ReadOnly)?;
+    let visits = |url: &str| -> Result<Vec<(i64, VisitTransition, bool)>> {
+        let url = Url::parse(url)?;
+        Ok(conn.query_rows_and_then(
+            "SELECT v.visit_date, v.visit_type, v.is_local
+             FROM moz_historyvisits v
+             JOIN moz_places h ON h.id = v.place_id
+             WHERE h.url_hash = hash(:url) AND h.url = :url
+             ORDER BY v.visit_date",
+            &[(":url", &url.as_str())],
+            |row| -> Result<_> {
+                Ok((
+                    row.get(0)?,
+                    VisitTransition::from_primitive(row.get(1)?).expect("valid transition"),
+                    row.get(2)?,
+                ))
+            },
+        )?)
+    };
+    assert_eq!(
+        visits("https://example.com/")?,
+        [
+            (1_565_117_389_897, VisitTransition::Typed, true),
+            (1_565_117_389_898, VisitTransition::Link, false),
+        ]
+    );
+    assert_eq!(
+        visits("https://example.com/redirected")?,
+        [(1_565_117_389_899, VisitTransition::RedirectTemporary, true)]
+    );
+    assert_eq!(
+        visits("http://💖.com/💖")?,
+        [(1_565_117_389_900, VisitTransition::Reload, true)]
+    );
+
+    let page = fetch_page_info(&conn, &Url::parse("https://example.com/")?)?
+        .expect("should exist")
+        .page;
+    assert_eq!(page.title, "Example");
+    assert_eq!(page.visit_count_local, 1);
+    assert_eq!(page.visit_count_remote, 1);
+    assert!(page.frecency > 0);
+
+    Ok(())
+}
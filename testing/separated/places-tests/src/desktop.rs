@@ -0,0 +1,241 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use places::api::places_api::{ConnectionType, PlacesApi};
+use places::storage::bookmarks::bookmarks_get_url_for_keyword;
+use places::storage::{fetch_page_info, tags};
+use places::{Error, PlacesDb, Result, VisitTransition};
+use rusqlite::Connection;
+use sql_support::ConnExt;
+use std::path::Path;
+use tempfile::tempdir;
+use url::Url;
+
+fn empty_desktop_db(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(include_str!("./desktop_schema.sql"))?;
+    Ok(conn)
+}
+
+fn insert_place(conn: &Connection, id: i64, url: &str, title: Option<&str>) -> Result<()> {
+    conn.execute(
+        "INSERT INTO moz_places(id, url, title) VALUES(:id, :url, :title)",
+        rusqlite::named_params! { ":id": id, ":url": url, ":title": title },
+    )?;
+    Ok(())
+}
+
+fn insert_visit(
+    conn: &Connection,
+    place_id: i64,
+    date_ms: i64,
+    visit_type: i64,
+    source: i64,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO moz_historyvisits(place_id, visit_date, visit_type, source)
+         VALUES(:place_id, :visit_date, :visit_type, :source)",
+        rusqlite::named_params! {
+            ":place_id": place_id,
+            ":visit_date": date_ms * 1000,
+            ":visit_type": visit_type,
+            ":source": source,
+        },
+    )?;
+    Ok(())
+}
+
+// Inserts an item with the given id, type, parent, position and title. Its
+// guid is made from its id.
+fn insert_item(
+    conn: &Connection,
+    id: i64,
+    item_type: i64,
+    fk: Option<i64>,
+    parent: i64,
+    position: i64,
+    title: &str,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO moz_bookmarks(id, type, fk, parent, position, title, dateAdded, lastModified, guid)
+         VALUES(:id, :type, :fk, :parent, :position, :title,
+                1565117389897000, 1565117389897000, printf('item%08d', :id))",
+        rusqlite::named_params! {
+            ":id": id,
+            ":type": item_type,
+            ":fk": fk,
+            ":parent": parent,
+            ":position": position,
+            ":title": title,
+        },
+    )?;
+    Ok(())
+}
+
+fn child_guids(conn: &PlacesDb, parent_guid: &str) -> Result<Vec<String>> {
+    Ok(conn.query_rows_and_then(
+        "SELECT b.guid FROM moz_bookmarks b
+         JOIN moz_bookmarks p ON p.id = b.parent
+         WHERE p.guid = :guid
+         ORDER BY b.position",
+        &[(":guid", &parent_guid)],
+        |row| row.get::<_, String>(0),
+    )?)
+}
+
+#[test]
+fn test_import_history() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    let tmpdir = tempdir().unwrap();
+    let desktop_path = tmpdir.path().join("desktop-places.sqlite");
+    let desktop_db = empty_desktop_db(&desktop_path)?;
+
+    insert_place(&desktop_db, 1, "https://example.com/", Some("Example"))?;
+    insert_place(&desktop_db, 2, "https://example.com/other", None)?;
+    insert_place(&desktop_db, 3, "not a url", Some("Invalid"))?;
+
+    // A typed visit, and a link visit from Sync.
+    insert_visit(&desktop_db, 1, 1_565_117_389_897, 2, 0)?;
+    insert_visit(&desktop_db, 1, 1_565_117_389_898, 1, 1)?;
+    // A visit type we don't know about.
+    insert_visit(&desktop_db, 2, 1_565_117_389_899, 42, 0)?;
+    insert_visit(&desktop_db, 3, 1_565_117_389_900, 1, 0)?;
+
+    let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
+    let metrics = places::import::import_desktop_history(&places_api, desktop_path)?;
+    assert_eq!(metrics.num_total, 4);
+    assert_eq!(metrics.num_succeeded, 3);
+    assert_eq!(metrics.num_failed, 1);
+
+    let conn = places_api.open_connection(ConnectionType::ReadOnly)?;
+    let visits = |url: &str| -> Result<Vec<(i64, VisitTransition, bool)>> {
+        let url = Url::parse(url)?;
+        Ok(conn.query_rows_and_then(
+            "SELECT v.visit_date, v.visit_type, v.is_local
+             FROM moz_historyvisits v
+             JOIN moz_places h ON h.id = v.place_id
+             WHERE h.url_hash = hash(:url) AND h.url = :url
+             ORDER BY v.visit_date",
+            &[(":url", &url.as_str())],
+            |row| -> Result<_> {
+                Ok((
+                    row.get(0)?,
+                    VisitTransition::from_primitive(row.get(1)?).expect("valid transition"),
+                    row.get(2)?,
+                ))
+            },
+        )?)
+    };
+    assert_eq!(
+        visits("https://example.com/")?,
+        [
+            (1_565_117_389_897, VisitTransition::Typed, true),
+            (1_565_117_389_898, VisitTransition::Link, false),
+        ]
+    );
+    assert_eq!(
+        visits("https://example.com/other")?,
+        [(1_565_117_389_899, VisitTransition::Link, true)]
+    );
+
+    let page = fetch_page_info(&conn, &Url::parse("https://example.com/")?)?
+        .expect("should exist")
+        .page;
+    assert_eq!(page.title, "Example");
+    assert_eq!(page.visit_count_local, 1);
+    assert_eq!(page.visit_count_remote, 1);
+    assert!(page.frecency > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_import_bookmarks() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    let tmpdir = tempdir().unwrap();
+    let desktop_path = tmpdir.path().join("desktop-places.sqlite");
+    let desktop_db = empty_desktop_db(&desktop_path)?;
+
+    insert_place(&desktop_db, 1, "http://example.com/a/%s", None)?;
+    insert_place(&desktop_db, 2, "http://example.com/b", None)?;
+    insert_place(&desktop_db, 3, "place:sort=8&maxResults=10", None)?;
+    insert_place(&desktop_db, 4, "http://example.com/feed/item", None)?;
+
+    // A folder on the toolbar, with a bookmark, a separator and a query.
+    insert_item(&desktop_db, 10, 2, None, 3, 0, "Work")?;
+    insert_item(&desktop_db, 11, 1, Some(1), 10, 0, "A")?;
+    insert_item(&desktop_db, 12, 3, None, 10, 1, "")?;
+    insert_item(&desktop_db, 13, 1, Some(3), 10, 2, "Most Visited")?;
+    // A livemark, which we leave out along with its children.
+    insert_item(&desktop_db, 14, 2, None, 3, 1, "Feed")?;
+    insert_item(&desktop_db, 15, 1, Some(4), 14, 0, "Feed item")?;
+    desktop_db.execute_batch(
+        "INSERT INTO moz_anno_attributes(id, name) VALUES(1, 'livemark/feedURI');
+         INSERT INTO moz_items_annos(item_id, anno_attribute_id, content)
+         VALUES(14, 1, 'http://example.com/feed');",
+    )?;
+    insert_item(&desktop_db, 16, 1, Some(2), 5, 0, "B")?;
+
+    // "work" on A and B, and "news" on A.
+    insert_item(&desktop_db, 20, 2, None, 4, 0, "work")?;
+    insert_item(&desktop_db, 21, 1, Some(1), 20, 0, "")?;
+    insert_item(&desktop_db, 22, 1, Some(2), 20, 1, "")?;
+    insert_item(&desktop_db, 23, 2, None, 4, 1, " news ")?;
+    insert_item(&desktop_db, 24, 1, Some(1), 23, 0, "")?;
+    desktop_db.execute(
+        "INSERT INTO moz_keywords(keyword, place_id) VALUES(' Search ', 1)",
+        [],
+    )?;
+
+    let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
+    let metrics = places::import::import_desktop_bookmarks(&places_api, desktop_path)?;
+    // The four roots, and everything under them but the tags.
+    assert_eq!(metrics.num_total, 11);
+    // The query and the livemark with its child are left out.
+    assert_eq!(metrics.num_succeeded, 8);
+    assert_eq!(metrics.num_failed, 3);
+    assert_eq!(metrics.num_keywords, 1);
+    assert_eq!(metrics.num_tags, 3);
+
+    let conn = places_api.open_connection(ConnectionType::ReadOnly)?;
+    assert_eq!(child_guids(&conn, "toolbar_____")?, ["item00000010"]);
+    assert_eq!(
+        child_guids(&conn, "item00000010")?,
+        ["item00000011", "item00000012"]
+    );
+    assert_eq!(child_guids(&conn, "unfiled_____")?, ["item00000016"]);
+    assert!(child_guids(&conn, "menu________")?.is_empty());
+
+    let a = Url::parse("http://example.com/a/%s")?;
+    let b = Url::parse("http://example.com/b")?;
+    assert_eq!(
+        bookmarks_get_url_for_keyword(&conn, "search")?,
+        Some(a.clone())
+    );
+    let mut tagged_work = tags::get_urls_with_tag(&conn, "work")?;
+    tagged_work.sort();
+    assert_eq!(tagged_work, [a.clone(), b]);
+    assert_eq!(tags::get_urls_with_tag(&conn, "news")?, [a]);
+    Ok(())
+}
+
+#[test]
+fn test_not_a_desktop_db() -> Result<()> {
+    let tmpdir = tempdir().unwrap();
+    let path = tmpdir.path().join("other.sqlite");
+    Connection::open(&path)?.execute_batch("CREATE TABLE moz_places(id INTEGER PRIMARY KEY)")?;
+
+    let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
+    assert!(matches!(
+        places::import::import_desktop_history(&places_api, &path),
+        Err(Error::UnsupportedDatabaseVersion(0))
+    ));
+    assert!(matches!(
+        places::import::import_desktop_bookmarks(&places_api, &path),
+        Err(Error::UnsupportedDatabaseVersion(0))
+    ));
+    Ok(())
+}
@@ -0,0 +1,79 @@
+-- The parts of Firefox Desktop's `places.sqlite` database which we import
+-- from.
+PRAGMA user_version = 74;
+
+CREATE TABLE moz_places(
+    id INTEGER PRIMARY KEY,
+    url LONGVARCHAR,
+    title LONGVARCHAR,
+    rev_host LONGVARCHAR,
+    visit_count INTEGER DEFAULT 0,
+    hidden INTEGER DEFAULT 0 NOT NULL,
+    typed INTEGER DEFAULT 0 NOT NULL,
+    frecency INTEGER DEFAULT -1 NOT NULL,
+    last_visit_date INTEGER,
+    guid TEXT,
+    foreign_count INTEGER DEFAULT 0 NOT NULL,
+    url_hash INTEGER DEFAULT 0 NOT NULL
+);
+
+CREATE TABLE moz_historyvisits(
+    id INTEGER PRIMARY KEY,
+    from_visit INTEGER,
+    place_id INTEGER,
+    visit_date INTEGER,
+    visit_type INTEGER,
+    session INTEGER,
+    source INTEGER DEFAULT 0 NOT NULL,
+    triggeringPlaceId INTEGER
+);
+
+CREATE TABLE moz_bookmarks(
+    id INTEGER PRIMARY KEY,
+    type INTEGER,
+    fk INTEGER DEFAULT NULL,
+    parent INTEGER,
+    position INTEGER,
+    title LONGVARCHAR,
+    keyword_id INTEGER,
+    folder_type TEXT,
+    dateAdded INTEGER,
+    lastModified INTEGER,
+    guid TEXT,
+    syncStatus INTEGER NOT NULL DEFAULT 0,
+    syncChangeCounter INTEGER NOT NULL DEFAULT 1
+);
+
+CREATE TABLE moz_keywords(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    keyword TEXT UNIQUE,
+    place_id INTEGER,
+    post_data TEXT
+);
+
+CREATE TABLE moz_anno_attributes(
+    id INTEGER PRIMARY KEY,
+    name VARCHAR(32) UNIQUE NOT NULL
+);
+
+CREATE TABLE moz_items_annos(
+    id INTEGER PRIMARY KEY,
+    item_id INTEGER NOT NULL,
+    anno_attribute_id INTEGER,
+    content LONGVARCHAR,
+    flags INTEGER DEFAULT 0,
+    expiration INTEGER DEFAULT 0,
+    type INTEGER DEFAULT 0,
+    dateAdded INTEGER DEFAULT 0,
+    lastModified INTEGER DEFAULT 0
+);
+
+-- The roots, which every profile has.
+INSERT INTO moz_bookmarks(id, type, parent, position, title, dateAdded, lastModified, guid)
+VALUES
+    (1, 2, 0, 0, '', 0, 0, 'root________'),
+    (2, 2, 1, 0, 'menu', 0, 0, 'menu________'),
+    (3, 2, 1, 1, 'toolbar', 0, 0, 'toolbar_____'),
+    (4, 2, 1, 2, 'tags', 0, 0, 'tags________'),
+    (5, 2, 1, 3, 'unfiled', 0, 0, 'unfiled_____'),
+    (6, 2, 1, 4, 'mobile', 0, 0, 'mobile______');
@@ -130,7 +130,7 @@ fn test_import_unsupported_db_version() -> Result<()> {
     let fennec_db = empty_fennec_db(&fennec_path)?;
     fennec_db.execute("PRAGMA user_version=22", [])?;
     let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
-    match places::import::import_fennec_bookmarks(&places_api, fennec_path).unwrap_err() {
+    match places::import::import_fennec_bookmarks(&places_api, fennec_path, None).unwrap_err() {
         Error::UnsupportedDatabaseVersion(_) => {}
         _ => unreachable!("Should fail with UnsupportedDatabaseVersion!"),
     }
@@ -342,19 +342,20 @@ fn test_import() -> Result<()> {
 
     let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
 
-    let metrics = places::import::import_fennec_bookmarks(&places_api, fennec_path)?;
+    let metrics = places::import::import_fennec_bookmarks(&places_api, fennec_path, None)?;
     let expected_metrics = BookmarksMigrationResult {
         num_succeeded: 13,
         total_duration: 4,
         num_failed: 1, // only failure is our bookmark with an invalid url.
         num_total: 14,
+        ..Default::default()
     };
     assert_eq!(metrics.num_succeeded, expected_metrics.num_succeeded);
     assert_eq!(metrics.num_failed, expected_metrics.num_failed);
     assert_eq!(metrics.num_total, expected_metrics.num_total);
     assert!(metrics.total_duration > 0);
 
-    let pinned = places::import::import_fennec_pinned_sites(&places_api, fennec_path_pinned)?;
+    let pinned = places::import::import_fennec_pinned_sites(&places_api, fennec_path_pinned, None)?;
     assert_eq!(pinned.len(), 1);
     assert_eq!(pinned[0].title, Some("Pinned Bookmark".to_owned()));
 
@@ -400,7 +401,7 @@ fn test_timestamp_sanitization() -> Result<()> {
         insert_bookmarks(&fennec_db, &bookmarks)?;
 
         let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
-        places::import::import_fennec_bookmarks(&places_api, fennec_path)?;
+        places::import::import_fennec_bookmarks(&places_api, fennec_path, None)?;
 
         let reader = places_api.open_connection(ConnectionType::ReadOnly)?;
         let b = fetch_bookmark(&reader, &Guid::from("bookmarkAAAA"), true)?.unwrap();
@@ -486,7 +487,7 @@ fn test_timestamp_sanitization_tags() -> Result<()> {
         insert_bookmarks(&fennec_db, &bookmarks)?;
 
         let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
-        places::import::import_fennec_bookmarks(&places_api, fennec_path)?;
+        places::import::import_fennec_bookmarks(&places_api, fennec_path, None)?;
 
         let reader = places_api.open_connection(ConnectionType::ReadOnly)?;
         let b = fetch_bookmark(&reader, &Guid::from("bookmarkAAAA"), true)?.unwrap();
@@ -573,7 +574,7 @@ fn test_positions() -> Result<()> {
     insert_bookmarks(&fennec_db, &bookmarks)?;
 
     let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
-    places::import::import_fennec_bookmarks(&places_api, fennec_path)?;
+    places::import::import_fennec_bookmarks(&places_api, fennec_path, None)?;
 
     let unfiled = match fetch_bookmark(
         &places_api.open_connection(ConnectionType::ReadOnly)?,
@@ -628,7 +629,7 @@ fn test_null_parent() -> Result<()> {
         .execute([])?;
 
     let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
-    places::import::import_fennec_bookmarks(&places_api, fennec_path)?;
+    places::import::import_fennec_bookmarks(&places_api, fennec_path, None)?;
 
     // should have ended up in unfiled.
     let unfiled = match fetch_bookmark(
@@ -650,6 +651,7 @@ fn test_null_parent() -> Result<()> {
 #[test]
 fn test_invalid_utf8() -> Result<()> {
     use places::api::places_api::ConnectionType;
+    use places::storage::bookmarks::bookmarks_get_url_for_keyword;
     use places::storage::bookmarks::fetch::fetch_bookmark;
     use url::Url;
 
@@ -680,9 +682,9 @@ fn test_invalid_utf8() -> Result<()> {
             ) VALUES (
                 10, {bad}, 'http://example.com/' || {bad}, {bm_type},
                 NULL, -1, {bad}, {bad},
-                -- We don't migrate tags, so it doesn't matter if they are in
-                -- the correct JSON format - we just want to ensure bad utf-8
-                -- there doesn't kill the migration.
+                -- Tags which aren't in the correct JSON format are dropped,
+                -- so we just want to ensure bad utf-8 there doesn't kill the
+                -- migration.
                 {bad},
                 -1, -1, -1,
                 {bad}, 0, -1, -1
@@ -693,7 +695,7 @@ fn test_invalid_utf8() -> Result<()> {
         .execute([])?;
 
     let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
-    places::import::import_fennec_bookmarks(&places_api, fennec_path)?;
+    places::import::import_fennec_bookmarks(&places_api, fennec_path, None)?;
     let conn = places_api.open_connection(ConnectionType::ReadOnly)?;
 
     // should have ended up in unfiled.
@@ -715,8 +717,87 @@ fn test_invalid_utf8() -> Result<()> {
     assert_eq!(child.title, Some(fixed));
     // We can't know exactly what the fixed guid is, but it must be valid.
     assert!(child.guid.is_valid_for_places());
-    // Can't check keyword or tags because we drop them except for sync users
-    // (and for them, we've dropped them until their first sync)
+    // The keyword is lowercased, so it's easiest to check it's there at all.
+    assert_eq!(
+        bookmarks_get_url_for_keyword(&conn, &fixed.to_lowercase())?,
+        Some(url)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_import_keywords_and_tags() -> Result<()> {
+    use places::api::places_api::ConnectionType;
+    use places::storage::bookmarks::bookmarks_get_url_for_keyword;
+    use places::storage::tags;
+    use url::Url;
+
+    let _ = env_logger::try_init();
+
+    let tmpdir = tempdir().unwrap();
+    let fennec_path = tmpdir.path().join("browser.db");
+    let fennec_db = empty_fennec_db(&fennec_path)?;
+
+    let bookmarks = [
+        FennecBookmark {
+            _id: 6,
+            parent: 5,
+            position: 0,
+            title: Some("A".to_owned()),
+            url: Some("http://example.com/a/%s".to_owned()),
+            keyword: Some(" Search ".to_owned()),
+            tags: Some(r#"["work", " news ", "work", "", 1]"#.to_owned()),
+            ..Default::default()
+        },
+        FennecBookmark {
+            _id: 7,
+            parent: 5,
+            position: 1,
+            title: Some("B".to_owned()),
+            url: Some("http://example.com/b".to_owned()),
+            keyword: Some("".to_owned()),
+            tags: Some(r#"["work"]"#.to_owned()),
+            ..Default::default()
+        },
+        FennecBookmark {
+            _id: 8,
+            parent: 5,
+            position: 2,
+            title: Some("C".to_owned()),
+            url: Some("http://example.com/c".to_owned()),
+            // Not JSON, so ignored.
+            tags: Some("work".to_owned()),
+            ..Default::default()
+        },
+    ];
+    insert_bookmarks(&fennec_db, &get_fennec_roots())?;
+    insert_bookmarks(&fennec_db, &bookmarks)?;
+
+    let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
+    let metrics = places::import::import_fennec_bookmarks(&places_api, fennec_path, None)?;
+    assert_eq!(metrics.num_failed, 0);
+    // One keyword, and "work" and "news" on A, and "work" on B.
+    assert_eq!(metrics.num_keywords, 1);
+    assert_eq!(metrics.num_tags, 3);
+
+    let conn = places_api.open_connection(ConnectionType::ReadOnly)?;
+    let a = Url::parse("http://example.com/a/%s")?;
+    let b = Url::parse("http://example.com/b")?;
+    let c = Url::parse("http://example.com/c")?;
+    assert_eq!(
+        bookmarks_get_url_for_keyword(&conn, "search")?,
+        Some(a.clone())
+    );
+    assert_eq!(bookmarks_get_url_for_keyword(&conn, "")?, None);
+
+    let mut tagged_work = tags::get_urls_with_tag(&conn, "work")?;
+    tagged_work.sort();
+    assert_eq!(tagged_work, [a.clone(), b]);
+    assert_eq!(tags::get_urls_with_tag(&conn, "news")?, [a.clone()]);
+    let mut tags_for_a = tags::get_tags_for_url(&conn, &a)?;
+    tags_for_a.sort();
+    assert_eq!(tags_for_a, ["news", "work"]);
+    assert!(tags::get_tags_for_url(&conn, &c)?.is_empty());
     Ok(())
 }
 
@@ -728,7 +809,7 @@ fn test_empty_db() -> Result<()> {
     empty_fennec_db(&fennec_path)?;
 
     let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
-    let metrics = places::import::import_fennec_bookmarks(&places_api, fennec_path)?;
+    let metrics = places::import::import_fennec_bookmarks(&places_api, fennec_path, None)?;
 
     // There were 0 Fennec bookmarks imported...
     assert_eq!(metrics.num_total, 0);
@@ -736,6 +817,8 @@ fn test_empty_db() -> Result<()> {
     // It's slightly odd, but it's OK for this edge case.
     assert_eq!(metrics.num_succeeded, 5);
     assert_eq!(metrics.num_failed, 0);
+    assert_eq!(metrics.num_keywords, 0);
+    assert_eq!(metrics.num_tags, 0);
     assert!(metrics.total_duration > 0);
     Ok(())
 }
@@ -814,7 +897,7 @@ fn do_test_sync_after_migrate(test_type: TimestampTestType) -> Result<()> {
     insert_bookmarks(&fennec_db, &bookmarks)?;
 
     let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
-    let metrics = places::import::import_fennec_bookmarks(&places_api, fennec_path)?;
+    let metrics = places::import::import_fennec_bookmarks(&places_api, fennec_path, None)?;
     assert_eq!(metrics.num_failed, 0);
 
     let writer = places_api.open_connection(ConnectionType::ReadWrite)?;
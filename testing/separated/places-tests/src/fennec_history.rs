@@ -132,7 +132,7 @@ fn test_import_unsupported_db_version() -> Result<()> {
     let fennec_db = empty_fennec_db(&fennec_path)?;
     fennec_db.execute("PRAGMA user_version=33", [])?;
     let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
-    match places::import::import_fennec_history(&places_api, fennec_path).unwrap_err() {
+    match places::import::import_fennec_history(&places_api, fennec_path, None).unwrap_err() {
         Error::UnsupportedDatabaseVersion(_) => {}
         _ => unreachable!("Should fail with UnsupportedDatabaseVersion!"),
     }
@@ -285,7 +285,7 @@ fn test_import() -> Result<()> {
     )
     .expect("should insert");
 
-    let metrics = places::import::import_fennec_history(&places_api, fennec_path)?;
+    let metrics = places::import::import_fennec_history(&places_api, fennec_path, None)?;
     let expected_metrics = HistoryMigrationResult {
         num_succeeded: 9,
         total_duration: 4,
@@ -352,7 +352,7 @@ fn test_invalid_utf8() -> Result<()> {
 
     let places_api = PlacesApi::new(tmpdir.path().join("places.sqlite"))?;
 
-    let metrics = places::import::import_fennec_history(&places_api, fennec_path)?;
+    let metrics = places::import::import_fennec_history(&places_api, fennec_path, None)?;
     println!("metrics: {:?}", metrics);
 
     let conn = places_api.open_connection(ConnectionType::ReadOnly)?;
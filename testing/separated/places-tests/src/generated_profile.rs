@@ -0,0 +1,61 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use places::api::places_api::{ConnectionType, PlacesApi};
+use places::storage::bookmarks::{
+    fetch::{fetch_tree, Item},
+    BookmarkRootGuid,
+};
+use places::storage::history::get_visited;
+use places::storage::{run_maintenance_optimize, run_maintenance_prune, run_maintenance_vacuum};
+use places::test_support::{generate_profile, ProfileConfig};
+use places::Result;
+use sql_support::ConnExt;
+use tempfile::tempdir;
+
+fn test_config() -> ProfileConfig {
+    ProfileConfig {
+        seed: 42,
+        num_hosts: 200,
+        num_visits: 5_000,
+        ..ProfileConfig::default()
+    }
+}
+
+#[test]
+fn test_generated_profile_survives_reopen_and_maintenance() -> Result<()> {
+    let _ = env_logger::try_init();
+    let tmpdir = tempdir().unwrap();
+    let path = tmpdir.path().join("places.sqlite");
+    let profile = {
+        let api = PlacesApi::new(&path)?;
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        generate_profile(&conn, &test_config())?
+    };
+
+    let api = PlacesApi::new(&path)?;
+    let conn = api.open_connection(ConnectionType::ReadWrite)?;
+    let visited = get_visited(&conn, profile.visited_urls.clone())?;
+    assert!(visited.into_iter().all(|v| v));
+
+    let num_visits: i64 = conn.query_one("SELECT COUNT(*) FROM moz_historyvisits")?;
+    assert_eq!(num_visits as usize, profile.visited_urls.len());
+
+    run_maintenance_prune(&conn, 0)?;
+    run_maintenance_vacuum(&conn)?;
+    run_maintenance_optimize(&conn)?;
+
+    let tree = fetch_tree(&conn, BookmarkRootGuid::Root.guid())?.expect("should have a tree");
+    let mut num_bookmarks = 0;
+    let mut pending = vec![tree];
+    while let Some(item) = pending.pop() {
+        match item {
+            Item::Bookmark { .. } => num_bookmarks += 1,
+            Item::Folder { f } => pending.extend(f.child_nodes.unwrap_or_default()),
+            Item::Separator { .. } => {}
+        }
+    }
+    assert_eq!(num_bookmarks, profile.num_bookmarks);
+    Ok(())
+}
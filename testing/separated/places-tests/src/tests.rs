@@ -3,7 +3,10 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 mod check_coop_tx;
+mod chrome_history;
+mod desktop;
 mod fennec_bookmarks;
 mod fennec_history;
+mod generated_profile;
 mod ios_bookmarks;
 mod ios_history;
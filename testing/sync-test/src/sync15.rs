@@ -137,6 +137,10 @@ impl SyncEngine for TestEngine {
         // this, we do nothing.
         Ok(())
     }
+
+    fn wipe_local(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 fn sync_client(c: &mut TestClient, desc: &str, engine: &dyn SyncEngine) {
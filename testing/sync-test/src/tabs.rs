@@ -64,6 +64,7 @@ fn test_tabs(c0: &mut TestClient, c1: &mut TestClient) {
             client_id: c0.fxa.get_current_device_id().unwrap(),
             client_name: String::new(),
             device_type: DeviceType::Mobile,
+            last_modified: 0,
             remote_tabs: vec![t0],
         },
     );
@@ -92,6 +93,7 @@ fn test_tabs(c0: &mut TestClient, c1: &mut TestClient) {
             client_id: c1.fxa.get_current_device_id().unwrap(),
             client_name: String::new(),
             device_type: DeviceType::Mobile,
+            last_modified: 0,
             remote_tabs: vec![t1, t2],
         },
     );